@@ -0,0 +1,125 @@
+//! Matching of large presentation definitions against a wallet of stored
+//! credentials, run with `cargo bench --bench matching`.
+//!
+//! As the holder, credentials are matched against the whole definition when
+//! the permission request is created, through
+//! `Holder::search_credentials_vs_presentation_definition`, while the fields
+//! of a descriptor are only evaluated once the consent screen displays it,
+//! through `PermissionRequest::requested_fields_for_descriptor`.
+//!
+//! Fails when matching a 50-descriptor definition against 200 credentials,
+//! and evaluating the fields of the first descriptor, takes 100ms or more.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use mobile_sdk_rs::credential::json_vc::JsonVc;
+use mobile_sdk_rs::credential::ParsedCredential;
+use mobile_sdk_rs::oid4vp::holder::Holder;
+use mobile_sdk_rs::oid4vp::permission_request::PermissionRequest;
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use serde_json::json;
+
+const DESCRIPTORS: usize = 50;
+const CREDENTIALS: usize = 200;
+const ITERATIONS: usize = 20;
+const BUDGET: Duration = Duration::from_millis(100);
+
+fn credentials() -> Vec<Arc<ParsedCredential>> {
+    (0..CREDENTIALS)
+        .map(|idx| {
+            let credential = json!({
+                "@context": ["https://www.w3.org/2018/credentials/v1"],
+                "type": ["VerifiableCredential", format!("Credential{}", idx % (DESCRIPTORS * 2))],
+                "issuer": "did:example:issuer",
+                "issuanceDate": "2024-01-01T00:00:00Z",
+                "credentialSubject": {
+                    "id": format!("did:example:holder{idx}"),
+                    "name": format!("Holder {idx}"),
+                    "birthdate": "1990-01-01",
+                    "address": { "country": "FR", "locality": "Paris" },
+                },
+            });
+            ParsedCredential::new_ldp_vc(JsonVc::new_from_json(credential.to_string()).unwrap())
+        })
+        .collect()
+}
+
+fn definition() -> PresentationDefinition {
+    serde_json::from_value(json!({
+        "id": "large",
+        "input_descriptors": (0..DESCRIPTORS)
+            .map(|idx| json!({
+                "id": format!("descriptor_{idx}"),
+                "constraints": {
+                    "fields": [
+                        {
+                            "path": ["$.type"],
+                            "filter": {
+                                "type": "array",
+                                "contains": { "const": format!("Credential{idx}") },
+                            },
+                        },
+                        { "path": ["$.credentialSubject.name"] },
+                        {
+                            "path": ["$.credentialSubject.address.country"],
+                            "filter": { "type": "string", "enum": ["FR", "DE", "IT"] },
+                        },
+                    ],
+                },
+            }))
+            .collect::<Vec<_>>(),
+    }))
+    .unwrap()
+}
+
+fn request() -> AuthorizationRequestObject {
+    serde_json::from_value(json!({
+        "client_id": "did:web:verifier.example.com",
+        "response_type": "vp_token",
+        "response_mode": "direct_post",
+        "response_uri": "https://verifier.example.com/response",
+        "nonce": "n-0S6_WzA2Mj",
+    }))
+    .unwrap()
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let holder = runtime
+        .block_on(Holder::new_with_credentials(credentials(), vec![]))
+        .unwrap();
+    let definition = definition();
+
+    let mut timings = (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            let matched = runtime
+                .block_on(holder.search_credentials_vs_presentation_definition(&definition))
+                .unwrap();
+            let permission_request = PermissionRequest::new(definition.clone(), matched, request());
+            let descriptor = permission_request.input_descriptors()[0].id.clone();
+            for credential in permission_request.credentials() {
+                black_box(
+                    permission_request
+                        .requested_fields_for_descriptor(&credential, descriptor.clone())
+                        .unwrap(),
+                );
+            }
+            let elapsed = start.elapsed();
+            assert_eq!(permission_request.credentials().len(), CREDENTIALS / 2);
+            elapsed
+        })
+        .collect::<Vec<_>>();
+    timings.sort();
+
+    let median = timings[ITERATIONS / 2];
+    println!(
+        "matching {DESCRIPTORS} descriptors against {CREDENTIALS} credentials: \
+         median {median:?}, max {:?}",
+        timings[ITERATIONS - 1]
+    );
+    assert!(median < BUDGET, "matching took {median:?}, over {BUDGET:?}");
+}
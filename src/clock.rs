@@ -0,0 +1,52 @@
+//! The clock validity and expiry checks read the current time from, so that
+//! tests are deterministic, and apps can use a trusted time source on devices
+//! whose clock cannot be trusted.
+//!
+//! Checks allow a leeway on either side of validity windows, to tolerate the
+//! skew between the clocks of the device and of issuers or verifiers.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Interface: Clock
+///
+/// The source of the current time.
+#[uniffi::export(with_foreign)]
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    /// Return the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The clock of the system.
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Browsers provide no system time to `wasm32-unknown-unknown`, so the
+    /// time is read from the JavaScript `Date`.
+    #[cfg(target_arch = "wasm32")]
+    fn now(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+    }
+}
+
+/// Return the clock of the system, the default clock of checks.
+pub(crate) fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// Whether a time has passed at `now`, allowing for the leeway.
+pub(crate) fn has_passed(time: SystemTime, now: SystemTime, leeway: Duration) -> bool {
+    // Times past the representable ones never pass.
+    time.checked_add(leeway).is_some_and(|time| time <= now)
+}
+
+/// Whether a time has been reached at `now`, allowing for the leeway.
+pub(crate) fn has_reached(time: SystemTime, now: SystemTime, leeway: Duration) -> bool {
+    now.checked_add(leeway).is_none_or(|now| time <= now)
+}
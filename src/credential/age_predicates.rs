@@ -0,0 +1,175 @@
+//! Age predicates, answering `age_over_NN` requests from the age claims the
+//! issuer attested rather than by disclosing the birth date.
+//!
+//! Following ISO 18013-5, a request for an `age_over_NN` the credential does
+//! not attest is answered with the nearest one it attests: the smallest
+//! threshold above `NN` attested true, else the greatest one below `NN`
+//! attested false. mdocs attest their predicates as `age_over_NN` data
+//! elements, and SD-JWT VCs as `age_over_NN` or `age_equal_or_over/NN` claims,
+//! which are answered alike.
+//!
+//! NOTE: the predicates are selectively disclosed, issuer-signed claims
+//! rather than zero-knowledge proofs, which none of the supported formats
+//! provide. Credentials without age claims cannot answer them.
+
+use super::disclosure::{parse_path, select_path, value_at, PathSegment, Pointer};
+
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use serde_json::Value as Json;
+
+/// The prefix of the names of age predicate claims, e.g. `age_over_18`.
+const AGE_OVER_PREFIX: &str = "age_over_";
+
+/// The claim of SD-JWT VCs whose members are age predicates by threshold,
+/// e.g. `age_equal_or_over/18`.
+const AGE_EQUAL_OR_OVER: &str = "age_equal_or_over";
+
+/// Return the pointer of a JSONPath made of member names only.
+fn name_pointer(path: &str) -> Option<Pointer> {
+    parse_path(path)?
+        .into_iter()
+        .map(|segment| match segment {
+            PathSegment::Name(name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Return the age threshold of a claim, if it is an age predicate.
+fn threshold(parent: &[String], name: &str) -> Option<u32> {
+    match name.strip_prefix(AGE_OVER_PREFIX) {
+        Some(threshold) => threshold.parse().ok(),
+        None if parent
+            .last()
+            .is_some_and(|claim| claim == AGE_EQUAL_OR_OVER) =>
+        {
+            name.parse().ok()
+        }
+        None => None,
+    }
+}
+
+/// Return the pointer and value of the attested age predicate nearest to the
+/// one requested at a path, if the path requests an age predicate.
+pub(crate) fn nearest_attested(credential: &Json, path: &str) -> Option<(Pointer, bool)> {
+    let mut pointer = name_pointer(path)?;
+    let name = pointer.pop()?;
+    let requested = threshold(&pointer, &name)?;
+
+    let attested = value_at(credential, &pointer)?
+        .as_object()?
+        .iter()
+        .filter_map(|(name, value)| Some((threshold(&pointer, name)?, value.as_bool()?, name)))
+        .collect::<Vec<_>>();
+    let (_, value, name) = attested
+        .iter()
+        .filter(|(threshold, value, _)| *value && *threshold > requested)
+        .min_by_key(|(threshold, ..)| *threshold)
+        .or_else(|| {
+            attested
+                .iter()
+                .filter(|(threshold, value, _)| !*value && *threshold < requested)
+                .max_by_key(|(threshold, ..)| *threshold)
+        })?;
+
+    pointer.push(name.to_string());
+    Some((pointer, *value))
+}
+
+/// Return the claims of a credential with the age predicates requested by the
+/// definition that it does not attest, derived from those it attests, for
+/// the credential to be matched against the definition.
+pub(crate) fn with_derived_predicates(claims: &Json, definition: &PresentationDefinition) -> Json {
+    let mut claims = claims.clone();
+    let Ok(definition) = serde_json::to_value(definition) else {
+        return claims;
+    };
+
+    let paths = definition["input_descriptors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|descriptor| descriptor["constraints"]["fields"].as_array())
+        .flatten()
+        .flat_map(|field| field["path"].as_array())
+        .flatten()
+        .filter_map(Json::as_str);
+    for path in paths {
+        if !select_path(&claims, path).is_empty() {
+            continue;
+        }
+        let (Some((_, value)), Some(mut pointer)) =
+            (nearest_attested(&claims, path), name_pointer(path))
+        else {
+            continue;
+        };
+
+        let name = pointer.pop().unwrap_or_default();
+        let parent = pointer
+            .iter()
+            .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+            .collect::<String>();
+        if let Some(Json::Object(parent)) = claims.pointer_mut(&parent) {
+            parent.insert(name, Json::Bool(value));
+        }
+    }
+    claims
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn derives_age_predicates() {
+        let mdoc = json!({
+            "org.iso.18013.5.1": {
+                "age_over_16": true,
+                "age_over_21": true,
+                "age_over_65": false,
+            }
+        });
+        let nearest = |credential: &Json, path: &str| {
+            nearest_attested(credential, path).map(|(pointer, value)| (pointer.join("/"), value))
+        };
+        assert_eq!(
+            nearest(&mdoc, "$['org.iso.18013.5.1']['age_over_18']"),
+            Some(("org.iso.18013.5.1/age_over_21".into(), true))
+        );
+        assert_eq!(
+            nearest(&mdoc, "$['org.iso.18013.5.1']['age_over_70']"),
+            Some(("org.iso.18013.5.1/age_over_65".into(), false))
+        );
+        assert_eq!(
+            nearest(&mdoc, "$['org.iso.18013.5.1']['age_over_30']"),
+            None
+        );
+        assert_eq!(nearest(&mdoc, "$['org.iso.18013.5.1']['birth_date']"), None);
+
+        let sd_jwt = json!({ "age_equal_or_over": { "21": true } });
+        assert_eq!(
+            nearest(&sd_jwt, "$.age_equal_or_over['18']"),
+            Some(("age_equal_or_over/21".into(), true))
+        );
+
+        let definition = serde_json::from_value(json!({
+            "id": "age",
+            "input_descriptors": [{
+                "id": "pid",
+                "constraints": {
+                    "fields": [{
+                        "path": ["$.age_equal_or_over['18']"],
+                        "filter": { "type": "boolean", "const": true },
+                    }],
+                },
+            }],
+        }))
+        .unwrap();
+        assert_eq!(
+            with_derived_predicates(&sd_jwt, &definition),
+            json!({ "age_equal_or_over": { "18": true, "21": true } })
+        );
+    }
+}
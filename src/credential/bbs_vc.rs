@@ -0,0 +1,200 @@
+//! JSON-LD credentials secured with `bbs-2023` Data Integrity proofs, which are
+//! presented with proofs derived for the disclosed claims only.
+//!
+//! Derived proofs are randomized, so verifiers cannot correlate presentations
+//! of a credential by its signature. They are bound to the nonce of the
+//! request through their presentation header.
+
+use super::json_vc::{JsonVc, JsonVcEncodingError, JsonVcInitError};
+use crate::{oid4vp::permission_request::RequestedField, CredentialType, KeyAlias};
+
+use std::sync::Arc;
+
+use openid4vp::core::{
+    presentation_definition::PresentationDefinition, response::parameters::VpTokenItem,
+};
+use serde_json::Value as Json;
+use ssi::{
+    claims::{data_integrity::AnySelectionOptions, VerificationParameters},
+    dids::{AnyDidMethod, DIDResolver},
+    json_pointer::JsonPointerBuf,
+    prelude::{AnyDataIntegrity, AnyJsonCredential},
+};
+use uuid::Uuid;
+
+/// The cryptosuite of BBS Data Integrity proofs.
+pub(crate) const BBS_2023: &str = "bbs-2023";
+
+#[derive(uniffi::Object, Debug, Clone)]
+/// A verifiable credential secured with a `bbs-2023` base proof.
+pub struct BbsVc {
+    vc: Arc<JsonVc>,
+}
+
+#[uniffi::export]
+impl BbsVc {
+    #[uniffi::constructor]
+    /// Construct a new credential from UTF-8 encoded JSON.
+    pub fn new_from_json(utf8_json_string: String) -> Result<Arc<Self>, BbsVcInitError> {
+        Self::from_json_vc(JsonVc::new_from_json(utf8_json_string)?)
+            .map_err(|_| BbsVcInitError::NotBbs2023)
+    }
+
+    /// The local ID of this credential.
+    pub fn id(&self) -> Uuid {
+        self.vc.id()
+    }
+
+    /// The keypair identified in the credential for use in a verifiable presentation.
+    pub fn key_alias(&self) -> Option<KeyAlias> {
+        self.vc.key_alias()
+    }
+
+    /// The type of this credential.
+    pub fn r#type(&self) -> CredentialType {
+        self.vc.r#type()
+    }
+
+    /// The credential as JSON-LD, with its base proof.
+    pub fn json_vc(&self) -> Arc<JsonVc> {
+        self.vc.clone()
+    }
+}
+
+impl BbsVc {
+    /// Wrap the credential when it is secured with a `bbs-2023` proof, or
+    /// return it unchanged.
+    pub(crate) fn from_json_vc(vc: Arc<JsonVc>) -> Result<Arc<Self>, Arc<JsonVc>> {
+        if is_bbs_2023(vc.raw()) {
+            Ok(Arc::new(Self { vc }))
+        } else {
+            Err(vc)
+        }
+    }
+
+    /// Check if the credential satisfies a presentation definition.
+    pub fn check_presentation_definition(&self, definition: &PresentationDefinition) -> bool {
+        self.vc.check_presentation_definition(definition)
+    }
+
+    /// Returns the requested fields given a presentation definition.
+    pub fn requested_fields(
+        &self,
+        definition: &PresentationDefinition,
+    ) -> Vec<Arc<RequestedField>> {
+        self.vc.requested_fields(definition)
+    }
+
+    /// Return the credential in an `ldp_vp` presentation, with a proof derived
+    /// for the disclosed fields and bound to the nonce.
+    ///
+    /// The claims the issuer made mandatory are disclosed as well.
+    ///
+    /// NOTE: the presentation is not secured with a proof of the holder yet.
+    pub(crate) async fn as_derived_vp_token(
+        &self,
+        disclosed_fields: &[Arc<RequestedField>],
+        nonce: &str,
+    ) -> Result<VpTokenItem, BbsVcPresentationError> {
+        let vc: AnyDataIntegrity<AnyJsonCredential> = serde_json::from_value(self.vc.raw().clone())
+            .map_err(|e| BbsVcPresentationError::ProofDecoding(format!("{e:?}")))?;
+
+        let selective_pointers = disclosed_fields
+            .iter()
+            .flat_map(|field| &field.pointers)
+            .map(|pointer| json_pointer(pointer))
+            .collect::<Result<Vec<_>, _>>()?;
+        let options = AnySelectionOptions {
+            selective_pointers,
+            presentation_header: Some(nonce.as_bytes().to_vec()),
+            ..Default::default()
+        };
+        let params =
+            VerificationParameters::from_resolver(AnyDidMethod::default().into_vm_resolver());
+
+        let derived = vc
+            .select(params, options)
+            .await
+            .map_err(|e| BbsVcPresentationError::Derivation(format!("{e:?}")))?;
+        let derived =
+            serde_json::to_value(derived).map_err(|_| JsonVcEncodingError::PresentationEncoding)?;
+
+        Ok(self.vc.presentation(derived)?)
+    }
+}
+
+/// Whether the credential is secured with a `bbs-2023` Data Integrity proof.
+pub(crate) fn is_bbs_2023(credential: &Json) -> bool {
+    let proofs = match &credential["proof"] {
+        Json::Array(proofs) => proofs.iter().collect(),
+        proof => vec![proof],
+    };
+    proofs
+        .into_iter()
+        .any(|proof| proof["cryptosuite"].as_str() == Some(BBS_2023))
+}
+
+/// Encode the location of a field as a JSON pointer, e.g. `/credentialSubject/name`.
+fn json_pointer(path: &[String]) -> Result<JsonPointerBuf, BbsVcPresentationError> {
+    let pointer: String = path
+        .iter()
+        .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+        .collect();
+    JsonPointerBuf::new(pointer.clone()).map_err(|_| BbsVcPresentationError::Pointer(pointer))
+}
+
+#[derive(Debug, uniffi::Error, thiserror::Error)]
+pub enum BbsVcInitError {
+    #[error(transparent)]
+    JsonVc(#[from] JsonVcInitError),
+    #[error("the credential is not secured with a bbs-2023 proof")]
+    NotBbs2023,
+}
+
+#[derive(Debug, uniffi::Error, thiserror::Error)]
+pub enum BbsVcPresentationError {
+    #[error("failed to decode the bbs-2023 proof of the credential: {0}")]
+    ProofDecoding(String),
+    #[error("invalid JSON pointer to a disclosed field: {0}")]
+    Pointer(String),
+    #[error("failed to derive a bbs-2023 proof: {0}")]
+    Derivation(String),
+    #[error(transparent)]
+    Encoding(#[from] JsonVcEncodingError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbs_credential() -> Json {
+        let mut credential: Json =
+            serde_json::from_str(include_str!("../../tests/res/vc")).unwrap();
+        credential["proof"] = serde_json::json!({
+            "type": "DataIntegrityProof",
+            "verificationMethod": "did:key:zUC7DerdEmfZ8f4pFajXgGwJoMkV1ofMTmEG5UoNvnWiPiLuGKNeqgRpLH2TV4Xe5mJ2cXV76gRN7LFQwapF1VFu6x2yrr5ci1mXqC1WNUrnHnLgvfZfMH7h6xP6qsf9EKRQrPQ#zUC7DerdEmfZ8f4pFajXgGwJoMkV1ofMTmEG5UoNvnWiPiLuGKNeqgRpLH2TV4Xe5mJ2cXV76gRN7LFQwapF1VFu6x2yrr5ci1mXqC1WNUrnHnLgvfZfMH7h6xP6qsf9EKRQrPQ",
+            "cryptosuite": BBS_2023,
+            "proofPurpose": "assertionMethod",
+            "proofValue": "u2V0ChVhQ",
+        });
+        credential
+    }
+
+    #[test]
+    fn parses_bbs_credentials() {
+        let ldp_vc = JsonVc::new_from_json(include_str!("../../tests/res/vc").into()).unwrap();
+        assert!(BbsVc::from_json_vc(ldp_vc).is_err());
+
+        let bbs_vc = BbsVc::new_from_json(bbs_credential().to_string()).unwrap();
+        assert_eq!(bbs_vc.json_vc().raw(), &bbs_credential());
+    }
+
+    #[test]
+    fn encodes_json_pointers() {
+        let path = ["credentialSubject".to_string(), "a/b~c".to_string()];
+        assert_eq!(
+            json_pointer(&path).unwrap().as_str(),
+            "/credentialSubject/a~1b~0c"
+        );
+    }
+}
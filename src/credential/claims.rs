@@ -0,0 +1,380 @@
+//! A format-agnostic tree of the claims of a credential, to render credential
+//! details without decoding SD-JWTs or CBOR on the native side.
+
+use super::disclosure::{DisclosedSdJwt, Pointer};
+use super::{ParsedCredential, ParsedCredentialInner};
+
+use std::sync::Arc;
+
+use base64::prelude::*;
+use serde_cbor::Value as Cbor;
+use serde_json::Value as Json;
+
+/// The value of a leaf claim.
+#[derive(Debug, Clone, PartialEq, uniffi::Enum)]
+pub enum ClaimLeaf {
+    Null,
+    Bool {
+        value: bool,
+    },
+    Integer {
+        value: i64,
+    },
+    Number {
+        value: f64,
+    },
+    Text {
+        value: String,
+    },
+    /// A byte string, e.g. a portrait, with its media type when it could be
+    /// identified.
+    Bytes {
+        value: Vec<u8>,
+        mime_type: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum ClaimsNodeKind {
+    Map,
+    Array,
+    Leaf,
+}
+
+#[derive(Debug)]
+enum ClaimsNodeValue {
+    Map(Vec<Arc<ClaimsNode>>),
+    Array(Vec<Arc<ClaimsNode>>),
+    Leaf(ClaimLeaf),
+}
+
+/// A claim of a credential: a map of named claims, an array of claims, or a
+/// leaf value.
+#[derive(Debug, uniffi::Object)]
+pub struct ClaimsNode {
+    name: Option<String>,
+    selectively_disclosable: bool,
+    value: ClaimsNodeValue,
+}
+
+#[uniffi::export]
+impl ClaimsNode {
+    /// Return the name of the claim, for the entries of maps.
+    pub fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    /// Whether the claim can be withheld from presentations, e.g. an SD-JWT
+    /// disclosure or an mdoc element.
+    pub fn selectively_disclosable(&self) -> bool {
+        self.selectively_disclosable
+    }
+
+    pub fn kind(&self) -> ClaimsNodeKind {
+        match self.value {
+            ClaimsNodeValue::Map(_) => ClaimsNodeKind::Map,
+            ClaimsNodeValue::Array(_) => ClaimsNodeKind::Array,
+            ClaimsNodeValue::Leaf(_) => ClaimsNodeKind::Leaf,
+        }
+    }
+
+    /// Return the entries of a map, or the items of an array, in order.
+    pub fn children(&self) -> Vec<Arc<ClaimsNode>> {
+        match &self.value {
+            ClaimsNodeValue::Map(children) | ClaimsNodeValue::Array(children) => children.clone(),
+            ClaimsNodeValue::Leaf(_) => vec![],
+        }
+    }
+
+    /// Return the entry of a map with the name.
+    pub fn get(&self, name: String) -> Option<Arc<ClaimsNode>> {
+        match &self.value {
+            ClaimsNodeValue::Map(children) => children
+                .iter()
+                .find(|child| child.name.as_ref() == Some(&name))
+                .cloned(),
+            _ => None,
+        }
+    }
+
+    /// Return the value of a leaf.
+    pub fn leaf(&self) -> Option<ClaimLeaf> {
+        match &self.value {
+            ClaimsNodeValue::Leaf(leaf) => Some(leaf.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[uniffi::export]
+impl ParsedCredential {
+    /// Return the claims of the credential, with every disclosure revealed.
+    ///
+    /// The root of mdocs maps their namespaces to their elements.
+    pub fn claims(&self) -> Arc<ClaimsNode> {
+        let sd_jwt: Option<&str> = match &self.inner {
+            ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => Some(sd_jwt.inner.as_ref()),
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => Some(sd_jwt.inner.as_ref()),
+            _ => None,
+        };
+
+        match (&self.inner, sd_jwt.and_then(DisclosedSdJwt::parse)) {
+            (ParsedCredentialInner::MsoMdoc(mdoc), _) => {
+                cbor_node(None, &mdoc.namespaces_as_cbor(), 0)
+            }
+            (_, Some(sd_jwt)) => {
+                let disclosed = sd_jwt
+                    .disclosures
+                    .iter()
+                    .map(|disclosure| &disclosure.pointer)
+                    .collect::<Vec<_>>();
+                claims_tree(&sd_jwt.reveal().unwrap_or(Json::Null), &|pointer| {
+                    disclosed.contains(&pointer)
+                })
+            }
+            // Every claim of a BBS credential can be withheld from derived proofs.
+            (ParsedCredentialInner::LdpVcBbs(_), _) => {
+                claims_tree(&self.claims_as_json().unwrap_or(Json::Null), &|_| true)
+            }
+            _ => claims_tree(&self.claims_as_json().unwrap_or(Json::Null), &|_| false),
+        }
+    }
+}
+
+/// Build the tree of JSON claims, flagging the claims at the pointers for
+/// which `disclosable` holds.
+pub(crate) fn claims_tree(
+    claims: &Json,
+    disclosable: &dyn Fn(&Pointer) -> bool,
+) -> Arc<ClaimsNode> {
+    node(None, claims, &mut vec![], disclosable)
+}
+
+fn node(
+    name: Option<String>,
+    value: &Json,
+    pointer: &mut Pointer,
+    disclosable: &dyn Fn(&Pointer) -> bool,
+) -> Arc<ClaimsNode> {
+    let selectively_disclosable = !pointer.is_empty() && disclosable(pointer);
+
+    let child = |name: String, value: &Json, pointer: &mut Pointer, named: bool| {
+        pointer.push(name.clone());
+        let child = node(named.then_some(name), value, pointer, disclosable);
+        pointer.pop();
+        child
+    };
+
+    let value = match value {
+        Json::Object(object) => ClaimsNodeValue::Map(
+            object
+                .iter()
+                .map(|(name, value)| child(name.clone(), value, pointer, true))
+                .collect(),
+        ),
+        Json::Array(array) => ClaimsNodeValue::Array(
+            array
+                .iter()
+                .enumerate()
+                .map(|(index, value)| child(index.to_string(), value, pointer, false))
+                .collect(),
+        ),
+        leaf => ClaimsNodeValue::Leaf(json_leaf(leaf)),
+    };
+
+    Arc::new(ClaimsNode {
+        name,
+        selectively_disclosable,
+        value,
+    })
+}
+
+/// Build the tree of the CBOR claims of an mdoc, at the given depth from its
+/// namespaces.
+fn cbor_node(name: Option<String>, value: &Cbor, depth: usize) -> Arc<ClaimsNode> {
+    let value = match value {
+        Cbor::Map(map) => ClaimsNodeValue::Map(
+            map.iter()
+                .map(|(name, value)| {
+                    let name = match name {
+                        Cbor::Text(name) => name.clone(),
+                        name => format!("{name:?}"),
+                    };
+                    cbor_node(Some(name), value, depth + 1)
+                })
+                .collect(),
+        ),
+        Cbor::Array(array) => ClaimsNodeValue::Array(
+            array
+                .iter()
+                .map(|value| cbor_node(None, value, depth + 1))
+                .collect(),
+        ),
+        // Tags, e.g. of full-dates, only qualify the value.
+        Cbor::Tag(_, value) => return cbor_node(name, value, depth),
+        leaf => ClaimsNodeValue::Leaf(cbor_leaf(leaf)),
+    };
+
+    Arc::new(ClaimsNode {
+        name,
+        // Every element of an mdoc is disclosed on its own.
+        selectively_disclosable: depth == 2,
+        value,
+    })
+}
+
+/// Return the value of a JSON claim, decoding data URLs to bytes.
+///
+/// Maps and arrays are returned as JSON text.
+pub(crate) fn json_leaf(value: &Json) -> ClaimLeaf {
+    match value {
+        Json::Null => ClaimLeaf::Null,
+        Json::Bool(value) => ClaimLeaf::Bool { value: *value },
+        Json::Number(number) => match number.as_i64() {
+            Some(value) => ClaimLeaf::Integer { value },
+            None => ClaimLeaf::Number {
+                value: number.as_f64().unwrap_or_default(),
+            },
+        },
+        Json::String(value) => data_url(value).unwrap_or_else(|| ClaimLeaf::Text {
+            value: value.clone(),
+        }),
+        value => ClaimLeaf::Text {
+            value: value.to_string(),
+        },
+    }
+}
+
+/// Return the value of a CBOR claim.
+///
+/// Maps and arrays are returned as their diagnostic text.
+pub(crate) fn cbor_leaf(value: &Cbor) -> ClaimLeaf {
+    match value {
+        Cbor::Null => ClaimLeaf::Null,
+        Cbor::Bool(value) => ClaimLeaf::Bool { value: *value },
+        Cbor::Integer(value) => match i64::try_from(*value) {
+            Ok(value) => ClaimLeaf::Integer { value },
+            Err(_) => ClaimLeaf::Number {
+                value: *value as f64,
+            },
+        },
+        Cbor::Float(value) => ClaimLeaf::Number { value: *value },
+        Cbor::Text(value) => ClaimLeaf::Text {
+            value: value.clone(),
+        },
+        Cbor::Bytes(value) => ClaimLeaf::Bytes {
+            value: value.clone(),
+            mime_type: sniff_mime_type(value).map(ToOwned::to_owned),
+        },
+        Cbor::Tag(_, value) => cbor_leaf(value),
+        value => ClaimLeaf::Text {
+            value: format!("{value:?}"),
+        },
+    }
+}
+
+/// Decode a base64 `data:` URL, as SD-JWT VCs carry images.
+fn data_url(value: &str) -> Option<ClaimLeaf> {
+    let (mime_type, data) = value.strip_prefix("data:")?.split_once(";base64,")?;
+    let value = BASE64_STANDARD.decode(data).ok()?;
+    let mime_type = match mime_type {
+        "" => sniff_mime_type(&value).map(ToOwned::to_owned),
+        mime_type => Some(mime_type.to_owned()),
+    };
+    Some(ClaimLeaf::Bytes { value, mime_type })
+}
+
+/// Identify the images ISO 18013-5 allows for portraits and signatures.
+fn sniff_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(&[0x00, 0x00, 0x00, 0x0C, 0x6A, 0x50, 0x20, 0x20]) {
+        Some("image/jp2")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_claims_trees() {
+        let claims = serde_json::json!({
+            "given_name": "Alice",
+            "age": 42,
+            "address": { "locality": "Paris" },
+            "nationalities": ["FR", "DE"],
+        });
+        let disclosed: Vec<Pointer> = vec![
+            vec!["address".into()],
+            vec!["nationalities".into(), "1".into()],
+        ];
+        let root = claims_tree(&claims, &|pointer| disclosed.contains(pointer));
+
+        assert_eq!(root.kind(), ClaimsNodeKind::Map);
+        assert_eq!(
+            root.get("given_name".into()).unwrap().leaf(),
+            Some(ClaimLeaf::Text {
+                value: "Alice".into()
+            })
+        );
+        assert_eq!(
+            root.get("age".into()).unwrap().leaf(),
+            Some(ClaimLeaf::Integer { value: 42 })
+        );
+
+        let address = root.get("address".into()).unwrap();
+        assert!(address.selectively_disclosable());
+        assert!(!address
+            .get("locality".into())
+            .unwrap()
+            .selectively_disclosable());
+
+        let nationalities = root.get("nationalities".into()).unwrap().children();
+        assert_eq!(nationalities[0].name(), None);
+        assert!(!nationalities[0].selectively_disclosable());
+        assert!(nationalities[1].selectively_disclosable());
+    }
+
+    #[test]
+    fn types_binary_claims() {
+        let portrait = [0xFF, 0xD8, 0xFF, 0xE0];
+        let expected = ClaimLeaf::Bytes {
+            value: portrait.to_vec(),
+            mime_type: Some("image/jpeg".into()),
+        };
+
+        assert_eq!(cbor_leaf(&Cbor::Bytes(portrait.to_vec())), expected);
+        assert_eq!(
+            json_leaf(&Json::String(format!(
+                "data:image/jpeg;base64,{}",
+                BASE64_STANDARD.encode(portrait)
+            ))),
+            expected
+        );
+
+        let namespaces = Cbor::Map(
+            [(
+                Cbor::Text("org.iso.18013.5.1".into()),
+                Cbor::Map(
+                    [(
+                        Cbor::Text("portrait".into()),
+                        Cbor::Bytes(portrait.to_vec()),
+                    )]
+                    .into(),
+                ),
+            )]
+            .into(),
+        );
+        let portrait = cbor_node(None, &namespaces, 0)
+            .get("org.iso.18013.5.1".into())
+            .and_then(|namespace| namespace.get("portrait".into()))
+            .unwrap();
+        assert!(portrait.selectively_disclosable());
+        assert_eq!(portrait.leaf(), Some(expected));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::oid4vci::context_loader_from_map;
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use serde_json::Value as Json;
+use ssi::json_ld::ContextLoader;
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum JsonLdContextError {
+    #[error("Failed to fetch the context {url}: {reason}")]
+    Fetch { url: String, reason: String },
+    #[error("Invalid context document {url}: {reason}")]
+    InvalidDocument { url: String, reason: String },
+    #[error("Failed to build the context loader: {0}")]
+    Loader(String),
+}
+
+/// JSON-LD context documents, by URL, used to canonicalize JSON-LD
+/// credentials without network access.
+///
+/// The contexts of the W3C VCDM and of the common Data Integrity suites are
+/// always available. Other contexts must be inserted, or prefetched while
+/// online.
+#[derive(Debug, Default, uniffi::Object)]
+pub struct JsonLdContextCache {
+    contexts: RwLock<HashMap<String, String>>,
+}
+
+#[uniffi::export]
+impl JsonLdContextCache {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Return the cached context documents, by URL, e.g. to persist them.
+    pub fn contexts(&self) -> HashMap<String, String> {
+        self.contexts
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Cache the context document of a URL, replacing any previous one.
+    pub fn insert(&self, url: String, document: String) -> Result<(), JsonLdContextError> {
+        if let Err(e) = serde_json::from_str::<Json>(&document) {
+            return Err(JsonLdContextError::InvalidDocument {
+                url,
+                reason: format!("{e:?}"),
+            });
+        }
+
+        self.contexts
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(url, document);
+        Ok(())
+    }
+
+    /// Remove every cached context document.
+    pub fn clear(&self) {
+        self.contexts
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl JsonLdContextCache {
+    /// Fetch and cache the context documents of the URLs that are not cached
+    /// yet, returning how many were fetched.
+    pub async fn prefetch(&self, urls: Vec<String>) -> Result<u32, JsonLdContextError> {
+        let mut fetched = 0;
+
+        for url in urls {
+            if self.contains(&url) {
+                continue;
+            }
+
+            let fetch_error = |e: reqwest::Error| JsonLdContextError::Fetch {
+                url: url.clone(),
+                reason: format!("{e:?}"),
+            };
+            let document = reqwest::Client::new()
+                .get(&url)
+                .header(
+                    reqwest::header::ACCEPT,
+                    "application/ld+json, application/json",
+                )
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(fetch_error)?
+                .text()
+                .await
+                .map_err(fetch_error)?;
+
+            self.insert(url, document)?;
+            fetched += 1;
+        }
+
+        Ok(fetched)
+    }
+}
+
+impl JsonLdContextCache {
+    fn contains(&self, url: &str) -> bool {
+        self.contexts
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .contains_key(url)
+    }
+
+    /// Return a context loader resolving the cached contexts, along with the
+    /// contexts that are always available.
+    pub(crate) fn loader(&self) -> Result<ContextLoader, JsonLdContextError> {
+        context_loader_from_map(self.contexts())
+            .map_err(|e| JsonLdContextError::Loader(format!("{e:?}")))
+    }
+}
+
+/// Return the URLs of the contexts referenced by a JSON-LD document.
+pub(crate) fn context_urls(document: &Json) -> Vec<String> {
+    match &document["@context"] {
+        Json::String(url) => vec![url.clone()],
+        Json::Array(contexts) => contexts
+            .iter()
+            .filter_map(Json::as_str)
+            .map(Into::into)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_contexts() {
+        let cache = JsonLdContextCache::new();
+        let url = "https://example.com/contexts/degree/v1".to_string();
+
+        assert!(matches!(
+            cache.insert(url.clone(), "not json".into()),
+            Err(JsonLdContextError::InvalidDocument { .. })
+        ));
+        cache
+            .insert(
+                url.clone(),
+                r#"{ "@context": { "degree": "https://example.com/#degree" } }"#.into(),
+            )
+            .unwrap();
+        assert!(cache.contains(&url));
+        assert!(cache.loader().is_ok());
+
+        let document = serde_json::json!({
+            "@context": [
+                "https://www.w3.org/2018/credentials/v1",
+                url.clone(),
+                { "name": "https://schema.org/name" }
+            ]
+        });
+        assert_eq!(
+            context_urls(&document),
+            vec!["https://www.w3.org/2018/credentials/v1", url.as_str()]
+        );
+
+        cache.clear();
+        assert!(cache.contexts().is_empty());
+    }
+}
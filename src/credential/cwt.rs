@@ -0,0 +1,317 @@
+//! CBOR Web Token (CWT) credentials, secured with a COSE_Sign1 of the issuer,
+//! as issued in some ecosystems, e.g. national IDs, that use the claims of
+//! ISO 18013-5 without the structure of mdocs.
+//!
+//! The claims are exposed as JSON, with the registered claims of RFC 8392 and
+//! RFC 8747 under their JWT names, e.g. `exp`, so that presentation
+//! definitions and validity checks apply to them, and byte strings base64url
+//! encoded. Presentation definitions select them with the `cwt` format.
+//!
+//! NOTE: CWTs are presented whole, as they have no selective disclosure, and
+//! without proof of possession of a holder key.
+
+use super::{Credential, CredentialFormat};
+use crate::{oid4vp::permission_request::RequestedField, CredentialType, KeyAlias};
+
+use std::sync::Arc;
+
+use base64::prelude::*;
+use openid4vp::core::{
+    presentation_definition::PresentationDefinition, response::parameters::VpTokenItem,
+};
+use serde_cbor::Value as Cbor;
+use serde_json::{Map, Value as Json};
+use uuid::Uuid;
+
+/// The CBOR tag of CWTs.
+const CWT_TAG: u64 = 61;
+
+/// The CBOR tag of COSE_Sign1 structures.
+const COSE_SIGN1_TAG: u64 = 18;
+
+/// The JWT names of the registered CWT claims, by key.
+const REGISTERED_CLAIMS: &[(i128, &str)] = &[
+    (1, "iss"),
+    (2, "sub"),
+    (3, "aud"),
+    (4, "exp"),
+    (5, "nbf"),
+    (6, "iat"),
+    (7, "cti"),
+    (8, "cnf"),
+];
+
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum CwtError {
+    #[error("Failed to decode the CWT: {0}")]
+    Decoding(String),
+    #[error("The CWT is not a COSE_Sign1")]
+    NotCoseSign1,
+    #[error("The payload of the CWT is not a map of claims")]
+    InvalidClaims,
+}
+
+/// A CWT credential.
+#[derive(Debug, uniffi::Object)]
+pub struct Cwt {
+    pub(crate) id: Uuid,
+    pub(crate) key_alias: Option<KeyAlias>,
+    pub(crate) r#type: CredentialType,
+    /// The claims of the credential, as JSON.
+    pub(crate) claims: Json,
+    /// The COSE_Sign1 of the credential, as issued.
+    pub(crate) inner: Vec<u8>,
+}
+
+#[uniffi::export]
+impl Cwt {
+    /// Parse a CWT from its CBOR encoding, tagged or not, with the type it
+    /// was issued as, since CWTs do not identify their type.
+    #[uniffi::constructor]
+    pub fn new_from_cbor(cbor: Vec<u8>, r#type: CredentialType) -> Result<Arc<Self>, CwtError> {
+        Ok(Arc::new(Self::from_cbor(
+            Uuid::new_v4(),
+            cbor,
+            r#type,
+            None,
+        )?))
+    }
+
+    /// Parse a CWT from its CBOR encoding, with a provided key alias.
+    #[uniffi::constructor]
+    pub fn new_from_cbor_with_key(
+        cbor: Vec<u8>,
+        r#type: CredentialType,
+        key_alias: KeyAlias,
+    ) -> Result<Arc<Self>, CwtError> {
+        Ok(Arc::new(Self::from_cbor(
+            Uuid::new_v4(),
+            cbor,
+            r#type,
+            Some(key_alias),
+        )?))
+    }
+
+    /// Return the ID for the credential.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Return the key alias for the credential.
+    pub fn key_alias(&self) -> Option<KeyAlias> {
+        self.key_alias.clone()
+    }
+
+    /// The type of the credential.
+    pub fn r#type(&self) -> CredentialType {
+        self.r#type.clone()
+    }
+
+    /// Return the claims as a UTF-8 encoded JSON string.
+    pub fn claims_as_json_string(&self) -> String {
+        self.claims.to_string()
+    }
+}
+
+impl Cwt {
+    fn from_cbor(
+        id: Uuid,
+        cbor: Vec<u8>,
+        r#type: CredentialType,
+        key_alias: Option<KeyAlias>,
+    ) -> Result<Self, CwtError> {
+        // Stored CWTs may be base64url encoded, as they are in VP tokens.
+        let inner = match std::str::from_utf8(&cbor)
+            .ok()
+            .and_then(|encoded| BASE64_URL_SAFE_NO_PAD.decode(encoded.trim()).ok())
+        {
+            Some(decoded) => decoded,
+            None => cbor,
+        };
+
+        let mut cwt = Self {
+            id,
+            key_alias,
+            r#type,
+            claims: Json::Null,
+            inner,
+        };
+        let Cbor::Array(cose_sign1) = cwt.cose_sign1()? else {
+            return Err(CwtError::NotCoseSign1);
+        };
+        let Some(Cbor::Bytes(payload)) = cose_sign1.get(2) else {
+            return Err(CwtError::NotCoseSign1);
+        };
+        let Cbor::Map(claims) =
+            serde_cbor::from_slice(payload).map_err(|e| CwtError::Decoding(format!("{e:?}")))?
+        else {
+            return Err(CwtError::InvalidClaims);
+        };
+
+        cwt.claims = claims
+            .iter()
+            .map(|(key, value)| {
+                let name = match key {
+                    Cbor::Integer(key) => REGISTERED_CLAIMS
+                        .iter()
+                        .find(|(registered, _)| registered == key)
+                        .map(|(_, name)| name.to_string()),
+                    _ => None,
+                };
+                (name.unwrap_or_else(|| claim_name(key)), json(value))
+            })
+            .collect::<Map<_, _>>()
+            .into();
+        Ok(cwt)
+    }
+
+    /// Return the COSE_Sign1 of the credential, without the CWT tag.
+    pub(crate) fn cose_sign1(&self) -> Result<Cbor, CwtError> {
+        let mut cbor = serde_cbor::from_slice(&self.inner)
+            .map_err(|e| CwtError::Decoding(format!("{e:?}")))?;
+        loop {
+            match cbor {
+                Cbor::Tag(CWT_TAG | COSE_SIGN1_TAG, inner) => cbor = *inner,
+                cbor => return Ok(cbor),
+            }
+        }
+    }
+
+    /// Return the claims as a JSON value.
+    pub fn claims_as_json(&self) -> Json {
+        self.claims.clone()
+    }
+
+    /// Check if the credential satisfies a presentation definition.
+    pub fn check_presentation_definition(&self, definition: &PresentationDefinition) -> bool {
+        // If the credential does not match the definition requested format,
+        // then return false.
+        if !definition.format().is_empty()
+            && !definition.contains_format(CredentialFormat::Cwt.to_string().as_str())
+        {
+            return false;
+        }
+
+        definition.is_credential_match(&self.claims)
+    }
+
+    /// Return the requested fields for the credential.
+    pub fn requested_fields(
+        &self,
+        definition: &PresentationDefinition,
+    ) -> Vec<Arc<RequestedField>> {
+        definition
+            .requested_fields(&self.claims)
+            .into_iter()
+            .map(|field| RequestedField::from(field).with_descriptor_purpose(definition))
+            .map(Arc::new)
+            .collect()
+    }
+
+    /// Return the credential as a VpToken, base64url encoded.
+    pub fn as_vp_token(&self) -> VpTokenItem {
+        VpTokenItem::String(BASE64_URL_SAFE_NO_PAD.encode(&self.inner))
+    }
+}
+
+impl TryFrom<Credential> for Arc<Cwt> {
+    type Error = CwtError;
+
+    fn try_from(credential: Credential) -> Result<Self, Self::Error> {
+        Ok(Arc::new(Cwt::from_cbor(
+            credential.id,
+            credential.payload,
+            credential.r#type,
+            credential.key_alias,
+        )?))
+    }
+}
+
+/// Return the JSON name of a claim key.
+fn claim_name(key: &Cbor) -> String {
+    match key {
+        Cbor::Text(name) => name.clone(),
+        Cbor::Integer(key) => key.to_string(),
+        key => json(key).to_string(),
+    }
+}
+
+/// Convert a CBOR value to JSON, base64url encoding byte strings.
+fn json(value: &Cbor) -> Json {
+    match value {
+        Cbor::Bool(value) => Json::Bool(*value),
+        Cbor::Integer(value) => i64::try_from(*value)
+            .map(Json::from)
+            .unwrap_or_else(|_| Json::String(value.to_string())),
+        Cbor::Float(value) => serde_json::Number::from_f64(*value)
+            .map(Json::Number)
+            .unwrap_or(Json::Null),
+        Cbor::Bytes(bytes) => Json::String(BASE64_URL_SAFE_NO_PAD.encode(bytes)),
+        Cbor::Text(text) => Json::String(text.clone()),
+        Cbor::Array(values) => Json::Array(values.iter().map(json).collect()),
+        Cbor::Map(entries) => entries
+            .iter()
+            .map(|(key, value)| (claim_name(key), json(value)))
+            .collect::<Map<_, _>>()
+            .into(),
+        Cbor::Tag(_, value) => json(value),
+        _ => Json::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    fn cwt() -> Vec<u8> {
+        let claims = BTreeMap::from([
+            (
+                Cbor::Integer(1),
+                Cbor::Text("https://issuer.example.com".into()),
+            ),
+            (Cbor::Integer(4), Cbor::Integer(4_102_444_800)),
+            (Cbor::Text("given_name".into()), Cbor::Text("Alice".into())),
+            (Cbor::Text("portrait".into()), Cbor::Bytes(vec![0xff, 0xd8])),
+        ]);
+        let protected = BTreeMap::from([(Cbor::Integer(1), Cbor::Integer(-7))]);
+
+        serde_cbor::to_vec(&Cbor::Array(vec![
+            Cbor::Bytes(serde_cbor::to_vec(&Cbor::Map(protected)).unwrap()),
+            Cbor::Map(BTreeMap::new()),
+            Cbor::Bytes(serde_cbor::to_vec(&Cbor::Map(claims)).unwrap()),
+            Cbor::Bytes(vec![0; 64]),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_cwts() {
+        let cwt = Cwt::new_from_cbor(cwt(), CredentialType("identity".into())).unwrap();
+        assert_eq!(cwt.claims["iss"], "https://issuer.example.com");
+        assert_eq!(cwt.claims["exp"], 4_102_444_800u64);
+        assert_eq!(cwt.claims["portrait"], "_9g");
+
+        let definition = |format: &str| -> PresentationDefinition {
+            serde_json::from_value(serde_json::json!({
+                "id": "identity",
+                "input_descriptors": [{
+                    "id": "identity",
+                    "format": { format: {} },
+                    "constraints": { "fields": [{ "path": ["$.given_name"] }] }
+                }]
+            }))
+            .unwrap()
+        };
+        assert!(cwt.check_presentation_definition(&definition("cwt")));
+        assert!(!cwt.check_presentation_definition(&definition("mso_mdoc")));
+        assert_eq!(cwt.requested_fields(&definition("cwt")).len(), 1);
+
+        let VpTokenItem::String(token) = cwt.as_vp_token() else {
+            panic!("expected a base64url encoded CWT");
+        };
+        let stored = Cwt::new_from_cbor(token.into_bytes(), cwt.r#type()).unwrap();
+        assert_eq!(stored.claims, cwt.claims);
+    }
+}
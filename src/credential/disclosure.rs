@@ -0,0 +1,731 @@
+//! Selective disclosure for SD-JWT credentials.
+//!
+//! Each disclosure of an SD-JWT is mapped to the location (a list of JSON
+//! pointer segments) of the claim it reveals in the revealed credential, so
+//! that the disclosures released in a presentation can be computed from the
+//! fields requested by a presentation definition.
+
+use super::age_predicates;
+use super::claims::json_leaf;
+use crate::oid4vp::permission_request::RequestedField;
+
+use std::sync::Arc;
+
+use base64::prelude::*;
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// The location of a claim in a JSON document, as JSON pointer segments.
+pub(crate) type Pointer = Vec<String>;
+
+/// A single disclosure of an SD-JWT.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Disclosure {
+    /// The disclosure as it appears in the compact SD-JWT.
+    pub encoded: String,
+    /// The location of the disclosed claim in the revealed credential.
+    pub pointer: Pointer,
+    /// The claim name, for object property disclosures.
+    pub name: Option<String>,
+    /// The disclosed value.
+    pub value: Json,
+}
+
+/// A compact SD-JWT split into its issuer-signed JWT and disclosures.
+#[derive(Debug, Clone)]
+pub(crate) struct DisclosedSdJwt {
+    pub issuer_jwt: String,
+    pub disclosures: Vec<Disclosure>,
+}
+
+impl DisclosedSdJwt {
+    /// Parse a compact SD-JWT.
+    ///
+    /// Disclosures that are not referenced from the issuer-signed JWT, or from
+    /// another disclosure, are dropped.
+    pub fn parse(compact: &str) -> Option<Self> {
+        let mut parts = compact.split('~');
+        let issuer_jwt = parts.next()?.to_owned();
+
+        let mut candidates = parts
+            .filter(|part| !part.is_empty() && part.matches('.').count() != 2)
+            .filter_map(|encoded| {
+                let decoded = BASE64_URL_SAFE_NO_PAD.decode(encoded).ok()?;
+                let Json::Array(array) = serde_json::from_slice(&decoded).ok()? else {
+                    return None;
+                };
+                let digest = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(encoded.as_bytes()));
+                Some((digest, encoded.to_owned(), array))
+            })
+            .collect::<Vec<_>>();
+
+        let payload = jwt_payload(&issuer_jwt)?;
+
+        let mut disclosures = vec![];
+        collect(&payload, &mut vec![], &mut candidates, &mut disclosures);
+
+        Some(Self {
+            issuer_jwt,
+            disclosures,
+        })
+    }
+
+    /// Return the disclosures needed to reveal the claims at the pointers.
+    ///
+    /// This includes the disclosures of every claim on the way to a selected
+    /// claim, and every disclosure nested inside a selected claim.
+    pub fn select<'a>(&'a self, pointers: &[Pointer]) -> Vec<&'a Disclosure> {
+        self.disclosures
+            .iter()
+            .filter(|disclosure| {
+                pointers.iter().any(|pointer| {
+                    pointer.starts_with(&disclosure.pointer)
+                        || disclosure.pointer.starts_with(pointer)
+                })
+            })
+            .collect()
+    }
+
+    /// Return the claims of the issuer-signed JWT, with every disclosure
+    /// revealed.
+    ///
+    /// Array elements that are not disclosed are removed.
+    pub fn reveal(&self) -> Option<Json> {
+        let mut claims = jwt_payload(&self.issuer_jwt)?;
+
+        // Disclosures are ordered from the outermost claim inwards, so the
+        // enclosing claims are revealed first.
+        for disclosure in self.disclosures.iter() {
+            let (last, parent) = disclosure.pointer.split_last()?;
+            match value_at_mut(&mut claims, parent)? {
+                Json::Object(object) => {
+                    object.insert(last.clone(), disclosure.value.clone());
+                }
+                Json::Array(array) => {
+                    *array.get_mut(last.parse::<usize>().ok()?)? = disclosure.value.clone();
+                }
+                _ => return None,
+            }
+        }
+
+        remove_digests(&mut claims);
+        Some(claims)
+    }
+
+    /// Encode a presentation of the SD-JWT releasing the given disclosures.
+    pub fn present(&self, disclosures: &[&Disclosure]) -> String {
+        let mut compact = self.issuer_jwt.clone();
+        for disclosure in disclosures {
+            compact.push('~');
+            compact.push_str(&disclosure.encoded);
+        }
+        compact.push('~');
+        compact
+    }
+}
+
+/// Decode the payload of a compact JWT.
+fn jwt_payload(jwt: &str) -> Option<Json> {
+    let payload = BASE64_URL_SAFE_NO_PAD.decode(jwt.split('.').nth(1)?).ok()?;
+    serde_json::from_slice(&payload).ok()
+}
+
+/// Return the value at a pointer, for modification.
+fn value_at_mut<'a>(json: &'a mut Json, pointer: &[String]) -> Option<&'a mut Json> {
+    pointer.iter().try_fold(json, |value, segment| match value {
+        Json::Object(object) => object.get_mut(segment),
+        Json::Array(array) => array.get_mut(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Remove the digests of the claims that are not disclosed.
+fn remove_digests(value: &mut Json) {
+    match value {
+        Json::Object(object) => {
+            object.remove("_sd");
+            object.remove("_sd_alg");
+            object.values_mut().for_each(remove_digests);
+        }
+        Json::Array(array) => {
+            array.retain(|element| {
+                !element
+                    .as_object()
+                    .is_some_and(|object| object.len() == 1 && object.contains_key("..."))
+            });
+            array.iter_mut().for_each(remove_digests);
+        }
+        _ => {}
+    }
+}
+
+/// Remove the disclosure with the given digest from the candidates.
+fn take(
+    candidates: &mut Vec<(String, String, Vec<Json>)>,
+    digest: &str,
+) -> Option<(String, String, Vec<Json>)> {
+    let idx = candidates.iter().position(|(d, _, _)| d == digest)?;
+    Some(candidates.swap_remove(idx))
+}
+
+fn collect(
+    value: &Json,
+    pointer: &mut Pointer,
+    candidates: &mut Vec<(String, String, Vec<Json>)>,
+    disclosures: &mut Vec<Disclosure>,
+) {
+    match value {
+        Json::Object(object) => {
+            let digests = object
+                .get("_sd")
+                .and_then(Json::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Json::as_str)
+                .filter_map(|digest| take(candidates, digest))
+                .collect::<Vec<_>>();
+
+            for (_, encoded, array) in digests {
+                let [_, Json::String(name), value] = array.as_slice() else {
+                    continue;
+                };
+                pointer.push(name.clone());
+                disclosures.push(Disclosure {
+                    encoded,
+                    pointer: pointer.clone(),
+                    name: Some(name.clone()),
+                    value: value.clone(),
+                });
+                collect(value, pointer, candidates, disclosures);
+                pointer.pop();
+            }
+
+            for (key, value) in object {
+                if key == "_sd" || key == "_sd_alg" {
+                    continue;
+                }
+                pointer.push(key.clone());
+                collect(value, pointer, candidates, disclosures);
+                pointer.pop();
+            }
+        }
+        Json::Array(array) => {
+            for (idx, element) in array.iter().enumerate() {
+                pointer.push(idx.to_string());
+
+                let digest = element
+                    .as_object()
+                    .filter(|object| object.len() == 1)
+                    .and_then(|object| object.get("..."))
+                    .and_then(Json::as_str);
+
+                match digest.and_then(|digest| take(candidates, digest)) {
+                    Some((_, encoded, array)) => {
+                        if let [_, value] = array.as_slice() {
+                            disclosures.push(Disclosure {
+                                encoded,
+                                pointer: pointer.clone(),
+                                name: None,
+                                value: value.clone(),
+                            });
+                            collect(value, pointer, candidates, disclosures);
+                        }
+                    }
+                    None => collect(element, pointer, candidates, disclosures),
+                }
+
+                pointer.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A segment of a JSONPath expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PathSegment {
+    Name(String),
+    Index(usize),
+    Wildcard,
+}
+
+/// Parse the subset of JSONPath used by presentation definitions: dot and
+/// bracket member names, array indices, and wildcards.
+pub(crate) fn parse_path(path: &str) -> Option<Vec<PathSegment>> {
+    let mut rest = path.trim().strip_prefix('$')?;
+    let mut segments = vec![];
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            let inner = after[..end].trim();
+            segments.push(match inner {
+                "*" => PathSegment::Wildcard,
+                _ if inner.starts_with(['\'', '"']) => {
+                    PathSegment::Name(inner.trim_matches(['\'', '"']).to_owned())
+                }
+                _ => PathSegment::Index(inner.parse().ok()?),
+            });
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            segments.push(match &after[..end] {
+                "" => return None,
+                "*" => PathSegment::Wildcard,
+                name => PathSegment::Name(name.to_owned()),
+            });
+            rest = &after[end..];
+        } else {
+            return None;
+        }
+    }
+
+    Some(segments)
+}
+
+/// Return the pointers of the values selected by a JSONPath expression.
+pub(crate) fn select_path(json: &Json, path: &str) -> Vec<Pointer> {
+    let Some(segments) = parse_path(path) else {
+        return vec![];
+    };
+
+    let mut selected = vec![(vec![], json)];
+    for segment in segments {
+        selected = selected
+            .into_iter()
+            .flat_map(|(pointer, value)| {
+                let children: Vec<(String, &Json)> = match (&segment, value) {
+                    (PathSegment::Name(name), Json::Object(object)) => object
+                        .get(name)
+                        .map(|v| (name.clone(), v))
+                        .into_iter()
+                        .collect(),
+                    (PathSegment::Index(idx), Json::Array(array)) => array
+                        .get(*idx)
+                        .map(|v| (idx.to_string(), v))
+                        .into_iter()
+                        .collect(),
+                    (PathSegment::Wildcard, Json::Object(object)) => {
+                        object.iter().map(|(k, v)| (k.clone(), v)).collect()
+                    }
+                    (PathSegment::Wildcard, Json::Array(array)) => array
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, v)| (idx.to_string(), v))
+                        .collect(),
+                    _ => vec![],
+                };
+
+                children.into_iter().map(move |(segment, child)| {
+                    let mut pointer = pointer.clone();
+                    pointer.push(segment);
+                    (pointer, child)
+                })
+            })
+            .collect();
+    }
+
+    selected.into_iter().map(|(pointer, _)| pointer).collect()
+}
+
+/// Return the value at a pointer.
+pub(crate) fn value_at<'a>(json: &'a Json, pointer: &Pointer) -> Option<&'a Json> {
+    pointer.iter().try_fold(json, |value, segment| match value {
+        Json::Object(object) => object.get(segment),
+        Json::Array(array) => array.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Return the JSONPath of the value at a pointer, e.g.
+/// `$['credentialSubject']['nationalities'][1]`.
+pub(crate) fn json_path(json: &Json, pointer: &Pointer) -> String {
+    let mut path = String::from("$");
+    let mut value = Some(json);
+    for segment in pointer {
+        match value {
+            Some(Json::Array(array)) => {
+                path.push_str(&format!("[{segment}]"));
+                value = segment.parse::<usize>().ok().and_then(|idx| array.get(idx));
+            }
+            _ => {
+                path.push_str(&format!("['{}']", segment.replace('\'', "\\'")));
+                value = value.and_then(|value| value.get(segment));
+            }
+        }
+    }
+    path
+}
+
+/// Return the fields of the credential requested by the presentation
+/// definition, along with the location of the claims they select.
+///
+/// As with constraint evaluation, the first path of a field that selects a
+/// value is used.
+pub(crate) fn requested_fields(
+    credential: &Json,
+    definition: &PresentationDefinition,
+) -> Vec<RequestedField> {
+    let Ok(definition) = serde_json::to_value(definition) else {
+        return vec![];
+    };
+
+    let mut fields = vec![];
+    for descriptor in definition["input_descriptors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let input_descriptor_id = descriptor["id"].as_str().unwrap_or_default().to_owned();
+
+        for field in descriptor["constraints"]["fields"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let paths = || {
+                field["path"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Json::as_str)
+            };
+            let Some((path, pointers)) = paths()
+                .map(|path| (path, select_path(credential, path)))
+                .find(|(_, pointers)| !pointers.is_empty())
+                // Age predicates the credential does not attest are answered
+                // with the nearest one it attests.
+                .or_else(|| {
+                    paths().find_map(|path| {
+                        age_predicates::nearest_attested(credential, path)
+                            .map(|(pointer, _)| (path, vec![pointer]))
+                    })
+                })
+            else {
+                continue;
+            };
+
+            fields.push(RequestedField {
+                id: Uuid::new_v4(),
+                name: field["name"].as_str().map(ToOwned::to_owned),
+                required: !field["optional"].as_bool().unwrap_or(false),
+                retained: field["intent_to_retain"].as_bool().unwrap_or(false),
+                purpose: field["purpose"]
+                    .as_str()
+                    .or_else(|| descriptor["purpose"].as_str())
+                    .map(ToOwned::to_owned),
+                input_descriptor_id: input_descriptor_id.clone(),
+                raw_fields: pointers
+                    .iter()
+                    .filter_map(|pointer| value_at(credential, pointer))
+                    .cloned()
+                    .collect(),
+                values: pointers
+                    .iter()
+                    .filter_map(|pointer| value_at(credential, pointer))
+                    .map(json_leaf)
+                    .collect(),
+                pointers,
+                path: Some(path.to_owned()),
+                mdoc_element: None,
+                filter: field.get("filter").cloned(),
+                label: None,
+            });
+        }
+    }
+
+    fields
+}
+
+/// Return the purpose of an input descriptor of the presentation definition.
+pub(crate) fn descriptor_purpose(
+    definition: &PresentationDefinition,
+    input_descriptor_id: &str,
+) -> Option<String> {
+    let definition = serde_json::to_value(definition).ok()?;
+    definition["input_descriptors"]
+        .as_array()?
+        .iter()
+        .find(|descriptor| descriptor["id"] == input_descriptor_id)?["purpose"]
+        .as_str()
+        .map(ToOwned::to_owned)
+}
+
+/// Check whether the input descriptors require disclosure to be limited to
+/// the requested fields.
+pub(crate) fn limit_disclosure_required(
+    definition: &PresentationDefinition,
+    input_descriptor_ids: &[&str],
+) -> bool {
+    let Ok(definition) = serde_json::to_value(definition) else {
+        return false;
+    };
+
+    let mut descriptors = definition["input_descriptors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|descriptor| {
+            input_descriptor_ids.is_empty()
+                || descriptor["id"]
+                    .as_str()
+                    .is_some_and(|id| input_descriptor_ids.contains(&id))
+        })
+        .peekable();
+
+    descriptors.peek().is_some()
+        && descriptors.all(|descriptor| descriptor["constraints"]["limit_disclosure"] == "required")
+}
+
+/// Return the fields that will be released when presenting the SD-JWT.
+///
+/// When disclosure is limited, only the requested fields are released.
+/// Otherwise the full credential is released, and every disclosure not covered
+/// by a requested field is added as an optional field, so that the holder can
+/// see everything that will be shared.
+pub(crate) fn released_fields(
+    sd_jwt: &DisclosedSdJwt,
+    credential: &Json,
+    definition: &PresentationDefinition,
+) -> Vec<Arc<RequestedField>> {
+    let mut fields = requested_fields(credential, definition);
+
+    let input_descriptor_ids = fields
+        .iter()
+        .map(|field| field.input_descriptor_id.clone())
+        .collect::<Vec<_>>();
+    let input_descriptor_ids = input_descriptor_ids
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    if !limit_disclosure_required(definition, &input_descriptor_ids) {
+        let input_descriptor_id = input_descriptor_ids.first().copied().unwrap_or_default();
+        let mut covered = fields
+            .iter()
+            .flat_map(|field| field.pointers.iter().cloned())
+            .collect::<Vec<_>>();
+
+        // Disclosures are ordered from the outermost claim inwards, so nested
+        // disclosures are covered by their enclosing claim.
+        for disclosure in sd_jwt.disclosures.iter() {
+            if covered.iter().any(|pointer| {
+                disclosure.pointer.starts_with(pointer) || pointer.starts_with(&disclosure.pointer)
+            }) {
+                continue;
+            }
+
+            covered.push(disclosure.pointer.clone());
+            fields.push(RequestedField {
+                id: Uuid::new_v4(),
+                name: disclosure
+                    .name
+                    .clone()
+                    .or_else(|| disclosure.pointer.last().cloned()),
+                required: false,
+                retained: false,
+                purpose: descriptor_purpose(definition, input_descriptor_id),
+                input_descriptor_id: input_descriptor_id.to_owned(),
+                raw_fields: vec![disclosure.value.clone()],
+                values: vec![json_leaf(&disclosure.value)],
+                pointers: vec![disclosure.pointer.clone()],
+                path: Some(json_path(credential, &disclosure.pointer)),
+                mdoc_element: None,
+                filter: None,
+                label: None,
+            });
+        }
+    }
+
+    fields.into_iter().map(Arc::new).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disclosure(value: Json) -> (String, String) {
+        let encoded = BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let digest = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(encoded.as_bytes()));
+        (encoded, digest)
+    }
+
+    fn sd_jwt() -> DisclosedSdJwt {
+        let (given_name, given_name_digest) =
+            disclosure(serde_json::json!(["salt1", "given_name", "Alice"]));
+        let (street, street_digest) =
+            disclosure(serde_json::json!(["salt2", "street", "1 Main St"]));
+        let (address, address_digest) = disclosure(serde_json::json!([
+            "salt3",
+            "address",
+            { "_sd": [street_digest], "locality": "Springfield" }
+        ]));
+        let (nationality, nationality_digest) = disclosure(serde_json::json!(["salt4", "FR"]));
+
+        let payload = serde_json::json!({
+            "credentialSubject": {
+                "_sd": [given_name_digest, address_digest],
+                "nationalities": [{ "...": nationality_digest }, "DE"],
+            },
+            "_sd_alg": "sha-256",
+        });
+        let compact = format!(
+            "eyJhbGciOiJFUzI1NiJ9.{}.c2ln~{given_name}~{address}~{street}~{nationality}~",
+            BASE64_URL_SAFE_NO_PAD.encode(payload.to_string())
+        );
+
+        DisclosedSdJwt::parse(&compact).unwrap()
+    }
+
+    fn pointer(segments: &[&str]) -> Pointer {
+        segments.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn maps_disclosures_to_claims() {
+        let sd_jwt = sd_jwt();
+        let pointers = sd_jwt
+            .disclosures
+            .iter()
+            .map(|disclosure| disclosure.pointer.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            pointers,
+            vec![
+                pointer(&["credentialSubject", "given_name"]),
+                pointer(&["credentialSubject", "address"]),
+                pointer(&["credentialSubject", "address", "street"]),
+                pointer(&["credentialSubject", "nationalities", "0"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn selects_enclosing_and_nested_disclosures() {
+        let sd_jwt = sd_jwt();
+
+        let street = sd_jwt.select(&[pointer(&["credentialSubject", "address", "street"])]);
+        assert_eq!(street.len(), 2);
+
+        let address = sd_jwt.select(&[pointer(&["credentialSubject", "address"])]);
+        assert_eq!(address, street);
+
+        let given_name = sd_jwt.select(&[pointer(&["credentialSubject", "given_name"])]);
+        assert_eq!(given_name.len(), 1);
+        assert!(sd_jwt
+            .present(&given_name)
+            .ends_with(&format!("~{}~", given_name[0].encoded)));
+    }
+
+    #[test]
+    fn reveals_disclosed_claims() {
+        let mut sd_jwt = sd_jwt();
+        assert_eq!(
+            sd_jwt.reveal().unwrap(),
+            serde_json::json!({
+                "credentialSubject": {
+                    "given_name": "Alice",
+                    "address": { "street": "1 Main St", "locality": "Springfield" },
+                    "nationalities": ["FR", "DE"],
+                }
+            })
+        );
+
+        // Claims that are not disclosed are left out.
+        sd_jwt
+            .disclosures
+            .retain(|disclosure| disclosure.name.is_some());
+        assert_eq!(
+            sd_jwt.reveal().unwrap()["credentialSubject"]["nationalities"],
+            serde_json::json!(["DE"])
+        );
+    }
+
+    #[rstest::rstest]
+    #[case::dot("$.credentialSubject.given_name", vec![pointer(&["credentialSubject", "given_name"])])]
+    #[case::bracket("$['credentialSubject']['given_name']", vec![pointer(&["credentialSubject", "given_name"])])]
+    #[case::index("$.credentialSubject.nationalities[1]", vec![pointer(&["credentialSubject", "nationalities", "1"])])]
+    #[case::wildcard("$.credentialSubject.nationalities[*]", vec![
+        pointer(&["credentialSubject", "nationalities", "0"]),
+        pointer(&["credentialSubject", "nationalities", "1"]),
+    ])]
+    #[case::missing("$.credentialSubject.family_name", vec![])]
+    fn selects_paths(#[case] path: &str, #[case] expected: Vec<Pointer>) {
+        let credential = serde_json::json!({
+            "credentialSubject": {
+                "given_name": "Alice",
+                "nationalities": ["FR", "DE"],
+            }
+        });
+
+        assert_eq!(select_path(&credential, path), expected);
+    }
+
+    #[test]
+    fn identifies_requested_fields() {
+        let definition: PresentationDefinition = serde_json::from_value(serde_json::json!({
+            "id": "identity",
+            "input_descriptors": [{
+                "id": "identity",
+                "constraints": {
+                    "fields": [{
+                        "name": "Nationalité",
+                        "path": ["$.nationality", "$.credentialSubject.nationalities[1]"]
+                    }]
+                }
+            }]
+        }))
+        .unwrap();
+        let credential = serde_json::json!({
+            "credentialSubject": { "nationalities": ["FR", "DE"] }
+        });
+
+        let fields = requested_fields(&credential, &definition);
+        assert_eq!(
+            fields[0].path.as_deref(),
+            Some("$.credentialSubject.nationalities[1]")
+        );
+        assert_eq!(
+            fields[0].pointers[0],
+            pointer(&["credentialSubject", "nationalities", "1"])
+        );
+        assert_eq!(
+            json_path(&credential, &fields[0].pointers[0]),
+            "$['credentialSubject']['nationalities'][1]"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_descriptor_purposes() {
+        let definition: PresentationDefinition = serde_json::from_value(serde_json::json!({
+            "id": "identity",
+            "input_descriptors": [{
+                "id": "identity",
+                "purpose": "To verify your identity",
+                "constraints": {
+                    "fields": [
+                        { "path": ["$.given_name"] },
+                        { "path": ["$.birthdate"], "purpose": "To check your age" }
+                    ]
+                }
+            }]
+        }))
+        .unwrap();
+        let credential = serde_json::json!({ "given_name": "Alice", "birthdate": "1990-01-01" });
+
+        let purposes = requested_fields(&credential, &definition)
+            .into_iter()
+            .map(|field| field.purpose)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            purposes,
+            vec![
+                Some("To verify your identity".into()),
+                Some("To check your age".into())
+            ]
+        );
+    }
+}
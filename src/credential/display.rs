@@ -0,0 +1,257 @@
+use super::ParsedCredential;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+/// An image to display for a credential, such as the issuer logo.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct DisplayImage {
+    /// The URI of the image, which may be a `data:` URI.
+    #[serde(alias = "url")]
+    pub uri: String,
+    pub alt_text: Option<String>,
+}
+
+/// Display metadata for a credential, as provided by the issuer in the
+/// `display` property of the OID4VCI credential configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct CredentialDisplay {
+    pub name: String,
+    /// The BCP 47 language tag of the display, if any.
+    pub locale: Option<String>,
+    pub description: Option<String>,
+    pub logo: Option<DisplayImage>,
+    pub background_image: Option<DisplayImage>,
+    /// The background color of the card, as a CSS color value.
+    pub background_color: Option<String>,
+    /// The text color of the card, as a CSS color value.
+    pub text_color: Option<String>,
+    /// The labels of the claims of the credential, in the locale of the
+    /// display.
+    #[serde(default)]
+    pub claims: Vec<ClaimDisplay>,
+}
+
+/// The label of a claim, from the claims metadata of the credential
+/// configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct ClaimDisplay {
+    /// The location of the claim in the credential, e.g.
+    /// `["credentialSubject", "birthDate"]`, or the namespace and identifier
+    /// of mdoc data elements.
+    pub path: Vec<String>,
+    pub name: String,
+}
+
+/// Parse the `display` property of a credential configuration, along with
+/// the labels of its claims.
+///
+/// Entries that do not have a name are skipped.
+///
+/// NOTE: claim labels are attached to the displays of the same locale, so
+/// labels in locales the credential has no display for are dropped.
+pub(crate) fn credential_display_from_configuration(
+    configuration: &Json,
+) -> Vec<CredentialDisplay> {
+    let mut displays: Vec<CredentialDisplay> = configuration
+        .get("display")
+        .and_then(Json::as_array)
+        .map(|displays| {
+            displays
+                .iter()
+                .filter_map(|display| serde_json::from_value(display.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let claims = claim_displays(configuration);
+    for display in &mut displays {
+        display.claims = claims
+            .iter()
+            .filter(|(_, locale, _)| {
+                locale.as_deref().map(str::to_lowercase)
+                    == display.locale.as_deref().map(str::to_lowercase)
+            })
+            .map(|(path, _, name)| ClaimDisplay {
+                path: path.clone(),
+                name: name.clone(),
+            })
+            .collect();
+    }
+    displays
+}
+
+/// The path, locale and name of the labels of claims.
+type ClaimLabels = Vec<(Vec<String>, Option<String>, String)>;
+
+/// Parse the claim labels of a credential configuration.
+///
+/// Claims are described by an array of claims descriptions with paths, as in
+/// OID4VCI 1.0, or by objects nested by claim name, as in earlier drafts.
+fn claim_displays(configuration: &Json) -> ClaimLabels {
+    fn labels(path: &[String], claim: &Json, claims: &mut ClaimLabels) {
+        for display in claim
+            .get("display")
+            .and_then(Json::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(name) = display.get("name").and_then(Json::as_str) {
+                let locale = display.get("locale").and_then(Json::as_str);
+                claims.push((
+                    path.to_vec(),
+                    locale.map(ToOwned::to_owned),
+                    name.to_owned(),
+                ));
+            }
+        }
+    }
+
+    fn nested(path: Vec<String>, claim: &Json, claims: &mut ClaimLabels) {
+        labels(&path, claim, claims);
+        for (name, value) in claim.as_object().into_iter().flatten() {
+            if name != "display" && value.is_object() {
+                let mut path = path.clone();
+                path.push(name.clone());
+                nested(path, value, claims);
+            }
+        }
+    }
+
+    let mut claims = vec![];
+    let metadata = configuration
+        .get("credential_metadata")
+        .unwrap_or(configuration);
+    match metadata.get("claims") {
+        Some(Json::Array(descriptions)) => {
+            for description in descriptions {
+                let path = description
+                    .get("path")
+                    .and_then(Json::as_array)
+                    .into_iter()
+                    .flatten()
+                    .map(|segment| match segment {
+                        Json::String(segment) => segment.clone(),
+                        segment => segment.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                labels(&path, description, &mut claims);
+            }
+        }
+        Some(claim @ Json::Object(_)) => nested(vec![], claim, &mut claims),
+        _ => {}
+    }
+    if let Some(subject) = configuration.pointer("/credential_definition/credentialSubject") {
+        nested(vec!["credentialSubject".into()], subject, &mut claims);
+    }
+    claims
+}
+
+/// Whether a locale matches a preferred locale, or one of its more specific
+/// variants, e.g. `en-US` matches `en`.
+pub(crate) fn locale_matches(locale: &str, preferred: &str) -> bool {
+    let (locale, preferred) = (locale.to_lowercase(), preferred.to_lowercase());
+    locale == preferred || locale.starts_with(&format!("{preferred}-"))
+}
+
+/// Select the display best matching the preferred locales, in order of
+/// preference.
+///
+/// A locale matches its more specific variants, e.g. `en` matches `en-US`.
+/// Falls back to the display without a locale, then to the first one.
+pub(crate) fn select_display<'a>(
+    displays: &'a [CredentialDisplay],
+    preferred_locales: &[String],
+) -> Option<&'a CredentialDisplay> {
+    preferred_locales
+        .iter()
+        .find_map(|preferred| {
+            displays.iter().find(|display| {
+                display
+                    .locale
+                    .as_deref()
+                    .is_some_and(|locale| locale_matches(locale, preferred))
+            })
+        })
+        .or_else(|| displays.iter().find(|display| display.locale.is_none()))
+        .or_else(|| displays.first())
+}
+
+#[uniffi::export]
+impl ParsedCredential {
+    /// Return the display metadata of the credential, for every locale the
+    /// issuer provided.
+    pub fn display(&self) -> Vec<CredentialDisplay> {
+        self.display.clone()
+    }
+
+    /// Return the display metadata best matching the preferred locales, in
+    /// order of preference.
+    pub fn localized_display(&self, preferred_locales: Vec<String>) -> Option<CredentialDisplay> {
+        select_display(&self.display, &preferred_locales).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_selects_display() {
+        let configuration = serde_json::json!({
+            "format": "jwt_vc_json",
+            "display": [
+                {
+                    "name": "University Credential",
+                    "locale": "en-US",
+                    "logo": {
+                        "uri": "https://university.example.edu/public/logo.png",
+                        "alt_text": "a square logo of a university"
+                    },
+                    "background_color": "#12107c",
+                    "text_color": "#FFFFFF"
+                },
+                { "name": "Diplôme universitaire", "locale": "fr" },
+                { "locale": "de" }
+            ],
+            "credential_definition": {
+                "credentialSubject": {
+                    "degree": {
+                        "type": {
+                            "display": [
+                                { "name": "Degree type", "locale": "en-US" },
+                                { "name": "Type de diplôme", "locale": "fr" }
+                            ]
+                        }
+                    }
+                }
+            }
+        });
+
+        let displays = credential_display_from_configuration(&configuration);
+        assert_eq!(displays.len(), 2);
+        assert_eq!(
+            displays[0].logo.as_ref().map(|logo| logo.uri.as_str()),
+            Some("https://university.example.edu/public/logo.png")
+        );
+        assert_eq!(
+            displays[1].claims,
+            vec![ClaimDisplay {
+                path: vec!["credentialSubject".into(), "degree".into(), "type".into()],
+                name: "Type de diplôme".into(),
+            }]
+        );
+
+        let select = |locales: &[&str]| {
+            let locales = locales.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+            select_display(&displays, &locales).map(|display| display.name.clone())
+        };
+        assert_eq!(
+            select(&["fr-CA", "fr"]).as_deref(),
+            Some("Diplôme universitaire")
+        );
+        assert_eq!(select(&["en"]).as_deref(), Some("University Credential"));
+        assert_eq!(select(&["ja"]).as_deref(), Some("University Credential"));
+        assert_eq!(select_display(&[], &[]), None);
+    }
+}
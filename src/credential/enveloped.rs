@@ -0,0 +1,170 @@
+//! VCDM 2.0 enveloped presentations, which wrap a secured presentation, such
+//! as an SD-JWT, in a `data:` URL of a JSON-LD `EnvelopedVerifiablePresentation`.
+
+use super::CredentialDecodingError;
+
+use base64::prelude::*;
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use serde_json::{Map, Value as Json};
+
+/// The VCDM 2.0 context.
+const VCDM2_CONTEXT: &str = "https://www.w3.org/ns/credentials/v2";
+/// The type of enveloped presentations.
+const ENVELOPED_PRESENTATION_TYPE: &str = "EnvelopedVerifiablePresentation";
+/// The media type of SD-JWT presentations.
+pub(crate) const VP_SD_JWT_MEDIA_TYPE: &str = "application/vp+sd-jwt";
+/// The value of the `envelope` parameter of a format, requesting enveloped
+/// presentations.
+const VCDM2_ENVELOPE: &str = "vcdm2";
+
+/// A presentation unwrapped from an enveloped presentation.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct EnvelopedPresentation {
+    /// The media type of the presentation, e.g. `application/vp+sd-jwt`.
+    pub media_type: String,
+    /// The presentation, e.g. a compact SD-JWT.
+    pub presentation: String,
+}
+
+/// Wrap a secured presentation in an enveloped presentation.
+#[uniffi::export]
+pub fn envelop_presentation(media_type: String, presentation: String) -> String {
+    Json::Object(envelop(&media_type, &presentation)).to_string()
+}
+
+/// Unwrap an enveloped presentation, given as JSON.
+#[uniffi::export]
+pub fn parse_enveloped_presentation(
+    json: String,
+) -> Result<EnvelopedPresentation, CredentialDecodingError> {
+    let json: Json = serde_json::from_str(&json)
+        .map_err(|e| CredentialDecodingError::Deserialization(format!("{e:?}")))?;
+    unwrap(&json)
+}
+
+/// Wrap a secured presentation in an enveloped presentation.
+///
+/// The compact serializations of JWTs and SD-JWTs only have URL safe
+/// characters, so they are not encoded.
+pub(crate) fn envelop(media_type: &str, presentation: &str) -> Map<String, Json> {
+    let mut enveloped = Map::new();
+    enveloped.insert("@context".into(), serde_json::json!([VCDM2_CONTEXT]));
+    enveloped.insert(
+        "id".into(),
+        format!("data:{media_type},{presentation}").into(),
+    );
+    enveloped.insert("type".into(), ENVELOPED_PRESENTATION_TYPE.into());
+    enveloped
+}
+
+/// Unwrap an enveloped presentation.
+pub(crate) fn unwrap(json: &Json) -> Result<EnvelopedPresentation, CredentialDecodingError> {
+    let error = |message: &str| CredentialDecodingError::Deserialization(message.into());
+
+    let is_enveloped = match &json["type"] {
+        Json::String(r#type) => r#type == ENVELOPED_PRESENTATION_TYPE,
+        Json::Array(types) => types
+            .iter()
+            .any(|r#type| r#type == ENVELOPED_PRESENTATION_TYPE),
+        _ => false,
+    };
+    if !is_enveloped {
+        return Err(error("not an enveloped presentation"));
+    }
+
+    let url = json["id"]
+        .as_str()
+        .and_then(|id| id.strip_prefix("data:"))
+        .ok_or_else(|| error("the id of the presentation is not a data URL"))?;
+    let (parameters, data) = url
+        .split_once(',')
+        .ok_or_else(|| error("the data URL has no data"))?;
+
+    let (media_type, base64) = match parameters.strip_suffix(";base64") {
+        Some(media_type) => (media_type, true),
+        None => (parameters, false),
+    };
+    let presentation = match base64 {
+        true => BASE64_STANDARD
+            .decode(data)
+            .ok()
+            .and_then(|data| String::from_utf8(data).ok())
+            .ok_or_else(|| error("the data URL is not valid base64"))?,
+        false => urlencoding::decode(data)
+            .map_err(|e| CredentialDecodingError::Deserialization(format!("{e:?}")))?
+            .into_owned(),
+    };
+
+    Ok(EnvelopedPresentation {
+        media_type: media_type.split(';').next().unwrap_or_default().to_owned(),
+        presentation,
+    })
+}
+
+/// Whether the definition requests `vcdm2_sd_jwt` credentials in enveloped
+/// presentations, with an `"envelope": "vcdm2"` parameter on the format of
+/// the input descriptor, or of the definition.
+///
+/// NOTE: the parameter is only seen if the presentation definition keeps the
+/// unknown parameters of formats when parsed.
+pub(crate) fn envelope_requested(
+    definition: &PresentationDefinition,
+    input_descriptor_id: Option<&str>,
+) -> bool {
+    serde_json::to_value(definition)
+        .is_ok_and(|definition| requests_envelope(&definition, input_descriptor_id))
+}
+
+fn requests_envelope(definition: &Json, input_descriptor_id: Option<&str>) -> bool {
+    let descriptor = definition["input_descriptors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|descriptor| {
+            input_descriptor_id.is_some_and(|id| descriptor["id"].as_str() == Some(id))
+        });
+
+    descriptor
+        .and_then(|descriptor| descriptor.get("format"))
+        .or_else(|| definition.get("format"))
+        .is_some_and(|format| format["vcdm2_sd_jwt"]["envelope"] == VCDM2_ENVELOPE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelops_presentations() {
+        let presentation =
+            "eyJhbGciOiJFUzI1NiJ9.eyJ2cCI6e319.c2ln~WyJzYWx0IiwibmFtZSIsIkFsaWNlIl0~";
+        let enveloped = Json::Object(envelop(VP_SD_JWT_MEDIA_TYPE, presentation));
+        assert_eq!(
+            unwrap(&enveloped).unwrap(),
+            EnvelopedPresentation {
+                media_type: VP_SD_JWT_MEDIA_TYPE.into(),
+                presentation: presentation.into(),
+            }
+        );
+
+        let base64 = serde_json::json!({
+            "@context": [VCDM2_CONTEXT],
+            "id": format!("data:application/vp+jwt;base64,{}", BASE64_STANDARD.encode("a.b.c")),
+            "type": [ENVELOPED_PRESENTATION_TYPE],
+        });
+        assert_eq!(unwrap(&base64).unwrap().presentation, "a.b.c");
+
+        assert!(unwrap(&serde_json::json!({ "type": "VerifiablePresentation" })).is_err());
+
+        let definition = serde_json::json!({
+            "id": "degree",
+            "input_descriptors": [{
+                "id": "degree",
+                "format": { "vcdm2_sd_jwt": { "envelope": "vcdm2" } },
+                "constraints": { "fields": [] }
+            }]
+        });
+        assert!(requests_envelope(&definition, Some("degree")));
+        assert!(!requests_envelope(&definition, Some("other")));
+    }
+}
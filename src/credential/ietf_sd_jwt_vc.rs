@@ -0,0 +1,249 @@
+use super::{
+    age_predicates,
+    disclosure::{self, DisclosedSdJwt},
+    vcdm2_sd_jwt::SdJwtError,
+    Credential, CredentialFormat,
+};
+use crate::{oid4vp::permission_request::RequestedField, CredentialType, KeyAlias};
+
+use std::sync::Arc;
+
+use openid4vp::core::{
+    presentation_definition::PresentationDefinition, response::parameters::VpTokenItem,
+};
+use serde_json::Value as Json;
+use ssi::claims::sd_jwt::SdJwtBuf;
+use uniffi::deps::log;
+use uuid::Uuid;
+
+/// The legacy format designation of IETF SD-JWT VCs.
+const LEGACY_FORMAT: &str = "vc+sd-jwt";
+
+/// An IETF SD-JWT VC, identified by its `vct` claim rather than wrapping a
+/// W3C VCDM credential.
+#[derive(Debug, uniffi::Object)]
+pub struct IetfSdJwtVc {
+    pub(crate) id: Uuid,
+    pub(crate) key_alias: Option<KeyAlias>,
+    pub(crate) vct: String,
+    /// The claims of the credential, with every disclosure revealed.
+    pub(crate) claims: Json,
+    pub(crate) inner: SdJwtBuf,
+}
+
+#[uniffi::export]
+impl IetfSdJwtVc {
+    /// Create a new IETF SD-JWT VC from a compact SD-JWT string.
+    #[uniffi::constructor]
+    pub fn new_from_compact_sd_jwt(input: String) -> Result<Arc<Self>, SdJwtError> {
+        Ok(Arc::new(Self::from_compact(Uuid::new_v4(), input, None)?))
+    }
+
+    /// Create a new IETF SD-JWT VC from a compact SD-JWT string with a
+    /// provided key alias.
+    #[uniffi::constructor]
+    pub fn new_from_compact_sd_jwt_with_key(
+        input: String,
+        key_alias: KeyAlias,
+    ) -> Result<Arc<Self>, SdJwtError> {
+        Ok(Arc::new(Self::from_compact(
+            Uuid::new_v4(),
+            input,
+            Some(key_alias),
+        )?))
+    }
+
+    /// Return the ID for the credential.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Return the key alias for the credential.
+    pub fn key_alias(&self) -> Option<KeyAlias> {
+        self.key_alias.clone()
+    }
+
+    /// The verifiable credential type, from the `vct` claim.
+    pub fn vct(&self) -> String {
+        self.vct.clone()
+    }
+
+    /// The type of this credential, which is its `vct`.
+    pub fn r#type(&self) -> CredentialType {
+        CredentialType(self.vct.clone())
+    }
+
+    /// Return the revealed claims as a UTF-8 encoded JSON string.
+    pub fn revealed_claims_as_json_string(&self) -> Result<String, SdJwtError> {
+        serde_json::to_string(&self.claims).map_err(|e| SdJwtError::Serialization(format!("{e:?}")))
+    }
+}
+
+impl IetfSdJwtVc {
+    fn from_compact(
+        id: Uuid,
+        input: String,
+        key_alias: Option<KeyAlias>,
+    ) -> Result<Self, SdJwtError> {
+        let inner = SdJwtBuf::new(input).map_err(|e| SdJwtError::InvalidSdJwt(format!("{e:?}")))?;
+
+        let claims = DisclosedSdJwt::parse(inner.as_ref())
+            .and_then(|sd_jwt| sd_jwt.reveal())
+            .ok_or_else(|| SdJwtError::SdJwtDecoding("failed to reveal the claims".into()))?;
+        let vct = claims["vct"]
+            .as_str()
+            .ok_or(SdJwtError::CredentialClaimMissing)?
+            .to_owned();
+
+        Ok(Self {
+            id,
+            key_alias,
+            vct,
+            claims,
+            inner,
+        })
+    }
+
+    /// Return the revealed claims as a JSON value.
+    pub fn revealed_claims_as_json(&self) -> Json {
+        self.claims.clone()
+    }
+
+    /// Check if the credential satisfies a presentation definition.
+    ///
+    /// Definitions usually select IETF SD-JWT VCs with a filter on the `vct`
+    /// claim, which is evaluated against the revealed claims.
+    pub fn check_presentation_definition(&self, definition: &PresentationDefinition) -> bool {
+        // If the credential does not match the definition requested format,
+        // then return false.
+        if !definition.format().is_empty()
+            && !definition.contains_format(CredentialFormat::DcSdJwt.to_string().as_str())
+            && !definition.contains_format(LEGACY_FORMAT)
+        {
+            return false;
+        }
+
+        definition.is_credential_match(&age_predicates::with_derived_predicates(
+            &self.claims,
+            definition,
+        ))
+    }
+
+    /// Return the requested fields for the credential.
+    ///
+    /// As for VCDM2 SD-JWTs, these are exactly the fields that will be
+    /// released when presenting the credential.
+    pub fn requested_fields(
+        &self,
+        definition: &PresentationDefinition,
+    ) -> Vec<Arc<RequestedField>> {
+        let Some(sd_jwt) = DisclosedSdJwt::parse(self.inner.as_ref()) else {
+            log::debug!("failed to map the disclosures of the credential: {self:?}");
+            return Vec::new();
+        };
+
+        disclosure::released_fields(&sd_jwt, &self.claims, definition)
+    }
+
+    /// Return the credential as a VpToken, releasing only the disclosures
+    /// needed for the given fields.
+    pub fn as_vp_token_with_fields(&self, fields: &[Arc<RequestedField>]) -> VpTokenItem {
+        let Some(sd_jwt) = DisclosedSdJwt::parse(self.inner.as_ref()) else {
+            return self.as_vp_token();
+        };
+
+        let pointers = fields
+            .iter()
+            .flat_map(|field| field.pointers.iter().cloned())
+            .collect::<Vec<_>>();
+
+        VpTokenItem::String(sd_jwt.present(&sd_jwt.select(&pointers)))
+    }
+
+    /// Return the credential as a VpToken, releasing every disclosure.
+    pub fn as_vp_token(&self) -> VpTokenItem {
+        let compact: &str = self.inner.as_ref();
+        VpTokenItem::String(compact.to_string())
+    }
+}
+
+impl TryFrom<Credential> for Arc<IetfSdJwtVc> {
+    type Error = SdJwtError;
+
+    fn try_from(credential: Credential) -> Result<Self, Self::Error> {
+        let input = String::from_utf8(credential.payload)
+            .map_err(|e| SdJwtError::InvalidSdJwt(format!("{e:?}")))?;
+
+        Ok(Arc::new(IetfSdJwtVc::from_compact(
+            credential.id,
+            input,
+            credential.key_alias,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base64::prelude::*;
+    use sha2::{Digest, Sha256};
+
+    fn sd_jwt_vc() -> String {
+        let encode = |value: Json| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let given_name = encode(serde_json::json!(["salt", "given_name", "Alice"]));
+        let digest = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(given_name.as_bytes()));
+
+        let header = encode(serde_json::json!({ "alg": "ES256", "typ": "dc+sd-jwt" }));
+        let payload = encode(serde_json::json!({
+            "iss": "https://issuer.example.com",
+            "vct": "https://credentials.example.com/identity_credential",
+            "_sd": [digest],
+            "_sd_alg": "sha-256",
+            "family_name": "Smith",
+        }));
+
+        format!("{header}.{payload}.c2lnbmF0dXJl~{given_name}~")
+    }
+
+    #[test]
+    fn matches_vct() {
+        let vc = IetfSdJwtVc::new_from_compact_sd_jwt(sd_jwt_vc()).unwrap();
+        assert_eq!(
+            vc.r#type().0,
+            "https://credentials.example.com/identity_credential"
+        );
+        assert_eq!(vc.claims["given_name"], "Alice");
+        assert!(vc.claims.get("_sd").is_none());
+
+        let definition = |vct: &str| -> PresentationDefinition {
+            serde_json::from_value(serde_json::json!({
+                "id": "identity",
+                "input_descriptors": [{
+                    "id": "identity",
+                    "format": { "dc+sd-jwt": {} },
+                    "constraints": {
+                        "fields": [
+                            {
+                                "path": ["$.vct"],
+                                "filter": { "type": "string", "const": vct }
+                            },
+                            { "path": ["$.given_name"] }
+                        ]
+                    }
+                }]
+            }))
+            .unwrap()
+        };
+
+        let identity = definition("https://credentials.example.com/identity_credential");
+        assert!(vc.check_presentation_definition(&identity));
+        assert!(!vc.check_presentation_definition(&definition("https://example.com/other")));
+
+        let fields = vc.requested_fields(&identity);
+        let VpTokenItem::String(token) = vc.as_vp_token_with_fields(&fields[1..2]) else {
+            panic!("expected a compact SD-JWT");
+        };
+        assert!(token.ends_with(&format!("~{}~", sd_jwt_vc().split('~').nth(1).unwrap())));
+    }
+}
@@ -1,16 +1,26 @@
+use super::context_cache::{self, JsonLdContextCache, JsonLdContextError};
 use super::{Credential, CredentialFormat, VcdmVersion};
 use crate::{oid4vp::permission_request::RequestedField, CredentialType, KeyAlias};
 
 use std::sync::Arc;
 
-use openid4vp::core::presentation_definition::PresentationDefinition;
+use openid4vp::core::{
+    presentation_definition::PresentationDefinition, response::parameters::VpTokenItem,
+};
 use serde_json::Value as Json;
 use ssi::{
-    claims::vc::{v1::Credential as _, v2::Credential as _},
-    prelude::AnyJsonCredential,
+    claims::{
+        vc::{v1::Credential as _, v2::Credential as _},
+        VerificationParameters,
+    },
+    dids::{AnyDidMethod, DIDResolver},
+    prelude::{AnyDataIntegrity, AnyJsonCredential},
 };
 use uuid::Uuid;
 
+/// The format of presentations of JSON-LD credentials.
+pub(crate) const LDP_VP_FORMAT: &str = "ldp_vp";
+
 #[derive(uniffi::Object, Debug, Clone)]
 /// A verifiable credential secured as JSON.
 pub struct JsonVc {
@@ -80,9 +90,47 @@ impl JsonVc {
             ssi::claims::vc::AnySpecializedJsonCredential::V2(vc) => vc.additional_types().to_vec(),
         }
     }
+
+    /// The URLs of the JSON-LD contexts of the credential, e.g. to prefetch
+    /// them with [JsonLdContextCache::prefetch].
+    pub fn context_urls(&self) -> Vec<String> {
+        context_cache::context_urls(&self.raw)
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl JsonVc {
+    /// Verify the Data Integrity proofs of the credential.
+    ///
+    /// The contexts of the credential are resolved from the cache, if any, so
+    /// that the credential can be canonicalized offline.
+    pub async fn verify(
+        &self,
+        contexts: Option<Arc<JsonLdContextCache>>,
+    ) -> Result<(), JsonVcVerificationError> {
+        let vc: AnyDataIntegrity<AnyJsonCredential> = serde_json::from_value(self.raw.clone())
+            .map_err(|e| JsonVcVerificationError::ProofDecoding(format!("{e:?}")))?;
+
+        let vm_resolver = AnyDidMethod::default().into_vm_resolver();
+        let params = match contexts {
+            Some(contexts) => VerificationParameters::from_resolver(vm_resolver)
+                .with_json_ld_loader(contexts.loader()?),
+            None => VerificationParameters::from_resolver(vm_resolver),
+        };
+
+        vc.verify(&params)
+            .await
+            .map_err(|e| JsonVcVerificationError::Verification(format!("{e:?}")))?
+            .map_err(|e| JsonVcVerificationError::Verification(format!("{e:?}")))
+    }
 }
 
 impl JsonVc {
+    /// The credential as JSON, with its proofs.
+    pub(crate) fn raw(&self) -> &Json {
+        &self.raw
+    }
+
     pub(crate) fn to_json_bytes(&self) -> Result<Vec<u8>, JsonVcEncodingError> {
         serde_json::to_vec(&self.raw).map_err(|_| JsonVcEncodingError::JsonBytesEncoding)
     }
@@ -124,6 +172,7 @@ impl JsonVc {
         // then return false.
         if !definition.format().is_empty()
             && !definition.contains_format(CredentialFormat::LdpVc.to_string().as_str())
+            && !definition.contains_format(LDP_VP_FORMAT)
         {
             return false;
         }
@@ -147,10 +196,37 @@ impl JsonVc {
         definition
             .requested_fields(&json)
             .into_iter()
-            .map(Into::into)
+            .map(|field| RequestedField::from(field).with_descriptor_purpose(definition))
             .map(Arc::new)
             .collect()
     }
+
+    /// Return the credential in an `ldp_vp` presentation, as a VpToken.
+    ///
+    /// NOTE: the presentation is not secured with a proof of the holder yet.
+    pub fn as_vp_token(&self) -> Result<VpTokenItem, JsonVcEncodingError> {
+        self.presentation(self.raw.clone())
+    }
+
+    /// Return a secured form of the credential, such as one with a derived
+    /// proof, in an `ldp_vp` presentation.
+    pub(crate) fn presentation(
+        &self,
+        credential: Json,
+    ) -> Result<VpTokenItem, JsonVcEncodingError> {
+        let context = match self.vcdm_version() {
+            VcdmVersion::V1 => "https://www.w3.org/2018/credentials/v1",
+            VcdmVersion::V2 => "https://www.w3.org/ns/credentials/v2",
+        };
+
+        serde_json::from_value(serde_json::json!({
+            "@context": [context],
+            "id": format!("urn:uuid:{}", Uuid::new_v4()),
+            "type": ["VerifiablePresentation"],
+            "verifiableCredential": [credential],
+        }))
+        .map_err(|_| JsonVcEncodingError::PresentationEncoding)
+    }
 }
 
 impl TryFrom<Credential> for Arc<JsonVc> {
@@ -177,4 +253,40 @@ pub enum JsonVcInitError {
 pub enum JsonVcEncodingError {
     #[error("failed to encode JSON as bytes")]
     JsonBytesEncoding,
+    #[error("failed to encode the credential in a presentation")]
+    PresentationEncoding,
+}
+
+#[derive(Debug, uniffi::Error, thiserror::Error)]
+pub enum JsonVcVerificationError {
+    #[error("failed to decode the Data Integrity proof of the credential: {0}")]
+    ProofDecoding(String),
+    #[error(transparent)]
+    Context(#[from] JsonLdContextError),
+    #[error("failed to verify the credential: {0}")]
+    Verification(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verifies_and_presents_credentials() {
+        let vc = JsonVc::new_from_json(include_str!("../../tests/res/vc").into()).unwrap();
+        assert_eq!(
+            vc.context_urls()[0],
+            "https://www.w3.org/2018/credentials/v1"
+        );
+        vc.verify(Some(JsonLdContextCache::new())).await.unwrap();
+
+        let mut tampered = vc.raw.clone();
+        tampered["credentialSubject"]["givenName"] = "MALLORY".into();
+        let tampered = JsonVc::from_json(Uuid::new_v4(), tampered, None).unwrap();
+        assert!(tampered.verify(None).await.is_err());
+
+        let token = serde_json::to_value(vc.as_vp_token().unwrap()).unwrap();
+        assert_eq!(token["type"], serde_json::json!(["VerifiablePresentation"]));
+        assert_eq!(token["verifiableCredential"][0], vc.raw);
+    }
 }
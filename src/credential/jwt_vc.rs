@@ -7,6 +7,7 @@ use base64::prelude::*;
 use openid4vp::core::{
     presentation_definition::PresentationDefinition, response::parameters::VpTokenItem,
 };
+use serde_json::Value as Json;
 use ssi::{
     claims::{
         jwt::IntoDecodedJwt,
@@ -17,6 +18,9 @@ use ssi::{
 };
 use uuid::Uuid;
 
+/// The format of presentations of JWT VCs.
+pub(crate) const JWT_VP_FORMAT: &str = "jwt_vp_json";
+
 #[derive(uniffi::Object, Debug, Clone)]
 /// A verifiable credential secured as a JWT.
 pub struct JwtVc {
@@ -145,7 +149,7 @@ impl JwtVc {
     }
 
     fn convert_to_json_string(base64_encoded_bytes: &[u8]) -> Option<String> {
-        String::from_utf8(BASE64_STANDARD_NO_PAD.decode(base64_encoded_bytes).ok()?).ok()
+        String::from_utf8(BASE64_URL_SAFE_NO_PAD.decode(base64_encoded_bytes).ok()?).ok()
     }
 
     /// Return the internal `AnyJsonCredential` type
@@ -159,6 +163,7 @@ impl JwtVc {
         // then return false.
         if !definition.format().is_empty()
             && !definition.contains_format(CredentialFormat::JwtVcJson.to_string().as_str())
+            && !definition.contains_format(JWT_VP_FORMAT)
         {
             return false;
         }
@@ -174,8 +179,17 @@ impl JwtVc {
             return false;
         };
 
-        // Check the JSON-encoded credential against the definition.
+        // Check the JSON-encoded credential against the definition, then the JWT
+        // payload, for definitions with paths such as `$.vc.credentialSubject`.
         definition.is_credential_match(&json)
+            || self
+                .payload_json()
+                .is_some_and(|payload| definition.is_credential_match(&payload))
+    }
+
+    /// Return the JWT payload as JSON.
+    fn payload_json(&self) -> Option<Json> {
+        serde_json::from_str(&self.payload_json_string).ok()
     }
 
     /// Returns the requested fields given a presentation definition.
@@ -190,16 +204,34 @@ impl JwtVc {
             return Vec::new();
         };
 
-        definition
-            .requested_fields(&json)
+        let mut fields = definition.requested_fields(&json);
+        if fields.is_empty() {
+            if let Some(payload) = self.payload_json() {
+                fields = definition.requested_fields(&payload);
+            }
+        }
+
+        fields
             .into_iter()
-            .map(Into::into)
+            .map(|field| RequestedField::from(field).with_descriptor_purpose(definition))
             .map(Arc::new)
             .collect()
     }
 
     /// Return the credential as a VpToken
     pub fn as_vp_token(&self) -> VpTokenItem {
+        Self::presentation_as_vp_token(&[self])
+    }
+
+    /// Return a single presentation of several credentials as a VpToken.
+    ///
+    /// The credentials appear in the presentation's `verifiableCredential` in order.
+    pub(crate) fn presentation_as_vp_token(credentials: &[&Self]) -> VpTokenItem {
+        VpTokenItem::from(Self::presentation(credentials))
+    }
+
+    /// Return a single presentation of several credentials.
+    pub(crate) fn presentation(credentials: &[&Self]) -> JsonPresentation {
         let id = UriBuf::new(format!("urn:uuid:{}", Uuid::new_v4()).as_bytes().to_vec()).ok();
 
         // TODO: determine how the holder ID should be set.
@@ -207,11 +239,14 @@ impl JwtVc {
 
         // NOTE: JwtVc types are ALWAYS VCDM 1.1, therefore using the v1::syntax::JsonPresentation
         // type.
-        VpTokenItem::from(JsonPresentation::new(
+        JsonPresentation::new(
             id,
             holder_id,
-            vec![self.credential.clone()],
-        ))
+            credentials
+                .iter()
+                .map(|credential| credential.credential.clone())
+                .collect(),
+        )
     }
 }
 
@@ -254,3 +289,60 @@ pub enum JwtVcInitError {
     #[error("failed to decode JWT payload as base64-encoded JSON")]
     PayloadDecoding,
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn jwt_vc() -> Arc<JwtVc> {
+        let encode = |value: serde_json::Value| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let header = encode(serde_json::json!({ "alg": "ES256", "typ": "JWT" }));
+        let payload = encode(serde_json::json!({
+            "iss": "did:example:issuer",
+            "vc": {
+                "@context": ["https://www.w3.org/2018/credentials/v1"],
+                "type": ["VerifiableCredential", "UniversityDegreeCredential"],
+                "issuer": "did:example:issuer",
+                "issuanceDate": "2010-01-01T00:00:00Z",
+                "credentialSubject": {
+                    "id": "did:example:holder",
+                    "degree": { "type": "BachelorDegree" }
+                }
+            }
+        }));
+
+        JwtVc::new_from_compact_jws(format!("{header}.{payload}.c2lnbmF0dXJl")).unwrap()
+    }
+
+    #[test]
+    fn matches_credential_and_payload_paths() {
+        let vc = jwt_vc();
+        assert_eq!(vc.types(), vec!["UniversityDegreeCredential"]);
+
+        let definition = |path: &str| -> PresentationDefinition {
+            serde_json::from_value(serde_json::json!({
+                "id": "degree",
+                "input_descriptors": [{
+                    "id": "degree",
+                    "format": { "jwt_vp_json": { "alg": ["ES256"] } },
+                    "constraints": {
+                        "fields": [{
+                            "path": [path],
+                            "filter": { "type": "string", "const": "BachelorDegree" }
+                        }]
+                    }
+                }]
+            }))
+            .unwrap()
+        };
+
+        assert!(vc.check_presentation_definition(&definition("$.credentialSubject.degree.type")));
+        assert!(vc.check_presentation_definition(&definition("$.vc.credentialSubject.degree.type")));
+        assert_eq!(
+            vc.requested_fields(&definition("$.vc.credentialSubject.degree.type"))
+                .len(),
+            1
+        );
+        assert!(!vc.check_presentation_definition(&definition("$.credentialSubject.name")));
+    }
+}
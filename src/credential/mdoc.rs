@@ -8,11 +8,15 @@ use isomdl::{
     definitions::{IssuerSigned, Mso},
     presentation::{device::Document, Stringify},
 };
+use openid4vp::core::presentation_definition::PresentationDefinition;
 use uuid::Uuid;
 
-use crate::{CredentialType, KeyAlias};
+use crate::{
+    oid4vp::permission_request::{MdocElementPath, RequestedField},
+    CredentialType, KeyAlias,
+};
 
-use super::{Credential, CredentialFormat};
+use super::{claims::cbor_leaf, disclosure, Credential, CredentialFormat};
 
 uniffi::custom_newtype!(Namespace, String);
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -121,6 +125,127 @@ impl Mdoc {
         &self.inner
     }
 
+    /// The data elements as a JSON object of namespaces to elements, against
+    /// which presentation definition paths such as
+    /// `$['org.iso.18013.5.1']['family_name']` are evaluated.
+    pub(crate) fn namespaces_as_json(&self) -> serde_json::Value {
+        self.document()
+            .namespaces
+            .clone()
+            .into_inner()
+            .into_iter()
+            .map(|(namespace, elements)| {
+                let elements = elements
+                    .into_inner()
+                    .into_values()
+                    .map(|tagged| {
+                        let element = tagged.into_inner();
+                        (
+                            element.element_identifier,
+                            serde_json::to_value(&element.element_value)
+                                .unwrap_or(serde_json::Value::Null),
+                        )
+                    })
+                    .collect::<serde_json::Map<_, _>>();
+                (namespace, serde_json::Value::Object(elements))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    /// The data elements as a CBOR map of namespaces to elements, keeping the
+    /// byte strings that JSON cannot represent, e.g. portraits.
+    pub(crate) fn namespaces_as_cbor(&self) -> serde_cbor::Value {
+        serde_cbor::Value::Map(
+            self.document()
+                .namespaces
+                .clone()
+                .into_inner()
+                .into_iter()
+                .map(|(namespace, elements)| {
+                    let elements = elements
+                        .into_inner()
+                        .into_values()
+                        .map(|tagged| {
+                            let element = tagged.into_inner();
+                            (
+                                serde_cbor::Value::Text(element.element_identifier),
+                                serde_cbor::value::to_value(&element.element_value)
+                                    .unwrap_or(serde_cbor::Value::Null),
+                            )
+                        })
+                        .collect();
+                    (
+                        serde_cbor::Value::Text(namespace),
+                        serde_cbor::Value::Map(elements),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Check if the credential satisfies a presentation definition.
+    ///
+    /// Following ISO 18013-7, an input descriptor requests an mdoc when its id
+    /// is the doctype of the mdoc.
+    pub fn check_presentation_definition(&self, definition: &PresentationDefinition) -> bool {
+        if !definition.format().is_empty()
+            && !definition.contains_format(CredentialFormat::MsoMdoc.to_string().as_str())
+        {
+            return false;
+        }
+
+        let doctype = self.doctype();
+        definition
+            .input_descriptors()
+            .iter()
+            .any(|descriptor| descriptor.id == doctype)
+    }
+
+    /// Returns the requested fields given a presentation definition.
+    ///
+    /// Only the requested data elements are released when presenting an mdoc.
+    pub fn requested_fields(
+        &self,
+        definition: &PresentationDefinition,
+    ) -> Vec<Arc<RequestedField>> {
+        let doctype = self.doctype();
+        let namespaces = self.namespaces_as_cbor();
+        disclosure::requested_fields(&self.namespaces_as_json(), definition)
+            .into_iter()
+            .filter(|field| field.input_descriptor_id == doctype)
+            .map(|mut field| {
+                // The JSON values lose the byte strings of the elements.
+                field.values = field
+                    .pointers
+                    .iter()
+                    .filter_map(|pointer| {
+                        pointer
+                            .iter()
+                            .try_fold(&namespaces, |value, key| match value {
+                                serde_cbor::Value::Map(map) => {
+                                    map.get(&serde_cbor::Value::Text(key.clone()))
+                                }
+                                serde_cbor::Value::Array(array) => {
+                                    array.get(key.parse::<usize>().ok()?)
+                                }
+                                _ => None,
+                            })
+                    })
+                    .map(cbor_leaf)
+                    .collect();
+                field.mdoc_element = match field.pointers.first().map(Vec::as_slice) {
+                    Some([namespace, identifier]) => Some(MdocElementPath {
+                        namespace: namespace.clone(),
+                        identifier: identifier.clone(),
+                    }),
+                    _ => None,
+                };
+                Arc::new(field)
+            })
+            .collect()
+    }
+
     fn new_from_issuer_signed(
         key_alias: KeyAlias,
         IssuerSigned {
@@ -189,6 +314,7 @@ impl TryFrom<Arc<Mdoc>> for Credential {
             payload: serde_cbor::to_vec(mdoc.document())
                 .map_err(|_| MdocEncodingError::DocumentCborEncoding)?,
             key_alias: Some(mdoc.key_alias()),
+            display: vec![],
         })
     }
 }
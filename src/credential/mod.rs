@@ -1,11 +1,31 @@
+pub(crate) mod age_predicates;
+pub mod bbs_vc;
+pub mod claims;
+pub mod context_cache;
+pub mod cwt;
+pub(crate) mod disclosure;
+pub mod display;
+pub mod enveloped;
+pub mod ietf_sd_jwt_vc;
 pub mod json_vc;
 pub mod jwt_vc;
 pub mod mdoc;
+pub mod open_badges;
+pub mod preview;
+pub mod schema;
+pub mod selective_disclosure;
+pub mod validity;
 pub mod vcdm2_sd_jwt;
+pub mod vehicle_title;
+pub mod verification;
 
 use std::sync::Arc;
 
 use crate::{oid4vp::permission_request::RequestedField, CredentialType, KeyAlias, Uuid};
+use bbs_vc::BbsVc;
+use cwt::{Cwt, CwtError};
+use display::CredentialDisplay;
+use ietf_sd_jwt_vc::IetfSdJwtVc;
 use json_vc::{JsonVc, JsonVcEncodingError, JsonVcInitError};
 use jwt_vc::{JwtVc, JwtVcInitError};
 use mdoc::{Mdoc, MdocEncodingError, MdocInitError};
@@ -13,6 +33,7 @@ use openid4vp::core::{
     presentation_definition::PresentationDefinition, response::parameters::VpTokenItem,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
 use vcdm2_sd_jwt::{SdJwtError, VCDM2SdJwt};
 
 /// An unparsed credential, retrieved from storage.
@@ -28,6 +49,9 @@ pub struct Credential {
     pub payload: Vec<u8>,
     /// The alias of the key that is authorized to present this credential.
     pub key_alias: Option<KeyAlias>,
+    /// The display metadata provided by the issuer, for every locale.
+    #[serde(default)]
+    pub display: Vec<CredentialDisplay>,
 }
 
 // Internal helper methods.
@@ -42,6 +66,7 @@ impl Credential {
 #[derive(Debug, Clone, uniffi::Object)]
 pub struct ParsedCredential {
     pub(crate) inner: ParsedCredentialInner,
+    pub(crate) display: Vec<CredentialDisplay>,
 }
 
 /// A credential that has been parsed as a known variant.
@@ -51,7 +76,11 @@ pub(crate) enum ParsedCredentialInner {
     JwtVcJson(Arc<JwtVc>),
     JwtVcJsonLd(Arc<JwtVc>),
     VCDM2SdJwt(Arc<VCDM2SdJwt>),
+    DcSdJwt(Arc<IetfSdJwtVc>),
     LdpVc(Arc<JsonVc>),
+    /// An `ldp_vc` secured with a `bbs-2023` proof, presented with derived proofs.
+    LdpVcBbs(Arc<BbsVc>),
+    Cwt(Arc<Cwt>),
     // More to come, for example:
     // SdJwt(...),
     // SdJwtJoseCose(...),
@@ -64,6 +93,7 @@ impl ParsedCredential {
     pub fn new_mso_mdoc(mdoc: Arc<Mdoc>) -> Arc<Self> {
         Arc::new(Self {
             inner: ParsedCredentialInner::MsoMdoc(mdoc),
+            display: vec![],
         })
     }
 
@@ -72,6 +102,7 @@ impl ParsedCredential {
     pub fn new_jwt_vc_json(jwt_vc: Arc<JwtVc>) -> Arc<Self> {
         Arc::new(Self {
             inner: ParsedCredentialInner::JwtVcJson(jwt_vc),
+            display: vec![],
         })
     }
 
@@ -80,6 +111,7 @@ impl ParsedCredential {
     pub fn new_jwt_vc_json_ld(jwt_vc: Arc<JwtVc>) -> Arc<Self> {
         Arc::new(Self {
             inner: ParsedCredentialInner::JwtVcJsonLd(jwt_vc),
+            display: vec![],
         })
     }
 
@@ -88,6 +120,16 @@ impl ParsedCredential {
     pub fn new_ldp_vc(json_vc: Arc<JsonVc>) -> Arc<Self> {
         Arc::new(Self {
             inner: ParsedCredentialInner::LdpVc(json_vc),
+            display: vec![],
+        })
+    }
+
+    #[uniffi::constructor]
+    /// Construct a new `ldp_vc` credential secured with a `bbs-2023` proof.
+    pub fn new_bbs_vc(bbs_vc: Arc<BbsVc>) -> Arc<Self> {
+        Arc::new(Self {
+            inner: ParsedCredentialInner::LdpVcBbs(bbs_vc),
+            display: vec![],
         })
     }
 
@@ -96,6 +138,25 @@ impl ParsedCredential {
     pub fn new_sd_jwt(sd_jwt_vc: Arc<VCDM2SdJwt>) -> Arc<Self> {
         Arc::new(Self {
             inner: ParsedCredentialInner::VCDM2SdJwt(sd_jwt_vc),
+            display: vec![],
+        })
+    }
+
+    #[uniffi::constructor]
+    /// Construct a new `dc+sd-jwt` credential.
+    pub fn new_dc_sd_jwt(sd_jwt_vc: Arc<IetfSdJwtVc>) -> Arc<Self> {
+        Arc::new(Self {
+            inner: ParsedCredentialInner::DcSdJwt(sd_jwt_vc),
+            display: vec![],
+        })
+    }
+
+    #[uniffi::constructor]
+    /// Construct a new `cwt` credential.
+    pub fn new_cwt(cwt: Arc<Cwt>) -> Arc<Self> {
+        Arc::new(Self {
+            inner: ParsedCredentialInner::Cwt(cwt),
+            display: vec![],
         })
     }
 
@@ -114,37 +175,68 @@ impl ParsedCredential {
 
     /// Convert a parsed credential into the generic form for storage.
     pub fn into_generic_form(&self) -> Result<Credential, CredentialEncodingError> {
-        match &self.inner {
-            ParsedCredentialInner::MsoMdoc(mdoc) => Ok(mdoc.clone().try_into()?),
-            ParsedCredentialInner::JwtVcJson(vc) => Ok(Credential {
+        let mut credential: Credential = match &self.inner {
+            ParsedCredentialInner::MsoMdoc(mdoc) => mdoc.clone().try_into()?,
+            ParsedCredentialInner::JwtVcJson(vc) => Credential {
                 id: vc.id(),
                 format: CredentialFormat::JwtVcJson,
                 r#type: vc.r#type(),
                 payload: vc.to_compact_jws_bytes(),
                 key_alias: vc.key_alias(),
-            }),
-            ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => Ok(Credential {
+                display: vec![],
+            },
+            ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => Credential {
                 id: sd_jwt.id(),
                 format: CredentialFormat::VCDM2SdJwt,
                 r#type: sd_jwt.r#type(),
                 payload: sd_jwt.inner.as_bytes().into(),
                 key_alias: sd_jwt.key_alias(),
-            }),
-            ParsedCredentialInner::JwtVcJsonLd(vc) => Ok(Credential {
+                display: vec![],
+            },
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => Credential {
+                id: sd_jwt.id(),
+                format: CredentialFormat::DcSdJwt,
+                r#type: sd_jwt.r#type(),
+                payload: sd_jwt.inner.as_bytes().into(),
+                key_alias: sd_jwt.key_alias(),
+                display: vec![],
+            },
+            ParsedCredentialInner::JwtVcJsonLd(vc) => Credential {
                 id: vc.id(),
                 format: CredentialFormat::JwtVcJsonLd,
                 r#type: vc.r#type(),
                 payload: vc.to_compact_jws_bytes(),
                 key_alias: vc.key_alias(),
-            }),
-            ParsedCredentialInner::LdpVc(vc) => Ok(Credential {
+                display: vec![],
+            },
+            ParsedCredentialInner::LdpVc(vc) => Credential {
                 id: vc.id(),
                 format: CredentialFormat::LdpVc,
                 r#type: vc.r#type(),
                 payload: vc.to_json_bytes()?,
                 key_alias: vc.key_alias(),
-            }),
-        }
+                display: vec![],
+            },
+            ParsedCredentialInner::LdpVcBbs(vc) => Credential {
+                id: vc.id(),
+                format: CredentialFormat::LdpVc,
+                r#type: vc.r#type(),
+                payload: vc.json_vc().to_json_bytes()?,
+                key_alias: vc.key_alias(),
+                display: vec![],
+            },
+            ParsedCredentialInner::Cwt(cwt) => Credential {
+                id: cwt.id(),
+                format: CredentialFormat::Cwt,
+                r#type: cwt.r#type(),
+                payload: cwt.inner.clone(),
+                key_alias: cwt.key_alias(),
+                display: vec![],
+            },
+        };
+        credential.display = self.display.clone();
+
+        Ok(credential)
     }
 
     /// Return the format of the credential.
@@ -154,7 +246,11 @@ impl ParsedCredential {
             ParsedCredentialInner::JwtVcJson(_) => CredentialFormat::JwtVcJson,
             ParsedCredentialInner::JwtVcJsonLd(_) => CredentialFormat::JwtVcJsonLd,
             ParsedCredentialInner::VCDM2SdJwt(_) => CredentialFormat::VCDM2SdJwt,
-            ParsedCredentialInner::LdpVc(_) => CredentialFormat::LdpVc,
+            ParsedCredentialInner::DcSdJwt(_) => CredentialFormat::DcSdJwt,
+            ParsedCredentialInner::LdpVc(_) | ParsedCredentialInner::LdpVcBbs(_) => {
+                CredentialFormat::LdpVc
+            }
+            ParsedCredentialInner::Cwt(_) => CredentialFormat::Cwt,
         }
     }
 
@@ -165,7 +261,10 @@ impl ParsedCredential {
             ParsedCredentialInner::JwtVcJson(arc) => arc.id(),
             ParsedCredentialInner::JwtVcJsonLd(arc) => arc.id(),
             ParsedCredentialInner::LdpVc(arc) => arc.id(),
+            ParsedCredentialInner::LdpVcBbs(arc) => arc.id(),
             ParsedCredentialInner::VCDM2SdJwt(arc) => arc.id(),
+            ParsedCredentialInner::DcSdJwt(arc) => arc.id(),
+            ParsedCredentialInner::Cwt(arc) => arc.id(),
         }
     }
 
@@ -176,7 +275,10 @@ impl ParsedCredential {
             ParsedCredentialInner::JwtVcJson(arc) => arc.key_alias(),
             ParsedCredentialInner::JwtVcJsonLd(arc) => arc.key_alias(),
             ParsedCredentialInner::LdpVc(arc) => arc.key_alias(),
+            ParsedCredentialInner::LdpVcBbs(arc) => arc.key_alias(),
             ParsedCredentialInner::VCDM2SdJwt(arc) => arc.key_alias(),
+            ParsedCredentialInner::DcSdJwt(arc) => arc.key_alias(),
+            ParsedCredentialInner::Cwt(arc) => arc.key_alias(),
         }
     }
 
@@ -187,10 +289,33 @@ impl ParsedCredential {
             ParsedCredentialInner::JwtVcJson(arc) => arc.r#type(),
             ParsedCredentialInner::JwtVcJsonLd(arc) => arc.r#type(),
             ParsedCredentialInner::LdpVc(arc) => arc.r#type(),
+            ParsedCredentialInner::LdpVcBbs(arc) => arc.r#type(),
             ParsedCredentialInner::VCDM2SdJwt(arc) => arc.r#type(),
+            ParsedCredentialInner::DcSdJwt(arc) => arc.r#type(),
+            ParsedCredentialInner::Cwt(arc) => arc.r#type(),
         }
     }
 
+    /// Return the issuer of the credential, if it is known.
+    ///
+    /// Mdocs do not identify their issuer other than through the signing
+    /// certificate, so `None` is returned for them.
+    pub fn issuer(&self) -> Option<String> {
+        let credential = self.definition_json()?;
+        let issuer = match &credential["issuer"] {
+            Json::String(issuer) => Some(issuer.clone()),
+            Json::Object(issuer) => issuer.get("id").and_then(Json::as_str).map(Into::into),
+            _ => None,
+        };
+
+        issuer.or_else(|| {
+            self.claims_as_json()?
+                .get("iss")
+                .and_then(Json::as_str)
+                .map(Into::into)
+        })
+    }
+
     /// Return the credential as a JwtVc if it is of that format.
     pub fn as_jwt_vc(&self) -> Option<Arc<JwtVc>> {
         match &self.inner {
@@ -208,6 +333,14 @@ impl ParsedCredential {
         }
     }
 
+    /// Return the credential as a BbsVc if it is secured with a `bbs-2023` proof.
+    pub fn as_bbs_vc(&self) -> Option<Arc<BbsVc>> {
+        match &self.inner {
+            ParsedCredentialInner::LdpVcBbs(bbs_vc) => Some(bbs_vc.clone()),
+            _ => None,
+        }
+    }
+
     /// Return the credential as an Mdoc if it is of that format.
     pub fn as_mso_mdoc(&self) -> Option<Arc<Mdoc>> {
         match &self.inner {
@@ -223,6 +356,22 @@ impl ParsedCredential {
             _ => None,
         }
     }
+
+    /// Return the credential as an IETF SD-JWT VC, if it is of that format.
+    pub fn as_dc_sd_jwt(&self) -> Option<Arc<IetfSdJwtVc>> {
+        match &self.inner {
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => Some(sd_jwt.clone()),
+            _ => None,
+        }
+    }
+
+    /// Return the credential as a CWT, if it is of that format.
+    pub fn as_cwt(&self) -> Option<Arc<Cwt>> {
+        match &self.inner {
+            ParsedCredentialInner::Cwt(cwt) => Some(cwt.clone()),
+            _ => None,
+        }
+    }
 }
 
 // Intneral Parsed Credential methods
@@ -233,10 +382,15 @@ impl ParsedCredential {
             ParsedCredentialInner::JwtVcJson(vc) => vc.check_presentation_definition(definition),
             ParsedCredentialInner::JwtVcJsonLd(vc) => vc.check_presentation_definition(definition),
             ParsedCredentialInner::LdpVc(vc) => vc.check_presentation_definition(definition),
+            ParsedCredentialInner::LdpVcBbs(vc) => vc.check_presentation_definition(definition),
             ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => {
                 sd_jwt.check_presentation_definition(definition)
             }
-            ParsedCredentialInner::MsoMdoc(_mdoc) => false,
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => {
+                sd_jwt.check_presentation_definition(definition)
+            }
+            ParsedCredentialInner::MsoMdoc(mdoc) => mdoc.check_presentation_definition(definition),
+            ParsedCredentialInner::Cwt(cwt) => cwt.check_presentation_definition(definition),
         }
     }
 
@@ -247,12 +401,53 @@ impl ParsedCredential {
     ) -> Vec<Arc<RequestedField>> {
         match &self.inner {
             ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => sd_jwt.requested_fields(definition),
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => sd_jwt.requested_fields(definition),
             ParsedCredentialInner::JwtVcJson(vc) => vc.requested_fields(definition),
             ParsedCredentialInner::JwtVcJsonLd(vc) => vc.requested_fields(definition),
             ParsedCredentialInner::LdpVc(vc) => vc.requested_fields(definition),
-            ParsedCredentialInner::MsoMdoc(_mdoc) => {
-                unimplemented!("Mdoc requested fields not implemented")
+            ParsedCredentialInner::LdpVcBbs(vc) => vc.requested_fields(definition),
+            ParsedCredentialInner::MsoMdoc(mdoc) => mdoc.requested_fields(definition),
+            ParsedCredentialInner::Cwt(cwt) => cwt.requested_fields(definition),
+        }
+    }
+
+    /// Return the claims of the credential as JSON, for the formats that are
+    /// JSON based.
+    pub(crate) fn claims_as_json(&self) -> Option<serde_json::Value> {
+        match &self.inner {
+            ParsedCredentialInner::JwtVcJson(vc) | ParsedCredentialInner::JwtVcJsonLd(vc) => {
+                serde_json::from_str(&vc.jws_payload_as_json_encoded_utf8_string()).ok()
+            }
+            ParsedCredentialInner::LdpVc(vc) => {
+                serde_json::from_str(&vc.credential_as_json_encoded_utf8_string()).ok()
+            }
+            ParsedCredentialInner::LdpVcBbs(vc) => {
+                serde_json::from_str(&vc.json_vc().credential_as_json_encoded_utf8_string()).ok()
+            }
+            ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => sd_jwt.revealed_claims_as_json().ok(),
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => Some(sd_jwt.revealed_claims_as_json()),
+            ParsedCredentialInner::Cwt(cwt) => Some(cwt.claims_as_json()),
+            ParsedCredentialInner::MsoMdoc(_) => None,
+        }
+    }
+
+    /// Return the credential as JSON, in the form presentation definition
+    /// paths are evaluated against.
+    pub(crate) fn definition_json(&self) -> Option<serde_json::Value> {
+        match &self.inner {
+            ParsedCredentialInner::JwtVcJson(vc) | ParsedCredentialInner::JwtVcJsonLd(vc) => {
+                serde_json::to_value(vc.credential()).ok()
+            }
+            ParsedCredentialInner::LdpVc(vc) => {
+                serde_json::from_str(&vc.credential_as_json_encoded_utf8_string()).ok()
+            }
+            ParsedCredentialInner::LdpVcBbs(vc) => {
+                serde_json::from_str(&vc.json_vc().credential_as_json_encoded_utf8_string()).ok()
             }
+            ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => sd_jwt.revealed_claims_as_json().ok(),
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => Some(sd_jwt.revealed_claims_as_json()),
+            ParsedCredentialInner::MsoMdoc(mdoc) => Some(mdoc.namespaces_as_json()),
+            ParsedCredentialInner::Cwt(cwt) => Some(cwt.claims_as_json()),
         }
     }
 
@@ -260,7 +455,13 @@ impl ParsedCredential {
     pub fn as_vp_token(&self) -> Result<VpTokenItem, CredentialEncodingError> {
         match &self.inner {
             ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => Ok(sd_jwt.as_vp_token()),
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => Ok(sd_jwt.as_vp_token()),
             ParsedCredentialInner::JwtVcJson(vc) => Ok(vc.as_vp_token()),
+            ParsedCredentialInner::LdpVc(vc) => Ok(vc.as_vp_token()?),
+            ParsedCredentialInner::Cwt(cwt) => Ok(cwt.as_vp_token()),
+            // Presenting the base proof would disclose every claim and make
+            // presentations linkable, so BBS credentials are only presented
+            // with derived proofs.
             _ => Err(CredentialEncodingError::VpToken(format!(
                 "Credential encoding for VP Token is not implemented for {:?}.",
                 self.inner,
@@ -273,22 +474,30 @@ impl TryFrom<Credential> for Arc<ParsedCredential> {
     type Error = CredentialDecodingError;
 
     fn try_from(credential: Credential) -> Result<Self, Self::Error> {
-        match credential.format {
-            CredentialFormat::MsoMdoc => Ok(ParsedCredential::new_mso_mdoc(credential.try_into()?)),
-            CredentialFormat::JwtVcJson => {
-                Ok(ParsedCredential::new_jwt_vc_json(credential.try_into()?))
-            }
+        let display = credential.display.clone();
+        let inner = match credential.format {
+            CredentialFormat::MsoMdoc => ParsedCredentialInner::MsoMdoc(credential.try_into()?),
+            CredentialFormat::JwtVcJson => ParsedCredentialInner::JwtVcJson(credential.try_into()?),
             CredentialFormat::JwtVcJsonLd => {
-                Ok(ParsedCredential::new_jwt_vc_json_ld(credential.try_into()?))
+                ParsedCredentialInner::JwtVcJsonLd(credential.try_into()?)
             }
             CredentialFormat::VCDM2SdJwt => {
-                Ok(ParsedCredential::new_sd_jwt(credential.try_into()?))
+                ParsedCredentialInner::VCDM2SdJwt(credential.try_into()?)
             }
-            CredentialFormat::LdpVc => Ok(ParsedCredential::new_ldp_vc(credential.try_into()?)),
-            _ => Err(CredentialDecodingError::UnsupportedCredentialFormat(
-                credential.format.to_string(),
-            )),
-        }
+            CredentialFormat::DcSdJwt => ParsedCredentialInner::DcSdJwt(credential.try_into()?),
+            CredentialFormat::LdpVc => match BbsVc::from_json_vc(credential.try_into()?) {
+                Ok(bbs_vc) => ParsedCredentialInner::LdpVcBbs(bbs_vc),
+                Err(json_vc) => ParsedCredentialInner::LdpVc(json_vc),
+            },
+            CredentialFormat::Cwt => ParsedCredentialInner::Cwt(credential.try_into()?),
+            _ => {
+                return Err(CredentialDecodingError::UnsupportedCredentialFormat(
+                    credential.format.to_string(),
+                ))
+            }
+        };
+
+        Ok(Arc::new(ParsedCredential { inner, display }))
     }
 }
 
@@ -320,6 +529,8 @@ pub enum CredentialDecodingError {
     JwtVc(#[from] JwtVcInitError),
     #[error("SD JWT VC decoding error: {0}")]
     SdJwt(#[from] SdJwtError),
+    #[error("CWT decoding error: {0}")]
+    Cwt(#[from] CwtError),
     #[error("Credential format is not yet supported for type: {0}")]
     UnsupportedCredentialFormat(String),
     #[error("Serialization error: {0}")]
@@ -347,6 +558,11 @@ pub enum CredentialFormat {
     LdpVc,
     #[serde(rename = "vcdm2_sd_jwt")]
     VCDM2SdJwt,
+    /// An IETF SD-JWT VC.
+    #[serde(rename = "dc+sd-jwt")]
+    DcSdJwt,
+    /// A CBOR Web Token, secured with a COSE_Sign1.
+    Cwt,
     #[serde(untagged)]
     Other(String), // For ease of expansion.
 }
@@ -359,6 +575,8 @@ impl std::fmt::Display for CredentialFormat {
             CredentialFormat::JwtVcJsonLd => write!(f, "jwt_vc_json-ld"),
             CredentialFormat::LdpVc => write!(f, "ldp_vc"),
             CredentialFormat::VCDM2SdJwt => write!(f, "vcdm2_sd_jwt"),
+            CredentialFormat::DcSdJwt => write!(f, "dc+sd-jwt"),
+            CredentialFormat::Cwt => write!(f, "cwt"),
             CredentialFormat::Other(s) => write!(f, "{s}"),
         }
     }
@@ -374,6 +592,8 @@ mod test {
     #[case::jwt_vc_json_ld(r#""jwt_vc_json-ld""#, CredentialFormat::JwtVcJsonLd)]
     #[case::ldp_vc(r#""ldp_vc""#, CredentialFormat::LdpVc)]
     #[case::ldp_vc(r#""vcdm2_sd_jwt""#, CredentialFormat::VCDM2SdJwt)]
+    #[case::dc_sd_jwt(r#""dc+sd-jwt""#, CredentialFormat::DcSdJwt)]
+    #[case::cwt(r#""cwt""#, CredentialFormat::Cwt)]
     #[case::other(r#""something_else""#, CredentialFormat::Other("something_else".into()))]
     fn credential_format_roundtrips(#[case] expected: String, #[case] value: CredentialFormat) {
         let serialized = serde_json::to_string(&value).unwrap();
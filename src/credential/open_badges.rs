@@ -0,0 +1,412 @@
+//! Education credentials, following the 1EdTech Open Badges 3.0 and
+//! Comprehensive Learner Record (CLR) 2.0 profiles.
+//!
+//! Badges and learner records are W3C VCs, stored and presented as any
+//! credential of their format, e.g. [super::json_vc::JsonVc] or
+//! [super::jwt_vc::JwtVc]; this module reads their typed contents, and builds
+//! the presentation definitions requesting them.
+
+use super::ParsedCredential;
+
+use std::sync::Arc;
+
+use base64::prelude::*;
+use serde_json::{json, Value as Json};
+
+/// The credential types of Open Badges.
+pub const OPEN_BADGE_TYPES: &[&str] = &["OpenBadgeCredential", "AchievementCredential"];
+
+/// The credential type of learner records.
+pub const CLR_TYPE: &str = "ClrCredential";
+
+#[derive(Debug, uniffi::Error, thiserror::Error)]
+pub enum OpenBadgeError {
+    #[error("the credential is not an Open Badge")]
+    NotAnOpenBadge,
+    #[error("the credential is not a learner record")]
+    NotAClr,
+    #[error("the credential has no credentialSubject")]
+    MissingSubject,
+    #[error("the badge has no achievement")]
+    MissingAchievement,
+}
+
+/// The achievement a badge is awarded for.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct Achievement {
+    pub id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    /// The kind of achievement, e.g. `Certificate` or `Course`.
+    pub achievement_type: Option<String>,
+    /// The narrative of the criteria of the achievement, or else their URL.
+    pub criteria: Option<String>,
+    /// The URL of the image of the achievement, which may be a data URL.
+    pub image: Option<String>,
+    /// The name of the creator of the achievement.
+    pub creator: Option<String>,
+}
+
+/// A result the recipient obtained for the achievement, e.g. a grade.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct AchievementResult {
+    pub value: Option<String>,
+    /// The status of the result, e.g. `Completed`.
+    pub status: Option<String>,
+    /// The ID of the result description of the achievement the result is for.
+    pub result_description: Option<String>,
+}
+
+/// The contents of an Open Badge.
+#[derive(Debug, uniffi::Object)]
+pub struct OpenBadge {
+    credential: Json,
+    subject: Json,
+}
+
+#[uniffi::export]
+impl OpenBadge {
+    #[uniffi::constructor]
+    pub fn from_parsed_credential(
+        credential: Arc<ParsedCredential>,
+    ) -> Result<Arc<Self>, OpenBadgeError> {
+        let credential = credential
+            .definition_json()
+            .ok_or(OpenBadgeError::NotAnOpenBadge)?;
+        Ok(Arc::new(Self::from_json(credential)?))
+    }
+
+    /// The name of the badge, defaulting to that of its achievement.
+    pub fn name(&self) -> Option<String> {
+        text(&self.credential["name"]).or_else(|| text(&self.subject["achievement"]["name"]))
+    }
+
+    /// The name of the issuer, or else its identifier.
+    pub fn issuer(&self) -> Option<String> {
+        profile_name(&self.credential["issuer"])
+    }
+
+    /// The identifier of the recipient, e.g. their DID.
+    pub fn recipient(&self) -> Option<String> {
+        text(&self.subject["id"])
+            .or_else(|| text(&first(&self.subject["identifier"])["identityHash"]))
+    }
+
+    /// The date the badge was awarded, defaulting to its issuance date.
+    pub fn awarded_date(&self) -> Option<String> {
+        text(&self.credential["awardedDate"])
+            .or_else(|| text(&self.credential["validFrom"]))
+            .or_else(|| text(&self.credential["issuanceDate"]))
+    }
+
+    pub fn achievement(&self) -> Achievement {
+        let achievement = &self.subject["achievement"];
+        Achievement {
+            id: text(&achievement["id"]),
+            name: text(&achievement["name"]).unwrap_or_default(),
+            description: text(&achievement["description"]),
+            achievement_type: text(&achievement["achievementType"]),
+            criteria: text(&achievement["criteria"]["narrative"])
+                .or_else(|| text(&achievement["criteria"]["id"])),
+            image: image(&achievement["image"]),
+            creator: profile_name(&achievement["creator"]),
+        }
+    }
+
+    pub fn results(&self) -> Vec<AchievementResult> {
+        many(&self.subject["result"])
+            .into_iter()
+            .map(|result| AchievementResult {
+                value: text(&result["value"]),
+                status: text(&result["status"]),
+                result_description: text(&result["resultDescription"]),
+            })
+            .collect()
+    }
+}
+
+impl OpenBadge {
+    fn from_json(credential: Json) -> Result<Self, OpenBadgeError> {
+        if !has_type(&credential, OPEN_BADGE_TYPES) {
+            return Err(OpenBadgeError::NotAnOpenBadge);
+        }
+
+        let subject = first(&credential["credentialSubject"]).clone();
+        if !subject.is_object() {
+            return Err(OpenBadgeError::MissingSubject);
+        }
+        if !subject["achievement"].is_object() {
+            return Err(OpenBadgeError::MissingAchievement);
+        }
+
+        Ok(Self {
+            credential,
+            subject,
+        })
+    }
+}
+
+/// The contents of a Comprehensive Learner Record, bundling the badges of a
+/// learner.
+#[derive(Debug, uniffi::Object)]
+pub struct LearnerRecord {
+    credential: Json,
+    subject: Json,
+}
+
+#[uniffi::export]
+impl LearnerRecord {
+    #[uniffi::constructor]
+    pub fn from_parsed_credential(
+        credential: Arc<ParsedCredential>,
+    ) -> Result<Arc<Self>, OpenBadgeError> {
+        let credential = credential
+            .definition_json()
+            .ok_or(OpenBadgeError::NotAClr)?;
+        if !has_type(&credential, &[CLR_TYPE]) {
+            return Err(OpenBadgeError::NotAClr);
+        }
+
+        let subject = first(&credential["credentialSubject"]).clone();
+        if !subject.is_object() {
+            return Err(OpenBadgeError::MissingSubject);
+        }
+
+        Ok(Arc::new(Self {
+            credential,
+            subject,
+        }))
+    }
+
+    pub fn name(&self) -> Option<String> {
+        text(&self.credential["name"])
+    }
+
+    /// The name of the issuer, or else its identifier.
+    pub fn issuer(&self) -> Option<String> {
+        profile_name(&self.credential["issuer"])
+    }
+
+    /// The badges of the record, which are embedded as JSON credentials or
+    /// compact VC-JWTs.
+    ///
+    /// NOTE: the signatures of the embedded badges are not verified, only that
+    /// of the record.
+    pub fn badges(&self) -> Vec<Arc<OpenBadge>> {
+        many(&self.subject["verifiableCredential"])
+            .into_iter()
+            .filter_map(|credential| match credential {
+                Json::String(jwt) => jwt_credential(jwt),
+                credential => Some(credential.clone()),
+            })
+            .filter_map(|credential| OpenBadge::from_json(credential).ok())
+            .map(Arc::new)
+            .collect()
+    }
+}
+
+#[uniffi::export]
+impl ParsedCredential {
+    /// Return the contents of the credential, if it is an Open Badge.
+    pub fn as_open_badge(self: Arc<Self>) -> Option<Arc<OpenBadge>> {
+        OpenBadge::from_parsed_credential(self).ok()
+    }
+
+    /// Return the contents of the credential, if it is a learner record.
+    pub fn as_learner_record(self: Arc<Self>) -> Option<Arc<LearnerRecord>> {
+        LearnerRecord::from_parsed_credential(self).ok()
+    }
+}
+
+/// Return a JSON encoded presentation definition requesting an Open Badge,
+/// for an achievement if its ID is given.
+///
+/// Badges are requested whole, without limiting disclosure.
+#[uniffi::export]
+pub fn open_badge_presentation_definition(achievement_id: Option<String>) -> String {
+    let mut fields = vec![json!({
+        "path": ["$.type", "$.vc.type"],
+        "filter": {
+            "type": "array",
+            "contains": { "enum": OPEN_BADGE_TYPES },
+        },
+    })];
+    if let Some(achievement_id) = &achievement_id {
+        fields.push(json!({
+            "path": [
+                "$.credentialSubject.achievement.id",
+                "$.vc.credentialSubject.achievement.id",
+            ],
+            "filter": { "type": "string", "const": achievement_id },
+        }));
+    }
+
+    json!({
+        "id": "open_badge",
+        "input_descriptors": [{
+            "id": "open_badge",
+            "name": "Open Badge",
+            "constraints": { "fields": fields },
+        }],
+    })
+    .to_string()
+}
+
+/// Check that a credential has one of the types.
+fn has_type(credential: &Json, types: &[&str]) -> bool {
+    many(&credential["type"])
+        .into_iter()
+        .filter_map(Json::as_str)
+        .any(|r#type| types.contains(&r#type))
+}
+
+/// Return the values of a property that is either a value or an array.
+fn many(value: &Json) -> Vec<&Json> {
+    match value {
+        Json::Array(values) => values.iter().collect(),
+        Json::Null => vec![],
+        value => vec![value],
+    }
+}
+
+fn first(value: &Json) -> &Json {
+    many(value).into_iter().next().unwrap_or(&Json::Null)
+}
+
+fn text(value: &Json) -> Option<String> {
+    value.as_str().map(ToOwned::to_owned)
+}
+
+/// Return the name of a profile, e.g. an issuer, or else its identifier.
+fn profile_name(profile: &Json) -> Option<String> {
+    text(profile)
+        .or_else(|| text(&profile["name"]))
+        .or_else(|| text(&profile["id"]))
+}
+
+/// Return the URL of an image, given as a URL or as an image object.
+fn image(image: &Json) -> Option<String> {
+    text(image).or_else(|| text(&image["id"]))
+}
+
+/// Return the credential of a compact VC-JWT, without verifying it.
+fn jwt_credential(jwt: &str) -> Option<Json> {
+    let payload = jwt.split('.').nth(1)?;
+    let mut claims: Json =
+        serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    // VC-JWTs of VCDM 1.1 nest the credential under the `vc` claim.
+    match claims.get_mut("vc").map(Json::take) {
+        Some(vc) => Some(vc),
+        None => Some(claims),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::json_vc::JsonVc;
+
+    fn badge(achievement_id: &str) -> Json {
+        json!({
+            "@context": [
+                "https://www.w3.org/ns/credentials/v2",
+                "https://purl.imsglobal.org/spec/ob/v3p0/context-3.0.3.json"
+            ],
+            "id": "urn:uuid:a63a60be-f4af-491c-87fc-2c8fd3007a58",
+            "type": ["VerifiableCredential", "OpenBadgeCredential"],
+            "issuer": {
+                "id": "did:example:university",
+                "type": ["Profile"],
+                "name": "Example University"
+            },
+            "validFrom": "2024-06-01T00:00:00Z",
+            "name": "Teamwork Badge",
+            "credentialSubject": {
+                "id": "did:example:learner",
+                "type": ["AchievementSubject"],
+                "achievement": {
+                    "id": achievement_id,
+                    "type": ["Achievement"],
+                    "achievementType": "Competency",
+                    "name": "Teamwork",
+                    "description": "Works well in teams.",
+                    "criteria": { "narrative": "Lead a team project." },
+                    "image": { "id": "https://example.edu/teamwork.png", "type": "Image" }
+                },
+                "result": [{ "type": ["Result"], "value": "A", "status": "Completed" }]
+            }
+        })
+    }
+
+    #[test]
+    fn reads_open_badges() {
+        let credential = ParsedCredential::new_ldp_vc(
+            JsonVc::new_from_json(badge("https://example.edu/achievements/teamwork").to_string())
+                .unwrap(),
+        );
+        let badge = credential.clone().as_open_badge().expect("an Open Badge");
+        assert_eq!(badge.name().as_deref(), Some("Teamwork Badge"));
+        assert_eq!(badge.issuer().as_deref(), Some("Example University"));
+        assert_eq!(badge.recipient().as_deref(), Some("did:example:learner"));
+        assert_eq!(
+            badge.awarded_date().as_deref(),
+            Some("2024-06-01T00:00:00Z")
+        );
+        let achievement = badge.achievement();
+        assert_eq!(achievement.name, "Teamwork");
+        assert_eq!(
+            achievement.criteria.as_deref(),
+            Some("Lead a team project.")
+        );
+        assert_eq!(
+            achievement.image.as_deref(),
+            Some("https://example.edu/teamwork.png")
+        );
+        assert_eq!(badge.results()[0].value.as_deref(), Some("A"));
+        assert!(credential.clone().as_learner_record().is_none());
+
+        let definition = |achievement_id: Option<&str>| {
+            serde_json::from_str(&open_badge_presentation_definition(
+                achievement_id.map(Into::into),
+            ))
+            .unwrap()
+        };
+        assert!(credential.check_presentation_definition(&definition(None)));
+        assert!(credential.check_presentation_definition(&definition(Some(
+            "https://example.edu/achievements/teamwork"
+        ))));
+        assert!(!credential.check_presentation_definition(&definition(Some(
+            "https://example.edu/achievements/other"
+        ))));
+
+        let record = ParsedCredential::new_ldp_vc(
+            JsonVc::new_from_json(
+                json!({
+                    "@context": [
+                        "https://www.w3.org/ns/credentials/v2",
+                        "https://purl.imsglobal.org/spec/clr/v2p0/context-2.0.1.json"
+                    ],
+                    "id": "urn:uuid:4a2d8f6e-3b1c-4d5e-9f70-8a1b2c3d4e5f",
+                    "type": ["VerifiableCredential", "ClrCredential"],
+                    "issuer": "did:example:university",
+                    "validFrom": "2024-06-01T00:00:00Z",
+                    "name": "Transcript",
+                    "credentialSubject": {
+                        "id": "did:example:learner",
+                        "type": ["ClrSubject"],
+                        "verifiableCredential": [
+                            badge("https://example.edu/achievements/teamwork"),
+                            badge("https://example.edu/achievements/leadership")
+                        ]
+                    }
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        )
+        .as_learner_record()
+        .expect("a learner record");
+        assert_eq!(record.issuer().as_deref(), Some("did:example:university"));
+        assert_eq!(record.badges().len(), 2);
+    }
+}
@@ -0,0 +1,162 @@
+//! Previews of incoming credentials, to show an "add this credential?" screen
+//! before the credential is stored.
+
+use super::claims::ClaimsNode;
+use super::mdoc::Mdoc;
+use super::validity::CredentialValidity;
+use super::{Credential, CredentialDecodingError, CredentialFormat, ParsedCredential};
+use crate::{CredentialType, KeyAlias, Uuid};
+
+use std::sync::Arc;
+
+/// The key alias of previewed mdocs, which are not bound to a key yet.
+const PREVIEW_KEY_ALIAS: &str = "preview";
+
+/// What an incoming credential is, to display before it is stored.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct CredentialPreview {
+    pub format: CredentialFormat,
+    pub r#type: CredentialType,
+    pub issuer: Option<String>,
+    pub validity: CredentialValidity,
+    pub claims: Arc<ClaimsNode>,
+}
+
+/// Preview a raw credential, as received from an issuer.
+///
+/// As [ParsedCredential::preview].
+#[uniffi::export]
+pub fn preview_credential(
+    raw: Vec<u8>,
+    format_hint: Option<CredentialFormat>,
+) -> Result<CredentialPreview, CredentialDecodingError> {
+    ParsedCredential::preview(raw, format_hint)
+}
+
+impl ParsedCredential {
+    /// Parse a raw credential, and return what it is without storing it.
+    ///
+    /// The format is detected from the credential when no hint is given:
+    /// JSON for `ldp_vc`, compact SD-JWTs, compact JWTs, and base64url
+    /// encoded `IssuerSigned` or CBOR documents for mdocs, or else CWTs.
+    ///
+    /// NOTE: CWTs do not identify their type, which is previewed as empty.
+    pub fn preview(
+        raw: Vec<u8>,
+        format_hint: Option<CredentialFormat>,
+    ) -> Result<CredentialPreview, CredentialDecodingError> {
+        let formats = match format_hint {
+            Some(format) => vec![format],
+            None => candidate_formats(&raw),
+        };
+
+        let mut error = None;
+        for format in formats {
+            match parse(raw.clone(), format) {
+                Ok(credential) => {
+                    return Ok(CredentialPreview {
+                        format: credential.format(),
+                        r#type: credential.r#type(),
+                        issuer: credential.issuer(),
+                        validity: credential.validity(),
+                        claims: credential.claims(),
+                    })
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+
+        Err(error.unwrap_or_else(|| {
+            CredentialDecodingError::Deserialization("unrecognized credential".into())
+        }))
+    }
+}
+
+/// Return the formats a raw credential may be in, most likely first.
+fn candidate_formats(raw: &[u8]) -> Vec<CredentialFormat> {
+    let Ok(text) = std::str::from_utf8(raw) else {
+        return vec![CredentialFormat::MsoMdoc, CredentialFormat::Cwt];
+    };
+    let text = text.trim();
+
+    if text.starts_with('{') {
+        vec![CredentialFormat::LdpVc]
+    } else if text.contains('~') {
+        vec![CredentialFormat::DcSdJwt, CredentialFormat::VCDM2SdJwt]
+    } else if text.split('.').count() == 3 {
+        vec![CredentialFormat::JwtVcJson, CredentialFormat::JwtVcJsonLd]
+    } else {
+        vec![CredentialFormat::MsoMdoc, CredentialFormat::Cwt]
+    }
+}
+
+fn parse(
+    raw: Vec<u8>,
+    format: CredentialFormat,
+) -> Result<Arc<ParsedCredential>, CredentialDecodingError> {
+    if format == CredentialFormat::MsoMdoc {
+        let key_alias = KeyAlias(PREVIEW_KEY_ALIAS.into());
+        let mdoc = match String::from_utf8(raw) {
+            Ok(issuer_signed) => Mdoc::new_from_base64url_encoded_issuer_signed(
+                issuer_signed.trim().into(),
+                key_alias,
+            )?,
+            Err(e) => Mdoc::from_cbor_encoded_document(e.into_bytes(), key_alias)?,
+        };
+        return Ok(ParsedCredential::new_mso_mdoc(mdoc));
+    }
+
+    Credential {
+        id: Uuid::new_v4(),
+        r#type: CredentialType(String::new()),
+        format,
+        payload: raw,
+        key_alias: None,
+        display: vec![],
+    }
+    .try_into_parsed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::claims::ClaimLeaf;
+
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn previews_credentials() {
+        let vc = serde_json::json!({
+            "@context": ["https://www.w3.org/2018/credentials/v1"],
+            "type": ["VerifiableCredential", "UniversityDegreeCredential"],
+            "issuer": "did:example:university",
+            "issuanceDate": "2024-01-01T00:00:00Z",
+            "credentialSubject": { "id": "did:example:holder", "name": "Alice" },
+        });
+
+        let preview = ParsedCredential::preview(vc.to_string().into_bytes(), None).unwrap();
+        assert_eq!(preview.format, CredentialFormat::LdpVc);
+        assert_eq!(preview.issuer.as_deref(), Some("did:example:university"));
+        assert_eq!(
+            preview.validity.not_before,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+        );
+        assert_eq!(
+            preview
+                .claims
+                .get("credentialSubject".into())
+                .and_then(|subject| subject.get("name".into()))
+                .and_then(|name| name.leaf()),
+            Some(ClaimLeaf::Text {
+                value: "Alice".into()
+            })
+        );
+
+        assert!(ParsedCredential::preview(b"not a credential".to_vec(), None).is_err());
+        assert!(ParsedCredential::preview(
+            vc.to_string().into_bytes(),
+            Some(CredentialFormat::JwtVcJson)
+        )
+        .is_err());
+    }
+}
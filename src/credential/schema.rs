@@ -0,0 +1,376 @@
+//! JSON Schema validation of credential payloads, against the schemas their
+//! `credentialSchema` references, for W3C VCs, or the type metadata of their
+//! `vct`, for IETF SD-JWT VCs, so that malformed issuer data is caught when a
+//! credential is imported or presented rather than by the verifier.
+//!
+//! NOTE: the type metadata a `vct` extends and the remote `$ref`s of schemas
+//! are not resolved.
+
+use super::verification::VerificationCheck;
+use super::{ParsedCredential, ParsedCredentialInner};
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use jsonschema::JSONSchema;
+use serde_json::Value as Json;
+
+/// The `credentialSchema` types whose `id` is the URL of a JSON Schema.
+const JSON_SCHEMA_TYPES: &[&str] = &["JsonSchema", "JsonSchemaValidator2018"];
+
+/// The `credentialSchema` type whose `id` is the URL of a credential holding
+/// the JSON Schema in its `credentialSubject.jsonSchema`.
+const JSON_SCHEMA_CREDENTIAL_TYPE: &str = "JsonSchemaCredential";
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum CredentialSchemaError {
+    #[error("Failed to fetch the document {url}: {reason}")]
+    Fetch { url: String, reason: String },
+    #[error("Invalid document {url}: {reason}")]
+    InvalidDocument { url: String, reason: String },
+}
+
+/// A claim of a credential violating its schema.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct SchemaViolation {
+    /// The JSON pointer of the claim, e.g. `/credentialSubject/birthDate`.
+    pub instance_path: String,
+    pub message: String,
+}
+
+/// The outcome of validating a credential against one of its schemas.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct SchemaValidation {
+    /// The URL of the schema, or of the type metadata embedding it.
+    pub schema_uri: String,
+    /// Skipped when the schema could not be fetched or compiled.
+    pub check: VerificationCheck,
+    pub violations: Vec<SchemaViolation>,
+}
+
+/// JSON Schemas and type metadata documents, by URL, used to validate
+/// credentials without fetching them every time.
+#[derive(Debug, Default, uniffi::Object)]
+pub struct CredentialSchemaCache {
+    documents: RwLock<HashMap<String, String>>,
+}
+
+#[uniffi::export]
+impl CredentialSchemaCache {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Return the cached documents, by URL, e.g. to persist them.
+    pub fn documents(&self) -> HashMap<String, String> {
+        self.documents
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Cache the document of a URL, replacing any previous one.
+    pub fn insert(&self, url: String, document: String) -> Result<(), CredentialSchemaError> {
+        if let Err(e) = serde_json::from_str::<Json>(&document) {
+            return Err(CredentialSchemaError::InvalidDocument {
+                url,
+                reason: format!("{e:?}"),
+            });
+        }
+
+        self.documents
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(url, document);
+        Ok(())
+    }
+
+    /// Remove every cached document.
+    pub fn clear(&self) {
+        self.documents
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+}
+
+impl CredentialSchemaCache {
+    /// Return the document of a URL, fetching and caching it if it is not
+    /// cached yet.
+    async fn get(&self, url: &str) -> Result<Json, CredentialSchemaError> {
+        let cached = self
+            .documents
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(url)
+            .cloned();
+        let document = match cached {
+            Some(document) => document,
+            None => {
+                let fetch_error = |e: reqwest::Error| CredentialSchemaError::Fetch {
+                    url: url.to_owned(),
+                    reason: format!("{e:?}"),
+                };
+                let document = reqwest::Client::new()
+                    .get(url)
+                    .header(
+                        reqwest::header::ACCEPT,
+                        "application/schema+json, application/json",
+                    )
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(fetch_error)?
+                    .text()
+                    .await
+                    .map_err(fetch_error)?;
+                self.insert(url.to_owned(), document.clone())?;
+                document
+            }
+        };
+
+        serde_json::from_str(&document).map_err(|e| CredentialSchemaError::InvalidDocument {
+            url: url.to_owned(),
+            reason: format!("{e:?}"),
+        })
+    }
+}
+
+/// Where the schema of a credential is read from.
+enum SchemaReference {
+    /// A JSON Schema.
+    Schema(String),
+    /// A credential holding a JSON Schema.
+    SchemaCredential(String),
+    /// The SD-JWT VC type metadata of a `vct`.
+    TypeMetadata(String),
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl ParsedCredential {
+    /// Validate the claims of the credential against the schemas it
+    /// references, fetching those missing from the cache.
+    ///
+    /// Credentials that reference no schema have no validation.
+    pub async fn validate_schemas(
+        &self,
+        cache: Arc<CredentialSchemaCache>,
+    ) -> Vec<SchemaValidation> {
+        let Some((instance, references)) = self.schema_references() else {
+            return vec![];
+        };
+
+        let mut validations = Vec::new();
+        for reference in references {
+            let (schema_uri, schema) = match reference {
+                SchemaReference::Schema(url) => {
+                    let schema = cache.get(&url).await;
+                    (url, schema)
+                }
+                SchemaReference::SchemaCredential(url) => {
+                    let schema = cache.get(&url).await.and_then(|credential| {
+                        let schema = &credential["credentialSubject"]["jsonSchema"];
+                        if !schema.is_object() {
+                            return Err(CredentialSchemaError::InvalidDocument {
+                                url: url.clone(),
+                                reason: "the credential holds no JSON Schema".into(),
+                            });
+                        }
+                        Ok(schema.clone())
+                    });
+                    (url, schema)
+                }
+                SchemaReference::TypeMetadata(vct) => {
+                    let schema = type_metadata_schema(&cache, &vct).await;
+                    (vct, schema)
+                }
+            };
+
+            validations.push(match schema {
+                Ok(schema) => validate(schema_uri, &schema, &instance),
+                Err(e) => SchemaValidation {
+                    schema_uri,
+                    check: VerificationCheck::Skipped {
+                        reason: format!("{e:?}"),
+                    },
+                    violations: vec![],
+                },
+            });
+        }
+        validations
+    }
+}
+
+impl ParsedCredential {
+    /// Return the claims validated against the schemas of the credential,
+    /// with the references of the schemas.
+    fn schema_references(&self) -> Option<(Json, Vec<SchemaReference>)> {
+        if let ParsedCredentialInner::DcSdJwt(_) = &self.inner {
+            let claims = self.claims_as_json()?;
+            let vct = claims["vct"]
+                .as_str()
+                .filter(|vct| vct.starts_with("https://"))?;
+            let references = vec![SchemaReference::TypeMetadata(vct.to_owned())];
+            return Some((claims, references));
+        }
+
+        let credential = self.definition_json()?;
+        let schemas = match &credential["credentialSchema"] {
+            Json::Array(schemas) => schemas.clone(),
+            Json::Object(_) => vec![credential["credentialSchema"].clone()],
+            _ => return None,
+        };
+        let references = schemas
+            .iter()
+            .filter_map(|schema| {
+                let id = schema["id"].as_str()?.to_owned();
+                match schema["type"].as_str()? {
+                    r#type if JSON_SCHEMA_TYPES.contains(&r#type) => {
+                        Some(SchemaReference::Schema(id))
+                    }
+                    JSON_SCHEMA_CREDENTIAL_TYPE => Some(SchemaReference::SchemaCredential(id)),
+                    _ => None,
+                }
+            })
+            .collect();
+        Some((credential, references))
+    }
+}
+
+/// Return the schema of the type metadata of a `vct`, embedded or
+/// referenced by its `schema_uri`.
+async fn type_metadata_schema(
+    cache: &CredentialSchemaCache,
+    vct: &str,
+) -> Result<Json, CredentialSchemaError> {
+    let metadata = cache.get(vct).await?;
+    if metadata["schema"].is_object() {
+        return Ok(metadata["schema"].clone());
+    }
+    match metadata["schema_uri"].as_str() {
+        Some(schema_uri) => cache.get(schema_uri).await,
+        None => Err(CredentialSchemaError::InvalidDocument {
+            url: vct.to_owned(),
+            reason: "the type metadata has no schema".into(),
+        }),
+    }
+}
+
+/// Validate claims against a schema.
+fn validate(schema_uri: String, schema: &Json, instance: &Json) -> SchemaValidation {
+    let compiled = match JSONSchema::compile(schema) {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            return SchemaValidation {
+                schema_uri,
+                check: VerificationCheck::Skipped {
+                    reason: format!("invalid schema: {e}"),
+                },
+                violations: vec![],
+            }
+        }
+    };
+
+    let violations = match compiled.validate(instance) {
+        Ok(()) => vec![],
+        Err(errors) => errors
+            .map(|error| SchemaViolation {
+                instance_path: error.instance_path.to_string(),
+                message: error.to_string(),
+            })
+            .collect::<Vec<_>>(),
+    };
+    let check = match violations.len() {
+        0 => VerificationCheck::Passed,
+        count => VerificationCheck::Failed {
+            reason: format!("{count} claims violate the schema"),
+        },
+    };
+    SchemaValidation {
+        schema_uri,
+        check,
+        violations,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::json_vc::JsonVc;
+
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn validates_credential_schemas() {
+        let schema_uri = "https://example.com/schemas/employee.json".to_string();
+        let cache = CredentialSchemaCache::new();
+        cache
+            .insert(
+                schema_uri.clone(),
+                json!({
+                    "type": "object",
+                    "required": ["credentialSubject"],
+                    "properties": {
+                        "credentialSubject": {
+                            "type": "object",
+                            "required": ["employeeId"],
+                            "properties": { "employeeId": { "type": "string" } },
+                        },
+                    },
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+        let credential = |subject: Json| {
+            ParsedCredential::new_ldp_vc(
+                JsonVc::new_from_json(
+                    json!({
+                        "@context": ["https://www.w3.org/2018/credentials/v1"],
+                        "type": ["VerifiableCredential"],
+                        "issuer": "did:example:issuer",
+                        "issuanceDate": "2024-01-01T00:00:00Z",
+                        "credentialSubject": subject,
+                        "credentialSchema": {
+                            "id": schema_uri,
+                            "type": "JsonSchema",
+                        },
+                    })
+                    .to_string(),
+                )
+                .unwrap(),
+            )
+        };
+
+        let validations = credential(json!({ "employeeId": "E-42" }))
+            .validate_schemas(cache.clone())
+            .await;
+        assert_eq!(
+            validations,
+            [SchemaValidation {
+                schema_uri: schema_uri.clone(),
+                check: VerificationCheck::Passed,
+                violations: vec![],
+            }]
+        );
+
+        let validations = credential(json!({ "employeeId": 42 }))
+            .validate_schemas(cache.clone())
+            .await;
+        assert!(matches!(
+            validations[0].check,
+            VerificationCheck::Failed { .. }
+        ));
+        assert_eq!(
+            validations[0].violations[0].instance_path,
+            "/credentialSubject/employeeId"
+        );
+
+        cache.clear();
+        assert!(matches!(
+            cache.insert(schema_uri, "not json".into()),
+            Err(CredentialSchemaError::InvalidDocument { .. })
+        ));
+    }
+}
@@ -0,0 +1,222 @@
+//! Management of the disclosures of SD-JWT credentials, to share reduced
+//! versions of credentials outside of OID4VP presentations.
+
+use super::{
+    disclosure::{self, DisclosedSdJwt},
+    ietf_sd_jwt_vc::IetfSdJwtVc,
+    vcdm2_sd_jwt::{SdJwtError, VCDM2SdJwt},
+};
+
+use base64::prelude::*;
+use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
+
+/// The only hash algorithm of disclosure digests that is supported.
+const SHA_256: &str = "sha-256";
+
+/// A disclosure of an SD-JWT, revealing a single claim.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct SdJwtDisclosure {
+    /// The digest the disclosure is referenced by, which identifies it.
+    pub digest: String,
+    /// The disclosure as it appears in the compact SD-JWT.
+    pub encoded: String,
+    /// The JSONPath of the disclosed claim in the revealed credential.
+    pub path: String,
+    /// The claim name, for object property disclosures.
+    pub name: Option<String>,
+    /// The disclosed value, as JSON.
+    pub value: String,
+}
+
+#[uniffi::export]
+impl VCDM2SdJwt {
+    /// Return the disclosures of the credential.
+    pub fn disclosures(&self) -> Result<Vec<SdJwtDisclosure>, SdJwtError> {
+        disclosures(self.inner.as_ref())
+    }
+
+    /// Return a compact SD-JWT releasing only the disclosures with the given
+    /// digests, and those of the claims enclosing them.
+    ///
+    /// NOTE: the redacted copy has no key binding, and is meant to be
+    /// presented rather than stored in place of the credential.
+    pub fn redacted(&self, digests: Vec<String>) -> Result<String, SdJwtError> {
+        redacted(self.inner.as_ref(), &digests)
+    }
+
+    /// Check that every disclosure of the credential is referenced by a
+    /// digest of the issuer-signed JWT.
+    pub fn verify_disclosure_digests(&self) -> Result<(), SdJwtError> {
+        verify_disclosure_digests(self.inner.as_ref())
+    }
+}
+
+#[uniffi::export]
+impl IetfSdJwtVc {
+    /// As [VCDM2SdJwt::disclosures].
+    pub fn disclosures(&self) -> Result<Vec<SdJwtDisclosure>, SdJwtError> {
+        disclosures(self.inner.as_ref())
+    }
+
+    /// As [VCDM2SdJwt::redacted].
+    pub fn redacted(&self, digests: Vec<String>) -> Result<String, SdJwtError> {
+        redacted(self.inner.as_ref(), &digests)
+    }
+
+    /// As [VCDM2SdJwt::verify_disclosure_digests].
+    pub fn verify_disclosure_digests(&self) -> Result<(), SdJwtError> {
+        verify_disclosure_digests(self.inner.as_ref())
+    }
+}
+
+fn disclosures(compact: &str) -> Result<Vec<SdJwtDisclosure>, SdJwtError> {
+    let sd_jwt = parse(compact)?;
+    let revealed = sd_jwt
+        .reveal()
+        .ok_or_else(|| SdJwtError::InvalidSdJwt("failed to reveal the claims".into()))?;
+
+    sd_jwt
+        .disclosures
+        .iter()
+        .map(|disclosure| {
+            Ok(SdJwtDisclosure {
+                digest: digest(&disclosure.encoded),
+                encoded: disclosure.encoded.clone(),
+                path: disclosure::json_path(&revealed, &disclosure.pointer),
+                name: disclosure.name.clone(),
+                value: serde_json::to_string(&disclosure.value)
+                    .map_err(|e| SdJwtError::Serialization(format!("{e:?}")))?,
+            })
+        })
+        .collect()
+}
+
+fn redacted(compact: &str, digests: &[String]) -> Result<String, SdJwtError> {
+    let sd_jwt = parse(compact)?;
+    let mut pointers = vec![];
+    for expected in digests {
+        let disclosure = sd_jwt
+            .disclosures
+            .iter()
+            .find(|disclosure| &digest(&disclosure.encoded) == expected)
+            .ok_or_else(|| SdJwtError::InvalidSdJwt(format!("unknown disclosure: {expected}")))?;
+        pointers.push(disclosure.pointer.clone());
+    }
+
+    // Only the enclosing claims of the chosen disclosures are released, not
+    // the claims nested inside them.
+    let released = sd_jwt
+        .disclosures
+        .iter()
+        .filter(|disclosure| {
+            pointers
+                .iter()
+                .any(|pointer| pointer.starts_with(&disclosure.pointer))
+        })
+        .collect::<Vec<_>>();
+    Ok(sd_jwt.present(&released))
+}
+
+fn verify_disclosure_digests(compact: &str) -> Result<(), SdJwtError> {
+    let sd_jwt = parse(compact)?;
+
+    let payload = compact
+        .split('~')
+        .next()
+        .and_then(|jwt| jwt.split('.').nth(1))
+        .and_then(|payload| BASE64_URL_SAFE_NO_PAD.decode(payload).ok())
+        .and_then(|payload| serde_json::from_slice::<Json>(&payload).ok())
+        .ok_or_else(|| SdJwtError::SdJwtDecoding("invalid issuer-signed JWT".into()))?;
+    let algorithm = payload["_sd_alg"].as_str().unwrap_or(SHA_256);
+    if algorithm != SHA_256 {
+        return Err(SdJwtError::InvalidSdJwt(format!(
+            "unsupported digest algorithm: {algorithm}"
+        )));
+    }
+
+    // Parsing drops the disclosures no digest references.
+    let unreferenced = compact
+        .split('~')
+        .skip(1)
+        .filter(|part| !part.is_empty() && part.matches('.').count() != 2)
+        .find(|encoded| {
+            !sd_jwt
+                .disclosures
+                .iter()
+                .any(|disclosure| disclosure.encoded == *encoded)
+        });
+    match unreferenced {
+        Some(encoded) => Err(SdJwtError::InvalidSdJwt(format!(
+            "the disclosure {encoded} is not referenced by the credential"
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn parse(compact: &str) -> Result<DisclosedSdJwt, SdJwtError> {
+    DisclosedSdJwt::parse(compact)
+        .ok_or_else(|| SdJwtError::SdJwtDecoding("failed to parse the disclosures".into()))
+}
+
+fn digest(encoded: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(encoded.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ssi::{
+        claims::{sd_jwt::SdAlg, vc_jose_cose::SdJwtVc},
+        json_pointer, JWK,
+    };
+
+    #[tokio::test]
+    async fn manages_disclosures() {
+        let claims: SdJwtVc = serde_json::from_value(serde_json::json!({
+            "@context": ["https://www.w3.org/ns/credentials/v2"],
+            "type": ["VerifiableCredential"],
+            "issuer": "did:example:issuer",
+            "credentialSubject": {
+                "name": "John Smith",
+                "email": "john.smith@example.com",
+            },
+        }))
+        .unwrap();
+        let compact = claims
+            .conceal_and_sign(
+                SdAlg::Sha256,
+                &[
+                    json_pointer!("/credentialSubject/name"),
+                    json_pointer!("/credentialSubject/email"),
+                ],
+                &JWK::generate_ed25519().unwrap(),
+            )
+            .await
+            .unwrap()
+            .to_string();
+        let credential = VCDM2SdJwt::new_from_compact_sd_jwt(compact.clone()).unwrap();
+        credential.verify_disclosure_digests().unwrap();
+
+        let disclosures = credential.disclosures().unwrap();
+        assert_eq!(disclosures.len(), 2);
+        let email = disclosures
+            .iter()
+            .find(|disclosure| disclosure.name.as_deref() == Some("email"))
+            .unwrap();
+        assert_eq!(email.path, "$['credentialSubject']['email']");
+        assert_eq!(email.value, r#""john.smith@example.com""#);
+
+        let redacted = VCDM2SdJwt::new_from_compact_sd_jwt(
+            credential.redacted(vec![email.digest.clone()]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(redacted.disclosures().unwrap(), vec![email.clone()]);
+        redacted.verify_disclosure_digests().unwrap();
+        assert!(credential.redacted(vec!["unknown".into()]).is_err());
+
+        let forged = BASE64_URL_SAFE_NO_PAD.encode(r#"["salt","admin",true]"#);
+        assert!(verify_disclosure_digests(&format!("{compact}{forged}~")).is_err());
+    }
+}
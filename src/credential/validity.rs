@@ -0,0 +1,155 @@
+use super::{ParsedCredential, ParsedCredentialInner};
+use crate::clock;
+
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value as Json;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// The validity window of a credential.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct CredentialValidity {
+    /// The time before which the credential is not valid, if any.
+    pub not_before: Option<SystemTime>,
+    /// The time at which the credential expires, if any.
+    pub expires_at: Option<SystemTime>,
+}
+
+impl CredentialValidity {
+    /// Check whether the credential is valid at the given time.
+    pub fn is_valid_at(&self, time: SystemTime) -> bool {
+        self.is_valid_with_leeway(time, Duration::ZERO)
+    }
+
+    /// Check whether the credential is valid at the given time, allowing for
+    /// a leeway on either side of the validity window.
+    pub fn is_valid_with_leeway(&self, time: SystemTime, leeway: Duration) -> bool {
+        let started = match self.not_before {
+            Some(not_before) => clock::has_reached(not_before, time, leeway),
+            None => true,
+        };
+        let expired = self
+            .expires_at
+            .is_some_and(|expires_at| clock::has_passed(expires_at, time, leeway));
+
+        started && !expired
+    }
+
+    /// Check whether the credential is still valid at `time`, but expires
+    /// within `duration` of it.
+    pub fn expires_within(&self, time: SystemTime, duration: Duration) -> bool {
+        self.expires_at.is_some_and(|expires_at| {
+            // A window past the representable times includes every expiry.
+            time < expires_at
+                && time
+                    .checked_add(duration)
+                    .is_none_or(|end| expires_at <= end)
+        })
+    }
+}
+
+#[uniffi::export]
+impl ParsedCredential {
+    /// Return the validity window of the credential.
+    pub fn validity(&self) -> CredentialValidity {
+        match &self.inner {
+            ParsedCredentialInner::MsoMdoc(mdoc) => {
+                let validity_info = &mdoc.document().mso.validity_info;
+                CredentialValidity {
+                    not_before: Some(validity_info.valid_from.into()),
+                    expires_at: Some(validity_info.valid_until.into()),
+                }
+            }
+            _ => self
+                .claims_as_json()
+                .map(|claims| validity_from_claims(&claims))
+                .unwrap_or(CredentialValidity {
+                    not_before: None,
+                    expires_at: None,
+                }),
+        }
+    }
+}
+
+/// Read the validity window from JWT (`nbf`, `exp`) or VCDM (`validFrom`,
+/// `validUntil`, and the VCDM 1.1 `issuanceDate`, `expirationDate`) claims.
+///
+/// JWT claims take precedence, as they are the ones checked by verifiers.
+pub(crate) fn validity_from_claims(claims: &Json) -> CredentialValidity {
+    // VC-JWTs (VCDM 1.1) nest the credential under the `vc` claim.
+    let credential = match claims.get("vc") {
+        Some(vc) => vc,
+        None => claims,
+    };
+
+    CredentialValidity {
+        not_before: numeric_date(&claims["nbf"])
+            .or_else(|| date_time(&credential["validFrom"]))
+            .or_else(|| date_time(&credential["issuanceDate"])),
+        expires_at: numeric_date(&claims["exp"])
+            .or_else(|| date_time(&credential["validUntil"]))
+            .or_else(|| date_time(&credential["expirationDate"])),
+    }
+}
+
+fn numeric_date(value: &Json) -> Option<SystemTime> {
+    value
+        .as_u64()
+        .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+fn date_time(value: &Json) -> Option<SystemTime> {
+    value
+        .as_str()
+        .and_then(|value| OffsetDateTime::parse(value, &Rfc3339).ok())
+        .map(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn reads_validity_from_claims() {
+        let jwt = serde_json::json!({
+            "nbf": 1_000,
+            "exp": 2_000,
+            "vc": { "expirationDate": "2030-01-01T00:00:00Z" }
+        });
+        assert_eq!(
+            validity_from_claims(&jwt),
+            CredentialValidity {
+                not_before: Some(at(1_000)),
+                expires_at: Some(at(2_000)),
+            }
+        );
+
+        let vcdm = serde_json::json!({
+            "validFrom": "1970-01-01T00:16:40Z",
+            "validUntil": "1970-01-01T00:33:20Z",
+        });
+        assert_eq!(validity_from_claims(&vcdm), validity_from_claims(&jwt));
+    }
+
+    #[test]
+    fn checks_expiry_window() {
+        let validity = CredentialValidity {
+            not_before: Some(at(1_000)),
+            expires_at: Some(at(2_000)),
+        };
+
+        assert!(!validity.is_valid_at(at(999)));
+        assert!(validity.is_valid_at(at(1_000)));
+        assert!(!validity.is_valid_at(at(2_000)));
+
+        let day = Duration::from_secs(86_400);
+        assert!(validity.expires_within(at(1_500), day));
+        assert!(!validity.expires_within(at(1_500), Duration::from_secs(100)));
+        assert!(!validity.expires_within(at(2_000), day));
+        assert!(validity.expires_within(at(1_500), Duration::MAX));
+    }
+}
@@ -1,4 +1,8 @@
-use super::{Credential, CredentialFormat, ParsedCredential, ParsedCredentialInner};
+use super::{
+    age_predicates,
+    disclosure::{self, DisclosedSdJwt},
+    Credential, CredentialFormat, ParsedCredential, ParsedCredentialInner,
+};
 use crate::{oid4vp::permission_request::RequestedField, CredentialType, KeyAlias};
 
 use std::sync::Arc;
@@ -72,11 +76,16 @@ impl VCDM2SdJwt {
             return false;
         };
 
-        // Check the JSON-encoded credential against the definition.
-        definition.is_credential_match(&json)
+        // Check the JSON-encoded credential against the definition, with the
+        // age predicates it can answer.
+        definition.is_credential_match(&age_predicates::with_derived_predicates(&json, definition))
     }
 
     /// Return the requested fields for the SD-JWT credential.
+    ///
+    /// These are exactly the fields that will be released when presenting the
+    /// credential: the requested fields if the definition requires disclosure
+    /// to be limited, and otherwise every selectively disclosable claim.
     pub fn requested_fields(
         &self,
         definition: &PresentationDefinition,
@@ -88,12 +97,27 @@ impl VCDM2SdJwt {
             return Vec::new();
         };
 
-        definition
-            .requested_fields(&json)
-            .into_iter()
-            .map(Into::into)
-            .map(Arc::new)
-            .collect()
+        let Some(sd_jwt) = DisclosedSdJwt::parse(self.inner.as_ref()) else {
+            log::debug!("failed to map the disclosures of the credential: {self:?}");
+            return Vec::new();
+        };
+
+        disclosure::released_fields(&sd_jwt, &json, definition)
+    }
+
+    /// Return the credential as a VpToken, releasing only the disclosures
+    /// needed for the given fields.
+    pub fn as_vp_token_with_fields(&self, fields: &[Arc<RequestedField>]) -> VpTokenItem {
+        let Some(sd_jwt) = DisclosedSdJwt::parse(self.inner.as_ref()) else {
+            return self.as_vp_token();
+        };
+
+        let pointers = fields
+            .iter()
+            .flat_map(|field| field.pointers.iter().cloned())
+            .collect::<Vec<_>>();
+
+        VpTokenItem::String(sd_jwt.present(&sd_jwt.select(&pointers)))
     }
 
     /// Return the credential as a VpToken
@@ -166,6 +190,7 @@ impl From<VCDM2SdJwt> for ParsedCredential {
     fn from(value: VCDM2SdJwt) -> Self {
         ParsedCredential {
             inner: ParsedCredentialInner::VCDM2SdJwt(Arc::new(value)),
+            display: vec![],
         }
     }
 }
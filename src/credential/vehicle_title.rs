@@ -0,0 +1,170 @@
+//! Vehicle title credentials, following the W3C Verifiable Vehicle Title
+//! (`https://w3id.org/vvt/v1`) profile.
+//!
+//! Vehicle titles are JSON-LD credentials, stored and presented as any
+//! [JsonVc]; this module reads their typed contents.
+
+use super::json_vc::JsonVc;
+use super::{ParsedCredential, ParsedCredentialInner};
+
+use std::sync::Arc;
+
+use serde_json::Value as Json;
+
+/// The credential type of vehicle titles.
+pub const VEHICLE_TITLE_TYPE: &str = "VehicleTitleCredential";
+
+#[derive(Debug, uniffi::Error, thiserror::Error)]
+pub enum VehicleTitleError {
+    #[error("the credential is not a {VEHICLE_TITLE_TYPE}")]
+    NotAVehicleTitle,
+    #[error("the vehicle title has no credentialSubject")]
+    MissingSubject,
+}
+
+/// An owner of the titled vehicle.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct VehicleOwner {
+    pub name: String,
+    /// The description of the owner, e.g. `Primary Owner`.
+    pub description: Option<String>,
+    /// The driver's license number of the owner.
+    pub license_number: Option<String>,
+}
+
+/// The odometer reading recorded on the title.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct OdometerReading {
+    pub value: String,
+    pub unit_code: Option<String>,
+    pub observation_date: Option<String>,
+    /// A brand of the reading, e.g. `ODOMETER IS NOT THE ACTUAL MILEAGE`.
+    pub description: Option<String>,
+}
+
+/// The contents of a vehicle title credential.
+#[derive(Debug, uniffi::Object)]
+pub struct VehicleTitle {
+    credential: Arc<JsonVc>,
+    subject: Json,
+}
+
+#[uniffi::export]
+impl VehicleTitle {
+    #[uniffi::constructor]
+    pub fn from_json_vc(credential: Arc<JsonVc>) -> Result<Arc<Self>, VehicleTitleError> {
+        if !credential.types().iter().any(|t| t == VEHICLE_TITLE_TYPE) {
+            return Err(VehicleTitleError::NotAVehicleTitle);
+        }
+
+        let subject = match serde_json::from_str::<Json>(
+            &credential.credential_as_json_encoded_utf8_string(),
+        ) {
+            Ok(mut credential) => match credential["credentialSubject"].take() {
+                // Titles have a single subject.
+                Json::Array(mut subjects) if !subjects.is_empty() => subjects.swap_remove(0),
+                subject @ Json::Object(_) => subject,
+                _ => return Err(VehicleTitleError::MissingSubject),
+            },
+            Err(_) => return Err(VehicleTitleError::MissingSubject),
+        };
+
+        Ok(Arc::new(Self {
+            credential,
+            subject,
+        }))
+    }
+
+    pub fn credential(&self) -> Arc<JsonVc> {
+        self.credential.clone()
+    }
+
+    /// The VIN of the vehicle.
+    pub fn vehicle_identification_number(&self) -> Option<String> {
+        self.text(&["vehicle", "vehicleIdentificationNumber"])
+    }
+
+    pub fn manufacturer(&self) -> Option<String> {
+        self.text(&["vehicle", "manufacturer", "name"])
+    }
+
+    /// The model year of the vehicle.
+    pub fn model_year(&self) -> Option<String> {
+        self.text(&["vehicle", "vehicleModelDate"])
+    }
+
+    pub fn license_plate(&self) -> Option<String> {
+        self.text(&["licensePlateIdentifier"])
+    }
+
+    pub fn title_issue_date(&self) -> Option<String> {
+        self.text(&["titleIssueDate"])
+    }
+
+    pub fn registration_expiration_date(&self) -> Option<String> {
+        self.text(&["registrationExpirationDate"])
+    }
+
+    /// The name of the lienholder, if the vehicle is under a lien.
+    pub fn lienholder(&self) -> Option<String> {
+        self.text(&["lienholder", "name"])
+    }
+
+    pub fn owners(&self) -> Vec<VehicleOwner> {
+        let owners = match &self.subject["owner"] {
+            Json::Array(owners) => owners.iter().collect(),
+            owner @ Json::Object(_) => vec![owner],
+            _ => vec![],
+        };
+
+        owners
+            .into_iter()
+            .filter_map(|owner| {
+                Some(VehicleOwner {
+                    name: owner["name"].as_str()?.to_owned(),
+                    description: owner["description"].as_str().map(ToOwned::to_owned),
+                    license_number: owner["hasCertification"]["certificationIdentification"]
+                        .as_str()
+                        .map(ToOwned::to_owned),
+                })
+            })
+            .collect()
+    }
+
+    pub fn odometer_reading(&self) -> Option<OdometerReading> {
+        let reading = &self.subject["odometerReading"];
+        Some(OdometerReading {
+            value: json_text(&reading["value"])?,
+            unit_code: json_text(&reading["unitCode"]),
+            observation_date: json_text(&reading["observationDate"]),
+            description: json_text(&reading["description"]),
+        })
+    }
+}
+
+impl VehicleTitle {
+    fn text(&self, path: &[&str]) -> Option<String> {
+        json_text(path.iter().fold(&self.subject, |value, key| &value[*key]))
+    }
+}
+
+/// Return a claim as text, as vehicle titles encode numbers either way.
+fn json_text(value: &Json) -> Option<String> {
+    match value {
+        Json::String(value) => Some(value.clone()),
+        Json::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[uniffi::export]
+impl ParsedCredential {
+    /// Return the contents of the credential, if it is a vehicle title.
+    pub fn as_vehicle_title(&self) -> Option<Arc<VehicleTitle>> {
+        match &self.inner {
+            ParsedCredentialInner::LdpVc(vc) => VehicleTitle::from_json_vc(vc.clone()).ok(),
+            ParsedCredentialInner::LdpVcBbs(vc) => VehicleTitle::from_json_vc(vc.json_vc()).ok(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,462 @@
+//! Local verification of stored credentials, for wallets to flag broken or
+//! tampered credentials before they are presented.
+
+use super::{ParsedCredential, ParsedCredentialInner};
+use crate::clock::{self, Clock};
+use crate::status::{CredentialStatus, StatusListFetcher};
+use crate::trust_list::TrustListManager;
+use crate::verifier::helpers;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use base64::prelude::*;
+use p256::{
+    ecdsa::{signature::Verifier, DerSignature, Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use serde_cbor::Value as Cbor;
+use serde_json::Value as Json;
+use ssi::{
+    claims::jws::verify_bytes,
+    dids::{AnyDidMethod, DIDResolver},
+    jwk::Algorithm,
+    prelude::{JwsString, VerificationParameters},
+    JWK,
+};
+use x509_cert::{
+    der::{Decode, Encode},
+    Certificate,
+};
+
+/// What to verify credentials against.
+#[derive(Debug, Clone, Default, uniffi::Record)]
+pub struct CredentialVerificationOptions {
+    /// The JWKs of known issuers, by issuer identifier or key ID, used
+    /// instead of resolving the key of the issuer, e.g. its DID.
+    pub issuer_keys: HashMap<String, String>,
+    /// The PEM encoded certificates trusted to issue the certificates of
+    /// issuers, e.g. IACA certificates for mdocs.
+    pub trust_anchors: Vec<String>,
+    /// The trusted lists whose services are trusted as well.
+    pub trust_list: Option<Arc<TrustListManager>>,
+    /// Whether to fetch the status list of the credential, which requires
+    /// network access.
+    pub check_status: bool,
+    /// The clock the validity window is checked against, or the system clock
+    /// if unset.
+    pub clock: Option<Arc<dyn Clock>>,
+    /// The leeway allowed on either side of the validity window, for clocks
+    /// skewed from the clock of the issuer.
+    pub clock_leeway: Duration,
+}
+
+/// The outcome of a verification check.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum VerificationCheck {
+    Passed,
+    Failed {
+        reason: String,
+    },
+    /// The check could not be made, e.g. the credential has no status.
+    Skipped {
+        reason: String,
+    },
+}
+
+impl VerificationCheck {
+    fn from_result(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Self::Passed,
+            Err(reason) => Self::Failed { reason },
+        }
+    }
+}
+
+/// The outcome of a local verification of a credential.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct CredentialVerification {
+    /// Whether no check failed.
+    pub valid: bool,
+    /// The signature of the issuer.
+    pub signature: VerificationCheck,
+    /// The validity window of the credential.
+    pub validity: VerificationCheck,
+    /// The status of the credential, as reported by its issuer.
+    pub status: VerificationCheck,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl ParsedCredential {
+    /// Verify the signature of the issuer, the validity window and, when
+    /// requested, the status of the credential.
+    ///
+    /// NOTE: only P-256 certificates are supported, and the element digests
+    /// of mdocs are not checked.
+    pub async fn verify(&self, options: CredentialVerificationOptions) -> CredentialVerification {
+        let signature = VerificationCheck::from_result(self.verify_signature(&options).await);
+
+        let now = options.clock.clone().unwrap_or_else(clock::system).now();
+        let validity = if self
+            .validity()
+            .is_valid_with_leeway(now, options.clock_leeway)
+        {
+            VerificationCheck::Passed
+        } else {
+            VerificationCheck::Failed {
+                reason: "the credential is expired or not yet valid".into(),
+            }
+        };
+
+        let status = if !options.check_status {
+            VerificationCheck::Skipped {
+                reason: "status checks were not requested".into(),
+            }
+        } else {
+            let status_lists = StatusListFetcher {
+                client: None,
+                verification: options.clone(),
+            };
+            match self
+                .status_verdict(&status_lists, None, false)
+                .await
+                .map(|verdict| verdict.status)
+            {
+                Ok(CredentialStatus::Valid) => VerificationCheck::Passed,
+                Ok(CredentialStatus::Revoked) => VerificationCheck::Failed {
+                    reason: "the credential is revoked".into(),
+                },
+                Ok(CredentialStatus::Suspended) => VerificationCheck::Failed {
+                    reason: "the credential is suspended".into(),
+                },
+                Ok(CredentialStatus::Unknown) => VerificationCheck::Skipped {
+                    reason: "the credential has no supported status".into(),
+                },
+                Err(e) => VerificationCheck::Skipped {
+                    reason: format!("{e:?}"),
+                },
+            }
+        };
+
+        CredentialVerification {
+            valid: ![&signature, &validity, &status]
+                .iter()
+                .any(|check| matches!(check, VerificationCheck::Failed { .. })),
+            signature,
+            validity,
+            status,
+        }
+    }
+}
+
+impl ParsedCredential {
+    async fn verify_signature(
+        &self,
+        options: &CredentialVerificationOptions,
+    ) -> Result<(), String> {
+        let trust_anchors = trust_anchors(options)?;
+
+        match &self.inner {
+            ParsedCredentialInner::JwtVcJson(vc) | ParsedCredentialInner::JwtVcJsonLd(vc) => {
+                let jws =
+                    String::from_utf8(vc.to_compact_jws_bytes()).map_err(|e| format!("{e:?}"))?;
+                verify_jws(&jws, options, &trust_anchors).await
+            }
+            ParsedCredentialInner::VCDM2SdJwt(sd_jwt) => {
+                verify_sd_jwt(sd_jwt.inner.as_ref(), options, &trust_anchors).await
+            }
+            ParsedCredentialInner::DcSdJwt(sd_jwt) => {
+                verify_sd_jwt(sd_jwt.inner.as_ref(), options, &trust_anchors).await
+            }
+            ParsedCredentialInner::LdpVc(vc) => vc.verify(None).await.map_err(|e| format!("{e:?}")),
+            // The base proof is verified like any Data Integrity proof.
+            ParsedCredentialInner::LdpVcBbs(vc) => vc
+                .json_vc()
+                .verify(None)
+                .await
+                .map_err(|e| format!("{e:?}")),
+            ParsedCredentialInner::MsoMdoc(mdoc) => {
+                let issuer_auth = serde_cbor::value::to_value(&mdoc.document().issuer_auth)
+                    .map_err(|e| format!("{e:?}"))?;
+                verify_cose_sign1(issuer_auth, &trust_anchors).map(|_| ())
+            }
+            ParsedCredentialInner::Cwt(cwt) => {
+                let cose_sign1 = cwt.cose_sign1().map_err(|e| format!("{e:?}"))?;
+                verify_cose_sign1(cose_sign1, &trust_anchors).map(|_| ())
+            }
+        }
+    }
+}
+
+/// Return the trust anchors of the options, along with the certificates of
+/// their trusted list.
+pub(crate) fn trust_anchors(
+    options: &CredentialVerificationOptions,
+) -> Result<Vec<Certificate>, String> {
+    let mut trust_anchors = options
+        .trust_anchors
+        .iter()
+        .map(|pem| {
+            let (_, der) = pem_rfc7468::decode_vec(pem.as_bytes()).map_err(|e| format!("{e:?}"))?;
+            Certificate::from_der(&der).map_err(|e| format!("{e:?}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(trust_list) = &options.trust_list {
+        trust_anchors.extend(trust_list.certificates().map_err(|e| format!("{e:?}"))?);
+    }
+    Ok(trust_anchors)
+}
+
+/// Verify the issuer JWT of an SD-JWT, and that its disclosures match it.
+async fn verify_sd_jwt(
+    compact: &str,
+    options: &CredentialVerificationOptions,
+    trust_anchors: &[Certificate],
+) -> Result<(), String> {
+    let jws = compact.split('~').next().unwrap_or_default();
+    verify_jws(jws, options, trust_anchors).await?;
+
+    super::disclosure::DisclosedSdJwt::parse(compact)
+        .and_then(|sd_jwt| sd_jwt.reveal())
+        .map(|_| ())
+        .ok_or_else(|| "the disclosures do not match the SD-JWT".into())
+}
+
+/// Verify a JWS with its `x5c` certificate, a known issuer key, or else the
+/// key of its DID.
+pub(crate) async fn verify_jws(
+    jws: &str,
+    options: &CredentialVerificationOptions,
+    trust_anchors: &[Certificate],
+) -> Result<(), String> {
+    let part = |index: usize| -> Result<Json, String> {
+        jws.split('.')
+            .nth(index)
+            .and_then(|part| BASE64_URL_SAFE_NO_PAD.decode(part).ok())
+            .and_then(|part| serde_json::from_slice(&part).ok())
+            .ok_or_else(|| "not a JWT".to_string())
+    };
+    let (header, payload) = (part(0)?, part(1)?);
+    let (signing_input, signature) = jws.rsplit_once('.').ok_or("not a JWS")?;
+    let signature = BASE64_URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|e| format!("{e:?}"))?;
+
+    if let Some(leaf) = header["x5c"].get(0).and_then(Json::as_str) {
+        let leaf = BASE64_STANDARD.decode(leaf).map_err(|e| format!("{e:?}"))?;
+        let key = trusted_key(&leaf, trust_anchors)?;
+        let signature = Signature::from_slice(&signature).map_err(|e| format!("{e:?}"))?;
+        return key
+            .verify(signing_input.as_bytes(), &signature)
+            .map_err(|e| format!("{e:?}"));
+    }
+
+    let known_key = [&header["kid"], &payload["iss"]]
+        .into_iter()
+        .filter_map(Json::as_str)
+        .find_map(|id| options.issuer_keys.get(id));
+    if let Some(jwk) = known_key {
+        let algorithm: Algorithm =
+            serde_json::from_value(header["alg"].clone()).map_err(|e| format!("{e:?}"))?;
+        let jwk: JWK = serde_json::from_str(jwk).map_err(|e| format!("{e:?}"))?;
+        return verify_bytes(algorithm, signing_input.as_bytes(), &jwk, &signature)
+            .map_err(|e| format!("{e:?}"));
+    }
+
+    let jws = JwsString::from_string(jws.to_owned()).map_err(|e| format!("{e:?}"))?;
+    let params = VerificationParameters::from_resolver(AnyDidMethod::default().into_vm_resolver());
+    jws.verify(params)
+        .await
+        .map_err(|e| format!("{e:?}"))?
+        .map_err(|e| format!("{e:?}"))
+}
+
+/// Verify a COSE_Sign1, e.g. the issuer signature of an mdoc, with its
+/// `x5chain` certificate, and return its payload.
+pub(crate) fn verify_cose_sign1(
+    cose_sign1: Cbor,
+    trust_anchors: &[Certificate],
+) -> Result<Vec<u8>, String> {
+    let cose_sign1 = match cose_sign1 {
+        Cbor::Tag(18, cose_sign1) => *cose_sign1,
+        cose_sign1 => cose_sign1,
+    };
+    let Cbor::Array(cose_sign1) = cose_sign1 else {
+        return Err("not a COSE_Sign1".into());
+    };
+    let [Cbor::Bytes(protected), Cbor::Map(unprotected), Cbor::Bytes(payload), Cbor::Bytes(signature)] =
+        &cose_sign1[..]
+    else {
+        return Err("not a COSE_Sign1".into());
+    };
+
+    let protected_header = match &protected[..] {
+        [] => Default::default(),
+        protected => match serde_cbor::from_slice(protected).map_err(|e| format!("{e:?}"))? {
+            Cbor::Map(header) => header,
+            _ => return Err("invalid protected header".into()),
+        },
+    };
+    let x5chain = unprotected
+        .get(&Cbor::Integer(33))
+        .or_else(|| protected_header.get(&Cbor::Integer(33)));
+    let leaf = match x5chain {
+        Some(Cbor::Bytes(leaf)) => leaf,
+        Some(Cbor::Array(chain)) => match chain.first() {
+            Some(Cbor::Bytes(leaf)) => leaf,
+            _ => return Err("invalid x5chain".into()),
+        },
+        _ => return Err("the signature has no x5chain".into()),
+    };
+    let key = trusted_key(leaf, trust_anchors)?;
+
+    let sig_structure = serde_cbor::to_vec(&Cbor::Array(vec![
+        Cbor::Text("Signature1".into()),
+        Cbor::Bytes(protected.clone()),
+        Cbor::Bytes(vec![]),
+        Cbor::Bytes(payload.clone()),
+    ]))
+    .map_err(|e| format!("{e:?}"))?;
+    let signature = Signature::from_slice(signature).map_err(|e| format!("{e:?}"))?;
+
+    key.verify(&sig_structure, &signature)
+        .map_err(|e| format!("{e:?}"))?;
+    Ok(payload.clone())
+}
+
+/// Check that a certificate is a trust anchor or was issued by one, and
+/// return its key.
+fn trusted_key(der: &[u8], trust_anchors: &[Certificate]) -> Result<VerifyingKey, String> {
+    let certificate = Certificate::from_der(der).map_err(|e| format!("{e:?}"))?;
+    helpers::check_validity(&certificate.tbs_certificate.validity).map_err(|e| format!("{e:?}"))?;
+
+    let key_of = |certificate: &Certificate| {
+        certificate
+            .tbs_certificate
+            .subject_public_key_info
+            .to_der()
+            .map_err(|e| format!("{e:?}"))
+            .and_then(|spki| VerifyingKey::from_public_key_der(&spki).map_err(|e| format!("{e:?}")))
+    };
+
+    if trust_anchors.contains(&certificate) {
+        return key_of(&certificate);
+    }
+
+    let tbs = certificate
+        .tbs_certificate
+        .to_der()
+        .map_err(|e| format!("{e:?}"))?;
+    let signature = DerSignature::from_bytes(certificate.signature.raw_bytes())
+        .map_err(|e| format!("{e:?}"))?;
+    let issued_by_anchor = trust_anchors
+        .iter()
+        .filter(|anchor| anchor.tbs_certificate.subject == certificate.tbs_certificate.issuer)
+        .any(|anchor| key_of(anchor).is_ok_and(|key| key.verify(&tbs, &signature).is_ok()));
+
+    if !issued_by_anchor {
+        return Err(format!(
+            "the certificate of {} was not issued by a trust anchor",
+            certificate.tbs_certificate.subject
+        ));
+    }
+    key_of(&certificate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::jwt_vc::JwtVc;
+    use crate::oid4vp::key_binding::tests::TestSigner;
+
+    use std::time::SystemTime;
+
+    use p256::ecdsa::{signature::Signer, SigningKey};
+
+    #[derive(Debug)]
+    struct FixedClock(SystemTime);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> SystemTime {
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn verifies_credentials_locally() {
+        let signer = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let encode = |value: Json| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let signing_input = format!(
+            "{}.{}",
+            encode(serde_json::json!({ "alg": "ES256", "typ": "JWT" })),
+            encode(serde_json::json!({
+                "iss": "did:example:issuer",
+                "vc": {
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiableCredential", "UniversityDegreeCredential"],
+                    "issuer": "did:example:issuer",
+                    "issuanceDate": "2010-01-01T00:00:00Z",
+                    "credentialSubject": { "id": "did:example:holder" }
+                }
+            })),
+        );
+        let signature: Signature = signer.0.sign(signing_input.as_bytes());
+        let options = CredentialVerificationOptions {
+            issuer_keys: [("did:example:issuer".into(), signer.jwk().to_string())].into(),
+            ..Default::default()
+        };
+
+        let credential = ParsedCredential::new_jwt_vc_json(
+            JwtVc::new_from_compact_jws(format!(
+                "{signing_input}.{}",
+                BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes())
+            ))
+            .unwrap(),
+        );
+        let verification = credential.verify(options.clone()).await;
+        assert!(verification.valid);
+        assert_eq!(verification.signature, VerificationCheck::Passed);
+        assert_eq!(verification.validity, VerificationCheck::Passed);
+        assert!(matches!(
+            verification.status,
+            VerificationCheck::Skipped { .. }
+        ));
+
+        // A minute before the issuance date, the credential is only valid
+        // with a leeway.
+        let clock: Arc<dyn Clock> = Arc::new(FixedClock(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_262_304_000 - 60),
+        ));
+        let verification = credential
+            .verify(CredentialVerificationOptions {
+                clock: Some(clock.clone()),
+                ..options.clone()
+            })
+            .await;
+        assert!(matches!(
+            verification.validity,
+            VerificationCheck::Failed { .. }
+        ));
+        let verification = credential
+            .verify(CredentialVerificationOptions {
+                clock: Some(clock),
+                clock_leeway: Duration::from_secs(120),
+                ..options.clone()
+            })
+            .await;
+        assert_eq!(verification.validity, VerificationCheck::Passed);
+
+        let tampered = ParsedCredential::new_jwt_vc_json(
+            JwtVc::new_from_compact_jws(format!(
+                "{signing_input}.{}",
+                BASE64_URL_SAFE_NO_PAD.encode([0; 64])
+            ))
+            .unwrap(),
+        );
+        let verification = tampered.verify(options).await;
+        assert!(!verification.valid);
+        assert!(matches!(
+            verification.signature,
+            VerificationCheck::Failed { .. }
+        ));
+    }
+}
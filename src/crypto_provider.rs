@@ -0,0 +1,362 @@
+//! The provider of the random numbers and the software cryptography of the
+//! SDK, so that deployments requiring FIPS-validated or platform provided
+//! cryptography can route them through platform libraries.
+//!
+//! The SDK uses the [SoftwareCryptoProvider], based on RustCrypto, unless a
+//! provider is installed with [install_crypto_provider]. The provider is used
+//! for the nonces and AES-256-GCM of [crate::encrypted_storage] and backups,
+//! the HKDF of mdoc device MACs, the nonces of OID4VP mdoc handovers, the
+//! ECDH and AES-256-GCM of encrypted `direct_post.jwt` responses, and the
+//! decryption of proximity requests whose ReaderAuth is verified.
+//!
+//! NOTE: ECDH with device keys goes through the [crate::signer::DeviceKeyAgreement].
+//! The session key derivation and encryption of proximity presentations are
+//! done by isomdl, which does not go through the provider.
+
+use std::fmt::Debug;
+use std::sync::{Arc, OnceLock};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use hkdf::Hkdf;
+use p256::{elliptic_curve::sec1::ToEncodedPoint, PublicKey, SecretKey};
+use sha2::Sha256;
+
+/// The length of the nonces of AES-256-GCM.
+pub(crate) const AES_GCM_NONCE_LEN: usize = 12;
+
+static PROVIDER: OnceLock<Arc<dyn CryptoProvider>> = OnceLock::new();
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum CryptoProviderError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
+    #[error("A crypto provider is already installed")]
+    AlreadyInstalled,
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("Decryption failed")]
+    DecryptionFailed,
+    #[error("Crypto provider error: {0}")]
+    Internal(String),
+}
+
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for CryptoProviderError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        CryptoProviderError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+/// Interface: CryptoProvider
+///
+/// The source of secure random bytes, and the implementation of the
+/// cryptographic primitives the SDK performs in software.
+#[uniffi::export(with_foreign)]
+pub trait CryptoProvider: Send + Sync + Debug {
+    /// Return `len` secure random bytes.
+    fn random_bytes(&self, len: u32) -> Result<Vec<u8>, CryptoProviderError>;
+
+    /// Return the P-256 ECDH shared secret, the x-coordinate of the shared
+    /// point, of a 32 bytes secret key and a SEC1 encoded public key.
+    fn ecdh_p256(
+        &self,
+        secret_key: Vec<u8>,
+        public_key: Vec<u8>,
+    ) -> Result<Vec<u8>, CryptoProviderError>;
+
+    /// Derive `len` bytes with HKDF-SHA256.
+    fn hkdf_sha256(
+        &self,
+        ikm: Vec<u8>,
+        salt: Option<Vec<u8>>,
+        info: Vec<u8>,
+        len: u32,
+    ) -> Result<Vec<u8>, CryptoProviderError>;
+
+    /// Encrypt with AES-256-GCM, returning the ciphertext followed by the
+    /// tag.
+    fn aes_gcm_seal(
+        &self,
+        key: Vec<u8>,
+        nonce: Vec<u8>,
+        plaintext: Vec<u8>,
+        aad: Vec<u8>,
+    ) -> Result<Vec<u8>, CryptoProviderError>;
+
+    /// Decrypt a ciphertext followed by its tag with AES-256-GCM.
+    fn aes_gcm_open(
+        &self,
+        key: Vec<u8>,
+        nonce: Vec<u8>,
+        ciphertext: Vec<u8>,
+        aad: Vec<u8>,
+    ) -> Result<Vec<u8>, CryptoProviderError>;
+}
+
+/// Install the provider of the cryptography of the SDK, in place of the
+/// [SoftwareCryptoProvider].
+///
+/// The provider can only be installed once per process, before the values it
+/// encrypts are read.
+#[uniffi::export]
+pub fn install_crypto_provider(
+    provider: Arc<dyn CryptoProvider>,
+) -> Result<(), CryptoProviderError> {
+    PROVIDER
+        .set(provider)
+        .map_err(|_| CryptoProviderError::AlreadyInstalled)
+}
+
+/// Return the installed provider, or else the [SoftwareCryptoProvider].
+pub(crate) fn provider() -> Arc<dyn CryptoProvider> {
+    PROVIDER
+        .get()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(SoftwareCryptoProvider))
+}
+
+/// The cryptography of RustCrypto, with the random numbers of the operating
+/// system.
+#[derive(Debug, Default)]
+pub struct SoftwareCryptoProvider;
+
+impl SoftwareCryptoProvider {
+    fn cipher(key: &[u8], nonce: &[u8]) -> Result<Aes256Gcm, CryptoProviderError> {
+        if nonce.len() != AES_GCM_NONCE_LEN {
+            return Err(CryptoProviderError::InvalidInput(format!(
+                "invalid nonce length: {}",
+                nonce.len()
+            )));
+        }
+        Aes256Gcm::new_from_slice(key)
+            .map_err(|_| CryptoProviderError::InvalidInput("invalid key length".into()))
+    }
+}
+
+impl CryptoProvider for SoftwareCryptoProvider {
+    fn random_bytes(&self, len: u32) -> Result<Vec<u8>, CryptoProviderError> {
+        let mut bytes = vec![0; len as usize];
+        OsRng
+            .try_fill_bytes(&mut bytes)
+            .map_err(|e| CryptoProviderError::Internal(format!("{e:?}")))?;
+        Ok(bytes)
+    }
+
+    fn ecdh_p256(
+        &self,
+        secret_key: Vec<u8>,
+        public_key: Vec<u8>,
+    ) -> Result<Vec<u8>, CryptoProviderError> {
+        let secret_key = SecretKey::from_slice(&secret_key)
+            .map_err(|e| CryptoProviderError::InvalidInput(format!("{e:?}")))?;
+        let public_key = PublicKey::from_sec1_bytes(&public_key)
+            .map_err(|e| CryptoProviderError::InvalidInput(format!("{e:?}")))?;
+
+        let shared = (public_key.to_projective() * *secret_key.to_nonzero_scalar()).to_affine();
+        shared
+            .to_encoded_point(false)
+            .x()
+            .map(|x| x.to_vec())
+            .ok_or_else(|| CryptoProviderError::Internal("the shared point is the identity".into()))
+    }
+
+    fn hkdf_sha256(
+        &self,
+        ikm: Vec<u8>,
+        salt: Option<Vec<u8>>,
+        info: Vec<u8>,
+        len: u32,
+    ) -> Result<Vec<u8>, CryptoProviderError> {
+        let mut okm = vec![0; len as usize];
+        Hkdf::<Sha256>::new(salt.as_deref(), &ikm)
+            .expand(&info, &mut okm)
+            .map_err(|e| CryptoProviderError::InvalidInput(format!("{e:?}")))?;
+        Ok(okm)
+    }
+
+    fn aes_gcm_seal(
+        &self,
+        key: Vec<u8>,
+        nonce: Vec<u8>,
+        plaintext: Vec<u8>,
+        aad: Vec<u8>,
+    ) -> Result<Vec<u8>, CryptoProviderError> {
+        Self::cipher(&key, &nonce)?
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|e| CryptoProviderError::Internal(format!("{e:?}")))
+    }
+
+    fn aes_gcm_open(
+        &self,
+        key: Vec<u8>,
+        nonce: Vec<u8>,
+        ciphertext: Vec<u8>,
+        aad: Vec<u8>,
+    ) -> Result<Vec<u8>, CryptoProviderError> {
+        Self::cipher(&key, &nonce)?
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| CryptoProviderError::DecryptionFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+    use crate::encrypted_storage::{EncryptedStorage, KeyProvider, KeyProviderError};
+    use crate::local_store::LocalStore;
+    use crate::storage_manager::StorageManagerInterface;
+
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// A provider counting the operations it delegates to the software one.
+    #[derive(Debug, Default)]
+    struct CountingProvider {
+        operations: AtomicU32,
+    }
+
+    impl CountingProvider {
+        fn count(&self) -> &SoftwareCryptoProvider {
+            self.operations.fetch_add(1, Ordering::SeqCst);
+            &SoftwareCryptoProvider
+        }
+    }
+
+    impl CryptoProvider for CountingProvider {
+        fn random_bytes(&self, len: u32) -> Result<Vec<u8>, CryptoProviderError> {
+            self.count().random_bytes(len)
+        }
+
+        fn ecdh_p256(
+            &self,
+            secret_key: Vec<u8>,
+            public_key: Vec<u8>,
+        ) -> Result<Vec<u8>, CryptoProviderError> {
+            self.count().ecdh_p256(secret_key, public_key)
+        }
+
+        fn hkdf_sha256(
+            &self,
+            ikm: Vec<u8>,
+            salt: Option<Vec<u8>>,
+            info: Vec<u8>,
+            len: u32,
+        ) -> Result<Vec<u8>, CryptoProviderError> {
+            self.count().hkdf_sha256(ikm, salt, info, len)
+        }
+
+        fn aes_gcm_seal(
+            &self,
+            key: Vec<u8>,
+            nonce: Vec<u8>,
+            plaintext: Vec<u8>,
+            aad: Vec<u8>,
+        ) -> Result<Vec<u8>, CryptoProviderError> {
+            self.count().aes_gcm_seal(key, nonce, plaintext, aad)
+        }
+
+        fn aes_gcm_open(
+            &self,
+            key: Vec<u8>,
+            nonce: Vec<u8>,
+            ciphertext: Vec<u8>,
+            aad: Vec<u8>,
+        ) -> Result<Vec<u8>, CryptoProviderError> {
+            self.count().aes_gcm_open(key, nonce, ciphertext, aad)
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestKeyProvider;
+
+    impl KeyProvider for TestKeyProvider {
+        fn current_key_id(&self) -> Result<String, KeyProviderError> {
+            Ok("key".into())
+        }
+
+        fn key(&self, _key_id: String) -> Result<Vec<u8>, KeyProviderError> {
+            Ok(vec![7; 32])
+        }
+    }
+
+    #[test]
+    fn software_provider_primitives() {
+        let provider = SoftwareCryptoProvider;
+        let (a, b) = (
+            SecretKey::from_slice(&[1; 32]).unwrap(),
+            SecretKey::from_slice(&[2; 32]).unwrap(),
+        );
+        let public = |key: &SecretKey| key.public_key().to_encoded_point(false).as_bytes().to_vec();
+        assert_eq!(
+            provider
+                .ecdh_p256(a.to_bytes().to_vec(), public(&b))
+                .unwrap(),
+            provider
+                .ecdh_p256(b.to_bytes().to_vec(), public(&a))
+                .unwrap()
+        );
+
+        // RFC 5869, test case 3.
+        let okm = provider
+            .hkdf_sha256(vec![0x0b; 22], None, vec![], 42)
+            .unwrap();
+        assert_eq!(okm[..8], [0x8d, 0xa4, 0xe7, 0x75, 0xa5, 0x63, 0xc1, 0x8f]);
+
+        let nonce = provider.random_bytes(AES_GCM_NONCE_LEN as u32).unwrap();
+        let sealed = provider
+            .aes_gcm_seal(
+                vec![7; 32],
+                nonce.clone(),
+                b"secret".to_vec(),
+                b"aad".to_vec(),
+            )
+            .unwrap();
+        assert_eq!(
+            provider
+                .aes_gcm_open(vec![7; 32], nonce.clone(), sealed.clone(), b"aad".to_vec())
+                .unwrap(),
+            b"secret"
+        );
+        assert!(matches!(
+            provider.aes_gcm_open(vec![7; 32], nonce, sealed, b"other".to_vec()),
+            Err(CryptoProviderError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn routes_through_installed_provider() {
+        let counting = Arc::new(CountingProvider::default());
+        install_crypto_provider(counting.clone()).unwrap();
+        assert!(matches!(
+            install_crypto_provider(Arc::new(SoftwareCryptoProvider)),
+            Err(CryptoProviderError::AlreadyInstalled)
+        ));
+
+        let storage = EncryptedStorage::new(Arc::new(LocalStore::new()), Arc::new(TestKeyProvider));
+        let before = counting.operations.load(Ordering::SeqCst);
+        storage
+            .add(Key("Credential.1".into()), Value(b"payload".to_vec()))
+            .unwrap();
+        assert_eq!(
+            storage.get(Key("Credential.1".into())).unwrap(),
+            Some(Value(b"payload".to_vec()))
+        );
+        // The nonce, the encryption and the decryption.
+        assert!(counting.operations.load(Ordering::SeqCst) >= before + 3);
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::metrics::{self, MetricsSink};
+
+use ssi::dids::{document, resolution, DIDResolver, DID};
+use uniffi::deps::log;
+
+/// The default time a resolved DID document is considered fresh.
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    document: Vec<u8>,
+    content_type: Option<String>,
+    resolved_at: SystemTime,
+}
+
+impl CacheEntry {
+    fn output(&self) -> resolution::Output<Vec<u8>> {
+        resolution::Output::new(
+            self.document.clone(),
+            document::Metadata::default(),
+            resolution::Metadata::from_content_type(self.content_type.clone()),
+        )
+    }
+}
+
+#[derive(Debug)]
+struct CacheConfig {
+    ttl: Duration,
+    offline: bool,
+}
+
+/// A cache of resolved DID documents, keyed by DID.
+///
+/// Entries are served while they are younger than the TTL. Stale entries are
+/// still served when the cache is in offline mode, or when re-resolving the
+/// DID fails.
+#[derive(Debug, uniffi::Object)]
+pub struct DidDocumentCache {
+    entries: RwLock<HashMap<String, CacheEntry>>,
+    config: RwLock<CacheConfig>,
+}
+
+impl Default for DidDocumentCache {
+    fn default() -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            config: RwLock::new(CacheConfig {
+                ttl: DEFAULT_TTL,
+                offline: false,
+            }),
+        }
+    }
+}
+
+#[uniffi::export]
+impl DidDocumentCache {
+    #[uniffi::constructor]
+    pub fn new(ttl: Duration) -> Arc<Self> {
+        let cache = Self::default();
+        cache.set_ttl(ttl);
+        Arc::new(cache)
+    }
+
+    /// Set the time a resolved DID document is considered fresh.
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .ttl = ttl;
+    }
+
+    /// Enable or disable offline mode, in which DIDs are only resolved from
+    /// the cache, regardless of the age of the entries.
+    pub fn set_offline(&self, offline: bool) {
+        self.config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .offline = offline;
+    }
+
+    /// Remove every entry from the cache.
+    pub fn clear(&self) {
+        self.entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+}
+
+impl DidDocumentCache {
+    fn entry(&self, did: &str) -> Option<CacheEntry> {
+        self.entries
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(did)
+            .cloned()
+    }
+
+    fn insert(&self, did: String, entry: CacheEntry) {
+        self.entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(did, entry);
+    }
+
+    /// Resolve a DID through the cache, falling back to `resolver` when
+    /// there is no fresh entry.
+    pub(crate) async fn resolve_representation(
+        &self,
+        resolver: &impl DIDResolver,
+        did: &DID,
+        options: resolution::Options,
+        now: SystemTime,
+    ) -> Result<resolution::Output<Vec<u8>>, resolution::Error> {
+        let (ttl, offline) = {
+            let config = self
+                .config
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            (config.ttl, config.offline)
+        };
+
+        let cached = self.entry(did.as_str());
+
+        if let Some(entry) = &cached {
+            // Entries do not expire when their TTL is past the representable
+            // times.
+            let fresh = entry
+                .resolved_at
+                .checked_add(ttl)
+                .is_none_or(|expires_at| expires_at > now);
+            if fresh || offline {
+                return Ok(entry.output());
+            }
+        }
+
+        if offline {
+            return Err(resolution::Error::Internal(format!(
+                "{did} is not cached and offline mode is enabled"
+            )));
+        }
+
+        match resolver.resolve_representation(did, options).await {
+            Ok(output) => {
+                self.insert(
+                    did.to_string(),
+                    CacheEntry {
+                        document: output.document.clone(),
+                        content_type: output.metadata.content_type.clone(),
+                        resolved_at: now,
+                    },
+                );
+                Ok(output)
+            }
+            Err(e) => match cached {
+                Some(entry) => {
+                    log::warn!("Failed to resolve {did}, using a stale DID document: {e:?}");
+                    Ok(entry.output())
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
+/// A DID resolver caching the documents resolved by another resolver.
+#[derive(Debug, Clone)]
+pub(crate) struct CachingDidResolver<R> {
+    pub(crate) resolver: R,
+    pub(crate) cache: Arc<DidDocumentCache>,
+    pub(crate) metrics: Option<Arc<dyn MetricsSink>>,
+}
+
+impl<R: DIDResolver> DIDResolver for CachingDidResolver<R> {
+    async fn resolve_representation<'a>(
+        &'a self,
+        did: &'a DID,
+        options: resolution::Options,
+    ) -> Result<resolution::Output<Vec<u8>>, resolution::Error> {
+        metrics::measure(
+            self.metrics.clone(),
+            metrics::DID_RESOLUTION,
+            self.cache
+                .resolve_representation(&self.resolver, did, options, SystemTime::now()),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use ssi::dids::DIDBuf;
+
+    #[derive(Default)]
+    struct CountingResolver {
+        calls: AtomicUsize,
+        unavailable: AtomicBool,
+    }
+
+    impl DIDResolver for CountingResolver {
+        async fn resolve_representation<'a>(
+            &'a self,
+            did: &'a DID,
+            _options: resolution::Options,
+        ) -> Result<resolution::Output<Vec<u8>>, resolution::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.unavailable.load(Ordering::SeqCst) {
+                return Err(resolution::Error::Internal("network unavailable".into()));
+            }
+
+            Ok(resolution::Output::new(
+                serde_json::json!({ "id": did }).to_string().into_bytes(),
+                document::Metadata::default(),
+                resolution::Metadata::from_content_type(Some("application/did+json".into())),
+            ))
+        }
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_entries() {
+        let cache = DidDocumentCache::new(Duration::from_secs(60));
+        let resolver = CountingResolver::default();
+        let did = DIDBuf::from_string("did:web:example.com".into()).unwrap();
+
+        for now in [at(1_000), at(1_059)] {
+            cache
+                .resolve_representation(&resolver, &did, Default::default(), now)
+                .await
+                .unwrap();
+        }
+        assert_eq!(resolver.calls.load(Ordering::SeqCst), 1);
+
+        cache
+            .resolve_representation(&resolver, &did, Default::default(), at(1_060))
+            .await
+            .unwrap();
+        assert_eq!(resolver.calls.load(Ordering::SeqCst), 2);
+
+        // Entries whose TTL overflows do not expire.
+        cache.set_ttl(Duration::MAX);
+        cache
+            .resolve_representation(&resolver, &did, Default::default(), at(1_000_000))
+            .await
+            .unwrap();
+        assert_eq!(resolver.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_stale_entries() {
+        let cache = DidDocumentCache::new(Duration::from_secs(60));
+        let resolver = CountingResolver::default();
+        let did = DIDBuf::from_string("did:web:example.com".into()).unwrap();
+        let other = DIDBuf::from_string("did:web:example.org".into()).unwrap();
+
+        cache
+            .resolve_representation(&resolver, &did, Default::default(), at(1_000))
+            .await
+            .unwrap();
+
+        // Stale entries are used when the DID cannot be re-resolved.
+        resolver.unavailable.store(true, Ordering::SeqCst);
+        assert!(cache
+            .resolve_representation(&resolver, &did, Default::default(), at(2_000))
+            .await
+            .is_ok());
+        assert_eq!(resolver.calls.load(Ordering::SeqCst), 2);
+
+        // Offline mode never reaches the resolver.
+        cache.set_offline(true);
+        assert!(cache
+            .resolve_representation(&resolver, &did, Default::default(), at(3_000))
+            .await
+            .is_ok());
+        assert!(cache
+            .resolve_representation(&resolver, &other, Default::default(), at(3_000))
+            .await
+            .is_err());
+        assert_eq!(resolver.calls.load(Ordering::SeqCst), 2);
+    }
+}
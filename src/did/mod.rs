@@ -1,8 +1,12 @@
 use ssi::dids::DIDResolver;
 
+pub use cache::*;
 pub use error::*;
+pub use resolver::*;
 
+mod cache;
 mod error;
+mod resolver;
 
 #[derive(uniffi::Enum)]
 pub enum DidMethod {
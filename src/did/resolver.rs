@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+
+use ssi::dids::{document, resolution, DIDKey, DIDMethod, DIDResolver, DIDWeb, DID, DIDJWK};
+
+const DID_JSON_CONTENT_TYPE: &str = "application/did+json";
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum DidResolutionError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
+    #[error("DID not found: {0}")]
+    NotFound(String),
+    #[error("Failed to resolve DID: {0}")]
+    Internal(String),
+}
+
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for DidResolutionError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        DidResolutionError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+/// Interface: DidMethodResolver
+///
+/// A DidMethodResolver resolves DIDs for a single DID method, and is
+/// implemented by the native platform for methods the SDK does not support
+/// out of the box.
+#[uniffi::export(with_foreign)]
+#[async_trait::async_trait]
+pub trait DidMethodResolver: Send + Sync + Debug {
+    /// Return the name of the DID method, e.g. `ion` for `did:ion`.
+    fn method(&self) -> String;
+
+    /// Resolve a DID, returning its DID document as JSON.
+    async fn resolve(&self, did: String) -> Result<String, DidResolutionError>;
+}
+
+/// A registry of DID method resolvers.
+///
+/// `did:key`, `did:jwk` and `did:web` are supported out of the box. Resolvers
+/// registered with [DidResolverRegistry::register] take precedence over the
+/// built-in ones for the same method.
+#[derive(Debug, Clone, Default, uniffi::Object)]
+pub struct DidResolverRegistry {
+    resolvers: Arc<RwLock<HashMap<String, Arc<dyn DidMethodResolver>>>>,
+}
+
+#[uniffi::export]
+impl DidResolverRegistry {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Register a resolver for its DID method, replacing any resolver
+    /// previously registered for that method.
+    pub fn register(&self, resolver: Arc<dyn DidMethodResolver>) {
+        let mut resolvers = self
+            .resolvers
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        resolvers.insert(resolver.method(), resolver);
+    }
+
+    /// Return the DID methods the registry can resolve.
+    pub fn methods(&self) -> Vec<String> {
+        let built_in = [
+            DIDKey::DID_METHOD_NAME,
+            DIDJWK::DID_METHOD_NAME,
+            DIDWeb::DID_METHOD_NAME,
+        ];
+        let mut methods: Vec<String> = built_in
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .chain(self.custom_methods())
+            .collect();
+        methods.sort();
+        methods.dedup();
+        methods
+    }
+}
+
+impl DidResolverRegistry {
+    fn custom_methods(&self) -> Vec<String> {
+        self.resolvers
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn custom_resolver(&self, method: &str) -> Option<Arc<dyn DidMethodResolver>> {
+        self.resolvers
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(method)
+            .cloned()
+    }
+}
+
+impl DIDResolver for DidResolverRegistry {
+    async fn resolve_representation<'a>(
+        &'a self,
+        did: &'a DID,
+        options: resolution::Options,
+    ) -> Result<resolution::Output<Vec<u8>>, resolution::Error> {
+        let method = did.method_name();
+
+        if let Some(resolver) = self.custom_resolver(method) {
+            let document = resolver
+                .resolve(did.to_string())
+                .await
+                .map_err(|e| match e {
+                    DidResolutionError::NotFound(_) => resolution::Error::NotFound,
+                    e => resolution::Error::Internal(format!("{e:?}")),
+                })?;
+
+            return Ok(resolution::Output::new(
+                document.into_bytes(),
+                document::Metadata::default(),
+                resolution::Metadata::from_content_type(Some(DID_JSON_CONTENT_TYPE.into())),
+            ));
+        }
+
+        match method {
+            DIDKey::DID_METHOD_NAME => DIDKey.resolve_representation(did, options).await,
+            DIDJWK::DID_METHOD_NAME => DIDJWK.resolve_representation(did, options).await,
+            DIDWeb::DID_METHOD_NAME => DIDWeb.resolve_representation(did, options).await,
+            method => Err(resolution::Error::MethodNotSupported(method.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ssi::dids::DIDBuf;
+
+    #[derive(Debug)]
+    struct ExampleResolver;
+
+    #[async_trait::async_trait]
+    impl DidMethodResolver for ExampleResolver {
+        fn method(&self) -> String {
+            "example".into()
+        }
+
+        async fn resolve(&self, did: String) -> Result<String, DidResolutionError> {
+            match did.as_str() {
+                "did:example:123" => Ok(serde_json::json!({
+                    "@context": "https://www.w3.org/ns/did/v1",
+                    "id": did,
+                })
+                .to_string()),
+                _ => Err(DidResolutionError::NotFound(did)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_built_in_methods() {
+        let registry = DidResolverRegistry::default();
+        let jwk = ssi::JWK::generate_p256();
+
+        for did in [DIDKey::generate(&jwk).unwrap(), DIDJWK::generate(&jwk)] {
+            let output = registry.resolve(did.as_did()).await.unwrap();
+            assert_eq!(output.document.id, did);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_custom_methods() {
+        let registry = DidResolverRegistry::default();
+        let did = DIDBuf::from_string("did:example:123".into()).unwrap();
+
+        assert!(matches!(
+            registry.resolve(did.as_did()).await,
+            Err(resolution::Error::MethodNotSupported(_))
+        ));
+
+        registry.register(Arc::new(ExampleResolver));
+        assert!(registry.methods().contains(&"example".to_owned()));
+
+        let output = registry.resolve(did.as_did()).await.unwrap();
+        assert_eq!(output.document.id, did);
+
+        let unknown = DIDBuf::from_string("did:example:456".into()).unwrap();
+        assert!(matches!(
+            registry.resolve(unknown.as_did()).await,
+            Err(resolution::Error::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,345 @@
+//! Encryption at rest for stored values.
+//!
+//! [EncryptedStorage] wraps a [StorageManagerInterface], sealing every value
+//! with AES-256-GCM before it reaches the underlying storage, and opening it
+//! transparently when it is read back. The keys are obtained from a
+//! [KeyProvider], backed by the platform keystore, and the encryption is done
+//! by the [crate::crypto_provider].
+//!
+//! Sealed values are framed as:
+//!
+//! ```text
+//! "VDCE" || version (1) || key id length (1) || key id || nonce (12) || ciphertext
+//! ```
+//!
+//! The storage key is used as associated data, so that a sealed value cannot
+//! be moved to a different key. Values without the header are plaintext
+//! values written before encryption was enabled, and are re-sealed by
+//! [EncryptedStorage::migrate]. They are only read in the migration mode, so
+//! that values written to the underlying storage, bypassing the encryption,
+//! are not accepted.
+
+use crate::common::*;
+use crate::crypto_provider::{self, AES_GCM_NONCE_LEN};
+use crate::storage_manager::*;
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const MAGIC: &[u8] = b"VDCE";
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = AES_GCM_NONCE_LEN;
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum KeyProviderError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
+    #[error("Key not found: {0}")]
+    KeyNotFound(String),
+    #[error("Key provider error: {0}")]
+    Internal(String),
+}
+
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for KeyProviderError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        KeyProviderError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+/// Interface: KeyProvider
+///
+/// The KeyProvider supplies the storage encryption keys, which are held by
+/// the native platform (Keychain, Android Keystore).
+///
+/// Each key is identified by a key id, which is stored alongside every value
+/// sealed with it, so that keys can be rotated without losing access to
+/// existing values.
+#[uniffi::export(with_foreign)]
+pub trait KeyProvider: Send + Sync + Debug {
+    /// Return the id of the key that new values are sealed with.
+    ///
+    /// The id must be at most 255 bytes long.
+    fn current_key_id(&self) -> Result<String, KeyProviderError>;
+
+    /// Return the 256-bit key for a key id.
+    fn key(&self, key_id: String) -> Result<Vec<u8>, KeyProviderError>;
+}
+
+/// A storage layer encrypting every value stored in an underlying storage.
+#[derive(Debug)]
+pub struct EncryptedStorage {
+    storage: Arc<dyn StorageManagerInterface>,
+    key_provider: Arc<dyn KeyProvider>,
+    /// Whether plaintext values are read, until they are migrated.
+    migration_mode: AtomicBool,
+}
+
+impl EncryptedStorage {
+    pub fn new(
+        storage: Arc<dyn StorageManagerInterface>,
+        key_provider: Arc<dyn KeyProvider>,
+    ) -> Self {
+        Self {
+            storage,
+            key_provider,
+            migration_mode: AtomicBool::new(false),
+        }
+    }
+
+    /// Set whether plaintext values are read, as they were before encryption
+    /// was enabled, rather than rejected.
+    ///
+    /// The migration mode ends once [EncryptedStorage::migrate] succeeds.
+    pub fn set_migration_mode(&self, enabled: bool) {
+        self.migration_mode.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Re-seal every value that is stored in plaintext, or sealed with a key
+    /// other than the current one.
+    ///
+    /// Returns the number of values that were re-sealed, and ends the
+    /// migration mode.
+    pub fn migrate(&self) -> Result<u32, StorageManagerError> {
+        let current_key_id = self.current_key_id()?;
+        let mut migrated = 0;
+
+        for key in self.storage.list()? {
+            let Some(value) = self.storage.get(key.clone())? else {
+                continue;
+            };
+
+            match sealed_key_id(&value.0) {
+                Some(key_id) if key_id == current_key_id.as_bytes() => continue,
+                Some(_) => {
+                    let value = self.open(&key, &value.0)?;
+                    self.add(key, Value(value))?;
+                }
+                None => self.add(key, value)?,
+            }
+
+            migrated += 1;
+        }
+
+        self.set_migration_mode(false);
+        Ok(migrated)
+    }
+
+    fn current_key_id(&self) -> Result<String, StorageManagerError> {
+        self.key_provider
+            .current_key_id()
+            .map_err(|e| StorageManagerError::KeyUnavailable(e.to_string()))
+    }
+
+    fn key(&self, key_id: &str) -> Result<Vec<u8>, StorageManagerError> {
+        let key = self
+            .key_provider
+            .key(key_id.to_owned())
+            .map_err(|e| StorageManagerError::KeyUnavailable(e.to_string()))?;
+
+        match key.len() {
+            32 => Ok(key),
+            _ => Err(StorageManagerError::KeyUnavailable(format!(
+                "invalid key: {key_id}"
+            ))),
+        }
+    }
+
+    /// Seal a value, using the key as associated data.
+    pub(crate) fn seal(&self, key: &Key, value: &[u8]) -> Result<Vec<u8>, StorageManagerError> {
+        let key_id = self.current_key_id()?;
+        let key_id_len = u8::try_from(key_id.len())
+            .map_err(|_| StorageManagerError::KeyUnavailable("key id too long".into()))?;
+
+        let provider = crypto_provider::provider();
+        let nonce = provider
+            .random_bytes(NONCE_LEN as u32)
+            .map_err(|_| StorageManagerError::InternalError)?;
+        let ciphertext = provider
+            .aes_gcm_seal(
+                self.key(&key_id)?,
+                nonce.clone(),
+                value.to_vec(),
+                key.0.as_bytes().to_vec(),
+            )
+            .map_err(|_| StorageManagerError::InternalError)?;
+
+        let mut sealed = Vec::with_capacity(MAGIC.len() + 2 + key_id.len() + NONCE_LEN);
+        sealed.extend_from_slice(MAGIC);
+        sealed.push(VERSION);
+        sealed.push(key_id_len);
+        sealed.extend_from_slice(key_id.as_bytes());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend(ciphertext);
+
+        Ok(sealed)
+    }
+
+    /// Open a value sealed with [EncryptedStorage::seal] under the same key.
+    pub(crate) fn open(&self, key: &Key, sealed: &[u8]) -> Result<Vec<u8>, StorageManagerError> {
+        let key_id = sealed_key_id(sealed).ok_or(StorageManagerError::CouldNotDecryptValue)?;
+        let rest = &sealed[MAGIC.len() + 2 + key_id.len()..];
+        if rest.len() < NONCE_LEN {
+            return Err(StorageManagerError::CouldNotDecryptValue);
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let key_id =
+            std::str::from_utf8(key_id).map_err(|_| StorageManagerError::CouldNotDecryptValue)?;
+
+        crypto_provider::provider()
+            .aes_gcm_open(
+                self.key(key_id)?,
+                nonce.to_vec(),
+                ciphertext.to_vec(),
+                key.0.as_bytes().to_vec(),
+            )
+            .map_err(|_| StorageManagerError::CouldNotDecryptValue)
+    }
+}
+
+/// Return the key id of a sealed value, or `None` if it is not sealed.
+fn sealed_key_id(value: &[u8]) -> Option<&[u8]> {
+    let header = value.strip_prefix(MAGIC)?;
+    match header {
+        [VERSION, len, rest @ ..] => rest.get(..*len as usize),
+        _ => None,
+    }
+}
+
+impl StorageManagerInterface for EncryptedStorage {
+    fn add(&self, key: Key, value: Value) -> Result<(), StorageManagerError> {
+        let sealed = self.seal(&key, &value.0)?;
+        self.storage.add(key, Value(sealed))
+    }
+
+    fn get(&self, key: Key) -> Result<Option<Value>, StorageManagerError> {
+        match self.storage.get(key.clone())? {
+            Some(value) if sealed_key_id(&value.0).is_some() => {
+                Ok(Some(Value(self.open(&key, &value.0)?)))
+            }
+            // Plaintext values from before encryption was enabled.
+            Some(value) if self.migration_mode.load(Ordering::SeqCst) => Ok(Some(value)),
+            Some(_) => Err(StorageManagerError::CouldNotDecryptValue),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<Key>, StorageManagerError> {
+        self.storage.list()
+    }
+
+    fn remove(&self, key: Key) -> Result<(), StorageManagerError> {
+        self.storage.remove(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+
+    use std::sync::RwLock;
+
+    #[derive(Debug)]
+    struct TestKeyProvider {
+        current: RwLock<String>,
+    }
+
+    impl KeyProvider for TestKeyProvider {
+        fn current_key_id(&self) -> Result<String, KeyProviderError> {
+            Ok(self.current.read().unwrap().clone())
+        }
+
+        fn key(&self, key_id: String) -> Result<Vec<u8>, KeyProviderError> {
+            match key_id.as_str() {
+                "key-1" => Ok(vec![1; 32]),
+                "key-2" => Ok(vec![2; 32]),
+                _ => Err(KeyProviderError::KeyNotFound(key_id)),
+            }
+        }
+    }
+
+    fn setup() -> (Arc<LocalStore>, Arc<TestKeyProvider>, EncryptedStorage) {
+        let local = Arc::new(LocalStore::new());
+        let keys = Arc::new(TestKeyProvider {
+            current: RwLock::new("key-1".into()),
+        });
+        let storage = EncryptedStorage::new(local.clone(), keys.clone());
+        (local, keys, storage)
+    }
+
+    #[test]
+    fn seals_values() {
+        let (local, _, storage) = setup();
+        let key = Key("Credential.1".into());
+
+        storage.add(key.clone(), Value(b"secret".to_vec())).unwrap();
+
+        let raw = local.get(key.clone()).unwrap().unwrap();
+        assert_eq!(sealed_key_id(&raw.0), Some(&b"key-1"[..]));
+        assert!(!raw.0.windows(6).any(|w| w == b"secret"));
+
+        assert_eq!(storage.get(key).unwrap().unwrap().0, b"secret");
+    }
+
+    #[test]
+    fn binds_values_to_their_key() {
+        let (local, _, storage) = setup();
+
+        storage
+            .add(Key("Credential.1".into()), Value(b"secret".to_vec()))
+            .unwrap();
+        let raw = local.get(Key("Credential.1".into())).unwrap().unwrap();
+        local.add(Key("Credential.2".into()), raw).unwrap();
+
+        assert!(matches!(
+            storage.get(Key("Credential.2".into())),
+            Err(StorageManagerError::CouldNotDecryptValue)
+        ));
+    }
+
+    #[test]
+    fn migrates_plaintext_and_rotated_values() {
+        let (local, keys, storage) = setup();
+
+        local
+            .add(Key("Credential.1".into()), Value(b"plaintext".to_vec()))
+            .unwrap();
+        storage
+            .add(Key("Credential.2".into()), Value(b"old key".to_vec()))
+            .unwrap();
+
+        *keys.current.write().unwrap() = "key-2".into();
+
+        // Plaintext values are only read in the migration mode.
+        assert!(matches!(
+            storage.get(Key("Credential.1".into())),
+            Err(StorageManagerError::CouldNotDecryptValue)
+        ));
+        storage.set_migration_mode(true);
+        assert_eq!(
+            storage.get(Key("Credential.1".into())).unwrap().unwrap().0,
+            b"plaintext"
+        );
+
+        assert_eq!(storage.migrate().unwrap(), 2);
+        assert_eq!(storage.migrate().unwrap(), 0);
+
+        for (key, value) in [("Credential.1", "plaintext"), ("Credential.2", "old key")] {
+            let raw = local.get(Key(key.into())).unwrap().unwrap();
+            assert_eq!(sealed_key_id(&raw.0), Some(&b"key-2"[..]));
+            assert_eq!(
+                storage.get(Key(key.into())).unwrap().unwrap().0,
+                value.as_bytes()
+            );
+        }
+
+        // The migration mode ended with the migration.
+        local
+            .add(Key("Credential.3".into()), Value(b"plaintext".to_vec()))
+            .unwrap();
+        assert!(storage.get(Key("Credential.3".into())).is_err());
+    }
+}
@@ -1,14 +1,27 @@
 uniffi::setup_scaffolding!();
 
+pub mod clock;
 pub mod common;
 pub mod credential;
+pub mod crypto_provider;
 pub mod did;
+pub mod encrypted_storage;
 pub mod local_store;
+pub mod logging;
 pub mod mdl;
+pub mod metrics;
 pub mod oid4vci;
 pub mod oid4vp;
+pub mod presentation_log;
 pub mod proof_of_possession;
+pub mod signer;
+pub mod status;
 pub mod storage_manager;
+#[cfg(feature = "test-harness")]
+pub mod test_harness;
+pub mod trust_anchors;
+pub mod trust_list;
+pub mod url_router;
 pub mod vdc_collection;
 pub mod verifier;
 pub mod w3c_vc_barcodes;
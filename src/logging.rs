@@ -0,0 +1,160 @@
+//! Bridge from the SDK logs to the logging of the host platform, such as
+//! `os_log` or Logcat.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::kv::{Key, Source, Value, VisitSource};
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum LoggingError {
+    #[error("A logger is already installed")]
+    AlreadyInstalled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, uniffi::Enum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Self::Error,
+            log::Level::Warn => Self::Warn,
+            log::Level::Info => Self::Info,
+            log::Level::Debug => Self::Debug,
+            log::Level::Trace => Self::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Self::Error,
+            LogLevel::Warn => Self::Warn,
+            LogLevel::Info => Self::Info,
+            LogLevel::Debug => Self::Debug,
+            LogLevel::Trace => Self::Trace,
+        }
+    }
+}
+
+/// A log message of the SDK.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    /// The module the message was logged from, e.g. `mobile_sdk_rs::oid4vp`.
+    pub target: String,
+    pub message: String,
+    /// Structured fields attached to the message.
+    pub fields: HashMap<String, String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl From<&log::Record<'_>> for LogRecord {
+    fn from(record: &log::Record<'_>) -> Self {
+        struct Fields(HashMap<String, String>);
+
+        impl<'kvs> VisitSource<'kvs> for Fields {
+            fn visit_pair(
+                &mut self,
+                key: Key<'kvs>,
+                value: Value<'kvs>,
+            ) -> Result<(), log::kv::Error> {
+                self.0.insert(key.to_string(), value.to_string());
+                Ok(())
+            }
+        }
+
+        let mut fields = Fields(HashMap::new());
+        // Visiting the fields into a map cannot fail.
+        let _ = record.key_values().visit(&mut fields);
+
+        Self {
+            level: record.level().into(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            fields: fields.0,
+            file: record.file().map(ToOwned::to_owned),
+            line: record.line(),
+        }
+    }
+}
+
+/// Interface: LogSink
+///
+/// The LogSink receives the log messages of the SDK, to forward them to the
+/// logging of the host platform.
+#[uniffi::export(with_foreign)]
+pub trait LogSink: Send + Sync + std::fmt::Debug {
+    fn log(&self, record: LogRecord);
+}
+
+struct SinkLogger {
+    sink: Arc<dyn LogSink>,
+    max_level: log::LevelFilter,
+}
+
+impl log::Log for SinkLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.max_level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.sink.log(record.into());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the sink receiving the log messages of the SDK up to the maximum
+/// level.
+///
+/// The sink can only be installed once per process.
+#[uniffi::export]
+pub fn install_log_sink(sink: Arc<dyn LogSink>, max_level: LogLevel) -> Result<(), LoggingError> {
+    let max_level = max_level.into();
+    log::set_boxed_logger(Box::new(SinkLogger { sink, max_level }))
+        .map_err(|_| LoggingError::AlreadyInstalled)?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_records() {
+        let fields = [("credential_id", "1234")];
+        let record = log::Record::builder()
+            .args(format_args!("stored credential"))
+            .level(log::Level::Info)
+            .target("mobile_sdk_rs::vdc_collection")
+            .line(Some(42))
+            .key_values(&fields)
+            .build();
+
+        assert_eq!(
+            LogRecord::from(&record),
+            LogRecord {
+                level: LogLevel::Info,
+                target: "mobile_sdk_rs::vdc_collection".into(),
+                message: "stored credential".into(),
+                fields: HashMap::from([("credential_id".into(), "1234".into())]),
+                file: None,
+                line: Some(42),
+            }
+        );
+        assert!(LogLevel::Error < LogLevel::Trace);
+    }
+}
@@ -0,0 +1,268 @@
+//! Device authentication of mdoc presentations, with the device keys held by
+//! the native platform through the [DeviceSigner] and [DeviceKeyAgreement].
+//!
+//! ISO 18013-5 allows either a `deviceSignature`, a COSE_Sign1 by the device
+//! key, or a `deviceMac`, a COSE_Mac0 with a key derived from the ECDH shared
+//! secret of the device key and the ephemeral key of the reader:
+//!
+//! ```text
+//! EMacKey = HKDF-SHA256(ZAB, salt = SHA-256(SessionTranscriptBytes), info = "EMacKey")
+//! ```
+//!
+//! In both cases the payload is the detached DeviceAuthenticationBytes:
+//!
+//! ```text
+//! DeviceAuthentication      = ["DeviceAuthentication", SessionTranscript, DocType, DeviceNameSpacesBytes]
+//! DeviceAuthenticationBytes = #6.24(bstr .cbor DeviceAuthentication)
+//! ```
+
+use crate::common::KeyAlias;
+use crate::crypto_provider;
+use crate::signer::{self, DeviceKeyAgreement, DeviceSigner, DeviceSignerError};
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use serde_cbor::Value as Cbor;
+use sha2::{Digest, Sha256};
+
+/// The COSE header label of the algorithm.
+const ALG_LABEL: i128 = 1;
+/// The COSE algorithm identifier of HMAC 256/256.
+const HMAC_256: i128 = 5;
+
+/// Compute the `deviceMac` of a document, as a CBOR encoded COSE_Mac0.
+///
+/// `reader_key` is the SEC1 encoded ephemeral public key of the reader,
+/// `session_transcript` the CBOR encoded SessionTranscript of the session,
+/// and `device_namespaces` the CBOR encoded DeviceNameSpacesBytes of the
+/// document.
+///
+/// NOTE: the proximity session signs its responses with the device key, so
+/// MACs are for responses assembled by the app.
+#[uniffi::export(async_runtime = "tokio")]
+pub async fn mdoc_device_mac(
+    agreement: Arc<dyn DeviceKeyAgreement>,
+    key_alias: KeyAlias,
+    reader_key: Vec<u8>,
+    session_transcript: Vec<u8>,
+    doc_type: String,
+    device_namespaces: Vec<u8>,
+) -> Result<Vec<u8>, DeviceSignerError> {
+    let decode = |bytes: &[u8]| serde_cbor::from_slice::<Cbor>(bytes).map_err(encoding);
+    let session_transcript = decode(&session_transcript)?;
+    let device_authentication = device_authentication_bytes(
+        session_transcript.clone(),
+        doc_type,
+        decode(&device_namespaces)?,
+    )?;
+
+    let device_mac = device_mac(
+        agreement.as_ref(),
+        &key_alias,
+        reader_key,
+        &session_transcript,
+        &device_authentication,
+    )
+    .await?;
+    serde_cbor::to_vec(&device_mac).map_err(encoding)
+}
+
+/// Encode the DeviceAuthenticationBytes of a document.
+pub(crate) fn device_authentication_bytes(
+    session_transcript: Cbor,
+    doc_type: String,
+    device_namespaces: Cbor,
+) -> Result<Vec<u8>, DeviceSignerError> {
+    let device_authentication = serde_cbor::to_vec(&Cbor::Array(vec![
+        Cbor::Text("DeviceAuthentication".into()),
+        session_transcript,
+        Cbor::Text(doc_type),
+        device_namespaces,
+    ]))
+    .map_err(encoding)?;
+    serde_cbor::to_vec(&Cbor::Tag(24, Box::new(Cbor::Bytes(device_authentication))))
+        .map_err(encoding)
+}
+
+/// Sign the DeviceAuthenticationBytes with the device key, returning the
+/// `deviceSignature` COSE_Sign1.
+pub(crate) async fn device_signature(
+    signer: &dyn DeviceSigner,
+    key_alias: &KeyAlias,
+    device_authentication: Vec<u8>,
+) -> Result<Cbor, DeviceSignerError> {
+    let algorithm = signer.algorithm(key_alias.clone())?;
+    let protected = serde_cbor::to_vec(&Cbor::Map(BTreeMap::from([(
+        Cbor::Integer(ALG_LABEL),
+        Cbor::Integer(signer::cose_algorithm(&algorithm)?),
+    )])))
+    .map_err(encoding)?;
+
+    // The payload is detached, so the signature covers the
+    // DeviceAuthenticationBytes through the Sig_structure.
+    let sig_structure = serde_cbor::to_vec(&Cbor::Array(vec![
+        Cbor::Text("Signature1".into()),
+        Cbor::Bytes(protected.clone()),
+        Cbor::Bytes(vec![]),
+        Cbor::Bytes(device_authentication),
+    ]))
+    .map_err(encoding)?;
+    let signature = signer::sign_raw(signer, key_alias, sig_structure).await?;
+
+    Ok(Cbor::Array(vec![
+        Cbor::Bytes(protected),
+        Cbor::Map(BTreeMap::new()),
+        Cbor::Null,
+        Cbor::Bytes(signature),
+    ]))
+}
+
+/// MAC the DeviceAuthenticationBytes with the EMacKey of the device key and
+/// the reader key, returning the `deviceMac` COSE_Mac0.
+pub(crate) async fn device_mac(
+    agreement: &dyn DeviceKeyAgreement,
+    key_alias: &KeyAlias,
+    reader_key: Vec<u8>,
+    session_transcript: &Cbor,
+    device_authentication: &[u8],
+) -> Result<Cbor, DeviceSignerError> {
+    let shared_secret = agreement
+        .shared_secret(key_alias.clone(), reader_key)
+        .await?;
+    let key = mac_key(&shared_secret, session_transcript)?;
+
+    let protected = serde_cbor::to_vec(&Cbor::Map(BTreeMap::from([(
+        Cbor::Integer(ALG_LABEL),
+        Cbor::Integer(HMAC_256),
+    )])))
+    .map_err(encoding)?;
+    let mac_structure = serde_cbor::to_vec(&Cbor::Array(vec![
+        Cbor::Text("MAC0".into()),
+        Cbor::Bytes(protected.clone()),
+        Cbor::Bytes(vec![]),
+        Cbor::Bytes(device_authentication.to_vec()),
+    ]))
+    .map_err(encoding)?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).map_err(encoding)?;
+    mac.update(&mac_structure);
+    let tag = mac.finalize().into_bytes().to_vec();
+
+    Ok(Cbor::Array(vec![
+        Cbor::Bytes(protected),
+        Cbor::Map(BTreeMap::new()),
+        Cbor::Null,
+        Cbor::Bytes(tag),
+    ]))
+}
+
+/// Derive the EMacKey from the ECDH shared secret and the SessionTranscript.
+fn mac_key(shared_secret: &[u8], session_transcript: &Cbor) -> Result<[u8; 32], DeviceSignerError> {
+    let session_transcript = serde_cbor::to_vec(session_transcript).map_err(encoding)?;
+    let session_transcript_bytes =
+        serde_cbor::to_vec(&Cbor::Tag(24, Box::new(Cbor::Bytes(session_transcript))))
+            .map_err(encoding)?;
+    let salt = Sha256::digest(session_transcript_bytes);
+
+    crypto_provider::provider()
+        .hkdf_sha256(
+            shared_secret.to_vec(),
+            Some(salt.to_vec()),
+            b"EMacKey".to_vec(),
+            32,
+        )
+        .map_err(encoding)?
+        .try_into()
+        .map_err(encoding)
+}
+
+fn encoding(e: impl std::fmt::Debug) -> DeviceSignerError {
+    DeviceSignerError::Signing(format!("{e:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use p256::{elliptic_curve::sec1::ToEncodedPoint, PublicKey, SecretKey};
+
+    #[derive(Debug)]
+    struct TestAgreement(SecretKey);
+
+    #[async_trait::async_trait]
+    impl DeviceKeyAgreement for TestAgreement {
+        async fn shared_secret(
+            &self,
+            _: KeyAlias,
+            public_key: Vec<u8>,
+        ) -> Result<Vec<u8>, DeviceSignerError> {
+            Ok(ecdh(
+                &self.0,
+                &PublicKey::from_sec1_bytes(&public_key).unwrap(),
+            ))
+        }
+    }
+
+    fn ecdh(secret: &SecretKey, public: &PublicKey) -> Vec<u8> {
+        let shared = (public.to_projective() * *secret.to_nonzero_scalar()).to_affine();
+        shared.to_encoded_point(false).x().unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn computes_device_macs() {
+        let device_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let reader_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        let session_transcript =
+            Cbor::Array(vec![Cbor::Bytes(vec![1]), Cbor::Bytes(vec![2]), Cbor::Null]);
+        let device_authentication = device_authentication_bytes(
+            session_transcript.clone(),
+            "org.iso.18013.5.1.mDL".into(),
+            Cbor::Tag(24, Box::new(Cbor::Bytes(vec![0xa0]))),
+        )
+        .unwrap();
+
+        let device_mac = device_mac(
+            &TestAgreement(device_key.clone()),
+            &KeyAlias("device".into()),
+            reader_key
+                .public_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+            &session_transcript,
+            &device_authentication,
+        )
+        .await
+        .unwrap();
+        let Cbor::Array(parts) = device_mac else {
+            panic!("not a COSE_Mac0");
+        };
+        let [Cbor::Bytes(protected), _, Cbor::Null, Cbor::Bytes(tag)] = parts.as_slice() else {
+            panic!("not a COSE_Mac0");
+        };
+
+        // The reader derives the same key from its side of the agreement.
+        let verify = |session_transcript: &Cbor| {
+            let key = mac_key(
+                &ecdh(&reader_key, &device_key.public_key()),
+                session_transcript,
+            )
+            .unwrap();
+            let mut mac = Hmac::<Sha256>::new_from_slice(&key).unwrap();
+            mac.update(
+                &serde_cbor::to_vec(&Cbor::Array(vec![
+                    Cbor::Text("MAC0".into()),
+                    Cbor::Bytes(protected.clone()),
+                    Cbor::Bytes(vec![]),
+                    Cbor::Bytes(device_authentication.clone()),
+                ]))
+                .unwrap(),
+            );
+            mac.verify_slice(tag).is_ok()
+        };
+        assert!(verify(&session_transcript));
+        assert!(!verify(&Cbor::Null));
+    }
+}
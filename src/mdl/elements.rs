@@ -0,0 +1,209 @@
+//! Typed accessors for the data elements of mDLs, from the ISO 18013-5 and
+//! AAMVA namespaces.
+
+use std::collections::BTreeMap;
+
+use serde_cbor::Value as Cbor;
+use time::{macros::format_description, Date, OffsetDateTime};
+
+use crate::credential::claims::{cbor_leaf, ClaimLeaf};
+use crate::credential::mdoc::Mdoc;
+
+/// The doctype of mDLs.
+pub const MDL_DOCTYPE: &str = "org.iso.18013.5.1.mDL";
+/// The namespace of the ISO 18013-5 mDL data elements.
+pub const MDL_NAMESPACE: &str = "org.iso.18013.5.1";
+/// The namespace of the AAMVA mDL data elements.
+pub const AAMVA_NAMESPACE: &str = "org.iso.18013.5.1.aamva";
+
+/// A vehicle category the holder is licensed to drive.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct DrivingPrivilege {
+    /// The vehicle category code, e.g. `B`.
+    pub vehicle_category_code: String,
+    /// The issue date, `YYYY-MM-DD`.
+    pub issue_date: Option<String>,
+    /// The expiry date, `YYYY-MM-DD`.
+    pub expiry_date: Option<String>,
+}
+
+#[uniffi::export]
+impl Mdoc {
+    /// Return a data element of the mdoc.
+    pub fn element(&self, namespace: String, identifier: String) -> Option<ClaimLeaf> {
+        self.element_value(&namespace, &identifier)
+            .as_ref()
+            .map(cbor_leaf)
+    }
+
+    /// Return a data element of the AAMVA namespace, e.g. `DHS_compliance`.
+    pub fn aamva_element(&self, identifier: String) -> Option<ClaimLeaf> {
+        self.element(AAMVA_NAMESPACE.into(), identifier)
+    }
+
+    pub fn family_name(&self) -> Option<String> {
+        self.mdl_text("family_name")
+    }
+
+    pub fn given_name(&self) -> Option<String> {
+        self.mdl_text("given_name")
+    }
+
+    /// Return the birth date, `YYYY-MM-DD`.
+    pub fn birth_date(&self) -> Option<String> {
+        self.mdl_text("birth_date")
+    }
+
+    pub fn document_number(&self) -> Option<String> {
+        self.mdl_text("document_number")
+    }
+
+    /// Return the portrait of the holder, usually a JPEG.
+    pub fn portrait(&self) -> Option<Vec<u8>> {
+        match self.element_value(MDL_NAMESPACE, "portrait")? {
+            Cbor::Bytes(portrait) => Some(portrait),
+            _ => None,
+        }
+    }
+
+    /// Return the vehicle categories the holder is licensed to drive.
+    pub fn driving_privileges(&self) -> Vec<DrivingPrivilege> {
+        let Some(Cbor::Array(privileges)) = self.element_value(MDL_NAMESPACE, "driving_privileges")
+        else {
+            return vec![];
+        };
+
+        privileges
+            .iter()
+            .filter_map(|privilege| {
+                let Cbor::Map(privilege) = untag(privilege) else {
+                    return None;
+                };
+                let text = |name: &str| match privilege.get(&Cbor::Text(name.into())).map(untag) {
+                    Some(Cbor::Text(value)) => Some(value.clone()),
+                    _ => None,
+                };
+
+                Some(DrivingPrivilege {
+                    vehicle_category_code: text("vehicle_category_code")?,
+                    issue_date: text("issue_date"),
+                    expiry_date: text("expiry_date"),
+                })
+            })
+            .collect()
+    }
+
+    /// Return whether the holder is over the age, from the `age_over_NN`
+    /// elements or else the birth date, or `None` if it cannot be told.
+    pub fn age_over(&self, age: u8) -> Option<bool> {
+        let Some(Cbor::Map(elements)) = self.namespace(MDL_NAMESPACE) else {
+            return None;
+        };
+        age_over(&elements, age, OffsetDateTime::now_utc().date())
+    }
+}
+
+impl Mdoc {
+    fn namespace(&self, namespace: &str) -> Option<Cbor> {
+        match self.namespaces_as_cbor() {
+            Cbor::Map(mut namespaces) => namespaces.remove(&Cbor::Text(namespace.into())),
+            _ => None,
+        }
+    }
+
+    fn element_value(&self, namespace: &str, identifier: &str) -> Option<Cbor> {
+        match self.namespace(namespace)? {
+            Cbor::Map(mut elements) => elements.remove(&Cbor::Text(identifier.into())),
+            _ => None,
+        }
+    }
+
+    fn mdl_text(&self, identifier: &str) -> Option<String> {
+        match self
+            .element_value(MDL_NAMESPACE, identifier)
+            .as_ref()
+            .map(untag)
+        {
+            Some(Cbor::Text(value)) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Return the value of a CBOR tag, e.g. of full-dates.
+fn untag(value: &Cbor) -> &Cbor {
+    match value {
+        Cbor::Tag(_, value) => untag(value),
+        value => value,
+    }
+}
+
+/// Tell whether the holder is over the age at `today`.
+///
+/// Following ISO 18013-5, being over an older age implies being over a
+/// younger one, and not being over a younger age implies not being over an
+/// older one.
+fn age_over(elements: &BTreeMap<Cbor, Cbor>, age: u8, today: Date) -> Option<bool> {
+    let attestations = elements.iter().filter_map(|(name, value)| {
+        let Cbor::Text(name) = name else {
+            return None;
+        };
+        let over = name.strip_prefix("age_over_")?.parse::<u8>().ok()?;
+        match untag(value) {
+            Cbor::Bool(value) => Some((over, *value)),
+            _ => None,
+        }
+    });
+    for (over, value) in attestations {
+        if (value && over >= age) || (!value && over <= age) {
+            return Some(value);
+        }
+    }
+
+    let Some(Cbor::Text(birth_date)) = elements.get(&Cbor::Text("birth_date".into())).map(untag)
+    else {
+        return None;
+    };
+    let birth_date = Date::parse(birth_date, format_description!("[year]-[month]-[day]")).ok()?;
+    let birthday = birth_date
+        .replace_year(birth_date.year() + age as i32)
+        .ok()
+        // Those born on February 29 come of age on March 1.
+        .or_else(|| {
+            Date::from_calendar_date(birth_date.year() + age as i32, time::Month::March, 1).ok()
+        })?;
+
+    Some(birthday <= today)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_age_over() {
+        let today = Date::from_calendar_date(2024, time::Month::June, 1).unwrap();
+        let elements = |entries: Vec<(&str, Cbor)>| -> BTreeMap<Cbor, Cbor> {
+            entries
+                .into_iter()
+                .map(|(name, value)| (Cbor::Text(name.into()), value))
+                .collect()
+        };
+
+        let attested = elements(vec![
+            ("age_over_18", Cbor::Bool(true)),
+            ("age_over_65", Cbor::Bool(false)),
+        ]);
+        assert_eq!(age_over(&attested, 18, today), Some(true));
+        assert_eq!(age_over(&attested, 16, today), Some(true));
+        assert_eq!(age_over(&attested, 70, today), Some(false));
+        assert_eq!(age_over(&attested, 21, today), None);
+
+        let born = elements(vec![(
+            "birth_date",
+            Cbor::Tag(1004, Box::new(Cbor::Text("2003-06-01".into()))),
+        )]);
+        assert_eq!(age_over(&born, 21, today), Some(true));
+        assert_eq!(age_over(&born, 22, today), Some(false));
+    }
+}
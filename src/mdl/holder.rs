@@ -9,13 +9,19 @@
 //! will use for the BLE central client:
 //!
 
+use super::reader_auth::{self, ReaderAuthError, ReaderIdentity};
+use super::receipt::ReleasedElement;
 use crate::common::*;
 use crate::credential::mdoc::Mdoc;
+use crate::crypto_provider;
+use crate::signer::{self, DeviceSigner};
+use crate::trust_anchors::{TrustAnchorError, TrustAnchorPurpose, TrustAnchorStore};
 use crate::{storage_manager::StorageManagerInterface, vdc_collection::VdcCollection};
 use std::ops::DerefMut;
+use std::time::SystemTime;
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, RwLock},
 };
 
 use isomdl::{
@@ -26,6 +32,7 @@ use isomdl::{
     },
     presentation::device::{self, SessionManagerInit},
 };
+use serde_cbor::Value as Cbor;
 
 /// Begin the mDL presentation process for the holder when the desired
 /// Mdoc is already stored in a [VdcCollection].
@@ -88,8 +95,12 @@ pub fn initialize_mdl_presentation(
     Ok(MdlPresentationSession {
         engaged: Mutex::new(engaged_state),
         in_process: Mutex::new(None),
+        trust_anchors: RwLock::new(None),
         qr_code_uri,
         ble_ident,
+        ble_uuid: uuid,
+        mdoc_id,
+        doc_type: mdoc.doctype(),
     })
 }
 
@@ -138,33 +149,169 @@ pub fn initialize_mdl_presentation_from_bytes(
     Ok(MdlPresentationSession {
         engaged: Mutex::new(engaged_state),
         in_process: Mutex::new(None),
+        trust_anchors: RwLock::new(None),
         qr_code_uri,
         ble_ident,
+        ble_uuid: uuid,
+        mdoc_id: mdoc.id(),
+        doc_type: mdoc.doctype(),
     })
 }
 
 #[derive(uniffi::Object)]
 pub struct MdlPresentationSession {
-    engaged: Mutex<device::SessionManagerEngaged>,
+    pub(crate) engaged: Mutex<device::SessionManagerEngaged>,
     in_process: Mutex<Option<InProcessRecord>>,
+    trust_anchors: RwLock<Option<Arc<TrustAnchorStore>>>,
     pub qr_code_uri: String,
     pub ble_ident: Vec<u8>,
+    pub(crate) ble_uuid: Uuid,
+    /// The ID of the presented mdoc.
+    pub(crate) mdoc_id: Uuid,
+    pub(crate) doc_type: String,
 }
 
 #[derive(uniffi::Object, Clone)]
 struct InProcessRecord {
     session: device::SessionManager,
     items_request: device::RequestedItems,
+    /// The reader of the request of the presented mdoc, if its ReaderAuth
+    /// was verified.
+    reader: Option<ReaderIdentity>,
+    /// The requested elements the holder permitted to share.
+    released: Vec<ReleasedElement>,
+    /// The response sent to the reader, with when it was retrieved.
+    response: Option<(Vec<u8>, SystemTime)>,
 }
 
 #[uniffi::export]
+impl MdlPresentationSession {
+    /// Constructs the response to be sent from the holder to the reader containing
+    /// the items of information the user has consented to share.
+    ///
+    /// Takes a HashMap of items the user has authorized the app to share, as well
+    /// as the id of a key stored in the key manager to be used to sign the response.
+    /// Returns a byte array containing the signed response to be returned to the
+    /// reader.
+    pub fn generate_response(
+        &self,
+        permitted_items: HashMap<String, HashMap<String, Vec<String>>>,
+    ) -> Result<Vec<u8>, SignatureError> {
+        let released = permitted_items
+            .iter()
+            .flat_map(|(doc_type, namespaces)| {
+                namespaces.iter().flat_map(move |(namespace, identifiers)| {
+                    identifiers
+                        .iter()
+                        .map(move |identifier| (doc_type, namespace, identifier))
+                })
+            })
+            .collect::<Vec<_>>();
+        let permitted = permitted_items
+            .into_iter()
+            .map(|(doc_type, namespaces)| {
+                let ns = namespaces.into_iter().collect();
+                (doc_type, ns)
+            })
+            .collect();
+        if let Some(ref mut in_process) = self.in_process.lock().unwrap().deref_mut() {
+            in_process.released = released
+                .into_iter()
+                .filter_map(|(doc_type, namespace, identifier)| {
+                    let intent_to_retain = intent_to_retain(
+                        &in_process.items_request,
+                        doc_type,
+                        namespace,
+                        identifier,
+                    )?;
+                    Some(ReleasedElement {
+                        namespace: namespace.clone(),
+                        identifier: identifier.clone(),
+                        intent_to_retain,
+                    })
+                })
+                .collect();
+            in_process.response = None;
+            in_process
+                .session
+                .prepare_response(&in_process.items_request, permitted);
+            Ok(in_process
+                .session
+                .get_next_signature_payload()
+                .map(|(_, payload)| payload)
+                .ok_or(SignatureError::Generic {
+                    value: "Failed to get next signature payload".to_string(),
+                })?
+                .to_vec())
+        } else {
+            Err(SignatureError::Generic {
+                value: "Could not get lock on session".to_string(),
+            })
+        }
+    }
+
+    pub fn submit_response(&self, der_signature: Vec<u8>) -> Result<Vec<u8>, SignatureError> {
+        let signature = p256::ecdsa::Signature::from_der(&der_signature).map_err(|e| {
+            SignatureError::InvalidSignature {
+                value: e.to_string(),
+            }
+        })?;
+        self.submit_signature(signature.to_bytes().to_vec())
+    }
+
+    /// Terminates the mDL exchange session.
+    ///
+    /// Returns the termination message to be transmitted to the reader.
+    pub fn terminate_session(&self) -> Result<Vec<u8>, TerminationError> {
+        let msg = session::SessionData {
+            data: None,
+            status: Some(session::Status::SessionTermination),
+        };
+        let msg_bytes = serde_cbor::to_vec(&msg).map_err(|e| TerminationError::Generic {
+            value: format!("Could not serialize message bytes: {e:?}"),
+        })?;
+        Ok(msg_bytes)
+    }
+
+    /// Returns the generated QR code
+    pub fn get_qr_code_uri(&self) -> String {
+        self.qr_code_uri.clone()
+    }
+
+    /// Returns the BLE identification
+    pub fn get_ble_ident(&self) -> Vec<u8> {
+        self.ble_ident.clone()
+    }
+
+    /// Set the store of trust anchors whose reader root certificates are
+    /// trusted to issue the certificates of the ReaderAuth of requests.
+    pub fn set_trust_anchor_store(&self, store: Arc<TrustAnchorStore>) -> Result<(), SessionError> {
+        *self
+            .trust_anchors
+            .write()
+            .map_err(|_| SessionError::Generic {
+                value: "Could not lock trust anchors".to_string(),
+            })? = Some(store);
+        Ok(())
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
 impl MdlPresentationSession {
     /// Handle a request from a reader that is seeking information from the mDL holder.
     ///
     /// Takes the raw bytes received from the reader by the holder over the transmission
     /// technology. Returns a Vector of information items requested by the reader, or an
     /// error.
-    pub fn handle_request(&self, request: Vec<u8>) -> Result<Vec<ItemsRequest>, RequestError> {
+    ///
+    /// The ReaderAuth of each request is verified against the reader root
+    /// certificates of the [TrustAnchorStore] of the session, and the
+    /// identity of the reader is returned with the items it requests. Without
+    /// a store, requests are not authenticated.
+    pub async fn handle_request(
+        &self,
+        request: Vec<u8>,
+    ) -> Result<Vec<ItemsRequest>, RequestError> {
         let (session_manager, items_requests) = {
             let session_establishment: SessionEstablishment = serde_cbor::from_slice(&request)
                 .map_err(|e| RequestError::Generic {
@@ -182,17 +329,39 @@ impl MdlPresentationSession {
                 })?
         };
 
+        let trust_anchors = self
+            .trust_anchors
+            .read()
+            .map_err(|_| RequestError::Generic {
+                value: "Could not lock trust anchors".to_string(),
+            })?
+            .clone();
+        let readers = match trust_anchors {
+            Some(trust_anchors) => {
+                authenticate_readers(&session_manager, &request, &trust_anchors).await?
+            }
+            None => vec![None; items_requests.len()],
+        };
+
         let mut in_process = self.in_process.lock().map_err(|_| RequestError::Generic {
             value: "Could not lock mutex".to_string(),
         })?;
         *in_process = Some(InProcessRecord {
             session: session_manager,
             items_request: items_requests.clone(),
+            reader: items_requests
+                .iter()
+                .zip(&readers)
+                .find(|(req, _)| req.doc_type == self.doc_type)
+                .and_then(|(_, reader)| reader.clone()),
+            released: vec![],
+            response: None,
         });
 
         Ok(items_requests
             .into_iter()
-            .map(|req| ItemsRequest {
+            .zip(readers)
+            .map(|(req, reader)| ItemsRequest {
                 doc_type: req.doc_type,
                 namespaces: req
                     .namespaces
@@ -203,64 +372,63 @@ impl MdlPresentationSession {
                         (ns, items_request)
                     })
                     .collect(),
+                reader,
             })
             .collect())
     }
 
-    /// Constructs the response to be sent from the holder to the reader containing
-    /// the items of information the user has consented to share.
+    /// Constructs the response containing the items of information the user
+    /// has consented to share, signed with the device key through the signer.
     ///
-    /// Takes a HashMap of items the user has authorized the app to share, as well
-    /// as the id of a key stored in the key manager to be used to sign the response.
-    /// Returns a byte array containing the signed response to be returned to the
-    /// reader.
-    pub fn generate_response(
+    /// Returns a byte array containing the signed response to be returned to
+    /// the reader.
+    ///
+    /// NOTE: the session only supports ES256 device keys.
+    pub async fn generate_signed_response(
         &self,
         permitted_items: HashMap<String, HashMap<String, Vec<String>>>,
+        signer: Arc<dyn DeviceSigner>,
+        key_alias: KeyAlias,
     ) -> Result<Vec<u8>, SignatureError> {
-        let permitted = permitted_items
-            .into_iter()
-            .map(|(doc_type, namespaces)| {
-                let ns = namespaces.into_iter().collect();
-                (doc_type, ns)
-            })
-            .collect();
-        if let Some(ref mut in_process) = self.in_process.lock().unwrap().deref_mut() {
-            in_process
-                .session
-                .prepare_response(&in_process.items_request, permitted);
-            Ok(in_process
-                .session
-                .get_next_signature_payload()
-                .map(|(_, payload)| payload)
-                .ok_or(SignatureError::Generic {
-                    value: "Failed to get next signature payload".to_string(),
-                })?
-                .to_vec())
-        } else {
-            Err(SignatureError::Generic {
-                value: "Could not get lock on session".to_string(),
-            })
+        let algorithm =
+            signer
+                .algorithm(key_alias.clone())
+                .map_err(|e| SignatureError::Generic {
+                    value: format!("{e:?}"),
+                })?;
+        if algorithm != "ES256" {
+            return Err(SignatureError::Generic {
+                value: format!("Unsupported device key algorithm: {algorithm}"),
+            });
         }
+
+        let payload = self.generate_response(permitted_items)?;
+        let signature = signer::sign_raw(signer.as_ref(), &key_alias, payload)
+            .await
+            .map_err(|e| SignatureError::Generic {
+                value: format!("{e:?}"),
+            })?;
+        self.submit_signature(signature)
     }
+}
 
-    pub fn submit_response(&self, der_signature: Vec<u8>) -> Result<Vec<u8>, SignatureError> {
-        let signature = p256::ecdsa::Signature::from_der(&der_signature).map_err(|e| {
-            SignatureError::InvalidSignature {
-                value: e.to_string(),
-            }
-        })?;
+impl MdlPresentationSession {
+    /// Submit the raw `r || s` signature of the response, and return the
+    /// response.
+    fn submit_signature(&self, signature: Vec<u8>) -> Result<Vec<u8>, SignatureError> {
         if let Some(ref mut in_process) = self.in_process.lock().unwrap().deref_mut() {
             in_process
                 .session
-                .submit_next_signature(signature.to_bytes().to_vec())
+                .submit_next_signature(signature)
                 .map_err(|e| SignatureError::Generic {
                     value: format!("Could not submit next signature: {e:?}"),
                 })?;
-            in_process
+            let response = in_process
                 .session
                 .retrieve_response()
-                .ok_or(SignatureError::TooManyDocuments)
+                .ok_or(SignatureError::TooManyDocuments)?;
+            in_process.response = Some((response.clone(), SystemTime::now()));
+            Ok(response)
         } else {
             Err(SignatureError::Generic {
                 value: "Could not get lock on session".to_string(),
@@ -268,29 +436,133 @@ impl MdlPresentationSession {
         }
     }
 
-    /// Terminates the mDL exchange session.
-    ///
-    /// Returns the termination message to be transmitted to the reader.
-    pub fn terminate_session(&self) -> Result<Vec<u8>, TerminationError> {
-        let msg = session::SessionData {
-            data: None,
-            status: Some(session::Status::SessionTermination),
-        };
-        let msg_bytes = serde_cbor::to_vec(&msg).map_err(|e| TerminationError::Generic {
-            value: format!("Could not serialize message bytes: {e:?}"),
-        })?;
-        Ok(msg_bytes)
+    /// Return the last response sent to the reader, if any.
+    pub(crate) fn last_release(&self) -> Option<Release> {
+        let in_process = self.in_process.lock().ok()?;
+        let in_process = in_process.as_ref()?;
+        let (response, sent_at) = in_process.response.clone()?;
+        Some(Release {
+            reader: in_process.reader.clone(),
+            elements: in_process.released.clone(),
+            response,
+            sent_at,
+        })
     }
+}
 
-    /// Returns the generated QR code
-    pub fn get_qr_code_uri(&self) -> String {
-        self.qr_code_uri.clone()
-    }
+/// A response sent to a reader.
+pub(crate) struct Release {
+    /// The reader, if the ReaderAuth of its request was verified.
+    pub(crate) reader: Option<ReaderIdentity>,
+    /// The elements released in the response.
+    pub(crate) elements: Vec<ReleasedElement>,
+    pub(crate) response: Vec<u8>,
+    pub(crate) sent_at: SystemTime,
+}
 
-    /// Returns the BLE identification
-    pub fn get_ble_ident(&self) -> Vec<u8> {
-        self.ble_ident.clone()
+/// Verify the ReaderAuth of each DocRequest of the DeviceRequest of a
+/// SessionEstablishment, and return the identity of the reader of each, or
+/// `None` for DocRequests without ReaderAuth.
+///
+/// NOTE: isomdl does not expose the decrypted DeviceRequest, so it is
+/// decrypted again with the SKReader of the serialized session.
+async fn authenticate_readers(
+    session: &device::SessionManager,
+    session_establishment: &[u8],
+    trust_anchors: &TrustAnchorStore,
+) -> Result<Vec<Option<ReaderIdentity>>, RequestError> {
+    let generic = |value: &str| RequestError::Generic {
+        value: value.to_string(),
+    };
+    let field = |value: &Cbor, name: &str| match value {
+        Cbor::Map(fields) => fields.get(&Cbor::Text(name.into())).cloned(),
+        _ => None,
+    };
+
+    let state = session_state(session).map_err(|e| RequestError::Generic {
+        value: format!("Could not serialize session: {e:?}"),
+    })?;
+    let session_transcript = field(&state, "session_transcript")
+        .ok_or_else(|| generic("The session has no session transcript"))?;
+    let sk_reader = match field(&state, "sk_reader") {
+        Some(Cbor::Bytes(key)) => key,
+        Some(Cbor::Array(key)) => key
+            .iter()
+            .map(|byte| match byte {
+                Cbor::Integer(byte) => u8::try_from(*byte).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| generic("The session has a malformed SKReader"))?,
+        _ => return Err(generic("The session has no SKReader")),
+    };
+
+    let session_establishment: Cbor =
+        serde_cbor::from_slice(session_establishment).map_err(|e| RequestError::Generic {
+            value: format!("Could not deserialize request: {e:?}"),
+        })?;
+    let Some(Cbor::Bytes(data)) = field(&session_establishment, "data") else {
+        return Err(generic("The request has no data"));
+    };
+    // The first message of the reader, with the reader identifier and a
+    // message counter of 1.
+    let mut nonce = [0; 12];
+    nonce[11] = 1;
+    let device_request = crypto_provider::provider()
+        .aes_gcm_open(sk_reader, nonce.to_vec(), data, vec![])
+        .map_err(|e| RequestError::Generic {
+            value: format!("Could not decrypt request: {e:?}"),
+        })?;
+    let device_request: Cbor =
+        serde_cbor::from_slice(&device_request).map_err(|e| RequestError::Generic {
+            value: format!("Could not deserialize device request: {e:?}"),
+        })?;
+    let Some(Cbor::Array(doc_requests)) = field(&device_request, "docRequests") else {
+        return Err(generic("The device request has no doc requests"));
+    };
+
+    let anchors = trust_anchors.anchors(TrustAnchorPurpose::Reader)?;
+    let mut readers = vec![];
+    for doc_request in doc_requests {
+        let reader = match field(&doc_request, "readerAuth") {
+            Some(reader_auth) => Some(
+                reader_auth::verify(
+                    &reader_auth,
+                    session_transcript.clone(),
+                    field(&doc_request, "itemsRequest")
+                        .ok_or_else(|| generic("The doc request has no items request"))?,
+                    &anchors,
+                    trust_anchors.checks_revocation(),
+                    SystemTime::now(),
+                )
+                .await?,
+            ),
+            None => None,
+        };
+        readers.push(reader);
     }
+    Ok(readers)
+}
+
+/// Return whether the reader intends to retain a requested element, or `None`
+/// if it was not requested.
+fn intent_to_retain(
+    items_request: &device::RequestedItems,
+    doc_type: &str,
+    namespace: &str,
+    identifier: &str,
+) -> Option<bool> {
+    items_request
+        .iter()
+        .filter(|request| request.doc_type == doc_type)
+        .find_map(|request| {
+            request
+                .namespaces
+                .clone()
+                .into_inner()
+                .get(namespace)
+                .and_then(|elements| elements.clone().into_inner().get(identifier).copied())
+        })
 }
 
 #[derive(thiserror::Error, uniffi::Error, Debug)]
@@ -303,12 +575,22 @@ pub enum SessionError {
 pub enum RequestError {
     #[error("{value}")]
     Generic { value: String },
+    #[error(transparent)]
+    ReaderAuth(#[from] ReaderAuthError),
+}
+
+impl From<TrustAnchorError> for RequestError {
+    fn from(value: TrustAnchorError) -> Self {
+        RequestError::ReaderAuth(value.into())
+    }
 }
 
 #[derive(uniffi::Record, Clone)]
 pub struct ItemsRequest {
     doc_type: String,
     namespaces: HashMap<String, HashMap<String, bool>>,
+    /// The reader, if the ReaderAuth of the request was verified.
+    reader: Option<ReaderIdentity>,
 }
 
 #[derive(thiserror::Error, uniffi::Error, Debug)]
@@ -347,6 +629,14 @@ pub enum KeyTransformationError {
     ToSEC1 { value: String },
 }
 
+/// Return the CBOR of the serialized state of a session, through its
+/// encoding, so that its tagged values are kept.
+pub(crate) fn session_state<T: serde::Serialize>(
+    state: &T,
+) -> Result<serde_cbor::Value, serde_cbor::Error> {
+    serde_cbor::from_slice(&serde_cbor::to_vec(state)?)
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::BTreeMap;
@@ -385,6 +675,7 @@ mod tests {
                 r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
                 payload: mdoc_bytes,
                 key_alias: Some(KeyAlias("Testing".to_string())),
+                display: vec![],
             })
             .unwrap();
 
@@ -418,7 +709,7 @@ mod tests {
             )
             .unwrap();
         // let request = reader_session_manager.new_request(namespaces).unwrap();
-        let _request_data = presentation_session.handle_request(request).unwrap();
+        let _request_data = presentation_session.handle_request(request).await.unwrap();
         let permitted_items = [(
             "org.iso.18013.5.1.mDL".to_string(),
             [(
@@ -462,6 +753,7 @@ mod tests {
                 r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
                 payload: mdoc_bytes,
                 key_alias: Some(KeyAlias("Testing".to_string())),
+                display: vec![],
             })
             .unwrap();
 
@@ -486,10 +778,15 @@ mod tests {
             ]),
         )
         .unwrap();
-        // let request = reader_session_manager.new_request(namespaces).unwrap();
-        let _request_data = presentation_session
+        presentation_session
+            .set_trust_anchor_store(TrustAnchorStore::new())
+            .unwrap();
+        let request_data = presentation_session
             .handle_request(reader_session_data.request)
+            .await
             .unwrap();
+        // The reader did not authenticate its request.
+        assert_eq!(request_data[0].reader, None);
         let permitted_items = [(
             "org.iso.18013.5.1.mDL".to_string(),
             [(
@@ -1,5 +1,10 @@
+pub mod device_auth;
+pub mod elements;
 pub mod holder;
+pub mod nfc;
 pub mod reader;
+pub mod reader_auth;
+pub mod receipt;
 
 use ssi::{
     claims::vc::v1::{data_integrity::any_credential_from_json_str, ToJwtClaims},
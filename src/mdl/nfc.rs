@@ -0,0 +1,402 @@
+//! NFC engagement for ISO 18013-5 proximity presentations.
+//!
+//! Instead of displaying a QR code, the holder can be engaged by a reader
+//! tapping the device. The NFC exchange only carries the device engagement and
+//! the carrier selection; the session itself continues over BLE using the same
+//! [MdlPresentationSession] methods as the QR flow, with the NFCHandover of
+//! the exchanged messages in its SessionTranscript.
+//!
+//! Two handover modes are supported:
+//!
+//! - Static handover: the app serves the message returned by
+//!   [MdlPresentationSession::nfc_static_handover_select] from its host card
+//!   emulation service.
+//! - Negotiated handover: the reader sends a Handover Request message, which is
+//!   passed to [MdlPresentationSession::nfc_negotiated_handover] to obtain the
+//!   Handover Select message to return.
+
+use super::holder::{session_state, MdlPresentationSession};
+use crate::common::Uuid;
+
+use base64::prelude::*;
+use isomdl::definitions::session::Handover;
+use serde_cbor::Value as CborValue;
+
+const TNF_WELL_KNOWN: u8 = 0x01;
+const TNF_MEDIA: u8 = 0x02;
+const TNF_EXTERNAL: u8 = 0x04;
+
+const FLAG_MB: u8 = 0x80;
+const FLAG_ME: u8 = 0x40;
+const FLAG_CF: u8 = 0x20;
+const FLAG_SR: u8 = 0x10;
+const FLAG_IL: u8 = 0x08;
+
+const RTD_HANDOVER_SELECT: &[u8] = b"Hs";
+const RTD_HANDOVER_REQUEST: &[u8] = b"Hr";
+const RTD_ALTERNATIVE_CARRIER: &[u8] = b"ac";
+const BLE_OOB_TYPE: &[u8] = b"application/vnd.bluetooth.le.oob";
+const DEVICE_ENGAGEMENT_TYPE: &[u8] = b"iso.org:18013:deviceengagement";
+
+/// Connection Handover version 1.5.
+const HANDOVER_VERSION: u8 = 0x15;
+/// Carrier power state: active.
+const CPS_ACTIVE: u8 = 0x01;
+const BLE_CARRIER_ID: &[u8] = b"0";
+const DEVICE_ENGAGEMENT_ID: &[u8] = b"mdoc";
+
+/// AD type for the LE role, and the value for "central only".
+const AD_LE_ROLE: u8 = 0x1C;
+const LE_ROLE_CENTRAL_ONLY: u8 = 0x01;
+/// AD type for a complete list of 128-bit service UUIDs.
+const AD_COMPLETE_128_BIT_UUIDS: u8 = 0x07;
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum NfcHandoverError {
+    #[error("failed to decode the device engagement from the QR code URI")]
+    DeviceEngagementDecoding,
+    #[error("malformed NDEF message: {value}")]
+    MalformedNdef { value: String },
+    #[error("the NDEF message is not a handover request")]
+    NotAHandoverRequest,
+    #[error("the reader did not offer a BLE carrier")]
+    UnsupportedCarrier,
+    #[error("failed to bind the session to the handover: {value}")]
+    Session { value: String },
+}
+
+#[uniffi::export]
+impl MdlPresentationSession {
+    /// Returns the NDEF Handover Select message for static NFC handover.
+    ///
+    /// The message advertises the BLE central client mode UUID of this session
+    /// and embeds the device engagement, so a reader can connect without
+    /// scanning the QR code.
+    pub fn nfc_static_handover_select(&self) -> Result<Vec<u8>, NfcHandoverError> {
+        let select = handover_select(&self.device_engagement_bytes()?, self.ble_uuid);
+        self.set_nfc_handover(&select, None)?;
+        Ok(select)
+    }
+
+    /// Responds to a reader's NDEF Handover Request for negotiated NFC handover.
+    ///
+    /// Takes the raw Handover Request message received from the reader, and
+    /// returns the Handover Select message to be transmitted back. An error is
+    /// returned if the reader does not offer BLE as an alternative carrier.
+    pub fn nfc_negotiated_handover(
+        &self,
+        handover_request: Vec<u8>,
+    ) -> Result<Vec<u8>, NfcHandoverError> {
+        check_handover_request(&handover_request)?;
+        let select = handover_select(&self.device_engagement_bytes()?, self.ble_uuid);
+        self.set_nfc_handover(&select, Some(&handover_request))?;
+        Ok(select)
+    }
+}
+
+impl MdlPresentationSession {
+    /// Replace the QR handover of the session with the NFCHandover of the
+    /// Handover Select and Request messages, which the SessionTranscript the
+    /// session keys and the device authentication are derived from carries.
+    ///
+    /// NOTE: the engaged session does not expose its handover, so it is
+    /// replaced in the serialized session.
+    fn set_nfc_handover(
+        &self,
+        select: &[u8],
+        request: Option<&[u8]>,
+    ) -> Result<(), NfcHandoverError> {
+        let session_error = |e: serde_cbor::Error| NfcHandoverError::Session {
+            value: format!("{e:?}"),
+        };
+        let handover = Handover::NFC(select.to_vec().into(), request.map(|r| r.to_vec().into()));
+
+        let mut engaged = self.engaged.lock().map_err(|_| NfcHandoverError::Session {
+            value: "Could not lock mutex".into(),
+        })?;
+        let mut state = session_state(&*engaged).map_err(session_error)?;
+        let CborValue::Map(fields) = &mut state else {
+            return Err(NfcHandoverError::Session {
+                value: "unexpected session state".into(),
+            });
+        };
+        fields.insert(
+            CborValue::Text("handover".into()),
+            session_state(&handover).map_err(session_error)?,
+        );
+        *engaged = serde_cbor::to_vec(&state)
+            .and_then(|state| serde_cbor::from_slice(&state))
+            .map_err(session_error)?;
+        Ok(())
+    }
+
+    fn device_engagement_bytes(&self) -> Result<Vec<u8>, NfcHandoverError> {
+        let encoded = self
+            .qr_code_uri
+            .strip_prefix("mdoc:")
+            .ok_or(NfcHandoverError::DeviceEngagementDecoding)?;
+        BASE64_URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|_| NfcHandoverError::DeviceEngagementDecoding)
+    }
+}
+
+/// A single NDEF record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NdefRecord {
+    pub tnf: u8,
+    pub record_type: Vec<u8>,
+    pub id: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl NdefRecord {
+    fn new(tnf: u8, record_type: &[u8], id: &[u8], payload: Vec<u8>) -> Self {
+        Self {
+            tnf,
+            record_type: record_type.to_vec(),
+            id: id.to_vec(),
+            payload,
+        }
+    }
+}
+
+/// Encode a list of records as an NDEF message.
+pub(crate) fn encode_ndef_message(records: &[NdefRecord]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let last = records.len().saturating_sub(1);
+
+    for (idx, record) in records.iter().enumerate() {
+        let short = record.payload.len() < 256;
+
+        let mut header = record.tnf & 0x07;
+        if idx == 0 {
+            header |= FLAG_MB;
+        }
+        if idx == last {
+            header |= FLAG_ME;
+        }
+        if short {
+            header |= FLAG_SR;
+        }
+        if !record.id.is_empty() {
+            header |= FLAG_IL;
+        }
+
+        out.push(header);
+        out.push(record.record_type.len() as u8);
+        if short {
+            out.push(record.payload.len() as u8);
+        } else {
+            out.extend_from_slice(&(record.payload.len() as u32).to_be_bytes());
+        }
+        if !record.id.is_empty() {
+            out.push(record.id.len() as u8);
+        }
+        out.extend_from_slice(&record.record_type);
+        out.extend_from_slice(&record.id);
+        out.extend_from_slice(&record.payload);
+    }
+
+    out
+}
+
+/// Decode an NDEF message into its records.
+///
+/// Chunked records are not used by ISO 18013-5 and are rejected.
+pub(crate) fn decode_ndef_message(bytes: &[u8]) -> Result<Vec<NdefRecord>, NfcHandoverError> {
+    let mut records = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let header = take(bytes, &mut pos, 1)?[0];
+        if header & FLAG_CF != 0 {
+            return Err(malformed("chunked records are not supported"));
+        }
+
+        let type_len = take(bytes, &mut pos, 1)?[0] as usize;
+        let payload_len = if header & FLAG_SR != 0 {
+            take(bytes, &mut pos, 1)?[0] as usize
+        } else {
+            let len = take(bytes, &mut pos, 4)?;
+            u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize
+        };
+        let id_len = if header & FLAG_IL != 0 {
+            take(bytes, &mut pos, 1)?[0] as usize
+        } else {
+            0
+        };
+
+        let record_type = take(bytes, &mut pos, type_len)?.to_vec();
+        let id = take(bytes, &mut pos, id_len)?.to_vec();
+        let payload = take(bytes, &mut pos, payload_len)?.to_vec();
+
+        records.push(NdefRecord {
+            tnf: header & 0x07,
+            record_type,
+            id,
+            payload,
+        });
+
+        if header & FLAG_ME != 0 {
+            break;
+        }
+    }
+
+    if pos != bytes.len() {
+        return Err(malformed("trailing bytes after the last record"));
+    }
+
+    Ok(records)
+}
+
+fn take<'a>(bytes: &'a [u8], pos: &mut usize, n: usize) -> Result<&'a [u8], NfcHandoverError> {
+    let slice = bytes
+        .get(*pos..*pos + n)
+        .ok_or_else(|| malformed("unexpected end of message"))?;
+    *pos += n;
+    Ok(slice)
+}
+
+fn malformed(value: &str) -> NfcHandoverError {
+    NfcHandoverError::MalformedNdef {
+        value: value.to_string(),
+    }
+}
+
+/// Build the Handover Select message for a BLE central client mode session.
+pub(crate) fn handover_select(device_engagement: &[u8], ble_uuid: Uuid) -> Vec<u8> {
+    let mut alternative_carrier = vec![CPS_ACTIVE, BLE_CARRIER_ID.len() as u8];
+    alternative_carrier.extend_from_slice(BLE_CARRIER_ID);
+    alternative_carrier.push(1);
+    alternative_carrier.push(DEVICE_ENGAGEMENT_ID.len() as u8);
+    alternative_carrier.extend_from_slice(DEVICE_ENGAGEMENT_ID);
+
+    let mut handover_select = vec![HANDOVER_VERSION];
+    handover_select.extend(encode_ndef_message(&[NdefRecord::new(
+        TNF_WELL_KNOWN,
+        RTD_ALTERNATIVE_CARRIER,
+        &[],
+        alternative_carrier,
+    )]));
+
+    // BLE advertising data is little-endian.
+    let mut uuid = ble_uuid.as_bytes().to_vec();
+    uuid.reverse();
+    let mut ble_oob = vec![2, AD_LE_ROLE, LE_ROLE_CENTRAL_ONLY];
+    ble_oob.push(uuid.len() as u8 + 1);
+    ble_oob.push(AD_COMPLETE_128_BIT_UUIDS);
+    ble_oob.extend(uuid);
+
+    encode_ndef_message(&[
+        NdefRecord::new(TNF_WELL_KNOWN, RTD_HANDOVER_SELECT, &[], handover_select),
+        NdefRecord::new(TNF_MEDIA, BLE_OOB_TYPE, BLE_CARRIER_ID, ble_oob),
+        NdefRecord::new(
+            TNF_EXTERNAL,
+            DEVICE_ENGAGEMENT_TYPE,
+            DEVICE_ENGAGEMENT_ID,
+            device_engagement.to_vec(),
+        ),
+    ])
+}
+
+/// Check that a Handover Request message offers a BLE carrier.
+pub(crate) fn check_handover_request(bytes: &[u8]) -> Result<(), NfcHandoverError> {
+    let records = decode_ndef_message(bytes)?;
+
+    match records.first() {
+        Some(record)
+            if record.tnf == TNF_WELL_KNOWN && record.record_type == RTD_HANDOVER_REQUEST => {}
+        _ => return Err(NfcHandoverError::NotAHandoverRequest),
+    }
+
+    records
+        .iter()
+        .skip(1)
+        .any(|record| record.tnf == TNF_MEDIA && record.record_type == BLE_OOB_TYPE)
+        .then_some(())
+        .ok_or(NfcHandoverError::UnsupportedCarrier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{CredentialType, KeyAlias};
+    use crate::credential::{Credential, CredentialFormat};
+    use crate::local_store::LocalStore;
+    use crate::mdl::holder::initialize_mdl_presentation;
+    use crate::vdc_collection::VdcCollection;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn handover_select_roundtrips() {
+        let uuid = Uuid::new_v4();
+        let message = handover_select(&[0xA0], uuid);
+        let records = decode_ndef_message(&message).unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].record_type, RTD_HANDOVER_SELECT);
+        assert_eq!(records[0].payload[0], HANDOVER_VERSION);
+        assert_eq!(records[1].id, BLE_CARRIER_ID);
+        assert_eq!(records[2].record_type, DEVICE_ENGAGEMENT_TYPE);
+        assert_eq!(records[2].payload, vec![0xA0]);
+
+        let carriers = decode_ndef_message(&records[0].payload[1..]).unwrap();
+        assert_eq!(carriers[0].record_type, RTD_ALTERNATIVE_CARRIER);
+    }
+
+    #[test]
+    fn binds_sessions_to_nfc_handovers() {
+        let mdoc = Uuid::new_v4();
+        let storage = Arc::new(LocalStore::new());
+        VdcCollection::new(storage.clone())
+            .add(&Credential {
+                id: mdoc,
+                format: CredentialFormat::MsoMdoc,
+                r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+                payload: BASE64_STANDARD
+                    .decode(include_str!("../../tests/res/mdoc.b64"))
+                    .unwrap(),
+                key_alias: Some(KeyAlias("Testing".into())),
+                display: vec![],
+            })
+            .unwrap();
+        let session = initialize_mdl_presentation(mdoc, Uuid::new_v4(), storage).unwrap();
+        let handover = |session: &MdlPresentationSession| {
+            let state = session_state(&*session.engaged.lock().unwrap()).unwrap();
+            let CborValue::Map(fields) = state else {
+                panic!("unexpected session state");
+            };
+            fields[&CborValue::Text("handover".into())].clone()
+        };
+        let qr_handover = handover(&session);
+
+        let select = session.nfc_static_handover_select().unwrap();
+        assert_ne!(handover(&session), qr_handover);
+        assert_eq!(
+            handover(&session),
+            session_state(&Handover::NFC(select.into(), None)).unwrap()
+        );
+    }
+
+    #[test]
+    fn handover_request_requires_ble() {
+        let request = |carrier: &[u8]| {
+            encode_ndef_message(&[
+                NdefRecord::new(TNF_WELL_KNOWN, RTD_HANDOVER_REQUEST, &[], vec![0x15]),
+                NdefRecord::new(TNF_MEDIA, carrier, b"0", vec![]),
+            ])
+        };
+
+        assert!(check_handover_request(&request(BLE_OOB_TYPE)).is_ok());
+        assert!(matches!(
+            check_handover_request(&request(b"application/vnd.wfa.nan")),
+            Err(NfcHandoverError::UnsupportedCarrier)
+        ));
+        assert!(matches!(
+            check_handover_request(&handover_select(&[], Uuid::new_v4())),
+            Err(NfcHandoverError::NotAHandoverRequest)
+        ));
+    }
+}
@@ -18,6 +18,8 @@ use isomdl::{
 };
 use uuid::Uuid;
 
+use crate::trust_anchors::{TrustAnchorPurpose, TrustAnchorStore};
+
 #[derive(thiserror::Error, uniffi::Error, Debug)]
 pub enum MDLReaderSessionError {
     #[error("{value}")]
@@ -105,6 +107,22 @@ pub fn establish_session(
     })
 }
 
+/// As [establish_session], trusting the IACA certificates of the trust
+/// anchor store.
+#[uniffi::export]
+pub fn establish_session_with_trust_anchor_store(
+    uri: String,
+    requested_items: HashMap<String, HashMap<String, bool>>,
+    trust_anchor_store: Arc<TrustAnchorStore>,
+) -> Result<MDLReaderSessionData, MDLReaderSessionError> {
+    let trust_anchors = trust_anchor_store
+        .list(TrustAnchorPurpose::Iaca)
+        .map_err(|e| MDLReaderSessionError::Generic {
+            value: format!("unable to read trust anchors: {e:?}"),
+        })?;
+    establish_session(uri, requested_items, Some(trust_anchors))
+}
+
 fn names_only_registry_from_pem(pem: &str) -> Result<TrustAnchor, x509::error::Error> {
     let ruleset = ValidationRuleSet {
         distinguished_names: vec!["2.5.4.6".to_string(), "2.5.4.8".to_string()],
@@ -0,0 +1,333 @@
+//! Verification of the ReaderAuth of ISO 18013-5 requests, so that the
+//! holder knows which reader is asking before any data is released.
+//!
+//! The reader signs, with the key of the leaf of its `x5chain`, the
+//! ReaderAuthenticationBytes of the request:
+//!
+//! ```text
+//! ReaderAuthentication      = ["ReaderAuthentication", SessionTranscript, ItemsRequestBytes]
+//! ReaderAuthenticationBytes = #6.24(bstr .cbor ReaderAuthentication)
+//! ```
+//!
+//! The chain must lead to a reader root certificate of the trust anchors.
+//!
+//! [super::holder::MdlPresentationSession::handle_request] verifies the
+//! ReaderAuth of the requests of proximity sessions, and
+//! [verify_reader_authentication] that of requests received otherwise.
+//!
+//! NOTE: only ES256 signatures are supported.
+
+use crate::trust_anchors::{verify_chain, TrustAnchorError, TrustAnchorPurpose, TrustAnchorStore};
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value as Cbor;
+use x509_cert::{
+    der::{asn1::ObjectIdentifier, Decode, Encode},
+    name::Name,
+    Certificate,
+};
+
+/// The COSE header label of the algorithm.
+const ALG_LABEL: i128 = 1;
+/// The COSE algorithm identifier of ES256.
+const ES256: i128 = -7;
+/// The COSE header label of the certificate chain.
+const X5CHAIN_LABEL: i128 = 33;
+/// The object identifiers of the common name and organization attributes.
+const COMMON_NAME: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.3");
+const ORGANIZATION: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.10");
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum ReaderAuthError {
+    #[error("Malformed reader authentication: {value}")]
+    Malformed { value: String },
+    #[error("Unsupported reader authentication algorithm: {value}")]
+    UnsupportedAlgorithm { value: String },
+    #[error("The reader authentication signature is invalid")]
+    InvalidSignature,
+    #[error(transparent)]
+    TrustAnchor(#[from] TrustAnchorError),
+}
+
+/// The verified identity of a reader, from the leaf certificate of its chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct ReaderIdentity {
+    pub common_name: Option<String>,
+    pub organization: Option<String>,
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+    /// The DER encoded leaf certificate.
+    pub certificate: Vec<u8>,
+}
+
+/// Verify the ReaderAuth of a request, and return the identity of the reader.
+///
+/// `reader_auth` is the CBOR encoded COSE_Sign1 of the DocRequest,
+/// `session_transcript` the CBOR encoded SessionTranscript of the session,
+/// and `items_request` the CBOR encoded ItemsRequestBytes of the DocRequest.
+#[uniffi::export(async_runtime = "tokio")]
+pub async fn verify_reader_authentication(
+    reader_auth: Vec<u8>,
+    session_transcript: Vec<u8>,
+    items_request: Vec<u8>,
+    trust_anchors: Arc<TrustAnchorStore>,
+) -> Result<ReaderIdentity, ReaderAuthError> {
+    let decode = |bytes: &[u8]| {
+        serde_cbor::from_slice::<Cbor>(bytes).map_err(|e| ReaderAuthError::Malformed {
+            value: format!("{e:?}"),
+        })
+    };
+
+    verify(
+        &decode(&reader_auth)?,
+        decode(&session_transcript)?,
+        decode(&items_request)?,
+        &trust_anchors.anchors(TrustAnchorPurpose::Reader)?,
+        trust_anchors.checks_revocation(),
+        SystemTime::now(),
+    )
+    .await
+}
+
+pub(crate) async fn verify(
+    reader_auth: &Cbor,
+    session_transcript: Cbor,
+    items_request: Cbor,
+    anchors: &[Certificate],
+    check_revocation: bool,
+    now: SystemTime,
+) -> Result<ReaderIdentity, ReaderAuthError> {
+    let reader_auth = match reader_auth {
+        Cbor::Tag(18, reader_auth) => reader_auth.as_ref(),
+        reader_auth => reader_auth,
+    };
+    let Cbor::Array(parts) = reader_auth else {
+        return Err(malformed("the reader authentication is not a COSE_Sign1"));
+    };
+    let [Cbor::Bytes(protected), Cbor::Map(unprotected), _, Cbor::Bytes(signature)] =
+        parts.as_slice()
+    else {
+        return Err(malformed("the reader authentication is not a COSE_Sign1"));
+    };
+
+    let protected_headers = match protected.is_empty() {
+        true => Cbor::Map(Default::default()),
+        false => serde_cbor::from_slice(protected).map_err(|e| malformed(&format!("{e:?}")))?,
+    };
+    let algorithm = header(&protected_headers, unprotected, ALG_LABEL);
+    if algorithm != Some(&Cbor::Integer(ES256)) {
+        return Err(ReaderAuthError::UnsupportedAlgorithm {
+            value: format!("{algorithm:?}"),
+        });
+    }
+
+    let chain = match header(&protected_headers, unprotected, X5CHAIN_LABEL) {
+        Some(Cbor::Bytes(certificate)) => vec![certificate.clone()],
+        Some(Cbor::Array(certificates)) => certificates
+            .iter()
+            .map(|certificate| match certificate {
+                Cbor::Bytes(certificate) => Ok(certificate.clone()),
+                _ => Err(malformed("the x5chain is not a list of certificates")),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err(malformed("the reader authentication has no x5chain")),
+    }
+    .iter()
+    .map(|der| Certificate::from_der(der).map_err(|e| malformed(&format!("{e:?}"))))
+    .collect::<Result<Vec<_>, _>>()?;
+    verify_chain(&chain, anchors, check_revocation, now).await?;
+    let leaf = &chain[0];
+
+    let reader_authentication = serde_cbor::to_vec(&Cbor::Array(vec![
+        Cbor::Text("ReaderAuthentication".into()),
+        session_transcript,
+        items_request,
+    ]))
+    .map_err(|e| malformed(&format!("{e:?}")))?;
+    let reader_authentication_bytes =
+        serde_cbor::to_vec(&Cbor::Tag(24, Box::new(Cbor::Bytes(reader_authentication))))
+            .map_err(|e| malformed(&format!("{e:?}")))?;
+    // The payload is detached, so the signature covers the
+    // ReaderAuthenticationBytes through the Sig_structure.
+    let sig_structure = serde_cbor::to_vec(&Cbor::Array(vec![
+        Cbor::Text("Signature1".into()),
+        Cbor::Bytes(protected.clone()),
+        Cbor::Bytes(vec![]),
+        Cbor::Bytes(reader_authentication_bytes),
+    ]))
+    .map_err(|e| malformed(&format!("{e:?}")))?;
+
+    let spki = leaf
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| malformed(&format!("{e:?}")))?;
+    let key = VerifyingKey::from_public_key_der(&spki).map_err(|e| {
+        ReaderAuthError::UnsupportedAlgorithm {
+            value: format!("{e:?}"),
+        }
+    })?;
+    let signature =
+        Signature::from_slice(signature).map_err(|_| ReaderAuthError::InvalidSignature)?;
+    key.verify(&sig_structure, &signature)
+        .map_err(|_| ReaderAuthError::InvalidSignature)?;
+
+    identity(leaf)
+}
+
+/// Return a header of the COSE_Sign1, from the protected headers first.
+pub(crate) fn header<'a>(
+    protected: &'a Cbor,
+    unprotected: &'a std::collections::BTreeMap<Cbor, Cbor>,
+    label: i128,
+) -> Option<&'a Cbor> {
+    let label = Cbor::Integer(label);
+    match protected {
+        Cbor::Map(protected) => protected.get(&label),
+        _ => None,
+    }
+    .or_else(|| unprotected.get(&label))
+}
+
+fn identity(certificate: &Certificate) -> Result<ReaderIdentity, ReaderAuthError> {
+    let tbs = &certificate.tbs_certificate;
+    Ok(ReaderIdentity {
+        common_name: attribute(&tbs.subject, COMMON_NAME),
+        organization: attribute(&tbs.subject, ORGANIZATION),
+        subject: tbs.subject.to_string(),
+        issuer: tbs.issuer.to_string(),
+        not_before: tbs.validity.not_before.to_system_time(),
+        not_after: tbs.validity.not_after.to_system_time(),
+        certificate: certificate
+            .to_der()
+            .map_err(|e| malformed(&format!("{e:?}")))?,
+    })
+}
+
+/// Return the first value of an attribute of a name, as text.
+fn attribute(name: &Name, oid: ObjectIdentifier) -> Option<String> {
+    name.0
+        .iter()
+        .flat_map(|rdn| rdn.0.iter())
+        .find(|attribute| attribute.oid == oid)
+        .and_then(|attribute| String::from_utf8(attribute.value.value().to_vec()).ok())
+}
+
+fn malformed(value: &str) -> ReaderAuthError {
+    ReaderAuthError::Malformed {
+        value: value.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use p256::ecdsa::{signature::Signer, SigningKey};
+
+    #[tokio::test]
+    async fn verifies_reader_authentication() {
+        let (_, der) =
+            pem_rfc7468::decode_vec(include_str!("../../tests/res/reader-cert.pem").as_bytes())
+                .unwrap();
+        let certificate = Certificate::from_der(&der).unwrap();
+        let key = SigningKey::from(
+            p256::SecretKey::from_sec1_pem(include_str!("../../tests/res/reader-key.pem")).unwrap(),
+        );
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+
+        let session_transcript =
+            Cbor::Array(vec![Cbor::Null, Cbor::Null, Cbor::Text("handover".into())]);
+        let items_request = Cbor::Tag(24, Box::new(Cbor::Bytes(b"items request".to_vec())));
+
+        let protected = serde_cbor::to_vec(&Cbor::Map(
+            [(Cbor::Integer(ALG_LABEL), Cbor::Integer(ES256))].into(),
+        ))
+        .unwrap();
+        let reader_authentication_bytes = serde_cbor::to_vec(&Cbor::Tag(
+            24,
+            Box::new(Cbor::Bytes(
+                serde_cbor::to_vec(&Cbor::Array(vec![
+                    Cbor::Text("ReaderAuthentication".into()),
+                    session_transcript.clone(),
+                    items_request.clone(),
+                ]))
+                .unwrap(),
+            )),
+        ))
+        .unwrap();
+        let sig_structure = serde_cbor::to_vec(&Cbor::Array(vec![
+            Cbor::Text("Signature1".into()),
+            Cbor::Bytes(protected.clone()),
+            Cbor::Bytes(vec![]),
+            Cbor::Bytes(reader_authentication_bytes),
+        ]))
+        .unwrap();
+        let signature: Signature = key.sign(&sig_structure);
+        let reader_auth = Cbor::Array(vec![
+            Cbor::Bytes(protected),
+            Cbor::Map([(Cbor::Integer(X5CHAIN_LABEL), Cbor::Bytes(der.clone()))].into()),
+            Cbor::Null,
+            Cbor::Bytes(signature.to_bytes().to_vec()),
+        ]);
+
+        let identity = verify(
+            &reader_auth,
+            session_transcript.clone(),
+            items_request.clone(),
+            &[certificate.clone()],
+            false,
+            now,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            identity.common_name.as_deref(),
+            Some("SpruceID Test Reader")
+        );
+        assert_eq!(
+            identity.organization.as_deref(),
+            Some("SpruceID Test Reader")
+        );
+        assert_eq!(identity.certificate, der);
+
+        // The signature is bound to the session.
+        assert!(matches!(
+            verify(
+                &reader_auth,
+                Cbor::Null,
+                items_request.clone(),
+                &[certificate],
+                false,
+                now
+            )
+            .await,
+            Err(ReaderAuthError::InvalidSignature)
+        ));
+        assert!(matches!(
+            verify(
+                &reader_auth,
+                session_transcript,
+                items_request,
+                &[],
+                false,
+                now
+            )
+            .await,
+            Err(ReaderAuthError::TrustAnchor(
+                TrustAnchorError::UntrustedChain(_)
+            ))
+        ));
+    }
+}
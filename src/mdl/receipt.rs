@@ -0,0 +1,325 @@
+//! Receipts of proximity presentations, so that the user has evidence of
+//! what a physical reader received.
+//!
+//! Once a response was sent to the reader, [MdlPresentationSession::generate_receipt]
+//! records the identity of the reader, the released elements along with
+//! whether the reader intends to retain them, and the hash of the response,
+//! in a JWS signed with the device key, and adds it to the presentation log.
+//!
+//! NOTE: the receipt is signed by the holder, not countersigned by the
+//! reader.
+
+use super::holder::{MdlPresentationSession, Release};
+use super::reader_auth::ReaderIdentity;
+use crate::common::*;
+use crate::oid4vp::verifier_review::{VerifierInfo, VerifierMetadata};
+use crate::presentation_log::{
+    PresentationLog, PresentationLogError, PresentationOutcome, PresentationRecord,
+    PresentedCredential,
+};
+use crate::signer::{self, DeviceSigner};
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// The `typ` of the JWS of receipts.
+const RECEIPT_TYP: &str = "mdoc-receipt+jwt";
+
+/// The JWS algorithms of the device keys receipts can be signed with.
+const SUPPORTED_ALGORITHMS: [&str; 4] = ["ES256", "ES384", "ES512", "EdDSA"];
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum ReceiptError {
+    #[error("No response was sent to the reader")]
+    NoResponse,
+    #[error("Unsupported device key algorithm: {value}")]
+    UnsupportedAlgorithm { value: String },
+    #[error("Failed to sign the receipt: {value}")]
+    Signing { value: String },
+    #[error(transparent)]
+    PresentationLog(#[from] PresentationLogError),
+}
+
+/// A data element released to a reader.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct ReleasedElement {
+    pub namespace: String,
+    pub identifier: String,
+    /// Whether the reader declared it intends to retain the element.
+    pub intent_to_retain: bool,
+}
+
+/// A signed receipt of a proximity presentation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct ProximityReceipt {
+    pub id: Uuid,
+    /// The reader, if its ReaderAuth was verified.
+    pub reader: Option<ReaderIdentity>,
+    pub credential_id: Uuid,
+    pub doc_type: String,
+    pub elements: Vec<ReleasedElement>,
+    /// When the response was sent to the reader.
+    pub timestamp: SystemTime,
+    /// The base64url encoded SHA-256 hash of the DeviceResponse.
+    pub response_hash: String,
+    /// The compact JWS of the receipt, signed with the device key.
+    pub jws: String,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl MdlPresentationSession {
+    /// Generate a receipt of the last response sent to the reader, signed
+    /// with the device key through the signer, and add it to the presentation
+    /// log, if any.
+    ///
+    /// The reader is the one whose ReaderAuth was verified by
+    /// [MdlPresentationSession::handle_request], if any.
+    pub async fn generate_receipt(
+        &self,
+        signer: Arc<dyn DeviceSigner>,
+        key_alias: KeyAlias,
+        presentation_log: Option<Arc<PresentationLog>>,
+    ) -> Result<ProximityReceipt, ReceiptError> {
+        let Release {
+            reader,
+            elements,
+            response,
+            sent_at: timestamp,
+        } = self.last_release().ok_or(ReceiptError::NoResponse)?;
+
+        let algorithm = signer
+            .algorithm(key_alias.clone())
+            .map_err(|e| ReceiptError::Signing {
+                value: format!("{e:?}"),
+            })?;
+        if !SUPPORTED_ALGORITHMS.contains(&algorithm.as_str()) {
+            return Err(ReceiptError::UnsupportedAlgorithm { value: algorithm });
+        }
+
+        let id = Uuid::new_v4();
+        let response_hash = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(response));
+        let issued_at = timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let claims = json!({
+            "jti": id,
+            "iat": issued_at,
+            "reader": reader.as_ref().map(|reader| json!({
+                "subject": reader.subject,
+                "x5t#S256": BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(&reader.certificate)),
+            })),
+            "credential_id": self.mdoc_id,
+            "doc_type": self.doc_type,
+            "elements": elements,
+            "response_hash": response_hash,
+        });
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD
+                .encode(json!({ "alg": algorithm, "typ": RECEIPT_TYP }).to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+        );
+        let payload = signing_input.clone().into_bytes();
+        let signature = signer::sign_raw(signer.as_ref(), &key_alias, payload)
+            .await
+            .map_err(|e| ReceiptError::Signing {
+                value: format!("{e:?}"),
+            })?;
+
+        let receipt = ProximityReceipt {
+            id,
+            reader,
+            credential_id: self.mdoc_id,
+            doc_type: self.doc_type.clone(),
+            elements,
+            timestamp,
+            response_hash,
+            jws: format!(
+                "{signing_input}.{}",
+                BASE64_URL_SAFE_NO_PAD.encode(signature)
+            ),
+        };
+
+        if let Some(log) = presentation_log {
+            log.add(PresentationRecord::from_receipt(&receipt))?;
+        }
+        Ok(receipt)
+    }
+}
+
+impl PresentationRecord {
+    /// Create a record of a proximity presentation from its receipt.
+    fn from_receipt(receipt: &ProximityReceipt) -> Self {
+        let verifier = match &receipt.reader {
+            Some(reader) => VerifierInfo {
+                client_id: reader.subject.clone(),
+                client_id_scheme: None,
+                response_uri: None,
+                client_id_verified: true,
+                client_metadata: VerifierMetadata {
+                    client_name: reader
+                        .organization
+                        .clone()
+                        .or_else(|| reader.common_name.clone()),
+                    ..Default::default()
+                },
+                key_change: None,
+            },
+            None => VerifierInfo {
+                client_id: String::new(),
+                client_id_scheme: None,
+                response_uri: None,
+                client_id_verified: false,
+                client_metadata: Default::default(),
+                key_change: None,
+            },
+        };
+
+        Self {
+            id: receipt.id,
+            verifier,
+            credentials: vec![PresentedCredential {
+                credential_id: receipt.credential_id,
+                credential_type: CredentialType(receipt.doc_type.clone()),
+                disclosed_fields: receipt
+                    .elements
+                    .iter()
+                    .map(|element| element.identifier.clone())
+                    .collect(),
+            }],
+            timestamp: receipt.timestamp,
+            outcome: PresentationOutcome::Submitted,
+            error: None,
+            receipt: Some(receipt.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{Credential, CredentialFormat};
+    use crate::local_store::LocalStore;
+    use crate::mdl::holder::initialize_mdl_presentation;
+    use crate::oid4vp::key_binding::tests::TestSigner;
+    use crate::vdc_collection::VdcCollection;
+
+    use p256::ecdsa::{
+        signature::{SignatureEncoding, Signer, Verifier},
+        Signature, SigningKey,
+    };
+
+    #[tokio::test]
+    async fn generates_proximity_receipts() {
+        let mdoc = Uuid::new_v4();
+        let storage = Arc::new(LocalStore::new());
+        VdcCollection::new(storage.clone())
+            .add(&Credential {
+                id: mdoc,
+                format: CredentialFormat::MsoMdoc,
+                r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+                payload: BASE64_STANDARD
+                    .decode(include_str!("../../tests/res/mdoc.b64"))
+                    .unwrap(),
+                key_alias: Some(KeyAlias("Testing".into())),
+                display: vec![],
+            })
+            .unwrap();
+        let device_key: SigningKey =
+            p256::SecretKey::from_sec1_pem(include_str!("../../tests/res/sec1.pem"))
+                .unwrap()
+                .into();
+        let receipt_signer = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let log = PresentationLog::new(storage.clone());
+
+        let session = initialize_mdl_presentation(mdoc, Uuid::new_v4(), storage).unwrap();
+        let generate = || {
+            session.generate_receipt(
+                Arc::new(TestSigner(receipt_signer.0.clone())),
+                KeyAlias("receipt".into()),
+                Some(log.clone()),
+            )
+        };
+        assert!(matches!(generate().await, Err(ReceiptError::NoResponse)));
+
+        let reader_session = crate::reader::establish_session(
+            session.qr_code_uri.clone(),
+            [(
+                "org.iso.18013.5.1".to_string(),
+                [
+                    ("given_name".to_string(), true),
+                    ("family_name".to_string(), false),
+                ]
+                .into_iter()
+                .collect(),
+            )]
+            .into_iter()
+            .collect(),
+            Some(vec![
+                include_str!("../../tests/res/root-cert.pem").to_string()
+            ]),
+        )
+        .unwrap();
+        session
+            .handle_request(reader_session.request)
+            .await
+            .unwrap();
+        let payload = session
+            .generate_response(
+                [(
+                    "org.iso.18013.5.1.mDL".to_string(),
+                    [(
+                        "org.iso.18013.5.1".to_string(),
+                        vec!["given_name".to_string(), "birth_date".to_string()],
+                    )]
+                    .into_iter()
+                    .collect(),
+                )]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap();
+        let signature: Signature = device_key.sign(&payload);
+        let response = session
+            .submit_response(signature.to_der().to_vec())
+            .unwrap();
+
+        let receipt = generate().await.unwrap();
+        assert_eq!(receipt.credential_id, mdoc);
+        // The reader did not authenticate its request.
+        assert_eq!(receipt.reader, None);
+        // Elements that were not requested are not released.
+        assert_eq!(
+            receipt.elements,
+            [ReleasedElement {
+                namespace: "org.iso.18013.5.1".into(),
+                identifier: "given_name".into(),
+                intent_to_retain: true,
+            }]
+        );
+        assert_eq!(
+            receipt.response_hash,
+            BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(&response))
+        );
+
+        let (signing_input, signature) = receipt.jws.rsplit_once('.').unwrap();
+        let signature =
+            Signature::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(signature).unwrap()).unwrap();
+        assert!(receipt_signer
+            .0
+            .verifying_key()
+            .verify(signing_input.as_bytes(), &signature)
+            .is_ok());
+
+        let record = log.get(receipt.id).unwrap().unwrap();
+        assert_eq!(record.receipt, Some(receipt));
+        assert_eq!(record.credentials[0].disclosed_fields, ["given_name"]);
+        assert!(!record.verifier.client_id_verified);
+    }
+}
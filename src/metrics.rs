@@ -0,0 +1,111 @@
+//! Metrics of the operations of the SDK, so that apps can monitor its
+//! performance in production without scraping logs.
+//!
+//! Each measured operation records its duration under its name, and
+//! increments the `<name>.success` or `<name>.failure` counter.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The validation of an authorization request, including the verification of
+/// its client ID.
+pub(crate) const REQUEST_VALIDATION: &str = "oid4vp.request_validation";
+/// The resolution of a DID document, whether or not it is cached.
+pub(crate) const DID_RESOLUTION: &str = "did.resolution";
+/// The matching of credentials against a presentation definition.
+pub(crate) const MATCHING: &str = "oid4vp.matching";
+/// The submission of a permission response to the verifier.
+pub(crate) const SUBMISSION: &str = "oid4vp.submission";
+
+/// Interface: MetricsSink
+///
+/// The MetricsSink receives named counters and duration measurements of the
+/// operations of the SDK, to forward them to a monitoring backend.
+#[uniffi::export(with_foreign)]
+pub trait MetricsSink: Send + Sync + std::fmt::Debug {
+    /// Increment the counter `name` by `value`.
+    fn counter(&self, name: String, value: u64);
+    /// Record a duration measurement of the operation `name`.
+    fn duration(&self, name: String, duration: Duration);
+}
+
+/// Run an operation, recording its duration and outcome in the sink, if one
+/// is set.
+pub(crate) async fn measure<T, E>(
+    sink: Option<Arc<dyn MetricsSink>>,
+    name: &str,
+    operation: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let Some(sink) = sink else {
+        return operation.await;
+    };
+
+    let started = Instant::now();
+    let result = operation.await;
+    sink.duration(name.into(), started.elapsed());
+
+    let outcome = match result {
+        Ok(_) => "success",
+        Err(_) => "failure",
+    };
+    sink.counter(format!("{name}.{outcome}"), 1);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct TestSink {
+        counters: Mutex<Vec<(String, u64)>>,
+        durations: Mutex<Vec<String>>,
+    }
+
+    impl MetricsSink for TestSink {
+        fn counter(&self, name: String, value: u64) {
+            self.counters.lock().unwrap().push((name, value));
+        }
+
+        fn duration(&self, name: String, _duration: Duration) {
+            self.durations.lock().unwrap().push(name);
+        }
+    }
+
+    #[tokio::test]
+    async fn measures_operations() {
+        assert_eq!(
+            measure(None, MATCHING, async { Ok::<_, ()>(1) }).await,
+            Ok(1)
+        );
+
+        let sink = Arc::new(TestSink::default());
+        let metrics: Arc<dyn MetricsSink> = sink.clone();
+        assert_eq!(
+            measure(Some(metrics.clone()), MATCHING, async { Ok::<_, ()>(1) }).await,
+            Ok(1)
+        );
+        assert_eq!(
+            measure(Some(metrics.clone()), SUBMISSION, async {
+                Err::<(), _>(())
+            })
+            .await,
+            Err(())
+        );
+
+        assert_eq!(
+            *sink.counters.lock().unwrap(),
+            vec![
+                ("oid4vp.matching.success".to_string(), 1),
+                ("oid4vp.submission.failure".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            *sink.durations.lock().unwrap(),
+            vec![MATCHING.to_string(), SUBMISSION.to_string()]
+        );
+    }
+}
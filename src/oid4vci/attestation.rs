@@ -0,0 +1,283 @@
+//! Attestation-based client authentication, with which issuers following
+//! HAIP authenticate wallets at their token endpoint.
+//!
+//! The token request carries the wallet attestation, issued by the wallet
+//! provider, and a proof of possession of the key it attests, signed by the
+//! device.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use base64::prelude::*;
+use oid4vci::oauth2::http::{Method, Request, Uri};
+use serde_json::{json, Value as Json};
+use uuid::Uuid;
+
+use super::{
+    AsyncHttpClient, HttpClientError, HttpRequest, HttpResponse, IHttpClient, Oid4vciError,
+};
+use crate::common::KeyAlias;
+use crate::signer::{self, DeviceSigner};
+
+/// The header carrying the wallet attestation.
+const ATTESTATION_HEADER: &str = "OAuth-Client-Attestation";
+/// The header carrying the proof of possession of the attested key.
+const ATTESTATION_POP_HEADER: &str = "OAuth-Client-Attestation-PoP";
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum WalletAttestationError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
+    #[error("The wallet attestation is unavailable: {0}")]
+    Unavailable(String),
+}
+
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for WalletAttestationError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        WalletAttestationError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+/// Interface: WalletAttestationProvider
+///
+/// The WalletAttestationProvider returns the wallet attestation JWT issued by
+/// the wallet provider, e.g. fetching a fresh one when the cached attestation
+/// has expired.
+#[uniffi::export(with_foreign)]
+#[async_trait]
+pub trait WalletAttestationProvider: Send + Sync {
+    async fn wallet_attestation(&self) -> Result<String, WalletAttestationError>;
+}
+
+/// The wallet attestation to authenticate token requests with, along with
+/// the device key it attests.
+#[derive(uniffi::Object)]
+pub struct WalletAttestation {
+    provider: Arc<dyn WalletAttestationProvider>,
+    signer: Arc<dyn DeviceSigner>,
+    key_alias: KeyAlias,
+}
+
+#[uniffi::export]
+impl WalletAttestation {
+    /// The proofs of possession are signed by the signer, with the key of the
+    /// alias, which must be the key in the `cnf` claim of the attestations.
+    #[uniffi::constructor]
+    pub fn new(
+        provider: Arc<dyn WalletAttestationProvider>,
+        signer: Arc<dyn DeviceSigner>,
+        key_alias: KeyAlias,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            provider,
+            signer,
+            key_alias,
+        })
+    }
+}
+
+impl WalletAttestation {
+    /// Return the client authentication headers of a token request to an
+    /// authorization server, identified by its metadata.
+    ///
+    /// The challenge of the proof of possession is retrieved from the
+    /// challenge endpoint of the authorization server, if it has one.
+    pub(crate) async fn headers_for(
+        &self,
+        authorization_server: &Json,
+        http_client: &IHttpClient,
+    ) -> Result<HashMap<String, String>, Oid4vciError> {
+        let audience = authorization_server["issuer"].as_str().ok_or_else(|| {
+            Oid4vciError::InvalidSession("authorization server issuer unset".into())
+        })?;
+
+        let challenge = match authorization_server["challenge_endpoint"].as_str() {
+            Some(endpoint) => Some(fetch_challenge(endpoint, http_client).await?),
+            None => None,
+        };
+
+        self.headers(audience, challenge).await
+    }
+
+    async fn headers(
+        &self,
+        audience: &str,
+        challenge: Option<String>,
+    ) -> Result<HashMap<String, String>, Oid4vciError> {
+        let attestation = self
+            .provider
+            .wallet_attestation()
+            .await
+            .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?;
+
+        // The attestation is issued to the client, which it identifies.
+        let client_id = attestation
+            .split('.')
+            .nth(1)
+            .and_then(|payload| BASE64_URL_SAFE_NO_PAD.decode(payload).ok())
+            .and_then(|payload| serde_json::from_slice::<Json>(&payload).ok())
+            .and_then(|payload| payload["sub"].as_str().map(ToOwned::to_owned))
+            .ok_or_else(|| {
+                Oid4vciError::InvalidParameter("invalid wallet attestation: missing sub".into())
+            })?;
+
+        let signer_error = |e: signer::DeviceSignerError| Oid4vciError::Generic(format!("{e:?}"));
+        let header = json!({
+            "typ": "oauth-client-attestation-pop+jwt",
+            "alg": self.signer.algorithm(self.key_alias.clone()).map_err(signer_error)?,
+        });
+        let issued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let mut claims = json!({
+            "iss": client_id,
+            "aud": audience,
+            "jti": Uuid::new_v4().to_string(),
+            "iat": issued_at,
+        });
+        if let Some(challenge) = challenge {
+            claims["challenge"] = json!(challenge);
+        }
+
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+        );
+        let signature = signer::sign_raw(
+            self.signer.as_ref(),
+            &self.key_alias,
+            signing_input.as_bytes().to_vec(),
+        )
+        .await
+        .map_err(signer_error)?;
+        let pop = format!(
+            "{signing_input}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(signature)
+        );
+
+        Ok(HashMap::from([
+            (ATTESTATION_HEADER.to_string(), attestation),
+            (ATTESTATION_POP_HEADER.to_string(), pop),
+        ]))
+    }
+}
+
+/// Retrieve a fresh challenge from the challenge endpoint of an authorization
+/// server.
+async fn fetch_challenge(
+    endpoint: &str,
+    http_client: &IHttpClient,
+) -> Result<String, Oid4vciError> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(Uri::from_str(endpoint).map_err(|_| HttpClientError::UrlParse)?)
+        .body(vec![])
+        .map_err(|_| HttpClientError::RequestBuilder)?;
+
+    let response = http_client.call(request).await?;
+    if !response.status().is_success() {
+        return Err(Oid4vciError::RequestError(format!(
+            "failed to retrieve the attestation challenge: {}",
+            response.status()
+        )));
+    }
+
+    serde_json::from_slice::<Json>(response.body())?["attestation_challenge"]
+        .as_str()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| {
+            Oid4vciError::RequestError("the challenge response has no attestation_challenge".into())
+        })
+}
+
+/// An HTTP client adding headers to every request, e.g. the client
+/// authentication headers of a token request.
+pub(crate) struct HeaderHttpClient {
+    pub inner: Arc<IHttpClient>,
+    pub headers: HashMap<String, String>,
+}
+
+#[async_trait]
+impl AsyncHttpClient for HeaderHttpClient {
+    async fn http_client(&self, mut request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+        request.headers.extend(self.headers.clone());
+        self.inner.call(request.try_into()?).await?.try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oid4vp::key_binding::tests::TestSigner;
+
+    use p256::ecdsa::{signature::Verifier, Signature, SigningKey};
+
+    struct TestProvider(String);
+
+    #[async_trait]
+    impl WalletAttestationProvider for TestProvider {
+        async fn wallet_attestation(&self) -> Result<String, WalletAttestationError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_attestation_proofs() {
+        let signer = Arc::new(TestSigner(SigningKey::from_slice(&[1; 32]).unwrap()));
+        let attestation = format!(
+            "eyJhbGciOiJFUzI1NiJ9.{}.c2ln",
+            BASE64_URL_SAFE_NO_PAD.encode(
+                json!({ "sub": "wallet-client", "cnf": { "jwk": signer.jwk() } }).to_string()
+            )
+        );
+        let wallet_attestation = WalletAttestation::new(
+            Arc::new(TestProvider(attestation.clone())),
+            signer.clone(),
+            KeyAlias("key".into()),
+        );
+
+        let headers = wallet_attestation
+            .headers("https://issuer.example.com", Some("challenge".into()))
+            .await
+            .unwrap();
+        assert_eq!(headers[ATTESTATION_HEADER], attestation);
+
+        let (signing_input, signature) = headers[ATTESTATION_POP_HEADER].rsplit_once('.').unwrap();
+        let signature =
+            Signature::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(signature).unwrap()).unwrap();
+        assert!(signer
+            .0
+            .verifying_key()
+            .verify(signing_input.as_bytes(), &signature)
+            .is_ok());
+
+        let claims: Json = serde_json::from_slice(
+            &BASE64_URL_SAFE_NO_PAD
+                .decode(signing_input.split('.').nth(1).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(claims["iss"], "wallet-client");
+        assert_eq!(claims["aud"], "https://issuer.example.com");
+        assert_eq!(claims["challenge"], "challenge");
+
+        // Attestations must identify the client.
+        let wallet_attestation = WalletAttestation::new(
+            Arc::new(TestProvider("e30.e30.c2ln".into())),
+            signer,
+            KeyAlias("key".into()),
+        );
+        assert!(wallet_attestation
+            .headers("https://issuer.example.com", None)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! Deferred issuance, when the issuer cannot issue a credential immediately
+//! and returns a transaction ID to poll with later.
+
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use oid4vci::oauth2::http::{header, Method, Request, Uri};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as Json};
+use ssi::{
+    claims::vc::v1::data_integrity::any_credential_from_json_str,
+    dids::{AnyDidMethod, DIDResolver, VerificationMethodDIDResolver},
+    prelude::{AnyDataIntegrity, AnyJsonCredential, AnyMethod, JwsString, VerificationParameters},
+};
+
+use super::{
+    context_loader_from_map, CredentialResponse, HttpClientError, IHttpClient, Oid4vciError,
+};
+use crate::credential::{display::CredentialDisplay, CredentialFormat};
+
+/// The error of issuers that have not issued the credential yet.
+const ISSUANCE_PENDING: &str = "issuance_pending";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DeferredIssuanceState {
+    transaction_id: String,
+    deferred_credential_endpoint: String,
+    access_token: String,
+    #[serde(default)]
+    notification_endpoint: Option<String>,
+    /// The format of the requested credential, e.g. `jwt_vc_json`.
+    format: String,
+    display: Vec<CredentialDisplay>,
+}
+
+/// A credential the issuer will issue later.
+///
+/// The handle can be serialized, so that the app can persist it and poll the
+/// issuer again after a restart.
+#[derive(Debug, uniffi::Object)]
+pub struct DeferredIssuance(DeferredIssuanceState);
+
+/// The outcome of polling a deferred issuance.
+#[derive(uniffi::Enum)]
+pub enum DeferredIssuanceStatus {
+    /// The credential is not issued yet. Poll again after the interval, in
+    /// seconds, if the issuer gave one.
+    Pending { interval: Option<u64> },
+    /// The credential is issued.
+    Issued {
+        credentials: Vec<CredentialResponse>,
+    },
+}
+
+#[uniffi::export]
+impl DeferredIssuance {
+    /// Restore a deferred issuance from its serialized form.
+    #[uniffi::constructor]
+    pub fn from_serialized(serialized: String) -> Result<Arc<Self>, Oid4vciError> {
+        Ok(Arc::new(Self(serde_json::from_str(&serialized)?)))
+    }
+
+    /// Serialize the deferred issuance, e.g. to persist it.
+    ///
+    /// The serialized form contains the access token of the issuance, and
+    /// must be stored securely.
+    pub fn serialize(&self) -> Result<String, Oid4vciError> {
+        Ok(serde_json::to_string(&self.0)?)
+    }
+
+    pub fn transaction_id(&self) -> String {
+        self.0.transaction_id.clone()
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl DeferredIssuance {
+    /// Ask the issuer for the credential, verifying it once issued.
+    pub async fn poll(
+        &self,
+        context_map: Option<HashMap<String, String>>,
+        http_client: Arc<IHttpClient>,
+    ) -> Result<DeferredIssuanceStatus, Oid4vciError> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(
+                Uri::from_str(&self.0.deferred_credential_endpoint)
+                    .map_err(|_| HttpClientError::UrlParse)?,
+            )
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {}", self.0.access_token),
+            )
+            .body(
+                json!({ "transaction_id": self.0.transaction_id })
+                    .to_string()
+                    .into_bytes(),
+            )
+            .map_err(|_| HttpClientError::RequestBuilder)?;
+
+        let response = http_client.call(request).await?;
+        let body: Json = serde_json::from_slice(response.body())?;
+
+        if !response.status().is_success() {
+            if body["error"] == ISSUANCE_PENDING {
+                return Ok(DeferredIssuanceStatus::Pending {
+                    interval: body["interval"].as_u64(),
+                });
+            }
+            return Err(Oid4vciError::RequestError(format!(
+                "deferred credential request failed: {} {body}",
+                response.status()
+            )));
+        }
+
+        let credentials = credentials_of(&body)?;
+
+        let notification = self
+            .0
+            .notification_endpoint
+            .as_ref()
+            .zip(body["notification_id"].as_str())
+            .map(
+                |(notification_endpoint, notification_id)| CredentialNotification {
+                    notification_endpoint: notification_endpoint.clone(),
+                    notification_id: notification_id.to_owned(),
+                    access_token: self.0.access_token.clone(),
+                },
+            );
+
+        let credentials = verify_credentials(&self.0.format, credentials, context_map)
+            .await?
+            .into_iter()
+            .map(|(format, payload)| CredentialResponse {
+                format,
+                payload,
+                display: self.0.display.clone(),
+                batch_id: None,
+                notification: notification.clone(),
+                refresh: None,
+            })
+            .collect();
+
+        Ok(DeferredIssuanceStatus::Issued { credentials })
+    }
+}
+impl DeferredIssuance {
+    pub(crate) fn new(
+        transaction_id: String,
+        deferred_credential_endpoint: String,
+        access_token: String,
+        notification_endpoint: Option<String>,
+        format: String,
+        display: Vec<CredentialDisplay>,
+    ) -> Self {
+        Self(DeferredIssuanceState {
+            transaction_id,
+            deferred_credential_endpoint,
+            access_token,
+            notification_endpoint,
+            format,
+            display,
+        })
+    }
+}
+
+/// Return the credentials of a credential response, holding either a single
+/// credential, or credential objects.
+pub(super) fn credentials_of(body: &Json) -> Result<Vec<Json>, Oid4vciError> {
+    match (&body["credential"], &body["credentials"]) {
+        (Json::Null, Json::Array(credentials)) => Ok(credentials
+            .iter()
+            .map(|credential| credential.get("credential").unwrap_or(credential).clone())
+            .collect()),
+        (Json::Null, _) => Err(Oid4vciError::RequestError(
+            "the credential response has no credential".into(),
+        )),
+        (credential, _) => Ok(vec![credential.clone()]),
+    }
+}
+
+/// Verify credentials issued in a format, e.g. `jwt_vc_json`, returning their
+/// payload.
+pub(super) async fn verify_credentials(
+    format: &str,
+    credentials: Vec<Json>,
+    context_map: Option<HashMap<String, String>>,
+) -> Result<Vec<(CredentialFormat, Vec<u8>)>, Oid4vciError> {
+    let vm_resolver: VerificationMethodDIDResolver<AnyDidMethod, AnyMethod> =
+        AnyDidMethod::default().into_vm_resolver();
+    let params = match context_map {
+        Some(context_map) => VerificationParameters::from_resolver(vm_resolver)
+            .with_json_ld_loader(context_loader_from_map(context_map)?),
+        None => VerificationParameters::from_resolver(vm_resolver),
+    };
+
+    let mut verified = vec![];
+    for credential in credentials {
+        verified.push(match (format, credential) {
+            ("jwt_vc_json", Json::String(jwt)) => {
+                JwsString::from_string(jwt.clone())
+                    .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?
+                    .verify(&params)
+                    .await?
+                    .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?;
+                (CredentialFormat::JwtVcJson, jwt.into_bytes())
+            }
+            ("jwt_vc_json-ld", credential) => {
+                let vc = serde_json::to_string(&credential)?;
+                any_credential_from_json_str(&vc)?
+                    .verify(&params)
+                    .await?
+                    .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?;
+                (CredentialFormat::JwtVcJsonLd, vc.into_bytes())
+            }
+            ("ldp_vc", credential) => {
+                let vc: AnyDataIntegrity<AnyJsonCredential> = serde_json::from_value(credential)?;
+                vc.verify(&params)
+                    .await?
+                    .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?;
+                (CredentialFormat::LdpVc, serde_json::to_vec(&vc)?)
+            }
+            (format, _) => {
+                return Err(Oid4vciError::Generic(format!(
+                    "unsupported credential format: {format}"
+                )))
+            }
+        });
+    }
+
+    Ok(verified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survives_serialization() {
+        let deferred = DeferredIssuance::new(
+            "8xLOxBtZp8".into(),
+            "https://issuer.example.com/deferred_credential".into(),
+            "czZCaGRSa3F0MzpnWDFmQmF0M2JW".into(),
+            None,
+            "jwt_vc_json".into(),
+            vec![],
+        );
+
+        let restored = DeferredIssuance::from_serialized(deferred.serialize().unwrap()).unwrap();
+        assert_eq!(restored.transaction_id(), "8xLOxBtZp8");
+        assert_eq!(
+            restored.0.deferred_credential_endpoint,
+            "https://issuer.example.com/deferred_credential"
+        );
+        assert!(DeferredIssuance::from_serialized("{}".into()).is_err());
+    }
+}
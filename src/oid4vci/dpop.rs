@@ -0,0 +1,304 @@
+//! DPoP (RFC 9449) proofs, with which issuers sender-constrain their access
+//! tokens to a key held by the device.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use base64::prelude::*;
+use serde_json::{json, Value as Json};
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+
+use super::{AsyncHttpClient, HttpClientError, HttpRequest, HttpResponse, IHttpClient};
+use crate::common::KeyAlias;
+use crate::signer::{self, DeviceSigner};
+
+/// The error servers respond with when proofs must include their nonce.
+const USE_DPOP_NONCE: &str = "use_dpop_nonce";
+
+/// DPoP proofs of possession of a device key, for the token and credential
+/// requests of an issuance.
+///
+/// The nonces servers provide are remembered by origin, for the proofs of
+/// the following requests.
+#[derive(uniffi::Object)]
+pub struct Dpop {
+    signer: Arc<dyn DeviceSigner>,
+    key_alias: KeyAlias,
+    nonces: Mutex<HashMap<String, String>>,
+}
+
+#[uniffi::export]
+impl Dpop {
+    /// The proofs are signed by the signer, with the key of the alias.
+    #[uniffi::constructor]
+    pub fn new(signer: Arc<dyn DeviceSigner>, key_alias: KeyAlias) -> Arc<Self> {
+        Arc::new(Self {
+            signer,
+            key_alias,
+            nonces: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Return an HTTP client adding DPoP proofs to the requests of the given
+    /// client, e.g. for the `oid4vci_exchange_*` functions.
+    pub fn http_client(self: Arc<Self>, client: Arc<IHttpClient>) -> Arc<IHttpClient> {
+        let client: Arc<dyn AsyncHttpClient> = Arc::new(DpopHttpClient {
+            inner: client,
+            dpop: self,
+        });
+        Arc::new(client.into())
+    }
+}
+
+impl Dpop {
+    /// Return a proof for a request, bound to its access token, if any.
+    async fn proof(
+        &self,
+        method: &str,
+        url: &Url,
+        access_token: Option<&str>,
+    ) -> Result<String, HttpClientError> {
+        let signer_error = |e: signer::DeviceSignerError| HttpClientError::Other {
+            error: format!("{e:?}"),
+        };
+
+        let jwk: Json = serde_json::from_str(
+            &self
+                .signer
+                .jwk(self.key_alias.clone())
+                .map_err(signer_error)?,
+        )
+        .map_err(|e| HttpClientError::Other {
+            error: format!("{e:?}"),
+        })?;
+        let header = json!({
+            "typ": "dpop+jwt",
+            "alg": self.signer.algorithm(self.key_alias.clone()).map_err(signer_error)?,
+            "jwk": jwk,
+        });
+
+        let mut target = url.clone();
+        target.set_query(None);
+        target.set_fragment(None);
+        let issued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let mut claims = json!({
+            "jti": Uuid::new_v4().to_string(),
+            "htm": method,
+            "htu": target.to_string(),
+            "iat": issued_at,
+        });
+        if let Some(nonce) = self.nonce(url) {
+            claims["nonce"] = json!(nonce);
+        }
+        if let Some(access_token) = access_token {
+            claims["ath"] = json!(BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(access_token)));
+        }
+
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+        );
+        let signature = signer::sign_raw(
+            self.signer.as_ref(),
+            &self.key_alias,
+            signing_input.as_bytes().to_vec(),
+        )
+        .await
+        .map_err(signer_error)?;
+
+        Ok(format!(
+            "{signing_input}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(signature)
+        ))
+    }
+
+    fn nonce(&self, url: &Url) -> Option<String> {
+        self.nonces
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&url.origin().ascii_serialization())
+            .cloned()
+    }
+
+    fn set_nonce(&self, url: &Url, nonce: String) {
+        self.nonces
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(url.origin().ascii_serialization(), nonce);
+    }
+}
+
+/// An HTTP client adding DPoP proofs to every request.
+///
+/// Bearer access tokens are sent as DPoP access tokens, and requests rejected
+/// for a missing nonce are retried once with the nonce the server provides.
+struct DpopHttpClient {
+    inner: Arc<IHttpClient>,
+    dpop: Arc<Dpop>,
+}
+
+impl DpopHttpClient {
+    async fn send(
+        &self,
+        mut request: HttpRequest,
+        url: &Url,
+    ) -> Result<HttpResponse, HttpClientError> {
+        let authorization = header(&request.headers, "authorization").map(ToOwned::to_owned);
+        let access_token = authorization.as_deref().and_then(|authorization| {
+            authorization
+                .strip_prefix("Bearer ")
+                .or_else(|| authorization.strip_prefix("DPoP "))
+        });
+
+        if let Some(access_token) = access_token {
+            request
+                .headers
+                .retain(|key, _| !key.eq_ignore_ascii_case("authorization"));
+            request
+                .headers
+                .insert("Authorization".into(), format!("DPoP {access_token}"));
+        }
+        let proof = self.dpop.proof(&request.method, url, access_token).await?;
+        request.headers.insert("DPoP".into(), proof);
+
+        let response: HttpResponse = self.inner.call(request.try_into()?).await?.try_into()?;
+        if let Some(nonce) = header(&response.headers, "dpop-nonce") {
+            self.dpop.set_nonce(url, nonce.to_owned());
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl AsyncHttpClient for DpopHttpClient {
+    async fn http_client(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+        let url = Url::parse(&request.url).map_err(|_| HttpClientError::UrlParse)?;
+
+        let response = self.send(request.clone(), &url).await?;
+        if requires_nonce(&response) && header(&response.headers, "dpop-nonce").is_some() {
+            return self.send(request, &url).await;
+        }
+        Ok(response)
+    }
+}
+
+/// Check whether a response rejects a request for its missing or stale
+/// nonce, from the authorization server or from the resource server.
+fn requires_nonce(response: &HttpResponse) -> bool {
+    match response.status_code {
+        400 => serde_json::from_slice::<Json>(&response.body)
+            .is_ok_and(|body| body["error"] == USE_DPOP_NONCE),
+        401 => header(&response.headers, "www-authenticate")
+            .is_some_and(|challenge| challenge.contains(USE_DPOP_NONCE)),
+        _ => false,
+    }
+}
+
+/// Return the value of a header, whatever the case of its name.
+fn header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oid4vp::key_binding::tests::TestSigner;
+
+    use p256::ecdsa::SigningKey;
+
+    /// A token endpoint requiring a nonce, recording the proofs it receives.
+    #[derive(Default)]
+    struct TestServer {
+        proofs: Mutex<Vec<Json>>,
+    }
+
+    #[async_trait]
+    impl AsyncHttpClient for TestServer {
+        async fn http_client(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+            let proof = &request.headers["DPoP"];
+            let claims: Json = serde_json::from_slice(
+                &BASE64_URL_SAFE_NO_PAD
+                    .decode(proof.split('.').nth(1).unwrap())
+                    .unwrap(),
+            )
+            .unwrap();
+            let mut proofs = self.proofs.lock().unwrap();
+            proofs.push(claims.clone());
+
+            let (status_code, body) = match claims.get("nonce") {
+                Some(_) => (
+                    200,
+                    json!({ "access_token": "token", "token_type": "DPoP" }),
+                ),
+                None => (400, json!({ "error": USE_DPOP_NONCE })),
+            };
+            Ok(HttpResponse {
+                status_code,
+                headers: HashMap::from([("dpop-nonce".into(), format!("nonce-{}", proofs.len()))]),
+                body: body.to_string().into_bytes(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_with_server_nonces() {
+        let server = Arc::new(TestServer::default());
+        let inner: Arc<dyn AsyncHttpClient> = server.clone();
+        let dpop = Dpop::new(
+            Arc::new(TestSigner(SigningKey::from_slice(&[1; 32]).unwrap())),
+            KeyAlias("key".into()),
+        );
+        let client = dpop.http_client(Arc::new(inner.into()));
+
+        let request = HttpRequest {
+            url: "https://issuer.example.com/token?ignored=1".into(),
+            method: "POST".into(),
+            headers: HashMap::new(),
+            body: vec![],
+        };
+        let response = client.call(request.try_into().unwrap()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+
+        let proofs = server.proofs.lock().unwrap();
+        assert_eq!(proofs.len(), 2);
+        assert_eq!(proofs[0]["htm"], "POST");
+        assert_eq!(proofs[0]["htu"], "https://issuer.example.com/token");
+        assert_eq!(proofs[1]["nonce"], "nonce-1");
+        assert_ne!(proofs[0]["jti"], proofs[1]["jti"]);
+    }
+
+    #[test]
+    fn detects_nonce_challenges() {
+        let response = |status_code, headers: &[(&str, &str)]| HttpResponse {
+            status_code,
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            body: vec![],
+        };
+
+        assert!(requires_nonce(&response(
+            401,
+            &[("WWW-Authenticate", r#"DPoP error="use_dpop_nonce""#)]
+        )));
+        assert!(!requires_nonce(&response(
+            401,
+            &[("WWW-Authenticate", r#"DPoP error="invalid_token""#)]
+        )));
+        assert!(!requires_nonce(&response(200, &[])));
+    }
+}
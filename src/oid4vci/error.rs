@@ -4,6 +4,7 @@ use ssi::{
 };
 
 use crate::did::DidError;
+use crate::vdc_collection::VdcCollectionError;
 
 use super::HttpClientError;
 
@@ -43,6 +44,9 @@ pub enum Oid4vciError {
     #[error("{_0}")]
     ContextMapError(#[from] FromContextMapError),
 
+    #[error("Issuance refused from the issuer: {_0}")]
+    UntrustedIssuer(String),
+
     #[error("{_0}")]
     Generic(String),
 }
@@ -95,3 +99,9 @@ impl From<HttpClientError> for Oid4vciError {
         Oid4vciError::RequestError(value.to_string())
     }
 }
+
+impl From<VdcCollectionError> for Oid4vciError {
+    fn from(value: VdcCollectionError) -> Self {
+        Oid4vciError::Generic(value.to_string())
+    }
+}
@@ -1,4 +1,6 @@
-use std::{collections::HashMap, future::Future, pin::Pin, str::FromStr, sync::Arc};
+use std::{
+    collections::HashMap, future::Future, pin::Pin, str::FromStr, sync::Arc, time::Duration,
+};
 
 use async_trait::async_trait;
 use either::Either;
@@ -7,6 +9,9 @@ use oid4vci::oauth2::{
     AsyncHttpClient as ExtAsyncHttpClient, HttpRequest as ExtHttpRequest,
     HttpResponse as ExtHttpResponse, SyncHttpClient as ExtSyncHttpClient,
 };
+use uniffi::deps::anyhow;
+
+use super::pinning::{pin_mismatch_host, TlsPinningConfig};
 
 #[derive(thiserror::Error, uniffi::Error, Debug)]
 pub enum HttpClientError {
@@ -34,10 +39,38 @@ pub enum HttpClientError {
     #[error("failed to parse header entry: ({key}, {value})")]
     HeaderEntryParse { key: String, value: String },
 
+    #[error("certificate of {host} does not match its pins")]
+    CertificatePinning { host: String },
+
     #[error("other error: {error}")]
     Other { error: String },
 }
 
+impl HttpClientError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::RequestBuilder => "http_client.request_builder",
+            Self::ResponseBuilder => "http_client.response_builder",
+            Self::UrlParse => "http_client.url_parse",
+            Self::MethodParse => "http_client.method_parse",
+            Self::HeaderParse => "http_client.header_parse",
+            Self::HeaderKeyParse { .. } => "http_client.header_key_parse",
+            Self::HeaderValueParse { .. } => "http_client.header_value_parse",
+            Self::HeaderEntryParse { .. } => "http_client.header_entry_parse",
+            Self::CertificatePinning { .. } => "http_client.certificate_pinning",
+            Self::Other { .. } => "http_client.other",
+        }
+    }
+}
+
+/// Return the stable, machine-readable code of the error, e.g.
+/// `http_client.certificate_pinning`.
+#[uniffi::export]
+pub fn http_client_error_code(error: HttpClientError) -> String {
+    error.code().to_string()
+}
+
 impl From<String> for HttpClientError {
     fn from(value: String) -> Self {
         Self::Other { error: value }
@@ -165,6 +198,170 @@ impl<'a, 'c> ExtAsyncHttpClient<'c> for IArc<dyn AsyncHttpClient + 'a> {
     }
 }
 
+/// Configuration of the built-in HTTP client.
+#[derive(uniffi::Record, Clone, Debug, Default)]
+pub struct HttpClientConfig {
+    /// Timeout for establishing a connection.
+    pub connect_timeout: Option<Duration>,
+    /// Timeout for a whole request, from connecting until the response body
+    /// has been read.
+    pub request_timeout: Option<Duration>,
+    /// URL of a proxy every request is sent through, e.g.
+    /// `http://proxy.example.com:8080`.
+    pub proxy_url: Option<String>,
+    /// Headers added to every request.
+    pub headers: HashMap<String, String>,
+    /// The `User-Agent` header of every request.
+    pub user_agent: Option<String>,
+    /// Certificate pinning, and the root certificates trusted.
+    pub tls_pinning: Option<TlsPinningConfig>,
+}
+
+#[derive(Clone, Debug)]
+/// Built-in HTTP client, backed by `reqwest`.
+///
+/// On `wasm32`, `reqwest` sends requests with the fetch API of the browser,
+/// which applies its own timeouts, so only the headers of the configuration
+/// are used.
+pub struct ReqwestHttpClient(reqwest::Client);
+
+impl ReqwestHttpClient {
+    pub fn new(config: &HttpClientConfig) -> Result<Self, HttpClientError> {
+        let mut builder = reqwest::Client::builder();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(timeout) = config.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            if let Some(timeout) = config.request_timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(proxy_url) = &config.proxy_url {
+                let proxy = reqwest::Proxy::all(proxy_url).map_err(|e| HttpClientError::Other {
+                    error: format!("invalid proxy url: {e:?}"),
+                })?;
+                builder = builder.proxy(proxy);
+            }
+            if let Some(user_agent) = &config.user_agent {
+                builder = builder.user_agent(user_agent);
+            }
+            if let Some(tls_pinning) = &config.tls_pinning {
+                builder = builder.use_preconfigured_tls(tls_pinning.client_config()?);
+            }
+        }
+        // The browser connects, and picks the proxies and trusted roots.
+        #[cfg(target_arch = "wasm32")]
+        if config.tls_pinning.is_some() {
+            return Err(HttpClientError::Other {
+                error: "certificate pinning is not supported by the fetch API".into(),
+            });
+        }
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (key, value) in &config.headers {
+            headers.insert(
+                reqwest::header::HeaderName::from_str(key)
+                    .map_err(|_| HttpClientError::HeaderKeyParse { key: key.clone() })?,
+                reqwest::header::HeaderValue::from_str(value).map_err(|_| {
+                    HttpClientError::HeaderValueParse {
+                        value: value.clone(),
+                    }
+                })?,
+            );
+        }
+
+        builder
+            .default_headers(headers)
+            .build()
+            .map(Self)
+            .map_err(|e| HttpClientError::Other {
+                error: format!("{e:?}"),
+            })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn send(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+        self.execute(request).await
+    }
+
+    /// Send the request from the browser task queue, as fetch futures are
+    /// not `Send`.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) async fn send(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        let client = self.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = sender.send(client.execute(request).await);
+        });
+        receiver.await.map_err(|_| HttpClientError::Other {
+            error: "the fetch request was dropped".into(),
+        })?
+    }
+
+    async fn execute(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+        let method =
+            reqwest::Method::from_str(&request.method).map_err(|_| HttpClientError::MethodParse)?;
+        let url = reqwest::Url::parse(&request.url).map_err(|_| HttpClientError::UrlParse)?;
+
+        let mut builder = self.0.request(method, url).body(request.body);
+        for (key, value) in request.headers {
+            builder = builder.header(key, value);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| match pin_mismatch_host(&e) {
+                Some(host) => HttpClientError::CertificatePinning { host },
+                None => HttpClientError::Other {
+                    error: format!("{e:?}"),
+                },
+            })?;
+
+        let status_code = response.status().as_u16();
+        let mut headers: HashMap<String, String> = HashMap::new();
+        for (key, value) in response.headers() {
+            let value = value.to_str().map_err(|_| HttpClientError::HeaderParse)?;
+            headers
+                .entry(key.to_string())
+                .and_modify(|values| {
+                    values.push(',');
+                    values.push_str(value);
+                })
+                .or_insert_with(|| value.to_string());
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| HttpClientError::Other {
+                error: format!("{e:?}"),
+            })?
+            .to_vec();
+
+        Ok(HttpResponse {
+            status_code,
+            headers,
+            body,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncHttpClient for ReqwestHttpClient {
+    async fn http_client(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+        self.send(request).await
+    }
+}
+
+#[async_trait]
+impl openid4vp::core::util::AsyncHttpClient for ReqwestHttpClient {
+    async fn execute(&self, request: ExtHttpRequest) -> anyhow::Result<ExtHttpResponse> {
+        let response: ExtHttpResponse = self.send(request.try_into()?).await?.try_into()?;
+        Ok(response)
+    }
+}
+
 #[derive(uniffi::Object)]
 /// Http client wrapper type that could either be a synchronous or asynchronous
 /// external (Kotlin, Swift, etc) client implementation, receveid as a dynamic
@@ -212,6 +409,13 @@ impl IHttpClient {
     fn new_async(client_impl: Arc<dyn AsyncHttpClient>) -> Arc<Self> {
         Arc::new(client_impl.into())
     }
+
+    /// Use the built-in HTTP client, with the given configuration.
+    #[uniffi::constructor(name = "new_with_config")]
+    fn new_with_config(config: HttpClientConfig) -> Result<Arc<Self>, HttpClientError> {
+        let client: Arc<dyn AsyncHttpClient> = Arc::new(ReqwestHttpClient::new(&config)?);
+        Ok(Arc::new(client.into()))
+    }
 }
 
 fn headermap_to_hashmap(headers: &HeaderMap) -> Result<HashMap<String, String>, HttpClientError> {
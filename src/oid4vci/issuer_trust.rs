@@ -0,0 +1,376 @@
+//! Trust evaluation of credential issuers during OID4VCI, from their signed
+//! metadata, the trust anchors and trusted lists of the app, and the types of
+//! credentials each issuer is allowed to issue.
+//!
+//! The verdict is recorded in the session, and the credentials of the session
+//! are not exchanged when it is refused.
+//!
+//! NOTE: signed metadata is only verified with an ES256 `x5c` chain, and the
+//! claims it signs are not used in place of the unsigned metadata.
+
+use super::{issuer_endpoint, Oid4vciError, Oid4vciSession};
+use crate::clock::{self, Clock};
+use crate::credential::verification::VerificationCheck;
+use crate::trust_anchors::verify_chain;
+use crate::trust_list::TrustListManager;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use base64::prelude::*;
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use serde_json::Value as Json;
+use x509_cert::{
+    der::{Decode, Encode},
+    Certificate,
+};
+
+/// What issuers are trusted for.
+#[derive(Debug, Clone, Default, uniffi::Record)]
+pub struct IssuerTrustPolicy {
+    /// The credential issuer identifiers trusted without signed metadata.
+    pub trusted_issuers: Vec<String>,
+    /// The PEM encoded certificates trusted to sign issuer metadata.
+    pub trust_anchors: Vec<String>,
+    /// The trusted lists whose services are trusted to sign issuer metadata
+    /// as well.
+    pub trust_list: Option<Arc<TrustListManager>>,
+    /// The credential types, e.g. `vct` values or doctypes, each issuer may
+    /// issue, by credential issuer identifier. Issuers without an entry may
+    /// issue any type.
+    pub allowed_types: HashMap<String, Vec<String>>,
+    /// Refuse issuance from issuers that are not known to be trusted, rather
+    /// than only warning about them.
+    pub refuse_unknown: bool,
+    /// The clock signed metadata is checked against, or the system clock if
+    /// unset.
+    pub clock: Option<Arc<dyn Clock>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum IssuerTrustLevel {
+    /// The issuer signed its metadata with a trusted certificate, or is
+    /// trusted by the app.
+    Trusted,
+    /// Nothing is known of the issuer.
+    Unknown,
+    /// The signed metadata of the issuer is invalid, or it offers types it
+    /// is not allowed to issue.
+    Untrusted,
+}
+
+/// The outcome of the trust evaluation of an issuer.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct IssuerTrustVerdict {
+    /// The credential issuer identifier.
+    pub issuer: String,
+    pub level: IssuerTrustLevel,
+    /// The signed metadata of the issuer, skipped when it has none.
+    pub signed_metadata: VerificationCheck,
+    /// The offered credential types the issuer is not allowed to issue.
+    pub disallowed_types: Vec<String>,
+    /// Whether the credentials of the session will not be exchanged.
+    pub refused: bool,
+}
+
+/// Evaluate the issuer of the session against the policy, recording the
+/// verdict in the session for issuance to be refused accordingly.
+#[uniffi::export]
+pub async fn oid4vci_evaluate_issuer_trust(
+    session: Arc<Oid4vciSession>,
+    policy: IssuerTrustPolicy,
+) -> Result<IssuerTrustVerdict, Oid4vciError> {
+    let issuer = issuer_endpoint(&session, "credential_issuer")?.ok_or(
+        Oid4vciError::InvalidSession("credential_issuer unset".into()),
+    )?;
+    let signed_metadata = serde_json::to_value(session.get_metadata()?)?["signed_metadata"]
+        .as_str()
+        .map(ToOwned::to_owned);
+    let offered_types = session
+        .get_credential_requests()?
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .flat_map(credential_types)
+        .collect::<Vec<_>>();
+
+    let verdict = evaluate(issuer, signed_metadata.as_deref(), &offered_types, &policy).await?;
+    if verdict.level == IssuerTrustLevel::Unknown && !verdict.refused {
+        log::warn!("Issuance from an unknown issuer: {}", verdict.issuer);
+    }
+    session.set_issuer_trust(verdict.clone())?;
+    Ok(verdict)
+}
+
+/// Fail when the issuer of the session was refused.
+pub(crate) fn check_issuer_trust(session: &Oid4vciSession) -> Result<(), Oid4vciError> {
+    match session.get_issuer_trust()? {
+        Some(verdict) if verdict.refused => Err(Oid4vciError::UntrustedIssuer(verdict.issuer)),
+        _ => Ok(()),
+    }
+}
+
+/// Return the credential types of a credential request, as JSON.
+fn credential_types(request: &Json) -> Vec<String> {
+    let mut types = ["vct", "doctype"]
+        .iter()
+        .filter_map(|name| request[name].as_str())
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    types.extend(
+        request["credential_definition"]["type"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Json::as_str)
+            .filter(|r#type| *r#type != "VerifiableCredential")
+            .map(ToOwned::to_owned),
+    );
+    types
+}
+
+async fn evaluate(
+    issuer: String,
+    signed_metadata: Option<&str>,
+    offered_types: &[String],
+    policy: &IssuerTrustPolicy,
+) -> Result<IssuerTrustVerdict, Oid4vciError> {
+    let signed_metadata = match signed_metadata {
+        None => VerificationCheck::Skipped {
+            reason: "the issuer metadata is not signed".into(),
+        },
+        Some(jwt) => {
+            let mut anchors = policy
+                .trust_anchors
+                .iter()
+                .map(|pem| {
+                    let (_, der) = pem_rfc7468::decode_vec(pem.as_bytes())
+                        .map_err(|e| Oid4vciError::InvalidParameter(format!("{e:?}")))?;
+                    Certificate::from_der(&der)
+                        .map_err(|e| Oid4vciError::InvalidParameter(format!("{e:?}")))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if let Some(trust_list) = &policy.trust_list {
+                anchors.extend(
+                    trust_list
+                        .certificates()
+                        .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?,
+                );
+            }
+            let now = policy.clock.clone().unwrap_or_else(clock::system).now();
+            match verify_signed_metadata(jwt, &issuer, &anchors, now).await {
+                Ok(()) => VerificationCheck::Passed,
+                Err(reason) => VerificationCheck::Failed { reason },
+            }
+        }
+    };
+
+    let disallowed_types = match policy.allowed_types.get(&issuer) {
+        Some(allowed) => offered_types
+            .iter()
+            .filter(|r#type| !allowed.contains(r#type))
+            .cloned()
+            .collect(),
+        None => vec![],
+    };
+
+    let level = if matches!(signed_metadata, VerificationCheck::Failed { .. })
+        || !disallowed_types.is_empty()
+    {
+        IssuerTrustLevel::Untrusted
+    } else if signed_metadata == VerificationCheck::Passed
+        || policy.trusted_issuers.contains(&issuer)
+    {
+        IssuerTrustLevel::Trusted
+    } else {
+        IssuerTrustLevel::Unknown
+    };
+    let refused = match level {
+        IssuerTrustLevel::Trusted => false,
+        IssuerTrustLevel::Unknown => policy.refuse_unknown,
+        IssuerTrustLevel::Untrusted => true,
+    };
+
+    Ok(IssuerTrustVerdict {
+        issuer,
+        level,
+        signed_metadata,
+        disallowed_types,
+        refused,
+    })
+}
+
+/// Verify that signed metadata is signed with the key of the leaf of its
+/// `x5c` chain, which chains to a trust anchor, and is about the issuer.
+async fn verify_signed_metadata(
+    jwt: &str,
+    issuer: &str,
+    anchors: &[Certificate],
+    now: SystemTime,
+) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Debug| format!("{e:?}");
+    let (signing_input, signature) = jwt
+        .rsplit_once('.')
+        .ok_or("the signed metadata is not a JWT")?;
+    let (header, payload) = signing_input
+        .split_once('.')
+        .ok_or("the signed metadata is not a JWT")?;
+    let decode = |part: &str| -> Result<Json, String> {
+        let bytes = BASE64_URL_SAFE_NO_PAD.decode(part).map_err(|e| error(&e))?;
+        serde_json::from_slice(&bytes).map_err(|e| error(&e))
+    };
+    let (header, payload) = (decode(header)?, decode(payload)?);
+
+    if header["alg"] != "ES256" {
+        return Err(format!(
+            "unsupported signed metadata algorithm: {}",
+            header["alg"]
+        ));
+    }
+    if payload["sub"].as_str() != Some(issuer) {
+        return Err("the signed metadata is not about the issuer".into());
+    }
+    if let Some(expires_at) = payload["exp"].as_u64() {
+        if clock::has_passed(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(expires_at),
+            now,
+            Duration::ZERO,
+        ) {
+            return Err("the signed metadata is expired".into());
+        }
+    }
+
+    let chain = header["x5c"]
+        .as_array()
+        .ok_or("the signed metadata has no x5c header")?
+        .iter()
+        .map(|certificate| {
+            let der = BASE64_STANDARD
+                .decode(certificate.as_str().ok_or("invalid x5c")?)
+                .map_err(|e| error(&e))?;
+            Certificate::from_der(&der).map_err(|e| error(&e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    verify_chain(&chain, anchors, false, now)
+        .await
+        .map_err(|e| error(&e))?;
+
+    let spki = chain[0]
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| error(&e))?;
+    let key = VerifyingKey::from_public_key_der(&spki).map_err(|e| error(&e))?;
+    let signature = BASE64_URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|e| error(&e))
+        .and_then(|signature| Signature::from_slice(&signature).map_err(|e| error(&e)))?;
+    key.verify(signing_input.as_bytes(), &signature)
+        .map_err(|_| "the signed metadata signature is invalid".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use p256::ecdsa::{signature::Signer, SigningKey};
+    use serde_json::json;
+
+    const ISSUER: &str = "https://issuer.example.com";
+
+    #[derive(Debug)]
+    struct FixedClock(SystemTime);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> SystemTime {
+            self.0
+        }
+    }
+
+    fn signed_metadata(sub: &str) -> String {
+        let certificate = include_str!("../../tests/res/reader-cert.pem");
+        let (_, der) = pem_rfc7468::decode_vec(certificate.as_bytes()).unwrap();
+        let key = SigningKey::from(
+            p256::SecretKey::from_sec1_pem(include_str!("../../tests/res/reader-key.pem")).unwrap(),
+        );
+        let encode = |value: Json| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let signing_input = format!(
+            "{}.{}",
+            encode(json!({ "alg": "ES256", "x5c": [BASE64_STANDARD.encode(der)] })),
+            encode(json!({ "iss": ISSUER, "sub": sub, "iat": 1_800_000_000 })),
+        );
+        let signature: Signature = key.sign(signing_input.as_bytes());
+        format!(
+            "{signing_input}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+
+    #[tokio::test]
+    async fn evaluates_issuer_trust() {
+        let policy = IssuerTrustPolicy {
+            trust_anchors: vec![include_str!("../../tests/res/reader-cert.pem").into()],
+            clock: Some(Arc::new(FixedClock(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1_900_000_000),
+            ))),
+            ..Default::default()
+        };
+        let offered = ["eu.europa.ec.eudi.pid.1".to_string()];
+        let types = &offered;
+        let verdict_of = |signed_metadata: Option<String>, policy: IssuerTrustPolicy| async move {
+            evaluate(ISSUER.into(), signed_metadata.as_deref(), types, &policy)
+                .await
+                .unwrap()
+        };
+
+        let verdict = verdict_of(Some(signed_metadata(ISSUER)), policy.clone()).await;
+        assert_eq!(verdict.level, IssuerTrustLevel::Trusted);
+        assert_eq!(verdict.signed_metadata, VerificationCheck::Passed);
+
+        let verdict = verdict_of(
+            Some(signed_metadata("https://other.example.com")),
+            policy.clone(),
+        )
+        .await;
+        assert_eq!(verdict.level, IssuerTrustLevel::Untrusted);
+        assert!(verdict.refused);
+
+        let verdict = verdict_of(None, policy.clone()).await;
+        assert_eq!(verdict.level, IssuerTrustLevel::Unknown);
+        assert!(!verdict.refused);
+        let verdict = verdict_of(
+            None,
+            IssuerTrustPolicy {
+                refuse_unknown: true,
+                ..policy.clone()
+            },
+        )
+        .await;
+        assert!(verdict.refused);
+
+        let verdict = verdict_of(
+            None,
+            IssuerTrustPolicy {
+                trusted_issuers: vec![ISSUER.into()],
+                allowed_types: [(ISSUER.into(), vec!["org.iso.18013.5.1.mDL".into()])].into(),
+                ..policy
+            },
+        )
+        .await;
+        assert_eq!(verdict.level, IssuerTrustLevel::Untrusted);
+        assert_eq!(verdict.disallowed_types, offered);
+
+        assert_eq!(
+            credential_types(&json!({
+                "format": "jwt_vc_json",
+                "credential_definition": { "type": ["VerifiableCredential", "EmployeeCredential"] },
+            })),
+            ["EmployeeCredential"]
+        );
+    }
+}
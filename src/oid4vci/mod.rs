@@ -11,12 +11,13 @@ use oid4vci::{
         },
     },
     credential::ResponseEnum,
-    credential_offer::CredentialOffer,
+    credential_offer::CredentialOffer as ICredentialOffer,
     metadata::{authorization_server::GrantType, AuthorizationServerMetadata, MetadataDiscovery},
     oauth2::{ClientId, RedirectUrl, TokenResponse as ITokenResponse},
     proof_of_possession::Proof,
     types::{CredentialOfferRequest, IssuerUrl, PreAuthorizedCode},
 };
+use serde_json::Value as Json;
 use ssi::{
     claims::{
         jwt::ToDecodedJwt, vc::v1::data_integrity::any_credential_from_json_str,
@@ -27,20 +28,48 @@ use ssi::{
 };
 use url::Url;
 
-use context_loader::context_loader_from_map;
+use attestation::HeaderHttpClient;
+pub use attestation::{WalletAttestation, WalletAttestationError, WalletAttestationProvider};
+pub(crate) use context_loader::context_loader_from_map;
+pub use deferred::{DeferredIssuance, DeferredIssuanceStatus};
+pub use dpop::Dpop;
 pub use error::*;
 pub use http_client::*;
+pub use issuer_trust::*;
 pub use metadata::*;
+pub use notification::*;
+pub use offer::*;
+pub(crate) use pinning::certificate_pinning_host;
+pub use pinning::TlsPinningConfig;
+pub use rebinding::*;
+pub use refresh::*;
+pub use replenishment::*;
 pub use session::*;
+use tx_code::TxCodeHttpClient;
+pub use tx_code::{TxCodeError, TxCodeHint, TxCodeProvider};
 pub use wrapper::*;
 
-use crate::credential::CredentialFormat;
+use crate::common::{KeyAlias, Uuid};
+use crate::credential::{display::credential_display_from_configuration, CredentialFormat};
+use crate::proof_of_possession::{device_proof, ProofType};
+use crate::signer::DeviceSigner;
 
+mod attestation;
 mod context_loader;
+mod deferred;
+mod dpop;
 mod error;
 mod http_client;
+mod issuer_trust;
 mod metadata;
+mod notification;
+mod offer;
+mod pinning;
+mod rebinding;
+mod refresh;
+mod replenishment;
 mod session;
+mod tx_code;
 mod wrapper;
 
 #[uniffi::export]
@@ -54,7 +83,7 @@ pub async fn oid4vci_initiate_with_offer(
         Oid4vciError::InvalidParameter("invalid credential_offer: failed to parse url".into())
     })?;
 
-    let credential_offer = CredentialOffer::from_request(
+    let credential_offer = ICredentialOffer::from_request(
         CredentialOfferRequest::from_url_checked(credential_offer).map_err(|_| {
             Oid4vciError::InvalidParameter("invalid credential_offer: failed to parse offer".into())
         })?,
@@ -125,7 +154,7 @@ pub async fn oid4vci_initiate_with_offer(
             Oid4vciError::RequestError("failed to discover authorization server metadata".into())
         })?;
 
-    let credential_requests: Vec<CoreProfilesCredentialRequest> = issuer_metadata
+    let offered_configurations = issuer_metadata
         .credential_configurations_supported()
         .iter()
         .filter(|config| {
@@ -133,6 +162,19 @@ pub async fn oid4vci_initiate_with_offer(
                 .credential_configuration_ids()
                 .contains(config.id())
         })
+        .collect::<Vec<_>>();
+
+    let credential_display = offered_configurations
+        .iter()
+        .map(|config| {
+            serde_json::to_value(config)
+                .map(|config| credential_display_from_configuration(&config))
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let credential_requests: Vec<CoreProfilesCredentialRequest> = offered_configurations
+        .into_iter()
         .map(|config| match config.profile_specific_fields() {
             CoreProfilesCredentialConfiguration::LdpVc(config) => {
                 let credential_definition =
@@ -160,6 +202,7 @@ pub async fn oid4vci_initiate_with_offer(
         })
         .collect();
 
+    let authorization_server_metadata = serde_json::to_value(&authorization_metadata)?;
     let client = client::Client::from_issuer_metadata(
         ClientId::new(client_id),
         RedirectUrl::new(redirect_url).unwrap(),
@@ -169,7 +212,9 @@ pub async fn oid4vci_initiate_with_offer(
 
     let mut session = Oid4vciSession::new(client.into());
     session.set_metadata(issuer_metadata.into());
+    session.set_authorization_server_metadata(authorization_server_metadata);
     session.set_credential_requests(credential_requests)?;
+    session.set_credential_display(credential_display)?;
     session.set_grants(grants)?;
 
     Ok(session)
@@ -206,6 +251,7 @@ pub async fn oid4vci_initiate(
         Oid4vciError::RequestError("failed to discover authorization server metadata".into())
     })?;
 
+    let authorization_server_metadata = serde_json::to_value(&authorization_metadata)?;
     let client = client::Client::from_issuer_metadata(
         ClientId::new(client_id),
         RedirectUrl::new(redirect_url).unwrap(),
@@ -215,6 +261,7 @@ pub async fn oid4vci_initiate(
 
     let mut session = Oid4vciSession::new(client.into());
     session.set_metadata(issuer_metadata.into());
+    session.set_authorization_server_metadata(authorization_server_metadata);
 
     Ok(session)
 }
@@ -223,16 +270,83 @@ pub async fn oid4vci_initiate(
 pub async fn oid4vci_exchange_token(
     session: Arc<Oid4vciSession>,
     http_client: Arc<IHttpClient>,
+) -> Result<Option<String>, Oid4vciError> {
+    exchange_token(session, None, None, http_client).await
+}
+
+/// Exchange the token, authenticating with the wallet attestation, as
+/// required by issuers following HAIP.
+#[uniffi::export]
+pub async fn oid4vci_exchange_token_with_attestation(
+    session: Arc<Oid4vciSession>,
+    wallet_attestation: Arc<WalletAttestation>,
+    http_client: Arc<IHttpClient>,
+) -> Result<Option<String>, Oid4vciError> {
+    exchange_token(session, Some(wallet_attestation), None, http_client).await
+}
+
+/// Exchange the token, collecting the transaction code from the user through
+/// the provider when the offer requires one.
+#[uniffi::export]
+pub async fn oid4vci_exchange_token_with_tx_code(
+    session: Arc<Oid4vciSession>,
+    tx_code_provider: Arc<dyn TxCodeProvider>,
+    http_client: Arc<IHttpClient>,
+) -> Result<Option<String>, Oid4vciError> {
+    exchange_token(session, None, Some(tx_code_provider), http_client).await
+}
+
+async fn exchange_token(
+    session: Arc<Oid4vciSession>,
+    wallet_attestation: Option<Arc<WalletAttestation>>,
+    tx_code_provider: Option<Arc<dyn TxCodeProvider>>,
+    mut http_client: Arc<IHttpClient>,
 ) -> Result<Option<String>, Oid4vciError> {
     // TODO: refactor with `try {}` once it stabilizes.
-    let code = (|| -> Result<PreAuthorizedCode, Oid4vciError> {
+    let (code, tx_code) = (|| -> Result<(PreAuthorizedCode, Json), Oid4vciError> {
         if let Some(pre_auth) = session.get_grants()?.pre_authorized_code() {
-            return Ok(pre_auth.pre_authorized_code().clone());
+            return Ok((
+                pre_auth.pre_authorized_code().clone(),
+                serde_json::to_value(pre_auth)?["tx_code"].take(),
+            ));
         }
 
         Err(Oid4vciError::UnsupportedGrantType)
     })()?;
 
+    if let Some(wallet_attestation) = wallet_attestation {
+        let headers = wallet_attestation
+            .headers_for(session.get_authorization_server_metadata()?, &http_client)
+            .await?;
+        let client: Arc<dyn AsyncHttpClient> = Arc::new(HeaderHttpClient {
+            inner: http_client,
+            headers,
+        });
+        http_client = Arc::new(client.into());
+    }
+
+    if !tx_code.is_null() {
+        let hint = TxCodeHint::from_grant(&tx_code);
+        let tx_code = tx_code_provider
+            .ok_or(Oid4vciError::InvalidParameter(
+                "the offer requires a transaction code".into(),
+            ))?
+            .tx_code(hint.clone())
+            .await
+            .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?;
+        if !hint.accepts(&tx_code) {
+            return Err(Oid4vciError::InvalidParameter(
+                "the transaction code does not match the offer".into(),
+            ));
+        }
+
+        let client: Arc<dyn AsyncHttpClient> = Arc::new(TxCodeHttpClient {
+            inner: http_client,
+            tx_code,
+        });
+        http_client = Arc::new(client.into());
+    }
+
     let token_response = match &http_client.0 {
         Either::Left(sync_client) => session
             .get_client()
@@ -261,17 +375,101 @@ pub async fn oid4vci_exchange_token(
     Ok(nonce)
 }
 
+/// Exchange the credentials of the session.
+///
+/// Several instances of each credential are issued when several proofs of
+/// possession are given for each credential request, e.g. a batch of
+/// single-use mdocs: the proofs of each request follow each other, in the
+/// order of the requests.
+///
+/// Credentials the issuer defers are not returned, but recorded in the
+/// session, see [oid4vci_deferred_issuances].
 #[uniffi::export]
 pub async fn oid4vci_exchange_credential(
     session: Arc<Oid4vciSession>,
     proofs_of_possession: Vec<String>,
     context_map: Option<HashMap<String, String>>,
     http_client: Arc<IHttpClient>,
+) -> Result<Vec<CredentialResponse>, Oid4vciError> {
+    exchange_credential(
+        session,
+        proofs_of_possession
+            .into_iter()
+            .map(|jwt| Proof::Jwt { jwt })
+            .collect(),
+        context_map,
+        http_client,
+    )
+    .await
+}
+
+/// Exchange the credentials of the session, proving possession of the device
+/// keys of the aliases through the signer.
+///
+/// The proofs use the `c_nonce` of the token response, or else a fresh one
+/// from the nonce endpoint of the issuer. As with
+/// [oid4vci_exchange_credential], giving several key aliases for each
+/// credential request issues a batch of instances bound to each key.
+#[uniffi::export]
+pub async fn oid4vci_exchange_credential_with_signer(
+    session: Arc<Oid4vciSession>,
+    signer: Arc<dyn DeviceSigner>,
+    key_aliases: Vec<KeyAlias>,
+    proof_type: ProofType,
+    context_map: Option<HashMap<String, String>>,
+    http_client: Arc<IHttpClient>,
+) -> Result<Vec<CredentialResponse>, Oid4vciError> {
+    let audience = issuer_endpoint(&session, "credential_issuer")?.ok_or(
+        Oid4vciError::InvalidSession("credential_issuer unset".into()),
+    )?;
+
+    let mut nonce = session
+        .get_token_response()?
+        .extra_fields()
+        .c_nonce
+        .clone()
+        .map(|v| v.secret().to_owned());
+    if nonce.is_none() {
+        if let Some(nonce_endpoint) = issuer_endpoint(&session, "nonce_endpoint")? {
+            nonce = refresh::post_json(&nonce_endpoint, None, None, &http_client).await?["c_nonce"]
+                .as_str()
+                .map(ToOwned::to_owned);
+        }
+    }
+
+    let mut proofs = vec![];
+    for key_alias in &key_aliases {
+        let proof = device_proof(
+            proof_type,
+            &audience,
+            None,
+            nonce.clone(),
+            signer.as_ref(),
+            key_alias,
+        )
+        .await
+        .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?;
+        proofs.push(match proof_type {
+            ProofType::Jwt => Proof::Jwt { jwt: proof },
+            ProofType::Cwt => Proof::Cwt { cwt: proof },
+        });
+    }
+
+    exchange_credential(session, proofs, context_map, http_client).await
+}
+
+async fn exchange_credential(
+    session: Arc<Oid4vciSession>,
+    proofs_of_possession: Vec<Proof>,
+    context_map: Option<HashMap<String, String>>,
+    http_client: Arc<IHttpClient>,
 ) -> Result<Vec<CredentialResponse>, Oid4vciError> {
     log::trace!("oid4vci_exchange_credential");
+    issuer_trust::check_issuer_trust(&session)?;
 
     log::trace!("session.get_credential_requests");
     let credential_requests = session.get_credential_requests()?.clone();
+    let credential_display = session.get_credential_display()?;
 
     log::trace!("credential_requests.is_empty");
     if credential_requests.is_empty() {
@@ -281,13 +479,33 @@ pub async fn oid4vci_exchange_credential(
     }
 
     log::trace!("compare length proofs_of_possession vs credential_requests");
-    if proofs_of_possession.len() != credential_requests.len() {
+    if proofs_of_possession.is_empty()
+        || proofs_of_possession.len() % credential_requests.len() != 0
+    {
         return Err(Oid4vciError::InvalidParameter(
-            "invalid number of proofs received, must match credential request count".into(),
+            "invalid number of proofs received, must be a multiple of the credential request count"
+                .into(),
         ));
     }
-
-    let credential_responses = if credential_requests.len() == 1 {
+    let batch_size = proofs_of_possession.len() / credential_requests.len();
+    let batch_ids = credential_requests
+        .iter()
+        .map(|_| Uuid::new_v4())
+        .collect::<Vec<_>>();
+    // The transaction IDs of deferred credentials, with their request index.
+    let mut deferred = vec![];
+    let access_token = session
+        .get_token_response()?
+        .access_token()
+        .secret()
+        .to_owned();
+    let notification_endpoint = issuer_endpoint(&session, "notification_endpoint")?;
+    let refreshes = credential_refreshes(&session, &credential_requests)?;
+    // Only single credential responses identify their issuance for
+    // notifications.
+    let mut notification = None;
+
+    let credential_responses = if proofs_of_possession.len() == 1 {
         log::trace!("processing single request");
 
         log::trace!("build request");
@@ -297,9 +515,7 @@ pub async fn oid4vci_exchange_credential(
                 session.get_token_response()?.access_token().clone(),
                 credential_requests.first().unwrap().to_owned(),
             )
-            .set_proof(Some(Proof::Jwt {
-                jwt: proofs_of_possession.first().unwrap().to_owned(),
-            }));
+            .set_proof(proofs_of_possession.into_iter().next());
 
         log::trace!("execute with http client");
         let response = match &http_client.0 {
@@ -307,11 +523,30 @@ pub async fn oid4vci_exchange_credential(
             Either::Right(async_client) => request.request_async(async_client).await,
         }?;
 
+        if let (Some(notification_endpoint), Some(notification_id)) = (
+            &notification_endpoint,
+            serde_json::to_value(&response)?["notification_id"].as_str(),
+        ) {
+            notification = Some(CredentialNotification {
+                notification_endpoint: notification_endpoint.clone(),
+                notification_id: notification_id.to_owned(),
+                access_token: access_token.clone(),
+            });
+        }
+
         log::trace!("match response kind");
         match response.response_kind() {
-            ResponseEnum::Immediate { credential } => vec![credential.to_owned()],
-            ResponseEnum::ImmediateMany { credentials } => credentials.to_owned(),
-            ResponseEnum::Deferred { .. } => todo!(),
+            ResponseEnum::Immediate { credential } => {
+                vec![(credential.to_owned(), 0, None)]
+            }
+            ResponseEnum::ImmediateMany { credentials } => credentials
+                .iter()
+                .map(|credential| (credential.to_owned(), 0, Some(batch_ids[0])))
+                .collect(),
+            ResponseEnum::Deferred { transaction_id, .. } => {
+                deferred.push((transaction_id.to_string(), 0));
+                vec![]
+            }
         }
     } else {
         log::trace!("processing muliple requests");
@@ -321,14 +556,12 @@ pub async fn oid4vci_exchange_credential(
             .get_client()
             .batch_request_credential(
                 session.get_token_response()?.access_token().clone(),
-                credential_requests.to_vec(),
-            )?
-            .set_proofs::<Oid4vciError>(
-                proofs_of_possession
-                    .into_iter()
-                    .map(|p| Proof::Jwt { jwt: p })
+                credential_requests
+                    .iter()
+                    .flat_map(|request| std::iter::repeat(request.clone()).take(batch_size))
                     .collect(),
-            )?;
+            )?
+            .set_proofs::<Oid4vciError>(proofs_of_possession)?;
 
         log::trace!("execute with http client");
         let response = match &http_client.0 {
@@ -340,14 +573,51 @@ pub async fn oid4vci_exchange_credential(
         response
             .credential_responses()
             .iter()
-            .flat_map(|r| match r {
-                ResponseEnum::Immediate { credential } => vec![credential.to_owned()],
-                ResponseEnum::ImmediateMany { credentials } => credentials.to_owned(),
-                ResponseEnum::Deferred { .. } => todo!(),
+            .enumerate()
+            .flat_map(|(idx, r)| {
+                // Each request is repeated for every instance of its batch.
+                let idx = idx / batch_size;
+                let batch_id = (batch_size > 1).then_some(batch_ids[idx]);
+                match r {
+                    ResponseEnum::Immediate { credential } => {
+                        vec![(credential.to_owned(), idx, batch_id)]
+                    }
+                    ResponseEnum::ImmediateMany { credentials } => credentials
+                        .iter()
+                        .map(|credential| (credential.to_owned(), idx, Some(batch_ids[idx])))
+                        .collect(),
+                    ResponseEnum::Deferred { transaction_id, .. } => {
+                        deferred.push((transaction_id.to_string(), idx));
+                        vec![]
+                    }
+                }
             })
-            .collect()
+            .collect::<Vec<_>>()
     };
 
+    if !deferred.is_empty() {
+        log::trace!("record deferred credentials");
+        let deferred_credential_endpoint =
+            issuer_endpoint(&session, "deferred_credential_endpoint")?.ok_or(
+                Oid4vciError::InvalidSession("deferred_credential_endpoint unset".into()),
+            )?;
+
+        for (transaction_id, idx) in deferred {
+            let format = serde_json::to_value(&credential_requests[idx])?["format"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned();
+            session.add_deferred_issuance(Arc::new(DeferredIssuance::new(
+                transaction_id,
+                deferred_credential_endpoint.clone(),
+                access_token.clone(),
+                notification_endpoint.clone(),
+                format,
+                credential_display.get(idx).cloned().unwrap_or_default(),
+            )))?;
+        }
+    }
+
     log::trace!("create vm_resolver");
     let vm_resolver = AnyDidMethod::default().into_vm_resolver();
     log::trace!("create verification params");
@@ -358,10 +628,17 @@ pub async fn oid4vci_exchange_credential(
     };
 
     log::trace!("verify and convert http response into credential response");
+    let params = &params;
+    let notification = &notification;
+    let credential_display = &credential_display;
+    let refreshes = &refreshes;
     futures::future::try_join_all(credential_responses.into_iter().map(
-        |credential_response| async {
+        |(credential_response, idx, batch_id)| async move {
             use oid4vci::core::profiles::CoreProfilesCredentialResponseType::*;
 
+            let display = credential_display.get(idx).cloned().unwrap_or_default();
+            let refresh = refreshes[idx].clone();
+
             match credential_response {
                 JwtVcJson(response) => {
                     log::trace!("processing a JwtVcJson");
@@ -371,7 +648,11 @@ pub async fn oid4vci_exchange_credential(
                     Ok(CredentialResponse {
                         format: CredentialFormat::JwtVcJson,
                         payload: rt
-                            .block_on(async { response.verify_jwt(&params).await.map(|_| ret) })?,
+                            .block_on(async { response.verify_jwt(params).await.map(|_| ret) })?,
+                        display,
+                        batch_id,
+                        notification: notification.clone(),
+                        refresh,
                     })
                 }
                 JwtVcJsonLd(response) => {
@@ -381,9 +662,13 @@ pub async fn oid4vci_exchange_credential(
                     Ok(CredentialResponse {
                         format: CredentialFormat::JwtVcJsonLd,
                         payload: any_credential_from_json_str(&vc)?
-                            .verify(&params)
+                            .verify(params)
                             .await
                             .map(|_| ret)?,
+                        display,
+                        batch_id,
+                        notification: notification.clone(),
+                        refresh,
                     })
                 }
                 LdpVc(response) => {
@@ -392,7 +677,11 @@ pub async fn oid4vci_exchange_credential(
                     let ret = serde_json::to_vec(&vc)?;
                     Ok(CredentialResponse {
                         format: CredentialFormat::LdpVc,
-                        payload: vc.verify(&params).await.map(|_| ret)?,
+                        payload: vc.verify(params).await.map(|_| ret)?,
+                        display,
+                        batch_id,
+                        notification: notification.clone(),
+                        refresh,
                     })
                 }
                 MsoMdoc(_) => todo!(),
@@ -401,3 +690,55 @@ pub async fn oid4vci_exchange_credential(
     ))
     .await
 }
+
+/// Return an endpoint of the credential issuer, from its metadata.
+fn issuer_endpoint(session: &Oid4vciSession, name: &str) -> Result<Option<String>, Oid4vciError> {
+    Ok(serde_json::to_value(session.get_metadata()?)?[name]
+        .as_str()
+        .map(ToOwned::to_owned))
+}
+
+/// Return what is needed to refresh the credentials of each request, when the
+/// issuer metadata has the endpoints to.
+fn credential_refreshes(
+    session: &Oid4vciSession,
+    credential_requests: &[CoreProfilesCredentialRequest],
+) -> Result<Vec<Option<CredentialRefresh>>, Oid4vciError> {
+    let token_response = session.get_token_response()?;
+    let token_endpoint = session.get_authorization_server_metadata()?["token_endpoint"]
+        .as_str()
+        .map(ToOwned::to_owned);
+    let (Some(credential_issuer), Some(token_endpoint), Some(credential_endpoint)) = (
+        issuer_endpoint(session, "credential_issuer")?,
+        token_endpoint,
+        issuer_endpoint(session, "credential_endpoint")?,
+    ) else {
+        return Ok(vec![None; credential_requests.len()]);
+    };
+
+    credential_requests
+        .iter()
+        .map(|request| {
+            Ok(Some(CredentialRefresh {
+                credential_issuer: credential_issuer.clone(),
+                token_endpoint: token_endpoint.clone(),
+                credential_endpoint: credential_endpoint.clone(),
+                nonce_endpoint: issuer_endpoint(session, "nonce_endpoint")?,
+                refresh_token: token_response
+                    .refresh_token()
+                    .map(|token| token.secret().to_owned()),
+                access_token: token_response.access_token().secret().to_owned(),
+                credential_request: serde_json::to_string(request)?,
+            }))
+        })
+        .collect()
+}
+
+/// Return the credentials the issuer deferred during the session, to poll
+/// later, e.g. after persisting them.
+#[uniffi::export]
+pub fn oid4vci_deferred_issuances(
+    session: Arc<Oid4vciSession>,
+) -> Result<Vec<Arc<DeferredIssuance>>, Oid4vciError> {
+    session.get_deferred_issuances()
+}
@@ -0,0 +1,225 @@
+//! Notifications to issuers of what became of the credentials they issued,
+//! sent to their notification endpoint.
+
+use std::{str::FromStr, sync::Arc};
+
+use oid4vci::oauth2::http::{header, Method, Request, Uri};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{HttpClientError, IHttpClient, Oid4vciError};
+use crate::common::Uuid;
+use crate::vdc_collection::{CredentialLifecycleHook, CredentialMetadata, VdcCollection};
+
+/// The attribute of the credential metadata holding its notification target.
+pub const NOTIFICATION_ATTRIBUTE: &str = "oid4vci_notification";
+
+/// Where to notify the issuer of a credential about it, when the issuer
+/// supports notifications.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct CredentialNotification {
+    pub notification_endpoint: String,
+    /// The ID the issuer identifies the issuance with.
+    pub notification_id: String,
+    pub access_token: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum NotificationEvent {
+    CredentialAccepted,
+    CredentialFailure,
+    CredentialDeleted,
+}
+
+impl NotificationEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::CredentialAccepted => "credential_accepted",
+            Self::CredentialFailure => "credential_failure",
+            Self::CredentialDeleted => "credential_deleted",
+        }
+    }
+}
+
+/// Return the metadata to store a credential with, for its issuer to be
+/// notified by an [Oid4vciNotifier], e.g. with
+/// [crate::vdc_collection::VdcCollection::add_with_metadata].
+#[uniffi::export]
+pub fn oid4vci_notification_metadata(
+    notification: CredentialNotification,
+) -> Result<CredentialMetadata, Oid4vciError> {
+    Ok(CredentialMetadata {
+        attributes: [(
+            NOTIFICATION_ATTRIBUTE.to_string(),
+            serde_json::to_string(&notification)?,
+        )]
+        .into(),
+        ..Default::default()
+    })
+}
+
+/// Notify an issuer of an event about a credential it issued.
+#[uniffi::export]
+pub async fn oid4vci_notify(
+    notification: CredentialNotification,
+    event: NotificationEvent,
+    event_description: Option<String>,
+    http_client: Arc<IHttpClient>,
+) -> Result<(), Oid4vciError> {
+    let mut body = json!({
+        "notification_id": notification.notification_id,
+        "event": event.as_str(),
+    });
+    if let Some(event_description) = event_description {
+        body["event_description"] = json!(event_description);
+    }
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(
+            Uri::from_str(&notification.notification_endpoint)
+                .map_err(|_| HttpClientError::UrlParse)?,
+        )
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", notification.access_token),
+        )
+        .body(body.to_string().into_bytes())
+        .map_err(|_| HttpClientError::RequestBuilder)?;
+
+    let response = http_client.call(request).await?;
+    if !response.status().is_success() {
+        return Err(Oid4vciError::RequestError(format!(
+            "notification failed: {}",
+            response.status()
+        )));
+    }
+
+    Ok(())
+}
+
+/// A lifecycle hook notifying issuers when their credentials are stored or
+/// deleted, for the credentials stored with the metadata of
+/// [oid4vci_notification_metadata].
+///
+/// Notifications are sent in the background, and failures are only logged,
+/// as they do not affect the credentials.
+#[derive(uniffi::Object)]
+pub struct Oid4vciNotifier {
+    http_client: Arc<IHttpClient>,
+}
+
+#[uniffi::export]
+impl Oid4vciNotifier {
+    #[uniffi::constructor]
+    pub fn new(http_client: Arc<IHttpClient>) -> Arc<Self> {
+        Arc::new(Self { http_client })
+    }
+
+    /// Register the notifier as a lifecycle hook of the collection.
+    pub fn register(self: Arc<Self>, collection: Arc<VdcCollection>) {
+        collection.add_lifecycle_hook(self);
+    }
+}
+
+impl Oid4vciNotifier {
+    fn notify(&self, id: Uuid, metadata: CredentialMetadata, event: NotificationEvent) {
+        let Some(notification) = metadata
+            .attributes
+            .get(NOTIFICATION_ATTRIBUTE)
+            .and_then(|notification| serde_json::from_str(notification).ok())
+        else {
+            return;
+        };
+
+        let http_client = self.http_client.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            if let Err(e) = rt.block_on(oid4vci_notify(notification, event, None, http_client)) {
+                log::warn!("failed to notify the issuer of credential {id}: {e:?}");
+            }
+        });
+    }
+}
+
+impl std::fmt::Debug for Oid4vciNotifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Oid4vciNotifier").finish_non_exhaustive()
+    }
+}
+
+impl CredentialLifecycleHook for Oid4vciNotifier {
+    fn credential_added(&self, id: Uuid, metadata: CredentialMetadata) {
+        self.notify(id, metadata, NotificationEvent::CredentialAccepted)
+    }
+
+    fn credential_deleted(&self, id: Uuid, metadata: CredentialMetadata) {
+        self.notify(id, metadata, NotificationEvent::CredentialDeleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oid4vci::{AsyncHttpClient, HttpRequest, HttpResponse};
+
+    use std::{collections::HashMap, sync::Mutex};
+
+    use async_trait::async_trait;
+    use serde_json::Value as Json;
+
+    /// A notification endpoint recording the requests it receives.
+    #[derive(Default)]
+    struct TestEndpoint(Mutex<Vec<HttpRequest>>);
+
+    #[async_trait]
+    impl AsyncHttpClient for TestEndpoint {
+        async fn http_client(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+            self.0.lock().unwrap().push(request);
+            Ok(HttpResponse {
+                status_code: 204,
+                headers: HashMap::new(),
+                body: vec![],
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn notifies_issuers() {
+        let endpoint = Arc::new(TestEndpoint::default());
+        let http_client: Arc<dyn AsyncHttpClient> = endpoint.clone();
+        let notification = CredentialNotification {
+            notification_endpoint: "https://issuer.example.com/notification".into(),
+            notification_id: "3fwe98js".into(),
+            access_token: "token".into(),
+        };
+
+        let metadata = oid4vci_notification_metadata(notification.clone()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<CredentialNotification>(
+                &metadata.attributes[NOTIFICATION_ATTRIBUTE]
+            )
+            .unwrap(),
+            notification
+        );
+
+        oid4vci_notify(
+            notification,
+            NotificationEvent::CredentialDeleted,
+            None,
+            Arc::new(http_client.into()),
+        )
+        .await
+        .unwrap();
+
+        let requests = endpoint.0.lock().unwrap();
+        assert_eq!(requests[0].url, "https://issuer.example.com/notification");
+        assert_eq!(requests[0].headers["authorization"], "Bearer token");
+        let body: Json = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(
+            body,
+            json!({ "notification_id": "3fwe98js", "event": "credential_deleted" })
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Credential offers, previewed before the app commits to the issuance.
+
+use std::{str::FromStr, sync::Arc};
+
+use oid4vci::oauth2::http::{Method, Request, Uri};
+use serde_json::Value as Json;
+use url::Url;
+
+use super::{HttpClientError, IHttpClient, Oid4vciError, TxCodeHint};
+use crate::credential::display::{credential_display_from_configuration, CredentialDisplay};
+
+/// The scheme of credential offer URLs.
+const CREDENTIAL_OFFER_SCHEME: &str = "openid-credential-offer";
+
+/// A credential offered by the issuer.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct OfferedCredential {
+    /// The ID of the credential configuration in the issuer metadata.
+    pub configuration_id: String,
+    /// The format of the credential, e.g. `jwt_vc_json`, if the issuer
+    /// metadata has its configuration.
+    pub format: Option<String>,
+    /// The types of the credential: the W3C types, the SD-JWT VC type, or the
+    /// mdoc doctype.
+    pub types: Vec<String>,
+    pub display: Vec<CredentialDisplay>,
+}
+
+/// A grant with which the offered credentials can be issued.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum OfferedGrant {
+    /// The offer is pre-authorized, and may require a transaction code.
+    PreAuthorizedCode { tx_code: Option<TxCodeHint> },
+    /// The user must authorize the issuance with the issuer.
+    AuthorizationCode { issuer_state: Option<String> },
+}
+
+/// What a credential offer offers, to display before the app commits to the
+/// issuance flow.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct CredentialOfferPreview {
+    pub credential_issuer: String,
+    /// The display metadata of the issuer, for every locale, e.g. its name.
+    pub issuer_display: Vec<CredentialDisplay>,
+    pub credentials: Vec<OfferedCredential>,
+    pub grants: Vec<OfferedGrant>,
+}
+
+/// A credential offer, resolved along with the metadata of its issuer.
+#[derive(Debug, uniffi::Object)]
+pub struct CredentialOffer {
+    offer: Json,
+    preview: CredentialOfferPreview,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl CredentialOffer {
+    /// Parse a credential offer, given as an `openid-credential-offer://` URL
+    /// or as JSON, resolving the offer by reference and the issuer metadata.
+    #[uniffi::constructor]
+    pub async fn parse(
+        url_or_json: String,
+        http_client: Arc<IHttpClient>,
+    ) -> Result<Arc<Self>, Oid4vciError> {
+        let offer = match serde_json::from_str::<Json>(&url_or_json) {
+            Ok(offer @ Json::Object(_)) => offer,
+            _ => {
+                let url = Url::parse(&url_or_json).map_err(|_| {
+                    Oid4vciError::InvalidParameter("invalid credential_offer: not a URL".into())
+                })?;
+                let parameter = |name: &str| {
+                    url.query_pairs()
+                        .find(|(key, _)| key == name)
+                        .map(|(_, v)| v)
+                };
+
+                match (
+                    parameter("credential_offer"),
+                    parameter("credential_offer_uri"),
+                ) {
+                    (Some(offer), _) => serde_json::from_str(&offer)?,
+                    (None, Some(offer_uri)) => get_json(&offer_uri, &http_client).await?,
+                    (None, None) => {
+                        return Err(Oid4vciError::InvalidParameter(
+                            "invalid credential_offer: no offer in the URL".into(),
+                        ))
+                    }
+                }
+            }
+        };
+
+        let credential_issuer = offer["credential_issuer"]
+            .as_str()
+            .ok_or_else(|| {
+                Oid4vciError::InvalidParameter(
+                    "invalid credential_offer: missing credential_issuer".into(),
+                )
+            })?
+            .to_owned();
+        let metadata = get_json(
+            &format!(
+                "{}/.well-known/openid-credential-issuer",
+                credential_issuer.trim_end_matches('/')
+            ),
+            &http_client,
+        )
+        .await?;
+
+        let preview = preview(&offer, &metadata, credential_issuer);
+        Ok(Arc::new(Self { offer, preview }))
+    }
+
+    pub fn preview(&self) -> CredentialOfferPreview {
+        self.preview.clone()
+    }
+
+    /// Return the offer as an `openid-credential-offer://` URL passing it by
+    /// value, e.g. for [super::oid4vci_initiate_with_offer].
+    pub fn url(&self) -> String {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("credential_offer", &self.offer.to_string())
+            .finish();
+        format!("{CREDENTIAL_OFFER_SCHEME}://?{query}")
+    }
+}
+
+/// Return the preview of an offer, from the metadata of its issuer.
+fn preview(offer: &Json, metadata: &Json, credential_issuer: String) -> CredentialOfferPreview {
+    let credentials = offer["credential_configuration_ids"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Json::as_str)
+        .map(|configuration_id| {
+            let configuration = &metadata["credential_configurations_supported"][configuration_id];
+            let types = match (
+                &configuration["credential_definition"]["type"],
+                &configuration["vct"],
+                &configuration["doctype"],
+            ) {
+                (Json::Array(types), _, _) => types
+                    .iter()
+                    .filter_map(Json::as_str)
+                    .map(ToOwned::to_owned)
+                    .collect(),
+                (_, Json::String(vct), _) => vec![vct.clone()],
+                (_, _, Json::String(doctype)) => vec![doctype.clone()],
+                _ => vec![],
+            };
+
+            OfferedCredential {
+                configuration_id: configuration_id.to_owned(),
+                format: configuration["format"].as_str().map(ToOwned::to_owned),
+                types,
+                display: credential_display_from_configuration(configuration),
+            }
+        })
+        .collect();
+
+    let grants = &offer["grants"];
+    let mut offered_grants = vec![];
+    if let Some(grant) = grants
+        .get("urn:ietf:params:oauth:grant-type:pre-authorized_code")
+        .filter(|grant| grant.is_object())
+    {
+        offered_grants.push(OfferedGrant::PreAuthorizedCode {
+            tx_code: grant.get("tx_code").map(TxCodeHint::from_grant),
+        });
+    }
+    if let Some(grant) = grants
+        .get("authorization_code")
+        .filter(|grant| grant.is_object())
+    {
+        offered_grants.push(OfferedGrant::AuthorizationCode {
+            issuer_state: grant["issuer_state"].as_str().map(ToOwned::to_owned),
+        });
+    }
+
+    CredentialOfferPreview {
+        credential_issuer,
+        issuer_display: credential_display_from_configuration(metadata),
+        credentials,
+        grants: offered_grants,
+    }
+}
+
+async fn get_json(url: &str, http_client: &IHttpClient) -> Result<Json, Oid4vciError> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(Uri::from_str(url).map_err(|_| HttpClientError::UrlParse)?)
+        .body(vec![])
+        .map_err(|_| HttpClientError::RequestBuilder)?;
+
+    let response = http_client.call(request).await?;
+    if !response.status().is_success() {
+        return Err(Oid4vciError::RequestError(format!(
+            "failed to retrieve {url}: {}",
+            response.status()
+        )));
+    }
+    Ok(serde_json::from_slice(response.body())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oid4vci::{AsyncHttpClient, HttpRequest, HttpResponse};
+
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    /// An issuer serving its metadata.
+    struct TestIssuer;
+
+    #[async_trait]
+    impl AsyncHttpClient for TestIssuer {
+        async fn http_client(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+            assert_eq!(
+                request.url,
+                "https://issuer.example.com/.well-known/openid-credential-issuer"
+            );
+            let metadata = json!({
+                "credential_issuer": "https://issuer.example.com",
+                "display": [{ "name": "Example University", "locale": "en-US" }],
+                "credential_configurations_supported": {
+                    "UniversityDegree": {
+                        "format": "jwt_vc_json",
+                        "credential_definition": {
+                            "type": ["VerifiableCredential", "UniversityDegreeCredential"]
+                        },
+                        "display": [{ "name": "University Degree" }],
+                    },
+                },
+            });
+            Ok(HttpResponse {
+                status_code: 200,
+                headers: HashMap::new(),
+                body: metadata.to_string().into_bytes(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn previews_offers() {
+        let http_client: Arc<dyn AsyncHttpClient> = Arc::new(TestIssuer);
+        let offer = json!({
+            "credential_issuer": "https://issuer.example.com",
+            "credential_configuration_ids": ["UniversityDegree"],
+            "grants": {
+                "urn:ietf:params:oauth:grant-type:pre-authorized_code": {
+                    "pre-authorized_code": "oaKazRN8I0IbtZ0C7JuMn5",
+                    "tx_code": { "length": 4, "input_mode": "numeric" },
+                },
+            },
+        });
+        let url = format!(
+            "openid-credential-offer://?{}",
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("credential_offer", &offer.to_string())
+                .finish()
+        );
+
+        let offer = CredentialOffer::parse(url.clone(), Arc::new(http_client.into()))
+            .await
+            .unwrap();
+        let preview = offer.preview();
+        assert_eq!(preview.issuer_display[0].name, "Example University");
+        assert_eq!(
+            preview.credentials[0].types,
+            ["VerifiableCredential", "UniversityDegreeCredential"]
+        );
+        assert_eq!(preview.credentials[0].display[0].name, "University Degree");
+        assert!(matches!(
+            &preview.grants[..],
+            [OfferedGrant::PreAuthorizedCode {
+                tx_code: Some(TxCodeHint {
+                    length: Some(4),
+                    ..
+                })
+            }]
+        ));
+        assert_eq!(offer.url(), url);
+    }
+}
@@ -0,0 +1,203 @@
+//! Certificate pinning for the built-in HTTP client.
+
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+
+use base64::prelude::*;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, CertificateError, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+};
+use sha2::{Digest, Sha256};
+use uniffi::deps::anyhow;
+use x509_cert::der::{Decode, Encode};
+
+use super::HttpClientError;
+
+/// TLS configuration restricting which servers the built-in HTTP client
+/// connects to.
+#[derive(uniffi::Record, Clone, Debug, Default)]
+pub struct TlsPinningConfig {
+    /// The base64 encoded SHA-256 hashes of the SubjectPublicKeyInfo pinned
+    /// for a host, by host name.
+    ///
+    /// A connection to a host with pins succeeds only when a certificate in
+    /// the chain presented by the server matches one of them. Connections to
+    /// other hosts are not pinned.
+    pub spki_pins: HashMap<String, Vec<String>>,
+    /// DER encoded root certificates, trusted instead of the built-in roots
+    /// when any is set.
+    pub root_certificates: Vec<Vec<u8>>,
+}
+
+impl TlsPinningConfig {
+    pub(crate) fn client_config(&self) -> Result<ClientConfig, HttpClientError> {
+        let mut roots = RootCertStore::empty();
+        if self.root_certificates.is_empty() {
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    anchor.subject,
+                    anchor.spki,
+                    anchor.name_constraints,
+                )
+            }));
+        }
+        for certificate in &self.root_certificates {
+            roots
+                .add(&Certificate(certificate.clone()))
+                .map_err(|e| HttpClientError::Other {
+                    error: format!("invalid root certificate: {e:?}"),
+                })?;
+        }
+
+        let verifier = PinningVerifier {
+            inner: WebPkiVerifier::new(roots, None),
+            spki_pins: self.spki_pins.clone(),
+        };
+
+        Ok(ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth())
+    }
+}
+
+/// The certificate chain of a host did not match its pins.
+#[derive(thiserror::Error, Debug)]
+#[error("certificate of {host} does not match its pins")]
+struct PinMismatch {
+    host: String,
+}
+
+/// Verifies certificates against the roots, then against the pins of the
+/// host.
+struct PinningVerifier {
+    inner: WebPkiVerifier,
+    spki_pins: HashMap<String, Vec<String>>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_string(),
+            ServerName::IpAddress(address) => address.to_string(),
+            _ => return Ok(verified),
+        };
+        let Some(pins) = self.spki_pins.get(&host) else {
+            return Ok(verified);
+        };
+
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|certificate| spki_hash(&certificate.0))
+            .any(|hash| pins.contains(&hash));
+        if !pinned {
+            return Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                Arc::new(PinMismatch { host }),
+            )));
+        }
+
+        Ok(verified)
+    }
+}
+
+/// Compute the base64 encoded SHA-256 hash of the SubjectPublicKeyInfo of a
+/// DER encoded certificate.
+fn spki_hash(certificate: &[u8]) -> Option<String> {
+    let certificate = x509_cert::Certificate::from_der(certificate).ok()?;
+    let spki = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .ok()?;
+    Some(BASE64_STANDARD.encode(Sha256::digest(spki)))
+}
+
+/// Find the host whose pins caused the error, if any.
+pub(crate) fn pin_mismatch_host(error: &(dyn std::error::Error + 'static)) -> Option<String> {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        // TLS errors are reported as the inner error of an I/O error.
+        let tls_error = error.downcast_ref::<rustls::Error>().or_else(|| {
+            error
+                .downcast_ref::<std::io::Error>()
+                .and_then(std::io::Error::get_ref)
+                .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+        });
+        if let Some(rustls::Error::InvalidCertificate(CertificateError::Other(other))) = tls_error {
+            if let Some(mismatch) = other.downcast_ref::<PinMismatch>() {
+                return Some(mismatch.host.clone());
+            }
+        }
+        source = error.source();
+    }
+    None
+}
+
+/// Find the host whose pins caused an HTTP request to fail, if any.
+pub(crate) fn certificate_pinning_host(error: &anyhow::Error) -> Option<String> {
+    error.chain().find_map(|error| match error.downcast_ref() {
+        Some(HttpClientError::CertificatePinning { host }) => Some(host.clone()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_pin_mismatches() {
+        let tls_error =
+            rustls::Error::InvalidCertificate(CertificateError::Other(Arc::new(PinMismatch {
+                host: "verifier.example.com".into(),
+            })));
+        let io_error = std::io::Error::new(std::io::ErrorKind::InvalidData, tls_error);
+        assert_eq!(
+            pin_mismatch_host(&io_error).as_deref(),
+            Some("verifier.example.com")
+        );
+
+        let io_error = std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(CertificateError::Expired),
+        );
+        assert_eq!(pin_mismatch_host(&io_error), None);
+
+        let error = anyhow::Error::new(HttpClientError::CertificatePinning {
+            host: "verifier.example.com".into(),
+        })
+        .context("failed to fetch the request");
+        assert_eq!(
+            certificate_pinning_host(&error).as_deref(),
+            Some("verifier.example.com")
+        );
+    }
+
+    #[test]
+    fn builds_client_configs() {
+        assert!(TlsPinningConfig::default().client_config().is_ok());
+        assert!(TlsPinningConfig {
+            root_certificates: vec![b"not a certificate".to_vec()],
+            ..Default::default()
+        }
+        .client_config()
+        .is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Re-binding of stored credentials to a new device key, e.g. after the OS
+//! invalidated the previous key on a biometric reset.
+//!
+//! Credentials bound to the previous key are refreshed with a proof of
+//! possession of the new key when their issuer supports it, see
+//! [oid4vci_refresh_credential]. The others can no longer be presented, and
+//! are marked with [KEY_REBINDING_ATTRIBUTE] for the app to have them issued
+//! again.
+
+use std::{collections::HashMap, sync::Arc};
+
+use super::{refresh::refresh_credential, IHttpClient, Oid4vciError, REFRESH_ATTRIBUTE};
+use crate::common::{KeyAlias, Uuid};
+use crate::signer::DeviceSigner;
+use crate::vdc_collection::{VdcCollection, VdcCollectionError};
+
+/// The attribute of the credential metadata marking credentials that could
+/// not be re-bound, holding the alias of the key they are bound to.
+pub const KEY_REBINDING_ATTRIBUTE: &str = "key_rebinding_required";
+
+/// The outcome of re-binding a credential.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum KeyRebindingOutcome {
+    /// The issuer issued the credential again, bound to the new key.
+    Rebound,
+    /// The issuer cannot issue the credential again without a new issuance,
+    /// as it was not stored with the metadata to refresh it.
+    NotRebindable,
+    /// Refreshing the credential failed, and can be retried.
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct KeyRebinding {
+    pub id: Uuid,
+    pub outcome: KeyRebindingOutcome,
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Get a list of the credentials bound to the key with the alias.
+    pub fn bound_to_key(&self, key_alias: KeyAlias) -> Result<Vec<Uuid>, VdcCollectionError> {
+        let mut ids = Vec::new();
+        for id in self.all_entries()? {
+            if let Some(credential) = self.get(id)? {
+                if credential.key_alias.as_ref() == Some(&key_alias) {
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Get a list of the credentials that could not be re-bound to a new key,
+    /// see [oid4vci_rebind_credentials].
+    pub fn requiring_rebinding(&self) -> Result<Vec<Uuid>, VdcCollectionError> {
+        let mut ids = Vec::new();
+        for id in self.all_entries()? {
+            if self
+                .metadata(id)?
+                .attributes
+                .contains_key(KEY_REBINDING_ATTRIBUTE)
+            {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+}
+
+/// Re-bind the credentials bound to a previous key to a new key, refreshing
+/// them with proofs of possession of the new key signed by the signer.
+///
+/// Credentials that are not re-bound are marked with
+/// [KEY_REBINDING_ATTRIBUTE], which is removed once they are. Calling this
+/// again retries the credentials that failed.
+///
+/// NOTE: credentials issued in batches are refreshed one by one, each
+/// replacing a single credential of the batch.
+#[uniffi::export]
+pub async fn oid4vci_rebind_credentials(
+    collection: Arc<VdcCollection>,
+    previous_key_alias: KeyAlias,
+    new_key_alias: KeyAlias,
+    signer: Option<Arc<dyn DeviceSigner>>,
+    context_map: Option<HashMap<String, String>>,
+    http_client: Arc<IHttpClient>,
+) -> Result<Vec<KeyRebinding>, Oid4vciError> {
+    let mut rebindings = Vec::new();
+
+    for id in collection.bound_to_key(previous_key_alias.clone())? {
+        let refreshable = collection
+            .metadata(id)?
+            .attributes
+            .contains_key(REFRESH_ATTRIBUTE);
+        let outcome = if !refreshable {
+            KeyRebindingOutcome::NotRebindable
+        } else {
+            match refresh_credential(
+                collection.clone(),
+                id,
+                Some(new_key_alias.clone()),
+                signer.clone(),
+                context_map.clone(),
+                http_client.clone(),
+            )
+            .await
+            {
+                Ok(_) => KeyRebindingOutcome::Rebound,
+                Err(e) => KeyRebindingOutcome::Failed {
+                    error: format!("{e:?}"),
+                },
+            }
+        };
+
+        match outcome {
+            KeyRebindingOutcome::Rebound => {
+                collection.remove_attribute(id, KEY_REBINDING_ATTRIBUTE.to_string())?
+            }
+            _ => collection.set_attribute(
+                id,
+                KEY_REBINDING_ATTRIBUTE.to_string(),
+                previous_key_alias.0.clone(),
+            )?,
+        }
+        rebindings.push(KeyRebinding { id, outcome });
+    }
+
+    Ok(rebindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::CredentialType;
+    use crate::credential::{Credential, CredentialFormat};
+    use crate::local_store::LocalStore;
+    use crate::oid4vci::{AsyncHttpClient, ReqwestHttpClient};
+
+    fn credential(key_alias: Option<&str>) -> Credential {
+        Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: vec![],
+            key_alias: key_alias.map(|alias| KeyAlias(alias.into())),
+            display: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn marks_credentials_that_cannot_be_rebound() {
+        let collection = Arc::new(VdcCollection::new(Arc::new(LocalStore::new())));
+        let (bound, other, unbound) = (
+            credential(Some("previous")),
+            credential(Some("other")),
+            credential(None),
+        );
+        for credential in [&bound, &other, &unbound] {
+            collection.add(credential).unwrap();
+        }
+        assert_eq!(
+            collection
+                .bound_to_key(KeyAlias("previous".into()))
+                .unwrap(),
+            vec![bound.id]
+        );
+
+        let http_client: Arc<dyn AsyncHttpClient> =
+            Arc::new(ReqwestHttpClient::new(&Default::default()).unwrap());
+        let rebindings = oid4vci_rebind_credentials(
+            collection.clone(),
+            KeyAlias("previous".into()),
+            KeyAlias("new".into()),
+            None,
+            None,
+            Arc::new(http_client.into()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            rebindings,
+            vec![KeyRebinding {
+                id: bound.id,
+                outcome: KeyRebindingOutcome::NotRebindable,
+            }]
+        );
+        assert_eq!(collection.requiring_rebinding().unwrap(), vec![bound.id]);
+        assert_eq!(
+            collection.metadata(bound.id).unwrap().attributes[KEY_REBINDING_ATTRIBUTE],
+            "previous"
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Refresh of stored credentials, re-issuing them before they expire with the
+//! grant of their original issuance.
+
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use oid4vci::oauth2::http::{header, Method, Request, Uri};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as Json};
+
+use super::deferred::{credentials_of, verify_credentials};
+use super::{
+    CredentialNotification, CredentialResponse, HttpClientError, IHttpClient, Oid4vciError,
+    NOTIFICATION_ATTRIBUTE,
+};
+use crate::common::{KeyAlias, Uuid};
+use crate::credential::Credential;
+use crate::proof_of_possession::{device_proof, ProofType};
+use crate::signer::DeviceSigner;
+use crate::vdc_collection::{CredentialMetadata, VdcCollection};
+
+/// The attribute of the credential metadata holding how to refresh it.
+pub const REFRESH_ATTRIBUTE: &str = "oid4vci_refresh";
+
+/// What is needed to request a credential again from its issuer, taken from
+/// the issuer metadata and the token response of its issuance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct CredentialRefresh {
+    pub credential_issuer: String,
+    pub token_endpoint: String,
+    pub credential_endpoint: String,
+    pub nonce_endpoint: Option<String>,
+    /// The refresh token, if the issuer granted one. Otherwise the access
+    /// token is reused, as long as it is valid.
+    pub refresh_token: Option<String>,
+    pub access_token: String,
+    /// The credential request of the issuance, as JSON.
+    pub credential_request: String,
+}
+
+/// Return the metadata to store an issued credential with, e.g. with
+/// [VdcCollection::add_with_metadata], for its issuer to be notified and for
+/// the credential to be refreshed.
+#[uniffi::export]
+pub fn oid4vci_credential_metadata(
+    response: CredentialResponse,
+) -> Result<CredentialMetadata, Oid4vciError> {
+    let mut attributes = HashMap::new();
+    if let Some(notification) = response.notification {
+        attributes.insert(
+            NOTIFICATION_ATTRIBUTE.to_string(),
+            serde_json::to_string(&notification)?,
+        );
+    }
+    if let Some(refresh) = response.refresh {
+        attributes.insert(
+            REFRESH_ATTRIBUTE.to_string(),
+            serde_json::to_string(&refresh)?,
+        );
+    }
+
+    Ok(CredentialMetadata {
+        attributes,
+        ..Default::default()
+    })
+}
+
+/// Refresh a stored credential, replacing it with the credential the issuer
+/// issues again, e.g. before it expires.
+///
+/// The credential keeps its ID, so that its tags, attributes and presentation
+/// history are preserved. Credentials bound to a key are requested with a
+/// proof of possession signed by the signer, with their key alias.
+///
+/// The credential must have been stored with the metadata of
+/// [oid4vci_credential_metadata].
+#[uniffi::export]
+pub async fn oid4vci_refresh_credential(
+    collection: Arc<VdcCollection>,
+    id: Uuid,
+    signer: Option<Arc<dyn DeviceSigner>>,
+    context_map: Option<HashMap<String, String>>,
+    http_client: Arc<IHttpClient>,
+) -> Result<Credential, Oid4vciError> {
+    refresh_credential(collection, id, None, signer, context_map, http_client).await
+}
+
+/// Refresh a stored credential, binding it to the key with the alias if one
+/// is given, or else to its current key.
+pub(super) async fn refresh_credential(
+    collection: Arc<VdcCollection>,
+    id: Uuid,
+    key_alias: Option<KeyAlias>,
+    signer: Option<Arc<dyn DeviceSigner>>,
+    context_map: Option<HashMap<String, String>>,
+    http_client: Arc<IHttpClient>,
+) -> Result<Credential, Oid4vciError> {
+    let credential = collection
+        .get(id)?
+        .ok_or_else(|| Oid4vciError::InvalidParameter(format!("unknown credential: {id}")))?;
+    let mut metadata = collection.metadata(id)?;
+    let mut refresh: CredentialRefresh = metadata
+        .attributes
+        .get(REFRESH_ATTRIBUTE)
+        .and_then(|refresh| serde_json::from_str(refresh).ok())
+        .ok_or_else(|| {
+            Oid4vciError::InvalidParameter(format!("the credential cannot be refreshed: {id}"))
+        })?;
+
+    let mut nonce = None;
+    if let Some(refresh_token) = &refresh.refresh_token {
+        let tokens = refresh_tokens(&refresh.token_endpoint, refresh_token, &http_client).await?;
+        refresh.access_token = tokens["access_token"]
+            .as_str()
+            .ok_or_else(|| {
+                Oid4vciError::RequestError("the token response has no access_token".into())
+            })?
+            .to_owned();
+        // Refresh tokens may be rotated.
+        if let Some(refresh_token) = tokens["refresh_token"].as_str() {
+            refresh.refresh_token = Some(refresh_token.to_owned());
+        }
+        nonce = tokens["c_nonce"].as_str().map(ToOwned::to_owned);
+    }
+    if nonce.is_none() {
+        if let Some(nonce_endpoint) = &refresh.nonce_endpoint {
+            nonce = post_json(nonce_endpoint, None, None, &http_client).await?["c_nonce"]
+                .as_str()
+                .map(ToOwned::to_owned);
+        }
+    }
+
+    let key_alias = key_alias.or_else(|| credential.key_alias.clone());
+    let mut credential_request: Json = serde_json::from_str(&refresh.credential_request)?;
+    if let (Some(key_alias), Some(signer)) = (&key_alias, &signer) {
+        credential_request["proof"] = json!({
+            "proof_type": "jwt",
+            "jwt": device_proof(
+                ProofType::Jwt,
+                &refresh.credential_issuer,
+                None,
+                nonce,
+                signer.as_ref(),
+                key_alias,
+            )
+            .await
+            .map_err(|e| Oid4vciError::Generic(format!("{e:?}")))?,
+        });
+    }
+
+    let body = post_json(
+        &refresh.credential_endpoint,
+        Some(&refresh.access_token),
+        Some(credential_request),
+        &http_client,
+    )
+    .await?;
+    let format = serde_json::from_str::<Json>(&refresh.credential_request)?["format"]
+        .as_str()
+        .unwrap_or_default()
+        .to_owned();
+    let (format, payload) = verify_credentials(&format, credentials_of(&body)?, context_map)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Oid4vciError::RequestError("the issuer issued no credential".into()))?;
+
+    // The notifications of the new credential use the new access token.
+    if let Some(notification_id) = body["notification_id"].as_str() {
+        if let Some(notification) =
+            metadata
+                .attributes
+                .get(NOTIFICATION_ATTRIBUTE)
+                .and_then(|notification| {
+                    serde_json::from_str::<CredentialNotification>(notification).ok()
+                })
+        {
+            let notification = CredentialNotification {
+                notification_id: notification_id.to_owned(),
+                access_token: refresh.access_token.clone(),
+                ..notification
+            };
+            metadata.attributes.insert(
+                NOTIFICATION_ATTRIBUTE.to_string(),
+                serde_json::to_string(&notification)?,
+            );
+        }
+    }
+    metadata.attributes.insert(
+        REFRESH_ATTRIBUTE.to_string(),
+        serde_json::to_string(&refresh)?,
+    );
+
+    let refreshed = Credential {
+        format,
+        payload,
+        key_alias,
+        ..credential
+    };
+    // Replacing the credential keeps its index entry and its metadata.
+    collection.add(&refreshed)?;
+    collection.set_metadata(id, metadata)?;
+
+    Ok(refreshed)
+}
+
+/// Exchange a refresh token at the token endpoint.
+async fn refresh_tokens(
+    token_endpoint: &str,
+    refresh_token: &str,
+    http_client: &IHttpClient,
+) -> Result<Json, Oid4vciError> {
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("grant_type", "refresh_token")
+        .append_pair("refresh_token", refresh_token)
+        .finish();
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(Uri::from_str(token_endpoint).map_err(|_| HttpClientError::UrlParse)?)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(body.into_bytes())
+        .map_err(|_| HttpClientError::RequestBuilder)?;
+
+    let response = http_client.call(request).await?;
+    if !response.status().is_success() {
+        return Err(Oid4vciError::RequestError(format!(
+            "failed to refresh the access token: {}",
+            response.status()
+        )));
+    }
+    Ok(serde_json::from_slice(response.body())?)
+}
+
+pub(super) async fn post_json(
+    endpoint: &str,
+    access_token: Option<&str>,
+    body: Option<Json>,
+    http_client: &IHttpClient,
+) -> Result<Json, Oid4vciError> {
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(Uri::from_str(endpoint).map_err(|_| HttpClientError::UrlParse)?);
+    if let Some(access_token) = access_token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {access_token}"));
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string().into_bytes()),
+        None => request.body(vec![]),
+    }
+    .map_err(|_| HttpClientError::RequestBuilder)?;
+
+    let response = http_client.call(request).await?;
+    if !response.status().is_success() {
+        return Err(Oid4vciError::RequestError(format!(
+            "request to {endpoint} failed: {}",
+            response.status()
+        )));
+    }
+    Ok(serde_json::from_slice(response.body())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::CredentialFormat;
+
+    #[test]
+    fn stores_refresh_metadata() {
+        let refresh = CredentialRefresh {
+            credential_issuer: "https://issuer.example.com".into(),
+            token_endpoint: "https://issuer.example.com/token".into(),
+            credential_endpoint: "https://issuer.example.com/credential".into(),
+            nonce_endpoint: None,
+            refresh_token: Some("refresh".into()),
+            access_token: "token".into(),
+            credential_request: json!({ "format": "jwt_vc_json" }).to_string(),
+        };
+        let metadata = oid4vci_credential_metadata(CredentialResponse {
+            format: CredentialFormat::JwtVcJson,
+            payload: vec![],
+            display: vec![],
+            batch_id: None,
+            notification: None,
+            refresh: Some(refresh.clone()),
+        })
+        .unwrap();
+
+        assert!(!metadata.attributes.contains_key(NOTIFICATION_ATTRIBUTE));
+        assert_eq!(
+            serde_json::from_str::<CredentialRefresh>(&metadata.attributes[REFRESH_ATTRIBUTE])
+                .unwrap(),
+            refresh
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Replenishment of pools of single-use credentials, see
+//! [VdcCollection::pool_status].
+//!
+//! The consumed instances of a pool are refreshed one by one, the issuer
+//! issuing each of them again as a fresh instance that can be presented
+//! once more, see [oid4vci_refresh_credential].
+
+use std::{collections::HashMap, sync::Arc};
+
+use super::{refresh::refresh_credential, IHttpClient, Oid4vciError};
+use crate::common::Uuid;
+use crate::signer::DeviceSigner;
+use crate::vdc_collection::{PoolStatus, VdcCollection, POOL_CONSUMED_ATTRIBUTE};
+
+/// Replenish the pool of a credential, refreshing its consumed instances, and
+/// return its status.
+///
+/// Replenishing stops at the first instance that fails to be refreshed, and
+/// calling this again resumes with it.
+#[uniffi::export]
+pub async fn oid4vci_replenish_pool(
+    collection: Arc<VdcCollection>,
+    id: Uuid,
+    signer: Option<Arc<dyn DeviceSigner>>,
+    context_map: Option<HashMap<String, String>>,
+    http_client: Arc<IHttpClient>,
+) -> Result<PoolStatus, Oid4vciError> {
+    pool_status(&collection, id)?;
+
+    for instance in collection.batch(id)? {
+        if !collection
+            .metadata(instance)?
+            .attributes
+            .contains_key(POOL_CONSUMED_ATTRIBUTE)
+        {
+            continue;
+        }
+
+        refresh_credential(
+            collection.clone(),
+            instance,
+            None,
+            signer.clone(),
+            context_map.clone(),
+            http_client.clone(),
+        )
+        .await?;
+        collection.remove_attribute(instance, POOL_CONSUMED_ATTRIBUTE.to_string())?;
+    }
+
+    pool_status(&collection, id)
+}
+
+fn pool_status(collection: &VdcCollection, id: Uuid) -> Result<PoolStatus, Oid4vciError> {
+    collection.pool_status(id)?.ok_or_else(|| {
+        Oid4vciError::InvalidParameter(format!("the credential is not pooled: {id}"))
+    })
+}
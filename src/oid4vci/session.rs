@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use futures::lock::Mutex;
 use oid4vci::{
     core::{
@@ -8,17 +10,24 @@ use oid4vci::{
     token,
 };
 
-use crate::credential::CredentialFormat;
+use crate::common::Uuid;
+use crate::credential::{display::CredentialDisplay, CredentialFormat};
 
-use super::Oid4vciError;
+use super::{
+    CredentialNotification, CredentialRefresh, DeferredIssuance, IssuerTrustVerdict, Oid4vciError,
+};
 
 #[derive(uniffi::Object)]
 pub struct Oid4vciSession {
     client: Client,
     metadata: Option<CredentialIssuerMetadata>,
+    authorization_server_metadata: Option<serde_json::Value>,
     token_response: Mutex<Option<TokenResponse>>,
     credential_request: Mutex<Option<CredentialRequest>>,
     grants: Mutex<Option<Grants>>,
+    credential_display: Mutex<Vec<Vec<CredentialDisplay>>>,
+    deferred_issuances: Mutex<Vec<Arc<DeferredIssuance>>>,
+    issuer_trust: Mutex<Option<IssuerTrustVerdict>>,
 }
 
 // TODO: some or all of these getters/setters can be converted to macros
@@ -27,9 +36,13 @@ impl Oid4vciSession {
         Self {
             client,
             metadata: None,
+            authorization_server_metadata: None,
             token_response: None.into(),
             credential_request: None.into(),
             grants: None.into(),
+            credential_display: Vec::new().into(),
+            deferred_issuances: Vec::new().into(),
+            issuer_trust: None.into(),
         }
     }
 
@@ -48,6 +61,19 @@ impl Oid4vciSession {
         self.metadata = Some(metadata);
     }
 
+    /// Return the metadata of the authorization server, as JSON.
+    pub fn get_authorization_server_metadata(&self) -> Result<&serde_json::Value, Oid4vciError> {
+        self.authorization_server_metadata
+            .as_ref()
+            .ok_or(Oid4vciError::InvalidSession(
+                "authorization_server_metadata unset".into(),
+            ))
+    }
+
+    pub fn set_authorization_server_metadata(&mut self, metadata: serde_json::Value) {
+        self.authorization_server_metadata = Some(metadata);
+    }
+
     pub fn get_token_response(&self) -> Result<token::Response, Oid4vciError> {
         self.token_response
             .try_lock()
@@ -122,6 +148,67 @@ impl Oid4vciSession {
 
         Ok(())
     }
+
+    /// Return the display metadata of the credentials, in the order of the
+    /// credential requests.
+    pub fn get_credential_display(&self) -> Result<Vec<Vec<CredentialDisplay>>, Oid4vciError> {
+        Ok(self
+            .credential_display
+            .try_lock()
+            .ok_or(Oid4vciError::LockError("credential_display".into()))?
+            .clone())
+    }
+
+    pub fn set_credential_display(
+        &self,
+        credential_display: Vec<Vec<CredentialDisplay>>,
+    ) -> Result<(), Oid4vciError> {
+        *(self
+            .credential_display
+            .try_lock()
+            .ok_or(Oid4vciError::LockError("credential_display".into()))?) = credential_display;
+
+        Ok(())
+    }
+
+    /// Return the credentials the issuer deferred during the session.
+    pub fn get_deferred_issuances(&self) -> Result<Vec<Arc<DeferredIssuance>>, Oid4vciError> {
+        Ok(self
+            .deferred_issuances
+            .try_lock()
+            .ok_or(Oid4vciError::LockError("deferred_issuances".into()))?
+            .clone())
+    }
+
+    pub fn add_deferred_issuance(
+        &self,
+        deferred_issuance: Arc<DeferredIssuance>,
+    ) -> Result<(), Oid4vciError> {
+        self.deferred_issuances
+            .try_lock()
+            .ok_or(Oid4vciError::LockError("deferred_issuances".into()))?
+            .push(deferred_issuance);
+
+        Ok(())
+    }
+
+    /// Return the trust verdict of the issuer, if it was evaluated.
+    pub fn get_issuer_trust(&self) -> Result<Option<IssuerTrustVerdict>, Oid4vciError> {
+        Ok(self
+            .issuer_trust
+            .try_lock()
+            .ok_or(Oid4vciError::LockError("issuer_trust".into()))?
+            .clone())
+    }
+
+    pub fn set_issuer_trust(&self, verdict: IssuerTrustVerdict) -> Result<(), Oid4vciError> {
+        *(self
+            .issuer_trust
+            .try_lock()
+            .ok_or(Oid4vciError::LockError("issuer_trust".into()))?) = Some(verdict);
+
+        Ok(())
+    }
 }
 
 macro_rules! wrap_external_type {
@@ -155,4 +242,20 @@ impl From<profiles::CoreProfilesCredentialRequest> for CredentialRequest {
 pub struct CredentialResponse {
     pub format: CredentialFormat,
     pub payload: Vec<u8>,
+    /// The display metadata of the credential configuration, for every locale.
+    pub display: Vec<CredentialDisplay>,
+    /// The ID shared by the instances issued for the same credential request,
+    /// when several are issued, e.g. single-use mdocs.
+    ///
+    /// See [crate::vdc_collection::VdcCollection::add_batch].
+    pub batch_id: Option<Uuid>,
+    /// Where to notify the issuer once the credential is stored or deleted,
+    /// when the issuer supports notifications.
+    ///
+    /// See [super::Oid4vciNotifier].
+    pub notification: Option<CredentialNotification>,
+    /// How to refresh the credential before it expires.
+    ///
+    /// See [super::oid4vci_refresh_credential].
+    pub refresh: Option<CredentialRefresh>,
 }
@@ -0,0 +1,114 @@
+//! Transaction codes of pre-authorized issuances, which the user receives
+//! from the issuer through another channel, and enters during the issuance.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value as Json;
+
+use super::{AsyncHttpClient, HttpClientError, HttpRequest, HttpResponse, IHttpClient};
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum TxCodeError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
+    #[error("The user cancelled the transaction code entry")]
+    Cancelled,
+}
+
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for TxCodeError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        TxCodeError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+/// How the transaction code of a credential offer is to be entered.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct TxCodeHint {
+    /// Whether the code is `numeric` or `text`.
+    pub input_mode: String,
+    /// The length of the code, if the issuer gave it.
+    pub length: Option<u32>,
+    /// A description of the code to display, e.g. where the user received it.
+    pub description: Option<String>,
+}
+
+impl TxCodeHint {
+    /// Return the hint of the `tx_code` object of a pre-authorized code grant.
+    pub(crate) fn from_grant(tx_code: &Json) -> Self {
+        Self {
+            input_mode: tx_code["input_mode"]
+                .as_str()
+                .unwrap_or("numeric")
+                .to_owned(),
+            length: tx_code["length"]
+                .as_u64()
+                .and_then(|length| length.try_into().ok()),
+            description: tx_code["description"].as_str().map(ToOwned::to_owned),
+        }
+    }
+
+    /// Check whether a code matches the hint.
+    pub(crate) fn accepts(&self, tx_code: &str) -> bool {
+        self.length
+            .is_none_or(|length| tx_code.chars().count() == length as usize)
+            && (self.input_mode != "numeric" || tx_code.chars().all(|c| c.is_ascii_digit()))
+    }
+}
+
+/// Interface: TxCodeProvider
+///
+/// The TxCodeProvider collects the transaction code of an offer from the
+/// user, e.g. with a PIN pad matching the hint.
+#[uniffi::export(with_foreign)]
+#[async_trait]
+pub trait TxCodeProvider: Send + Sync {
+    async fn tx_code(&self, hint: TxCodeHint) -> Result<String, TxCodeError>;
+}
+
+/// An HTTP client adding the transaction code to the form parameters of the
+/// token request.
+pub(crate) struct TxCodeHttpClient {
+    pub inner: Arc<IHttpClient>,
+    pub tx_code: String,
+}
+
+#[async_trait]
+impl AsyncHttpClient for TxCodeHttpClient {
+    async fn http_client(&self, mut request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+        let parameter = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("tx_code", &self.tx_code)
+            .finish();
+        if !request.body.is_empty() {
+            request.body.push(b'&');
+        }
+        request.body.extend(parameter.into_bytes());
+
+        self.inner.call(request.try_into()?).await?.try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn checks_codes_against_hints() {
+        let hint = TxCodeHint::from_grant(&json!({
+            "length": 4,
+            "input_mode": "numeric",
+            "description": "Please provide the code sent to your email",
+        }));
+        assert_eq!(hint.length, Some(4));
+        assert!(hint.accepts("0123"));
+        assert!(!hint.accepts("012"));
+        assert!(!hint.accepts("01a3"));
+
+        let hint = TxCodeHint::from_grant(&json!({ "input_mode": "text" }));
+        assert!(hint.accepts("x7-Q"));
+        assert_eq!(hint.description, None);
+    }
+}
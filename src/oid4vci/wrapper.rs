@@ -4,16 +4,27 @@ use std::{
 };
 
 use super::{
-    oid4vci_exchange_credential, oid4vci_exchange_token, oid4vci_get_metadata, oid4vci_initiate,
-    oid4vci_initiate_with_offer, AsyncHttpClient, CredentialResponse, IHttpClient, Oid4vciError,
-    Oid4vciMetadata, Oid4vciSession, SyncHttpClient,
+    oid4vci_deferred_issuances, oid4vci_exchange_credential,
+    oid4vci_exchange_credential_with_signer, oid4vci_get_metadata, oid4vci_initiate,
+    oid4vci_initiate_with_offer, oid4vci_refresh_credential, AsyncHttpClient, CredentialResponse,
+    DeferredIssuance, DeferredIssuanceStatus, Dpop, HttpClientConfig, IHttpClient, Oid4vciError,
+    Oid4vciMetadata, Oid4vciSession, ReqwestHttpClient, SyncHttpClient, TxCodeProvider,
+    WalletAttestation,
 };
+use crate::common::{KeyAlias, Uuid};
+use crate::credential::Credential;
+use crate::proof_of_possession::ProofType;
+use crate::signer::DeviceSigner;
+use crate::vdc_collection::VdcCollection;
 
 #[derive(uniffi::Object)]
 pub struct Oid4vci {
     http_client: Arc<IHttpClient>,
     session: Mutex<Option<Arc<Oid4vciSession>>>,
     context_map: Mutex<Option<HashMap<String, String>>>,
+    wallet_attestation: Mutex<Option<Arc<WalletAttestation>>>,
+    dpop: Mutex<Option<Arc<Dpop>>>,
+    tx_code_provider: Mutex<Option<Arc<dyn TxCodeProvider>>>,
 }
 
 impl Oid4vci {
@@ -26,6 +37,38 @@ impl Oid4vci {
         Ok(context_map.clone())
     }
 
+    fn wallet_attestation(&self) -> Result<Option<Arc<WalletAttestation>>, Oid4vciError> {
+        let wallet_attestation = self
+            .wallet_attestation
+            .lock()
+            .map_err(|_| Oid4vciError::LockError("wallet_attestation".into()))?;
+
+        Ok(wallet_attestation.clone())
+    }
+
+    fn tx_code_provider(&self) -> Result<Option<Arc<dyn TxCodeProvider>>, Oid4vciError> {
+        let tx_code_provider = self
+            .tx_code_provider
+            .lock()
+            .map_err(|_| Oid4vciError::LockError("tx_code_provider".into()))?;
+
+        Ok(tx_code_provider.clone())
+    }
+
+    /// Return the HTTP client of the token and credential requests, which
+    /// carry DPoP proofs when DPoP is set.
+    fn token_http_client(&self) -> Result<Arc<IHttpClient>, Oid4vciError> {
+        let dpop = self
+            .dpop
+            .lock()
+            .map_err(|_| Oid4vciError::LockError("dpop".into()))?;
+
+        Ok(match dpop.as_ref() {
+            Some(dpop) => dpop.clone().http_client(self.http_client.clone()),
+            None => self.http_client.clone(),
+        })
+    }
+
     fn session(&self) -> Result<Arc<Oid4vciSession>, Oid4vciError> {
         let session = self
             .session
@@ -73,7 +116,16 @@ impl Oid4vci {
 
     #[uniffi::constructor(name = "new_with_default_async_client")]
     fn new_async() -> Arc<Self> {
-        todo!("add reqwest default async client")
+        let client = ReqwestHttpClient::new(&HttpClientConfig::default())
+            .expect("failed to build the default http client");
+        Self::with_async_client(Arc::new(client))
+    }
+
+    /// Use the built-in HTTP client, with the given configuration.
+    #[uniffi::constructor(name = "new_with_http_config")]
+    fn with_http_config(config: HttpClientConfig) -> Result<Arc<Self>, Oid4vciError> {
+        let client = ReqwestHttpClient::new(&config)?;
+        Ok(Self::with_async_client(Arc::new(client)))
     }
 
     #[uniffi::constructor(name = "new_with_sync_client")]
@@ -82,6 +134,9 @@ impl Oid4vci {
         Self {
             session: Mutex::new(None),
             context_map: Mutex::new(None),
+            wallet_attestation: Mutex::new(None),
+            dpop: Mutex::new(None),
+            tx_code_provider: Mutex::new(None),
             http_client,
         }
         .into()
@@ -93,6 +148,9 @@ impl Oid4vci {
         Self {
             session: Mutex::new(None),
             context_map: Mutex::new(None),
+            wallet_attestation: Mutex::new(None),
+            dpop: Mutex::new(None),
+            tx_code_provider: Mutex::new(None),
             http_client,
         }
         .into()
@@ -120,6 +178,51 @@ impl Oid4vci {
         Ok(())
     }
 
+    /// Authenticate token requests with the wallet attestation, as required
+    /// by issuers following HAIP.
+    fn set_wallet_attestation(
+        &self,
+        wallet_attestation: Arc<WalletAttestation>,
+    ) -> Result<(), Oid4vciError> {
+        let mut value = self
+            .wallet_attestation
+            .lock()
+            .map_err(|_| Oid4vciError::LockError("wallet_attestation".into()))?;
+
+        *value = Some(wallet_attestation);
+
+        Ok(())
+    }
+
+    /// Sender-constrain the access tokens with DPoP proofs, as required by
+    /// some issuers.
+    fn set_dpop(&self, dpop: Arc<Dpop>) -> Result<(), Oid4vciError> {
+        let mut value = self
+            .dpop
+            .lock()
+            .map_err(|_| Oid4vciError::LockError("dpop".into()))?;
+
+        *value = Some(dpop);
+
+        Ok(())
+    }
+
+    /// Collect the transaction codes offers require from the user, through
+    /// the provider.
+    fn set_tx_code_provider(
+        &self,
+        tx_code_provider: Arc<dyn TxCodeProvider>,
+    ) -> Result<(), Oid4vciError> {
+        let mut value = self
+            .tx_code_provider
+            .lock()
+            .map_err(|_| Oid4vciError::LockError("tx_code_provider".into()))?;
+
+        *value = Some(tx_code_provider);
+
+        Ok(())
+    }
+
     fn initiate_logger(&self) {
         #[cfg(target_os = "android")]
         android_logger::init_once(
@@ -161,7 +264,13 @@ impl Oid4vci {
     }
 
     async fn exchange_token(&self) -> Result<Option<String>, Oid4vciError> {
-        oid4vci_exchange_token(self.session()?, self.http_client.clone()).await
+        super::exchange_token(
+            self.session()?,
+            self.wallet_attestation()?,
+            self.tx_code_provider()?,
+            self.token_http_client()?,
+        )
+        .await
     }
 
     async fn exchange_credential(
@@ -172,7 +281,59 @@ impl Oid4vci {
             self.session()?,
             proofs_of_possession,
             self.context_map()?,
-            self.http_client.clone(),
+            self.token_http_client()?,
+        )
+        .await
+    }
+
+    /// Exchange the credentials, proving possession of the device keys through
+    /// the signer.
+    async fn exchange_credential_with_signer(
+        &self,
+        signer: Arc<dyn DeviceSigner>,
+        key_aliases: Vec<KeyAlias>,
+        proof_type: ProofType,
+    ) -> Result<Vec<CredentialResponse>, Oid4vciError> {
+        oid4vci_exchange_credential_with_signer(
+            self.session()?,
+            signer,
+            key_aliases,
+            proof_type,
+            self.context_map()?,
+            self.token_http_client()?,
+        )
+        .await
+    }
+
+    /// Return the credentials the issuer deferred during the session.
+    fn deferred_issuances(&self) -> Result<Vec<Arc<DeferredIssuance>>, Oid4vciError> {
+        oid4vci_deferred_issuances(self.session()?)
+    }
+
+    /// Poll a deferred issuance, of this session or restored from a previous
+    /// one.
+    async fn poll_deferred_issuance(
+        &self,
+        deferred_issuance: Arc<DeferredIssuance>,
+    ) -> Result<DeferredIssuanceStatus, Oid4vciError> {
+        deferred_issuance
+            .poll(self.context_map()?, self.token_http_client()?)
+            .await
+    }
+
+    /// Refresh a stored credential, see [oid4vci_refresh_credential].
+    async fn refresh_credential(
+        &self,
+        collection: Arc<VdcCollection>,
+        id: Uuid,
+        signer: Option<Arc<dyn DeviceSigner>>,
+    ) -> Result<Credential, Oid4vciError> {
+        oid4vci_refresh_credential(
+            collection,
+            id,
+            signer,
+            self.context_map()?,
+            self.token_http_client()?,
         )
         .await
     }
@@ -0,0 +1,250 @@
+//! Cache of the artifacts a verifier references by URI, such as presentation
+//! definitions, client metadata and JWKS, so that requests can proceed with
+//! recent copies when fetching them fails.
+
+use super::response_errors;
+use crate::oid4vci::{HttpClientError, HttpRequest, HttpResponse, ReqwestHttpClient};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+use std::time::{Duration, SystemTime};
+
+use oid4vci::oauth2::{HttpRequest as ExtHttpRequest, HttpResponse as ExtHttpResponse};
+use uniffi::deps::{anyhow, log};
+
+/// The default time a cached artifact can be served for.
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+tokio::task_local! {
+    /// Set when an artifact is served from the cache while handling a request.
+    static SERVED_FROM_CACHE: Arc<AtomicBool>;
+}
+
+/// Run a future, returning whether any artifact was served from the cache
+/// while it ran.
+pub(crate) async fn track_cache_use<F: Future>(future: F) -> (F::Output, bool) {
+    let served_from_cache = Arc::new(AtomicBool::new(false));
+    let output = SERVED_FROM_CACHE
+        .scope(served_from_cache.clone(), future)
+        .await;
+    (output, served_from_cache.load(Ordering::SeqCst))
+}
+
+#[derive(Debug, Clone)]
+struct CachedArtifact {
+    response: HttpResponse,
+    fetched_at: SystemTime,
+}
+
+/// A cache of verifier artifacts, keyed by URI.
+///
+/// Only successful JSON responses to `GET` requests are cached, so that
+/// request objects, which are single use, are always fetched. Cached copies
+/// are only served when fetching fails, and while they are younger than the
+/// maximum age.
+#[derive(Debug, uniffi::Object)]
+pub struct VerifierArtifactCache {
+    entries: RwLock<HashMap<String, CachedArtifact>>,
+    max_age: RwLock<Duration>,
+}
+
+impl Default for VerifierArtifactCache {
+    fn default() -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            max_age: RwLock::new(DEFAULT_MAX_AGE),
+        }
+    }
+}
+
+#[uniffi::export]
+impl VerifierArtifactCache {
+    #[uniffi::constructor]
+    pub fn new(max_age: Duration) -> Arc<Self> {
+        let cache = Self::default();
+        cache.set_max_age(max_age);
+        Arc::new(cache)
+    }
+
+    /// Set the time a cached artifact can be served for.
+    pub fn set_max_age(&self, max_age: Duration) {
+        *self
+            .max_age
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = max_age;
+    }
+
+    /// Remove every cached artifact.
+    pub fn clear(&self) {
+        self.entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+}
+
+impl VerifierArtifactCache {
+    fn store(&self, uri: &str, response: &HttpResponse, now: SystemTime) {
+        if !is_cacheable(response) {
+            return;
+        }
+
+        self.entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(
+                uri.to_string(),
+                CachedArtifact {
+                    response: response.clone(),
+                    fetched_at: now,
+                },
+            );
+    }
+
+    fn lookup(&self, uri: &str, now: SystemTime) -> Option<HttpResponse> {
+        let max_age = *self
+            .max_age
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        self.entries
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(uri)
+            .filter(|artifact| {
+                now.duration_since(artifact.fetched_at)
+                    .is_ok_and(|age| age <= max_age)
+            })
+            .map(|artifact| artifact.response.clone())
+    }
+}
+
+/// Check whether a response is a successful JSON response, which excludes
+/// JWT request objects.
+fn is_cacheable(response: &HttpResponse) -> bool {
+    let content_type = response
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-type"))
+        .map(|(_, value)| value.to_lowercase());
+
+    (200..300).contains(&response.status_code)
+        && content_type.is_some_and(|content_type| {
+            content_type.contains("json") && !content_type.contains("jwt")
+        })
+}
+
+/// HTTP client of the holder, falling back to the verifier artifact cache
+/// when fetching fails.
+#[derive(Debug, Clone)]
+pub struct CachingHttpClient {
+    client: ReqwestHttpClient,
+    cache: Arc<VerifierArtifactCache>,
+}
+
+impl CachingHttpClient {
+    pub(crate) fn new(client: ReqwestHttpClient, cache: Arc<VerifierArtifactCache>) -> Self {
+        Self { client, cache }
+    }
+
+    /// Return the client, without the cache, e.g. to poll endpoints whose
+    /// responses must be fresh.
+    pub(crate) fn uncached(&self) -> &ReqwestHttpClient {
+        &self.client
+    }
+
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, HttpClientError> {
+        let cacheable = request.method.eq_ignore_ascii_case("GET");
+        let uri = request.url.clone();
+
+        match self.client.send(request).await {
+            Ok(response) => {
+                if cacheable {
+                    self.cache.store(&uri, &response, SystemTime::now());
+                } else {
+                    response_errors::record_response(&response);
+                }
+                Ok(response)
+            }
+            // A certificate that does not match its pins is not a connectivity
+            // failure.
+            Err(e @ HttpClientError::CertificatePinning { .. }) => Err(e),
+            Err(e) if cacheable => match self.cache.lookup(&uri, SystemTime::now()) {
+                Some(response) => {
+                    log::warn!("Serving {uri} from the cache: {e}");
+                    let _ =
+                        SERVED_FROM_CACHE.try_with(|served| served.store(true, Ordering::SeqCst));
+                    Ok(response)
+                }
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl openid4vp::core::util::AsyncHttpClient for CachingHttpClient {
+    async fn execute(&self, request: ExtHttpRequest) -> anyhow::Result<ExtHttpResponse> {
+        let response: ExtHttpResponse = self.send(request.try_into()?).await?.try_into()?;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content_type: &str) -> HttpResponse {
+        HttpResponse {
+            status_code: 200,
+            headers: HashMap::from([("content-type".to_string(), content_type.to_string())]),
+            body: b"{}".to_vec(),
+        }
+    }
+
+    #[test]
+    fn caches_json_artifacts() {
+        let cache = VerifierArtifactCache::new(Duration::from_secs(60));
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        cache.store(
+            "https://verifier.example.com/pd",
+            &response("application/json"),
+            now,
+        );
+        cache.store(
+            "https://verifier.example.com/request",
+            &response("application/oauth-authz-req+jwt"),
+            now,
+        );
+
+        let lookup = |uri: &str, seconds| {
+            cache
+                .lookup(uri, now + Duration::from_secs(seconds))
+                .is_some()
+        };
+        assert!(lookup("https://verifier.example.com/pd", 60));
+        assert!(!lookup("https://verifier.example.com/pd", 61));
+        assert!(!lookup("https://verifier.example.com/request", 0));
+
+        cache.clear();
+        assert!(!lookup("https://verifier.example.com/pd", 0));
+    }
+
+    #[tokio::test]
+    async fn tracks_cache_use() {
+        let (_, served_from_cache) = track_cache_use(async {}).await;
+        assert!(!served_from_cache);
+
+        let (_, served_from_cache) = track_cache_use(async {
+            SERVED_FROM_CACHE.with(|served| served.store(true, Ordering::SeqCst));
+        })
+        .await;
+        assert!(served_from_cache);
+    }
+}
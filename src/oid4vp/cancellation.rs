@@ -0,0 +1,67 @@
+use super::error::OID4VPError;
+use super::holder::Holder;
+
+use std::future::Future;
+
+#[uniffi::export]
+impl Holder {
+    /// Cancel every in-flight `authorization_request` and
+    /// `submit_permission_response`, which fail with [OID4VPError::Cancelled].
+    ///
+    /// Their HTTP requests are aborted, and flows started after the call are
+    /// not affected.
+    pub fn cancel(&self) {
+        self.cancellation
+            .send_modify(|generation| *generation = generation.wrapping_add(1));
+    }
+}
+
+impl Holder {
+    /// Run a future until it completes, or the holder is cancelled.
+    ///
+    /// On cancellation the future is dropped, which aborts its in-flight HTTP
+    /// requests.
+    pub(crate) async fn cancellable<T>(
+        &self,
+        future: impl Future<Output = Result<T, OID4VPError>>,
+    ) -> Result<T, OID4VPError> {
+        let mut cancelled = self.cancellation.subscribe();
+
+        tokio::select! {
+            result = future => result,
+            _ = cancelled.changed() => Err(OID4VPError::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn cancels_in_flight_flows() {
+        let holder = Holder::new_with_credentials(vec![], vec![]).await.unwrap();
+
+        let task = tokio::spawn({
+            let holder: Arc<Holder> = holder.clone();
+            async move {
+                holder
+                    .cancellable(std::future::pending::<Result<(), OID4VPError>>())
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        while !task.is_finished() {
+            holder.cancel();
+            tokio::task::yield_now().await;
+        }
+        assert!(matches!(task.await.unwrap(), Err(OID4VPError::Cancelled)));
+
+        assert!(holder
+            .cancellable(async { Ok::<_, OID4VPError>(()) })
+            .await
+            .is_ok());
+    }
+}
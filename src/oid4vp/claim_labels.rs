@@ -0,0 +1,173 @@
+//! Human-readable, localized labels of requested fields, e.g. "Date of
+//! birth" or "Geburtsdatum" rather than `birth_date`.
+//!
+//! Labels are read from the overrides of the app first, then from the claim
+//! display metadata of the issuer, see
+//! [CredentialDisplay::claims](crate::credential::display::CredentialDisplay::claims),
+//! in the preferred locales of the app, in order of preference.
+
+use super::permission_request::RequestedField;
+use crate::credential::display::{locale_matches, select_display, ClaimDisplay};
+use crate::credential::ParsedCredential;
+
+use serde::{Deserialize, Serialize};
+
+/// A label of the app for a claim, replacing that of the issuer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct ClaimLabel {
+    /// The claim, as the name of the requested field, its claim path joined
+    /// with `.`, e.g. `credentialSubject.birthDate`, or the identifier of an
+    /// mdoc data element.
+    pub claim: String,
+    /// The BCP 47 language tag of the label, or `None` for the label of every
+    /// other locale.
+    pub locale: Option<String>,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct ClaimLabelConfig {
+    /// The locales of the labels, in order of preference.
+    pub preferred_locales: Vec<String>,
+    pub overrides: Vec<ClaimLabel>,
+}
+
+/// Return the label of a requested field of the credential, if any.
+pub(crate) fn label(
+    config: &ClaimLabelConfig,
+    credential: &ParsedCredential,
+    field: &RequestedField,
+) -> Option<String> {
+    let claim_path = field.claim_path();
+    let mdoc_element = field.mdoc_element();
+    let overrides = |locale: Option<&str>| {
+        config.overrides.iter().find(|label| {
+            let claim_matches = field.name.as_deref() == Some(&label.claim)
+                || (!claim_path.is_empty() && claim_path.join(".") == label.claim)
+                || mdoc_element
+                    .as_ref()
+                    .is_some_and(|element| element.identifier == label.claim);
+            let locale_matches = match (label.locale.as_deref(), locale) {
+                (Some(label), Some(preferred)) => locale_matches(label, preferred),
+                (label, preferred) => label.is_none() && preferred.is_none(),
+            };
+            claim_matches && locale_matches
+        })
+    };
+    let issued = |claims: &[ClaimDisplay]| {
+        claims
+            .iter()
+            .find(|claim| {
+                claim.path == claim_path
+                    || mdoc_element.as_ref().is_some_and(|element| {
+                        claim.path == [element.namespace.clone(), element.identifier.clone()]
+                    })
+            })
+            .map(|claim| claim.name.clone())
+    };
+
+    for preferred in &config.preferred_locales {
+        if let Some(label) = overrides(Some(preferred)) {
+            return Some(label.label.clone());
+        }
+        let localized = credential.display.iter().find(|display| {
+            display
+                .locale
+                .as_deref()
+                .is_some_and(|locale| locale_matches(locale, preferred))
+        });
+        if let Some(label) = localized.and_then(|display| issued(&display.claims)) {
+            return Some(label);
+        }
+    }
+
+    overrides(None)
+        .map(|label| label.label.clone())
+        .or_else(|| {
+            select_display(&credential.display, &config.preferred_locales)
+                .and_then(|display| issued(&display.claims))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::display::CredentialDisplay;
+    use crate::credential::json_vc::JsonVc;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn localizes_labels() {
+        let display = |locale: &str, label: &str| CredentialDisplay {
+            name: "Identity Credential".into(),
+            locale: Some(locale.into()),
+            description: None,
+            logo: None,
+            background_image: None,
+            background_color: None,
+            text_color: None,
+            claims: vec![ClaimDisplay {
+                path: vec!["credentialSubject".into(), "birthDate".into()],
+                name: label.into(),
+            }],
+        };
+        let mut credential = ParsedCredential::new_ldp_vc(
+            JsonVc::new_from_json(
+                serde_json::json!({
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiableCredential"],
+                    "issuer": "did:example:issuer",
+                    "issuanceDate": "2024-01-01T00:00:00Z",
+                    "credentialSubject": { "birthDate": "1990-01-01" }
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        );
+        Arc::get_mut(&mut credential).unwrap().display = vec![
+            display("en", "Date of birth"),
+            display("de", "Geburtsdatum"),
+        ];
+        let field = RequestedField {
+            id: uuid::Uuid::new_v4(),
+            name: Some("birthDate".into()),
+            required: true,
+            retained: false,
+            purpose: None,
+            input_descriptor_id: "identity".into(),
+            raw_fields: vec![],
+            pointers: vec![vec!["credentialSubject".into(), "birthDate".into()]],
+            values: vec![],
+            path: None,
+            mdoc_element: None,
+            filter: None,
+            label: None,
+        };
+
+        let label = |preferred_locales: &[&str], overrides: Vec<ClaimLabel>| {
+            let config = ClaimLabelConfig {
+                preferred_locales: preferred_locales.iter().map(|l| l.to_string()).collect(),
+                overrides,
+            };
+            super::label(&config, &credential, &field)
+        };
+        assert_eq!(
+            label(&["de", "en"], vec![]).as_deref(),
+            Some("Geburtsdatum")
+        );
+        assert_eq!(label(&["fr"], vec![]).as_deref(), Some("Date of birth"));
+        assert_eq!(
+            label(
+                &["de"],
+                vec![ClaimLabel {
+                    claim: "credentialSubject.birthDate".into(),
+                    locale: Some("de".into()),
+                    label: "Geburtstag".into(),
+                }]
+            )
+            .as_deref(),
+            Some("Geburtstag")
+        );
+    }
+}
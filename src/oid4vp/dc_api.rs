@@ -0,0 +1,286 @@
+//! Support for OID4VP requests delivered through the W3C Digital Credentials
+//! API, as used by browsers and Android CredentialManager.
+//!
+//! Requests arrive as the JSON `data` of the `openid4vp` protocol rather than
+//! as a URL, together with the origin of the calling website or app:
+//!
+//! - Unsigned requests carry the request parameters directly, and have no
+//!   client identifier of their own. They are bound to the caller's origin,
+//!   which is used as the client identifier (`web-origin:<origin>`).
+//! - Signed requests carry a `request` JWT, which must list the caller's
+//!   origin in its `expected_origins` parameter.
+//!
+//! Responses are returned to the platform by [Holder::dc_api_response] rather
+//! than posted to the verifier, and the mdocs they present are bound to the
+//! caller's origin.
+
+use super::error::OID4VPError;
+use super::flow_events::FlowEvent;
+use super::holder::Holder;
+use super::permission_request::{PermissionRequest, PermissionResponse};
+use super::request;
+use super::response_encryption;
+use crate::common::Url;
+use crate::metrics;
+
+use std::sync::Arc;
+
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use openid4vp::wallet::Wallet as OID4VPWallet;
+use serde_json::{json, Map, Value as Json};
+
+/// Response mode for responses returned through the Digital Credentials API.
+pub const RESPONSE_MODE_DC_API: &str = "dc_api";
+/// Response mode for encrypted responses returned through the Digital
+/// Credentials API.
+pub const RESPONSE_MODE_DC_API_JWT: &str = "dc_api.jwt";
+
+const WEB_ORIGIN_PREFIX: &str = "web-origin:";
+
+#[uniffi::export(async_runtime = "tokio")]
+impl Holder {
+    /// Given an OID4VP request received through the Digital Credentials API,
+    /// return a permission request, which provides a list of requested
+    /// credentials and requested fields that align with the presentation
+    /// definition of the request.
+    ///
+    /// `request_json` is the `data` of the `openid4vp` protocol request, and
+    /// `origin` is the origin of the caller as reported by the platform.
+    ///
+    /// The request is checked as those received as URLs, against the size
+    /// limits, the profile and the replay guard of the holder, and analyzed
+    /// for signs of phishing.
+    pub async fn authorization_request_from_dc_api(
+        &self,
+        request_json: String,
+        origin: String,
+    ) -> Result<Arc<PermissionRequest>, OID4VPError> {
+        self.cancellable(self.resolve_dc_api_request(request_json, origin))
+            .await
+    }
+
+    /// Return the response to a request received through the Digital
+    /// Credentials API, as the JSON `data` the platform returns to the
+    /// caller: the `vp_token` and `presentation_submission` of `dc_api`
+    /// responses, or the encrypted `response` of `dc_api.jwt` ones.
+    ///
+    /// The mdocs of the response are bound to the origin of the caller.
+    pub async fn dc_api_response(
+        &self,
+        response: Arc<PermissionResponse>,
+    ) -> Result<String, OID4VPError> {
+        let request = &response.authorization_request;
+        if response.dc_api_origin.is_none() {
+            return Err(OID4VPError::UnsupportedResponseMode {
+                response_mode: request::string_parameter(request, "response_mode")
+                    .unwrap_or_default(),
+            });
+        }
+
+        let (response, authorization_response) = self.prepare_response(response).await?;
+        let request = &response.authorization_request;
+        let result = if is_encrypted(request) {
+            response_encryption::encrypt_response(request, &authorization_response, None)
+                .map(|jwe| json!({ "response": jwe }).to_string())
+        } else {
+            response_encryption::response_parameters(request, &authorization_response)
+                .map(|parameters| parameters.to_string())
+        }
+        .map_err(OID4VPError::from);
+
+        self.complete_response(&response, &result).await?;
+        if result.is_ok() {
+            self.emit(FlowEvent::ResponseSubmitted);
+        }
+        result
+    }
+}
+
+impl Holder {
+    async fn resolve_dc_api_request(
+        &self,
+        request_json: String,
+        origin: String,
+    ) -> Result<Arc<PermissionRequest>, OID4VPError> {
+        let origin = normalize_origin(&origin)?;
+        self.check_request_size(&request_json)?;
+
+        let mut parameters: Map<String, Json> =
+            serde_json::from_str(&request_json).map_err(OID4VPError::json_syntax_parse)?;
+        let request_object = parameters
+            .get("request")
+            .and_then(Json::as_str)
+            .map(ToOwned::to_owned);
+
+        let request: AuthorizationRequestObject = match &request_object {
+            Some(jwt) => {
+                self.check_request_object(Some(jwt))?;
+                check_expected_origins(jwt, &origin)?;
+
+                // Verify the signed request the same way as one passed by value in a URL.
+                let url = Url::parse_with_params("openid4vp://", [("request", jwt)])
+                    .map_err(OID4VPError::request_validation)?;
+                metrics::measure(
+                    self.metrics_sink(),
+                    metrics::REQUEST_VALIDATION,
+                    self.validate_request(url),
+                )
+                .await
+                .map_err(OID4VPError::request_validation)?
+            }
+            None => {
+                self.check_request_object(None)?;
+                parameters.insert(
+                    "client_id".into(),
+                    Json::String(format!("{WEB_ORIGIN_PREFIX}{origin}")),
+                );
+                serde_json::from_value(Json::Object(parameters))
+                    .map_err(OID4VPError::request_validation)?
+            }
+        };
+
+        if !is_dc_api(&request) {
+            return Err(OID4VPError::UnsupportedResponseMode {
+                response_mode: request::string_parameter(&request, "response_mode")
+                    .unwrap_or_default(),
+            });
+        }
+
+        let permission_request = self
+            .accept_request(request, request_object.as_deref(), None, None)
+            .await?;
+        Ok(Arc::new(PermissionRequest {
+            dc_api_origin: Some(origin),
+            ..(*permission_request).clone()
+        }))
+    }
+}
+
+/// Check whether the responses to a request are returned through the Digital
+/// Credentials API.
+pub(crate) fn is_dc_api(request: &AuthorizationRequestObject) -> bool {
+    matches!(
+        request::string_parameter(request, "response_mode").as_deref(),
+        Some(RESPONSE_MODE_DC_API | RESPONSE_MODE_DC_API_JWT)
+    )
+}
+
+/// Check whether the responses to a request are encrypted responses of the
+/// Digital Credentials API.
+pub(crate) fn is_encrypted(request: &AuthorizationRequestObject) -> bool {
+    request::string_parameter(request, "response_mode").as_deref() == Some(RESPONSE_MODE_DC_API_JWT)
+}
+
+/// Normalize the origin reported by the platform.
+///
+/// Web origins are reduced to their `scheme://host[:port]` serialization.
+/// Other origins, such as Android `android:apk-key-hash:` origins, are
+/// compared verbatim.
+pub(crate) fn normalize_origin(origin: &str) -> Result<String, OID4VPError> {
+    let origin = origin.trim();
+    if origin.is_empty() {
+        return Err(OID4VPError::InvalidOrigin {
+            origin: origin.to_owned(),
+        });
+    }
+
+    match Url::parse(origin) {
+        Ok(url) if url.origin().is_tuple() => Ok(url.origin().ascii_serialization()),
+        Ok(_) => Ok(origin.to_owned()),
+        Err(_) => Err(OID4VPError::InvalidOrigin {
+            origin: origin.to_owned(),
+        }),
+    }
+}
+
+/// Check that a signed request lists the origin in its `expected_origins`.
+pub(crate) fn check_expected_origins(jwt: &str, origin: &str) -> Result<(), OID4VPError> {
+    let claims = request::jwt_part(jwt, 1).ok_or_else(|| OID4VPError::RequestValidation {
+        reason: "malformed request object".into(),
+        source: None,
+    })?;
+
+    let expected =
+        claims["expected_origins"]
+            .as_array()
+            .ok_or_else(|| OID4VPError::OriginMismatch {
+                origin: origin.to_owned(),
+            })?;
+
+    expected
+        .iter()
+        .filter_map(Json::as_str)
+        .filter_map(|expected| normalize_origin(expected).ok())
+        .any(|expected| expected == origin)
+        .then_some(())
+        .ok_or_else(|| OID4VPError::OriginMismatch {
+            origin: origin.to_owned(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::*;
+
+    fn unsigned_jwt(claims: Json) -> String {
+        format!(
+            "{}.{}.",
+            BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    #[rstest::rstest]
+    #[case::web(
+        "https://verifier.example.com/path?q=1",
+        "https://verifier.example.com"
+    )]
+    #[case::port(
+        "https://verifier.example.com:8443/",
+        "https://verifier.example.com:8443"
+    )]
+    #[case::android("android:apk-key-hash:abc123", "android:apk-key-hash:abc123")]
+    fn normalizes_origins(#[case] origin: &str, #[case] expected: &str) {
+        assert_eq!(normalize_origin(origin).unwrap(), expected);
+    }
+
+    #[test]
+    fn detects_dc_api_response_modes() {
+        let request = |response_mode: &str| -> AuthorizationRequestObject {
+            serde_json::from_value(serde_json::json!({
+                "client_id": "web-origin:https://verifier.example.com",
+                "response_type": "vp_token",
+                "response_mode": response_mode,
+                "nonce": "n-0S6_WzA2Mj",
+            }))
+            .unwrap()
+        };
+
+        assert!(is_dc_api(&request("dc_api")) && !is_encrypted(&request("dc_api")));
+        assert!(is_dc_api(&request("dc_api.jwt")) && is_encrypted(&request("dc_api.jwt")));
+        assert!(!is_dc_api(&request("direct_post")));
+        // Responses are returned through the platform, not posted.
+        assert!(crate::oid4vp::draft::check(&request("dc_api")).is_ok());
+        assert!(crate::oid4vp::draft::check(&request("direct_post")).is_err());
+    }
+
+    #[test]
+    fn expected_origins_must_contain_origin() {
+        let jwt = unsigned_jwt(serde_json::json!({
+            "expected_origins": ["https://verifier.example.com"]
+        }));
+
+        assert!(check_expected_origins(&jwt, "https://verifier.example.com").is_ok());
+        assert!(matches!(
+            check_expected_origins(&jwt, "https://attacker.example.com"),
+            Err(OID4VPError::OriginMismatch { .. })
+        ));
+
+        let jwt = unsigned_jwt(serde_json::json!({}));
+        assert!(matches!(
+            check_expected_origins(&jwt, "https://verifier.example.com"),
+            Err(OID4VPError::OriginMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+//! Upper bounds on the size of presentation definitions, checked before they
+//! are parsed, so that verifiers cannot make matching arbitrarily slow.
+
+use super::error::OID4VPError;
+
+use serde_json::Value as Json;
+
+/// The default maximum number of input descriptors of a definition.
+pub const DEFAULT_MAX_INPUT_DESCRIPTORS: u32 = 100;
+
+/// The default maximum number of fields, across every input descriptor.
+pub const DEFAULT_MAX_FIELDS: u32 = 1_000;
+
+/// The default maximum nesting depth of the filters of fields.
+pub const DEFAULT_MAX_FILTER_DEPTH: u32 = 16;
+
+/// The limits presentation definitions must stay within.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct PresentationDefinitionLimits {
+    /// Defaults to [DEFAULT_MAX_INPUT_DESCRIPTORS].
+    pub max_input_descriptors: Option<u32>,
+    /// Defaults to [DEFAULT_MAX_FIELDS].
+    pub max_fields: Option<u32>,
+    /// The maximum nesting depth of the JSON Schema filter of each field,
+    /// defaulting to [DEFAULT_MAX_FILTER_DEPTH].
+    pub max_filter_depth: Option<u32>,
+}
+
+impl PresentationDefinitionLimits {
+    /// Check a presentation definition, before it is parsed.
+    pub(crate) fn check(&self, definition: &Json) -> Result<(), OID4VPError> {
+        let descriptors = definition["input_descriptors"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let max_descriptors = self
+            .max_input_descriptors
+            .unwrap_or(DEFAULT_MAX_INPUT_DESCRIPTORS);
+        if descriptors.len() > max_descriptors as usize {
+            return Err(OID4VPError::PresentationDefinitionTooLarge(format!(
+                "{} input descriptors, more than {max_descriptors}",
+                descriptors.len()
+            )));
+        }
+
+        let fields = descriptors
+            .iter()
+            .filter_map(|descriptor| descriptor["constraints"]["fields"].as_array())
+            .flatten()
+            .collect::<Vec<_>>();
+        let max_fields = self.max_fields.unwrap_or(DEFAULT_MAX_FIELDS);
+        if fields.len() > max_fields as usize {
+            return Err(OID4VPError::PresentationDefinitionTooLarge(format!(
+                "{} fields, more than {max_fields}",
+                fields.len()
+            )));
+        }
+
+        let max_depth = self.max_filter_depth.unwrap_or(DEFAULT_MAX_FILTER_DEPTH);
+        match fields.iter().map(|field| depth(&field["filter"])).max() {
+            Some(filter_depth) if filter_depth > max_depth => {
+                Err(OID4VPError::PresentationDefinitionTooLarge(format!(
+                    "a filter nested {filter_depth} levels deep, more than {max_depth}"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Return the nesting depth of the objects and arrays of a JSON value.
+fn depth(value: &Json) -> u32 {
+    match value {
+        Json::Array(values) => 1 + values.iter().map(depth).max().unwrap_or_default(),
+        Json::Object(values) => 1 + values.values().map(depth).max().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn bounds_presentation_definitions() {
+        let definition = |descriptors: usize, filter: Json| {
+            json!({
+                "id": "large",
+                "input_descriptors": (0..descriptors)
+                    .map(|idx| json!({
+                        "id": format!("descriptor_{idx}"),
+                        "constraints": {
+                            "fields": [{
+                                "path": ["$.credentialSubject.id"],
+                                "filter": filter,
+                            }],
+                        },
+                    }))
+                    .collect::<Vec<_>>(),
+            })
+        };
+        let filter = json!({ "type": "string" });
+        let limits = PresentationDefinitionLimits::default();
+        assert!(limits.check(&definition(50, filter.clone())).is_ok());
+
+        let err = limits.check(&definition(101, filter.clone())).unwrap_err();
+        assert_eq!(err.code(), "oid4vp.presentation_definition_too_large");
+
+        let limits = PresentationDefinitionLimits {
+            max_fields: Some(10),
+            ..Default::default()
+        };
+        assert!(matches!(
+            limits.check(&definition(11, filter.clone())),
+            Err(OID4VPError::PresentationDefinitionTooLarge(..))
+        ));
+
+        let deep = (0..20).fold(filter, |filter, _| json!({ "not": filter }));
+        assert!(matches!(
+            PresentationDefinitionLimits::default().check(&definition(1, deep)),
+            Err(OID4VPError::PresentationDefinitionTooLarge(..))
+        ));
+    }
+}
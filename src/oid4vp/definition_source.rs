@@ -0,0 +1,83 @@
+//! Where the presentation definition of a request came from, recorded on the
+//! permission request for audits.
+//!
+//! Verifiers passing their definition by reference can advertise its digest
+//! with a `presentation_definition_uri_integrity` parameter, in the format of
+//! Subresource Integrity metadata, e.g. `sha256-<base64 digest>`. Fetched
+//! definitions that match none of the digests are refused.
+//!
+//! NOTE: the integrity parameter is not part of OID4VP, so most verifiers do
+//! not send it, and their definitions are not checked.
+
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use uniffi::deps::anyhow::{self, bail};
+
+/// The request parameter advertising the digest of the presentation
+/// definition at the `presentation_definition_uri`.
+pub(crate) const INTEGRITY_PARAMETER: &str = "presentation_definition_uri_integrity";
+
+/// Where the presentation definition of a request came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Enum)]
+pub enum PresentationDefinitionSource {
+    /// The definition was passed in the `presentation_definition` parameter.
+    Inline,
+    /// The definition was fetched from the `presentation_definition_uri`.
+    Reference {
+        uri: String,
+        /// Whether the definition matched the digest advertised by the
+        /// request.
+        integrity_verified: bool,
+    },
+    /// The definition is the query template of a `scope` value of the
+    /// request.
+    Scope { scope: String },
+}
+
+/// Check a fetched definition against Subresource Integrity metadata, which
+/// it must match one digest of.
+pub(crate) fn check_integrity(integrity: &str, definition: &[u8]) -> anyhow::Result<()> {
+    let mut supported = false;
+    for entry in integrity.split_whitespace() {
+        // Drop the options, which have no defined meaning.
+        let entry = entry.split('?').next().unwrap_or_default();
+        let Some((algorithm, expected)) = entry.split_once('-') else {
+            continue;
+        };
+        let digest = match algorithm {
+            "sha256" => Sha256::digest(definition).to_vec(),
+            "sha384" => Sha384::digest(definition).to_vec(),
+            "sha512" => Sha512::digest(definition).to_vec(),
+            _ => continue,
+        };
+        supported = true;
+        if BASE64_STANDARD
+            .decode(expected)
+            .is_ok_and(|expected| expected == digest)
+        {
+            return Ok(());
+        }
+    }
+
+    if !supported {
+        bail!("unsupported presentation definition integrity: {integrity}")
+    }
+    bail!("the presentation definition does not match its integrity metadata")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_definition_integrity() {
+        let definition = br#"{"id":"definition","input_descriptors":[]}"#;
+        let digest = BASE64_STANDARD.encode(Sha256::digest(definition));
+
+        assert!(check_integrity(&format!("sha256-{digest}"), definition).is_ok());
+        assert!(check_integrity(&format!("md5-abc sha256-{digest}?v=1"), definition).is_ok());
+        assert!(check_integrity(&format!("sha256-{digest}"), b"{}").is_err());
+        assert!(check_integrity("md5-abc", definition).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! Denial responses, telling the verifier that the user rejected its request
+//! with an `access_denied` authorization error response, so that it does not
+//! wait for a presentation.
+
+use super::draft;
+use super::error::OID4VPError;
+use super::flow_events::FlowEvent;
+use super::holder::Holder;
+use super::permission_request::PermissionRequest;
+use super::request;
+use crate::common::Url;
+use crate::oid4vci::certificate_pinning_host;
+
+use std::sync::Arc;
+
+use oid4vci::oauth2::http::{header, Method, Request};
+use openid4vp::core::util::AsyncHttpClient;
+use serde_json::Value as Json;
+use uniffi::deps::anyhow::{self, bail, Context};
+
+/// The error code of the response, from RFC 6749.
+const ACCESS_DENIED: &str = "access_denied";
+
+#[uniffi::export(async_runtime = "tokio")]
+impl Holder {
+    /// Tell the verifier that the user denied its request, by posting an
+    /// `access_denied` error response to its `response_uri`, or the
+    /// `redirect_uri` of draft 18 requests, with the reason as the error
+    /// description.
+    ///
+    /// Returns the URI the verifier asks to redirect the user to, if any.
+    ///
+    /// As responses, denials are only posted to URIs the
+    /// [super::response_policy::ResponseUriPolicy] of the holder allows.
+    ///
+    /// NOTE: requests without a `response_uri`, such as those of the Digital
+    /// Credentials API, cannot be denied this way.
+    pub async fn submit_denial(
+        &self,
+        permission_request: Arc<PermissionRequest>,
+        reason: Option<String>,
+    ) -> Result<Option<Url>, OID4VPError> {
+        let request = &permission_request.request;
+        self.response_uri_policy
+            .read()
+            .map_err(|_| OID4VPError::LockError("response_uri_policy".into()))?
+            .check_request(request)?;
+        let response_uri =
+            draft::response_endpoint(request).ok_or_else(|| OID4VPError::ResponseSubmission {
+                reason: "the request has no response_uri".into(),
+                source: None,
+            })?;
+        let state = request::string_parameter(request, "state");
+
+        let redirect_uri = self
+            .cancellable(async {
+                post_denial(
+                    &self.client,
+                    &response_uri,
+                    state.as_deref(),
+                    reason.as_deref(),
+                )
+                .await
+                .map_err(|e| match certificate_pinning_host(&e) {
+                    Some(host) => OID4VPError::CertificatePinning { host },
+                    None => OID4VPError::response_submission(e),
+                })
+            })
+            .await?;
+
+        self.emit(FlowEvent::ResponseSubmitted);
+        if let Some(redirect_uri) = &redirect_uri {
+            self.emit(FlowEvent::RedirectReceived {
+                redirect_uri: redirect_uri.clone(),
+            });
+        }
+
+        Ok(redirect_uri)
+    }
+}
+
+/// Encode the parameters of an `access_denied` error response.
+fn denial_body(state: Option<&str>, reason: Option<&str>) -> String {
+    let mut body = url::form_urlencoded::Serializer::new(String::new());
+    body.append_pair("error", ACCESS_DENIED);
+    if let Some(reason) = reason {
+        body.append_pair("error_description", reason);
+    }
+    if let Some(state) = state {
+        body.append_pair("state", state);
+    }
+    body.finish()
+}
+
+/// Post an `access_denied` error response, and return the `redirect_uri` of
+/// the response of the verifier.
+async fn post_denial(
+    client: &impl AsyncHttpClient,
+    response_uri: &str,
+    state: Option<&str>,
+    reason: Option<&str>,
+) -> anyhow::Result<Option<Url>> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(response_uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(denial_body(state, reason).into_bytes())
+        .context("failed to build the denial response")?;
+
+    let response = client.execute(request).await?;
+    if !response.status().is_success() {
+        bail!("request to {response_uri} failed: {}", response.status());
+    }
+
+    redirect_uri(response.body())
+}
+
+/// Read the optional `redirect_uri` of the response of the verifier.
+pub(crate) fn redirect_uri(body: &[u8]) -> anyhow::Result<Option<Url>> {
+    if body.is_empty() {
+        return Ok(None);
+    }
+    let body: Json = serde_json::from_slice(body).context("the response is not JSON")?;
+
+    body.get("redirect_uri")
+        .and_then(Json::as_str)
+        .map(Url::parse)
+        .transpose()
+        .context("invalid redirect_uri")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_denial_responses() {
+        assert_eq!(denial_body(None, None), "error=access_denied");
+        assert_eq!(
+            denial_body(Some("abc"), Some("Declined by the user")),
+            "error=access_denied&error_description=Declined+by+the+user&state=abc"
+        );
+
+        assert_eq!(redirect_uri(b"").unwrap(), None);
+        assert_eq!(redirect_uri(b"{}").unwrap(), None);
+        assert_eq!(
+            redirect_uri(br#"{"redirect_uri":"https://verifier.example.com/done"}"#).unwrap(),
+            Some(Url::parse("https://verifier.example.com/done").unwrap())
+        );
+        assert!(redirect_uri(br#"{"redirect_uri":"not a url"}"#).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Lazy evaluation of the input descriptors of permission requests, so that
+//! consent screens of definitions with many descriptors only evaluate, and
+//! marshal across the FFI, the fields of the descriptors they display.
+
+use super::error::OID4VPError;
+use super::permission_request::{PermissionRequest, RequestedField};
+use crate::common::*;
+use crate::credential::ParsedCredential;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use serde_json::{json, Value as Json};
+
+/// An input descriptor of a permission request, without its fields.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct InputDescriptorInfo {
+    pub id: String,
+    pub name: Option<String>,
+    pub purpose: Option<String>,
+    /// The number of fields the descriptor constrains.
+    pub field_count: u32,
+}
+
+/// The definitions of single input descriptors, and the fields they request
+/// from each credential, by credential ID, evaluated on first use.
+#[derive(Debug, Default)]
+pub(crate) struct DescriptorCache {
+    definitions: HashMap<String, PresentationDefinition>,
+    fields: HashMap<(Uuid, String), Vec<Arc<RequestedField>>>,
+}
+
+#[uniffi::export]
+impl PermissionRequest {
+    /// Return the input descriptors of the presentation definition, without
+    /// evaluating their fields.
+    pub fn input_descriptors(&self) -> Vec<InputDescriptorInfo> {
+        self.definition
+            .input_descriptors()
+            .iter()
+            .filter_map(|descriptor| serde_json::to_value(descriptor).ok())
+            .map(|descriptor| InputDescriptorInfo {
+                id: descriptor["id"].as_str().unwrap_or_default().to_owned(),
+                name: descriptor["name"].as_str().map(ToOwned::to_owned),
+                purpose: descriptor["purpose"].as_str().map(ToOwned::to_owned),
+                field_count: descriptor["constraints"]["fields"]
+                    .as_array()
+                    .map_or(0, |fields| fields.len() as u32),
+            })
+            .collect()
+    }
+
+    /// Return the fields a single input descriptor requests from a
+    /// credential, with their localized labels.
+    ///
+    /// Unlike [PermissionRequest::requested_fields], only the descriptor is
+    /// evaluated, once, and the same fields are returned by later calls.
+    pub fn requested_fields_for_descriptor(
+        &self,
+        credential: &Arc<ParsedCredential>,
+        input_descriptor_id: String,
+    ) -> Result<Vec<Arc<RequestedField>>, OID4VPError> {
+        let mut cache = self
+            .descriptor_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let key = (credential.id(), input_descriptor_id);
+        if let Some(fields) = cache.fields.get(&key) {
+            return Ok(fields.clone());
+        }
+
+        let definition = match cache.definitions.get(&key.1) {
+            Some(definition) => definition.clone(),
+            None => {
+                let definition = self.descriptor_definition(&key.1)?;
+                cache.definitions.insert(key.1.clone(), definition.clone());
+                definition
+            }
+        };
+        let fields = self.labelled_fields(credential, &definition);
+        cache.fields.insert(key, fields.clone());
+        Ok(fields)
+    }
+}
+
+impl PermissionRequest {
+    /// Return a presentation definition with the input descriptor alone.
+    fn descriptor_definition(
+        &self,
+        input_descriptor_id: &str,
+    ) -> Result<PresentationDefinition, OID4VPError> {
+        let descriptor = self
+            .definition
+            .input_descriptors()
+            .iter()
+            .find(|descriptor| descriptor.id == input_descriptor_id)
+            .ok_or(OID4VPError::InputDescriptorNotFound)?;
+        let descriptor =
+            serde_json::to_value(descriptor).map_err(OID4VPError::definition_resolution)?;
+
+        let mut definition =
+            serde_json::to_value(&self.definition).map_err(OID4VPError::definition_resolution)?;
+        definition["input_descriptors"] = json!([descriptor]);
+        // The requirements may refer to the groups of other descriptors.
+        if let Json::Object(definition) = &mut definition {
+            definition.remove("submission_requirements");
+        }
+        serde_json::from_value(definition).map_err(OID4VPError::definition_resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::json_vc::JsonVc;
+
+    #[test]
+    fn evaluates_descriptors_lazily() {
+        let credential = ParsedCredential::new_ldp_vc(
+            JsonVc::new_from_json(
+                json!({
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiableCredential", "IdentityCredential"],
+                    "issuer": "did:example:issuer",
+                    "issuanceDate": "2024-01-01T00:00:00Z",
+                    "credentialSubject": {
+                        "id": "did:example:holder",
+                        "birthdate": "1990-01-01",
+                        "nationality": "FR",
+                    },
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        );
+        let definition = serde_json::from_value(json!({
+            "id": "identity",
+            "input_descriptors": [
+                {
+                    "id": "age",
+                    "purpose": "Age verification",
+                    "constraints": {
+                        "fields": [{ "path": ["$.credentialSubject.birthdate"] }],
+                    },
+                },
+                {
+                    "id": "nationality",
+                    "constraints": {
+                        "fields": [
+                            { "path": ["$.credentialSubject.nationality"] },
+                            { "path": ["$.issuer"] },
+                        ],
+                    },
+                },
+            ],
+        }))
+        .unwrap();
+        let request = serde_json::from_value(json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+        }))
+        .unwrap();
+        let permission_request =
+            PermissionRequest::new(definition, vec![credential.clone()], request);
+
+        let descriptors = permission_request.input_descriptors();
+        assert_eq!(
+            descriptors[0],
+            InputDescriptorInfo {
+                id: "age".into(),
+                name: None,
+                purpose: Some("Age verification".into()),
+                field_count: 1,
+            }
+        );
+        assert_eq!(descriptors[1].field_count, 2);
+
+        let fields = permission_request
+            .requested_fields_for_descriptor(&credential, "nationality".into())
+            .unwrap();
+        assert_eq!(fields.len(), 2);
+        // Later calls return the same fields, rather than evaluating the
+        // descriptor again.
+        let again = permission_request
+            .requested_fields_for_descriptor(&credential, "nationality".into())
+            .unwrap();
+        assert_eq!(again[0].id(), fields[0].id());
+
+        assert!(matches!(
+            permission_request.requested_fields_for_descriptor(&credential, "unknown".into()),
+            Err(OID4VPError::InputDescriptorNotFound)
+        ));
+    }
+}
@@ -0,0 +1,293 @@
+//! Detection of the OpenID4VP draft a request follows, so that one wallet can
+//! respond to verifiers implementing different drafts.
+//!
+//! - Draft 18 verifiers identify themselves with a `client_id_scheme`, and
+//!   expect `direct_post` responses at their `redirect_uri`.
+//! - Draft 20 verifiers expect them at their `response_uri`.
+//! - Draft 22 and later verifiers drop `client_id_scheme`, and prefix their
+//!   client ID with its scheme instead, e.g. `x509_san_dns:example.com`.
+//!
+//! Requests are verified against the scheme and the identifier of their
+//! client ID as their draft conveys them. The VP tokens and presentation
+//! submissions of presentation definition requests have the same shape in
+//! all of these drafts.
+//!
+//! NOTE: the VP tokens of DCQL requests, keyed by credential query, are not
+//! supported.
+
+use super::dc_api;
+use super::error::OID4VPError;
+use super::permission_request::PermissionRequest;
+use super::request;
+use crate::common::Url;
+
+use oid4vci::oauth2::http::{header, Method, Request};
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use openid4vp::core::response::{AuthorizationResponse, UnencodedAuthorizationResponse};
+use openid4vp::core::util::AsyncHttpClient;
+use serde_json::Value as Json;
+use uniffi::deps::anyhow::{self, bail, Context};
+
+/// The client ID prefixes of draft 22 and later, which replace the
+/// `client_id_scheme` parameter. DIDs are prefixed on their own.
+const CLIENT_ID_PREFIXES: &[&str] = &[
+    "redirect_uri:",
+    "x509_san_dns:",
+    "x509_san_uri:",
+    "x509_hash:",
+    "verifier_attestation:",
+    "decentralized_identifier:",
+    "web-origin:",
+    "openid_federation:",
+];
+
+/// The OpenID4VP draft of an authorization request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum OID4VPDraft {
+    Draft18,
+    Draft20,
+    /// Draft 22 and later.
+    Draft22,
+}
+
+#[uniffi::export]
+impl PermissionRequest {
+    /// Return the OpenID4VP draft the request follows.
+    pub fn draft(&self) -> OID4VPDraft {
+        detect(&self.request)
+    }
+}
+
+/// Detect the draft of a request from the parameters it uses.
+pub(crate) fn detect(request: &AuthorizationRequestObject) -> OID4VPDraft {
+    let parameters = request::parameters(request);
+    if !parameters.contains_key("client_id_scheme") {
+        let client_id = &request.client_id().0;
+        if CLIENT_ID_PREFIXES
+            .iter()
+            .any(|prefix| client_id.starts_with(prefix))
+        {
+            return OID4VPDraft::Draft22;
+        }
+    }
+
+    if parameters.contains_key("redirect_uri") && !parameters.contains_key("response_uri") {
+        OID4VPDraft::Draft18
+    } else {
+        OID4VPDraft::Draft20
+    }
+}
+
+/// Return the client ID scheme of a request: its `client_id_scheme` up to
+/// draft 20, or the prefix of its client ID from draft 22. DIDs are of the
+/// `did` scheme in any draft.
+pub(crate) fn client_id_scheme(request: &AuthorizationRequestObject) -> Option<String> {
+    let client_id = &request.client_id().0;
+    match detect(request) {
+        OID4VPDraft::Draft22 => client_id_prefix(client_id).map(|(scheme, _)| scheme.into()),
+        OID4VPDraft::Draft18 | OID4VPDraft::Draft20 => {
+            request::string_parameter(request, "client_id_scheme")
+                .or_else(|| client_id.starts_with("did:").then(|| "did".into()))
+        }
+    }
+}
+
+/// Return the client ID of a request without the scheme prefix of draft 22
+/// and later, e.g. the DNS name of an `x509_san_dns` client.
+pub(crate) fn client_identifier(request: &AuthorizationRequestObject) -> &str {
+    let client_id = &request.client_id().0;
+    match detect(request) {
+        OID4VPDraft::Draft22 => client_id_prefix(client_id)
+            .map(|(_, identifier)| identifier)
+            .unwrap_or(client_id),
+        OID4VPDraft::Draft18 | OID4VPDraft::Draft20 => client_id,
+    }
+}
+
+/// Split a client ID into its scheme prefix, without the colon, and the rest.
+fn client_id_prefix(client_id: &str) -> Option<(&str, &str)> {
+    CLIENT_ID_PREFIXES.iter().find_map(|prefix| {
+        client_id
+            .strip_prefix(prefix)
+            .map(|identifier| (prefix.trim_end_matches(':'), identifier))
+    })
+}
+
+/// Return the URI `direct_post` responses to a request are posted to.
+pub(crate) fn response_endpoint(request: &AuthorizationRequestObject) -> Option<String> {
+    match detect(request) {
+        OID4VPDraft::Draft18 => request::string_parameter(request, "redirect_uri"),
+        OID4VPDraft::Draft20 | OID4VPDraft::Draft22 => {
+            request::string_parameter(request, "response_uri")
+        }
+    }
+}
+
+/// Check that a validated request has what its draft needs to be responded
+/// to.
+///
+/// Responses to requests of the Digital Credentials API are returned through
+/// the platform, so these requests need no response endpoint.
+pub(crate) fn check(request: &AuthorizationRequestObject) -> Result<(), OID4VPError> {
+    if dc_api::is_dc_api(request) {
+        return Ok(());
+    }
+    if response_endpoint(request).is_none() {
+        let parameter = match detect(request) {
+            OID4VPDraft::Draft18 => "redirect_uri",
+            OID4VPDraft::Draft20 | OID4VPDraft::Draft22 => "response_uri",
+        };
+        return Err(OID4VPError::RequestValidation {
+            reason: format!("the request has no {parameter}"),
+            source: None,
+        });
+    }
+    Ok(())
+}
+
+/// Post a draft 18 response to the `redirect_uri` of the request, and return
+/// the `redirect_uri` of the response of the verifier.
+pub(crate) async fn submit_draft_18_response(
+    client: &impl AsyncHttpClient,
+    request: &AuthorizationRequestObject,
+    response: AuthorizationResponse,
+) -> anyhow::Result<Option<Url>> {
+    let Some(redirect_uri) = response_endpoint(request) else {
+        bail!("the request has no redirect_uri")
+    };
+    let AuthorizationResponse::Unencoded(response) = response else {
+        bail!("draft 18 responses cannot be encrypted")
+    };
+    let body = form_body(
+        &response,
+        request::string_parameter(request, "state").as_deref(),
+    )?;
+
+    post_form(client, &redirect_uri, body).await
+}
+
+/// Post the form encoded parameters of a response to the URI, and return the
+/// `redirect_uri` of the response of the verifier.
+pub(crate) async fn post_form(
+    client: &impl AsyncHttpClient,
+    uri: &str,
+    body: String,
+) -> anyhow::Result<Option<Url>> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(body.into_bytes())
+        .context("failed to build the response")?;
+
+    let response = client.execute(request).await?;
+    if !response.status().is_success() {
+        bail!("request to {uri} failed: {}", response.status());
+    }
+
+    super::denial::redirect_uri(response.body())
+}
+
+/// Encode the parameters of a response, with string VP tokens as is and
+/// the others as JSON.
+fn form_body(
+    response: &UnencodedAuthorizationResponse,
+    state: Option<&str>,
+) -> anyhow::Result<String> {
+    let vp_token = match serde_json::to_value(&response.vp_token)? {
+        Json::String(vp_token) => vp_token,
+        vp_token => vp_token.to_string(),
+    };
+
+    let mut body = url::form_urlencoded::Serializer::new(String::new());
+    body.append_pair("vp_token", &vp_token);
+    body.append_pair(
+        "presentation_submission",
+        &serde_json::to_string(&response.presentation_submission)?,
+    );
+    if let Some(state) = state {
+        body.append_pair("state", state);
+    }
+    Ok(body.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(parameters: Json) -> AuthorizationRequestObject {
+        let mut request = serde_json::json!({
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "nonce": "n-0S6_WzA2Mj",
+        });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(parameters.as_object().unwrap().clone());
+        serde_json::from_value(request).unwrap()
+    }
+
+    #[test]
+    fn detects_drafts() {
+        let draft_18 = request(serde_json::json!({
+            "client_id": "https://verifier.example.com/callback",
+            "client_id_scheme": "redirect_uri",
+            "redirect_uri": "https://verifier.example.com/callback",
+        }));
+        assert_eq!(detect(&draft_18), OID4VPDraft::Draft18);
+        assert_eq!(
+            response_endpoint(&draft_18).as_deref(),
+            Some("https://verifier.example.com/callback")
+        );
+
+        let draft_20 = request(serde_json::json!({
+            "client_id": "verifier.example.com",
+            "client_id_scheme": "x509_san_dns",
+            "response_uri": "https://verifier.example.com/response",
+        }));
+        assert_eq!(detect(&draft_20), OID4VPDraft::Draft20);
+        assert!(check(&draft_20).is_ok());
+
+        let draft_22 = request(serde_json::json!({
+            "client_id": "x509_san_dns:verifier.example.com",
+            "response_uri": "https://verifier.example.com/response",
+        }));
+        assert_eq!(detect(&draft_22), OID4VPDraft::Draft22);
+        assert!(check(&draft_22).is_ok());
+    }
+
+    #[test]
+    fn reads_client_ids_per_draft() {
+        let draft_20 = request(serde_json::json!({
+            "client_id": "verifier.example.com",
+            "client_id_scheme": "x509_san_dns",
+            "response_uri": "https://verifier.example.com/response",
+        }));
+        assert_eq!(client_id_scheme(&draft_20).as_deref(), Some("x509_san_dns"));
+        assert_eq!(client_identifier(&draft_20), "verifier.example.com");
+
+        let draft_22 = request(serde_json::json!({
+            "client_id": "x509_san_dns:verifier.example.com",
+            "response_uri": "https://verifier.example.com/response",
+        }));
+        assert_eq!(client_id_scheme(&draft_22).as_deref(), Some("x509_san_dns"));
+        assert_eq!(client_identifier(&draft_22), "verifier.example.com");
+
+        let did = request(serde_json::json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_uri": "https://verifier.example.com/response",
+        }));
+        assert_eq!(client_id_scheme(&did).as_deref(), Some("did"));
+        assert_eq!(client_identifier(&did), "did:web:verifier.example.com");
+
+        let decentralized_identifier = request(serde_json::json!({
+            "client_id": "decentralized_identifier:did:web:verifier.example.com",
+            "response_uri": "https://verifier.example.com/response",
+        }));
+        assert_eq!(
+            client_identifier(&decentralized_identifier),
+            "did:web:verifier.example.com"
+        );
+    }
+}
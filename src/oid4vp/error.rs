@@ -1,6 +1,50 @@
+//! The errors of OID4VP flows.
+//!
+//! Each error has a stable, machine-readable code, e.g.
+//! `oid4vp.verifier_denied`, so that apps can handle errors without parsing
+//! their messages. The errors of other modules wrapped by [OID4VPError], such
+//! as [PermissionResponseError], keep their own codes. Unlike the messages,
+//! codes do not change once released.
+//!
+//! Errors caused by another error, such as a JSON or HTTP error of a
+//! dependency, keep it as their [ErrorSource].
+
 // use super::request_signer::RequestSignerError;
 
 use super::permission_request::PermissionResponseError;
+use super::replay::RequestReplayError;
+use super::response_encryption::ResponseEncryptionError;
+use super::trusted_verifiers::TrustedVerifierError;
+use crate::common::Url;
+
+use std::error::Error;
+use std::time::SystemTime;
+
+use uniffi::deps::anyhow;
+
+/// The error another error was caused by, with the messages of its chain of
+/// sources, outermost first, e.g. for logs and bug reports.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, uniffi::Record)]
+#[error("{}", messages.join(": "))]
+pub struct ErrorSource {
+    pub messages: Vec<String>,
+}
+
+impl From<&(dyn Error + 'static)> for ErrorSource {
+    fn from(error: &(dyn Error + 'static)) -> Self {
+        Self {
+            messages: std::iter::successors(Some(error), |error| error.source())
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// The message of an error, and the error as the source of another one.
+pub(crate) fn caused_by(error: anyhow::Error) -> (String, Option<ErrorSource>) {
+    let source: &(dyn Error + 'static) = error.as_ref();
+    (error.to_string(), Some(source.into()))
+}
 
 /// The [OID4VPError] enum represents the errors that can occur
 /// when using the oid4vp foreign library.
@@ -8,16 +52,48 @@ use super::permission_request::PermissionResponseError;
 pub enum OID4VPError {
     #[error("An unexpected foreign callback error occurred: {0}")]
     UnexpectedUniFFICallbackError(String),
-    #[error("Failed to validate the OID4VP Request: {0}")]
-    RequestValidation(String),
-    #[error("Failed to resolve the presentation definition: {0}")]
-    PresentationDefinitionResolution(String),
+    #[error("Failed to validate the OID4VP Request: {reason}")]
+    RequestValidation {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Failed to resolve the presentation definition: {reason}")]
+    PresentationDefinitionResolution {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
     #[error("Failed to create verifiable presentation token: {0}")]
     Token(String),
-    #[error("Unsupported Response Mode for OID4VP Request: {0}")]
-    UnsupportedResponseMode(String),
-    #[error("Failed to submit OID4VP response: {0}")]
-    ResponseSubmission(String),
+    #[error("Unsupported Response Mode for OID4VP Request: {response_mode}")]
+    UnsupportedResponseMode { response_mode: String },
+    #[error("Failed to submit OID4VP response: {reason}")]
+    ResponseSubmission {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error(transparent)]
+    ResponseEncryption(#[from] ResponseEncryptionError),
+    /// The response could not be delivered, e.g. as the verifier is
+    /// unreachable or temporarily unavailable, and can be submitted again.
+    #[error("Failed to submit OID4VP response, which can be retried: {reason}")]
+    ResponseSubmissionRetryable {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    /// The verifier rejected the response with an error response.
+    #[error("The verifier rejected the response: {error}")]
+    VerifierRejected {
+        status_code: u16,
+        /// The error code, e.g. `invalid_request`.
+        error: String,
+        error_description: Option<String>,
+        /// The URI the verifier asks to redirect the user to, if any.
+        redirect_uri: Option<Url>,
+    },
     #[error("Credential callback error: {0}")]
     CredentialCallback(String),
     #[error("Failed to create presentation submission: {0}")]
@@ -26,16 +102,32 @@ pub enum OID4VPError {
     InvalidDIDUrl(String),
     #[error("Failed to generate DID key URL: {0}")]
     DIDKeyGenerateUrl(String),
-    #[error("Failed to encode credential: {0}")]
-    CredentialEncodingError(String),
-    #[error("Failed to decode credential: {0}")]
-    CredentialDecodingError(String),
-    #[error("Failed to parse JSON syntax: {0}")]
-    JsonSyntaxParse(String),
+    #[error("Failed to encode credential: {reason}")]
+    CredentialEncodingError {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Failed to decode credential: {reason}")]
+    CredentialDecodingError {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Failed to parse JSON syntax: {reason}")]
+    JsonSyntaxParse {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
     #[error(transparent)]
     VdcCollection(#[from] crate::vdc_collection::VdcCollectionError),
-    #[error("HTTP Client Initialization Error: {0}")]
-    HttpClientInitialization(String),
+    #[error("HTTP Client Initialization Error: {reason}")]
+    HttpClientInitialization {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
     #[error("Signing algorithm not found: {0}")]
     SigningAlgorithmNotFound(String),
     #[error("Unsupported Client ID Scheme: {0}")]
@@ -54,10 +146,201 @@ pub enum OID4VPError {
     AuthorizationRequestNotFound,
     #[error("Request signer not found")]
     RequestSignerNotFound,
-    #[error("Failed to initialize metadata: {0}")]
-    MetadataInitialization(String),
+    #[error("Failed to initialize metadata: {reason}")]
+    MetadataInitialization {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
     #[error(transparent)]
     PermissionResponse(#[from] PermissionResponseError),
+    #[error("Failed to acquire lock for {0}")]
+    LockError(String),
+    #[error("Invalid request origin: {origin}")]
+    InvalidOrigin { origin: String },
+    #[error("Request is not bound to the origin: {origin}")]
+    OriginMismatch { origin: String },
+    #[error("Verifier was denied by the user: {client_id}")]
+    VerifierDenied { client_id: String },
+    #[error("Certificate does not match the pins of the host: {host}")]
+    CertificatePinning { host: String },
+    #[error("The request was cancelled")]
+    Cancelled,
+    #[error("Invalid transaction data: {0}")]
+    InvalidTransactionData(String),
+    #[error("The request is not a signed request object")]
+    UnsignedRequestObject,
+    #[error("The request object is signed with a weak algorithm: {algorithm}")]
+    WeakRequestAlgorithm { algorithm: String },
+    #[error("The request object is signed with an algorithm that is not allowed: {algorithm}")]
+    DisallowedRequestAlgorithm { algorithm: String },
+    #[error(transparent)]
+    RequestReplay(#[from] RequestReplayError),
+    #[error(transparent)]
+    TrustedVerifier(#[from] TrustedVerifierError),
+    #[error("The authorization request has expired")]
+    RequestExpired,
+    /// The request is not valid before `valid_from`, its `nbf`, or its `iat`
+    /// when it was issued in the future.
+    #[error("The authorization request is not valid before {valid_from:?}")]
+    RequestNotYetValid { valid_from: SystemTime },
+    #[error("The authorization request is larger than {max_size} bytes")]
+    RequestTooLarge { max_size: u32 },
+    #[error("The request is outside the profile: {0}")]
+    OutOfProfile(String),
+    #[error("Failed to resolve the federation trust chain: {reason}")]
+    FederationResolution {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("No pending session has the ID: {session_id}")]
+    UnknownSession { session_id: String },
+    #[error("The response policy does not allow sending responses to: {uri}")]
+    DisallowedResponseUri { uri: String },
+    #[error("A selected credential violates its schema {schema_uri}: {reason}")]
+    SchemaViolation { schema_uri: String, reason: String },
+    #[error("The presentation definition is too large: {0}")]
+    PresentationDefinitionTooLarge(String),
+}
+
+impl OID4VPError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnexpectedUniFFICallbackError(..) => "oid4vp.unexpected_callback_error",
+            Self::RequestValidation { .. } => "oid4vp.request_validation",
+            Self::PresentationDefinitionResolution { .. } => {
+                "oid4vp.presentation_definition_resolution"
+            }
+            Self::Token(..) => "oid4vp.token",
+            Self::UnsupportedResponseMode { .. } => "oid4vp.unsupported_response_mode",
+            Self::ResponseSubmission { .. } => "oid4vp.response_submission",
+            Self::ResponseEncryption(e) => e.code(),
+            Self::ResponseSubmissionRetryable { .. } => "oid4vp.response_submission_retryable",
+            Self::VerifierRejected { .. } => "oid4vp.verifier_rejected",
+            Self::CredentialCallback(..) => "oid4vp.credential_callback",
+            Self::PresentationSubmissionCreation(..) => "oid4vp.presentation_submission_creation",
+            Self::InvalidDIDUrl(..) => "oid4vp.invalid_did_url",
+            Self::DIDKeyGenerateUrl(..) => "oid4vp.did_key_generate_url",
+            Self::CredentialEncodingError { .. } => "oid4vp.credential_encoding_error",
+            Self::CredentialDecodingError { .. } => "oid4vp.credential_decoding_error",
+            Self::JsonSyntaxParse { .. } => "oid4vp.json_syntax_parse",
+            Self::VdcCollection(e) => e.code(),
+            Self::HttpClientInitialization { .. } => "oid4vp.http_client_initialization",
+            Self::SigningAlgorithmNotFound(..) => "oid4vp.signing_algorithm_not_found",
+            Self::InvalidClientIdScheme(..) => "oid4vp.invalid_client_id_scheme",
+            Self::InputDescriptorNotFound => "oid4vp.input_descriptor_not_found",
+            Self::VpTokenParse(..) => "oid4vp.vp_token_parse",
+            Self::VpTokenCreate(..) => "oid4vp.vp_token_create",
+            Self::JwkParse(..) => "oid4vp.jwk_parse",
+            Self::VdcCollectionNotInitialized => "oid4vp.vdc_collection_not_initialized",
+            Self::AuthorizationRequestNotFound => "oid4vp.authorization_request_not_found",
+            Self::RequestSignerNotFound => "oid4vp.request_signer_not_found",
+            Self::MetadataInitialization { .. } => "oid4vp.metadata_initialization",
+            Self::PermissionResponse(e) => e.code(),
+            Self::LockError(..) => "oid4vp.lock_error",
+            Self::InvalidOrigin { .. } => "oid4vp.invalid_origin",
+            Self::OriginMismatch { .. } => "oid4vp.origin_mismatch",
+            Self::VerifierDenied { .. } => "oid4vp.verifier_denied",
+            Self::CertificatePinning { .. } => "oid4vp.certificate_pinning",
+            Self::Cancelled => "oid4vp.cancelled",
+            Self::InvalidTransactionData(..) => "oid4vp.invalid_transaction_data",
+            Self::UnsignedRequestObject => "oid4vp.unsigned_request_object",
+            Self::WeakRequestAlgorithm { .. } => "oid4vp.weak_request_algorithm",
+            Self::DisallowedRequestAlgorithm { .. } => "oid4vp.disallowed_request_algorithm",
+            Self::RequestReplay(e) => e.code(),
+            Self::TrustedVerifier(e) => e.code(),
+            Self::RequestExpired => "oid4vp.request_expired",
+            Self::RequestNotYetValid { .. } => "oid4vp.request_not_yet_valid",
+            Self::RequestTooLarge { .. } => "oid4vp.request_too_large",
+            Self::OutOfProfile(..) => "oid4vp.out_of_profile",
+            Self::FederationResolution { .. } => "oid4vp.federation_resolution",
+            Self::UnknownSession { .. } => "oid4vp.unknown_session",
+            Self::DisallowedResponseUri { .. } => "oid4vp.disallowed_response_uri",
+            Self::SchemaViolation { .. } => "oid4vp.schema_violation",
+            Self::PresentationDefinitionTooLarge(..) => "oid4vp.presentation_definition_too_large",
+        }
+    }
+
+    /// Check whether the operation can be retried as is, e.g. after the
+    /// network is available again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::ResponseSubmissionRetryable { .. })
+    }
+
+    /// The request failed to validate with the error.
+    pub(crate) fn request_validation(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::RequestValidation { reason, source }
+    }
+
+    /// The presentation definition failed to resolve with the error.
+    pub(crate) fn definition_resolution(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::PresentationDefinitionResolution { reason, source }
+    }
+
+    /// The response failed to submit with the error.
+    pub(crate) fn response_submission(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::ResponseSubmission { reason, source }
+    }
+
+    /// The response failed to submit with the error, and can be submitted again.
+    pub(crate) fn retryable_submission(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::ResponseSubmissionRetryable { reason, source }
+    }
+
+    /// JSON failed to parse with the error.
+    pub(crate) fn json_syntax_parse(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::JsonSyntaxParse { reason, source }
+    }
+
+    /// A credential failed to encode with the error.
+    pub(crate) fn credential_encoding(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::CredentialEncodingError { reason, source }
+    }
+
+    /// A credential failed to decode with the error.
+    pub(crate) fn credential_decoding(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::CredentialDecodingError { reason, source }
+    }
+
+    /// The HTTP client failed to initialize with the error.
+    pub(crate) fn http_client_initialization(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::HttpClientInitialization { reason, source }
+    }
+
+    /// The metadata failed to initialize with the error.
+    pub(crate) fn metadata_initialization(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::MetadataInitialization { reason, source }
+    }
+
+    /// The federation trust chain failed to resolve with the error.
+    pub(crate) fn federation_resolution(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::FederationResolution { reason, source }
+    }
+}
+
+/// Return the stable, machine-readable code of the error, e.g.
+/// `oid4vp.verifier_denied`.
+#[uniffi::export]
+pub fn oid4vp_error_code(error: OID4VPError) -> String {
+    error.code().to_string()
+}
+
+/// Check whether the operation failing with the error can be retried as is.
+#[uniffi::export]
+pub fn oid4vp_error_is_retryable(error: OID4VPError) -> bool {
+    error.is_retryable()
 }
 
 // Handle unexpected errors when calling a foreign callback
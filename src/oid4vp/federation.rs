@@ -0,0 +1,507 @@
+//! Resolution of verifiers using the OpenID Federation client ID scheme,
+//! whose client ID is their entity identifier.
+//!
+//! The entity configuration of the verifier, a self-signed JWT served at
+//! `/.well-known/openid-federation`, names its superiors in its
+//! `authority_hints`. Each superior vouches for the keys of its subordinate
+//! with a subordinate statement, fetched from its `federation_fetch_endpoint`,
+//! up to a configured trust anchor:
+//!
+//! ```text
+//! verifier EC <- subordinate statement of superior <- ... <- trust anchor keys
+//! ```
+//!
+//! The request object must then be signed with a key of the verifier
+//! metadata of the entity configuration.
+//!
+//! NOTE: the metadata policies of superiors are not applied, and keys are
+//! only read from `jwks`, not from `jwks_uri` or `signed_jwks_uri`.
+
+use super::error::OID4VPError;
+use super::request;
+
+use std::time::{Duration, SystemTime};
+
+use base64::prelude::*;
+use futures::future::BoxFuture;
+use oid4vci::oauth2::http::{header, Method, Request};
+use openid4vp::core::util::AsyncHttpClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use ssi::{claims::jws::verify_bytes, jwk::Algorithm, JWK};
+
+/// The `client_id_scheme` of federation entities, in drafts with the
+/// parameter.
+const CLIENT_ID_SCHEME: &str = "entity_id";
+/// The prefix of federation client IDs, in drafts without the parameter.
+const CLIENT_ID_PREFIX: &str = "openid_federation:";
+/// The path of entity configurations, relative to the entity identifier.
+const ENTITY_CONFIGURATION_PATH: &str = "/.well-known/openid-federation";
+/// The media type of entity statements.
+const ENTITY_STATEMENT_TYPE: &str = "entity-statement+jwt";
+/// The entity types whose metadata describes verifiers, in order of
+/// preference.
+const VERIFIER_ENTITY_TYPES: &[&str] = &["openid_credential_verifier", "openid_relying_party"];
+/// The maximum number of superiors between a verifier and a trust anchor.
+const MAX_CHAIN_LENGTH: usize = 5;
+
+/// A trust anchor of the federations whose verifiers are trusted.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct FederationTrustAnchor {
+    /// The entity identifier of the trust anchor.
+    pub entity_id: String,
+    /// The federation keys of the trust anchor, as a JSON encoded JWK set.
+    pub jwks: String,
+}
+
+/// A verifier whose trust chain leads to a trust anchor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct FederationVerifier {
+    /// The entity identifier of the verifier.
+    pub entity_id: String,
+    /// The entity identifiers of the chain, from the verifier to the trust
+    /// anchor.
+    pub chain: Vec<String>,
+    /// The verifier metadata of the entity configuration, as JSON.
+    pub metadata: String,
+    /// The `organization_name` of the `federation_entity` metadata, if any.
+    pub organization_name: Option<String>,
+    /// The earliest expiry of the statements of the chain.
+    pub expires_at: SystemTime,
+}
+
+/// Return the entity identifier of the verifier of a request object, if it
+/// uses the OpenID Federation client ID scheme.
+pub(crate) fn entity_id(request_object: &str) -> Option<String> {
+    let claims = request::jwt_part(request_object, 1)?;
+    let client_id = claims["client_id"].as_str()?;
+
+    match claims["client_id_scheme"].as_str() {
+        Some(CLIENT_ID_SCHEME) => Some(client_id.to_owned()),
+        Some(_) => None,
+        None => client_id
+            .strip_prefix(CLIENT_ID_PREFIX)
+            .map(ToOwned::to_owned),
+    }
+}
+
+/// Resolve the trust chain of a verifier to one of the trust anchors, and
+/// verify that the request object is signed with a key of its metadata.
+///
+/// Returns the verified claims of the request object, and the verifier.
+pub(crate) async fn resolve(
+    entity_id: &str,
+    request_object: &str,
+    trust_anchors: &[FederationTrustAnchor],
+    client: &(impl AsyncHttpClient + Sync),
+    now: SystemTime,
+) -> Result<(Json, FederationVerifier), OID4VPError> {
+    if trust_anchors.is_empty() {
+        return Err(error("no federation trust anchors are configured"));
+    }
+
+    let (configuration_jwt, configuration) =
+        entity_configuration(entity_id, None, client, now).await?;
+    let (chain, expires_at) = chain_to_anchor(
+        entity_id.to_owned(),
+        configuration_jwt,
+        configuration.clone(),
+        trust_anchors,
+        client,
+        now,
+        0,
+    )
+    .await?;
+
+    let metadata = VERIFIER_ENTITY_TYPES
+        .iter()
+        .find_map(|entity_type| configuration["metadata"].get(*entity_type))
+        .ok_or_else(|| error("the entity configuration has no verifier metadata"))?;
+    let claims = verify_jwt(request_object, &metadata["jwks"], None)?;
+
+    Ok((
+        claims,
+        FederationVerifier {
+            entity_id: entity_id.to_owned(),
+            chain,
+            metadata: metadata.to_string(),
+            organization_name: configuration["metadata"]["federation_entity"]["organization_name"]
+                .as_str()
+                .map(ToOwned::to_owned),
+            expires_at,
+        },
+    ))
+}
+
+/// Follow the authority hints of an entity to a trust anchor, returning the
+/// entity identifiers of the chain and its earliest expiry.
+fn chain_to_anchor<'a, C: AsyncHttpClient + Sync>(
+    entity_id: String,
+    configuration_jwt: String,
+    configuration: Json,
+    trust_anchors: &'a [FederationTrustAnchor],
+    client: &'a C,
+    now: SystemTime,
+    depth: usize,
+) -> BoxFuture<'a, Result<(Vec<String>, SystemTime), OID4VPError>> {
+    Box::pin(async move {
+        if depth >= MAX_CHAIN_LENGTH {
+            return Err(error("the trust chain is too long"));
+        }
+        let expires_at = expiry(&configuration)?;
+
+        let mut last_error = error(&format!("{entity_id} has no authority hints"));
+        for superior_id in configuration["authority_hints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Json::as_str)
+        {
+            let anchor = trust_anchors
+                .iter()
+                .find(|anchor| anchor.entity_id == superior_id);
+            let anchor_jwks = match anchor {
+                Some(anchor) => Some(
+                    serde_json::from_str::<Json>(&anchor.jwks)
+                        .map_err(OID4VPError::federation_resolution)?,
+                ),
+                None => None,
+            };
+
+            let result: Result<_, OID4VPError> = async {
+                let (superior_jwt, superior) =
+                    entity_configuration(superior_id, anchor_jwks.as_ref(), client, now).await?;
+                let statement = subordinate_statement(&superior, &entity_id, client, now).await?;
+
+                // The superior vouches for the keys the entity signs its
+                // configuration with.
+                verify_jwt(&configuration_jwt, &statement["jwks"], None)?;
+                let expires_at = expires_at.min(expiry(&statement)?);
+
+                let (mut chain, superior_expires_at) = match anchor {
+                    Some(_) => (vec![superior_id.to_owned()], expiry(&superior)?),
+                    None => {
+                        chain_to_anchor(
+                            superior_id.to_owned(),
+                            superior_jwt,
+                            superior,
+                            trust_anchors,
+                            client,
+                            now,
+                            depth + 1,
+                        )
+                        .await?
+                    }
+                };
+                chain.insert(0, entity_id.clone());
+                Ok((chain, expires_at.min(superior_expires_at)))
+            }
+            .await;
+
+            match result {
+                Ok(chain) => return Ok(chain),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    })
+}
+
+/// Fetch and verify the entity configuration of an entity, with the keys of
+/// a trust anchor, or its own keys.
+async fn entity_configuration(
+    entity_id: &str,
+    anchor_jwks: Option<&Json>,
+    client: &(impl AsyncHttpClient + Sync),
+    now: SystemTime,
+) -> Result<(String, Json), OID4VPError> {
+    let url = format!(
+        "{}{ENTITY_CONFIGURATION_PATH}",
+        entity_id.trim_end_matches('/')
+    );
+    let jwt = fetch(&url, client).await?;
+
+    let claims = request::jwt_part(&jwt, 1).ok_or_else(|| error("not a JWT"))?;
+    let jwks = anchor_jwks.unwrap_or(&claims["jwks"]);
+    let claims = verify_jwt(&jwt, jwks, Some(ENTITY_STATEMENT_TYPE))?;
+    if claims["iss"] != entity_id || claims["sub"] != entity_id {
+        return Err(error(&format!(
+            "the entity configuration of {entity_id} is not self-issued"
+        )));
+    }
+    check_expiry(&claims, now)?;
+
+    Ok((jwt, claims))
+}
+
+/// Fetch and verify the statement of a superior about a subordinate.
+async fn subordinate_statement(
+    superior: &Json,
+    subordinate_id: &str,
+    client: &(impl AsyncHttpClient + Sync),
+    now: SystemTime,
+) -> Result<Json, OID4VPError> {
+    let superior_id = superior["sub"].as_str().unwrap_or_default();
+    let endpoint = superior["metadata"]["federation_entity"]["federation_fetch_endpoint"]
+        .as_str()
+        .ok_or_else(|| error(&format!("{superior_id} has no federation fetch endpoint")))?;
+    let mut url = url::Url::parse(endpoint).map_err(OID4VPError::federation_resolution)?;
+    url.query_pairs_mut().append_pair("sub", subordinate_id);
+
+    let jwt = fetch(url.as_str(), client).await?;
+    let claims = verify_jwt(&jwt, &superior["jwks"], Some(ENTITY_STATEMENT_TYPE))?;
+    if claims["iss"] != superior_id || claims["sub"] != subordinate_id {
+        return Err(error(&format!(
+            "the statement of {superior_id} is not about {subordinate_id}"
+        )));
+    }
+    check_expiry(&claims, now)?;
+
+    Ok(claims)
+}
+
+async fn fetch(url: &str, client: &(impl AsyncHttpClient + Sync)) -> Result<String, OID4VPError> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .header(header::ACCEPT, "application/entity-statement+jwt")
+        .body(vec![])
+        .map_err(OID4VPError::federation_resolution)?;
+    let response = client
+        .execute(request)
+        .await
+        .map_err(OID4VPError::federation_resolution)?;
+    if !response.status().is_success() {
+        return Err(error(&format!(
+            "request to {url} failed: {}",
+            response.status()
+        )));
+    }
+
+    String::from_utf8(response.into_body())
+        .map(|jwt| jwt.trim().to_owned())
+        .map_err(OID4VPError::federation_resolution)
+}
+
+/// Verify a JWT with the key of a JWK set its `kid` names, or any key of the
+/// set if it names none, and return its claims.
+fn verify_jwt(jwt: &str, jwks: &Json, typ: Option<&str>) -> Result<Json, OID4VPError> {
+    let header = request::jwt_part(jwt, 0).ok_or_else(|| error("not a JWT"))?;
+    if typ.is_some_and(|typ| header["typ"] != typ) {
+        return Err(error("the statement is not an entity statement"));
+    }
+    let (signing_input, signature) = jwt.rsplit_once('.').ok_or_else(|| error("not a JWT"))?;
+    let signature = BASE64_URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(OID4VPError::federation_resolution)?;
+    let algorithm: Algorithm = serde_json::from_value(header["alg"].clone())
+        .map_err(OID4VPError::federation_resolution)?;
+
+    let verified = jwks["keys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|key| header.get("kid").is_none() || key["kid"] == header["kid"])
+        .filter_map(|key| serde_json::from_value::<JWK>(key.clone()).ok())
+        .any(|key| verify_bytes(algorithm, signing_input.as_bytes(), &key, &signature).is_ok());
+    if !verified {
+        return Err(error(
+            "the signature does not verify with the keys of the entity",
+        ));
+    }
+
+    request::jwt_part(jwt, 1).ok_or_else(|| error("not a JWT"))
+}
+
+fn check_expiry(claims: &Json, now: SystemTime) -> Result<(), OID4VPError> {
+    if expiry(claims)? <= now {
+        return Err(error(&format!(
+            "the statement of {} has expired",
+            claims["iss"].as_str().unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+fn expiry(claims: &Json) -> Result<SystemTime, OID4VPError> {
+    claims["exp"]
+        .as_u64()
+        .map(|exp| SystemTime::UNIX_EPOCH + Duration::from_secs(exp))
+        .ok_or_else(|| error("the statement has no expiry"))
+}
+
+fn error(reason: &str) -> OID4VPError {
+    OID4VPError::FederationResolution {
+        reason: reason.into(),
+        source: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use oid4vci::oauth2::{http::Response, HttpRequest, HttpResponse};
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+    use serde_json::json;
+    use uniffi::deps::anyhow;
+
+    struct TestClient(HashMap<String, String>);
+
+    #[async_trait::async_trait]
+    impl AsyncHttpClient for TestClient {
+        async fn execute(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+            let response = match self.0.get(&request.uri().to_string()) {
+                Some(body) => Response::builder()
+                    .status(200)
+                    .body(body.clone().into_bytes()),
+                None => Response::builder().status(404).body(vec![]),
+            };
+            Ok(response?)
+        }
+    }
+
+    fn jwks(key: &SigningKey, kid: &str) -> Json {
+        let point = key.verifying_key().to_encoded_point(false);
+        json!({ "keys": [{
+            "kty": "EC",
+            "crv": "P-256",
+            "kid": kid,
+            "x": BASE64_URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+            "y": BASE64_URL_SAFE_NO_PAD.encode(point.y().unwrap()),
+        }] })
+    }
+
+    fn sign(key: &SigningKey, kid: &str, typ: &str, claims: Json) -> String {
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD
+                .encode(json!({ "alg": "ES256", "kid": kid, "typ": typ }).to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+        );
+        let signature: Signature = key.sign(signing_input.as_bytes());
+        format!(
+            "{signing_input}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+
+    #[tokio::test]
+    async fn resolves_trust_chains() {
+        let verifier = "https://verifier.example.com";
+        let anchor = "https://anchor.example.com";
+        let (verifier_key, request_key, anchor_key) = (
+            SigningKey::from_slice(&[1; 32]).unwrap(),
+            SigningKey::from_slice(&[2; 32]).unwrap(),
+            SigningKey::from_slice(&[3; 32]).unwrap(),
+        );
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        let client = TestClient(HashMap::from([
+            (
+                format!("{verifier}{ENTITY_CONFIGURATION_PATH}"),
+                sign(
+                    &verifier_key,
+                    "federation",
+                    ENTITY_STATEMENT_TYPE,
+                    json!({
+                        "iss": verifier,
+                        "sub": verifier,
+                        "exp": 3_000,
+                        "jwks": jwks(&verifier_key, "federation"),
+                        "authority_hints": [anchor],
+                        "metadata": {
+                            "federation_entity": { "organization_name": "Verifier" },
+                            "openid_credential_verifier": {
+                                "client_name": "Verifier",
+                                "jwks": jwks(&request_key, "request"),
+                            },
+                        },
+                    }),
+                ),
+            ),
+            (
+                format!("{anchor}{ENTITY_CONFIGURATION_PATH}"),
+                sign(
+                    &anchor_key,
+                    "anchor",
+                    ENTITY_STATEMENT_TYPE,
+                    json!({
+                        "iss": anchor,
+                        "sub": anchor,
+                        "exp": 3_000,
+                        "jwks": jwks(&anchor_key, "anchor"),
+                        "metadata": { "federation_entity": {
+                            "federation_fetch_endpoint": format!("{anchor}/fetch"),
+                        } },
+                    }),
+                ),
+            ),
+            (
+                format!("{anchor}/fetch?sub=https%3A%2F%2Fverifier.example.com"),
+                sign(
+                    &anchor_key,
+                    "anchor",
+                    ENTITY_STATEMENT_TYPE,
+                    json!({
+                        "iss": anchor,
+                        "sub": verifier,
+                        "exp": 2_000,
+                        "jwks": jwks(&verifier_key, "federation"),
+                    }),
+                ),
+            ),
+        ]));
+
+        let request_object = sign(
+            &request_key,
+            "request",
+            "oauth-authz-req+jwt",
+            json!({ "client_id": verifier, "client_id_scheme": "entity_id", "nonce": "n" }),
+        );
+        assert_eq!(entity_id(&request_object).as_deref(), Some(verifier));
+
+        let trust_anchors = [FederationTrustAnchor {
+            entity_id: anchor.into(),
+            jwks: jwks(&anchor_key, "anchor").to_string(),
+        }];
+        let (claims, resolved) = resolve(verifier, &request_object, &trust_anchors, &client, now)
+            .await
+            .unwrap();
+        assert_eq!(claims["nonce"], "n");
+        assert_eq!(
+            resolved.chain,
+            vec![verifier.to_string(), anchor.to_string()]
+        );
+        assert_eq!(resolved.organization_name.as_deref(), Some("Verifier"));
+        assert_eq!(
+            resolved.expires_at,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(2_000)
+        );
+
+        // Other anchors, and request objects signed with federation keys, are
+        // not trusted.
+        let other_anchor = [FederationTrustAnchor {
+            entity_id: anchor.into(),
+            jwks: jwks(&verifier_key, "anchor").to_string(),
+        }];
+        assert!(
+            resolve(verifier, &request_object, &other_anchor, &client, now)
+                .await
+                .is_err()
+        );
+        let request_object = sign(
+            &verifier_key,
+            "request",
+            "oauth-authz-req+jwt",
+            json!({ "client_id": verifier }),
+        );
+        assert!(
+            resolve(verifier, &request_object, &trust_anchors, &client, now)
+                .await
+                .is_err()
+        );
+    }
+}
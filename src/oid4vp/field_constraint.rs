@@ -0,0 +1,113 @@
+//! Constraints on the values of requested fields, read from the JSON schema
+//! filters of their input descriptors, so that consent screens can explain
+//! e.g. that the nationality must be `DE`.
+
+use super::permission_request::RequestedField;
+
+use serde_json::Value as Json;
+
+/// What the values of a requested field must be.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct FieldConstraint {
+    /// The JSON type the value must have, e.g. `string`.
+    pub value_type: Option<String>,
+    /// The value the field must be, as JSON.
+    pub equals: Option<String>,
+    /// The values the field must be one of, as JSON.
+    pub one_of: Vec<String>,
+    /// The regular expression string values must match.
+    pub pattern: Option<String>,
+    /// The lower bound of the value, as text, e.g. `18` or `2006-01-01`.
+    pub minimum: Option<String>,
+    /// The upper bound of the value, as text.
+    pub maximum: Option<String>,
+    /// Whether the constraint applies to an element of an array value,
+    /// rather than to the value itself.
+    pub applies_to_element: bool,
+    /// The JSON schema filter the constraint was read from.
+    pub filter: String,
+}
+
+#[uniffi::export]
+impl RequestedField {
+    /// Return the constraint on the values of the field, if it has a filter.
+    pub fn constraint(&self) -> Option<FieldConstraint> {
+        self.filter.as_ref().map(FieldConstraint::from_filter)
+    }
+}
+
+impl FieldConstraint {
+    /// Read a constraint from a filter, or from the schema of the elements
+    /// an array must contain.
+    pub(crate) fn from_filter(filter: &Json) -> Self {
+        let (schema, applies_to_element) = match filter.get("contains") {
+            Some(contains) => (contains, true),
+            None => (filter, false),
+        };
+        let bound = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| schema.get(*key))
+                .map(|value| match value {
+                    Json::String(value) => value.clone(),
+                    value => value.to_string(),
+                })
+        };
+
+        Self {
+            value_type: schema["type"].as_str().map(ToOwned::to_owned),
+            equals: schema.get("const").map(Json::to_string),
+            one_of: schema["enum"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(Json::to_string)
+                .collect(),
+            pattern: schema["pattern"].as_str().map(ToOwned::to_owned),
+            minimum: bound(&[
+                "minimum",
+                "exclusiveMinimum",
+                "formatMinimum",
+                "formatExclusiveMinimum",
+            ]),
+            maximum: bound(&[
+                "maximum",
+                "exclusiveMaximum",
+                "formatMaximum",
+                "formatExclusiveMaximum",
+            ]),
+            applies_to_element,
+            filter: filter.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn reads_constraints() {
+        let nationality = FieldConstraint::from_filter(&json!({
+            "type": "array",
+            "contains": { "type": "string", "const": "DE" },
+        }));
+        assert_eq!(nationality.equals.as_deref(), Some("\"DE\""));
+        assert_eq!(nationality.value_type.as_deref(), Some("string"));
+        assert!(nationality.applies_to_element);
+
+        let birthdate = FieldConstraint::from_filter(&json!({
+            "type": "string",
+            "format": "date",
+            "formatMaximum": "2006-01-01",
+        }));
+        assert_eq!(birthdate.maximum.as_deref(), Some("2006-01-01"));
+        assert_eq!(birthdate.minimum, None);
+
+        let level = FieldConstraint::from_filter(&json!({ "enum": [1, 2], "minimum": 1 }));
+        assert_eq!(level.one_of, vec!["1".to_string(), "2".to_string()]);
+        assert_eq!(level.minimum.as_deref(), Some("1"));
+        assert!(!level.applies_to_element);
+    }
+}
@@ -0,0 +1,92 @@
+use super::holder::Holder;
+use super::status_watch::VerifierStatus;
+use super::verifier_review::VerifierInfo;
+use crate::common::Url;
+
+use std::sync::Arc;
+
+/// A step of a presentation flow.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum FlowEvent {
+    /// The authorization request was fetched and validated.
+    RequestFetched { verifier: VerifierInfo },
+    /// The verifier is trusted, or the user allowed it.
+    VerifierVerified { verifier: VerifierInfo },
+    /// Matching the stored credentials against the request started.
+    MatchingStarted,
+    /// The permission response was submitted to the verifier.
+    ResponseSubmitted,
+    /// The verifier returned a URI to redirect the user to.
+    RedirectReceived { redirect_uri: Url },
+    /// The verifier returned an endpoint to watch the status of the
+    /// presentation with, see [Holder::watch_verifier_status].
+    StatusUriReceived { status_uri: Url },
+    /// The verifier reported a new status of the presentation.
+    VerifierStatusChanged { status: VerifierStatus },
+}
+
+/// Interface: FlowDelegate
+///
+/// The FlowDelegate is notified as a presentation flow progresses, so that
+/// apps can drive progress UI and analytics.
+#[uniffi::export(with_foreign)]
+pub trait FlowDelegate: Send + Sync + std::fmt::Debug {
+    fn on_event(&self, event: FlowEvent);
+}
+
+#[uniffi::export]
+impl Holder {
+    /// Set the delegate notified as presentation flows progress.
+    pub fn set_flow_delegate(&self, delegate: Arc<dyn FlowDelegate>) {
+        *self
+            .flow_delegate
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(delegate);
+    }
+}
+
+impl Holder {
+    /// Notify the flow delegate, if one is set.
+    pub(crate) fn emit(&self, event: FlowEvent) {
+        let delegate = self
+            .flow_delegate
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        if let Some(delegate) = delegate {
+            delegate.on_event(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct TestDelegate(Mutex<Vec<FlowEvent>>);
+
+    impl FlowDelegate for TestDelegate {
+        fn on_event(&self, event: FlowEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn emits_flow_events() {
+        let holder = Holder::new_with_credentials(vec![], vec![]).await.unwrap();
+        holder.emit(FlowEvent::MatchingStarted);
+
+        let delegate = Arc::new(TestDelegate::default());
+        holder.set_flow_delegate(delegate.clone());
+        holder.emit(FlowEvent::MatchingStarted);
+        holder.emit(FlowEvent::ResponseSubmitted);
+
+        assert_eq!(
+            *delegate.0.lock().unwrap(),
+            vec![FlowEvent::MatchingStarted, FlowEvent::ResponseSubmitted]
+        );
+    }
+}
@@ -1,10 +1,52 @@
+use super::artifact_cache::{track_cache_use, CachingHttpClient, VerifierArtifactCache};
+use super::claim_labels::ClaimLabelConfig;
+use super::definition_limits::PresentationDefinitionLimits;
+use super::draft::{self, OID4VPDraft};
 use super::error::OID4VPError;
+use super::federation::{self, FederationTrustAnchor, FederationVerifier};
+use super::flow_events::{FlowDelegate, FlowEvent};
+use super::holder_builder::HolderBuilder;
+use super::key_attestation::KeyAttestationProvider;
+use super::matching::{match_credentials, Candidate};
+use super::minimization::{self, MinimizationPolicy};
+use super::parsing_mode::{self, RequestParsingMode};
 use super::permission_request::*;
+use super::persistence;
+use super::profile::Profile;
+use super::replay::RequestReplayGuard;
+use super::request;
+use super::request_policy::RequestObjectPolicy;
+use super::request_uri;
+use super::response_encryption;
+use super::response_policy::ResponseUriPolicy;
+use super::risk_analysis::{self, RiskAnalysisConfig};
+use super::scope::QueryTemplate;
+use super::sessions::Session;
+use super::status_watch;
+use super::transaction_data;
+use super::trusted_verifiers::TrustedVerifierStore;
+use super::validity;
+use super::verifier_review::{VerifierInfo, VerifierReviewDelegate};
+use super::wallet_metadata::{self, with_request_algorithms, SUPPORTED_ALGORITHMS};
+use super::x509_client_id;
+use crate::clock::{self, Clock};
 use crate::common::*;
+use crate::credential::schema::CredentialSchemaCache;
+use crate::credential::verification::{CredentialVerificationOptions, VerificationCheck};
 use crate::credential::*;
-use crate::vdc_collection::VdcCollection;
+use crate::did::{CachingDidResolver, DidDocumentCache, DidMethodResolver, DidResolverRegistry};
+use crate::metrics::{self, MetricsSink};
+use crate::oid4vci::certificate_pinning_host;
+use crate::presentation_log::{PresentationLog, PresentationOutcome, PresentationRecord};
+use crate::signer::DeviceSigner;
+use crate::status::{StatusListCache, StatusListFetcher};
+use crate::trust_anchors::{TrustAnchorPurpose, TrustAnchorStore};
+use crate::trust_list::TrustListManager;
+use crate::vdc_collection::{CredentialFilter, VdcCollection, VdcCollectionError};
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
 
 use openid4vp::core::authorization_request::parameters::ClientIdScheme;
 use openid4vp::core::credential_format::{ClaimFormatDesignation, ClaimFormatPayload};
@@ -17,12 +59,13 @@ use openid4vp::{
             AuthorizationRequestObject,
         },
         metadata::WalletMetadata,
+        response::AuthorizationResponse,
     },
     wallet::Wallet as OID4VPWallet,
 };
-use ssi::dids::DIDWeb;
 use ssi::dids::VerificationMethodDIDResolver;
 use ssi::prelude::AnyJwkMethod;
+use tokio::sync::watch;
 use uniffi::deps::{anyhow, log};
 
 /// A Holder is an entity that possesses one or more Verifiable Credentials.
@@ -37,35 +80,133 @@ pub struct Holder {
     /// Metadata about the holder.
     pub(crate) metadata: WalletMetadata,
 
+    /// Whether the declared formats are restricted to those of the
+    /// credentials of the holder.
+    pub(crate) derive_formats: bool,
+
     /// HTTP Request Client
-    pub(crate) client: openid4vp::core::util::ReqwestClient,
+    pub(crate) client: CachingHttpClient,
+
+    /// Cache of the verifier artifacts fetched by `client`.
+    pub(crate) artifact_cache: Arc<VerifierArtifactCache>,
 
     /// A list of trusted DIDs.
-    #[allow(dead_code)]
-    pub(crate) trusted_dids: Vec<String>,
+    pub(crate) trusted_dids: RwLock<Vec<String>>,
+
+    /// Store of the verifiers trusted at runtime, with their policies.
+    pub(crate) trusted_verifiers: RwLock<Option<Arc<TrustedVerifierStore>>>,
+
+    /// Trusted lists whose services verify `x509_san_dns` requests.
+    pub(crate) trust_list: RwLock<Option<Arc<TrustListManager>>>,
+
+    /// Reader root certificates verifying `x509_san_dns` requests.
+    pub(crate) trust_anchors: RwLock<Option<Arc<TrustAnchorStore>>>,
+
+    /// Delegate reviewing verifiers that are not trusted.
+    pub(crate) verifier_review_delegate: RwLock<Option<Arc<dyn VerifierReviewDelegate>>>,
 
     /// Provide optional credentials to the holder instance.
     pub(crate) provided_credentials: Option<Vec<Arc<ParsedCredential>>>,
+
+    /// Signer for the device keys credentials are bound to.
+    pub(crate) device_signer: RwLock<Option<Arc<dyn DeviceSigner>>>,
+
+    /// Provider of the attestations of the device keys, for verifiers
+    /// requesting them.
+    pub(crate) key_attestation_provider: RwLock<Option<Arc<dyn KeyAttestationProvider>>>,
+
+    /// Resolvers for the DIDs of verifiers using the `did` client ID scheme.
+    pub(crate) did_resolver: DidResolverRegistry,
+
+    /// Cache of the DID documents resolved by `did_resolver`.
+    pub(crate) did_cache: Arc<DidDocumentCache>,
+
+    /// Log the submitted permission responses are recorded in.
+    pub(crate) presentation_log: RwLock<Option<Arc<PresentationLog>>>,
+
+    /// Notifies in-flight requests when the holder is cancelled.
+    pub(crate) cancellation: watch::Sender<u64>,
+
+    /// Delegate notified as presentation flows progress.
+    pub(crate) flow_delegate: RwLock<Option<Arc<dyn FlowDelegate>>>,
+
+    /// Policy authorization requests must meet.
+    pub(crate) request_object_policy: RwLock<RequestObjectPolicy>,
+
+    /// Limits presentation definitions must stay within.
+    pub(crate) definition_limits: RwLock<PresentationDefinitionLimits>,
+
+    /// Policy the response and redirect URIs of requests must meet.
+    pub(crate) response_uri_policy: RwLock<ResponseUriPolicy>,
+
+    /// Cache of the schemas selected credentials are validated against
+    /// before they are presented.
+    pub(crate) schema_cache: RwLock<Option<Arc<CredentialSchemaCache>>>,
+
+    /// Guard refusing authorization requests that were already received.
+    pub(crate) replay_guard: RwLock<Option<Arc<RequestReplayGuard>>>,
+
+    /// Whether matching stops once the submission requirements are satisfiable.
+    pub(crate) short_circuit_matching: RwLock<bool>,
+
+    /// Cache of the status lists fetched when checking the status of matched
+    /// credentials.
+    pub(crate) status_cache: RwLock<Option<Arc<StatusListCache>>>,
+
+    /// How strictly presentation definitions are parsed.
+    pub(crate) parsing_mode: RwLock<RequestParsingMode>,
+
+    /// Sink the durations and outcomes of operations are recorded in.
+    pub(crate) metrics_sink: RwLock<Option<Arc<dyn MetricsSink>>>,
+
+    /// The interoperability profile requests must conform to.
+    pub(crate) profile: RwLock<Profile>,
+
+    /// Trust anchors of the federations whose verifiers are trusted.
+    pub(crate) federation_trust_anchors: RwLock<Vec<FederationTrustAnchor>>,
+
+    /// Configuration of the risk analysis of requests, if enabled.
+    pub(crate) risk_analysis: RwLock<Option<RiskAnalysisConfig>>,
+
+    /// The clock expiry and validity checks read the time from.
+    pub(crate) clock: RwLock<Arc<dyn Clock>>,
+
+    /// The leeway of expiry and validity checks, for skewed clocks.
+    pub(crate) clock_leeway: RwLock<Duration>,
+
+    /// The query templates of the `scope` values of requests, for requests
+    /// without a presentation definition.
+    pub(crate) scope_queries: RwLock<HashMap<String, QueryTemplate>>,
+
+    /// The data minimization policies of the app.
+    pub(crate) minimization_policies: RwLock<Vec<MinimizationPolicy>>,
+
+    /// The localization of the labels of requested fields.
+    pub(crate) claim_labels: RwLock<ClaimLabelConfig>,
+
+    /// The pending presentation sessions, by ID.
+    pub(crate) sessions: Mutex<HashMap<Uuid, Session>>,
+
+    /// How long presentation sessions last.
+    pub(crate) session_ttl: RwLock<Duration>,
 }
 
 #[uniffi::export(async_runtime = "tokio")]
 impl Holder {
     /// Uses VDC collection to retrieve the credentials for a given presentation definition.
+    ///
+    /// The HTTP client and the wallet metadata are configured through
+    /// [HolderBuilder].
     #[uniffi::constructor]
     pub async fn new(
         vdc_collection: Arc<VdcCollection>,
         trusted_dids: Vec<String>,
     ) -> Result<Arc<Self>, OID4VPError> {
-        let client = openid4vp::core::util::ReqwestClient::new()
-            .map_err(|e| OID4VPError::HttpClientInitialization(format!("{e:?}")))?;
-
-        Ok(Arc::new(Self {
-            client,
-            vdc_collection: Some(vdc_collection),
-            metadata: Self::metadata()?,
-            trusted_dids,
-            provided_credentials: None,
-        }))
+        HolderBuilder::new()
+            .vdc_collection(vdc_collection)
+            .trusted_dids(trusted_dids)
+            .build()
+            .await
     }
 
     /// Construct a new holder with provided credentials
@@ -73,21 +214,19 @@ impl Holder {
     ///
     /// This constructor will use the provided credentials for the presentation,
     /// instead of searching for credentials in the VDC collection.
+    ///
+    /// The HTTP client and the wallet metadata are configured through
+    /// [HolderBuilder].
     #[uniffi::constructor]
     pub async fn new_with_credentials(
         provided_credentials: Vec<Arc<ParsedCredential>>,
         trusted_dids: Vec<String>,
     ) -> Result<Arc<Self>, OID4VPError> {
-        let client = openid4vp::core::util::ReqwestClient::new()
-            .map_err(|e| OID4VPError::HttpClientInitialization(format!("{e:?}")))?;
-
-        Ok(Arc::new(Self {
-            client,
-            vdc_collection: None,
-            metadata: Self::metadata()?,
-            trusted_dids,
-            provided_credentials: Some(provided_credentials),
-        }))
+        HolderBuilder::new()
+            .credentials(provided_credentials)
+            .trusted_dids(trusted_dids)
+            .build()
+            .await
     }
 
     /// Given an authorization request URL, return a permission request,
@@ -95,117 +234,1082 @@ impl Holder {
     /// that align with the presentation definition of the request.
     ///
     /// This will fetch the presentation definition from the verifier.
+    ///
+    /// Verifier artifacts that cannot be fetched are served from the verifier
+    /// artifact cache when it has a recent copy, which is flagged by
+    /// [PermissionRequest::served_from_cache].
     pub async fn authorization_request(
         &self,
         url: Url,
-        // Callback here to allow for review of untrusted DIDs.
     ) -> Result<Arc<PermissionRequest>, OID4VPError> {
-        let request = self
-            .validate_request(url)
-            .await
-            .map_err(|e| OID4VPError::RequestValidation(format!("{e:?}")))?;
+        let (permission_request, served_from_cache) =
+            track_cache_use(self.cancellable(self.resolve_authorization_request(url))).await;
+        let permission_request = permission_request?;
 
-        match request.response_mode() {
-            ResponseMode::DirectPost | ResponseMode::DirectPostJwt => {
-                self.permission_request(request).await
-            }
-            ResponseMode::Unsupported(mode) => {
-                Err(OID4VPError::UnsupportedResponseMode(mode.to_owned()))
-            }
+        if !served_from_cache {
+            return Ok(permission_request);
+        }
+        Ok(Arc::new(PermissionRequest {
+            served_from_cache,
+            ..(*permission_request).clone()
+        }))
+    }
+
+    /// Return the cache of verifier artifacts, such as presentation
+    /// definitions and client metadata, which are served when fetching them
+    /// fails.
+    pub fn verifier_artifact_cache(&self) -> Arc<VerifierArtifactCache> {
+        self.artifact_cache.clone()
+    }
+
+    /// Set the signer used for device authentication when presenting
+    /// credentials bound to a device key, such as mdocs.
+    pub fn set_device_signer(&self, signer: Arc<dyn DeviceSigner>) -> Result<(), OID4VPError> {
+        *self
+            .device_signer
+            .write()
+            .map_err(|_| OID4VPError::LockError("device_signer".into()))? = Some(signer);
+        Ok(())
+    }
+
+    /// Set the provider of the attestations of the device keys, embedded in
+    /// the presentations of verifiers requesting them.
+    pub fn set_key_attestation_provider(
+        &self,
+        provider: Arc<dyn KeyAttestationProvider>,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .key_attestation_provider
+            .write()
+            .map_err(|_| OID4VPError::LockError("key_attestation_provider".into()))? =
+            Some(provider);
+        Ok(())
+    }
+
+    /// Set the delegate reviewing verifiers that are not in the trust store.
+    pub fn set_verifier_review_delegate(
+        &self,
+        delegate: Arc<dyn VerifierReviewDelegate>,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .verifier_review_delegate
+            .write()
+            .map_err(|_| OID4VPError::LockError("verifier_review_delegate".into()))? =
+            Some(delegate);
+        Ok(())
+    }
+
+    /// Set the policy authorization requests must meet, e.g. to require
+    /// signed request objects.
+    pub fn set_request_object_policy(
+        &self,
+        policy: RequestObjectPolicy,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .request_object_policy
+            .write()
+            .map_err(|_| OID4VPError::LockError("request_object_policy".into()))? = policy;
+        Ok(())
+    }
+
+    /// Set the limits presentation definitions must stay within, for
+    /// requests with larger definitions to fail with
+    /// [OID4VPError::PresentationDefinitionTooLarge].
+    pub fn set_definition_limits(
+        &self,
+        limits: PresentationDefinitionLimits,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .definition_limits
+            .write()
+            .map_err(|_| OID4VPError::LockError("definition_limits".into()))? = limits;
+        Ok(())
+    }
+
+    /// Set the policy the response and redirect URIs of requests must meet
+    /// for responses to be submitted, e.g. to only send responses to
+    /// allow-listed domains.
+    pub fn set_response_uri_policy(&self, policy: ResponseUriPolicy) -> Result<(), OID4VPError> {
+        *self
+            .response_uri_policy
+            .write()
+            .map_err(|_| OID4VPError::LockError("response_uri_policy".into()))? = policy;
+        Ok(())
+    }
+
+    /// Set the cache of the schemas selected credentials are validated
+    /// against, see [ParsedCredential::validate_schemas], for responses with
+    /// credentials violating their schemas not to be submitted.
+    pub fn set_schema_cache(&self, cache: Arc<CredentialSchemaCache>) -> Result<(), OID4VPError> {
+        *self
+            .schema_cache
+            .write()
+            .map_err(|_| OID4VPError::LockError("schema_cache".into()))? = Some(cache);
+        Ok(())
+    }
+
+    /// Set the interoperability profile requests must conform to, e.g.
+    /// [Profile::Haip] to reject requests outside of HAIP.
+    pub fn set_profile(&self, profile: Profile) -> Result<(), OID4VPError> {
+        *self
+            .profile
+            .write()
+            .map_err(|_| OID4VPError::LockError("profile".into()))? = profile;
+        Ok(())
+    }
+
+    /// Set the trust anchors of the federations whose verifiers, using the
+    /// OpenID Federation client ID scheme, are trusted.
+    pub fn set_federation_trust_anchors(
+        &self,
+        trust_anchors: Vec<FederationTrustAnchor>,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .federation_trust_anchors
+            .write()
+            .map_err(|_| OID4VPError::LockError("federation_trust_anchors".into()))? =
+            trust_anchors;
+        Ok(())
+    }
+
+    /// Enable the risk analysis of authorization requests, whose warnings are
+    /// returned by [PermissionRequest::risk_warnings].
+    pub fn set_risk_analysis(&self, config: RiskAnalysisConfig) -> Result<(), OID4VPError> {
+        *self
+            .risk_analysis
+            .write()
+            .map_err(|_| OID4VPError::LockError("risk_analysis".into()))? = Some(config);
+        Ok(())
+    }
+
+    /// Set the clock expiry and validity checks read the time from, instead
+    /// of the clock of the system.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) -> Result<(), OID4VPError> {
+        *self
+            .clock
+            .write()
+            .map_err(|_| OID4VPError::LockError("clock".into()))? = clock;
+        Ok(())
+    }
+
+    /// Set the leeway of expiry and validity checks, tolerating the skew
+    /// between the clocks of the device and of verifiers.
+    pub fn set_clock_leeway(&self, leeway: Duration) -> Result<(), OID4VPError> {
+        *self
+            .clock_leeway
+            .write()
+            .map_err(|_| OID4VPError::LockError("clock_leeway".into()))? = leeway;
+        Ok(())
+    }
+
+    /// Set the query templates of the `scope` values requests without a
+    /// presentation definition are resolved with, replacing the
+    /// [default_scope_queries](super::scope::default_scope_queries).
+    pub fn set_scope_queries(
+        &self,
+        queries: HashMap<String, QueryTemplate>,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .scope_queries
+            .write()
+            .map_err(|_| OID4VPError::LockError("scope_queries".into()))? = queries;
+        Ok(())
+    }
+
+    /// Set the data minimization policies enforced by the permission
+    /// responses of the following requests.
+    pub fn set_minimization_policies(
+        &self,
+        policies: Vec<MinimizationPolicy>,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .minimization_policies
+            .write()
+            .map_err(|_| OID4VPError::LockError("minimization_policies".into()))? = policies;
+        Ok(())
+    }
+
+    /// Set the preferred locales and the overrides of the labels of the
+    /// requested fields of the following requests, see
+    /// [RequestedField::label].
+    pub fn set_claim_labels(&self, config: ClaimLabelConfig) -> Result<(), OID4VPError> {
+        *self
+            .claim_labels
+            .write()
+            .map_err(|_| OID4VPError::LockError("claim_labels".into()))? = config;
+        Ok(())
+    }
+
+    /// Set the guard refusing authorization requests whose `nonce` or
+    /// `state` was already received.
+    pub fn set_request_replay_guard(
+        &self,
+        guard: Arc<RequestReplayGuard>,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .replay_guard
+            .write()
+            .map_err(|_| OID4VPError::LockError("replay_guard".into()))? = Some(guard);
+        Ok(())
+    }
+
+    /// Remove the requests the replay guard remembers for longer than its TTL,
+    /// by the clock of the holder.
+    pub fn purge_expired_requests(&self) -> Result<(), OID4VPError> {
+        if let Some(replay_guard) = self.replay_guard()? {
+            replay_guard.purge_expired(self.now()?)?;
         }
+        Ok(())
+    }
+
+    /// Set the store of trusted verifiers, whose policies are consulted
+    /// before the trusted DIDs when reviewing verifiers.
+    pub fn set_trusted_verifier_store(
+        &self,
+        store: Arc<TrustedVerifierStore>,
+    ) -> Result<(), OID4VPError> {
+        *self
+            .trusted_verifiers
+            .write()
+            .map_err(|_| OID4VPError::LockError("trusted_verifiers".into()))? = Some(store);
+        Ok(())
+    }
+
+    /// Set the trusted lists whose services are trusted to issue the
+    /// certificates of verifiers using the `x509_san_dns` client ID scheme.
+    pub fn set_trust_list(&self, trust_list: Arc<TrustListManager>) -> Result<(), OID4VPError> {
+        *self
+            .trust_list
+            .write()
+            .map_err(|_| OID4VPError::LockError("trust_list".into()))? = Some(trust_list);
+        Ok(())
+    }
+
+    /// Set the store of trust anchors whose reader root certificates are
+    /// trusted to issue the certificates of verifiers using the
+    /// `x509_san_dns` client ID scheme.
+    pub fn set_trust_anchor_store(&self, store: Arc<TrustAnchorStore>) -> Result<(), OID4VPError> {
+        *self
+            .trust_anchors
+            .write()
+            .map_err(|_| OID4VPError::LockError("trust_anchors".into()))? = Some(store);
+        Ok(())
+    }
+
+    /// Set whether matching credentials stops as soon as the matches satisfy
+    /// the submission requirements, which cuts latency on large collections
+    /// at the cost of offering fewer alternatives.
+    pub fn set_short_circuit_matching(&self, enabled: bool) -> Result<(), OID4VPError> {
+        *self
+            .short_circuit_matching
+            .write()
+            .map_err(|_| OID4VPError::LockError("short_circuit_matching".into()))? = enabled;
+        Ok(())
+    }
+
+    /// Set the cache of the status lists fetched by
+    /// [PermissionRequest::credentials_with_status].
+    pub fn set_status_list_cache(&self, cache: Arc<StatusListCache>) -> Result<(), OID4VPError> {
+        *self
+            .status_cache
+            .write()
+            .map_err(|_| OID4VPError::LockError("status_cache".into()))? = Some(cache);
+        Ok(())
+    }
+
+    /// Set how strictly presentation definitions are parsed. In lenient mode,
+    /// the repaired deviations are returned by [PermissionRequest::warnings].
+    pub fn set_request_parsing_mode(&self, mode: RequestParsingMode) -> Result<(), OID4VPError> {
+        *self
+            .parsing_mode
+            .write()
+            .map_err(|_| OID4VPError::LockError("parsing_mode".into()))? = mode;
+        Ok(())
+    }
+
+    /// Set the sink the durations and outcomes of request validation, DID
+    /// resolution, matching and submission are recorded in.
+    pub fn set_metrics_sink(&self, sink: Arc<dyn MetricsSink>) -> Result<(), OID4VPError> {
+        *self
+            .metrics_sink
+            .write()
+            .map_err(|_| OID4VPError::LockError("metrics_sink".into()))? = Some(sink);
+        Ok(())
+    }
+
+    /// Set the log every submitted permission response is recorded in.
+    pub fn set_presentation_log(&self, log: Arc<PresentationLog>) -> Result<(), OID4VPError> {
+        *self
+            .presentation_log
+            .write()
+            .map_err(|_| OID4VPError::LockError("presentation_log".into()))? = Some(log);
+        Ok(())
+    }
+
+    /// Register a resolver for a DID method that is not supported out of
+    /// the box, or to override a built-in one.
+    pub fn register_did_method_resolver(&self, resolver: Arc<dyn DidMethodResolver>) {
+        self.did_resolver.register(resolver);
+    }
+
+    /// Return the cache of resolved DID documents, to configure its TTL and
+    /// offline mode.
+    pub fn did_document_cache(&self) -> Arc<DidDocumentCache> {
+        self.did_cache.clone()
     }
 
     pub async fn submit_permission_response(
         &self,
         response: Arc<PermissionResponse>,
     ) -> Result<Option<Url>, OID4VPError> {
-        self.submit_response(
-            response.authorization_request.clone(),
-            response.authorization_response()?,
-        )
-        .await
-        .map_err(|e| OID4VPError::ResponseSubmission(format!("{e:?}")))
+        self.response_uri_policy
+            .read()
+            .map_err(|_| OID4VPError::LockError("response_uri_policy".into()))?
+            .check_request(&response.authorization_request)?;
+
+        let (response, authorization_response) = self.prepare_response(response).await?;
+        let encrypted_response = match response.authorization_request.response_mode() {
+            ResponseMode::DirectPostJwt => Some(response_encryption::encrypt_response(
+                &response.authorization_request,
+                &authorization_response,
+                Some(&response.mdoc_generated_nonce),
+            )?),
+            _ => None,
+        };
+        let result = self
+            .cancellable(async {
+                let request = &response.authorization_request;
+                let submission =
+                    metrics::measure(self.metrics_sink(), metrics::SUBMISSION, async {
+                        if let Some(jwe) = &encrypted_response {
+                            return response_encryption::submit_encrypted_response(
+                                &self.client,
+                                request,
+                                jwe,
+                            )
+                            .await;
+                        }
+                        match draft::detect(request) {
+                            OID4VPDraft::Draft18 => {
+                                draft::submit_draft_18_response(
+                                    &self.client,
+                                    request,
+                                    authorization_response,
+                                )
+                                .await
+                            }
+                            OID4VPDraft::Draft20 | OID4VPDraft::Draft22 => {
+                                self.submit_response(request.clone(), authorization_response)
+                                    .await
+                            }
+                        }
+                    });
+                let (redirect_uri, last_response) =
+                    response_errors::track_submission(submission).await?;
+                Ok((
+                    redirect_uri,
+                    last_response.as_ref().and_then(status_watch::status_uri),
+                ))
+            })
+            .await;
+
+        self.complete_response(&response, &result).await?;
+
+        match &result {
+            Ok((redirect_uri, status_uri)) => {
+                self.emit(FlowEvent::ResponseSubmitted);
+                if let Some(redirect_uri) = redirect_uri {
+                    self.emit(FlowEvent::RedirectReceived {
+                        redirect_uri: redirect_uri.clone(),
+                    });
+                }
+                if let Some(status_uri) = status_uri {
+                    self.emit(FlowEvent::StatusUriReceived {
+                        status_uri: status_uri.clone(),
+                    });
+                }
+            }
+            // Verifiers rejecting the response may still redirect the user.
+            Err(OID4VPError::VerifierRejected {
+                redirect_uri: Some(redirect_uri),
+                ..
+            }) => self.emit(FlowEvent::RedirectReceived {
+                redirect_uri: redirect_uri.clone(),
+            }),
+            Err(_) => {}
+        }
+
+        result.map(|(redirect_uri, _)| redirect_uri)
     }
 }
 
 // Internal methods for the Holder.
 impl Holder {
+    /// Check a response before it is returned to the verifier, and return it
+    /// with the key attestation provider of the holder, along with its
+    /// authorization response.
+    pub(crate) async fn prepare_response(
+        &self,
+        response: Arc<PermissionResponse>,
+    ) -> Result<(Arc<PermissionResponse>, AuthorizationResponse), OID4VPError> {
+        // Responses restored after the request expired are not submitted.
+        persistence::check_expiry(
+            &response.authorization_request,
+            self.now()?,
+            self.clock_leeway()?,
+        )?;
+        self.check_schemas(&response).await?;
+
+        let signer = self
+            .device_signer
+            .read()
+            .map_err(|_| OID4VPError::LockError("device_signer".into()))?
+            .clone();
+        let key_attestation_provider = self
+            .key_attestation_provider
+            .read()
+            .map_err(|_| OID4VPError::LockError("key_attestation_provider".into()))?
+            .clone();
+        let response = match key_attestation_provider {
+            Some(provider) if response.key_attestation_provider.is_none() => {
+                response.with_key_attestation_provider(provider)
+            }
+            _ => response,
+        };
+
+        let authorization_response = response.authorization_response(signer).await?;
+        Ok((response, authorization_response))
+    }
+
+    /// Record the outcome of returning a response to the verifier, and
+    /// consume the credentials it presented if it was returned.
+    pub(crate) async fn complete_response<T>(
+        &self,
+        response: &PermissionResponse,
+        result: &Result<T, OID4VPError>,
+    ) -> Result<(), OID4VPError> {
+        self.record_presentation(response, result)?;
+        if result.is_ok() {
+            self.consume_pool_instances(response).await;
+            self.record_credential_usage(response).await;
+            self.pin_verifier_key(response);
+        }
+        Ok(())
+    }
+
+    /// Return the metadata sent to verifiers, declaring only the formats of
+    /// the credentials of the holder when configured to.
+    ///
+    /// NOTE: requests are validated against the full metadata, see
+    /// [OID4VPWallet::metadata], as the derived formats are a subset of it.
+    pub(crate) async fn wallet_metadata(&self) -> Result<WalletMetadata, OID4VPError> {
+        if !self.derive_formats {
+            return Ok(self.metadata.clone());
+        }
+
+        let mut formats = self
+            .provided_credentials
+            .iter()
+            .flatten()
+            .map(|credential| credential.format())
+            .collect::<Vec<_>>();
+        if let Some(vdc_collection) = &self.vdc_collection {
+            formats.extend(vdc_collection.clone().formats_async().await?);
+        }
+        wallet_metadata::with_credential_formats(&self.metadata, &formats)
+    }
+
+    /// Return the current time, from the clock of the holder.
+    pub(crate) fn now(&self) -> Result<SystemTime, OID4VPError> {
+        Ok(self
+            .clock
+            .read()
+            .map_err(|_| OID4VPError::LockError("clock".into()))?
+            .now())
+    }
+
+    /// Return the clock of the holder.
+    pub(crate) fn clock(&self) -> Result<Arc<dyn Clock>, OID4VPError> {
+        Ok(self
+            .clock
+            .read()
+            .map_err(|_| OID4VPError::LockError("clock".into()))?
+            .clone())
+    }
+
+    /// Return the leeway allowed when comparing times to the clock.
+    pub(crate) fn clock_leeway(&self) -> Result<Duration, OID4VPError> {
+        Ok(*self
+            .clock_leeway
+            .read()
+            .map_err(|_| OID4VPError::LockError("clock_leeway".into()))?)
+    }
+
+    /// Return the metrics sink, if one is set.
+    pub(crate) fn metrics_sink(&self) -> Option<Arc<dyn MetricsSink>> {
+        self.metrics_sink
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Record the outcome of submitting a permission response in the
+    /// presentation log, if one is set.
+    fn record_presentation<T>(
+        &self,
+        response: &PermissionResponse,
+        result: &Result<T, OID4VPError>,
+    ) -> Result<(), OID4VPError> {
+        let presentation_log = self
+            .presentation_log
+            .read()
+            .map_err(|_| OID4VPError::LockError("presentation_log".into()))?
+            .clone();
+        let Some(presentation_log) = presentation_log else {
+            return Ok(());
+        };
+
+        let (outcome, error) = match result {
+            Ok(_) => (PresentationOutcome::Submitted, None),
+            Err(e) => (PresentationOutcome::Failed, Some(e.to_string())),
+        };
+        let record = PresentationRecord::new(response, self.now()?, outcome, error);
+
+        // Failing to record the presentation does not fail the submission.
+        if let Err(e) = presentation_log.add(record) {
+            log::warn!("Failed to record the presentation: {e:?}");
+        }
+
+        Ok(())
+    }
+
+    /// Mark the presented instances of pools of single-use credentials as
+    /// consumed, for the next presentation to use fresh instances.
+    async fn consume_pool_instances(&self, response: &PermissionResponse) {
+        let Some(vdc_collection) = &self.vdc_collection else {
+            return;
+        };
+        for credential in &response.selected_credentials {
+            // Failing to consume an instance does not fail the submission.
+            if let Err(e) = vdc_collection
+                .clone()
+                .consume_pool_instance_async(credential.id())
+                .await
+            {
+                log::warn!("Failed to consume the pooled credential: {e:?}");
+            }
+        }
+    }
+
+    /// Fail when a selected credential of a response violates its schemas.
+    async fn check_schemas(&self, response: &PermissionResponse) -> Result<(), OID4VPError> {
+        let Some(cache) = self
+            .schema_cache
+            .read()
+            .map_err(|_| OID4VPError::LockError("schema_cache".into()))?
+            .clone()
+        else {
+            return Ok(());
+        };
+
+        for credential in &response.selected_credentials {
+            for validation in credential.validate_schemas(cache.clone()).await {
+                if let VerificationCheck::Failed { reason } = validation.check {
+                    return Err(OID4VPError::SchemaViolation {
+                        schema_uri: validation.schema_uri,
+                        reason,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the presentation of the stored credentials of a submitted
+    /// response in their usage statistics.
+    async fn record_credential_usage(&self, response: &PermissionResponse) {
+        let Some(vdc_collection) = &self.vdc_collection else {
+            return;
+        };
+        let used_at = match self.now() {
+            Ok(used_at) => used_at,
+            Err(e) => {
+                log::warn!("Failed to record the usage of the credentials: {e:?}");
+                return;
+            }
+        };
+        for credential in &response.selected_credentials {
+            // Failing to record the usage does not fail the submission, and
+            // credentials provided to the holder are not stored.
+            match vdc_collection
+                .clone()
+                .record_usage_async(credential.id(), used_at)
+                .await
+            {
+                Ok(()) | Err(VdcCollectionError::NotFound(_)) => {}
+                Err(e) => log::warn!("Failed to record the usage of the credential: {e:?}"),
+            }
+        }
+    }
+
     /// Return the static metadata for the holder.
     ///
     /// This method is used to initialize the metadata for the holder.
     pub(crate) fn metadata() -> Result<WalletMetadata, OID4VPError> {
         let mut metadata = WalletMetadata::openid4vp_scheme_static();
 
-        // Insert support for the VCDM2 SD JWT format.
-        metadata.vp_formats_supported_mut().0.insert(
-            ClaimFormatDesignation::Other("vcdm2_sd_jwt".into()),
-            ClaimFormatPayload::AlgValuesSupported(vec!["ES256".into()]),
-        );
+        // Insert support for the VCDM2 SD JWT, IETF SD-JWT VC and CWT formats.
+        for format in ["vcdm2_sd_jwt", "dc+sd-jwt", "cwt"] {
+            metadata.vp_formats_supported_mut().0.insert(
+                ClaimFormatDesignation::Other(format.into()),
+                ClaimFormatPayload::AlgValuesSupported(
+                    SUPPORTED_ALGORITHMS
+                        .iter()
+                        .map(|alg| alg.to_string())
+                        .collect(),
+                ),
+            );
+        }
 
         metadata
             // Insert support for the DID client ID scheme.
             .add_client_id_schemes_supported(ClientIdScheme::Did)
-            .map_err(|e| OID4VPError::MetadataInitialization(format!("{e:?}")))?;
+            .map_err(OID4VPError::metadata_initialization)?;
+        metadata
+            .add_client_id_schemes_supported(ClientIdScheme::X509SanDns)
+            .map_err(OID4VPError::metadata_initialization)?;
 
-        Ok(metadata)
+        with_request_algorithms(metadata)
     }
 
     /// This will return all the credentials that match the presentation definition.
-    async fn search_credentials_vs_presentation_definition(
+    ///
+    /// The provided credentials are matched first, then those of the
+    /// collection, which are scoped to its active profile. Credentials of
+    /// formats the interoperability profile does not allow are skipped, and
+    /// matches are streamed to the delegate of a streaming request as they are
+    /// found.
+    pub async fn search_credentials_vs_presentation_definition(
         &self,
         definition: &PresentationDefinition,
     ) -> Result<Vec<Arc<ParsedCredential>>, OID4VPError> {
-        let credentials = match &self.provided_credentials {
-            // Use a pre-selected list of credentials if provided.
-            Some(credentials) => credentials.to_owned(),
-            None => match &self.vdc_collection {
-                None => vec![],
-                Some(vdc_collection) => vdc_collection
-                    .all_entries()?
+        let mut candidates = self
+            .provided_credentials
+            .iter()
+            .flatten()
+            .cloned()
+            .map(Candidate::Parsed)
+            .collect::<Vec<_>>();
+        if let Some(vdc_collection) = &self.vdc_collection {
+            let ids = vdc_collection
+                .clone()
+                .query_async(CredentialFilter {
+                    formats: requested_formats(definition),
+                    ..Default::default()
+                })
+                .await?;
+            candidates.extend(
+                vdc_collection
+                    .pool_candidates(ids)?
                     .into_iter()
-                    .filter_map(|id| {
-                        vdc_collection
-                            .get(id)
-                            .ok()
-                            .flatten()
-                            .and_then(|cred| cred.try_into_parsed().ok())
-                    })
-                    .collect::<Vec<Arc<ParsedCredential>>>(),
-            },
+                    .map(|id| Candidate::Stored(vdc_collection.clone(), id)),
+            );
         }
-        .into_iter()
-        .filter_map(
-            |cred| match cred.check_presentation_definition(definition) {
-                true => Some(cred),
-                false => None,
-            },
+
+        let short_circuit = *self
+            .short_circuit_matching
+            .read()
+            .map_err(|_| OID4VPError::LockError("short_circuit_matching".into()))?;
+
+        Ok(match_credentials(candidates, definition, self.profile()?, short_circuit).await)
+    }
+
+    /// Validate an authorization request, and return its permission request.
+    ///
+    /// Request objects referenced by a `request_uri` are fetched first, so
+    /// that every request is checked against the request object policy, and
+    /// validated as requests passed by value, in the `request` parameter or
+    /// as plain parameters.
+    async fn resolve_authorization_request(
+        &self,
+        url: Url,
+    ) -> Result<Arc<PermissionRequest>, OID4VPError> {
+        let validation_error = |e: anyhow::Error| match certificate_pinning_host(&e) {
+            Some(host) => OID4VPError::CertificatePinning { host },
+            None => OID4VPError::request_validation(e),
+        };
+
+        let request_uri = request_uri::request_uri(&url).map(|(request_uri, _)| request_uri);
+        if request_uri.is_none() {
+            self.check_request_size(url.as_str())?;
+        }
+        let url =
+            request_uri::dereference_request_uri(url, &self.wallet_metadata().await?, &self.client)
+                .await
+                .map_err(validation_error)?;
+        let request_object = url
+            .query_pairs()
+            .find(|(name, _)| name == "request")
+            .map(|(_, value)| value.into_owned());
+        if let Some(request_object) = &request_object {
+            self.check_request_size(request_object)?;
+        }
+        self.check_request_object(request_object.as_deref())?;
+
+        // Requests of federation entities are verified through their trust
+        // chain rather than by the client ID schemes of the library.
+        let federation_entity = request_object.as_deref().and_then(federation::entity_id);
+        let (request, federation_verifier) = match (federation_entity, &request_object) {
+            (Some(entity_id), Some(request_object)) => {
+                let (request, verifier) = metrics::measure(
+                    self.metrics_sink(),
+                    metrics::REQUEST_VALIDATION,
+                    self.validate_federation_request(&entity_id, request_object),
+                )
+                .await?;
+                (request, Some(verifier))
+            }
+            _ => {
+                let request = metrics::measure(
+                    self.metrics_sink(),
+                    metrics::REQUEST_VALIDATION,
+                    self.validate_request(url),
+                )
+                .await
+                .map_err(validation_error)?;
+                (request, None)
+            }
+        };
+        if let ResponseMode::Unsupported(mode) = request.response_mode() {
+            return Err(OID4VPError::UnsupportedResponseMode {
+                response_mode: mode.to_owned(),
+            });
+        }
+
+        self.accept_request(
+            request,
+            request_object.as_deref(),
+            request_uri.as_deref(),
+            federation_verifier,
         )
-        .collect::<Vec<Arc<ParsedCredential>>>();
+        .await
+    }
+
+    /// Check a validated request, whichever way it was received, against the
+    /// profile, its draft and the replay guard, and return its permission
+    /// request, with the signs of phishing the risk analysis finds.
+    ///
+    /// `request_object` is the signed request object of the request, if any,
+    /// and `request_uri` the URI it was fetched from.
+    pub(crate) async fn accept_request(
+        &self,
+        request: AuthorizationRequestObject,
+        request_object: Option<&str>,
+        request_uri: Option<&str>,
+        federation_verifier: Option<FederationVerifier>,
+    ) -> Result<Arc<PermissionRequest>, OID4VPError> {
+        self.profile()?.check_request(request_object, &request)?;
+        draft::check(&request)?;
 
-        Ok(credentials)
+        let verifier_key = match request_object {
+            Some(request_object) => self.key_fingerprint(request_object).await,
+            None => None,
+        };
+        // Replayed requests are refused before their definition and metadata
+        // are resolved, but only accepted requests are recorded, so that the
+        // verifier can send a request refused for another reason again.
+        self.check_replay(&request)?;
+        let permission_request = self.permission_request(request, verifier_key).await?;
+        self.record_request(&permission_request.request)?;
+
+        let risk_warnings = match self.risk_analysis()? {
+            Some(config) => {
+                risk_analysis::analyze(&config, request_uri, &permission_request.request)
+            }
+            None => vec![],
+        };
+
+        Ok(Arc::new(PermissionRequest {
+            federation_verifier,
+            risk_warnings,
+            ..(*permission_request).clone()
+        }))
+    }
+
+    /// Verify the request object of a federation entity, with the trust
+    /// chain of the entity to a federation trust anchor.
+    async fn validate_federation_request(
+        &self,
+        entity_id: &str,
+        request_object: &str,
+    ) -> Result<(AuthorizationRequestObject, FederationVerifier), OID4VPError> {
+        let trust_anchors = self
+            .federation_trust_anchors
+            .read()
+            .map_err(|_| OID4VPError::LockError("federation_trust_anchors".into()))?
+            .clone();
+
+        let (claims, verifier) = federation::resolve(
+            entity_id,
+            request_object,
+            &trust_anchors,
+            &self.client,
+            self.now()?,
+        )
+        .await?;
+        let request = serde_json::from_value(claims).map_err(OID4VPError::request_validation)?;
+        Ok((request, verifier))
+    }
+
+    /// Refuse a request that was already received, if a replay guard is set.
+    fn check_replay(&self, request: &AuthorizationRequestObject) -> Result<(), OID4VPError> {
+        if let Some(replay_guard) = self.replay_guard()? {
+            replay_guard.check(
+                &request.client_id().0,
+                request::string_parameter(request, "nonce").as_deref(),
+                request::string_parameter(request, "state").as_deref(),
+                self.now()?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Record an accepted request, if a replay guard is set.
+    fn record_request(&self, request: &AuthorizationRequestObject) -> Result<(), OID4VPError> {
+        if let Some(replay_guard) = self.replay_guard()? {
+            replay_guard.record(
+                &request.client_id().0,
+                request::string_parameter(request, "nonce").as_deref(),
+                request::string_parameter(request, "state").as_deref(),
+                self.now()?,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn replay_guard(&self) -> Result<Option<Arc<RequestReplayGuard>>, OID4VPError> {
+        Ok(self
+            .replay_guard
+            .read()
+            .map_err(|_| OID4VPError::LockError("replay_guard".into()))?
+            .clone())
+    }
+
+    /// Check a request object, or its absence, against the request object
+    /// policy.
+    pub(crate) fn check_request_object(
+        &self,
+        request_object: Option<&str>,
+    ) -> Result<(), OID4VPError> {
+        self.request_object_policy
+            .read()
+            .map_err(|_| OID4VPError::LockError("request_object_policy".into()))?
+            .check(request_object)
+    }
+
+    pub(crate) fn check_request_size(&self, request: &str) -> Result<(), OID4VPError> {
+        self.request_object_policy
+            .read()
+            .map_err(|_| OID4VPError::LockError("request_object_policy".into()))?
+            .check_size(request)
+    }
+
+    fn risk_analysis(&self) -> Result<Option<RiskAnalysisConfig>, OID4VPError> {
+        Ok(self
+            .risk_analysis
+            .read()
+            .map_err(|_| OID4VPError::LockError("risk_analysis".into()))?
+            .clone())
+    }
+
+    fn profile(&self) -> Result<Profile, OID4VPError> {
+        Ok(*self
+            .profile
+            .read()
+            .map_err(|_| OID4VPError::LockError("profile".into()))?)
     }
 
     // Internal method for returning the `PermissionRequest` for an oid4vp request.
-    async fn permission_request(
+    //
+    // `verifier_key` is the fingerprint of the key the request object is
+    // signed with, if any, compared with the pinned key of the verifier.
+    pub(crate) async fn permission_request(
         &self,
         request: AuthorizationRequestObject,
+        verifier_key: Option<String>,
     ) -> Result<Arc<PermissionRequest>, OID4VPError> {
+        validity::check_validity(&request, self.now()?, self.clock_leeway()?)?;
+        let mut verifier = VerifierInfo::from(&request);
+        verifier.key_change = self.key_change(&verifier, verifier_key.as_deref())?;
+        self.emit(FlowEvent::RequestFetched {
+            verifier: verifier.clone(),
+        });
+        let mut denied_fields = self.review_verifier(verifier.clone()).await?;
+        let (stripped, never_disclosed) = minimization::applicable(
+            &self
+                .minimization_policies
+                .read()
+                .map_err(|_| OID4VPError::LockError("minimization_policies".into()))?,
+            &verifier,
+        );
+        for field in stripped {
+            if !denied_fields.contains(&field) {
+                denied_fields.push(field);
+            }
+        }
+        self.emit(FlowEvent::VerifierVerified { verifier });
+
+        let transaction_data = transaction_data::from_request(&request)?;
+
         // Resolve the presentation definition.
-        let presentation_definition = request
-            .resolve_presentation_definition(self.http_client())
-            .await
-            .map_err(|e| OID4VPError::PresentationDefinitionResolution(format!("{e:?}")))?
-            .into_parsed();
+        let parsing_mode = *self
+            .parsing_mode
+            .read()
+            .map_err(|_| OID4VPError::LockError("parsing_mode".into()))?;
+        let scope_queries = self
+            .scope_queries
+            .read()
+            .map_err(|_| OID4VPError::LockError("scope_queries".into()))?
+            .clone();
+        let (definition, definition_source) =
+            parsing_mode::raw_presentation_definition(&request, &self.client, &scope_queries)
+                .await
+                .map_err(OID4VPError::definition_resolution)?;
+        self.definition_limits
+            .read()
+            .map_err(|_| OID4VPError::LockError("definition_limits".into()))?
+            .check(&definition)?;
+        let (presentation_definition, warnings) =
+            parsing_mode::parse_presentation_definition(definition, parsing_mode)?;
+        for warning in &warnings {
+            log::warn!("Repaired the presentation definition: {warning}");
+        }
+        self.profile()?
+            .check_formats(&requested_formats(&presentation_definition))?;
 
-        let credentials = self
-            .search_credentials_vs_presentation_definition(&presentation_definition)
-            .await?;
+        self.emit(FlowEvent::MatchingStarted);
+        let credentials = metrics::measure(
+            self.metrics_sink(),
+            metrics::MATCHING,
+            self.search_credentials_vs_presentation_definition(&presentation_definition),
+        )
+        .await?;
 
-        Ok(PermissionRequest::new(
-            presentation_definition.clone(),
-            credentials.clone(),
+        Ok(Arc::new(PermissionRequest {
+            definition: presentation_definition,
+            credentials,
             request,
-        ))
+            served_from_cache: false,
+            transaction_data,
+            denied_fields,
+            never_disclosed,
+            claim_labels: self
+                .claim_labels
+                .read()
+                .map_err(|_| OID4VPError::LockError("claim_labels".into()))?
+                .clone(),
+            warnings,
+            definition_source: Some(definition_source),
+            vdc_collection: self.vdc_collection.clone(),
+            federation_verifier: None,
+            risk_warnings: vec![],
+            verifier_key,
+            status_cache: self
+                .status_cache
+                .read()
+                .map_err(|_| OID4VPError::LockError("status_cache".into()))?
+                .clone(),
+            status_lists: StatusListFetcher {
+                client: Some(self.client.uncached().clone()),
+                verification: CredentialVerificationOptions {
+                    trust_list: self
+                        .trust_list
+                        .read()
+                        .map_err(|_| OID4VPError::LockError("trust_list".into()))?
+                        .clone(),
+                    clock: Some(self.clock()?),
+                    clock_leeway: self.clock_leeway()?,
+                    ..Default::default()
+                },
+            },
+            descriptor_cache: Default::default(),
+            clock: self.clock()?,
+            clock_leeway: self.clock_leeway()?,
+            dc_api_origin: None,
+        }))
+    }
+}
+
+/// Return the credential formats a presentation definition can be satisfied
+/// with, or an empty list if it accepts any format, or formats that are not
+/// known.
+fn requested_formats(definition: &PresentationDefinition) -> Vec<CredentialFormat> {
+    let Ok(definition) = serde_json::to_value(definition) else {
+        return vec![];
+    };
+
+    let format_keys = |value: &serde_json::Value| {
+        value
+            .get("format")
+            .and_then(|format| format.as_object())
+            .map(|format| format.keys().cloned().collect::<Vec<_>>())
+    };
+
+    // Input descriptors without formats accept the top-level formats, or any
+    // format when there are none.
+    let top_level = format_keys(&definition);
+    let accepts_any = top_level.is_none();
+    let mut designations = top_level.unwrap_or_default();
+    for descriptor in definition["input_descriptors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        match format_keys(descriptor) {
+            Some(formats) => designations.extend(formats),
+            None if accepts_any => return vec![],
+            None => {}
+        }
+    }
+
+    let mut formats = Vec::new();
+    for designation in designations {
+        let Some(format) = designated_format(&designation) else {
+            return vec![];
+        };
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+
+    formats
+}
+
+/// Return the credential format of a claim format designation, if known.
+pub(crate) fn designated_format(designation: &str) -> Option<CredentialFormat> {
+    Some(match designation {
+        "mso_mdoc" => CredentialFormat::MsoMdoc,
+        "jwt_vc_json" | "jwt_vp_json" | "jwt_vc" | "jwt_vp" => CredentialFormat::JwtVcJson,
+        "jwt_vc_json-ld" | "jwt_vp_json-ld" => CredentialFormat::JwtVcJsonLd,
+        "ldp_vc" | "ldp_vp" | "ldp" => CredentialFormat::LdpVc,
+        "vcdm2_sd_jwt" => CredentialFormat::VCDM2SdJwt,
+        "dc+sd-jwt" | "vc+sd-jwt" => CredentialFormat::DcSdJwt,
+        "cwt" => CredentialFormat::Cwt,
+        _ => return None,
+    })
+}
+
+impl Holder {
+    /// Return the resolver of the verification methods of DIDs, through the
+    /// DID resolvers and the DID document cache of the holder.
+    pub(crate) fn verification_method_resolver(
+        &self,
+    ) -> VerificationMethodDIDResolver<CachingDidResolver<DidResolverRegistry>, AnyJwkMethod> {
+        VerificationMethodDIDResolver::new(CachingDidResolver {
+            resolver: self.did_resolver.clone(),
+            cache: self.did_cache.clone(),
+            metrics: self.metrics_sink(),
+        })
     }
 }
 
@@ -219,33 +1323,74 @@ impl RequestVerifier for Holder {
     ) -> anyhow::Result<()> {
         log::debug!("Verifying DID request.");
 
-        let resolver: VerificationMethodDIDResolver<DIDWeb, AnyJwkMethod> =
-            VerificationMethodDIDResolver::new(DIDWeb);
+        let resolver = self.verification_method_resolver();
 
         // NOTE: This is temporary solution that will allow any DID to be
         // trusted. This will be replaced by the trust manager in the future.
-        let client_id = decoded_request.client_id();
+        let client_id = draft::client_identifier(decoded_request);
 
         verify_with_resolver(
             &self.metadata,
             decoded_request,
             request_jwt,
-            Some(&[client_id.0.clone()]),
+            Some(&[client_id.to_owned()]),
             &resolver,
         )
         .await?;
 
         Ok(())
     }
+
+    /// Performs verification on Authorization Request Objects when `client_id_scheme` is `x509_san_dns`.
+    async fn x509_san_dns(
+        &self,
+        decoded_request: &AuthorizationRequestObject,
+        request_jwt: String,
+    ) -> anyhow::Result<()> {
+        log::debug!("Verifying x509_san_dns request.");
+
+        let trust_list = self
+            .trust_list
+            .read()
+            .map_err(|_| anyhow::anyhow!("failed to read the trust list"))?
+            .clone();
+        let store = self
+            .trust_anchors
+            .read()
+            .map_err(|_| anyhow::anyhow!("failed to read the trust anchors"))?
+            .clone();
+
+        let mut trust_anchors = match trust_list {
+            Some(trust_list) => trust_list.certificates()?,
+            None => vec![],
+        };
+        let mut check_revocation = false;
+        if let Some(store) = store {
+            trust_anchors.extend(store.anchors(TrustAnchorPurpose::Reader)?);
+            check_revocation = store.checks_revocation();
+        }
+
+        x509_client_id::verify(
+            decoded_request,
+            &request_jwt,
+            &trust_anchors,
+            check_revocation,
+            self.now()?,
+        )
+        .await
+    }
 }
 
 impl OID4VPWallet for Holder {
-    type HttpClient = openid4vp::core::util::ReqwestClient;
+    type HttpClient = CachingHttpClient;
 
     fn http_client(&self) -> &Self::HttpClient {
         &self.client
     }
 
+    /// The metadata requests are validated against, with every supported
+    /// format, unlike the metadata sent to verifiers, which may be derived
+    /// from the credentials of the holder.
     fn metadata(&self) -> &WalletMetadata {
         &self.metadata
     }
@@ -254,6 +1399,8 @@ impl OID4VPWallet for Holder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::credential::json_vc::JsonVc;
+    use openid4vp::core::response::parameters::VpTokenItem;
     use vcdm2_sd_jwt::VCDM2SdJwt;
 
     // NOTE: This test requires the `companion` service to be running and
@@ -306,15 +1453,92 @@ mod tests {
         }
 
         // NOTE: passing `parsed_credentials` as `selected_credentials`.
-        let response = permission_request.create_permission_response(parsed_credentials);
+        let response = permission_request.create_permission_response(parsed_credentials)?;
 
         holder.submit_permission_response(response).await?;
 
         Ok(())
     }
 
+    #[test]
+    fn reads_requested_formats() {
+        let definition = |value: serde_json::Value| -> PresentationDefinition {
+            serde_json::from_value(value).unwrap()
+        };
+
+        let mdl = definition(serde_json::json!({
+            "id": "mdl",
+            "input_descriptors": [{
+                "id": "org.iso.18013.5.1.mDL",
+                "format": { "mso_mdoc": { "alg": ["ES256"] } },
+                "constraints": {}
+            }]
+        }));
+        assert_eq!(requested_formats(&mdl), vec![CredentialFormat::MsoMdoc]);
+
+        let any = definition(serde_json::json!({
+            "id": "any",
+            "input_descriptors": [{ "id": "any", "constraints": {} }]
+        }));
+        assert_eq!(requested_formats(&any), vec![]);
+    }
+
     #[tokio::test]
     async fn test_vehicle_title() -> Result<(), Box<dyn std::error::Error>> {
+        let example_vehicle_title = include_str!("../../tests/examples/vehicle_title.json");
+        let json_vc = JsonVc::new_from_json(example_vehicle_title.into())?;
+        let credential = ParsedCredential::new_ldp_vc(json_vc);
+
+        let title = credential
+            .as_vehicle_title()
+            .expect("the credential is a vehicle title");
+        assert_eq!(
+            title.vehicle_identification_number().as_deref(),
+            Some("4Y1SL65848Z411439")
+        );
+        assert_eq!(title.owners().len(), 2);
+        assert_eq!(
+            title.owners()[0].license_number.as_deref(),
+            Some("542426814")
+        );
+
+        let definition: PresentationDefinition = serde_json::from_value(serde_json::json!({
+            "id": "vehicle-title",
+            "input_descriptors": [{
+                "id": "vehicle-title",
+                "format": { "ldp_vc": { "proof_type": ["DataIntegrityProof"] } },
+                "constraints": {
+                    "fields": [
+                        {
+                            "path": ["$.type"],
+                            "filter": {
+                                "type": "array",
+                                "contains": { "const": "VehicleTitleCredential" }
+                            }
+                        },
+                        {
+                            "path": ["$.credentialSubject.vehicle.vehicleIdentificationNumber"],
+                            "name": "VIN"
+                        }
+                    ]
+                }
+            }]
+        }))?;
+
+        assert!(credential.check_presentation_definition(&definition));
+        let requested_fields = credential.requested_fields(&definition);
+        assert!(requested_fields
+            .iter()
+            .any(|field| field.name().as_deref() == Some("VIN")));
+
+        let VpTokenItem::JsonObject(presentation) = credential.as_vp_token()? else {
+            panic!("vehicle titles are presented as ldp_vp");
+        };
+        assert_eq!(
+            presentation["verifiableCredential"][0]["type"][1],
+            "VehicleTitleCredential"
+        );
+
         Ok(())
     }
 }
@@ -0,0 +1,392 @@
+use super::artifact_cache::{CachingHttpClient, VerifierArtifactCache};
+use super::claim_labels::ClaimLabelConfig;
+use super::definition_limits::PresentationDefinitionLimits;
+use super::error::OID4VPError;
+use super::federation::FederationTrustAnchor;
+use super::flow_events::FlowDelegate;
+use super::holder::Holder;
+use super::key_attestation::KeyAttestationProvider;
+use super::minimization::MinimizationPolicy;
+use super::parsing_mode::RequestParsingMode;
+use super::profile::Profile;
+use super::replay::RequestReplayGuard;
+use super::request_policy::RequestObjectPolicy;
+use super::response_policy::ResponseUriPolicy;
+use super::risk_analysis::RiskAnalysisConfig;
+use super::scope::{self, QueryTemplate};
+use super::sessions;
+use super::trusted_verifiers::TrustedVerifierStore;
+use super::verifier_review::VerifierReviewDelegate;
+use super::wallet_metadata::WalletMetadataConfig;
+use crate::clock::{self, Clock};
+use crate::credential::schema::CredentialSchemaCache;
+use crate::credential::ParsedCredential;
+use crate::did::{DidDocumentCache, DidMethodResolver, DidResolverRegistry};
+use crate::metrics::MetricsSink;
+use crate::oid4vci::{HttpClientConfig, ReqwestHttpClient};
+use crate::presentation_log::PresentationLog;
+use crate::signer::DeviceSigner;
+use crate::status::StatusListCache;
+use crate::trust_anchors::TrustAnchorStore;
+use crate::trust_list::TrustListManager;
+use crate::vdc_collection::VdcCollection;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+#[derive(Debug, Default)]
+struct HolderConfig {
+    vdc_collection: Option<Arc<VdcCollection>>,
+    provided_credentials: Option<Vec<Arc<ParsedCredential>>>,
+    trusted_dids: Vec<String>,
+    trusted_verifiers: Option<Arc<TrustedVerifierStore>>,
+    trust_list: Option<Arc<TrustListManager>>,
+    trust_anchors: Option<Arc<TrustAnchorStore>>,
+    http_client_config: Option<HttpClientConfig>,
+    metadata_config: Option<WalletMetadataConfig>,
+    did_method_resolvers: Vec<Arc<dyn DidMethodResolver>>,
+    device_signer: Option<Arc<dyn DeviceSigner>>,
+    key_attestation_provider: Option<Arc<dyn KeyAttestationProvider>>,
+    verifier_review_delegate: Option<Arc<dyn VerifierReviewDelegate>>,
+    flow_delegate: Option<Arc<dyn FlowDelegate>>,
+    presentation_log: Option<Arc<PresentationLog>>,
+    request_object_policy: RequestObjectPolicy,
+    definition_limits: PresentationDefinitionLimits,
+    response_uri_policy: ResponseUriPolicy,
+    schema_cache: Option<Arc<CredentialSchemaCache>>,
+    replay_guard: Option<Arc<RequestReplayGuard>>,
+    short_circuit_matching: bool,
+    status_cache: Option<Arc<StatusListCache>>,
+    parsing_mode: RequestParsingMode,
+    metrics_sink: Option<Arc<dyn MetricsSink>>,
+    profile: Profile,
+    federation_trust_anchors: Vec<FederationTrustAnchor>,
+    risk_analysis: Option<RiskAnalysisConfig>,
+    clock: Option<Arc<dyn Clock>>,
+    clock_leeway: Duration,
+    scope_queries: Option<HashMap<String, QueryTemplate>>,
+    minimization_policies: Vec<MinimizationPolicy>,
+    claim_labels: ClaimLabelConfig,
+    session_ttl: Option<Duration>,
+}
+
+/// A builder of [Holder]s, combining any of the credential sources,
+/// configurations and delegates of the holder.
+///
+/// Credentials are matched from both the VDC collection and the provided
+/// credentials when both are set.
+#[derive(Debug, Default, uniffi::Object)]
+pub struct HolderBuilder {
+    config: Mutex<HolderConfig>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl HolderBuilder {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Match credentials from the VDC collection.
+    pub fn vdc_collection(self: Arc<Self>, vdc_collection: Arc<VdcCollection>) -> Arc<Self> {
+        self.config().vdc_collection = Some(vdc_collection);
+        self
+    }
+
+    /// Match the provided credentials.
+    pub fn credentials(self: Arc<Self>, credentials: Vec<Arc<ParsedCredential>>) -> Arc<Self> {
+        self.config().provided_credentials = Some(credentials);
+        self
+    }
+
+    /// Trust the verifiers with these DIDs, without asking the verifier review
+    /// delegate.
+    pub fn trusted_dids(self: Arc<Self>, trusted_dids: Vec<String>) -> Arc<Self> {
+        self.config().trusted_dids = trusted_dids;
+        self
+    }
+
+    /// As [Holder::set_trusted_verifier_store].
+    pub fn trusted_verifier_store(self: Arc<Self>, store: Arc<TrustedVerifierStore>) -> Arc<Self> {
+        self.config().trusted_verifiers = Some(store);
+        self
+    }
+
+    /// As [Holder::set_trust_list].
+    pub fn trust_list(self: Arc<Self>, trust_list: Arc<TrustListManager>) -> Arc<Self> {
+        self.config().trust_list = Some(trust_list);
+        self
+    }
+
+    /// As [Holder::set_trust_anchor_store].
+    pub fn trust_anchor_store(self: Arc<Self>, store: Arc<TrustAnchorStore>) -> Arc<Self> {
+        self.config().trust_anchors = Some(store);
+        self
+    }
+
+    pub fn http_client_config(self: Arc<Self>, config: HttpClientConfig) -> Arc<Self> {
+        self.config().http_client_config = Some(config);
+        self
+    }
+
+    /// Override the capabilities declared in the wallet metadata.
+    pub fn metadata_config(self: Arc<Self>, config: WalletMetadataConfig) -> Arc<Self> {
+        self.config().metadata_config = Some(config);
+        self
+    }
+
+    /// Register a resolver for a DID method, as with
+    /// [Holder::register_did_method_resolver].
+    pub fn did_method_resolver(self: Arc<Self>, resolver: Arc<dyn DidMethodResolver>) -> Arc<Self> {
+        self.config().did_method_resolvers.push(resolver);
+        self
+    }
+
+    pub fn device_signer(self: Arc<Self>, signer: Arc<dyn DeviceSigner>) -> Arc<Self> {
+        self.config().device_signer = Some(signer);
+        self
+    }
+
+    /// As [Holder::set_key_attestation_provider].
+    pub fn key_attestation_provider(
+        self: Arc<Self>,
+        provider: Arc<dyn KeyAttestationProvider>,
+    ) -> Arc<Self> {
+        self.config().key_attestation_provider = Some(provider);
+        self
+    }
+
+    pub fn verifier_review_delegate(
+        self: Arc<Self>,
+        delegate: Arc<dyn VerifierReviewDelegate>,
+    ) -> Arc<Self> {
+        self.config().verifier_review_delegate = Some(delegate);
+        self
+    }
+
+    pub fn flow_delegate(self: Arc<Self>, delegate: Arc<dyn FlowDelegate>) -> Arc<Self> {
+        self.config().flow_delegate = Some(delegate);
+        self
+    }
+
+    pub fn presentation_log(self: Arc<Self>, log: Arc<PresentationLog>) -> Arc<Self> {
+        self.config().presentation_log = Some(log);
+        self
+    }
+
+    pub fn request_object_policy(self: Arc<Self>, policy: RequestObjectPolicy) -> Arc<Self> {
+        self.config().request_object_policy = policy;
+        self
+    }
+
+    /// As [Holder::set_definition_limits].
+    pub fn definition_limits(self: Arc<Self>, limits: PresentationDefinitionLimits) -> Arc<Self> {
+        self.config().definition_limits = limits;
+        self
+    }
+
+    /// As [Holder::set_response_uri_policy].
+    pub fn response_uri_policy(self: Arc<Self>, policy: ResponseUriPolicy) -> Arc<Self> {
+        self.config().response_uri_policy = policy;
+        self
+    }
+
+    /// As [Holder::set_schema_cache].
+    pub fn schema_cache(self: Arc<Self>, cache: Arc<CredentialSchemaCache>) -> Arc<Self> {
+        self.config().schema_cache = Some(cache);
+        self
+    }
+
+    pub fn request_replay_guard(self: Arc<Self>, guard: Arc<RequestReplayGuard>) -> Arc<Self> {
+        self.config().replay_guard = Some(guard);
+        self
+    }
+
+    /// As [Holder::set_short_circuit_matching].
+    pub fn short_circuit_matching(self: Arc<Self>, enabled: bool) -> Arc<Self> {
+        self.config().short_circuit_matching = enabled;
+        self
+    }
+
+    /// As [Holder::set_status_list_cache].
+    pub fn status_list_cache(self: Arc<Self>, cache: Arc<StatusListCache>) -> Arc<Self> {
+        self.config().status_cache = Some(cache);
+        self
+    }
+
+    /// As [Holder::set_request_parsing_mode].
+    pub fn request_parsing_mode(self: Arc<Self>, mode: RequestParsingMode) -> Arc<Self> {
+        self.config().parsing_mode = mode;
+        self
+    }
+
+    /// As [Holder::set_metrics_sink].
+    pub fn metrics_sink(self: Arc<Self>, sink: Arc<dyn MetricsSink>) -> Arc<Self> {
+        self.config().metrics_sink = Some(sink);
+        self
+    }
+
+    /// As [Holder::set_profile].
+    pub fn profile(self: Arc<Self>, profile: Profile) -> Arc<Self> {
+        self.config().profile = profile;
+        self
+    }
+
+    /// As [Holder::set_federation_trust_anchors].
+    pub fn federation_trust_anchors(
+        self: Arc<Self>,
+        trust_anchors: Vec<FederationTrustAnchor>,
+    ) -> Arc<Self> {
+        self.config().federation_trust_anchors = trust_anchors;
+        self
+    }
+
+    /// As [Holder::set_risk_analysis].
+    pub fn risk_analysis(self: Arc<Self>, config: RiskAnalysisConfig) -> Arc<Self> {
+        self.config().risk_analysis = Some(config);
+        self
+    }
+
+    /// As [Holder::set_clock].
+    pub fn clock(self: Arc<Self>, clock: Arc<dyn Clock>) -> Arc<Self> {
+        self.config().clock = Some(clock);
+        self
+    }
+
+    /// As [Holder::set_clock_leeway].
+    pub fn clock_leeway(self: Arc<Self>, leeway: Duration) -> Arc<Self> {
+        self.config().clock_leeway = leeway;
+        self
+    }
+
+    /// As [Holder::set_scope_queries].
+    pub fn scope_queries(self: Arc<Self>, queries: HashMap<String, QueryTemplate>) -> Arc<Self> {
+        self.config().scope_queries = Some(queries);
+        self
+    }
+
+    /// As [Holder::set_minimization_policies].
+    pub fn minimization_policies(self: Arc<Self>, policies: Vec<MinimizationPolicy>) -> Arc<Self> {
+        self.config().minimization_policies = policies;
+        self
+    }
+
+    /// As [Holder::set_claim_labels].
+    pub fn claim_labels(self: Arc<Self>, config: ClaimLabelConfig) -> Arc<Self> {
+        self.config().claim_labels = config;
+        self
+    }
+
+    /// As [Holder::set_session_ttl].
+    pub fn session_ttl(self: Arc<Self>, ttl: Duration) -> Arc<Self> {
+        self.config().session_ttl = Some(ttl);
+        self
+    }
+
+    /// Build the holder.
+    ///
+    /// Outbound requests use the HTTP client configuration, or the defaults
+    /// when it is unset. The wallet metadata declares the capabilities of the
+    /// metadata configuration, or the default ones when it is unset.
+    pub async fn build(&self) -> Result<Arc<Holder>, OID4VPError> {
+        let config = std::mem::take(&mut *self.config());
+
+        let client = ReqwestHttpClient::new(&config.http_client_config.unwrap_or_default())
+            .map_err(OID4VPError::http_client_initialization)?;
+        let artifact_cache = Arc::new(VerifierArtifactCache::default());
+        let did_resolver = DidResolverRegistry::default();
+        for resolver in config.did_method_resolvers {
+            did_resolver.register(resolver);
+        }
+
+        Ok(Arc::new(Holder {
+            client: CachingHttpClient::new(client, artifact_cache.clone()),
+            artifact_cache,
+            vdc_collection: config.vdc_collection,
+            derive_formats: config
+                .metadata_config
+                .as_ref()
+                .is_some_and(|config| config.derive_formats),
+            metadata: match config.metadata_config {
+                Some(config) => config.wallet_metadata()?,
+                None => Holder::metadata()?,
+            },
+            trusted_dids: RwLock::new(config.trusted_dids),
+            trusted_verifiers: RwLock::new(config.trusted_verifiers),
+            trust_list: RwLock::new(config.trust_list),
+            trust_anchors: RwLock::new(config.trust_anchors),
+            provided_credentials: config.provided_credentials,
+            device_signer: RwLock::new(config.device_signer),
+            key_attestation_provider: RwLock::new(config.key_attestation_provider),
+            verifier_review_delegate: RwLock::new(config.verifier_review_delegate),
+            did_resolver,
+            did_cache: Arc::new(DidDocumentCache::default()),
+            presentation_log: RwLock::new(config.presentation_log),
+            cancellation: watch::channel(0).0,
+            flow_delegate: RwLock::new(config.flow_delegate),
+            request_object_policy: RwLock::new(config.request_object_policy),
+            definition_limits: RwLock::new(config.definition_limits),
+            response_uri_policy: RwLock::new(config.response_uri_policy),
+            schema_cache: RwLock::new(config.schema_cache),
+            replay_guard: RwLock::new(config.replay_guard),
+            short_circuit_matching: RwLock::new(config.short_circuit_matching),
+            status_cache: RwLock::new(config.status_cache),
+            parsing_mode: RwLock::new(config.parsing_mode),
+            metrics_sink: RwLock::new(config.metrics_sink),
+            profile: RwLock::new(config.profile),
+            federation_trust_anchors: RwLock::new(config.federation_trust_anchors),
+            risk_analysis: RwLock::new(config.risk_analysis),
+            clock: RwLock::new(config.clock.unwrap_or_else(clock::system)),
+            clock_leeway: RwLock::new(config.clock_leeway),
+            scope_queries: RwLock::new(
+                config
+                    .scope_queries
+                    .unwrap_or_else(scope::default_scope_queries),
+            ),
+            minimization_policies: RwLock::new(config.minimization_policies),
+            claim_labels: RwLock::new(config.claim_labels),
+            sessions: Default::default(),
+            session_ttl: RwLock::new(config.session_ttl.unwrap_or(sessions::DEFAULT_SESSION_TTL)),
+        }))
+    }
+}
+
+impl HolderBuilder {
+    fn config(&self) -> std::sync::MutexGuard<'_, HolderConfig> {
+        self.config
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+
+    #[tokio::test]
+    async fn builds_holders() {
+        let holder = HolderBuilder::new()
+            .vdc_collection(Arc::new(VdcCollection::new(Arc::new(LocalStore::new()))))
+            .credentials(vec![])
+            .trusted_dids(vec!["did:web:verifier.example.com".into()])
+            .request_object_policy(RequestObjectPolicy {
+                require_signed: true,
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+
+        assert!(holder.vdc_collection.is_some());
+        assert!(holder.provided_credentials.is_some());
+        assert_eq!(
+            *holder.trusted_dids.read().unwrap(),
+            vec!["did:web:verifier.example.com".to_string()]
+        );
+        assert!(holder.request_object_policy.read().unwrap().require_signed);
+    }
+}
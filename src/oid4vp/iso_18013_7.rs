@@ -0,0 +1,386 @@
+//! Presentation of mdocs to remote verifiers over OID4VP, as profiled by
+//! ISO/IEC 18013-7 Annex B.
+//!
+//! In the unattended flow there is no device engagement or reader key, so the
+//! SessionTranscript is bound to the authorization request instead:
+//!
+//! ```text
+//! SessionTranscript = [null, null, OID4VPHandover]
+//! OID4VPHandover    = [clientIdHash, responseUriHash, nonce]
+//! clientIdHash      = SHA-256(CBOR([client_id, mdocGeneratedNonce]))
+//! responseUriHash   = SHA-256(CBOR([response_uri, mdocGeneratedNonce]))
+//! ```
+//!
+//! Requests of the Digital Credentials API are bound to the origin of the
+//! caller instead, as defined by OpenID4VP 1.0, Appendix B.2.6.2:
+//!
+//! ```text
+//! OpenID4VPDCAPIHandover     = ["OpenID4VPDCAPIHandover", SHA-256(CBOR(OpenID4VPDCAPIHandoverInfo))]
+//! OpenID4VPDCAPIHandoverInfo = [origin, nonce, jwkThumbprint / null]
+//! ```
+//!
+//! The resulting DeviceResponse is returned as the base64url-encoded vp_token.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use base64::prelude::*;
+use isomdl::definitions::{
+    helpers::{NonEmptyMap, NonEmptyVec},
+    IssuerSigned,
+};
+use serde::Serialize;
+use serde_cbor::Value as Cbor;
+use sha2::{Digest, Sha256};
+
+use crate::{credential::mdoc::Mdoc, mdl::device_auth, signer::DeviceSigner};
+
+use super::key_attestation::KEY_ATTESTATION_HEADER;
+use super::permission_request::{PermissionResponseError, RequestedElement, RequestedField};
+
+/// The request parameters the OID4VP handover is bound to.
+#[derive(Debug, Clone)]
+pub(crate) struct Oid4vpHandover {
+    pub client_id: String,
+    pub response_uri: String,
+    pub nonce: String,
+    pub mdoc_generated_nonce: String,
+}
+
+impl Oid4vpHandover {
+    /// Construct the SessionTranscript for this handover.
+    pub fn session_transcript(&self) -> Result<Cbor, PermissionResponseError> {
+        let client_id_hash = hash_with_nonce(&self.client_id, &self.mdoc_generated_nonce)?;
+        let response_uri_hash = hash_with_nonce(&self.response_uri, &self.mdoc_generated_nonce)?;
+
+        Ok(Cbor::Array(vec![
+            Cbor::Null,
+            Cbor::Null,
+            Cbor::Array(vec![
+                Cbor::Bytes(client_id_hash),
+                Cbor::Bytes(response_uri_hash),
+                Cbor::Text(self.nonce.clone()),
+            ]),
+        ]))
+    }
+}
+
+/// The request parameters the handover of requests of the Digital
+/// Credentials API is bound to.
+#[derive(Debug, Clone)]
+pub(crate) struct DcApiHandover {
+    pub origin: String,
+    pub nonce: String,
+    /// The RFC 7638 thumbprint of the key the response is encrypted to, for
+    /// encrypted responses.
+    pub jwk_thumbprint: Option<Vec<u8>>,
+}
+
+impl DcApiHandover {
+    /// Construct the SessionTranscript for this handover.
+    pub fn session_transcript(&self) -> Result<Cbor, PermissionResponseError> {
+        let handover_info = serde_cbor::to_vec(&Cbor::Array(vec![
+            Cbor::Text(self.origin.clone()),
+            Cbor::Text(self.nonce.clone()),
+            self.jwk_thumbprint.clone().map_or(Cbor::Null, Cbor::Bytes),
+        ]))
+        .map_err(PermissionResponseError::mdoc_presentation)?;
+
+        Ok(Cbor::Array(vec![
+            Cbor::Null,
+            Cbor::Null,
+            Cbor::Array(vec![
+                Cbor::Text("OpenID4VPDCAPIHandover".into()),
+                Cbor::Bytes(Sha256::digest(handover_info).to_vec()),
+            ]),
+        ]))
+    }
+}
+
+/// Generate a fresh mdoc nonce for an OID4VP handover.
+pub(crate) fn generate_mdoc_nonce() -> Result<String, PermissionResponseError> {
+    let nonce = crate::crypto_provider::provider()
+        .random_bytes(16)
+        .map_err(PermissionResponseError::mdoc_presentation)?;
+    Ok(BASE64_URL_SAFE_NO_PAD.encode(nonce))
+}
+
+fn hash_with_nonce(value: &str, nonce: &str) -> Result<Vec<u8>, PermissionResponseError> {
+    let to_hash = serde_cbor::to_vec(&Cbor::Array(vec![
+        Cbor::Text(value.to_string()),
+        Cbor::Text(nonce.to_string()),
+    ]))
+    .map_err(PermissionResponseError::mdoc_presentation)?;
+
+    Ok(Sha256::digest(to_hash).to_vec())
+}
+
+/// Return the (namespace, element) pairs selected by the requested fields.
+pub(crate) fn requested_elements(fields: &[Arc<RequestedField>]) -> BTreeSet<(String, String)> {
+    fields
+        .iter()
+        .flat_map(|field| field.pointers.iter())
+        .filter_map(|pointer| match pointer.as_slice() {
+            [namespace, element] => Some((namespace.clone(), element.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Return the data elements requested by the fields, with whether the
+/// verifier intends to retain them.
+///
+/// Elements requested by several fields are retained, or required, if any of
+/// them is.
+pub(crate) fn element_requests(fields: &[Arc<RequestedField>]) -> Vec<RequestedElement> {
+    let mut elements: Vec<RequestedElement> = vec![];
+    for field in fields {
+        for pointer in field.pointers.iter() {
+            let [namespace, identifier] = pointer.as_slice() else {
+                continue;
+            };
+            match elements
+                .iter_mut()
+                .find(|e| &e.namespace == namespace && &e.identifier == identifier)
+            {
+                Some(element) => {
+                    element.intent_to_retain |= field.retained;
+                    element.required |= field.required;
+                }
+                None => elements.push(RequestedElement {
+                    namespace: namespace.clone(),
+                    identifier: identifier.clone(),
+                    intent_to_retain: field.retained,
+                    required: field.required,
+                }),
+            }
+        }
+    }
+    elements
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceResponse {
+    version: &'static str,
+    documents: Vec<ResponseDocument>,
+    status: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponseDocument {
+    doc_type: String,
+    issuer_signed: IssuerSigned,
+    device_signed: Cbor,
+}
+
+fn tag24(value: &Cbor) -> Result<Cbor, PermissionResponseError> {
+    let bytes = serde_cbor::to_vec(value).map_err(PermissionResponseError::mdoc_presentation)?;
+    Ok(Cbor::Tag(24, Box::new(Cbor::Bytes(bytes))))
+}
+
+fn cbor_map<const N: usize>(entries: [(Cbor, Cbor); N]) -> Cbor {
+    Cbor::Map(entries.into_iter().collect())
+}
+
+/// Build the DeviceResponse presenting the requested elements of the mdoc,
+/// with the device signature computed over the given SessionTranscript, and
+/// carrying the attestation of the device key if one is given.
+pub(crate) async fn device_response(
+    mdoc: &Mdoc,
+    elements: &BTreeSet<(String, String)>,
+    session_transcript: Cbor,
+    signer: &dyn DeviceSigner,
+    key_attestation: Option<String>,
+) -> Result<Vec<u8>, PermissionResponseError> {
+    let document = mdoc.document();
+    let doctype = mdoc.doctype();
+
+    let namespaces = document
+        .namespaces
+        .clone()
+        .into_inner()
+        .into_iter()
+        .filter_map(|(namespace, items)| {
+            let disclosed = items
+                .into_inner()
+                .into_iter()
+                .filter(|(element, _)| elements.contains(&(namespace.clone(), element.clone())))
+                .map(|(_, item)| item)
+                .collect::<Vec<_>>();
+            NonEmptyVec::try_from(disclosed)
+                .ok()
+                .map(|items| (namespace, items))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let issuer_signed = IssuerSigned {
+        namespaces: NonEmptyMap::try_from(namespaces).ok(),
+        issuer_auth: document.issuer_auth.clone(),
+    };
+
+    // No device-signed elements are released.
+    let device_namespaces = tag24(&Cbor::Map(BTreeMap::new()))?;
+
+    let device_authentication = device_auth::device_authentication_bytes(
+        session_transcript,
+        doctype.clone(),
+        device_namespaces.clone(),
+    )?;
+    let mut device_signature =
+        device_auth::device_signature(signer, &mdoc.key_alias(), device_authentication).await?;
+    // The unprotected header is not signed by the device key, the attestation
+    // being signed by its own issuer and bound to the key it attests.
+    if let (Some(attestation), Cbor::Array(cose_sign1)) = (key_attestation, &mut device_signature) {
+        cose_sign1[1] = cbor_map([(
+            Cbor::Text(KEY_ATTESTATION_HEADER.into()),
+            Cbor::Text(attestation),
+        )]);
+    }
+
+    let device_signed = cbor_map([
+        (Cbor::Text("nameSpaces".into()), device_namespaces),
+        (
+            Cbor::Text("deviceAuth".into()),
+            cbor_map([(Cbor::Text("deviceSignature".into()), device_signature)]),
+        ),
+    ]);
+
+    serde_cbor::to_vec(&DeviceResponse {
+        version: "1.0",
+        documents: vec![ResponseDocument {
+            doc_type: doctype,
+            issuer_signed,
+            device_signed,
+        }],
+        status: 0,
+    })
+    .map_err(PermissionResponseError::mdoc_presentation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_requested_elements() {
+        let field = |pointers: Vec<Vec<String>>, retained: bool| {
+            Arc::new(RequestedField {
+                id: uuid::Uuid::new_v4(),
+                name: None,
+                required: !retained,
+                retained,
+                purpose: None,
+                input_descriptor_id: "org.iso.18013.5.1.mDL".into(),
+                raw_fields: vec![],
+                pointers,
+                values: vec![],
+                path: None,
+                mdoc_element: None,
+                filter: None,
+                label: None,
+            })
+        };
+
+        let family_name = || vec![vec!["org.iso.18013.5.1".into(), "family_name".into()]];
+        let fields = [
+            field(family_name(), false),
+            field(vec![vec!["org.iso.18013.5.1".into()]], false),
+            field(family_name(), true),
+        ];
+
+        assert_eq!(
+            requested_elements(&fields),
+            BTreeSet::from([("org.iso.18013.5.1".into(), "family_name".into())])
+        );
+        assert_eq!(
+            element_requests(&fields),
+            vec![RequestedElement {
+                namespace: "org.iso.18013.5.1".into(),
+                identifier: "family_name".into(),
+                intent_to_retain: true,
+                required: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn session_transcript_binds_request() {
+        let handover = Oid4vpHandover {
+            client_id: "verifier.example.com".into(),
+            response_uri: "https://verifier.example.com/response".into(),
+            nonce: "abc".into(),
+            mdoc_generated_nonce: "xyz".into(),
+        };
+
+        let Cbor::Array(transcript) = handover.session_transcript().unwrap() else {
+            panic!("transcript is not an array");
+        };
+        assert_eq!(transcript[0], Cbor::Null);
+        assert_eq!(transcript[1], Cbor::Null);
+
+        let Cbor::Array(oid4vp_handover) = &transcript[2] else {
+            panic!("handover is not an array");
+        };
+        let hash = |value: &str| {
+            Cbor::Bytes(
+                Sha256::digest(
+                    serde_cbor::to_vec(&Cbor::Array(vec![
+                        Cbor::Text(value.into()),
+                        Cbor::Text("xyz".into()),
+                    ]))
+                    .unwrap(),
+                )
+                .to_vec(),
+            )
+        };
+        assert_eq!(oid4vp_handover[0], hash("verifier.example.com"));
+        assert_eq!(
+            oid4vp_handover[1],
+            hash("https://verifier.example.com/response")
+        );
+        assert_eq!(oid4vp_handover[2], Cbor::Text("abc".into()));
+
+        let other = Oid4vpHandover {
+            mdoc_generated_nonce: "other".into(),
+            ..handover
+        };
+        assert_ne!(
+            other.session_transcript().unwrap(),
+            Cbor::Array(transcript.clone())
+        );
+    }
+
+    #[test]
+    fn dc_api_session_transcript_binds_origin() {
+        let handover = DcApiHandover {
+            origin: "https://verifier.example.com".into(),
+            nonce: "abc".into(),
+            jwk_thumbprint: None,
+        };
+
+        let Cbor::Array(transcript) = handover.session_transcript().unwrap() else {
+            panic!("transcript is not an array");
+        };
+        let handover_info = serde_cbor::to_vec(&Cbor::Array(vec![
+            Cbor::Text("https://verifier.example.com".into()),
+            Cbor::Text("abc".into()),
+            Cbor::Null,
+        ]))
+        .unwrap();
+        assert_eq!(
+            transcript[2],
+            Cbor::Array(vec![
+                Cbor::Text("OpenID4VPDCAPIHandover".into()),
+                Cbor::Bytes(Sha256::digest(handover_info).to_vec()),
+            ])
+        );
+
+        let other = DcApiHandover {
+            origin: "https://attacker.example.com".into(),
+            ..handover
+        };
+        assert_ne!(other.session_transcript().unwrap(), Cbor::Array(transcript));
+    }
+}
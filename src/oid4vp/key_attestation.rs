@@ -0,0 +1,97 @@
+//! Attestations of the holder keys credentials are presented with, proving to
+//! verifiers that the keys are hardware-backed.
+//!
+//! The attestation is supplied by the app, e.g. an Android key attestation
+//! certificate chain or an OID4VCI `key-attestation+jwt`, and embedded:
+//!
+//! - in the `key_attestation` header of the KB-JWT of SD-JWT presentations,
+//!   and of the JWT VPs of JWT VCs, as in the JWT proofs of OID4VCI;
+//! - in the `key_attestation` unprotected header of the `deviceSignature` of
+//!   mdoc presentations, the attestation being signed by its own issuer.
+//!
+//! NOTE: no profile defines how verifiers request key attestations yet, so
+//! they are only embedded when the request sets the
+//! [KEY_ATTESTATION_PARAMETER] parameter to `true`.
+
+use super::request;
+use crate::common::KeyAlias;
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use serde_json::Value as Json;
+
+/// The authorization request parameter requesting key attestations.
+pub const KEY_ATTESTATION_PARAMETER: &str = "key_attestation_required";
+
+/// The JWT and COSE header carrying the key attestation.
+pub(crate) const KEY_ATTESTATION_HEADER: &str = "key_attestation";
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum KeyAttestationError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
+    #[error("The key attestation is unavailable: {0}")]
+    Unavailable(String),
+}
+
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for KeyAttestationError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        KeyAttestationError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+/// Interface: KeyAttestationProvider
+///
+/// The KeyAttestationProvider returns the platform attestation of the key with
+/// the alias, bound to the nonce of the request when the platform supports
+/// attestation challenges.
+#[uniffi::export(with_foreign)]
+#[async_trait]
+pub trait KeyAttestationProvider: Send + Sync + Debug {
+    async fn key_attestation(
+        &self,
+        key_alias: KeyAlias,
+        nonce: String,
+    ) -> Result<String, KeyAttestationError>;
+}
+
+/// Check whether the authorization request asks for key attestations.
+pub(crate) fn requested(request: &AuthorizationRequestObject) -> bool {
+    match request::parameters(request).get(KEY_ATTESTATION_PARAMETER) {
+        Some(Json::Bool(required)) => *required,
+        Some(Json::String(required)) => required == "true",
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn detects_requested_attestations() {
+        let request = |parameter: Json| -> AuthorizationRequestObject {
+            let mut request = json!({
+                "client_id": "did:web:verifier.example.com",
+                "response_type": "vp_token",
+                "response_mode": "direct_post",
+                "response_uri": "https://verifier.example.com/response",
+                "nonce": "n-0S6_WzA2Mj",
+            });
+            if !parameter.is_null() {
+                request[KEY_ATTESTATION_PARAMETER] = parameter;
+            }
+            serde_json::from_value(request).unwrap()
+        };
+
+        assert!(requested(&request(json!(true))));
+        assert!(requested(&request(json!("true"))));
+        assert!(!requested(&request(json!(false))));
+        assert!(!requested(&request(Json::Null)));
+    }
+}
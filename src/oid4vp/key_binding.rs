@@ -0,0 +1,260 @@
+//! Key binding JWTs of SD-JWT presentations.
+//!
+//! A key binding JWT proves that the presentation is made by the holder of the
+//! key the credential is bound to, through its `cnf` claim, and binds the
+//! presentation to the request it answers.
+
+use super::key_attestation::KEY_ATTESTATION_HEADER;
+use super::permission_request::PermissionResponseError;
+use super::transaction_data;
+use crate::common::KeyAlias;
+use crate::signer::{self, DeviceSigner};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::prelude::*;
+use serde_json::{json, Value as Json};
+use sha2::{Digest, Sha256};
+
+/// The JWK parameters identifying a public key.
+const PUBLIC_KEY_PARAMETERS: &[&str] = &["kty", "crv", "x", "y", "n", "e"];
+
+/// What a key binding JWT is bound to.
+#[derive(Debug, Clone)]
+pub(crate) struct KeyBinding {
+    /// The client ID of the verifier.
+    pub audience: String,
+    /// The nonce of the authorization request.
+    pub nonce: String,
+    /// The hashes of the transaction data the presentation authorizes.
+    pub transaction_data_hashes: Vec<String>,
+    /// The attestation of the device key, if the verifier requests one.
+    pub key_attestation: Option<String>,
+    /// When the presentation is made, by the clock of the holder.
+    pub issued_at: SystemTime,
+}
+
+/// Append a key binding JWT to an SD-JWT presentation, of the form
+/// `<issuer-jwt>~<disclosure>~...~`.
+///
+/// Credentials that are neither bound to a key through a `cnf` claim, nor
+/// stored with a key alias, are presented without one, unless they authorize
+/// transactions.
+pub(crate) async fn bind_presentation(
+    presentation: String,
+    claims: &Json,
+    key_alias: Option<KeyAlias>,
+    signer: Option<&dyn DeviceSigner>,
+    binding: &KeyBinding,
+) -> Result<String, PermissionResponseError> {
+    let cnf = match (claims.get("cnf"), &key_alias) {
+        (None, None) if binding.transaction_data_hashes.is_empty() => return Ok(presentation),
+        (None, None) => {
+            return Err(unbound(
+                "transaction data can only be authorized by a key bound credential",
+            ))
+        }
+        (None, Some(_)) => return Err(unbound("the credential has no cnf claim")),
+        (Some(cnf), _) => cnf,
+    };
+
+    let key_alias = key_alias.ok_or_else(|| unbound("the credential has no key alias"))?;
+    let signer =
+        signer.ok_or_else(|| PermissionResponseError::DeviceSignerRequired("SD-JWT".into()))?;
+
+    let jwk: Json = serde_json::from_str(&signer.jwk(key_alias.clone())?)
+        .map_err(PermissionResponseError::key_binding)?;
+    if !cnf.get("jwk").is_some_and(|cnf| same_public_key(cnf, &jwk)) {
+        return Err(unbound(
+            "the cnf key of the credential is not held by the device signer",
+        ));
+    }
+
+    let mut header = json!({
+        "typ": "kb+jwt",
+        "alg": signer.algorithm(key_alias.clone())?,
+    });
+    if let Some(attestation) = &binding.key_attestation {
+        header[KEY_ATTESTATION_HEADER] = json!(attestation);
+    }
+    let issued_at = binding
+        .issued_at
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let mut payload = json!({
+        "iat": issued_at,
+        "aud": binding.audience,
+        "nonce": binding.nonce,
+        "sd_hash": BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(presentation.as_bytes())),
+    });
+    if !binding.transaction_data_hashes.is_empty() {
+        payload["transaction_data_hashes"] = json!(binding.transaction_data_hashes);
+        payload["transaction_data_hashes_alg"] = json!(transaction_data::HASH_ALGORITHM);
+    }
+
+    let signing_input = format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+        BASE64_URL_SAFE_NO_PAD.encode(payload.to_string()),
+    );
+    let signature = signer::sign_raw(signer, &key_alias, signing_input.as_bytes().to_vec()).await?;
+
+    Ok(format!(
+        "{presentation}{signing_input}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(signature)
+    ))
+}
+
+/// Check whether two JWKs are of the same public key.
+pub(crate) fn same_public_key(a: &Json, b: &Json) -> bool {
+    a.get("kty").is_some()
+        && PUBLIC_KEY_PARAMETERS
+            .iter()
+            .all(|parameter| a.get(parameter) == b.get(parameter))
+}
+
+fn unbound(reason: &str) -> PermissionResponseError {
+    PermissionResponseError::KeyBinding {
+        reason: reason.into(),
+        source: None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::signer::DeviceSignerError;
+
+    use p256::ecdsa::{
+        signature::{Signer, Verifier},
+        Signature, SigningKey,
+    };
+
+    /// A device signer holding a single P-256 key.
+    #[derive(Debug)]
+    pub(crate) struct TestSigner(pub SigningKey);
+
+    impl TestSigner {
+        pub(crate) fn jwk(&self) -> Json {
+            let point = self.0.verifying_key().to_encoded_point(false);
+            json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "x": BASE64_URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+                "y": BASE64_URL_SAFE_NO_PAD.encode(point.y().unwrap()),
+            })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DeviceSigner for TestSigner {
+        fn algorithm(&self, _key_alias: KeyAlias) -> Result<String, DeviceSignerError> {
+            Ok("ES256".into())
+        }
+
+        fn jwk(&self, _key_alias: KeyAlias) -> Result<String, DeviceSignerError> {
+            Ok(TestSigner::jwk(self).to_string())
+        }
+
+        async fn sign(
+            &self,
+            _key_alias: KeyAlias,
+            payload: Vec<u8>,
+        ) -> Result<Vec<u8>, DeviceSignerError> {
+            let signature: Signature = self.0.sign(&payload);
+            Ok(signature.to_der().as_bytes().to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn binds_presentations() {
+        let signer = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let binding = KeyBinding {
+            audience: "did:web:verifier".into(),
+            nonce: "n-0S6_WzA2Mj".into(),
+            transaction_data_hashes: vec!["fOBUSQvo46yQO-wRwXBcGqvnbKIueISEL961_Sjd4do".into()],
+            key_attestation: Some("eyJ0eXAiOiJrZXktYXR0ZXN0YXRpb24rand0In0.e30.c2ln".into()),
+            issued_at: SystemTime::now(),
+        };
+        let key_alias = Some(KeyAlias("key".into()));
+        let presentation = "eyJhbGciOiJFUzI1NiJ9.e30.c2ln~WyJzYWx0IiwibmFtZSIsIkFsaWNlIl0~";
+        let claims = json!({ "cnf": { "jwk": signer.jwk() } });
+
+        let bound = bind_presentation(
+            presentation.into(),
+            &claims,
+            key_alias.clone(),
+            Some(&signer),
+            &binding,
+        )
+        .await
+        .unwrap();
+        let kb_jwt = bound.strip_prefix(presentation).unwrap();
+
+        let (signing_input, signature) = kb_jwt.rsplit_once('.').unwrap();
+        let signature =
+            Signature::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(signature).unwrap()).unwrap();
+        assert!(signer
+            .0
+            .verifying_key()
+            .verify(signing_input.as_bytes(), &signature)
+            .is_ok());
+
+        let decode = |part: usize| -> Json {
+            serde_json::from_slice(
+                &BASE64_URL_SAFE_NO_PAD
+                    .decode(signing_input.split('.').nth(part).unwrap())
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            decode(0)[KEY_ATTESTATION_HEADER],
+            json!(binding.key_attestation)
+        );
+        let payload = decode(1);
+        assert_eq!(payload["aud"], "did:web:verifier");
+        assert_eq!(payload["nonce"], "n-0S6_WzA2Mj");
+        assert_eq!(
+            payload["sd_hash"],
+            BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(presentation.as_bytes()))
+        );
+        assert_eq!(
+            payload["transaction_data_hashes"],
+            json!(binding.transaction_data_hashes)
+        );
+
+        // Credentials bound to a key the device does not hold cannot be presented.
+        let other = TestSigner(SigningKey::from_slice(&[2; 32]).unwrap());
+        let claims = json!({ "cnf": { "jwk": other.jwk() } });
+        assert!(matches!(
+            bind_presentation(
+                presentation.into(),
+                &claims,
+                key_alias,
+                Some(&signer),
+                &binding
+            )
+            .await,
+            Err(PermissionResponseError::KeyBinding { .. })
+        ));
+
+        // Unbound credentials are presented as is, but cannot authorize
+        // transactions.
+        assert!(matches!(
+            bind_presentation(presentation.into(), &json!({}), None, None, &binding).await,
+            Err(PermissionResponseError::KeyBinding { .. })
+        ));
+        let binding = KeyBinding {
+            transaction_data_hashes: vec![],
+            ..binding
+        };
+        assert_eq!(
+            bind_presentation(presentation.into(), &json!({}), None, None, &binding)
+                .await
+                .unwrap(),
+            presentation
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Trust-on-first-use pinning of the keys verifiers sign their requests with,
+//! protecting users from verifiers impersonated between sessions.
+//!
+//! The key of a verifier is pinned in the [TrustedVerifierStore] of the
+//! holder on the first successful submission of a response to it. The
+//! verifier review delegate is then asked about the following requests of the
+//! verifier signed with another key, or not signed at all, even when the
+//! verifier is trusted, with [VerifierInfo::key_change].
+//!
+//! Keys are identified by the leaf certificate of the `x5c` header of request
+//! objects, or else the RFC 7638 thumbprint of their `jwk` header, or of the
+//! key of the DID verification method of their `kid` header, in that order.
+
+use super::error::OID4VPError;
+use super::holder::Holder;
+use super::permission_request::PermissionResponse;
+use super::request;
+use super::trusted_verifiers::{TrustedVerifierError, TrustedVerifierStore};
+use super::verifier_review::VerifierInfo;
+use crate::storage_manager::*;
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use base64::prelude::*;
+use openid4vp::core::authorization_request::verification::RequestVerifier;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ssi::dids::DIDURLBuf;
+use ssi::verification_methods::{
+    JwkVerificationMethod, ReferenceOrOwnedRef, VerificationMethodResolver,
+};
+use ssi::JWK;
+use uniffi::deps::log;
+
+/// Internal prefix for verifier key pin keys.
+const KEY_PREFIX: &str = "VerifierKeyPin.";
+
+/// The key a verifier signed its requests with when it was first presented
+/// to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct VerifierKeyPin {
+    pub client_id: String,
+    /// The fingerprint of the key, `x5c:` followed by the SHA-256 digest of
+    /// the leaf certificate, in hexadecimal, or `jwk:` followed by the
+    /// thumbprint of the key.
+    pub fingerprint: String,
+    pub pinned_at: SystemTime,
+}
+
+/// A request of a verifier signed with another key than the pinned one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct VerifierKeyChange {
+    pub pinned: VerifierKeyPin,
+    /// The fingerprint of the key of the request, or `None` when the request
+    /// is not signed.
+    pub fingerprint: Option<String>,
+}
+
+impl Holder {
+    /// Return the fingerprint of the key a request object is signed with,
+    /// resolving the DID verification method of its `kid` header.
+    pub(crate) async fn key_fingerprint(&self, request_jwt: &str) -> Option<String> {
+        let header = request::jwt_part(request_jwt, 0)?;
+
+        if let Some(leaf) = header["x5c"][0].as_str() {
+            let leaf = BASE64_STANDARD.decode(leaf).ok()?;
+            return Some(format!("x5c:{}", hex::encode(Sha256::digest(leaf))));
+        }
+        let jwk: JWK = match (header.get("jwk"), header["kid"].as_str()) {
+            (Some(jwk), _) => serde_json::from_value(jwk.clone()).ok()?,
+            (None, Some(kid)) => {
+                let kid = DIDURLBuf::new(kid.as_bytes().to_vec()).ok()?;
+                self.verification_method_resolver()
+                    .resolve_verification_method(
+                        None,
+                        Some(ReferenceOrOwnedRef::Reference(kid.as_iri())),
+                    )
+                    .await
+                    .ok()?
+                    .to_jwk()
+                    .into_owned()
+            }
+            (None, None) => return None,
+        };
+        jwk.thumbprint()
+            .ok()
+            .map(|thumbprint| format!("jwk:{thumbprint}"))
+    }
+}
+
+#[uniffi::export]
+impl TrustedVerifierStore {
+    /// Get the pinned signing key of a verifier, if any.
+    pub fn pinned_key(
+        &self,
+        client_id: String,
+    ) -> Result<Option<VerifierKeyPin>, TrustedVerifierError> {
+        let Some(raw) = self
+            .storage
+            .get(Self::pin_key(&client_id))
+            .map_err(TrustedVerifierError::LoadFailed)?
+        else {
+            return Ok(None);
+        };
+
+        serde_cbor::from_slice(&raw.0)
+            .map(Some)
+            .map_err(|_| TrustedVerifierError::DeserializeFailed)
+    }
+
+    /// Pin the signing key of a verifier, replacing the pinned one, e.g. once
+    /// the user confirmed a key rotation.
+    pub fn pin_key(&self, pin: VerifierKeyPin) -> Result<(), TrustedVerifierError> {
+        let value = serde_cbor::to_vec(&pin).map_err(|_| TrustedVerifierError::SerializeFailed)?;
+
+        self.storage
+            .add(Self::pin_key(&pin.client_id), Value(value))
+            .map_err(TrustedVerifierError::StoreFailed)
+    }
+
+    /// Forget the pinned signing key of a verifier, so that the next key it
+    /// is presented to with is pinned.
+    pub fn unpin_key(&self, client_id: String) -> Result<(), TrustedVerifierError> {
+        self.storage
+            .remove(Self::pin_key(&client_id))
+            .map_err(TrustedVerifierError::DeleteFailed)
+    }
+}
+
+impl TrustedVerifierStore {
+    fn pin_key(client_id: &str) -> Key {
+        Key(format!("{KEY_PREFIX}{client_id}"))
+    }
+}
+
+impl Holder {
+    /// Compare the signing key of a request with the pinned key of its
+    /// verifier, if any.
+    pub(crate) fn key_change(
+        &self,
+        verifier: &VerifierInfo,
+        fingerprint: Option<&str>,
+    ) -> Result<Option<VerifierKeyChange>, OID4VPError> {
+        let Some(store) = self.trusted_verifier_store()? else {
+            return Ok(None);
+        };
+        let Some(pinned) = store.pinned_key(verifier.client_id.clone())? else {
+            return Ok(None);
+        };
+
+        if Some(pinned.fingerprint.as_str()) == fingerprint {
+            return Ok(None);
+        }
+        Ok(Some(VerifierKeyChange {
+            pinned,
+            fingerprint: fingerprint.map(ToOwned::to_owned),
+        }))
+    }
+
+    /// Pin the signing key of the request a response was submitted to, if
+    /// its verifier has none pinned yet or the user accepted its change.
+    pub(crate) fn pin_verifier_key(&self, response: &PermissionResponse) {
+        let Some(fingerprint) = response.verifier_key.clone() else {
+            return;
+        };
+        let pin = || -> Result<(), OID4VPError> {
+            let Some(store) = self.trusted_verifier_store()? else {
+                return Ok(());
+            };
+            let client_id = response.authorization_request.client_id().0.clone();
+            if store
+                .pinned_key(client_id.clone())?
+                .is_some_and(|pinned| pinned.fingerprint == fingerprint)
+            {
+                return Ok(());
+            }
+            Ok(store.pin_key(VerifierKeyPin {
+                client_id,
+                fingerprint: fingerprint.clone(),
+                pinned_at: self.now()?,
+            })?)
+        };
+
+        // Failing to pin the key does not fail the submission.
+        if let Err(e) = pin() {
+            log::warn!("Failed to pin the key of the verifier: {e:?}");
+        }
+    }
+
+    fn trusted_verifier_store(&self) -> Result<Option<Arc<TrustedVerifierStore>>, OID4VPError> {
+        Ok(self
+            .trusted_verifiers
+            .read()
+            .map_err(|_| OID4VPError::LockError("trusted_verifiers".into()))?
+            .clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+
+    use serde_json::Value as Json;
+
+    fn jwt(header: Json) -> String {
+        format!(
+            "{}.e30.c2ln",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string())
+        )
+    }
+
+    #[tokio::test]
+    async fn pins_verifier_keys() {
+        let holder = Holder::new_with_credentials(vec![], vec![]).await.unwrap();
+
+        let certificate = BASE64_STANDARD.encode(b"certificate");
+        let x5c = holder
+            .key_fingerprint(&jwt(serde_json::json!({ "x5c": [certificate] })))
+            .await
+            .unwrap();
+        assert_eq!(
+            x5c,
+            format!("x5c:{}", hex::encode(Sha256::digest(b"certificate")))
+        );
+        // The key of a DID verification method is that of the same key given
+        // inline.
+        let key = JWK::generate_p256().to_public();
+        let thumbprint = format!("jwk:{}", key.thumbprint().unwrap());
+        assert_eq!(
+            holder
+                .key_fingerprint(&jwt(serde_json::json!({ "jwk": key })))
+                .await,
+            Some(thumbprint.clone())
+        );
+        let kid = ssi::dids::DIDJWK::generate_url(&key);
+        assert_eq!(
+            holder
+                .key_fingerprint(&jwt(serde_json::json!({ "kid": kid.to_string() })))
+                .await,
+            Some(thumbprint)
+        );
+        assert_eq!(
+            holder
+                .key_fingerprint(&jwt(serde_json::json!({ "alg": "ES256" })))
+                .await,
+            None
+        );
+        let verifier = VerifierInfo {
+            client_id: "verifier.example.com".into(),
+            client_id_scheme: Some("x509_san_dns".into()),
+            response_uri: None,
+            client_id_verified: true,
+            client_metadata: Default::default(),
+            key_change: None,
+        };
+        let store = TrustedVerifierStore::new(Arc::new(LocalStore::new()));
+        holder.set_trusted_verifier_store(store.clone()).unwrap();
+        assert_eq!(holder.key_change(&verifier, Some(&x5c)).unwrap(), None);
+
+        let pinned = VerifierKeyPin {
+            client_id: verifier.client_id.clone(),
+            fingerprint: x5c.clone(),
+            pinned_at: SystemTime::UNIX_EPOCH,
+        };
+        store.pin_key(pinned.clone()).unwrap();
+        assert_eq!(holder.key_change(&verifier, Some(&x5c)).unwrap(), None);
+        assert_eq!(
+            holder.key_change(&verifier, Some("x5c:other")).unwrap(),
+            Some(VerifierKeyChange {
+                pinned: pinned.clone(),
+                fingerprint: Some("x5c:other".into()),
+            })
+        );
+        assert_eq!(
+            holder.key_change(&verifier, None).unwrap(),
+            Some(VerifierKeyChange {
+                pinned,
+                fingerprint: None,
+            })
+        );
+
+        // Pins are kept apart from the trusted verifiers.
+        assert!(store.list().unwrap().is_empty());
+        store.unpin_key(verifier.client_id.clone()).unwrap();
+        assert_eq!(store.pinned_key(verifier.client_id).unwrap(), None);
+    }
+}
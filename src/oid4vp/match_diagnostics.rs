@@ -0,0 +1,326 @@
+//! Explanations of why stored credentials do not match a presentation
+//! definition, to tell users what is missing rather than show an empty list.
+
+use super::error::OID4VPError;
+use super::holder::designated_format;
+use super::permission_request::PermissionRequest;
+use crate::common::*;
+use crate::credential::{disclosure, CredentialFormat, ParsedCredential};
+
+use std::sync::Arc;
+
+use serde_json::Value as Json;
+
+/// A constraint of an input descriptor a credential fails.
+#[derive(Debug, Clone, PartialEq, uniffi::Enum)]
+pub enum ConstraintFailure {
+    /// The input descriptor does not accept the format of the credential.
+    WrongFormat { format: CredentialFormat },
+    /// The input descriptor requests mdocs of another doctype.
+    WrongDoctype { doctype: String },
+    /// No path of a required field selects a claim of the credential.
+    MissingField {
+        name: Option<String>,
+        paths: Vec<String>,
+    },
+    /// The claims a required field selects do not pass its filter.
+    FilterMismatch {
+        name: Option<String>,
+        paths: Vec<String>,
+        /// The JSON schema filter of the field.
+        filter: String,
+    },
+}
+
+/// The constraints of an input descriptor a credential fails, none if the
+/// credential satisfies the input descriptor.
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct DescriptorDiagnostics {
+    pub input_descriptor_id: String,
+    pub failures: Vec<ConstraintFailure>,
+}
+
+/// How a stored credential fares against each input descriptor.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct CredentialDiagnostics {
+    pub credential_id: Uuid,
+    pub format: CredentialFormat,
+    pub r#type: CredentialType,
+    pub descriptors: Vec<DescriptorDiagnostics>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl PermissionRequest {
+    /// Return, for each credential of the collection the request was matched
+    /// against, the constraints of each input descriptor it fails.
+    ///
+    /// Credentials that cannot be parsed are skipped.
+    ///
+    /// NOTE: restored permission requests, and requests matched against
+    /// provided credentials only, have no collection, and no diagnostics.
+    pub async fn match_diagnostics(&self) -> Result<Vec<CredentialDiagnostics>, OID4VPError> {
+        let Some(vdc_collection) = &self.vdc_collection else {
+            return Ok(vec![]);
+        };
+        let Ok(definition) = serde_json::to_value(&self.definition) else {
+            return Ok(vec![]);
+        };
+
+        let mut diagnostics = vec![];
+        for id in vdc_collection.clone().all_entries_async().await? {
+            let Some(credential) = vdc_collection
+                .clone()
+                .get_async(id)
+                .await?
+                .and_then(|credential| credential.try_into_parsed().ok())
+            else {
+                continue;
+            };
+            diagnostics.push(diagnose(&credential, &definition));
+        }
+        Ok(diagnostics)
+    }
+}
+
+/// Evaluate a credential against each input descriptor of a definition.
+pub(crate) fn diagnose(
+    credential: &Arc<ParsedCredential>,
+    definition: &Json,
+) -> CredentialDiagnostics {
+    let format = credential.format();
+    let json = credential.definition_json().unwrap_or(Json::Null);
+
+    let descriptors = definition["input_descriptors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|descriptor| {
+            let input_descriptor_id = descriptor["id"].as_str().unwrap_or_default().to_owned();
+            let mut failures = vec![];
+
+            let formats = descriptor
+                .get("format")
+                .or_else(|| definition.get("format"))
+                .and_then(Json::as_object);
+            if let Some(formats) = formats {
+                if !formats
+                    .keys()
+                    .any(|designation| designated_format(designation).as_ref() == Some(&format))
+                {
+                    failures.push(ConstraintFailure::WrongFormat {
+                        format: format.clone(),
+                    });
+                }
+            }
+
+            if format == CredentialFormat::MsoMdoc {
+                let doctype = credential.r#type().0;
+                if input_descriptor_id != doctype {
+                    failures.push(ConstraintFailure::WrongDoctype { doctype });
+                }
+            }
+
+            for field in descriptor["constraints"]["fields"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|field| !field["optional"].as_bool().unwrap_or(false))
+            {
+                failures.extend(diagnose_field(&json, field));
+            }
+
+            DescriptorDiagnostics {
+                input_descriptor_id,
+                failures,
+            }
+        })
+        .collect();
+
+    CredentialDiagnostics {
+        credential_id: credential.id(),
+        format,
+        r#type: credential.r#type(),
+        descriptors,
+    }
+}
+
+/// Evaluate a required field, with the first of its paths that selects
+/// claims, as when matching.
+fn diagnose_field(json: &Json, field: &Json) -> Option<ConstraintFailure> {
+    let name = field["name"].as_str().map(ToOwned::to_owned);
+    let paths = field["path"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Json::as_str)
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+
+    let Some(pointers) = paths
+        .iter()
+        .map(|path| disclosure::select_path(json, path))
+        .find(|pointers| !pointers.is_empty())
+    else {
+        return Some(ConstraintFailure::MissingField { name, paths });
+    };
+
+    let filter = field.get("filter")?;
+    let passes = pointers
+        .iter()
+        .filter_map(|pointer| disclosure::value_at(json, pointer))
+        .any(|value| passes_filter(value, filter));
+    (!passes).then(|| ConstraintFailure::FilterMismatch {
+        name,
+        paths,
+        filter: filter.to_string(),
+    })
+}
+
+/// Evaluate the common keywords of a JSON schema filter.
+///
+/// NOTE: other keywords, such as `pattern`, are not evaluated, and pass.
+fn passes_filter(value: &Json, filter: &Json) -> bool {
+    let Some(filter) = filter.as_object() else {
+        return true;
+    };
+
+    filter
+        .iter()
+        .all(|(keyword, expected)| match keyword.as_str() {
+            "type" => match expected.as_str() {
+                Some("string") => value.is_string(),
+                Some("number") => value.is_number(),
+                Some("integer") => value.is_i64() || value.is_u64(),
+                Some("boolean") => value.is_boolean(),
+                Some("array") => value.is_array(),
+                Some("object") => value.is_object(),
+                Some("null") => value.is_null(),
+                _ => true,
+            },
+            "const" => value == expected,
+            "enum" => expected
+                .as_array()
+                .is_some_and(|values| values.contains(value)),
+            "contains" => value
+                .as_array()
+                .is_some_and(|values| values.iter().any(|value| passes_filter(value, expected))),
+            "minimum" => compare_numbers(value, expected).is_some_and(|order| order.is_ge()),
+            "exclusiveMinimum" => {
+                compare_numbers(value, expected).is_some_and(|order| order.is_gt())
+            }
+            "maximum" => compare_numbers(value, expected).is_some_and(|order| order.is_le()),
+            "exclusiveMaximum" => {
+                compare_numbers(value, expected).is_some_and(|order| order.is_lt())
+            }
+            // Dates and times in ISO 8601 compare as strings.
+            "formatMinimum" => compare_strings(value, expected).is_some_and(|order| order.is_ge()),
+            "formatExclusiveMinimum" => {
+                compare_strings(value, expected).is_some_and(|order| order.is_gt())
+            }
+            "formatMaximum" => compare_strings(value, expected).is_some_and(|order| order.is_le()),
+            "formatExclusiveMaximum" => {
+                compare_strings(value, expected).is_some_and(|order| order.is_lt())
+            }
+            "minLength" => length(value)
+                .zip(expected.as_u64())
+                .is_some_and(|(length, min)| length >= min),
+            "maxLength" => length(value)
+                .zip(expected.as_u64())
+                .is_some_and(|(length, max)| length <= max),
+            _ => true,
+        })
+}
+
+fn compare_numbers(value: &Json, expected: &Json) -> Option<std::cmp::Ordering> {
+    value.as_f64()?.partial_cmp(&expected.as_f64()?)
+}
+
+fn compare_strings(value: &Json, expected: &Json) -> Option<std::cmp::Ordering> {
+    Some(value.as_str()?.cmp(expected.as_str()?))
+}
+
+fn length(value: &Json) -> Option<u64> {
+    value.as_str().map(|value| value.chars().count() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::json_vc::JsonVc;
+
+    use serde_json::json;
+
+    #[test]
+    fn explains_non_matches() {
+        let credential = ParsedCredential::new_ldp_vc(
+            JsonVc::new_from_json(
+                json!({
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiableCredential", "IdentityCredential"],
+                    "issuer": "did:example:issuer",
+                    "issuanceDate": "2024-01-01T00:00:00Z",
+                    "credentialSubject": {
+                        "id": "did:example:holder",
+                        "nationalities": ["FR"],
+                        "birthdate": "1990-01-01",
+                    },
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        );
+        let definition = json!({
+            "id": "identity",
+            "input_descriptors": [{
+                "id": "identity",
+                "format": { "ldp_vc": { "proof_type": ["Ed25519Signature2018"] } },
+                "constraints": {
+                    "fields": [
+                        {
+                            "path": ["$.credentialSubject.nationalities"],
+                            "filter": { "type": "array", "contains": { "const": "DE" } }
+                        },
+                        {
+                            "path": ["$.credentialSubject.birthdate"],
+                            "filter": { "type": "string", "formatMaximum": "2006-01-01" }
+                        },
+                        { "name": "Family name", "path": ["$.credentialSubject.family_name"] },
+                        { "path": ["$.credentialSubject.email"], "optional": true }
+                    ]
+                }
+            }, {
+                "id": "mdl",
+                "format": { "mso_mdoc": { "alg": ["ES256"] } },
+                "constraints": { "fields": [] }
+            }]
+        });
+
+        let diagnostics = diagnose(&credential, &definition);
+        assert_eq!(
+            diagnostics.descriptors,
+            vec![
+                DescriptorDiagnostics {
+                    input_descriptor_id: "identity".into(),
+                    failures: vec![
+                        ConstraintFailure::FilterMismatch {
+                            name: None,
+                            paths: vec!["$.credentialSubject.nationalities".into()],
+                            filter: json!({ "type": "array", "contains": { "const": "DE" } })
+                                .to_string(),
+                        },
+                        ConstraintFailure::MissingField {
+                            name: Some("Family name".into()),
+                            paths: vec!["$.credentialSubject.family_name".into()],
+                        },
+                    ],
+                },
+                DescriptorDiagnostics {
+                    input_descriptor_id: "mdl".into(),
+                    failures: vec![ConstraintFailure::WrongFormat {
+                        format: CredentialFormat::LdpVc
+                    }],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Matching of credentials against presentation definitions, parsing and
+//! evaluating credentials on a bounded pool of blocking tasks.
+//!
+//! On `wasm32`, where browsers run a single thread without a tokio runtime,
+//! credentials are matched one at a time instead.
+
+use super::profile::Profile;
+use super::streaming;
+use super::submission_requirements::SubmissionRequirements;
+use crate::common::Uuid;
+use crate::credential::ParsedCredential;
+use crate::vdc_collection::VdcCollection;
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use openid4vp::core::presentation_definition::PresentationDefinition;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::task::JoinSet;
+
+/// The maximum number of credentials parsed and evaluated at once.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_MATCHING_TASKS: usize = 8;
+
+/// A credential to match against a presentation definition.
+pub(crate) enum Candidate {
+    /// A credential provided to the holder, already parsed.
+    Parsed(Arc<ParsedCredential>),
+    /// A credential of the collection, parsed when matched.
+    Stored(Arc<VdcCollection>, Uuid),
+}
+
+impl Candidate {
+    async fn matched(
+        self,
+        definition: Arc<PresentationDefinition>,
+        profile: Profile,
+    ) -> Option<Arc<ParsedCredential>> {
+        let credential = match self {
+            Self::Parsed(credential) => credential,
+            Self::Stored(collection, id) => collection.get_parsed_async(id).await.ok().flatten()?,
+        };
+        if !profile.allows_format(&credential.format()) {
+            return None;
+        }
+        let matched = move || {
+            credential
+                .check_presentation_definition(&definition)
+                .then_some(credential)
+        };
+        #[cfg(target_arch = "wasm32")]
+        let matched = Some(matched());
+        #[cfg(not(target_arch = "wasm32"))]
+        let matched = tokio::task::spawn_blocking(matched).await.ok();
+        matched.flatten()
+    }
+}
+
+/// Return the candidates that match the presentation definition, in the order
+/// of the candidates, streaming them as they are found.
+///
+/// Candidates of formats the profile does not allow never match.
+///
+/// With `short_circuit`, matching stops once the matches satisfy the
+/// submission requirements, so later candidates are not offered.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn match_credentials(
+    candidates: Vec<Candidate>,
+    definition: &PresentationDefinition,
+    profile: Profile,
+    short_circuit: bool,
+) -> Vec<Arc<ParsedCredential>> {
+    let parallelism = std::thread::available_parallelism()
+        .map(|parallelism| parallelism.get())
+        .unwrap_or(1)
+        .min(MAX_MATCHING_TASKS);
+    let requirements = SubmissionRequirements::new(definition);
+    let definition = Arc::new(definition.clone());
+
+    let mut candidates = candidates.into_iter().enumerate();
+    let mut tasks = JoinSet::new();
+    let mut matches = vec![];
+    loop {
+        while tasks.len() < parallelism {
+            let Some((index, candidate)) = candidates.next() else {
+                break;
+            };
+            let definition = definition.clone();
+            tasks.spawn(async move { (index, candidate.matched(definition, profile).await) });
+        }
+
+        let Some(result) = tasks.join_next().await else {
+            break;
+        };
+        let Ok((index, Some(credential))) = result else {
+            continue;
+        };
+        streaming::emit_match(&credential);
+        matches.push((index, credential));
+
+        if short_circuit && is_satisfiable(&requirements, &matches) {
+            tasks.abort_all();
+            break;
+        }
+    }
+
+    matches.sort_by_key(|(index, _)| *index);
+    matches
+        .into_iter()
+        .map(|(_, credential)| credential)
+        .collect()
+}
+
+/// As the native [match_credentials], one candidate at a time.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn match_credentials(
+    candidates: Vec<Candidate>,
+    definition: &PresentationDefinition,
+    profile: Profile,
+    short_circuit: bool,
+) -> Vec<Arc<ParsedCredential>> {
+    let requirements = SubmissionRequirements::new(definition);
+    let definition = Arc::new(definition.clone());
+
+    let mut matches = vec![];
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        let Some(credential) = candidate.matched(definition.clone(), profile).await else {
+            continue;
+        };
+        streaming::emit_match(&credential);
+        matches.push((index, credential));
+
+        if short_circuit && is_satisfiable(&requirements, &matches) {
+            break;
+        }
+    }
+
+    matches
+        .into_iter()
+        .map(|(_, credential)| credential)
+        .collect()
+}
+
+fn is_satisfiable(
+    requirements: &SubmissionRequirements,
+    matches: &[(usize, Arc<ParsedCredential>)],
+) -> bool {
+    let credentials = matches
+        .iter()
+        .map(|(_, credential)| credential.clone())
+        .collect::<Vec<_>>();
+    let assigned = requirements
+        .assign(&credentials)
+        .into_iter()
+        .flatten()
+        .collect::<BTreeSet<_>>();
+    requirements.is_satisfied(&assigned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::json_vc::JsonVc;
+
+    #[tokio::test]
+    async fn matches_credentials_in_parallel() {
+        let title = || {
+            ParsedCredential::new_ldp_vc(
+                JsonVc::new_from_json(
+                    include_str!("../../tests/examples/vehicle_title.json").into(),
+                )
+                .unwrap(),
+            )
+        };
+        let definition: PresentationDefinition = serde_json::from_value(serde_json::json!({
+            "id": "vehicle-title",
+            "input_descriptors": [{
+                "id": "vehicle-title",
+                "constraints": {
+                    "fields": [{ "path": ["$.credentialSubject.vehicle.vehicleIdentificationNumber"] }]
+                }
+            }]
+        }))
+        .unwrap();
+        let titles = (0..20).map(|_| title()).collect::<Vec<_>>();
+        let candidates = || {
+            titles
+                .iter()
+                .cloned()
+                .map(Candidate::Parsed)
+                .collect::<Vec<_>>()
+        };
+
+        let matches = match_credentials(candidates(), &definition, Profile::Default, false).await;
+        assert_eq!(
+            matches.iter().map(|c| c.id()).collect::<Vec<_>>(),
+            titles.iter().map(|c| c.id()).collect::<Vec<_>>()
+        );
+
+        let matches = match_credentials(candidates(), &definition, Profile::Default, true).await;
+        assert_eq!(matches.len(), 1);
+
+        // Formats out of the profile are not matched, nor streamed.
+        let matches = match_credentials(candidates(), &definition, Profile::Haip, false).await;
+        assert!(matches.is_empty());
+    }
+}
@@ -0,0 +1,332 @@
+//! Verification of the mdoc DeviceResponses presented to a
+//! [super::verifier::Verifier] over OID4VP, as profiled by ISO/IEC 18013-7
+//! Annex B.
+//!
+//! Each document must be signed by a document signer chaining to an IACA
+//! trust anchor, disclose elements matching the digests of its MSO, be valid
+//! at the verification time, and be authenticated by the device key of its
+//! MSO over the SessionTranscript of the request.
+//!
+//! NOTE: only ES256 signatures and `deviceSignature` device authentication
+//! are supported.
+
+use super::iso_18013_7::Oid4vpHandover;
+use crate::clock;
+use crate::mdl::{device_auth, reader_auth};
+use crate::trust_anchors::verify_chain;
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use base64::prelude::*;
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use serde_cbor::Value as Cbor;
+use serde_json::{Map, Value as Json};
+use sha2::{Digest, Sha256};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uniffi::deps::anyhow::{anyhow, bail, Context, Result};
+use x509_cert::{
+    der::{Decode, Encode},
+    Certificate,
+};
+
+/// The COSE header label of the algorithm.
+const ALG_LABEL: i128 = 1;
+/// The COSE algorithm identifier of ES256.
+const ES256: i128 = -7;
+/// The COSE header label of the certificate chain.
+const X5CHAIN_LABEL: i128 = 33;
+
+/// Verify a base64url-encoded DeviceResponse bound to the handover, and
+/// return the disclosed elements of its documents, by namespace.
+pub(crate) async fn verify_device_response(
+    presentation: &str,
+    handover: &Oid4vpHandover,
+    trust_anchors: &[Certificate],
+    check_revocation: bool,
+    now: SystemTime,
+    leeway: Duration,
+) -> Result<Json> {
+    let response: Cbor = serde_cbor::from_slice(
+        &BASE64_URL_SAFE_NO_PAD
+            .decode(presentation)
+            .context("the device response is not base64url-encoded")?,
+    )
+    .context("the device response is not CBOR")?;
+    if field(&response, "status")? != &Cbor::Integer(0) {
+        bail!("the device response has an error status")
+    }
+    let Cbor::Array(documents) = field(&response, "documents")? else {
+        bail!("the device response has no documents")
+    };
+
+    let session_transcript = handover.session_transcript()?;
+    let mut claims: BTreeMap<String, Map<String, Json>> = BTreeMap::new();
+    for document in documents {
+        let verified = verify_document(
+            document,
+            &session_transcript,
+            trust_anchors,
+            check_revocation,
+            now,
+            leeway,
+        )
+        .await?;
+        for (namespace, elements) in verified {
+            claims.entry(namespace).or_default().extend(elements);
+        }
+    }
+    Ok(Json::Object(
+        claims
+            .into_iter()
+            .map(|(namespace, elements)| (namespace, Json::Object(elements)))
+            .collect(),
+    ))
+}
+
+async fn verify_document(
+    document: &Cbor,
+    session_transcript: &Cbor,
+    trust_anchors: &[Certificate],
+    check_revocation: bool,
+    now: SystemTime,
+    leeway: Duration,
+) -> Result<Vec<(String, Map<String, Json>)>> {
+    let Cbor::Text(doc_type) = field(document, "docType")? else {
+        bail!("the document has no docType")
+    };
+    let issuer_signed = field(document, "issuerSigned")?;
+
+    // The MSO is signed by the document signer, whose certificate chains to
+    // an IACA.
+    let issuer_auth = CoseSign1::parse(field(issuer_signed, "issuerAuth")?)?;
+    let chain = match issuer_auth.header(X5CHAIN_LABEL) {
+        Some(Cbor::Bytes(certificate)) => vec![certificate.clone()],
+        Some(Cbor::Array(certificates)) => certificates
+            .iter()
+            .map(|certificate| match certificate {
+                Cbor::Bytes(certificate) => Ok(certificate.clone()),
+                _ => Err(anyhow!("the x5chain is not a list of certificates")),
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => bail!("the issuerAuth has no x5chain"),
+    }
+    .iter()
+    .map(|der| Certificate::from_der(der).context("invalid x5chain certificate"))
+    .collect::<Result<Vec<_>>>()?;
+    verify_chain(&chain, trust_anchors, check_revocation, now).await?;
+    let spki = chain[0].tbs_certificate.subject_public_key_info.to_der()?;
+    let document_signer = VerifyingKey::from_public_key_der(&spki)
+        .map_err(|e| anyhow!("the document signer key is not a P-256 key: {e:?}"))?;
+    let Some(Cbor::Bytes(payload)) = &issuer_auth.payload else {
+        bail!("the issuerAuth has no payload")
+    };
+    issuer_auth
+        .verify(&document_signer, payload)
+        .context("the issuerAuth signature is invalid")?;
+
+    let mso = match serde_cbor::from_slice(payload)? {
+        Cbor::Tag(24, mso) => match *mso {
+            Cbor::Bytes(mso) => serde_cbor::from_slice(&mso)?,
+            _ => bail!("the MSO is not encoded"),
+        },
+        mso => mso,
+    };
+    if field(&mso, "docType")? != &Cbor::Text(doc_type.clone()) {
+        bail!("the MSO is not for the docType {doc_type}")
+    }
+    if field(&mso, "digestAlgorithm")? != &Cbor::Text("SHA-256".into()) {
+        bail!("unsupported digest algorithm")
+    }
+
+    let validity = field(&mso, "validityInfo")?;
+    if !clock::has_reached(date(field(validity, "validFrom")?)?, now, leeway) {
+        bail!("the {doc_type} document is not valid yet")
+    }
+    if clock::has_passed(date(field(validity, "validUntil")?)?, now, leeway) {
+        bail!("the {doc_type} document has expired")
+    }
+
+    // Each disclosed element is checked against its digest in the MSO.
+    let value_digests = field(&mso, "valueDigests")?;
+    let mut claims = vec![];
+    let namespaces = match field(issuer_signed, "nameSpaces") {
+        Ok(Cbor::Map(namespaces)) => namespaces.iter().collect(),
+        _ => vec![],
+    };
+    for (namespace, items) in namespaces {
+        let (Cbor::Text(namespace), Cbor::Array(items)) = (namespace, items) else {
+            bail!("invalid issuer-signed namespace")
+        };
+        let Cbor::Map(digests) = field(value_digests, namespace)? else {
+            bail!("invalid value digests of {namespace}")
+        };
+
+        let mut elements = Map::new();
+        for item in items {
+            let Cbor::Tag(24, encoded) = item else {
+                bail!("the issuer-signed items of {namespace} are not encoded")
+            };
+            let Cbor::Bytes(encoded) = encoded.as_ref() else {
+                bail!("the issuer-signed items of {namespace} are not encoded")
+            };
+            let digest = Sha256::digest(serde_cbor::to_vec(item)?).to_vec();
+            let item: Cbor = serde_cbor::from_slice(encoded)?;
+            let Cbor::Text(identifier) = field(&item, "elementIdentifier")? else {
+                bail!("invalid element identifier in {namespace}")
+            };
+            if digests.get(field(&item, "digestID")?) != Some(&Cbor::Bytes(digest)) {
+                bail!("the digest of {namespace} {identifier} does not match the MSO")
+            }
+            elements.insert(identifier.clone(), json(field(&item, "elementValue")?));
+        }
+        claims.push((namespace.clone(), elements));
+    }
+
+    // The device key of the MSO signs the DeviceAuthenticationBytes, bound to
+    // the SessionTranscript of the request.
+    let device_signed = field(document, "deviceSigned")?;
+    let device_signature = CoseSign1::parse(
+        field(field(device_signed, "deviceAuth")?, "deviceSignature")
+            .context("only deviceSignature device authentication is supported")?,
+    )?;
+    let device_key = device_key(field(field(&mso, "deviceKeyInfo")?, "deviceKey")?)?;
+    let device_authentication = device_auth::device_authentication_bytes(
+        session_transcript.clone(),
+        doc_type.clone(),
+        field(device_signed, "nameSpaces")?.clone(),
+    )?;
+    device_signature
+        .verify(&device_key, &device_authentication)
+        .context("the device signature is invalid")?;
+
+    Ok(claims)
+}
+
+/// A COSE_Sign1, with its decoded protected headers.
+struct CoseSign1<'a> {
+    protected: &'a [u8],
+    protected_headers: Cbor,
+    unprotected: &'a BTreeMap<Cbor, Cbor>,
+    payload: Option<Cbor>,
+    signature: &'a [u8],
+}
+
+impl<'a> CoseSign1<'a> {
+    fn parse(value: &'a Cbor) -> Result<Self> {
+        let value = match value {
+            Cbor::Tag(18, value) => value.as_ref(),
+            value => value,
+        };
+        let Cbor::Array(parts) = value else {
+            bail!("not a COSE_Sign1")
+        };
+        let [Cbor::Bytes(protected), Cbor::Map(unprotected), payload, Cbor::Bytes(signature)] =
+            parts.as_slice()
+        else {
+            bail!("not a COSE_Sign1")
+        };
+
+        Ok(Self {
+            protected,
+            protected_headers: match protected.is_empty() {
+                true => Cbor::Map(Default::default()),
+                false => serde_cbor::from_slice(protected)?,
+            },
+            unprotected,
+            payload: (payload != &Cbor::Null).then(|| payload.clone()),
+            signature,
+        })
+    }
+
+    fn header(&self, label: i128) -> Option<&Cbor> {
+        reader_auth::header(&self.protected_headers, self.unprotected, label)
+    }
+
+    /// Verify the ES256 signature of the COSE_Sign1 over the payload, which
+    /// may be detached.
+    fn verify(&self, key: &VerifyingKey, payload: &[u8]) -> Result<()> {
+        let algorithm = self.header(ALG_LABEL);
+        if algorithm != Some(&Cbor::Integer(ES256)) {
+            bail!("unsupported COSE algorithm: {algorithm:?}")
+        }
+        let sig_structure = serde_cbor::to_vec(&Cbor::Array(vec![
+            Cbor::Text("Signature1".into()),
+            Cbor::Bytes(self.protected.to_vec()),
+            Cbor::Bytes(vec![]),
+            Cbor::Bytes(payload.to_vec()),
+        ]))?;
+        let signature = Signature::from_slice(self.signature).map_err(|e| anyhow!("{e:?}"))?;
+        key.verify(&sig_structure, &signature)
+            .context("the signature does not match")
+    }
+}
+
+/// Return a field of a CBOR map.
+fn field<'a>(value: &'a Cbor, name: &str) -> Result<&'a Cbor> {
+    match value {
+        Cbor::Map(map) => map.get(&Cbor::Text(name.into())),
+        _ => None,
+    }
+    .with_context(|| format!("missing {name}"))
+}
+
+/// Parse a `tdate`, tagged or not.
+fn date(value: &Cbor) -> Result<SystemTime> {
+    let value = match value {
+        Cbor::Tag(0, value) => value.as_ref(),
+        value => value,
+    };
+    let Cbor::Text(date) = value else {
+        bail!("invalid date")
+    };
+    Ok(OffsetDateTime::parse(date, &Rfc3339)
+        .with_context(|| format!("invalid date {date}"))?
+        .into())
+}
+
+/// Return the P-256 verifying key of a COSE_Key.
+fn device_key(key: &Cbor) -> Result<VerifyingKey> {
+    let Cbor::Map(key) = key else {
+        bail!("the device key is not a COSE_Key")
+    };
+    let label = |label: i128| key.get(&Cbor::Integer(label));
+    // An EC2 key on the P-256 curve.
+    if label(1) != Some(&Cbor::Integer(2)) || label(-1) != Some(&Cbor::Integer(1)) {
+        bail!("the device key is not a P-256 key")
+    }
+    let (Some(Cbor::Bytes(x)), Some(Cbor::Bytes(y))) = (label(-2), label(-3)) else {
+        bail!("the device key has no coordinates")
+    };
+    VerifyingKey::from_sec1_bytes(&[&[0x04], x.as_slice(), y.as_slice()].concat())
+        .map_err(|e| anyhow!("invalid device key: {e:?}"))
+}
+
+/// Convert an element value to JSON, with byte strings base64url-encoded
+/// and tags, e.g. of full-dates, dropped.
+fn json(value: &Cbor) -> Json {
+    match value {
+        Cbor::Bool(value) => (*value).into(),
+        Cbor::Integer(value) => i64::try_from(*value)
+            .map(Json::from)
+            .unwrap_or_else(|_| value.to_string().into()),
+        Cbor::Float(value) => serde_json::Number::from_f64(*value).map_or(Json::Null, Json::Number),
+        Cbor::Bytes(value) => BASE64_URL_SAFE_NO_PAD.encode(value).into(),
+        Cbor::Text(value) => value.clone().into(),
+        Cbor::Array(values) => values.iter().map(json).collect(),
+        Cbor::Map(values) => Json::Object(
+            values
+                .iter()
+                .map(|(name, value)| match name {
+                    Cbor::Text(name) => (name.clone(), json(value)),
+                    name => (json(name).to_string(), json(value)),
+                })
+                .collect(),
+        ),
+        Cbor::Tag(_, value) => json(value),
+        _ => Json::Null,
+    }
+}
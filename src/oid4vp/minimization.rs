@@ -0,0 +1,152 @@
+//! Data minimization policies of the app, limiting the fields disclosed to
+//! verifiers beyond what the holder consents to, e.g. "never disclose
+//! `document_number` to unverified verifiers".
+//!
+//! The policies applying to the verifier of a request are enforced by the
+//! permission responses of its [PermissionRequest], which report the fields
+//! they withheld with [PermissionResponse::withheld_fields].
+
+use super::permission_request::{PermissionRequest, PermissionResponse};
+use super::verifier_review::VerifierInfo;
+use crate::common::Uuid;
+
+/// How a policy limits the disclosure of a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum MinimizationRule {
+    /// The field is only disclosed when the verifier requires it.
+    StripUnlessRequired,
+    /// The field is never disclosed, even when the verifier requires it, in
+    /// which case the verifier may refuse the response.
+    NeverDisclose,
+}
+
+/// The verifiers a policy applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum MinimizationScope {
+    AllVerifiers,
+    /// Verifiers whose client ID was not authenticated, see
+    /// [VerifierInfo::client_id_verified].
+    UnverifiedVerifiers,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct MinimizationPolicy {
+    /// The name of the field, as in [super::permission_request::RequestedField::name].
+    pub field: String,
+    pub rule: MinimizationRule,
+    pub scope: MinimizationScope,
+}
+
+/// A requested field of a selected credential that is not disclosed, because
+/// of a policy of the app or of the verifier.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct WithheldField {
+    pub credential_id: Uuid,
+    pub name: String,
+    /// Whether the verifier requires the field.
+    pub required: bool,
+}
+
+#[uniffi::export]
+impl PermissionRequest {
+    /// Return the names of the fields the policies of the app never
+    /// disclose to the verifier, even when it requires them.
+    pub fn never_disclosed_fields(&self) -> Vec<String> {
+        self.never_disclosed.clone()
+    }
+}
+
+#[uniffi::export]
+impl PermissionResponse {
+    /// Return the requested fields of the selected credentials that the
+    /// policies of the app or of the verifier withhold, for display.
+    ///
+    /// Optional fields the holder did not select are not included.
+    pub fn withheld_fields(&self) -> Vec<WithheldField> {
+        let mut withheld = vec![];
+        for credential in &self.selected_credentials {
+            for field in credential.requested_fields(&self.presentation_definition) {
+                let Some(name) = &field.name else {
+                    continue;
+                };
+                let denied = !field.required && self.denied_fields.contains(name);
+                if denied || self.never_disclosed.contains(name) {
+                    withheld.push(WithheldField {
+                        credential_id: credential.id(),
+                        name: name.clone(),
+                        required: field.required,
+                    });
+                }
+            }
+        }
+        withheld
+    }
+}
+
+/// Return the names of the fields the policies applying to the verifier
+/// strip unless required, and those they never disclose.
+pub(crate) fn applicable(
+    policies: &[MinimizationPolicy],
+    verifier: &VerifierInfo,
+) -> (Vec<String>, Vec<String>) {
+    let (mut stripped, mut never_disclosed) = (vec![], vec![]);
+    for policy in policies {
+        let applies = match policy.scope {
+            MinimizationScope::AllVerifiers => true,
+            MinimizationScope::UnverifiedVerifiers => !verifier.client_id_verified,
+        };
+        if !applies {
+            continue;
+        }
+
+        let fields = match policy.rule {
+            MinimizationRule::StripUnlessRequired => &mut stripped,
+            MinimizationRule::NeverDisclose => &mut never_disclosed,
+        };
+        if !fields.contains(&policy.field) {
+            fields.push(policy.field.clone());
+        }
+    }
+    (stripped, never_disclosed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_policies_by_verifier() {
+        let policies = [
+            MinimizationPolicy {
+                field: "document_number".into(),
+                rule: MinimizationRule::NeverDisclose,
+                scope: MinimizationScope::UnverifiedVerifiers,
+            },
+            MinimizationPolicy {
+                field: "resident_address".into(),
+                rule: MinimizationRule::StripUnlessRequired,
+                scope: MinimizationScope::AllVerifiers,
+            },
+        ];
+        let verifier = |client_id_verified| VerifierInfo {
+            client_id: "verifier.example.com".into(),
+            client_id_scheme: Some("x509_san_dns".into()),
+            response_uri: None,
+            client_id_verified,
+            client_metadata: Default::default(),
+            key_change: None,
+        };
+
+        assert_eq!(
+            applicable(&policies, &verifier(false)),
+            (
+                vec!["resident_address".to_string()],
+                vec!["document_number".to_string()]
+            )
+        );
+        assert_eq!(
+            applicable(&policies, &verifier(true)),
+            (vec!["resident_address".to_string()], vec![])
+        );
+    }
+}
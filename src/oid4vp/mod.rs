@@ -1,4 +1,51 @@
+pub mod artifact_cache;
+mod cancellation;
+pub mod claim_labels;
+pub mod dc_api;
+pub mod definition_limits;
+pub mod definition_source;
+mod denial;
+pub mod descriptors;
+pub mod draft;
 pub mod error;
+pub mod federation;
+pub mod field_constraint;
+pub mod flow_events;
 pub mod holder;
+pub mod holder_builder;
+mod iso_18013_7;
+pub mod key_attestation;
+pub(crate) mod key_binding;
+pub mod key_pinning;
+pub mod match_diagnostics;
+mod matching;
+mod mdoc_verification;
+pub mod minimization;
+pub mod parsing_mode;
 pub mod permission_request;
+mod persistence;
+mod preview;
+pub mod profile;
+pub mod ranking;
+pub mod replay;
+pub(crate) mod request;
+pub mod request_policy;
+pub mod request_signer;
+pub mod request_summary;
+mod request_uri;
+pub mod response_encryption;
+mod response_errors;
+pub mod response_policy;
+pub mod risk_analysis;
+pub mod scope;
+pub mod sessions;
+pub mod status_watch;
+pub mod streaming;
+pub mod submission_requirements;
+pub mod transaction_data;
+pub mod trusted_verifiers;
+mod validity;
 pub mod verifier;
+pub mod verifier_review;
+pub mod wallet_metadata;
+mod x509_client_id;
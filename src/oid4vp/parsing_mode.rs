@@ -0,0 +1,356 @@
+//! How strictly the presentation definitions of verifiers are parsed.
+//!
+//! Real-world verifiers send slightly non-conformant presentation
+//! definitions. In lenient mode, the deviations that can be repaired without
+//! changing what is requested are, and reported as [RequestWarning]s on the
+//! permission request. In strict mode, for conformance testing, the same
+//! deviations are errors.
+
+use super::definition_source::{self, PresentationDefinitionSource};
+use super::error::OID4VPError;
+use super::request;
+use super::scope::{self, QueryTemplate};
+
+use std::collections::HashMap;
+
+use oid4vci::oauth2::http::{header, Method, Request};
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use openid4vp::core::util::AsyncHttpClient;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as Json};
+use uniffi::deps::anyhow::{self, bail, Context};
+use uuid::Uuid;
+
+/// How strictly presentation definitions are parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, uniffi::Enum)]
+pub enum RequestParsingMode {
+    /// Parse presentation definitions as is, failing on any deviation the
+    /// parser does not accept.
+    #[default]
+    Standard,
+    /// Repair the deviations that can be repaired safely, reporting each as
+    /// a warning.
+    Lenient,
+    /// Fail on any deviation lenient mode would repair.
+    Strict,
+}
+
+/// A deviation of a presentation definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, uniffi::Enum)]
+pub enum RequestWarningKind {
+    /// The presentation definition was a JSON string rather than an object.
+    EncodedAsString,
+    /// The presentation definition or an input descriptor had no `id`, and
+    /// one was generated.
+    MissingId,
+    /// `input_descriptors` was a single descriptor rather than an array.
+    NotAnArray,
+    /// An input descriptor had no `constraints`, which were set to none.
+    MissingConstraints,
+    /// The `path` of a field was a string rather than an array.
+    PathNotAnArray,
+    /// A boolean, such as `optional` or `intent_to_retain`, was a string.
+    BooleanAsString,
+    /// `limit_disclosure` was neither `required` nor `preferred`, and was
+    /// ignored.
+    InvalidLimitDisclosure,
+}
+
+/// A deviation of a presentation definition that was repaired.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct RequestWarning {
+    pub kind: RequestWarningKind,
+    /// The JSON pointer of the deviation in the presentation definition.
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for RequestWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Return the presentation definition of a request, unparsed, and where it
+/// came from, fetching it from its `presentation_definition_uri` if it is
+/// passed by reference, or expanding the query template of its `scope` if it
+/// is passed neither way.
+pub(crate) async fn raw_presentation_definition(
+    request: &AuthorizationRequestObject,
+    client: &impl AsyncHttpClient,
+    scope_queries: &HashMap<String, QueryTemplate>,
+) -> anyhow::Result<(Json, PresentationDefinitionSource)> {
+    let mut parameters = request::parameters(request);
+    let uri = parameters
+        .get("presentation_definition_uri")
+        .and_then(Json::as_str)
+        .map(ToOwned::to_owned);
+    if let Some(definition) = parameters.remove("presentation_definition") {
+        if uri.is_some() {
+            bail!("the request has both a presentation definition and its URI")
+        }
+        return Ok((definition, PresentationDefinitionSource::Inline));
+    }
+    let Some(uri) = uri else {
+        let scope = parameters.get("scope").and_then(Json::as_str);
+        let Some((scope, definition)) =
+            scope.and_then(|scope| scope::presentation_definition(scope, scope_queries))
+        else {
+            bail!("the request has no presentation definition")
+        };
+        return Ok((definition?, PresentationDefinitionSource::Scope { scope }));
+    };
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(uri)
+        .header(header::ACCEPT, "application/json")
+        .body(vec![])
+        .context("failed to build the presentation definition request")?;
+    let response = client.execute(request).await?;
+    if !response.status().is_success() {
+        bail!("request to {uri} failed: {}", response.status());
+    }
+
+    let integrity = parameters
+        .get(definition_source::INTEGRITY_PARAMETER)
+        .and_then(Json::as_str);
+    if let Some(integrity) = integrity {
+        definition_source::check_integrity(integrity, response.body())?;
+    }
+    let definition = serde_json::from_slice(response.body())
+        .context("the presentation definition is not JSON")?;
+    Ok((
+        definition,
+        PresentationDefinitionSource::Reference {
+            uri,
+            integrity_verified: integrity.is_some(),
+        },
+    ))
+}
+
+/// Parse a presentation definition in the given mode, returning the
+/// deviations that were repaired.
+pub(crate) fn parse_presentation_definition(
+    mut definition: Json,
+    mode: RequestParsingMode,
+) -> Result<(PresentationDefinition, Vec<RequestWarning>), OID4VPError> {
+    let warnings = match mode {
+        RequestParsingMode::Standard => vec![],
+        RequestParsingMode::Lenient | RequestParsingMode::Strict => repair(&mut definition),
+    };
+    if mode == RequestParsingMode::Strict && !warnings.is_empty() {
+        let warnings = warnings.iter().map(ToString::to_string).collect::<Vec<_>>();
+        return Err(OID4VPError::PresentationDefinitionResolution {
+            reason: warnings.join("; "),
+            source: None,
+        });
+    }
+
+    let definition =
+        serde_json::from_value(definition).map_err(OID4VPError::definition_resolution)?;
+    Ok((definition, warnings))
+}
+
+/// Repair the deviations of a presentation definition, in place.
+fn repair(definition: &mut Json) -> Vec<RequestWarning> {
+    let mut warnings = vec![];
+    let mut warn = |kind, path: String, message: &str| {
+        warnings.push(RequestWarning {
+            kind,
+            path,
+            message: message.into(),
+        })
+    };
+
+    if let Some(parsed) = definition
+        .as_str()
+        .and_then(|encoded| serde_json::from_str::<Json>(encoded).ok())
+        .filter(Json::is_object)
+    {
+        *definition = parsed;
+        warn(
+            RequestWarningKind::EncodedAsString,
+            "".into(),
+            "the presentation definition is a JSON string",
+        );
+    }
+    let Some(definition) = definition.as_object_mut() else {
+        return warnings;
+    };
+
+    if !definition.contains_key("id") {
+        definition.insert("id".into(), Json::String(Uuid::new_v4().to_string()));
+        warn(
+            RequestWarningKind::MissingId,
+            "/id".into(),
+            "the presentation definition has no id",
+        );
+    }
+
+    let descriptors = definition
+        .entry("input_descriptors")
+        .or_insert_with(|| Json::Array(vec![]));
+    if descriptors.is_object() {
+        *descriptors = Json::Array(vec![descriptors.take()]);
+        warn(
+            RequestWarningKind::NotAnArray,
+            "/input_descriptors".into(),
+            "the input descriptors are not an array",
+        );
+    }
+
+    for (index, descriptor) in descriptors.as_array_mut().into_iter().flatten().enumerate() {
+        let Some(descriptor) = descriptor.as_object_mut() else {
+            continue;
+        };
+        let path = format!("/input_descriptors/{index}");
+
+        if !descriptor.contains_key("id") {
+            descriptor.insert(
+                "id".into(),
+                Json::String(format!("input_descriptor_{index}")),
+            );
+            warn(
+                RequestWarningKind::MissingId,
+                format!("{path}/id"),
+                "the input descriptor has no id",
+            );
+        }
+
+        let constraints = descriptor
+            .entry("constraints")
+            .or_insert_with(|| Json::Null);
+        if constraints.is_null() {
+            *constraints = Json::Object(Map::new());
+            warn(
+                RequestWarningKind::MissingConstraints,
+                format!("{path}/constraints"),
+                "the input descriptor has no constraints",
+            );
+        }
+        let Some(constraints) = constraints.as_object_mut() else {
+            continue;
+        };
+
+        if let Some(limit_disclosure) = constraints.get("limit_disclosure") {
+            if !matches!(limit_disclosure.as_str(), Some("required" | "preferred")) {
+                constraints.remove("limit_disclosure");
+                warn(
+                    RequestWarningKind::InvalidLimitDisclosure,
+                    format!("{path}/constraints/limit_disclosure"),
+                    "limit_disclosure is neither required nor preferred",
+                );
+            }
+        }
+
+        let fields = constraints.get_mut("fields").and_then(Json::as_array_mut);
+        for (index, field) in fields.into_iter().flatten().enumerate() {
+            let Some(field) = field.as_object_mut() else {
+                continue;
+            };
+            let path = format!("{path}/constraints/fields/{index}");
+
+            if let Some(field_path) = field.get_mut("path").filter(|path| path.is_string()) {
+                *field_path = Json::Array(vec![field_path.take()]);
+                warn(
+                    RequestWarningKind::PathNotAnArray,
+                    format!("{path}/path"),
+                    "the path of the field is not an array",
+                );
+            }
+
+            for name in ["optional", "intent_to_retain"] {
+                let Some(value) = field.get_mut(name) else {
+                    continue;
+                };
+                let repaired = match value.as_str() {
+                    Some("true") => true,
+                    Some("false") => false,
+                    _ => continue,
+                };
+                *value = Json::Bool(repaired);
+                warn(
+                    RequestWarningKind::BooleanAsString,
+                    format!("{path}/{name}"),
+                    "the boolean is a string",
+                );
+            }
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repairs_presentation_definitions() {
+        let malformed = serde_json::json!({
+            "input_descriptors": {
+                "constraints": {
+                    "limit_disclosure": "yes",
+                    "fields": [{
+                        "path": "$.credentialSubject.vehicle.vehicleIdentificationNumber",
+                        "intent_to_retain": "false",
+                    }]
+                }
+            }
+        });
+
+        assert!(
+            parse_presentation_definition(malformed.clone(), RequestParsingMode::Standard).is_err()
+        );
+        assert!(matches!(
+            parse_presentation_definition(malformed.clone(), RequestParsingMode::Strict),
+            Err(OID4VPError::PresentationDefinitionResolution { .. })
+        ));
+
+        let (definition, warnings) = parse_presentation_definition(
+            Json::String(malformed.to_string()),
+            RequestParsingMode::Lenient,
+        )
+        .unwrap();
+        assert_eq!(definition.input_descriptors()[0].id, "input_descriptor_0");
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| (warning.kind, warning.path.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (RequestWarningKind::EncodedAsString, ""),
+                (RequestWarningKind::MissingId, "/id"),
+                (RequestWarningKind::NotAnArray, "/input_descriptors"),
+                (RequestWarningKind::MissingId, "/input_descriptors/0/id"),
+                (
+                    RequestWarningKind::InvalidLimitDisclosure,
+                    "/input_descriptors/0/constraints/limit_disclosure"
+                ),
+                (
+                    RequestWarningKind::PathNotAnArray,
+                    "/input_descriptors/0/constraints/fields/0/path"
+                ),
+                (
+                    RequestWarningKind::BooleanAsString,
+                    "/input_descriptors/0/constraints/fields/0/intent_to_retain"
+                ),
+            ]
+        );
+
+        let conformant = serde_json::json!({
+            "id": "vehicle-title",
+            "input_descriptors": [{
+                "id": "vehicle-title",
+                "constraints": {
+                    "fields": [{ "path": ["$.credentialSubject.vehicle.vehicleIdentificationNumber"] }]
+                }
+            }]
+        });
+        let (_, warnings) =
+            parse_presentation_definition(conformant, RequestParsingMode::Strict).unwrap();
+        assert!(warnings.is_empty());
+    }
+}
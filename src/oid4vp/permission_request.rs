@@ -1,15 +1,50 @@
-use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use base64::prelude::*;
+use openid4vp::core::authorization_request::{
+    parameters::ResponseMode, AuthorizationRequestObject,
+};
 use openid4vp::core::presentation_definition::PresentationDefinition;
 use openid4vp::core::presentation_submission::{DescriptorMap, PresentationSubmission};
 use openid4vp::core::response::parameters::{VpToken, VpTokenItem};
 use openid4vp::core::response::{AuthorizationResponse, UnencodedAuthorizationResponse};
 
+use super::claim_labels::{self, ClaimLabelConfig};
+use super::dc_api;
+use super::definition_source::PresentationDefinitionSource;
+use super::descriptors::DescriptorCache;
+use super::draft;
+use super::error::{caused_by, ErrorSource};
+use super::federation::FederationVerifier;
+use super::iso_18013_7::{self, DcApiHandover, Oid4vpHandover};
+use super::key_attestation::{self, KeyAttestationProvider};
+use super::key_binding::{self, KeyBinding};
+use super::parsing_mode::RequestWarning;
+use super::request;
+use super::response_encryption;
+use super::risk_analysis::RiskWarning;
+use super::submission_requirements::{validate_selection, SubmissionRequirements};
+use super::transaction_data::{self, TransactionData};
+use super::verifier_review::VerifierInfo;
+use crate::clock::{self, Clock};
 use crate::common::*;
-use crate::credential::{Credential, CredentialEncodingError, ParsedCredential};
+use crate::credential::{
+    bbs_vc::BbsVcPresentationError,
+    claims::{json_leaf, ClaimLeaf},
+    disclosure, enveloped,
+    json_vc::LDP_VP_FORMAT,
+    jwt_vc::{JwtVc, JWT_VP_FORMAT},
+    mdoc::Mdoc,
+    Credential, CredentialEncodingError, ParsedCredential,
+};
+use crate::signer::{self, DeviceSigner, DeviceSignerError};
+use crate::status::{self, CredentialStatus, StatusListCache, StatusListFetcher};
+use crate::vdc_collection::VdcCollection;
 
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use uniffi::deps::anyhow;
 
 /// Type alias for mapping input descriptor ids to matching credentials
 /// stored in the VDC collection. This mapping is used to provide a
@@ -51,17 +86,123 @@ pub enum PermissionRequestError {
     /// failed to present the credential.
     #[error("Credential Presentation Error: {0}")]
     CredentialPresentation(String),
+
+    /// The selected credentials do not satisfy the submission requirements
+    /// of the presentation definition.
+    #[error("Submission requirements not met: {0}")]
+    SubmissionRequirementsNotMet(String),
 }
 
 #[derive(uniffi::Error, thiserror::Error, Debug)]
 pub enum PermissionResponseError {
-    #[error("Failed to parse JsonPath: {0}")]
-    JsonPathParse(String),
+    #[error("Failed to parse JsonPath: {reason}")]
+    JsonPathParse {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
     #[error(transparent)]
     CredentialEncoding(#[from] CredentialEncodingError),
+    #[error("A device signer is required to present a {0} credential")]
+    DeviceSignerRequired(String),
+    #[error(transparent)]
+    DeviceSigner(#[from] DeviceSignerError),
+    #[error("Authorization request is missing the parameter: {0}")]
+    MissingRequestParameter(String),
+    #[error("Failed to create mdoc presentation: {reason}")]
+    MdocPresentation {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error(transparent)]
+    BbsPresentation(#[from] BbsVcPresentationError),
+    #[error("Submission requirements not met: {0}")]
+    SubmissionRequirementsNotMet(String),
+    #[error("Failed to bind the presentation to the device key: {reason}")]
+    KeyBinding {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Failed to attest the device key: {reason}")]
+    KeyAttestation {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Failed to encode the response as JSON: {reason}")]
+    JsonEncoding {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    /// mdocs can only be presented in encrypted responses, whose `apu` conveys
+    /// the mdoc generated nonce their device authentication is bound to.
+    #[error("mdocs cannot be presented with the {0} response mode, which cannot convey the mdoc generated nonce")]
+    UnencryptedMdocResponse(String),
+}
+
+impl PermissionResponseError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::JsonPathParse { .. } => "permission_response.json_path_parse",
+            Self::CredentialEncoding(..) => "permission_response.credential_encoding",
+            Self::DeviceSignerRequired(..) => "permission_response.device_signer_required",
+            Self::DeviceSigner(e) => e.code(),
+            Self::MissingRequestParameter(..) => "permission_response.missing_request_parameter",
+            Self::MdocPresentation { .. } => "permission_response.mdoc_presentation",
+            Self::BbsPresentation(..) => "permission_response.bbs_presentation",
+            Self::SubmissionRequirementsNotMet(..) => {
+                "permission_response.submission_requirements_not_met"
+            }
+            Self::KeyBinding { .. } => "permission_response.key_binding",
+            Self::KeyAttestation { .. } => "permission_response.key_attestation",
+            Self::JsonEncoding { .. } => "permission_response.json_encoding",
+            Self::UnencryptedMdocResponse(..) => "permission_response.unencrypted_mdoc_response",
+        }
+    }
+
+    /// A JsonPath failed to parse with the error.
+    pub(crate) fn json_path_parse(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::JsonPathParse { reason, source }
+    }
+
+    /// The mdoc presentation failed to encode with the error.
+    pub(crate) fn mdoc_presentation(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::MdocPresentation { reason, source }
+    }
+
+    /// The presentation failed to bind to the device key with the error.
+    pub(crate) fn key_binding(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::KeyBinding { reason, source }
+    }
+
+    /// The device key failed to attest with the error.
+    pub(crate) fn key_attestation(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::KeyAttestation { reason, source }
+    }
+
+    /// The response failed to encode as JSON with the error.
+    pub(crate) fn json_encoding(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::JsonEncoding { reason, source }
+    }
 }
 
-#[derive(Debug, uniffi::Object)]
+/// Return the stable, machine-readable code of the error, e.g.
+/// `permission_response.submission_requirements_not_met`.
+#[uniffi::export]
+pub fn permission_response_error_code(error: PermissionResponseError) -> String {
+    error.code().to_string()
+}
+
+#[derive(Debug, Clone, uniffi::Object)]
 pub struct RequestedField {
     /// A unique ID for the requested field
     pub(crate) id: Uuid,
@@ -74,6 +215,25 @@ pub struct RequestedField {
     // being selected by the input descriptor JSON path
     // selector.
     pub(crate) raw_fields: Vec<serde_json::Value>,
+    // the locations of the `raw_fields` in the credential, where known.
+    pub(crate) pointers: Vec<Vec<String>>,
+    // the typed values of the `raw_fields`, keeping byte strings.
+    pub(crate) values: Vec<ClaimLeaf>,
+    // the JSONPath of the request selecting the `raw_fields`, where known.
+    pub(crate) path: Option<String>,
+    // the data element of mdoc fields.
+    pub(crate) mdoc_element: Option<MdocElementPath>,
+    // the JSON schema filter of the field, if any.
+    pub(crate) filter: Option<serde_json::Value>,
+    // the localized label of the field, if any.
+    pub(crate) label: Option<String>,
+}
+
+/// The namespace and identifier of an mdoc data element.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, uniffi::Record)]
+pub struct MdocElementPath {
+    pub namespace: String,
+    pub identifier: String,
 }
 
 impl<'a> From<openid4vp::core::input_descriptor::RequestedField<'a>> for RequestedField {
@@ -85,11 +245,21 @@ impl<'a> From<openid4vp::core::input_descriptor::RequestedField<'a>> for Request
             retained: value.retained,
             purpose: value.purpose,
             input_descriptor_id: value.input_descriptor_id,
+            values: value
+                .raw_fields
+                .iter()
+                .map(|field| json_leaf(field))
+                .collect(),
             raw_fields: value
                 .raw_fields
                 .into_iter()
                 .map(ToOwned::to_owned)
                 .collect(),
+            pointers: vec![],
+            path: None,
+            mdoc_element: None,
+            filter: None,
+            label: None,
         }
     }
 }
@@ -104,6 +274,15 @@ impl RequestedField {
     pub fn input_descriptor_id(&self) -> &String {
         &self.input_descriptor_id
     }
+
+    /// Fall back to the purpose of the input descriptor of the field, when
+    /// the field has none of its own.
+    pub(crate) fn with_descriptor_purpose(mut self, definition: &PresentationDefinition) -> Self {
+        if self.purpose.is_none() {
+            self.purpose = disclosure::descriptor_purpose(definition, &self.input_descriptor_id);
+        }
+        self
+    }
 }
 
 /// Public methods for the RequestedField struct.
@@ -114,6 +293,12 @@ impl RequestedField {
         self.name.clone()
     }
 
+    /// Return the human-readable label of the field, localized with the
+    /// claim labels of the holder, or else its name.
+    pub fn label(&self) -> Option<String> {
+        self.label.clone().or_else(|| self.name.clone())
+    }
+
     /// Return the field required status
     pub fn required(&self) -> bool {
         self.required
@@ -124,7 +309,8 @@ impl RequestedField {
         self.retained
     }
 
-    /// Return the purpose of the requested field.
+    /// Return why the field is requested: the purpose of the field, or else
+    /// that of its input descriptor.
     pub fn purpose(&self) -> Option<String> {
         self.purpose.clone()
     }
@@ -136,13 +322,128 @@ impl RequestedField {
             .filter_map(|value| serde_json::to_string(value).ok())
             .collect()
     }
+
+    /// Return the values of the raw fields, with byte strings such as
+    /// portraits as bytes rather than text.
+    pub fn values(&self) -> Vec<ClaimLeaf> {
+        self.values.clone()
+    }
+
+    /// Return the JSONPath of the request that selected the field, e.g.
+    /// `$.credentialSubject.address.street`.
+    ///
+    /// Unlike the name, the path does not change with the language of the
+    /// request, so it can be mapped to localized labels and icons.
+    pub fn path(&self) -> Option<String> {
+        self.path.clone()
+    }
+
+    /// Return the location of the first selected claim in the credential,
+    /// as object keys and array indices, e.g.
+    /// `["credentialSubject", "address", "street"]`.
+    pub fn claim_path(&self) -> Vec<String> {
+        self.pointers.first().cloned().unwrap_or_default()
+    }
+
+    /// Return the data element of the field, for fields of mdocs.
+    pub fn mdoc_element(&self) -> Option<MdocElementPath> {
+        self.mdoc_element.clone()
+    }
+}
+
+/// An item of the VP token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum VpTokenEntry {
+    /// The selected credential at the index, presented on its own.
+    Credential(usize),
+    /// A presentation of the selected credentials at the indices.
+    Presentation(Vec<usize>),
+}
+
+/// Construct a descriptor map for a credential nested in a presentation.
+fn nested_descriptor_map(
+    id: &str,
+    format: &str,
+    path: &str,
+    nested_format: &str,
+    nested_path: &str,
+) -> Result<DescriptorMap, PermissionResponseError> {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "format": format,
+        "path": path,
+        "path_nested": {
+            "id": id,
+            "format": nested_format,
+            "path": nested_path,
+        },
+    }))
+    .map_err(PermissionResponseError::json_path_parse)
+}
+
+/// A data element requested from an mdoc.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct RequestedElement {
+    pub namespace: String,
+    pub identifier: String,
+    /// Whether the verifier intends to store the element, rather than only
+    /// display it.
+    pub intent_to_retain: bool,
+    pub required: bool,
+}
+
+/// A credential matching a permission request, along with its status.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct CredentialWithStatus {
+    pub credential: Arc<ParsedCredential>,
+    /// The status of the credential, or `None` if it could not be checked.
+    pub status: Option<CredentialStatus>,
+    /// The time the oldest status list the status was read from was fetched
+    /// at, which is earlier than the check when it was served from the cache.
+    pub status_fetched_at: Option<SystemTime>,
 }
 
 #[derive(Debug, Clone, uniffi::Object)]
 pub struct PermissionRequest {
-    definition: PresentationDefinition,
-    credentials: Vec<Arc<ParsedCredential>>,
-    request: AuthorizationRequestObject,
+    pub(crate) definition: PresentationDefinition,
+    pub(crate) credentials: Vec<Arc<ParsedCredential>>,
+    pub(crate) request: AuthorizationRequestObject,
+    pub(crate) served_from_cache: bool,
+    pub(crate) transaction_data: Vec<TransactionData>,
+    pub(crate) denied_fields: Vec<String>,
+    /// The names of the fields the policies of the app never disclose to the
+    /// verifier.
+    pub(crate) never_disclosed: Vec<String>,
+    /// How the labels of the requested fields are localized.
+    pub(crate) claim_labels: ClaimLabelConfig,
+    pub(crate) warnings: Vec<RequestWarning>,
+    /// Where the presentation definition came from, if it was resolved from
+    /// a request.
+    pub(crate) definition_source: Option<PresentationDefinitionSource>,
+    pub(crate) status_cache: Option<Arc<StatusListCache>>,
+    /// How the status lists of the credentials are fetched and verified.
+    pub(crate) status_lists: StatusListFetcher,
+    /// The collection the credentials were matched from, if any.
+    pub(crate) vdc_collection: Option<Arc<VdcCollection>>,
+    /// The verifier resolved through its federation trust chain, if it uses
+    /// the OpenID Federation client ID scheme.
+    pub(crate) federation_verifier: Option<FederationVerifier>,
+    /// The signs of phishing the risk analysis found, if enabled.
+    pub(crate) risk_warnings: Vec<RiskWarning>,
+    /// The fingerprint of the key the request object is signed with, to be
+    /// pinned once a response is submitted.
+    pub(crate) verifier_key: Option<String>,
+    /// The input descriptors evaluated so far, see
+    /// [PermissionRequest::requested_fields_for_descriptor].
+    pub(crate) descriptor_cache: Arc<Mutex<DescriptorCache>>,
+    /// The clock of the holder, which presentations are dated with.
+    pub(crate) clock: Arc<dyn Clock>,
+    /// The leeway of the holder for clocks skewed from the one of the
+    /// verifier.
+    pub(crate) clock_leeway: Duration,
+    /// The origin of the caller, for requests received through the Digital
+    /// Credentials API.
+    pub(crate) dc_api_origin: Option<String>,
 }
 
 impl PermissionRequest {
@@ -155,8 +456,45 @@ impl PermissionRequest {
             definition,
             credentials,
             request,
+            served_from_cache: false,
+            transaction_data: vec![],
+            denied_fields: vec![],
+            never_disclosed: vec![],
+            claim_labels: ClaimLabelConfig::default(),
+            warnings: vec![],
+            definition_source: None,
+            status_cache: None,
+            status_lists: Default::default(),
+            vdc_collection: None,
+            federation_verifier: None,
+            risk_warnings: vec![],
+            verifier_key: None,
+            descriptor_cache: Default::default(),
+            clock: clock::system(),
+            clock_leeway: Duration::ZERO,
+            dc_api_origin: None,
         })
     }
+
+    /// Return the fields a definition requests from a credential, with their
+    /// localized labels.
+    pub(crate) fn labelled_fields(
+        &self,
+        credential: &Arc<ParsedCredential>,
+        definition: &PresentationDefinition,
+    ) -> Vec<Arc<RequestedField>> {
+        credential
+            .requested_fields(definition)
+            .into_iter()
+            .map(|field| {
+                let label = claim_labels::label(&self.claim_labels, credential, &field);
+                Arc::new(RequestedField {
+                    label,
+                    ..(*field).clone()
+                })
+            })
+            .collect()
+    }
 }
 
 #[uniffi::export]
@@ -167,29 +505,165 @@ impl PermissionRequest {
         self.credentials.clone()
     }
 
-    /// Return the requested fields for a given credential.
+    /// Return the verifier making the request, with the metadata it asserts
+    /// about itself, for consent screens.
+    pub fn verifier(&self) -> VerifierInfo {
+        VerifierInfo::from(&self.request)
+    }
+
+    /// Return the verifier resolved through its federation trust chain, with
+    /// the metadata of its entity configuration, if it uses the OpenID
+    /// Federation client ID scheme.
+    pub fn federation_verifier(&self) -> Option<FederationVerifier> {
+        self.federation_verifier.clone()
+    }
+
+    /// Return the signs that the request may be a phishing attempt, for
+    /// warnings on consent screens, when the risk analysis is enabled.
+    pub fn risk_warnings(&self) -> Vec<RiskWarning> {
+        self.risk_warnings.clone()
+    }
+
+    /// Return whether any verifier artifact, such as the presentation
+    /// definition, was served from the cache because fetching it failed.
+    pub fn served_from_cache(&self) -> bool {
+        self.served_from_cache
+    }
+
+    /// Return the transactions the verifier asks the holder to authorize
+    /// with the presentation, for display.
+    pub fn transaction_data(&self) -> Vec<TransactionData> {
+        self.transaction_data.clone()
+    }
+
+    /// Return the names of the optional fields the policy of the verifier
+    /// denies, which are never disclosed.
+    pub fn denied_fields(&self) -> Vec<String> {
+        self.denied_fields.clone()
+    }
+
+    /// Return the deviations of the presentation definition that were
+    /// repaired, when it was parsed in lenient mode.
+    pub fn warnings(&self) -> Vec<RequestWarning> {
+        self.warnings.clone()
+    }
+
+    /// Return where the presentation definition came from, inline or fetched
+    /// from its URI, for audits.
+    pub fn definition_source(&self) -> Option<PresentationDefinitionSource> {
+        self.definition_source.clone()
+    }
+
+    /// Return the requested fields for a given credential, with their
+    /// localized labels.
     ///
     /// NOTE: This will return only the requested fields for a given credential.
     pub fn requested_fields(&self, credential: &Arc<ParsedCredential>) -> Vec<Arc<RequestedField>> {
-        credential.requested_fields(&self.definition)
+        self.labelled_fields(credential, &self.definition)
     }
 
     /// Construct a new permission response for the given credential.
+    ///
+    /// Fails when the crypto provider cannot generate the mdoc generated
+    /// nonce of the response.
     pub fn create_permission_response(
         &self,
         selected_credentials: Vec<Arc<ParsedCredential>>,
-    ) -> Arc<PermissionResponse> {
-        Arc::new(PermissionResponse {
+    ) -> Result<Arc<PermissionResponse>, PermissionResponseError> {
+        Ok(Arc::new(PermissionResponse {
             selected_credentials,
             presentation_definition: self.definition.clone(),
             authorization_request: self.request.clone(),
-        })
+            selected_fields: None,
+            transaction_data: self.transaction_data.clone(),
+            withhold_retained: false,
+            denied_fields: self.denied_fields.clone(),
+            never_disclosed: self.never_disclosed.clone(),
+            mdoc_generated_nonce: iso_18013_7::generate_mdoc_nonce()?,
+            key_attestation_provider: None,
+            verifier_key: self.verifier_key.clone(),
+            clock: self.clock.clone(),
+            clock_leeway: self.clock_leeway,
+            dc_api_origin: self.dc_api_origin.clone(),
+        }))
+    }
+
+    /// Construct a new permission response for the given credentials,
+    /// disclosing only the chosen optional fields of each credential.
+    ///
+    /// `selected_fields` maps the id of each selected credential to the
+    /// optional fields, as returned by [PermissionRequest::requested_fields],
+    /// the holder consented to disclose. Required fields are always disclosed.
+    ///
+    /// Fails as [PermissionRequest::create_permission_response].
+    pub fn create_permission_response_with_selected_fields(
+        &self,
+        selected_credentials: Vec<Arc<ParsedCredential>>,
+        selected_fields: HashMap<Uuid, Vec<Arc<RequestedField>>>,
+    ) -> Result<Arc<PermissionResponse>, PermissionResponseError> {
+        Ok(Arc::new(PermissionResponse {
+            selected_credentials,
+            presentation_definition: self.definition.clone(),
+            authorization_request: self.request.clone(),
+            selected_fields: Some(selected_fields),
+            transaction_data: self.transaction_data.clone(),
+            withhold_retained: false,
+            denied_fields: self.denied_fields.clone(),
+            never_disclosed: self.never_disclosed.clone(),
+            mdoc_generated_nonce: iso_18013_7::generate_mdoc_nonce()?,
+            key_attestation_provider: None,
+            verifier_key: self.verifier_key.clone(),
+            clock: self.clock.clone(),
+            clock_leeway: self.clock_leeway,
+            dc_api_origin: self.dc_api_origin.clone(),
+        }))
     }
 
     /// Return the purpose of the presentation request.
     pub fn purpose(&self) -> Option<String> {
         self.definition.purpose().map(ToOwned::to_owned)
     }
+
+    /// Return the data elements requested from an mdoc, with whether the
+    /// verifier intends to retain them, or an empty list for other
+    /// credentials.
+    pub fn requested_elements(&self, credential: &Arc<ParsedCredential>) -> Vec<RequestedElement> {
+        if credential.as_mso_mdoc().is_none() {
+            return vec![];
+        }
+        iso_18013_7::element_requests(&credential.requested_fields(&self.definition))
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl PermissionRequest {
+    /// Return the filtered list of credentials that matched the presentation
+    /// definition, along with their status, so that revoked or suspended
+    /// credentials can be hidden.
+    ///
+    /// This fetches the status lists referenced by each credential with the
+    /// HTTP client of the holder, unless they are in the status list cache of
+    /// the holder, and verifies them against the issuer of the credential and
+    /// the trusted list of the holder.
+    pub async fn credentials_with_status(&self) -> Vec<CredentialWithStatus> {
+        let mut credentials = Vec::with_capacity(self.credentials.len());
+
+        for credential in self.credentials.iter() {
+            let verdict = status::try_check_status(
+                credential,
+                &self.status_lists,
+                self.status_cache.as_deref(),
+            )
+            .await;
+            credentials.push(CredentialWithStatus {
+                credential: credential.clone(),
+                status: verdict.as_ref().map(|verdict| verdict.status),
+                status_fetched_at: verdict.and_then(|verdict| verdict.fetched_at),
+            });
+        }
+
+        credentials
+    }
 }
 
 /// This struct is used to represent the response to a permission request.
@@ -203,64 +677,527 @@ pub struct PermissionResponse {
     pub selected_credentials: Vec<Arc<ParsedCredential>>,
     pub presentation_definition: PresentationDefinition,
     pub authorization_request: AuthorizationRequestObject,
-    // TODO: provide an optional internal mapping of `JsonPointer`s
-    // for selective disclosure that are selected as part of the requested fields.
+    /// The optional fields the holder consented to disclose, by credential id.
+    ///
+    /// When `None`, every requested field is disclosed.
+    pub selected_fields: Option<HashMap<Uuid, Vec<Arc<RequestedField>>>>,
+    /// The transactions authorized by the presentation.
+    pub transaction_data: Vec<TransactionData>,
+    /// Whether to withhold the fields the verifier intends to retain.
+    pub withhold_retained: bool,
+    /// The names of the optional fields that are never disclosed.
+    pub denied_fields: Vec<String>,
+    /// The names of the fields that are never disclosed, even when required.
+    pub never_disclosed: Vec<String>,
+    /// The nonce the device authentication of mdocs is bound to, along with
+    /// the request, through the OID4VP handover.
+    pub mdoc_generated_nonce: String,
+    /// Provides the attestations of the device keys, embedded in the
+    /// presentations when the verifier requests them.
+    pub(crate) key_attestation_provider: Option<Arc<dyn KeyAttestationProvider>>,
+    /// The fingerprint of the key the request object is signed with.
+    pub(crate) verifier_key: Option<String>,
+    /// The clock presentations are dated with.
+    pub(crate) clock: Arc<dyn Clock>,
+    /// The leeway of the expiry of the request, for skewed clocks.
+    pub(crate) clock_leeway: Duration,
+    /// The origin of the caller, for responses returned through the Digital
+    /// Credentials API, which their mdocs are bound to.
+    pub(crate) dc_api_origin: Option<String>,
+}
+
+#[uniffi::export]
+impl PermissionResponse {
+    /// Return a copy of the response that withholds the selectively
+    /// disclosable fields the verifier intends to retain, such as mdoc data
+    /// elements, even required ones, for holders consenting to their display
+    /// but not their storage.
+    ///
+    /// The verifier may refuse a response missing required fields.
+    pub fn withholding_retained_fields(&self) -> Arc<PermissionResponse> {
+        Arc::new(PermissionResponse {
+            withhold_retained: true,
+            ..self.clone()
+        })
+    }
+
+    /// Return the nonce the device authentication of the presented mdocs is
+    /// bound to, which the holder sends to the verifier as the `apu` of the
+    /// JWE of `direct_post.jwt` responses.
+    pub fn mdoc_generated_nonce(&self) -> String {
+        self.mdoc_generated_nonce.clone()
+    }
+
+    /// Return a copy of the response embedding the attestations of the device
+    /// keys from the provider, when the verifier requests them.
+    pub fn with_key_attestation_provider(
+        &self,
+        provider: Arc<dyn KeyAttestationProvider>,
+    ) -> Arc<PermissionResponse> {
+        Arc::new(PermissionResponse {
+            key_attestation_provider: Some(provider),
+            ..self.clone()
+        })
+    }
 }
 
 impl PermissionResponse {
+    /// Return the layout of the selected credentials in the VP token.
+    ///
+    /// JWT VCs are presented together in a single presentation, placed at the
+    /// position of the first of them. Other credentials are presented on their own.
+    pub(crate) fn vp_token_layout(&self) -> Vec<VpTokenEntry> {
+        let jwt_vcs = self
+            .selected_credentials
+            .iter()
+            .enumerate()
+            .filter(|(_, cred)| cred.as_jwt_vc().is_some())
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+
+        self.selected_credentials
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, cred)| match cred.as_jwt_vc() {
+                Some(_) => (jwt_vcs.first() == Some(&idx))
+                    .then(|| VpTokenEntry::Presentation(jwt_vcs.clone())),
+                None => Some(VpTokenEntry::Credential(idx)),
+            })
+            .collect()
+    }
+
+    /// Return the id of the input descriptor each selected credential is
+    /// presented for.
+    ///
+    /// Each selected credential is mapped to an input descriptor it satisfies. When
+    /// no such descriptor is found, the credential is assumed to correspond to the
+    /// input descriptor at the same position.
+    fn descriptor_ids(&self, requirements: &SubmissionRequirements) -> Vec<Option<String>> {
+        let descriptors = self.presentation_definition.input_descriptors();
+        requirements
+            .assign(&self.selected_credentials)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, id)| id.or_else(|| descriptors.get(idx).map(|d| d.id.to_string())))
+            .collect()
+    }
+
     // Construct a DescriptorMap for the presentation submission based on the
     // credentials returned from the VDC collection.
     pub fn create_descriptor_map(&self) -> Result<Vec<DescriptorMap>, PermissionResponseError> {
-        let is_singular = self.selected_credentials.len() == 1;
-
-        self.presentation_definition
-            .input_descriptors()
-            // TODO: It is possible for an input descriptor to have multiple credentials,
-            // in which case, it may be expected that the descriptor map will have a nested
-            // path. When creating a descriptor map, it may be better to use a mapping of input descriptor
-            // id to a list of credentials, whereby each descriptor id is mapped to a descriptor map,
-            // with a nested path for each credential it maps onto.
-            //
-            // Currently, each selected credential is provided its own descriptor map associated with
-            // the corresponding input descriptor. It is assumed that each input descriptor corresponds
-            // to a single verifiable credential.
-            .iter()
-            .zip(self.selected_credentials.iter())
-            .enumerate()
-            .map(|(idx, (descriptor, cred))| {
-                let vc_path = if is_singular {
-                    "$".to_string()
-                } else {
-                    format!("$[{idx}]")
+        let requirements = SubmissionRequirements::new(&self.presentation_definition);
+        if !requirements.requirements.is_empty() {
+            validate_selection(&self.presentation_definition, &self.selected_credentials)
+                .map_err(PermissionResponseError::SubmissionRequirementsNotMet)?;
+        }
+
+        let descriptor_ids = self.descriptor_ids(&requirements);
+
+        let layout = self.vp_token_layout();
+        let is_singular = layout.len() == 1;
+        let mut descriptor_map = vec![];
+
+        for (position, entry) in layout.into_iter().enumerate() {
+            let vp_path = if is_singular {
+                "$".to_string()
+            } else {
+                format!("$[{position}]")
+            };
+
+            match entry {
+                VpTokenEntry::Credential(idx) => {
+                    let Some(id) = &descriptor_ids[idx] else {
+                        continue;
+                    };
+                    let credential = &self.selected_credentials[idx];
+                    // JSON-LD credentials are presented in a presentation of their own.
+                    if credential.as_json_vc().is_some() || credential.as_bbs_vc().is_some() {
+                        descriptor_map.push(nested_descriptor_map(
+                            id,
+                            LDP_VP_FORMAT,
+                            &vp_path,
+                            &credential.format().to_string(),
+                            "$.verifiableCredential[0]",
+                        )?);
+                        continue;
+                    }
+                    descriptor_map.push(DescriptorMap::new(
+                        id.clone(),
+                        self.selected_credentials[idx].format().to_string().as_str(),
+                        vp_path
+                            .parse()
+                            .map_err(PermissionResponseError::json_path_parse)?,
+                    ));
+                }
+                VpTokenEntry::Presentation(indices) => {
+                    for (nested, idx) in indices.into_iter().enumerate() {
+                        let Some(id) = &descriptor_ids[idx] else {
+                            continue;
+                        };
+                        descriptor_map.push(nested_descriptor_map(
+                            id,
+                            JWT_VP_FORMAT,
+                            &vp_path,
+                            &self.selected_credentials[idx].format().to_string(),
+                            &format!("$.verifiableCredential[{nested}]"),
+                        )?);
+                    }
                 }
-                .parse()
-                .map_err(|e| PermissionResponseError::JsonPathParse(format!("{e:?}")))?;
+            }
+        }
 
-                Ok(DescriptorMap::new(
-                    descriptor.id.to_string(),
-                    cred.format().to_string().as_str(),
-                    vc_path,
-                ))
+        Ok(descriptor_map)
+    }
+
+    /// Return the fields of a selected credential that are disclosed: all of
+    /// the required fields, and the optional fields the holder consented to
+    /// that are not denied, less the retained fields when they are withheld
+    /// and the fields that are never disclosed.
+    pub(crate) fn disclosed_fields(
+        &self,
+        credential: &ParsedCredential,
+    ) -> Vec<Arc<RequestedField>> {
+        let fields = credential.requested_fields(&self.presentation_definition);
+
+        let fields = match &self.selected_fields {
+            None => fields,
+            Some(selected_fields) => {
+                let selected = selected_fields
+                    .get(&credential.id())
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+
+                fields
+                    .into_iter()
+                    .filter(|field| field.required)
+                    .chain(selected.iter().filter(|field| !field.required).cloned())
+                    .collect()
+            }
+        };
+
+        fields
+            .into_iter()
+            .filter(|field| !(self.withhold_retained && field.retained))
+            .filter(|field| field.required || !self.is_denied(field))
+            .filter(|field| {
+                !field
+                    .name
+                    .as_ref()
+                    .is_some_and(|name| self.never_disclosed.contains(name))
             })
             .collect()
     }
 
+    fn is_denied(&self, field: &RequestedField) -> bool {
+        field
+            .name
+            .as_ref()
+            .is_some_and(|name| self.denied_fields.contains(name))
+    }
+
     /// Create a VP token based on the selected credentials returned in the permission response.
-    pub fn create_vp_token(&self) -> Result<VpToken, PermissionResponseError> {
-        let tokens = self
-            .selected_credentials
-            .iter()
-            .map(|cred| cred.as_vp_token())
-            .collect::<Result<Vec<VpTokenItem>, CredentialEncodingError>>()?;
+    ///
+    /// Presenting an mdoc requires device authentication, for which the `signer` is used.
+    /// JWT VCs bound to a device key are presented in a JWT VP signed with it.
+    pub async fn create_vp_token(
+        &self,
+        signer: Option<Arc<dyn DeviceSigner>>,
+    ) -> Result<VpToken, PermissionResponseError> {
+        let mut tokens = Vec::with_capacity(self.selected_credentials.len());
+        let descriptor_ids =
+            self.descriptor_ids(&SubmissionRequirements::new(&self.presentation_definition));
+
+        for entry in self.vp_token_layout() {
+            let (cred, descriptor_id) = match entry {
+                VpTokenEntry::Credential(idx) => (
+                    &self.selected_credentials[idx],
+                    descriptor_ids[idx].as_deref(),
+                ),
+                VpTokenEntry::Presentation(indices) => {
+                    let jwt_vcs = indices
+                        .iter()
+                        .filter_map(|idx| self.selected_credentials[*idx].as_jwt_vc())
+                        .collect::<Vec<_>>();
+                    let jwt_vcs = jwt_vcs.iter().map(Arc::as_ref).collect::<Vec<_>>();
+                    // The presentation is declared as `jwt_vp_json`, so it
+                    // cannot be sent unsigned.
+                    let (Some(signer), Some(key_alias)) =
+                        (&signer, jwt_vcs.first().and_then(|vc| vc.key_alias()))
+                    else {
+                        let format = self.selected_credentials[indices[0]].format();
+                        return Err(PermissionResponseError::DeviceSignerRequired(
+                            format.to_string(),
+                        ));
+                    };
+                    tokens.push(
+                        self.create_jwt_vp_token(&jwt_vcs, signer.as_ref(), &key_alias)
+                            .await?,
+                    );
+                    continue;
+                }
+            };
+
+            // BBS credentials are presented with a proof derived for the
+            // disclosed fields only.
+            if let Some(bbs_vc) = cred.as_bbs_vc() {
+                let nonce = request::string_parameter(&self.authorization_request, "nonce")
+                    .ok_or_else(|| {
+                        PermissionResponseError::MissingRequestParameter("nonce".into())
+                    })?;
+                tokens.push(
+                    bbs_vc
+                        .as_derived_vp_token(&self.disclosed_fields(cred), &nonce)
+                        .await?,
+                );
+                continue;
+            }
+
+            let token = match cred.as_mso_mdoc() {
+                Some(mdoc) => {
+                    let signer = signer.as_ref().ok_or_else(|| {
+                        PermissionResponseError::DeviceSignerRequired(cred.format().to_string())
+                    })?;
+                    self.create_mdoc_vp_token(cred, &mdoc, signer.as_ref())
+                        .await?
+                }
+                None => match (cred.as_sd_jwt(), cred.as_dc_sd_jwt()) {
+                    // Only release the disclosures of the requested fields.
+                    (Some(sd_jwt), _) => {
+                        let token = sd_jwt.as_vp_token_with_fields(&self.disclosed_fields(cred));
+                        let token = self
+                            .bind_sd_jwt_vp_token(cred, descriptor_id, token, signer.as_deref())
+                            .await?;
+                        self.envelop_if_requested(descriptor_id, token)
+                    }
+                    (_, Some(sd_jwt)) => {
+                        let token = sd_jwt.as_vp_token_with_fields(&self.disclosed_fields(cred));
+                        self.bind_sd_jwt_vp_token(cred, descriptor_id, token, signer.as_deref())
+                            .await?
+                    }
+                    _ => cred.as_vp_token()?,
+                },
+            };
+            tokens.push(token);
+        }
 
         Ok(VpToken(tokens))
     }
 
+    /// Wrap a VCDM 2.0 SD-JWT presentation in an enveloped presentation, when
+    /// the input descriptor requests it.
+    fn envelop_if_requested(&self, descriptor_id: Option<&str>, token: VpTokenItem) -> VpTokenItem {
+        match token {
+            VpTokenItem::String(presentation)
+                if enveloped::envelope_requested(&self.presentation_definition, descriptor_id) =>
+            {
+                VpTokenItem::JsonObject(enveloped::envelop(
+                    enveloped::VP_SD_JWT_MEDIA_TYPE,
+                    &presentation,
+                ))
+            }
+            token => token,
+        }
+    }
+
+    /// Append a key binding JWT, bound to the request and to the transactions
+    /// the credential authorizes, to an SD-JWT VP token.
+    async fn bind_sd_jwt_vp_token(
+        &self,
+        credential: &ParsedCredential,
+        descriptor_id: Option<&str>,
+        token: VpTokenItem,
+        signer: Option<&dyn DeviceSigner>,
+    ) -> Result<VpTokenItem, PermissionResponseError> {
+        let presentation = match token {
+            VpTokenItem::String(presentation) => presentation,
+            token => return Ok(token),
+        };
+
+        let binding = KeyBinding {
+            audience: self.authorization_request.client_id().0.clone(),
+            nonce: request::string_parameter(&self.authorization_request, "nonce")
+                .ok_or_else(|| PermissionResponseError::MissingRequestParameter("nonce".into()))?,
+            transaction_data_hashes: self
+                .transaction_data
+                .iter()
+                .filter(|transaction| transaction.applies_to(descriptor_id))
+                .map(TransactionData::hash)
+                .collect(),
+            key_attestation: match credential.key_alias() {
+                Some(key_alias) => self.key_attestation(&key_alias).await?,
+                None => None,
+            },
+            issued_at: self.clock.now(),
+        };
+        let claims = credential.claims_as_json().unwrap_or_default();
+
+        key_binding::bind_presentation(
+            presentation,
+            &claims,
+            credential.key_alias(),
+            signer,
+            &binding,
+        )
+        .await
+        .map(VpTokenItem::String)
+    }
+
+    /// Create a JWT VP token presenting the JWT VCs, signed with the device key.
+    async fn create_jwt_vp_token(
+        &self,
+        credentials: &[&JwtVc],
+        signer: &dyn DeviceSigner,
+        key_alias: &KeyAlias,
+    ) -> Result<VpTokenItem, PermissionResponseError> {
+        let encoding_error = |e: serde_json::Error| {
+            PermissionResponseError::CredentialEncoding(CredentialEncodingError::VpToken(format!(
+                "{e:?}"
+            )))
+        };
+
+        let jwk: serde_json::Value =
+            serde_json::from_str(&signer.jwk(key_alias.clone())?).map_err(encoding_error)?;
+        let mut header = serde_json::json!({
+            "alg": signer.algorithm(key_alias.clone())?,
+            "typ": "JWT",
+            "jwk": jwk,
+        });
+        if let Some(attestation) = self.key_attestation(key_alias).await? {
+            header[key_attestation::KEY_ATTESTATION_HEADER] = attestation.into();
+        }
+
+        let issued_at = self
+            .clock
+            .now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let mut claims = serde_json::json!({
+            "aud": self.authorization_request.client_id().0,
+            "iat": issued_at,
+            "jti": format!("urn:uuid:{}", Uuid::new_v4()),
+            "vp": serde_json::to_value(JwtVc::presentation(credentials)).map_err(encoding_error)?,
+        });
+        if let Some(nonce) = request::string_parameter(&self.authorization_request, "nonce") {
+            claims["nonce"] = nonce.into();
+        }
+
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+        );
+        let signature =
+            signer::sign_raw(signer, key_alias, signing_input.as_bytes().to_vec()).await?;
+
+        Ok(VpTokenItem::String(format!(
+            "{signing_input}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(signature)
+        )))
+    }
+
+    /// Create the ISO 18013-7 DeviceResponse VP token for an mdoc.
+    async fn create_mdoc_vp_token(
+        &self,
+        credential: &ParsedCredential,
+        mdoc: &Mdoc,
+        signer: &dyn DeviceSigner,
+    ) -> Result<VpTokenItem, PermissionResponseError> {
+        let parameter = |name: &str| {
+            request::string_parameter(&self.authorization_request, name)
+                .ok_or_else(|| PermissionResponseError::MissingRequestParameter(name.into()))
+        };
+
+        let session_transcript = match &self.dc_api_origin {
+            Some(origin) => DcApiHandover {
+                origin: origin.clone(),
+                nonce: parameter("nonce")?,
+                jwk_thumbprint: response_encryption::encryption_key_thumbprint(
+                    &self.authorization_request,
+                )
+                .filter(|_| dc_api::is_encrypted(&self.authorization_request)),
+            }
+            .session_transcript()?,
+            // Draft 18 responses are posted to the `redirect_uri` instead.
+            None => Oid4vpHandover {
+                client_id: self.authorization_request.client_id().0.clone(),
+                response_uri: draft::response_endpoint(&self.authorization_request).ok_or_else(
+                    || PermissionResponseError::MissingRequestParameter("response_uri".into()),
+                )?,
+                nonce: parameter("nonce")?,
+                mdoc_generated_nonce: self.mdoc_generated_nonce.clone(),
+            }
+            .session_transcript()?,
+        };
+
+        let elements = iso_18013_7::requested_elements(&self.disclosed_fields(credential));
+
+        let device_response = iso_18013_7::device_response(
+            mdoc,
+            &elements,
+            session_transcript,
+            signer,
+            self.key_attestation(&mdoc.key_alias()).await?,
+        )
+        .await?;
+
+        Ok(VpTokenItem::String(
+            BASE64_URL_SAFE_NO_PAD.encode(device_response),
+        ))
+    }
+
+    /// Return the attestation of the device key with the alias, if the
+    /// verifier requests one and a provider is set.
+    async fn key_attestation(
+        &self,
+        key_alias: &KeyAlias,
+    ) -> Result<Option<String>, PermissionResponseError> {
+        let Some(provider) = self
+            .key_attestation_provider
+            .as_ref()
+            .filter(|_| key_attestation::requested(&self.authorization_request))
+        else {
+            return Ok(None);
+        };
+        let nonce = request::string_parameter(&self.authorization_request, "nonce")
+            .ok_or_else(|| PermissionResponseError::MissingRequestParameter("nonce".into()))?;
+
+        provider
+            .key_attestation(key_alias.clone(), nonce)
+            .await
+            .map(Some)
+            .map_err(PermissionResponseError::key_attestation)
+    }
+
     /// Return the authorization response object.
-    pub fn authorization_response(&self) -> Result<AuthorizationResponse, PermissionResponseError> {
+    ///
+    /// mdocs are only presented in `direct_post.jwt` responses, as other
+    /// response modes have no way to convey the mdoc generated nonce, or in
+    /// responses of the Digital Credentials API, bound to the origin instead.
+    pub async fn authorization_response(
+        &self,
+        signer: Option<Arc<dyn DeviceSigner>>,
+    ) -> Result<AuthorizationResponse, PermissionResponseError> {
+        let presents_mdocs = self
+            .selected_credentials
+            .iter()
+            .any(|credential| credential.as_mso_mdoc().is_some());
+        if presents_mdocs
+            && self.dc_api_origin.is_none()
+            && !matches!(
+                self.authorization_request.response_mode(),
+                ResponseMode::DirectPostJwt
+            )
+        {
+            return Err(PermissionResponseError::UnencryptedMdocResponse(
+                request::string_parameter(&self.authorization_request, "response_mode")
+                    .unwrap_or_default(),
+            ));
+        }
+
         Ok(AuthorizationResponse::Unencoded(
             UnencodedAuthorizationResponse {
-                vp_token: self.create_vp_token()?,
+                vp_token: self.create_vp_token(signer).await?,
                 presentation_submission: self.create_presentation_submission()?,
             },
         ))
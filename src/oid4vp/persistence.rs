@@ -0,0 +1,384 @@
+//! Serialization of permission requests and responses, so that apps killed
+//! between showing the consent screen and the user returning can restore the
+//! request, and submit the response later.
+//!
+//! Requests whose request object has expired cannot be restored, nor their
+//! responses submitted.
+
+use super::claim_labels::ClaimLabelConfig;
+use super::definition_source::PresentationDefinitionSource;
+use super::error::OID4VPError;
+use super::federation::FederationVerifier;
+use super::iso_18013_7;
+use super::parsing_mode::RequestWarning;
+use super::permission_request::{PermissionRequest, PermissionResponse, RequestedField};
+use super::request;
+use super::risk_analysis::RiskWarning;
+use super::transaction_data;
+use crate::clock::{self, Clock};
+use crate::credential::{Credential, ParsedCredential};
+use crate::Uuid;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+#[derive(Serialize, Deserialize)]
+struct SavedPermissionRequest {
+    definition: PresentationDefinition,
+    credentials: Vec<Credential>,
+    request: AuthorizationRequestObject,
+    served_from_cache: bool,
+    denied_fields: Vec<String>,
+    #[serde(default)]
+    never_disclosed: Vec<String>,
+    #[serde(default)]
+    claim_labels: ClaimLabelConfig,
+    #[serde(default)]
+    warnings: Vec<RequestWarning>,
+    #[serde(default)]
+    definition_source: Option<PresentationDefinitionSource>,
+    #[serde(default)]
+    federation_verifier: Option<FederationVerifier>,
+    #[serde(default)]
+    risk_warnings: Vec<RiskWarning>,
+    #[serde(default)]
+    verifier_key: Option<String>,
+    #[serde(default)]
+    clock_leeway: Duration,
+    #[serde(default)]
+    dc_api_origin: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPermissionResponse {
+    selected_credentials: Vec<Credential>,
+    presentation_definition: PresentationDefinition,
+    authorization_request: AuthorizationRequestObject,
+    selected_fields: Option<HashMap<Uuid, Vec<SavedField>>>,
+    withhold_retained: bool,
+    denied_fields: Vec<String>,
+    #[serde(default)]
+    never_disclosed: Vec<String>,
+    /// Unset in responses saved before the nonce was, which get a new one.
+    #[serde(default)]
+    mdoc_generated_nonce: Option<String>,
+    #[serde(default)]
+    verifier_key: Option<String>,
+    #[serde(default)]
+    clock_leeway: Duration,
+    #[serde(default)]
+    dc_api_origin: Option<String>,
+}
+
+/// A selected field, identified by what it selects, since the ids of the
+/// requested fields are not stable.
+#[derive(Serialize, Deserialize)]
+struct SavedField {
+    input_descriptor_id: String,
+    name: Option<String>,
+    raw_fields: Vec<Json>,
+}
+
+impl SavedField {
+    fn matches(&self, field: &RequestedField) -> bool {
+        self.input_descriptor_id == field.input_descriptor_id
+            && self.name == field.name
+            && self.raw_fields == field.raw_fields
+    }
+}
+
+impl From<&Arc<RequestedField>> for SavedField {
+    fn from(field: &Arc<RequestedField>) -> Self {
+        Self {
+            input_descriptor_id: field.input_descriptor_id.clone(),
+            name: field.name.clone(),
+            raw_fields: field.raw_fields.clone(),
+        }
+    }
+}
+
+#[uniffi::export]
+impl PermissionRequest {
+    /// Restore a permission request saved with [PermissionRequest::to_json].
+    ///
+    /// Fails with [OID4VPError::RequestExpired] when the request has expired
+    /// by the system clock, allowing for the leeway of the holder it was
+    /// created by.
+    #[uniffi::constructor]
+    pub fn from_json(json: String) -> Result<Arc<Self>, OID4VPError> {
+        Self::from_json_with_clock(json, clock::system())
+    }
+
+    /// As [PermissionRequest::from_json], with the clock of the holder, e.g.
+    /// a trusted time source.
+    #[uniffi::constructor]
+    pub fn from_json_with_clock(
+        json: String,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Arc<Self>, OID4VPError> {
+        let saved: SavedPermissionRequest =
+            serde_json::from_str(&json).map_err(OID4VPError::json_syntax_parse)?;
+        check_expiry(&saved.request, clock.now(), saved.clock_leeway)?;
+
+        Ok(Arc::new(PermissionRequest {
+            definition: saved.definition,
+            credentials: parse_credentials(saved.credentials)?,
+            transaction_data: transaction_data::from_request(&saved.request)?,
+            request: saved.request,
+            served_from_cache: saved.served_from_cache,
+            denied_fields: saved.denied_fields,
+            never_disclosed: saved.never_disclosed,
+            claim_labels: saved.claim_labels,
+            warnings: saved.warnings,
+            definition_source: saved.definition_source,
+            vdc_collection: None,
+            status_cache: None,
+            status_lists: Default::default(),
+            federation_verifier: saved.federation_verifier,
+            risk_warnings: saved.risk_warnings,
+            verifier_key: saved.verifier_key,
+            descriptor_cache: Default::default(),
+            clock,
+            clock_leeway: saved.clock_leeway,
+            dc_api_origin: saved.dc_api_origin,
+        }))
+    }
+
+    /// Serialize the permission request, to be restored with
+    /// [PermissionRequest::from_json].
+    ///
+    /// NOTE: the serialized request contains the matched credentials, and
+    /// should be stored as securely as they are.
+    pub fn to_json(&self) -> Result<String, OID4VPError> {
+        serde_json::to_string(&SavedPermissionRequest {
+            definition: self.definition.clone(),
+            credentials: generic_credentials(&self.credentials)?,
+            request: self.request.clone(),
+            served_from_cache: self.served_from_cache,
+            denied_fields: self.denied_fields.clone(),
+            never_disclosed: self.never_disclosed.clone(),
+            claim_labels: self.claim_labels.clone(),
+            warnings: self.warnings.clone(),
+            definition_source: self.definition_source.clone(),
+            federation_verifier: self.federation_verifier.clone(),
+            risk_warnings: self.risk_warnings.clone(),
+            verifier_key: self.verifier_key.clone(),
+            clock_leeway: self.clock_leeway,
+            dc_api_origin: self.dc_api_origin.clone(),
+        })
+        .map_err(OID4VPError::json_syntax_parse)
+    }
+
+    /// Return the time the request expires at, if it has an expiry.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        request::expires_at(&self.request)
+    }
+}
+
+#[uniffi::export]
+impl PermissionResponse {
+    /// Restore a permission response saved with [PermissionResponse::to_json].
+    ///
+    /// Fails with [OID4VPError::RequestExpired] when the request it responds
+    /// to has expired by the system clock, allowing for the leeway of the
+    /// holder it was created by.
+    #[uniffi::constructor]
+    pub fn from_json(json: String) -> Result<Arc<Self>, OID4VPError> {
+        Self::from_json_with_clock(json, clock::system())
+    }
+
+    /// As [PermissionResponse::from_json], with the clock of the holder, e.g.
+    /// a trusted time source.
+    #[uniffi::constructor]
+    pub fn from_json_with_clock(
+        json: String,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Arc<Self>, OID4VPError> {
+        let saved: SavedPermissionResponse =
+            serde_json::from_str(&json).map_err(OID4VPError::json_syntax_parse)?;
+        check_expiry(
+            &saved.authorization_request,
+            clock.now(),
+            saved.clock_leeway,
+        )?;
+
+        let selected_credentials = parse_credentials(saved.selected_credentials)?;
+        let selected_fields = saved.selected_fields.map(|mut selected_fields| {
+            selected_credentials
+                .iter()
+                .filter_map(|credential| {
+                    let fields = selected_fields.remove(&credential.id())?;
+                    let fields = credential
+                        .requested_fields(&saved.presentation_definition)
+                        .into_iter()
+                        .filter(|field| fields.iter().any(|saved| saved.matches(field)))
+                        .collect();
+                    Some((credential.id(), fields))
+                })
+                .collect()
+        });
+
+        Ok(Arc::new(PermissionResponse {
+            transaction_data: transaction_data::from_request(&saved.authorization_request)?,
+            selected_credentials,
+            presentation_definition: saved.presentation_definition,
+            authorization_request: saved.authorization_request,
+            selected_fields,
+            withhold_retained: saved.withhold_retained,
+            denied_fields: saved.denied_fields,
+            never_disclosed: saved.never_disclosed,
+            mdoc_generated_nonce: match saved.mdoc_generated_nonce {
+                Some(nonce) => nonce,
+                None => iso_18013_7::generate_mdoc_nonce()?,
+            },
+            key_attestation_provider: None,
+            verifier_key: saved.verifier_key,
+            clock,
+            clock_leeway: saved.clock_leeway,
+            dc_api_origin: saved.dc_api_origin,
+        }))
+    }
+
+    /// Serialize the permission response, to be restored with
+    /// [PermissionResponse::from_json] and submitted later.
+    ///
+    /// NOTE: the serialized response contains the selected credentials, and
+    /// should be stored as securely as they are.
+    pub fn to_json(&self) -> Result<String, OID4VPError> {
+        let selected_fields = self.selected_fields.as_ref().map(|selected_fields| {
+            selected_fields
+                .iter()
+                .map(|(id, fields)| (*id, fields.iter().map(SavedField::from).collect()))
+                .collect()
+        });
+
+        serde_json::to_string(&SavedPermissionResponse {
+            selected_credentials: generic_credentials(&self.selected_credentials)?,
+            presentation_definition: self.presentation_definition.clone(),
+            authorization_request: self.authorization_request.clone(),
+            selected_fields,
+            withhold_retained: self.withhold_retained,
+            denied_fields: self.denied_fields.clone(),
+            never_disclosed: self.never_disclosed.clone(),
+            mdoc_generated_nonce: Some(self.mdoc_generated_nonce.clone()),
+            verifier_key: self.verifier_key.clone(),
+            clock_leeway: self.clock_leeway,
+            dc_api_origin: self.dc_api_origin.clone(),
+        })
+        .map_err(OID4VPError::json_syntax_parse)
+    }
+}
+
+/// Fail with [OID4VPError::RequestExpired] when the request has expired at
+/// `now`, allowing for the leeway.
+pub(crate) fn check_expiry(
+    request: &AuthorizationRequestObject,
+    now: SystemTime,
+    leeway: Duration,
+) -> Result<(), OID4VPError> {
+    match request::expires_at(request) {
+        Some(expires_at) if clock::has_passed(expires_at, now, leeway) => {
+            Err(OID4VPError::RequestExpired)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn generic_credentials(
+    credentials: &[Arc<ParsedCredential>],
+) -> Result<Vec<Credential>, OID4VPError> {
+    credentials
+        .iter()
+        .map(|credential| {
+            credential
+                .into_generic_form()
+                .map_err(OID4VPError::credential_encoding)
+        })
+        .collect()
+}
+
+fn parse_credentials(
+    credentials: Vec<Credential>,
+) -> Result<Vec<Arc<ParsedCredential>>, OID4VPError> {
+    credentials
+        .iter()
+        .map(|credential| {
+            credential
+                .try_into_parsed()
+                .map_err(OID4VPError::credential_decoding)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission_request(exp: SystemTime) -> Arc<PermissionRequest> {
+        let exp = exp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let definition = serde_json::from_value(serde_json::json!({
+            "id": "definition",
+            "input_descriptors": [],
+        }))
+        .unwrap();
+        let request = serde_json::from_value(serde_json::json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+            "state": "abc",
+            "exp": exp,
+        }))
+        .unwrap();
+
+        PermissionRequest::new(definition, vec![], request)
+    }
+
+    #[test]
+    fn restores_permission_requests() {
+        let exp = SystemTime::now() + Duration::from_secs(60);
+        let permission_request = permission_request(exp);
+
+        let restored = PermissionRequest::from_json(permission_request.to_json().unwrap()).unwrap();
+        assert_eq!(
+            restored.verifier().client_id,
+            "did:web:verifier.example.com"
+        );
+        assert_eq!(
+            restored.expires_at().map(|expires_at| expires_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()),
+            Some(
+                exp.duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            )
+        );
+
+        let response = restored.create_permission_response(vec![]).unwrap();
+        let restored = PermissionResponse::from_json(response.to_json().unwrap()).unwrap();
+        assert!(restored.selected_fields.is_none());
+
+        let expired = permission_request(SystemTime::now() - Duration::from_secs(60));
+        assert!(matches!(
+            PermissionRequest::from_json(expired.to_json().unwrap()),
+            Err(OID4VPError::RequestExpired)
+        ));
+        let response = expired.create_permission_response(vec![]).unwrap();
+        assert!(matches!(
+            PermissionResponse::from_json(response.to_json().unwrap()),
+            Err(OID4VPError::RequestExpired)
+        ));
+    }
+}
@@ -0,0 +1,165 @@
+//! Dry runs of presentations, returning what the submission of a permission
+//! response would send to the verifier without sending it, so that QA and
+//! security teams can inspect what leaves the device.
+//!
+//! NOTE: the preview is the plaintext of the response, before any encryption
+//! of `direct_post.jwt` responses. Every preview is created afresh, so the
+//! presentation submission ID, the salts of the signatures and the
+//! `mdoc_generated_nonce` bound ones differ from those of the submission.
+
+use super::permission_request::{PermissionResponse, PermissionResponseError};
+use crate::common::KeyAlias;
+use crate::signer::{DeviceSigner, DeviceSignerError};
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value as Json;
+
+/// The parameters of the authorization response a permission response would
+/// be submitted with.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct VpTokenPreview {
+    /// The `vp_token` parameter, as sent: string tokens as is, and the others
+    /// as JSON.
+    pub vp_token: String,
+    /// The `presentation_submission` parameter, as JSON.
+    pub presentation_submission: String,
+    /// Whether the signatures of the device keys are stubbed.
+    pub stubbed_signatures: bool,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl PermissionResponse {
+    /// Return the `vp_token` and `presentation_submission` the response would
+    /// be submitted with.
+    ///
+    /// With `stub_signatures`, the signer only provides the algorithms and
+    /// public keys of the device keys, and signatures are placeholders, so
+    /// that previews do not prompt the user for biometrics. The signer is
+    /// still required wherever the submission requires it, e.g. for mdocs.
+    pub async fn preview_vp_token(
+        &self,
+        signer: Option<Arc<dyn DeviceSigner>>,
+        stub_signatures: bool,
+    ) -> Result<VpTokenPreview, PermissionResponseError> {
+        let stubbed_signatures = stub_signatures && signer.is_some();
+        let signer = match signer {
+            Some(signer) if stub_signatures => {
+                Some(Arc::new(StubSigner(signer)) as Arc<dyn DeviceSigner>)
+            }
+            signer => signer,
+        };
+
+        let vp_token = self.create_vp_token(signer).await?;
+        let presentation_submission = self.create_presentation_submission()?;
+
+        let vp_token = match serde_json::to_value(&vp_token)
+            .map_err(PermissionResponseError::json_encoding)?
+        {
+            Json::String(vp_token) => vp_token,
+            vp_token => vp_token.to_string(),
+        };
+        Ok(VpTokenPreview {
+            vp_token,
+            presentation_submission: serde_json::to_string(&presentation_submission)
+                .map_err(PermissionResponseError::json_encoding)?,
+            stubbed_signatures,
+        })
+    }
+}
+
+/// A signer reading the keys from another signer, and signing with
+/// placeholder signatures.
+#[derive(Debug)]
+struct StubSigner(Arc<dyn DeviceSigner>);
+
+#[async_trait]
+impl DeviceSigner for StubSigner {
+    fn algorithm(&self, key_alias: KeyAlias) -> Result<String, DeviceSignerError> {
+        self.0.algorithm(key_alias)
+    }
+
+    fn jwk(&self, key_alias: KeyAlias) -> Result<String, DeviceSignerError> {
+        self.0.jwk(key_alias)
+    }
+
+    async fn sign(
+        &self,
+        _key_alias: KeyAlias,
+        _payload: Vec<u8>,
+    ) -> Result<Vec<u8>, DeviceSignerError> {
+        // A well-formed P-256 signature, in the raw `r || s` form, that no
+        // verifier accepts.
+        Ok(vec![1; 64])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{jwt_vc::tests::jwt_vc, ParsedCredential};
+    use crate::oid4vp::permission_request::PermissionRequest;
+
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn previews_the_response() {
+        let definition = serde_json::from_value(json!({
+            "id": "definition",
+            "input_descriptors": [],
+        }))
+        .unwrap();
+        let request = serde_json::from_value(json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+        }))
+        .unwrap();
+        let response = PermissionRequest::new(definition, vec![], request)
+            .create_permission_response(vec![])
+            .unwrap();
+
+        let preview = response.preview_vp_token(None, true).await.unwrap();
+        let presentation_submission: Json =
+            serde_json::from_str(&preview.presentation_submission).unwrap();
+        assert_eq!(presentation_submission["definition_id"], "definition");
+        assert_eq!(presentation_submission["descriptor_map"], json!([]));
+        assert!(!preview.stubbed_signatures);
+    }
+
+    #[tokio::test]
+    async fn requires_a_signer_for_jwt_vps() {
+        let definition = serde_json::from_value(json!({
+            "id": "definition",
+            "input_descriptors": [{
+                "id": "degree",
+                "constraints": {
+                    "fields": [{ "path": ["$.credentialSubject.degree"] }],
+                },
+            }],
+        }))
+        .unwrap();
+        let request = serde_json::from_value(json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+        }))
+        .unwrap();
+        let credential = ParsedCredential::new_jwt_vc_json(jwt_vc());
+        let response = PermissionRequest::new(definition, vec![credential.clone()], request)
+            .create_permission_response(vec![credential])
+            .unwrap();
+
+        // The presentation is declared as `jwt_vp_json`, and cannot be sent
+        // unsigned.
+        assert!(matches!(
+            response.preview_vp_token(None, false).await,
+            Err(PermissionResponseError::DeviceSignerRequired(_))
+        ));
+    }
+}
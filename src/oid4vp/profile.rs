@@ -0,0 +1,168 @@
+//! Interoperability profiles constraining the requests the holder accepts.
+//!
+//! In [Profile::Haip], requests must conform to the OpenID4VC High Assurance
+//! Interoperability Profile, as required of EUDI wallets: signed request
+//! objects of `x509_san_dns` or `x509_hash` verifiers, encrypted
+//! `direct_post.jwt` or `dc_api.jwt` responses, and SD-JWT VCs or mdocs only.
+
+use super::dc_api;
+use super::draft;
+use super::error::OID4VPError;
+use super::request_policy;
+use crate::credential::CredentialFormat;
+
+use openid4vp::core::authorization_request::{
+    parameters::ResponseMode, AuthorizationRequestObject,
+};
+
+/// The algorithms HAIP request objects may be signed with.
+const HAIP_ALGORITHMS: &[&str] = &["ES256"];
+/// The client ID schemes of HAIP verifiers.
+const HAIP_CLIENT_ID_SCHEMES: &[&str] = &["x509_san_dns", "x509_hash"];
+/// The credential formats HAIP verifiers may request.
+const HAIP_FORMATS: &[CredentialFormat] = &[CredentialFormat::DcSdJwt, CredentialFormat::MsoMdoc];
+
+/// The interoperability profile requests must conform to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, uniffi::Enum)]
+pub enum Profile {
+    /// Accept any request the holder supports.
+    #[default]
+    Default,
+    /// Only accept requests conforming to the High Assurance
+    /// Interoperability Profile.
+    Haip,
+}
+
+impl Profile {
+    /// Check an authorization request, and its request object, against the
+    /// profile.
+    pub(crate) fn check_request(
+        &self,
+        request_object: Option<&str>,
+        request: &AuthorizationRequestObject,
+    ) -> Result<(), OID4VPError> {
+        if *self == Profile::Default {
+            return Ok(());
+        }
+
+        let Some(request_object) = request_object else {
+            return Err(out_of_profile("requests must be signed request objects"));
+        };
+        let algorithm = request_policy::request_object_algorithm(request_object)?;
+        if !HAIP_ALGORITHMS.contains(&algorithm.as_str()) {
+            return Err(out_of_profile(format!(
+                "request objects must be signed with ES256, not {algorithm}"
+            )));
+        }
+
+        let scheme = draft::client_id_scheme(request);
+        if !scheme
+            .as_deref()
+            .is_some_and(|scheme| HAIP_CLIENT_ID_SCHEMES.contains(&scheme))
+        {
+            return Err(out_of_profile(format!(
+                "verifiers must use the x509_san_dns or x509_hash client ID scheme, not {}",
+                scheme.as_deref().unwrap_or("none")
+            )));
+        }
+
+        if !matches!(request.response_mode(), ResponseMode::DirectPostJwt)
+            && !dc_api::is_encrypted(request)
+        {
+            return Err(out_of_profile(
+                "responses must be encrypted with the direct_post.jwt or dc_api.jwt response mode",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check the credential formats a presentation definition requests.
+    pub(crate) fn check_formats(&self, formats: &[CredentialFormat]) -> Result<(), OID4VPError> {
+        match formats.iter().find(|format| !self.allows_format(format)) {
+            Some(format) => Err(out_of_profile(format!(
+                "credentials must be SD-JWT VCs or mdocs, not {format}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether credentials of the format can be presented in the profile.
+    pub(crate) fn allows_format(&self, format: &CredentialFormat) -> bool {
+        match self {
+            Profile::Default => true,
+            Profile::Haip => HAIP_FORMATS.contains(format),
+        }
+    }
+}
+
+fn out_of_profile(reason: impl Into<String>) -> OID4VPError {
+    OID4VPError::OutOfProfile(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base64::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn enforces_haip() {
+        let jwt = |alg: &str| {
+            format!(
+                "{}.{}.",
+                BASE64_URL_SAFE_NO_PAD.encode(json!({ "alg": alg }).to_string()),
+                BASE64_URL_SAFE_NO_PAD.encode("{}"),
+            )
+        };
+        let request = |client_id: &str, response_mode: &str| -> AuthorizationRequestObject {
+            serde_json::from_value(json!({
+                "client_id": client_id,
+                "response_type": "vp_token",
+                "response_mode": response_mode,
+                "response_uri": "https://verifier.example.com/response",
+                "nonce": "n-0S6_WzA2Mj",
+            }))
+            .unwrap()
+        };
+
+        let conformant = request("x509_san_dns:verifier.example.com", "direct_post.jwt");
+        assert!(Profile::Default.check_request(None, &conformant).is_ok());
+        assert!(Profile::Haip
+            .check_request(Some(&jwt("ES256")), &conformant)
+            .is_ok());
+        assert!(Profile::Haip
+            .check_request(Some(&jwt("ES256")), &request("x509_hash:abc", "dc_api.jwt"))
+            .is_ok());
+
+        for (request_object, request) in [
+            (None, conformant.clone()),
+            (Some(jwt("EdDSA")), conformant.clone()),
+            (
+                Some(jwt("ES256")),
+                request("did:web:verifier.example.com", "direct_post.jwt"),
+            ),
+            (
+                Some(jwt("ES256")),
+                request("x509_san_dns:verifier.example.com", "direct_post"),
+            ),
+        ] {
+            assert!(matches!(
+                Profile::Haip.check_request(request_object.as_deref(), &request),
+                Err(OID4VPError::OutOfProfile(_))
+            ));
+        }
+
+        assert!(Profile::Haip
+            .check_formats(&[CredentialFormat::MsoMdoc, CredentialFormat::DcSdJwt])
+            .is_ok());
+        assert!(matches!(
+            Profile::Haip.check_formats(&[CredentialFormat::LdpVc]),
+            Err(OID4VPError::OutOfProfile(_))
+        ));
+        assert!(Profile::Default
+            .check_formats(&[CredentialFormat::LdpVc])
+            .is_ok());
+    }
+}
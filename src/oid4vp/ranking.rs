@@ -0,0 +1,223 @@
+//! Ranking of the credentials matching a request, so that apps can preselect
+//! a sensible default in their credential pickers.
+//!
+//! Credentials are ranked, best first, by:
+//! - whether they disclose only the requested fields, rather than all their
+//!   claims;
+//! - the number of fields that must be disclosed;
+//! - whether they remain valid beyond [NEAR_EXPIRY];
+//! - whether they were presented to the verifier before;
+//! - their issuance date, most recent first.
+
+use super::permission_request::PermissionRequest;
+use crate::credential::{CredentialFormat, ParsedCredential};
+use crate::presentation_log::PresentationLog;
+
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use uniffi::deps::log;
+
+/// The remaining validity under which a credential is near expiry.
+pub const NEAR_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A credential matching a request, with the factors of its rank.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct RankedCredential {
+    pub credential: Arc<ParsedCredential>,
+    /// Whether only the requested fields are disclosed, rather than every
+    /// claim of the credential.
+    pub selective_disclosure: bool,
+    /// The number of requested fields that must be disclosed.
+    pub required_fields: u32,
+    /// Whether the credential has expired, or expires within [NEAR_EXPIRY],
+    /// by the clock of the holder.
+    pub near_expiry: bool,
+    /// Whether the credential was presented to the verifier before,
+    /// according to the presentation log.
+    pub previously_used: bool,
+    pub issued_at: Option<SystemTime>,
+}
+
+#[uniffi::export]
+impl PermissionRequest {
+    /// Return the matched credentials, best first, or only those matching an
+    /// input descriptor when its ID is given.
+    ///
+    /// The presentation log, if any, tells which credentials were presented
+    /// to the verifier before.
+    ///
+    /// NOTE: credentials matching an input descriptor without fields are not
+    /// known to match it, and are only ranked when no ID is given.
+    pub fn ranked_credentials(
+        &self,
+        input_descriptor_id: Option<String>,
+        presentation_log: Option<Arc<PresentationLog>>,
+    ) -> Vec<RankedCredential> {
+        let client_id = &self.request.client_id().0;
+        let previously_used = match presentation_log.map(|log| log.all()).transpose() {
+            Ok(records) => records
+                .into_iter()
+                .flatten()
+                .filter(|record| &record.verifier.client_id == client_id)
+                .flat_map(|record| record.credentials)
+                .map(|credential| credential.credential_id)
+                .collect(),
+            Err(e) => {
+                log::warn!("Failed to read the presentation log: {e:?}");
+                vec![]
+            }
+        };
+
+        let now = self.clock.now();
+        let mut ranked = vec![];
+        for credential in &self.credentials {
+            let fields = credential
+                .requested_fields(&self.definition)
+                .into_iter()
+                .filter(|field| {
+                    input_descriptor_id
+                        .as_ref()
+                        .is_none_or(|id| field.input_descriptor_id() == id)
+                })
+                .collect::<Vec<_>>();
+            if input_descriptor_id.is_some() && fields.is_empty() {
+                continue;
+            }
+
+            let validity = credential.validity();
+            ranked.push(RankedCredential {
+                credential: credential.clone(),
+                selective_disclosure: matches!(
+                    credential.format(),
+                    CredentialFormat::MsoMdoc
+                        | CredentialFormat::VCDM2SdJwt
+                        | CredentialFormat::DcSdJwt
+                ),
+                required_fields: fields.iter().filter(|field| field.required()).count() as u32,
+                near_expiry: validity
+                    .expires_at
+                    .is_some_and(|expires_at| expires_at <= now + NEAR_EXPIRY),
+                previously_used: previously_used.contains(&credential.id()),
+                issued_at: validity.not_before,
+            });
+        }
+
+        rank(&mut ranked);
+        ranked
+    }
+}
+
+/// Sort credentials, best first.
+fn rank(credentials: &mut [RankedCredential]) {
+    credentials.sort_by_key(|credential| {
+        (
+            !credential.selective_disclosure,
+            credential.required_fields,
+            credential.near_expiry,
+            !credential.previously_used,
+            Reverse(credential.issued_at),
+        )
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+    use crate::credential::json_vc::JsonVc;
+    use crate::local_store::LocalStore;
+    use crate::oid4vp::verifier_review::VerifierInfo;
+    use crate::presentation_log::{PresentationOutcome, PresentationRecord, PresentedCredential};
+
+    use serde_json::json;
+
+    fn credential(issued_at: &str, expires_at: &str) -> Arc<ParsedCredential> {
+        ParsedCredential::new_ldp_vc(
+            JsonVc::new_from_json(
+                json!({
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiableCredential", "IdentityCredential"],
+                    "issuer": "did:example:issuer",
+                    "issuanceDate": issued_at,
+                    "expirationDate": expires_at,
+                    "credentialSubject": {
+                        "id": "did:example:holder",
+                        "birthdate": "1990-01-01",
+                    },
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn ranks_credentials() {
+        let older = credential("2020-01-01T00:00:00Z", "2100-01-01T00:00:00Z");
+        let newer = credential("2024-01-01T00:00:00Z", "2100-01-01T00:00:00Z");
+        let used = credential("2010-01-01T00:00:00Z", "2100-01-01T00:00:00Z");
+        let expired = credential("2025-01-01T00:00:00Z", "2020-01-01T00:00:00Z");
+
+        let definition = serde_json::from_value(json!({
+            "id": "identity",
+            "input_descriptors": [{
+                "id": "identity",
+                "constraints": {
+                    "fields": [{ "path": ["$.credentialSubject.birthdate"] }],
+                },
+            }],
+        }))
+        .unwrap();
+        let request = serde_json::from_value(json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+        }))
+        .unwrap();
+        let permission_request = PermissionRequest::new(
+            definition,
+            vec![older.clone(), expired.clone(), used.clone(), newer.clone()],
+            request,
+        );
+
+        let log = PresentationLog::new(Arc::new(LocalStore::new()));
+        log.add(PresentationRecord {
+            id: Uuid::new_v4(),
+            verifier: VerifierInfo {
+                client_id: "did:web:verifier.example.com".into(),
+                client_id_scheme: None,
+                response_uri: None,
+                client_id_verified: true,
+                client_metadata: Default::default(),
+                key_change: None,
+            },
+            credentials: vec![PresentedCredential {
+                credential_id: used.id(),
+                credential_type: used.r#type(),
+                disclosed_fields: vec!["birthdate".into()],
+            }],
+            timestamp: SystemTime::now(),
+            outcome: PresentationOutcome::Submitted,
+            error: None,
+            receipt: None,
+        })
+        .unwrap();
+
+        let ranked = permission_request.ranked_credentials(Some("identity".into()), Some(log));
+        assert_eq!(
+            ranked
+                .iter()
+                .map(|ranked| ranked.credential.id())
+                .collect::<Vec<_>>(),
+            [used.id(), newer.id(), older.id(), expired.id()]
+        );
+        assert!(ranked[3].near_expiry);
+        assert!(permission_request
+            .ranked_credentials(Some("other".into()), None)
+            .is_empty());
+    }
+}
@@ -0,0 +1,196 @@
+//! Replay protection for authorization requests.
+//!
+//! The `nonce` and `state` values of the requests the holder accepts are
+//! recorded in the [RequestReplayGuard], so that a request presented again,
+//! e.g. an old QR code re-submitted by an attacker, is refused.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::common::*;
+use crate::storage_manager::*;
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Internal prefix for replay guard keys.
+const KEY_PREFIX: &str = "RequestReplay.";
+
+#[derive(Error, Debug, uniffi::Error)]
+pub enum RequestReplayError {
+    /// The request has already been seen.
+    #[error("The request was already received: {0}")]
+    Replayed(String),
+
+    /// Attempting to write the record to storage failed.
+    #[error("Failed to Write to Storage")]
+    StoreFailed(StorageManagerError),
+
+    /// Attempting to read the record from storage failed.
+    #[error("Failed to Read from Storage")]
+    LoadFailed(StorageManagerError),
+
+    /// Attempting to delete a record from storage failed.
+    #[error("Failed to Delete from Storage")]
+    DeleteFailed(StorageManagerError),
+}
+
+impl RequestReplayError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Replayed(..) => "oid4vp.request_replayed",
+            Self::StoreFailed(..) => "oid4vp.replay_store_failed",
+            Self::LoadFailed(..) => "oid4vp.replay_load_failed",
+            Self::DeleteFailed(..) => "oid4vp.replay_delete_failed",
+        }
+    }
+}
+
+/// Request Replay Guard
+///
+/// A store of the `nonce` and `state` values of the received authorization
+/// requests, persisted in the storage manager. Values are remembered for the
+/// TTL, which should exceed the lifetime of the requests.
+#[derive(Debug, uniffi::Object)]
+pub struct RequestReplayGuard {
+    storage: Arc<dyn StorageManagerInterface>,
+    ttl: Duration,
+}
+
+#[uniffi::export]
+impl RequestReplayGuard {
+    #[uniffi::constructor]
+    pub fn new(engine: Arc<dyn StorageManagerInterface>, ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            storage: engine,
+            ttl,
+        })
+    }
+}
+
+impl RequestReplayGuard {
+    /// Fail if the `nonce` or `state` of a request of the verifier was already
+    /// seen within the TTL at `now`, without recording them.
+    pub(crate) fn check(
+        &self,
+        client_id: &str,
+        nonce: Option<&str>,
+        state: Option<&str>,
+        now: SystemTime,
+    ) -> Result<(), RequestReplayError> {
+        for (name, key) in Self::keys(client_id, nonce, state) {
+            if self
+                .seen_at(&key)?
+                .is_some_and(|seen| self.fresh(seen, now))
+            {
+                return Err(RequestReplayError::Replayed(name.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the `nonce` and `state` of an accepted request of the verifier,
+    /// as seen at `now`.
+    pub(crate) fn record(
+        &self,
+        client_id: &str,
+        nonce: Option<&str>,
+        state: Option<&str>,
+        now: SystemTime,
+    ) -> Result<(), RequestReplayError> {
+        let value = serde_cbor::to_vec(&now)
+            .map_err(|_| RequestReplayError::StoreFailed(StorageManagerError::InternalError))?;
+        for (_, key) in Self::keys(client_id, nonce, state) {
+            self.storage
+                .add(key, Value(value.clone()))
+                .map_err(RequestReplayError::StoreFailed)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the values that are older than the TTL at `now`.
+    pub(crate) fn purge_expired(&self, now: SystemTime) -> Result<(), RequestReplayError> {
+        for key in self
+            .storage
+            .list()
+            .map_err(RequestReplayError::LoadFailed)?
+        {
+            if !key.0.starts_with(KEY_PREFIX) {
+                continue;
+            }
+            if self
+                .seen_at(&key)?
+                .is_some_and(|seen| !self.fresh(seen, now))
+            {
+                self.storage
+                    .remove(key)
+                    .map_err(RequestReplayError::DeleteFailed)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn keys(client_id: &str, nonce: Option<&str>, state: Option<&str>) -> Vec<(&'static str, Key)> {
+        [("nonce", nonce), ("state", state)]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, Self::key(client_id, name, value?))))
+            .collect()
+    }
+
+    /// Return the time a value was seen at, if it was.
+    fn seen_at(&self, key: &Key) -> Result<Option<SystemTime>, RequestReplayError> {
+        Ok(self
+            .storage
+            .get(key.clone())
+            .map_err(RequestReplayError::LoadFailed)?
+            .and_then(|raw| serde_cbor::from_slice(&raw.0).ok()))
+    }
+
+    fn fresh(&self, seen: SystemTime, now: SystemTime) -> bool {
+        now.duration_since(seen).is_ok_and(|age| age <= self.ttl)
+    }
+
+    /// Values are hashed, as they are chosen by the verifier.
+    fn key(client_id: &str, name: &str, value: &str) -> Key {
+        let digest = Sha256::digest(format!("{client_id}\n{name}\n{value}"));
+        Key(format!("{KEY_PREFIX}{}", hex::encode(digest)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+
+    #[test]
+    fn rejects_replayed_requests() {
+        let guard = RequestReplayGuard::new(Arc::new(LocalStore::new()), Duration::from_secs(60));
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        // Looking a request up does not record it.
+        guard.check("verifier", Some("a"), Some("s"), now).unwrap();
+        guard.check("verifier", Some("a"), Some("s"), now).unwrap();
+
+        guard.record("verifier", Some("a"), Some("s"), now).unwrap();
+        assert!(matches!(
+            guard.check("verifier", Some("a"), None, now),
+            Err(RequestReplayError::Replayed(_))
+        ));
+        assert!(matches!(
+            guard.check("verifier", Some("b"), Some("s"), now),
+            Err(RequestReplayError::Replayed(_))
+        ));
+
+        // Values are scoped to the verifier.
+        guard.check("other", Some("a"), Some("s"), now).unwrap();
+
+        // Values are forgotten after the TTL, and purged by the given clock.
+        let later = now + Duration::from_secs(61);
+        guard.check("verifier", Some("a"), None, later).unwrap();
+        guard.purge_expired(now).unwrap();
+        assert_eq!(guard.storage.list().unwrap().len(), 2);
+        guard.purge_expired(later).unwrap();
+        assert!(guard.storage.list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,47 @@
+//! Internal helpers for reading authorization request parameters that are not
+//! exposed as typed accessors on [AuthorizationRequestObject], and the JWTs
+//! they are carried in.
+
+use std::time::{Duration, SystemTime};
+
+use base64::prelude::*;
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use serde_json::{Map, Value as Json};
+
+/// Return all of the parameters of the authorization request as a JSON object.
+pub(crate) fn parameters(request: &AuthorizationRequestObject) -> Map<String, Json> {
+    match serde_json::to_value(request) {
+        Ok(Json::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Return a string parameter of the authorization request.
+pub(crate) fn string_parameter(request: &AuthorizationRequestObject, name: &str) -> Option<String> {
+    parameters(request)
+        .get(name)
+        .and_then(Json::as_str)
+        .map(ToOwned::to_owned)
+}
+
+/// Decode the header, at index 0, or the claims, at index 1, of a compact
+/// JWT, e.g. of a request object.
+///
+/// The signature is not verified: that of request objects is verified when
+/// the request is validated.
+pub(crate) fn jwt_part(jwt: &str, index: usize) -> Option<Json> {
+    let part = jwt.split('.').nth(index)?;
+    serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(part).ok()?).ok()
+}
+
+/// Return the expiry of the authorization request, from the `exp` claim of
+/// its request object.
+pub(crate) fn expires_at(request: &AuthorizationRequestObject) -> Option<SystemTime> {
+    time_claim(request, "exp")
+}
+
+/// Return a time claim of the request object, such as `nbf` or `iat`.
+pub(crate) fn time_claim(request: &AuthorizationRequestObject, name: &str) -> Option<SystemTime> {
+    let time = parameters(request).get(name).and_then(Json::as_f64)?;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(time).ok()?)
+}
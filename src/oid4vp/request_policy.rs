@@ -0,0 +1,144 @@
+//! The policy authorization requests must meet before they are validated,
+//! such as being signed request objects (JAR) with an allowed algorithm.
+
+use super::error::OID4VPError;
+use super::request;
+use super::wallet_metadata::SUPPORTED_REQUEST_ALGORITHMS;
+
+/// The default maximum size of a request, in bytes.
+pub const DEFAULT_MAX_REQUEST_SIZE: u32 = 64 * 1024;
+
+/// The policy authorization requests must meet.
+///
+/// The default policy accepts unsigned requests, and request objects signed
+/// with any algorithm the holder supports.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct RequestObjectPolicy {
+    /// Refuse requests that are not signed request objects, whether passed as
+    /// URL parameters or signed with `none`.
+    pub require_signed: bool,
+    /// The algorithms request objects may be signed with, e.g. `ES256`.
+    /// Empty allows every algorithm the holder supports.
+    pub allowed_algorithms: Vec<String>,
+    /// The maximum size, in bytes, of the request objects, and of the
+    /// requests passed by value in the URL, defaulting to
+    /// [DEFAULT_MAX_REQUEST_SIZE].
+    pub max_request_size: Option<u32>,
+}
+
+impl RequestObjectPolicy {
+    /// Check the request object of an authorization request, if it has one.
+    pub(crate) fn check(&self, request_object: Option<&str>) -> Result<(), OID4VPError> {
+        let Some(request_object) = request_object else {
+            return match self.require_signed {
+                true => Err(OID4VPError::UnsignedRequestObject),
+                false => Ok(()),
+            };
+        };
+
+        let algorithm = request_object_algorithm(request_object)?;
+        let algorithm = algorithm.as_str();
+
+        match algorithm {
+            "none" if self.require_signed => Err(OID4VPError::UnsignedRequestObject),
+            "none" => Ok(()),
+            // Symmetric algorithms cannot authenticate the verifier.
+            _ if is_weak_algorithm(algorithm) => Err(OID4VPError::WeakRequestAlgorithm {
+                algorithm: algorithm.into(),
+            }),
+            _ if !self.allows(algorithm) => Err(OID4VPError::DisallowedRequestAlgorithm {
+                algorithm: algorithm.into(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the size of a request passed by value, or of a request object.
+    pub(crate) fn check_size(&self, request: &str) -> Result<(), OID4VPError> {
+        let max_size = self.max_request_size.unwrap_or(DEFAULT_MAX_REQUEST_SIZE);
+        match request.len() > max_size as usize {
+            true => Err(OID4VPError::RequestTooLarge { max_size }),
+            false => Ok(()),
+        }
+    }
+
+    fn allows(&self, algorithm: &str) -> bool {
+        match self.allowed_algorithms.is_empty() {
+            true => SUPPORTED_REQUEST_ALGORITHMS.contains(&algorithm),
+            false => self.allowed_algorithms.iter().any(|alg| alg == algorithm),
+        }
+    }
+}
+
+/// Read the `alg` of the header of a request object, empty when it has none.
+pub(crate) fn request_object_algorithm(request_object: &str) -> Result<String, OID4VPError> {
+    let header =
+        request::jwt_part(request_object, 0).ok_or_else(|| OID4VPError::RequestValidation {
+            reason: "the request object is not a JWT".into(),
+            source: None,
+        })?;
+    Ok(header["alg"].as_str().unwrap_or_default().into())
+}
+
+fn is_weak_algorithm(algorithm: &str) -> bool {
+    algorithm.is_empty() || algorithm.starts_with("HS")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn enforces_request_object_policies() {
+        let jwt = |alg: &str| {
+            format!(
+                "{}.{}.",
+                BASE64_URL_SAFE_NO_PAD.encode(json!({ "alg": alg }).to_string()),
+                BASE64_URL_SAFE_NO_PAD.encode("{}"),
+            )
+        };
+
+        let policy = RequestObjectPolicy::default();
+        assert!(policy.check(None).is_ok());
+        assert!(policy.check(Some(&jwt("none"))).is_ok());
+        assert!(policy.check(Some(&jwt("ES256"))).is_ok());
+        assert!(matches!(
+            policy.check(Some(&jwt("RS256"))),
+            Err(OID4VPError::DisallowedRequestAlgorithm { .. })
+        ));
+
+        let policy = RequestObjectPolicy {
+            require_signed: true,
+            allowed_algorithms: vec!["EdDSA".into(), "HS256".into()],
+            max_request_size: Some(64),
+        };
+        assert!(matches!(
+            policy.check(None),
+            Err(OID4VPError::UnsignedRequestObject)
+        ));
+        assert!(matches!(
+            policy.check(Some(&jwt("none"))),
+            Err(OID4VPError::UnsignedRequestObject)
+        ));
+        assert!(matches!(
+            policy.check(Some(&jwt("HS256"))),
+            Err(OID4VPError::WeakRequestAlgorithm { .. })
+        ));
+        assert!(matches!(
+            policy.check(Some(&jwt("ES256"))),
+            Err(OID4VPError::DisallowedRequestAlgorithm { .. })
+        ));
+        assert!(policy.check(Some(&jwt("EdDSA"))).is_ok());
+
+        assert!(policy.check_size(&jwt("EdDSA")).is_ok());
+        assert!(matches!(
+            policy.check_size(&"a".repeat(65)),
+            Err(OID4VPError::RequestTooLarge { max_size: 64 })
+        ));
+        assert!(RequestObjectPolicy::default()
+            .check_size(&"a".repeat(65))
+            .is_ok());
+    }
+}
@@ -1,7 +1,10 @@
+#[cfg(test)]
 use ssi::{claims::jws::JwsSigner, JWK};
 
 #[derive(Debug, thiserror::Error, uniffi::Error)]
 pub enum RequestSignerError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
     #[error("Unsupported algorithm")]
     UnsupportedAlgorithm,
     #[error("Failed to sign the request")]
@@ -21,11 +24,20 @@ pub trait RequestSignerInterface: Send + Sync + std::fmt::Debug {
     async fn try_sign(&self, payload: Vec<u8>) -> Result<Vec<u8>, RequestSignerError>;
 }
 
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for RequestSignerError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        RequestSignerError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+#[cfg(test)]
 #[derive(Debug, Clone)]
 pub(crate) struct ExampleRequestSigner {
     jwk: JWK,
 }
 
+#[cfg(test)]
 impl Default for ExampleRequestSigner {
     fn default() -> Self {
         Self {
@@ -34,6 +46,7 @@ impl Default for ExampleRequestSigner {
     }
 }
 
+#[cfg(test)]
 #[async_trait::async_trait]
 impl RequestSignerInterface for ExampleRequestSigner {
     fn alg(&self) -> Result<String, RequestSignerError> {
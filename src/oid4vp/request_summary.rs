@@ -0,0 +1,184 @@
+//! A summary of the contents of a permission request, returned in a single
+//! call so that consent screens need not query each credential and field
+//! across the FFI.
+
+use super::permission_request::{
+    MdocElementPath, PermissionRequest, RequestedElement, RequestedField,
+};
+use super::risk_analysis::RiskWarning;
+use super::transaction_data::TransactionData;
+use super::verifier_review::VerifierInfo;
+use crate::common::*;
+use crate::credential::{claims::ClaimLeaf, CredentialFormat, ParsedCredential};
+
+use std::sync::Arc;
+
+/// The contents of a permission request, for consent screens.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct PermissionRequestSummary {
+    pub verifier: VerifierInfo,
+    pub purpose: Option<String>,
+    pub credentials: Vec<MatchedCredentialSummary>,
+    pub transaction_data: Vec<TransactionData>,
+    pub risk_warnings: Vec<RiskWarning>,
+}
+
+/// A credential matching a permission request, with the fields requested
+/// from it.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct MatchedCredentialSummary {
+    pub credential: Arc<ParsedCredential>,
+    pub id: Uuid,
+    pub format: CredentialFormat,
+    pub r#type: CredentialType,
+    pub issuer: Option<String>,
+    pub requested_fields: Vec<RequestedFieldSummary>,
+    /// The requested data elements, for mdocs, as returned by
+    /// [PermissionRequest::requested_elements].
+    pub requested_elements: Vec<RequestedElement>,
+}
+
+/// A field requested from a credential, with the data of the getters of
+/// [RequestedField].
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct RequestedFieldSummary {
+    /// The field, to pass to
+    /// [PermissionRequest::create_permission_response_with_selected_fields].
+    pub field: Arc<RequestedField>,
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub label: Option<String>,
+    pub required: bool,
+    pub retained: bool,
+    pub purpose: Option<String>,
+    pub values: Vec<ClaimLeaf>,
+    pub path: Option<String>,
+    pub claim_path: Vec<String>,
+    pub mdoc_element: Option<MdocElementPath>,
+}
+
+impl From<Arc<RequestedField>> for RequestedFieldSummary {
+    fn from(field: Arc<RequestedField>) -> Self {
+        Self {
+            id: field.id(),
+            name: field.name(),
+            label: field.label(),
+            required: field.required(),
+            retained: field.retained(),
+            purpose: field.purpose(),
+            values: field.values(),
+            path: field.path(),
+            claim_path: field.claim_path(),
+            mdoc_element: field.mdoc_element(),
+            field,
+        }
+    }
+}
+
+#[uniffi::export]
+impl PermissionRequest {
+    /// Return the verifier, the matched credentials and the fields requested
+    /// from each of them at once, rather than through
+    /// [PermissionRequest::requested_fields] for every credential.
+    pub fn summary(&self) -> PermissionRequestSummary {
+        let credentials = self
+            .credentials
+            .iter()
+            .map(|credential| {
+                let fields = self.requested_fields(credential);
+                let requested_elements = match credential.as_mso_mdoc() {
+                    Some(_) => super::iso_18013_7::element_requests(&fields),
+                    None => vec![],
+                };
+                MatchedCredentialSummary {
+                    credential: credential.clone(),
+                    id: credential.id(),
+                    format: credential.format(),
+                    r#type: credential.r#type(),
+                    issuer: credential.issuer(),
+                    requested_fields: fields.into_iter().map(Into::into).collect(),
+                    requested_elements,
+                }
+            })
+            .collect();
+
+        PermissionRequestSummary {
+            verifier: self.verifier(),
+            purpose: self.purpose(),
+            credentials,
+            transaction_data: self.transaction_data.clone(),
+            risk_warnings: self.risk_warnings.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::json_vc::JsonVc;
+
+    use serde_json::json;
+
+    #[test]
+    fn summarizes_permission_requests() {
+        let credential = ParsedCredential::new_ldp_vc(
+            JsonVc::new_from_json(
+                json!({
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiableCredential", "IdentityCredential"],
+                    "issuer": "did:example:issuer",
+                    "issuanceDate": "2024-01-01T00:00:00Z",
+                    "credentialSubject": {
+                        "id": "did:example:holder",
+                        "birthdate": "1990-01-01",
+                    },
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        );
+        let definition = serde_json::from_value(json!({
+            "id": "identity",
+            "purpose": "Age verification",
+            "input_descriptors": [{
+                "id": "identity",
+                "constraints": {
+                    "fields": [{
+                        "name": "Birth date",
+                        "path": ["$.credentialSubject.birthdate"],
+                    }],
+                },
+            }],
+        }))
+        .unwrap();
+        let request = serde_json::from_value(json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+        }))
+        .unwrap();
+        let permission_request =
+            PermissionRequest::new(definition, vec![credential.clone()], request);
+
+        let summary = permission_request.summary();
+        assert_eq!(summary.verifier.client_id, "did:web:verifier.example.com");
+        assert_eq!(summary.purpose.as_deref(), Some("Age verification"));
+        assert_eq!(summary.credentials.len(), 1);
+
+        let matched = &summary.credentials[0];
+        assert_eq!(matched.id, credential.id());
+        assert_eq!(matched.issuer.as_deref(), Some("did:example:issuer"));
+        assert!(matched.requested_elements.is_empty());
+
+        let expected = permission_request.requested_fields(&credential);
+        assert_eq!(matched.requested_fields.len(), expected.len());
+        let field = &matched.requested_fields[0];
+        assert_eq!(field.id, field.field.id());
+        assert_eq!(field.label, expected[0].label());
+        assert_eq!(field.label.as_deref(), Some("Birth date"));
+        assert!(field.required);
+        assert_eq!(field.values, expected[0].values());
+    }
+}
@@ -0,0 +1,138 @@
+//! Dereferencing of `request_uri`s, including the `request_uri_method=post`
+//! flow, where the wallet POSTs its metadata and a `wallet_nonce` to the
+//! `request_uri`, so that the verifier can tailor the request object to the
+//! capabilities of the wallet and bind it to the nonce.
+
+use super::request;
+
+use oid4vci::oauth2::http::{header, Method, Request};
+use openid4vp::core::{metadata::WalletMetadata, util::AsyncHttpClient};
+use uniffi::deps::anyhow::{self, bail, Context};
+use url::Url;
+use uuid::Uuid;
+
+const REQUEST_URI_METHOD_POST: &str = "post";
+
+/// Return the `request_uri` of an authorization request URL, and whether the
+/// verifier asks for it to be POSTed to.
+pub(crate) fn request_uri(url: &Url) -> Option<(String, bool)> {
+    let mut request_uri = None;
+    let mut method_post = false;
+    for (name, value) in url.query_pairs() {
+        match name.as_ref() {
+            "request_uri" => request_uri = Some(value.into_owned()),
+            "request_uri_method" => method_post = value == REQUEST_URI_METHOD_POST,
+            _ => {}
+        }
+    }
+    request_uri.map(|request_uri| (request_uri, method_post))
+}
+
+/// Fetch the request object of an authorization request URL from its
+/// `request_uri`, and return the URL with the request object passed by value,
+/// to be validated as any other. URLs without a `request_uri` are returned
+/// as is.
+///
+/// When the verifier asks for `request_uri_method=post`, the wallet metadata
+/// and a fresh wallet nonce are POSTed, and the request object must be bound
+/// to the nonce.
+pub(crate) async fn dereference_request_uri(
+    url: Url,
+    metadata: &WalletMetadata,
+    client: &impl AsyncHttpClient,
+) -> anyhow::Result<Url> {
+    let Some((request_uri, method_post)) = request_uri(&url) else {
+        return Ok(url);
+    };
+
+    let request = Request::builder()
+        .uri(&request_uri)
+        .header(header::ACCEPT, "application/oauth-authz-req+jwt");
+    let (request, wallet_nonce) = if method_post {
+        let wallet_nonce = Uuid::new_v4().to_string();
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("wallet_metadata", &serde_json::to_string(metadata)?)
+            .append_pair("wallet_nonce", &wallet_nonce)
+            .finish();
+        let request = request
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body.into_bytes());
+        (request, Some(wallet_nonce))
+    } else {
+        (request.method(Method::GET).body(vec![]), None)
+    };
+    let request = request.context("failed to build the request_uri request")?;
+
+    let response = client.execute(request).await?;
+    if !response.status().is_success() {
+        bail!("request to {request_uri} failed: {}", response.status());
+    }
+    let request_object = String::from_utf8(response.into_body())
+        .context("the request object is not UTF-8")?
+        .trim()
+        .to_owned();
+    if let Some(wallet_nonce) = wallet_nonce {
+        check_wallet_nonce(&request_object, &wallet_nonce)?;
+    }
+
+    let mut url = url;
+    let parameters: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != "request_uri" && name != "request_uri_method")
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(parameters)
+        .append_pair("request", &request_object);
+
+    Ok(url)
+}
+
+/// Check that a request object is bound to the wallet nonce.
+fn check_wallet_nonce(request_object: &str, wallet_nonce: &str) -> anyhow::Result<()> {
+    let claims = request::jwt_part(request_object, 1).context("the request object is not a JWT")?;
+
+    match claims["wallet_nonce"].as_str() {
+        Some(nonce) if nonce == wallet_nonce => Ok(()),
+        _ => bail!("the request object is not bound to the wallet nonce"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::*;
+    use serde_json::{json, Value as Json};
+
+    #[test]
+    fn binds_request_objects_to_wallet_nonces() {
+        let url = Url::parse(
+            "openid4vp://?client_id=verifier&request_uri=https%3A%2F%2Fverifier.example.com%2Frequest&request_uri_method=post",
+        )
+        .unwrap();
+        assert_eq!(
+            request_uri(&url),
+            Some(("https://verifier.example.com/request".into(), true))
+        );
+        let url =
+            Url::parse("openid4vp://?client_id=verifier&request_uri=https://verifier.example.com")
+                .unwrap();
+        assert_eq!(
+            request_uri(&url),
+            Some(("https://verifier.example.com".into(), false))
+        );
+
+        let jwt = |claims: Json| {
+            format!(
+                "{}.{}.",
+                BASE64_URL_SAFE_NO_PAD.encode(json!({"alg": "ES256"}).to_string()),
+                BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+            )
+        };
+        assert!(check_wallet_nonce(&jwt(json!({"wallet_nonce": "nonce"})), "nonce").is_ok());
+        assert!(check_wallet_nonce(&jwt(json!({"wallet_nonce": "other"})), "nonce").is_err());
+        assert!(check_wallet_nonce(&jwt(json!({})), "nonce").is_err());
+    }
+}
@@ -0,0 +1,499 @@
+//! Encryption of `direct_post.jwt` responses into the JWE the verifier asks
+//! for in its `client_metadata`:
+//!
+//! ```json
+//! {
+//!   "authorization_encrypted_response_alg": "ECDH-ES",
+//!   "authorization_encrypted_response_enc": "A256GCM",
+//!   "jwks": { "keys": [{ "kty": "EC", "crv": "P-256", "use": "enc", "x": "...", "y": "..." }] }
+//! }
+//! ```
+//!
+//! The response parameters are encrypted to the first P-256 key of the
+//! verifier with ECDH-ES direct key agreement. As profiled by ISO/IEC
+//! 18013-7, the `apu` of the JWE is the mdoc generated nonce of the OID4VP
+//! handover and its `apv` the nonce of the request, which is how the verifier
+//! learns the nonce the device authentication of mdocs is bound to.
+//!
+//! `dc_api.jwt` responses of the Digital Credentials API are encrypted the
+//! same way, without an `apu`, as their mdocs are bound to the origin of the
+//! caller and to the thumbprint of the key instead.
+//!
+//! The [super::verifier::Verifier] decrypts the responses encrypted to the
+//! ephemeral keys of its requests the same way.
+//!
+//! NOTE: only ECDH-ES with A256GCM is supported, and the keys of the verifier
+//! are only read from `jwks`, not from `jwks_uri`.
+
+use super::draft;
+use super::error::{caused_by, ErrorSource};
+use super::request;
+use crate::common::Url;
+use crate::crypto_provider::{self, CryptoProviderError, AES_GCM_NONCE_LEN};
+
+use base64::prelude::*;
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use openid4vp::core::response::AuthorizationResponse;
+use openid4vp::core::util::AsyncHttpClient;
+use p256::{elliptic_curve::sec1::ToEncodedPoint, PublicKey, SecretKey};
+use serde_json::{json, Value as Json};
+use sha2::{Digest, Sha256};
+use uniffi::deps::anyhow::{self, bail};
+
+/// The key agreement algorithm of the encrypted responses.
+pub const ECDH_ES: &str = "ECDH-ES";
+/// The content encryption algorithm of the encrypted responses.
+pub const A256GCM: &str = "A256GCM";
+
+const AES_GCM_TAG_LEN: usize = 16;
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum ResponseEncryptionError {
+    #[error("The verifier does not declare the {parameter} to encrypt the response with")]
+    MissingParameter { parameter: String },
+    #[error("Unsupported response encryption algorithm: {algorithm}")]
+    UnsupportedAlgorithm { algorithm: String },
+    #[error("Unsupported response content encryption: {encryption}")]
+    UnsupportedEncryption { encryption: String },
+    #[error("The verifier has no P-256 key to encrypt the response to")]
+    NoEncryptionKey,
+    #[error("Invalid encryption key: {reason}")]
+    InvalidKey {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error(transparent)]
+    CryptoProvider(#[from] CryptoProviderError),
+    #[error("Failed to encode the response: {reason}")]
+    Encoding {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Failed to decrypt the response: {reason}")]
+    Decryption {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+}
+
+impl ResponseEncryptionError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::MissingParameter { .. } => "response_encryption.missing_parameter",
+            Self::UnsupportedAlgorithm { .. } => "response_encryption.unsupported_algorithm",
+            Self::UnsupportedEncryption { .. } => "response_encryption.unsupported_encryption",
+            Self::NoEncryptionKey => "response_encryption.no_encryption_key",
+            Self::InvalidKey { .. } => "response_encryption.invalid_key",
+            Self::CryptoProvider(..) => "response_encryption.crypto_provider",
+            Self::Encoding { .. } => "response_encryption.encoding",
+            Self::Decryption { .. } => "response_encryption.decryption",
+        }
+    }
+
+    /// The encryption key is invalid, with the error.
+    pub(crate) fn invalid_key(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::InvalidKey { reason, source }
+    }
+
+    /// The response failed to encode with the error.
+    pub(crate) fn encoding(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::Encoding { reason, source }
+    }
+
+    /// The response failed to decrypt with the error.
+    pub(crate) fn decryption(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::Decryption { reason, source }
+    }
+}
+
+/// Encrypt the parameters of a response to the key of the verifier, with the
+/// mdoc generated nonce, if any, as the `apu`, and return the compact JWE.
+pub(crate) fn encrypt_response(
+    request: &AuthorizationRequestObject,
+    response: &AuthorizationResponse,
+    mdoc_generated_nonce: Option<&str>,
+) -> Result<String, ResponseEncryptionError> {
+    let metadata = client_metadata(request);
+    let parameter = |name: &str| {
+        metadata[name]
+            .as_str()
+            .ok_or_else(|| ResponseEncryptionError::MissingParameter {
+                parameter: name.into(),
+            })
+    };
+
+    let algorithm = parameter("authorization_encrypted_response_alg")?;
+    if algorithm != ECDH_ES {
+        return Err(ResponseEncryptionError::UnsupportedAlgorithm {
+            algorithm: algorithm.into(),
+        });
+    }
+    let encryption = parameter("authorization_encrypted_response_enc")?;
+    if encryption != A256GCM {
+        return Err(ResponseEncryptionError::UnsupportedEncryption {
+            encryption: encryption.into(),
+        });
+    }
+    let (kid, verifier_key) = encryption_key(&metadata["jwks"])?;
+
+    let provider = crypto_provider::provider();
+    let ephemeral_key = SecretKey::from_slice(&provider.random_bytes(32)?)
+        .map_err(ResponseEncryptionError::invalid_key)?;
+    let shared_secret = provider.ecdh_p256(
+        ephemeral_key.to_bytes().to_vec(),
+        verifier_key.to_encoded_point(false).as_bytes().to_vec(),
+    )?;
+
+    let apu = mdoc_generated_nonce.unwrap_or_default().as_bytes();
+    let apv = request::string_parameter(request, "nonce").unwrap_or_default();
+    let key = concat_kdf(&shared_secret, encryption, apu, apv.as_bytes());
+
+    let mut header = json!({
+        "alg": ECDH_ES,
+        "enc": encryption,
+        "epk": jwk(&ephemeral_key.public_key()),
+        "apv": BASE64_URL_SAFE_NO_PAD.encode(&apv),
+    });
+    if !apu.is_empty() {
+        header["apu"] = BASE64_URL_SAFE_NO_PAD.encode(apu).into();
+    }
+    if let Some(kid) = kid {
+        header["kid"] = kid.into();
+    }
+    let header = BASE64_URL_SAFE_NO_PAD.encode(header.to_string());
+
+    let iv = provider.random_bytes(AES_GCM_NONCE_LEN as u32)?;
+    let mut ciphertext = provider.aes_gcm_seal(
+        key,
+        iv.clone(),
+        serde_json::to_vec(&response_parameters(request, response)?)
+            .map_err(ResponseEncryptionError::encoding)?,
+        header.as_bytes().to_vec(),
+    )?;
+    let tag = ciphertext.split_off(ciphertext.len().saturating_sub(AES_GCM_TAG_LEN));
+
+    Ok(format!(
+        "{header}..{}.{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(iv),
+        BASE64_URL_SAFE_NO_PAD.encode(ciphertext),
+        BASE64_URL_SAFE_NO_PAD.encode(tag),
+    ))
+}
+
+/// Generate an ephemeral key for the responses to a request to be encrypted
+/// to.
+pub(crate) fn generate_decryption_key() -> Result<SecretKey, ResponseEncryptionError> {
+    SecretKey::from_slice(&crypto_provider::provider().random_bytes(32)?)
+        .map_err(ResponseEncryptionError::invalid_key)
+}
+
+/// Return the JWK the responses encrypted to a key are encrypted with, to be
+/// declared in the `jwks` of a request.
+pub(crate) fn encryption_jwk(key: &SecretKey, kid: &str) -> Json {
+    let mut jwk = jwk(&key.public_key());
+    jwk["kid"] = kid.into();
+    jwk["use"] = "enc".into();
+    jwk["alg"] = ECDH_ES.into();
+    jwk
+}
+
+/// Return the `kid` of the key a compact JWE is encrypted to, if it names it.
+pub(crate) fn jwe_kid(jwe: &str) -> Option<String> {
+    let header = BASE64_URL_SAFE_NO_PAD.decode(jwe.split('.').next()?).ok()?;
+    let header: Json = serde_json::from_slice(&header).ok()?;
+    header["kid"].as_str().map(ToOwned::to_owned)
+}
+
+/// Decrypt a compact JWE encrypted to the key, and return its parameters,
+/// with its `apu`, e.g. the mdoc generated nonce.
+pub(crate) fn decrypt_response(
+    jwe: &str,
+    key: &SecretKey,
+) -> Result<(Json, Vec<u8>), ResponseEncryptionError> {
+    let [header, encrypted_key, iv, ciphertext, tag] = jwe.split('.').collect::<Vec<_>>()[..]
+    else {
+        return Err(ResponseEncryptionError::Decryption {
+            reason: "the response is not a compact JWE".into(),
+            source: None,
+        });
+    };
+    let decode = |value: &str| {
+        BASE64_URL_SAFE_NO_PAD
+            .decode(value)
+            .map_err(ResponseEncryptionError::decryption)
+    };
+    let protected: Json =
+        serde_json::from_slice(&decode(header)?).map_err(ResponseEncryptionError::decryption)?;
+
+    if protected["alg"] != ECDH_ES || !encrypted_key.is_empty() {
+        return Err(ResponseEncryptionError::UnsupportedAlgorithm {
+            algorithm: protected["alg"].to_string(),
+        });
+    }
+    if protected["enc"] != A256GCM {
+        return Err(ResponseEncryptionError::UnsupportedEncryption {
+            encryption: protected["enc"].to_string(),
+        });
+    }
+    let ephemeral_key = public_key(&protected["epk"])?;
+    let parameter = |name: &str| {
+        protected[name]
+            .as_str()
+            .map(decode)
+            .transpose()
+            .map(Option::unwrap_or_default)
+    };
+    let (apu, apv) = (parameter("apu")?, parameter("apv")?);
+
+    let provider = crypto_provider::provider();
+    let shared_secret = provider.ecdh_p256(
+        key.to_bytes().to_vec(),
+        ephemeral_key.to_encoded_point(false).as_bytes().to_vec(),
+    )?;
+    let plaintext = provider.aes_gcm_open(
+        concat_kdf(&shared_secret, A256GCM, &apu, &apv),
+        decode(iv)?,
+        [decode(ciphertext)?, decode(tag)?].concat(),
+        header.as_bytes().to_vec(),
+    )?;
+    let parameters =
+        serde_json::from_slice(&plaintext).map_err(ResponseEncryptionError::decryption)?;
+
+    Ok((parameters, apu))
+}
+
+/// Post an encrypted response to the response endpoint of the request, as
+/// its `response` parameter, and return the `redirect_uri` of the response of
+/// the verifier.
+pub(crate) async fn submit_encrypted_response(
+    client: &impl AsyncHttpClient,
+    request: &AuthorizationRequestObject,
+    jwe: &str,
+) -> anyhow::Result<Option<Url>> {
+    let Some(response_uri) = draft::response_endpoint(request) else {
+        bail!("the request has no response_uri")
+    };
+
+    let mut body = url::form_urlencoded::Serializer::new(String::new());
+    body.append_pair("response", jwe);
+    draft::post_form(client, &response_uri, body.finish()).await
+}
+
+/// Return the RFC 7638 thumbprint of the key responses to the request are
+/// encrypted to, if it has one.
+pub(crate) fn encryption_key_thumbprint(request: &AuthorizationRequestObject) -> Option<Vec<u8>> {
+    let (_, public_key) = encryption_key(&client_metadata(request)["jwks"]).ok()?;
+    let jwk = jwk(&public_key);
+    // The required members of EC keys, in lexicographic order.
+    let members = format!(
+        r#"{{"crv":"P-256","kty":"EC","x":{},"y":{}}}"#,
+        jwk["x"], jwk["y"]
+    );
+    Some(Sha256::digest(members).to_vec())
+}
+
+fn client_metadata(request: &AuthorizationRequestObject) -> Json {
+    request::parameters(request)
+        .remove("client_metadata")
+        .unwrap_or_default()
+}
+
+/// Return the parameters of a response as a JSON object, with its `state`.
+pub(crate) fn response_parameters(
+    request: &AuthorizationRequestObject,
+    response: &AuthorizationResponse,
+) -> Result<Json, ResponseEncryptionError> {
+    let AuthorizationResponse::Unencoded(response) = response else {
+        return Err(ResponseEncryptionError::Encoding {
+            reason: "the response is already encoded".into(),
+            source: None,
+        });
+    };
+
+    let mut parameters = json!({
+        "vp_token": serde_json::to_value(&response.vp_token).map_err(ResponseEncryptionError::encoding)?,
+        "presentation_submission": serde_json::to_value(&response.presentation_submission)
+            .map_err(ResponseEncryptionError::encoding)?,
+    });
+    if let Some(state) = request::string_parameter(request, "state") {
+        parameters["state"] = state.into();
+    }
+    Ok(parameters)
+}
+
+/// Return the ID and the public key of the first P-256 key of the JWKS that
+/// can be used for encryption.
+fn encryption_key(jwks: &Json) -> Result<(Option<String>, PublicKey), ResponseEncryptionError> {
+    let key = jwks["keys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|key| {
+            key["kty"] == "EC"
+                && key["crv"] == "P-256"
+                && key["use"].as_str().is_none_or(|usage| usage == "enc")
+        })
+        .ok_or(ResponseEncryptionError::NoEncryptionKey)?;
+
+    Ok((key["kid"].as_str().map(ToOwned::to_owned), public_key(key)?))
+}
+
+/// Return the P-256 public key of a JWK.
+fn public_key(key: &Json) -> Result<PublicKey, ResponseEncryptionError> {
+    let coordinate = |name: &str| {
+        key[name]
+            .as_str()
+            .and_then(|value| BASE64_URL_SAFE_NO_PAD.decode(value).ok())
+            .filter(|value| value.len() == 32)
+            .ok_or_else(|| ResponseEncryptionError::InvalidKey {
+                reason: format!("invalid {name}"),
+                source: None,
+            })
+    };
+    let point = [vec![0x04], coordinate("x")?, coordinate("y")?].concat();
+    PublicKey::from_sec1_bytes(&point).map_err(ResponseEncryptionError::invalid_key)
+}
+
+/// Return the JWK of a P-256 public key.
+fn jwk(public_key: &PublicKey) -> Json {
+    let point = public_key.to_encoded_point(false);
+    let coordinate = |value: Option<&_>| value.map(|value| BASE64_URL_SAFE_NO_PAD.encode(value));
+    json!({
+        "kty": "EC",
+        "crv": "P-256",
+        "x": coordinate(point.x()),
+        "y": coordinate(point.y()),
+    })
+}
+
+/// Derive the 256 bits content encryption key of ECDH-ES with the Concat KDF
+/// of RFC 7518, section 4.6.2.
+fn concat_kdf(shared_secret: &[u8], encryption: &str, apu: &[u8], apv: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(1u32.to_be_bytes());
+    hasher.update(shared_secret);
+    for value in [encryption.as_bytes(), apu, apv] {
+        hasher.update((value.len() as u32).to_be_bytes());
+        hasher.update(value);
+    }
+    hasher.update(256u32.to_be_bytes());
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypts_responses_to_the_verifier_key() {
+        let verifier_key = SecretKey::from_slice(&[3; 32]).unwrap();
+        let mut encryption_jwk = jwk(&verifier_key.public_key());
+        encryption_jwk["kid"] = "enc-1".into();
+        encryption_jwk["use"] = "enc".into();
+        let request: AuthorizationRequestObject = serde_json::from_value(json!({
+            "client_id": "x509_san_dns:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post.jwt",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+            "state": "abc",
+            "client_metadata": {
+                "authorization_encrypted_response_alg": ECDH_ES,
+                "authorization_encrypted_response_enc": A256GCM,
+                "jwks": { "keys": [
+                    { "kty": "EC", "crv": "P-256", "use": "sig", "x": "", "y": "" },
+                    encryption_jwk,
+                ] },
+            },
+        }))
+        .unwrap();
+        let response = AuthorizationResponse::Unencoded(
+            serde_json::from_value(json!({
+                "vp_token": "token",
+                "presentation_submission": {
+                    "id": "submission",
+                    "definition_id": "definition",
+                    "descriptor_map": [],
+                },
+            }))
+            .unwrap(),
+        );
+
+        let jwe = encrypt_response(&request, &response, Some("mdoc-nonce")).unwrap();
+        let [header, key, ..] = jwe.split('.').collect::<Vec<_>>()[..] else {
+            panic!("not a compact JWE: {jwe}");
+        };
+        assert!(key.is_empty());
+        let decode = |value: &str| BASE64_URL_SAFE_NO_PAD.decode(value).unwrap();
+        let protected: Json = serde_json::from_slice(&decode(header)).unwrap();
+        assert_eq!(protected["kid"], "enc-1");
+        assert_eq!(jwe_kid(&jwe).as_deref(), Some("enc-1"));
+        assert_eq!(decode(protected["apu"].as_str().unwrap()), b"mdoc-nonce");
+        assert_eq!(decode(protected["apv"].as_str().unwrap()), b"n-0S6_WzA2Mj");
+
+        let (parameters, apu) = decrypt_response(&jwe, &verifier_key).unwrap();
+        assert_eq!(apu, b"mdoc-nonce");
+        assert_eq!(parameters["vp_token"], "token");
+        assert_eq!(parameters["state"], "abc");
+    }
+
+    #[test]
+    fn requires_supported_encryption() {
+        let request = |metadata: Json| -> AuthorizationRequestObject {
+            serde_json::from_value(json!({
+                "client_id": "x509_san_dns:verifier.example.com",
+                "response_type": "vp_token",
+                "response_mode": "direct_post.jwt",
+                "nonce": "n-0S6_WzA2Mj",
+                "client_metadata": metadata,
+            }))
+            .unwrap()
+        };
+        let response = AuthorizationResponse::Unencoded(
+            serde_json::from_value(json!({
+                "vp_token": "token",
+                "presentation_submission": {
+                    "id": "submission",
+                    "definition_id": "definition",
+                    "descriptor_map": [],
+                },
+            }))
+            .unwrap(),
+        );
+
+        assert!(matches!(
+            encrypt_response(&request(json!({})), &response, None),
+            Err(ResponseEncryptionError::MissingParameter { .. })
+        ));
+        assert!(matches!(
+            encrypt_response(
+                &request(json!({
+                    "authorization_encrypted_response_alg": ECDH_ES,
+                    "authorization_encrypted_response_enc": "A128CBC-HS256",
+                })),
+                &response,
+                None
+            ),
+            Err(ResponseEncryptionError::UnsupportedEncryption { .. })
+        ));
+        assert!(matches!(
+            encrypt_response(
+                &request(json!({
+                    "authorization_encrypted_response_alg": ECDH_ES,
+                    "authorization_encrypted_response_enc": A256GCM,
+                    "jwks": { "keys": [] },
+                })),
+                &response,
+                None
+            ),
+            Err(ResponseEncryptionError::NoEncryptionKey)
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+//! Errors of the submission of authorization responses, telling terminal
+//! rejections by the verifier apart from failures the submission can be
+//! retried after.
+//!
+//! The library only reports the status of the responses of verifiers, so the
+//! holder HTTP client records the last response to a `POST` while a
+//! submission is tracked, for its error response to be parsed:
+//!
+//! ```json
+//! { "error": "invalid_request", "error_description": "...", "redirect_uri": "..." }
+//! ```
+
+use super::error::OID4VPError;
+use crate::common::Url;
+use crate::oid4vci::{certificate_pinning_host, HttpClientError, HttpResponse};
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value as Json;
+use uniffi::deps::anyhow;
+
+tokio::task_local! {
+    /// The last response to a `POST` received while a submission is tracked.
+    static LAST_RESPONSE: Arc<Mutex<Option<HttpResponse>>>;
+}
+
+/// Record a response of the holder HTTP client, if a submission is tracked.
+pub(crate) fn record_response(response: &HttpResponse) {
+    let _ = LAST_RESPONSE.try_with(|last| {
+        *last.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(response.clone());
+    });
+}
+
+/// Run a submission, mapping its errors with the last response it received,
+/// which is returned along with its output.
+pub(crate) async fn track_submission<T, F>(
+    future: F,
+) -> Result<(T, Option<HttpResponse>), OID4VPError>
+where
+    F: Future<Output = anyhow::Result<T>>,
+{
+    let last = Arc::new(Mutex::new(None));
+    let result = LAST_RESPONSE.scope(last.clone(), future).await;
+    let last = last
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take();
+
+    match result {
+        Ok(output) => Ok((output, last)),
+        Err(e) => Err(submission_error(e, last)),
+    }
+}
+
+/// Map the error of a submission, given the last response it received.
+fn submission_error(error: anyhow::Error, response: Option<HttpResponse>) -> OID4VPError {
+    if let Some(host) = certificate_pinning_host(&error) {
+        return OID4VPError::CertificatePinning { host };
+    }
+
+    match response {
+        // The verifier may be temporarily unavailable, or rate limiting.
+        Some(response) if response.status_code >= 500 || response.status_code == 429 => {
+            OID4VPError::ResponseSubmissionRetryable {
+                reason: format!("the verifier responded with {}", response.status_code),
+                source: None,
+            }
+        }
+        Some(response) if !(200..300).contains(&response.status_code) => {
+            let body = serde_json::from_slice::<Json>(&response.body).unwrap_or_default();
+            let text = |name: &str| body.get(name).and_then(Json::as_str).map(ToOwned::to_owned);
+
+            OID4VPError::VerifierRejected {
+                status_code: response.status_code,
+                error: text("error").unwrap_or_else(|| "invalid_request".into()),
+                error_description: text("error_description"),
+                redirect_uri: text("redirect_uri").and_then(|uri| Url::parse(&uri).ok()),
+            }
+        }
+        // No response was received, e.g. the connection failed or timed out.
+        None if error
+            .chain()
+            .any(|error| matches!(error.downcast_ref(), Some(HttpClientError::Other { .. }))) =>
+        {
+            OID4VPError::retryable_submission(error)
+        }
+        _ => OID4VPError::response_submission(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    fn response(status_code: u16, body: &str) -> HttpResponse {
+        HttpResponse {
+            status_code,
+            headers: HashMap::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_verifier_error_responses() {
+        let submit = |response: HttpResponse| {
+            track_submission(async move {
+                record_response(&response);
+                Err::<(), _>(anyhow::anyhow!("request failed: {}", response.status_code))
+            })
+        };
+
+        match submit(response(
+            400,
+            r#"{
+                "error": "invalid_presentation",
+                "error_description": "The credential has expired",
+                "redirect_uri": "https://verifier.example.com/failed"
+            }"#,
+        ))
+        .await
+        {
+            Err(OID4VPError::VerifierRejected {
+                status_code,
+                error,
+                error_description,
+                redirect_uri,
+            }) => {
+                assert_eq!(status_code, 400);
+                assert_eq!(error, "invalid_presentation");
+                assert_eq!(
+                    error_description.as_deref(),
+                    Some("The credential has expired")
+                );
+                assert_eq!(
+                    redirect_uri,
+                    Some(Url::parse("https://verifier.example.com/failed").unwrap())
+                );
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        assert!(matches!(
+            submit(response(503, "")).await,
+            Err(OID4VPError::ResponseSubmissionRetryable { .. })
+        ));
+
+        let unreachable = track_submission(async {
+            Err::<(), _>(
+                anyhow::Error::new(HttpClientError::Other {
+                    error: "connection refused".into(),
+                })
+                .context("failed to submit the response"),
+            )
+        })
+        .await;
+        match unreachable {
+            Err(OID4VPError::ResponseSubmissionRetryable {
+                reason,
+                source: Some(source),
+            }) => {
+                // The error keeps the HTTP client error it was caused by.
+                assert_eq!(reason, "failed to submit the response");
+                assert_eq!(source.messages.len(), 2);
+                assert_eq!(source.messages[0], reason);
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! The policy the `response_uri` and `redirect_uri` of authorization requests
+//! must meet for responses to be submitted to them, so that malicious
+//! requests cannot direct the holder to internal services or exfiltrate
+//! credentials to arbitrary hosts.
+//!
+//! NOTE: hosts are checked as written in the URIs: names resolving to private
+//! addresses are not detected.
+
+use super::error::OID4VPError;
+use super::request;
+use crate::common::Url;
+
+use std::net::IpAddr;
+
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use url::Host;
+
+/// The policy response and redirect URIs must meet.
+///
+/// The default policy allows every URI.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct ResponseUriPolicy {
+    /// Refuse URIs that are not `https`.
+    pub require_https: bool,
+    /// The domains responses may be sent to, along with their subdomains,
+    /// e.g. `verifier.example.com`. Empty allows every domain.
+    pub allowed_domains: Vec<String>,
+    /// Refuse URIs whose host is `localhost`, or a loopback, private,
+    /// link-local or unspecified IP address.
+    pub block_private_addresses: bool,
+}
+
+impl ResponseUriPolicy {
+    /// Check the response and redirect URIs of a request.
+    pub(crate) fn check_request(
+        &self,
+        request: &AuthorizationRequestObject,
+    ) -> Result<(), OID4VPError> {
+        ["response_uri", "redirect_uri"]
+            .into_iter()
+            .filter_map(|name| request::string_parameter(request, name))
+            .try_for_each(|uri| self.check(&uri))
+    }
+
+    /// Check a response or redirect URI.
+    pub(crate) fn check(&self, uri: &str) -> Result<(), OID4VPError> {
+        let disallowed = || OID4VPError::DisallowedResponseUri {
+            uri: uri.to_owned(),
+        };
+        let url = Url::parse(uri).map_err(|_| disallowed())?;
+
+        if self.require_https && url.scheme() != "https" {
+            return Err(disallowed());
+        }
+        if self.block_private_addresses && url.host().is_some_and(|host| is_private(&host)) {
+            return Err(disallowed());
+        }
+        if !self.allowed_domains.is_empty() {
+            let Some(Host::Domain(domain)) = url.host() else {
+                return Err(disallowed());
+            };
+            let domain = domain.to_lowercase();
+            if !self.allowed_domains.iter().any(|allowed| {
+                let allowed = allowed.to_lowercase();
+                domain == allowed || domain.ends_with(&format!(".{allowed}"))
+            }) {
+                return Err(disallowed());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_private(host: &Host<&str>) -> bool {
+    let address = match host {
+        Host::Domain(domain) => {
+            let domain = domain.to_lowercase();
+            return domain == "localhost" || domain.ends_with(".localhost");
+        }
+        Host::Ipv4(address) => IpAddr::V4(*address),
+        Host::Ipv6(address) => match address.to_ipv4_mapped() {
+            Some(address) => IpAddr::V4(address),
+            None => IpAddr::V6(*address),
+        },
+    };
+
+    match address {
+        IpAddr::V4(address) => {
+            address.is_loopback()
+                || address.is_private()
+                || address.is_link_local()
+                || address.is_unspecified()
+                // The shared address space of carrier-grade NATs.
+                || (address.octets()[0] == 100 && address.octets()[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(address) => {
+            address.is_loopback()
+                || address.is_unspecified()
+                // Unique local and link-local addresses.
+                || address.segments()[0] & 0xfe00 == 0xfc00
+                || address.segments()[0] & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enforces_response_uri_policies() {
+        let policy = ResponseUriPolicy::default();
+        assert!(policy.check("http://127.0.0.1/response").is_ok());
+
+        let policy = ResponseUriPolicy {
+            require_https: true,
+            allowed_domains: vec![],
+            block_private_addresses: true,
+        };
+        assert!(policy
+            .check("https://verifier.example.com/response")
+            .is_ok());
+        for uri in [
+            "http://verifier.example.com/response",
+            "https://localhost:8080/response",
+            "https://10.0.0.1/response",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/response",
+            "https://[::ffff:192.168.0.1]/response",
+            "https://[fd00::1]/response",
+            "not a uri",
+        ] {
+            assert!(
+                matches!(
+                    policy.check(uri),
+                    Err(OID4VPError::DisallowedResponseUri { .. })
+                ),
+                "{uri}"
+            );
+        }
+
+        let policy = ResponseUriPolicy {
+            allowed_domains: vec!["Example.com".into()],
+            ..Default::default()
+        };
+        assert!(policy
+            .check("https://verifier.example.com/response")
+            .is_ok());
+        assert!(policy.check("https://example.com/response").is_ok());
+        assert!(policy
+            .check("https://example.com.attacker.net/response")
+            .is_err());
+        assert!(policy.check("https://notexample.com/response").is_err());
+        assert!(policy.check("https://93.184.216.34/response").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! Heuristics flagging authorization requests that may be phishing attempts,
+//! so that apps can warn users before they consent.
+//!
+//! The analysis flags:
+//! - domains that look like, but are not, the known domains of the config;
+//! - `request_uri`s served from another domain than the client ID's;
+//! - responses sent to endpoints that are not HTTPS.
+//!
+//! NOTE: internationalized domains are compared in their punycode form, so
+//! lookalikes with non-Latin characters are not flagged.
+
+use super::draft;
+
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The minimum length of the known domains a one-character difference from
+/// is flagged, to avoid flagging short unrelated domains.
+const MIN_EDIT_DISTANCE_LENGTH: usize = 6;
+
+/// The configuration of the risk analysis of authorization requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct RiskAnalysisConfig {
+    /// The domains of the verifiers users deal with, whose lookalikes are
+    /// flagged, e.g. `bank.example.com`.
+    pub known_domains: Vec<String>,
+}
+
+/// A sign that an authorization request may be a phishing attempt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Enum)]
+pub enum RiskWarning {
+    /// A domain of the request resembles a known domain it is not part of.
+    LookalikeDomain { domain: String, resembles: String },
+    /// The request object was served from another domain than the domain of
+    /// the client ID, e.g. the DNS name of its certificate or its `did:web`.
+    HostMismatch {
+        request_uri_host: String,
+        client_id_domain: String,
+    },
+    /// The response would be sent to an endpoint that is not HTTPS.
+    InsecureResponseEndpoint { uri: String },
+}
+
+/// Analyze a validated authorization request, and the `request_uri` it was
+/// fetched from, if any.
+pub(crate) fn analyze(
+    config: &RiskAnalysisConfig,
+    request_uri: Option<&str>,
+    request: &AuthorizationRequestObject,
+) -> Vec<RiskWarning> {
+    let mut warnings = vec![];
+
+    let client_id_domain = client_id_domain(
+        draft::client_identifier(request),
+        draft::client_id_scheme(request).as_deref(),
+    );
+    let request_uri_host = request_uri.and_then(host);
+    if let (Some(request_uri_host), Some(client_id_domain)) = (&request_uri_host, &client_id_domain)
+    {
+        if !same_site(request_uri_host, client_id_domain) {
+            warnings.push(RiskWarning::HostMismatch {
+                request_uri_host: request_uri_host.clone(),
+                client_id_domain: client_id_domain.clone(),
+            });
+        }
+    }
+
+    let response_uri = draft::response_endpoint(request);
+    if let Some(uri) = &response_uri {
+        if !Url::parse(uri).is_ok_and(|url| url.scheme() == "https") {
+            warnings.push(RiskWarning::InsecureResponseEndpoint { uri: uri.clone() });
+        }
+    }
+
+    let mut domains = vec![];
+    for domain in [
+        client_id_domain,
+        request_uri_host,
+        response_uri.as_deref().and_then(host),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if !domains.contains(&domain) {
+            domains.push(domain);
+        }
+    }
+    for domain in domains {
+        if let Some(known) = config
+            .known_domains
+            .iter()
+            .find(|known| resembles(&domain, &known.to_lowercase()))
+        {
+            warnings.push(RiskWarning::LookalikeDomain {
+                domain,
+                resembles: known.clone(),
+            });
+        }
+    }
+
+    warnings
+}
+
+/// Return the domain a client ID, without its scheme prefix, is bound to, if
+/// its scheme binds it to one.
+fn client_id_domain(client_id: &str, client_id_scheme: Option<&str>) -> Option<String> {
+    if let Some(did) = client_id.strip_prefix("did:web:") {
+        let domain = urlencoding::decode(did.split(':').next()?).ok()?;
+        // Drop the port, which is percent-encoded in `did:web`s.
+        return domain.split(':').next().map(str::to_lowercase);
+    }
+    if client_id_scheme == Some("x509_san_dns") {
+        return Some(client_id.to_lowercase());
+    }
+    host(client_id)
+}
+
+fn host(uri: &str) -> Option<String> {
+    Url::parse(uri)
+        .ok()?
+        .host_str()
+        .map(|host| host.to_lowercase())
+}
+
+/// Whether two hosts are the same, or one is a subdomain of the other.
+fn same_site(a: &str, b: &str) -> bool {
+    a == b || a.ends_with(&format!(".{b}")) || b.ends_with(&format!(".{a}"))
+}
+
+/// Whether a domain resembles a known domain it is not part of: it reads the
+/// same once confusable characters are replaced, it is one character away,
+/// or it starts with the known domain, e.g. `bank.example.com.attacker.io`.
+fn resembles(domain: &str, known: &str) -> bool {
+    if domain == known || domain.ends_with(&format!(".{known}")) {
+        return false;
+    }
+
+    domain.starts_with(&format!("{known}."))
+        || skeleton(domain) == skeleton(known)
+        || (known.len() >= MIN_EDIT_DISTANCE_LENGTH && edit_distance(domain, known) == 1)
+}
+
+/// Replace the characters and sequences that are commonly confused for
+/// others, and drop hyphens.
+fn skeleton(domain: &str) -> String {
+    domain
+        .replace("rn", "m")
+        .replace("vv", "w")
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | 'i' => 'l',
+            '5' => 's',
+            c => c,
+        })
+        .collect()
+}
+
+/// The Levenshtein distance of two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn flags_risky_requests() {
+        let config = RiskAnalysisConfig {
+            known_domains: vec!["mybank.example".into()],
+        };
+        let request = |client_id: &str, response_uri: &str| -> AuthorizationRequestObject {
+            serde_json::from_value(json!({
+                "client_id": client_id,
+                "response_type": "vp_token",
+                "response_mode": "direct_post",
+                "response_uri": response_uri,
+                "nonce": "n-0S6_WzA2Mj",
+            }))
+            .unwrap()
+        };
+
+        assert_eq!(
+            analyze(
+                &config,
+                Some("https://api.mybank.example/request"),
+                &request(
+                    "x509_san_dns:mybank.example",
+                    "https://mybank.example/response"
+                ),
+            ),
+            vec![]
+        );
+
+        assert_eq!(
+            analyze(
+                &config,
+                Some("https://attacker.example/request"),
+                &request(
+                    "did:web:myb4nk.example%3A8443",
+                    "http://mybank.example.attacker.example/response"
+                ),
+            ),
+            vec![
+                RiskWarning::HostMismatch {
+                    request_uri_host: "attacker.example".into(),
+                    client_id_domain: "myb4nk.example".into(),
+                },
+                RiskWarning::InsecureResponseEndpoint {
+                    uri: "http://mybank.example.attacker.example/response".into(),
+                },
+                RiskWarning::LookalikeDomain {
+                    domain: "myb4nk.example".into(),
+                    resembles: "mybank.example".into(),
+                },
+                RiskWarning::LookalikeDomain {
+                    domain: "mybank.example.attacker.example".into(),
+                    resembles: "mybank.example".into(),
+                },
+            ]
+        );
+
+        assert!(resembles("mybank-example.com", "mybankexample.com"));
+        assert!(resembles("rnybank.example", "mybank.example"));
+        assert!(!resembles("otherbank.example", "mybank.example"));
+    }
+}
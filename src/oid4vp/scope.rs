@@ -0,0 +1,121 @@
+//! Requests naming a pre-registered `scope` instead of passing a presentation
+//! definition.
+//!
+//! OID4VP lets wallets and verifiers agree out of band on the presentation
+//! definitions that scope values stand for. The holder maps scope values to
+//! query templates, the built-in ones by default, e.g. `mdl_age_over_18`.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value as Json};
+use uniffi::deps::anyhow::{self, Context};
+
+/// The namespace of the mDL data elements.
+const MDL_NAMESPACE: &str = "org.iso.18013.5.1";
+
+/// The doctype of mDLs, which is the ID of their input descriptors.
+const MDL_DOCTYPE: &str = "org.iso.18013.5.1.mDL";
+
+/// A query a scope value stands for.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum QueryTemplate {
+    /// Whether the holder of an mDL is over 18.
+    MdlAgeOver18,
+    /// Whether the holder of an mDL is over 21.
+    MdlAgeOver21,
+    /// The name, birth date and portrait of the holder of an mDL.
+    MdlIdentity,
+    /// A custom presentation definition, as JSON.
+    PresentationDefinition { json: String },
+}
+
+impl QueryTemplate {
+    /// Return the presentation definition of the template, identified by the
+    /// scope value it was requested with.
+    pub(crate) fn presentation_definition(&self, scope: &str) -> anyhow::Result<Json> {
+        let elements: &[&str] = match self {
+            Self::MdlAgeOver18 => &["age_over_18"],
+            Self::MdlAgeOver21 => &["age_over_21"],
+            Self::MdlIdentity => &["family_name", "given_name", "birth_date", "portrait"],
+            Self::PresentationDefinition { json } => {
+                return serde_json::from_str(json)
+                    .context("the presentation definition of the scope is not JSON")
+            }
+        };
+
+        let fields = elements
+            .iter()
+            .map(|element| {
+                json!({
+                    "path": [format!("$['{MDL_NAMESPACE}']['{element}']")],
+                    "intent_to_retain": false,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({
+            "id": scope,
+            "input_descriptors": [{
+                "id": MDL_DOCTYPE,
+                "format": { "mso_mdoc": { "alg": ["ES256"] } },
+                "constraints": {
+                    "limit_disclosure": "required",
+                    "fields": fields,
+                },
+            }],
+        }))
+    }
+}
+
+/// Return the built-in query templates, by the scope values they are
+/// requested with.
+#[uniffi::export]
+pub fn default_scope_queries() -> HashMap<String, QueryTemplate> {
+    HashMap::from([
+        ("mdl_age_over_18".into(), QueryTemplate::MdlAgeOver18),
+        ("mdl_age_over_21".into(), QueryTemplate::MdlAgeOver21),
+        ("mdl_identity".into(), QueryTemplate::MdlIdentity),
+    ])
+}
+
+/// Return the first value of a `scope` parameter that has a query template,
+/// and the presentation definition it stands for.
+pub(crate) fn presentation_definition(
+    scope: &str,
+    queries: &HashMap<String, QueryTemplate>,
+) -> Option<(String, anyhow::Result<Json>)> {
+    scope.split_whitespace().find_map(|value| {
+        queries
+            .get(value)
+            .map(|template| (value.to_owned(), template.presentation_definition(value)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openid4vp::core::presentation_definition::PresentationDefinition;
+
+    #[test]
+    fn maps_scopes_to_definitions() {
+        let mut queries = default_scope_queries();
+        queries.insert(
+            "custom".into(),
+            QueryTemplate::PresentationDefinition {
+                json: r#"{"id":"custom","input_descriptors":[]}"#.into(),
+            },
+        );
+
+        let (scope, definition) = presentation_definition("openid mdl_age_over_18", &queries)
+            .expect("the scope has a template");
+        assert_eq!(scope, "mdl_age_over_18");
+        let definition: PresentationDefinition =
+            serde_json::from_value(definition.unwrap()).unwrap();
+        assert_eq!(definition.id(), "mdl_age_over_18");
+        assert_eq!(definition.input_descriptors()[0].id, MDL_DOCTYPE);
+
+        let (_, definition) = presentation_definition("custom", &queries).unwrap();
+        assert_eq!(definition.unwrap()["id"], "custom");
+        assert!(presentation_definition("openid profile", &queries).is_none());
+    }
+}
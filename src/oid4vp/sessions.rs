@@ -0,0 +1,240 @@
+//! Concurrent presentation sessions, for wallets receiving a new
+//! authorization request while another one is pending, e.g. an NFC tap
+//! during a QR code flow.
+//!
+//! Each session holds its permission request under its own ID until it is
+//! submitted, ended, or expires, and its submission can be cancelled without
+//! affecting the other sessions, unlike [Holder::cancel].
+//!
+//! NOTE: flow events are not tagged with the session they belong to.
+
+use super::error::OID4VPError;
+use super::holder::Holder;
+use super::permission_request::{PermissionRequest, PermissionResponse};
+use super::request;
+use crate::common::{Url, Uuid};
+
+use std::collections::HashMap;
+use std::sync::{Arc, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use openid4vp::core::authorization_request::verification::RequestVerifier;
+use tokio::sync::watch;
+
+/// How long sessions last, unless their request expires earlier.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct PresentationSession {
+    pub id: Uuid,
+    pub permission_request: Arc<PermissionRequest>,
+    pub started_at: SystemTime,
+    /// The time the session expires at, at the latest the expiry of its
+    /// request.
+    pub expires_at: SystemTime,
+}
+
+/// A pending session, and the sender cancelling its submission.
+#[derive(Debug)]
+pub(crate) struct Session {
+    session: PresentationSession,
+    cancellation: watch::Sender<()>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl Holder {
+    /// Set how long the following sessions last, unless their request
+    /// expires earlier.
+    pub fn set_session_ttl(&self, ttl: Duration) -> Result<(), OID4VPError> {
+        *self
+            .session_ttl
+            .write()
+            .map_err(|_| OID4VPError::LockError("session_ttl".into()))? = ttl;
+        Ok(())
+    }
+
+    /// Resolve an authorization request, as [Holder::authorization_request],
+    /// in a new session.
+    pub async fn start_session(&self, url: Url) -> Result<PresentationSession, OID4VPError> {
+        let permission_request = self.authorization_request(url).await?;
+        self.register_session(permission_request)
+    }
+
+    /// Return the pending sessions, from the oldest to the newest.
+    pub fn active_sessions(&self) -> Result<Vec<PresentationSession>, OID4VPError> {
+        let mut sessions = self
+            .pending_sessions()?
+            .values()
+            .map(|session| session.session.clone())
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| session.started_at);
+        Ok(sessions)
+    }
+
+    /// Return a pending session.
+    pub fn session(&self, id: Uuid) -> Result<PresentationSession, OID4VPError> {
+        self.pending_sessions()?
+            .get(&id)
+            .map(|session| session.session.clone())
+            .ok_or_else(|| OID4VPError::UnknownSession {
+                session_id: id.to_string(),
+            })
+    }
+
+    /// Submit the permission response to the request of a pending session, as
+    /// [Holder::submit_permission_response], ending the session unless the
+    /// submission can be retried.
+    pub async fn submit_session(
+        &self,
+        id: Uuid,
+        response: Arc<PermissionResponse>,
+    ) -> Result<Option<Url>, OID4VPError> {
+        let mut cancelled = self
+            .pending_sessions()?
+            .get(&id)
+            .map(|session| session.cancellation.subscribe())
+            .ok_or_else(|| OID4VPError::UnknownSession {
+                session_id: id.to_string(),
+            })?;
+
+        let result = tokio::select! {
+            result = self.submit_permission_response(response) => result,
+            _ = cancelled.changed() => Err(OID4VPError::Cancelled),
+        };
+        if !result.as_ref().is_err_and(OID4VPError::is_retryable) {
+            self.end_session(id)?;
+        }
+        result
+    }
+
+    /// End a session, cancelling its in-flight submission, if any.
+    pub fn end_session(&self, id: Uuid) -> Result<(), OID4VPError> {
+        if let Some(session) = self.sessions_lock()?.remove(&id) {
+            session.cancellation.send_replace(());
+        }
+        Ok(())
+    }
+}
+
+impl Holder {
+    /// Start a session for a resolved permission request.
+    pub(crate) fn register_session(
+        &self,
+        permission_request: Arc<PermissionRequest>,
+    ) -> Result<PresentationSession, OID4VPError> {
+        let started_at = self.now()?;
+        let ttl = *self
+            .session_ttl
+            .read()
+            .map_err(|_| OID4VPError::LockError("session_ttl".into()))?;
+        let mut expires_at = started_at + ttl;
+        if let Some(request_expiry) = request::expires_at(&permission_request.request) {
+            expires_at = expires_at.min(request_expiry);
+        }
+
+        let session = PresentationSession {
+            id: Uuid::new_v4(),
+            permission_request,
+            started_at,
+            expires_at,
+        };
+        self.sessions_lock()?.insert(
+            session.id,
+            Session {
+                session: session.clone(),
+                cancellation: watch::channel(()).0,
+            },
+        );
+        Ok(session)
+    }
+
+    /// Lock the sessions, ending the expired ones.
+    fn pending_sessions(&self) -> Result<MutexGuard<'_, HashMap<Uuid, Session>>, OID4VPError> {
+        let now = self.now()?;
+        let mut sessions = self.sessions_lock()?;
+        sessions.retain(|_, session| {
+            let pending = session.session.expires_at > now;
+            if !pending {
+                session.cancellation.send_replace(());
+            }
+            pending
+        });
+        Ok(sessions)
+    }
+
+    fn sessions_lock(&self) -> Result<MutexGuard<'_, HashMap<Uuid, Session>>, OID4VPError> {
+        self.sessions
+            .lock()
+            .map_err(|_| OID4VPError::LockError("sessions".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn permission_request(exp: SystemTime) -> Arc<PermissionRequest> {
+        let definition = serde_json::from_value(json!({
+            "id": "definition",
+            "input_descriptors": [],
+        }))
+        .unwrap();
+        let request = serde_json::from_value(json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+            "exp": exp.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs(),
+        }))
+        .unwrap();
+        PermissionRequest::new(definition, vec![], request)
+    }
+
+    #[tokio::test]
+    async fn tracks_concurrent_sessions() {
+        let holder = Holder::new_with_credentials(vec![], vec![]).await.unwrap();
+        let now = holder.now().unwrap();
+
+        let qr = holder
+            .register_session(permission_request(now + Duration::from_secs(60)))
+            .unwrap();
+        let nfc = holder
+            .register_session(permission_request(now + Duration::from_secs(3600)))
+            .unwrap();
+        let expired = holder
+            .register_session(permission_request(now - Duration::from_secs(1)))
+            .unwrap();
+        assert!(qr.expires_at < nfc.expires_at);
+        assert!(nfc.expires_at <= nfc.started_at + DEFAULT_SESSION_TTL);
+
+        let ids = |holder: &Holder| {
+            holder
+                .active_sessions()
+                .unwrap()
+                .into_iter()
+                .map(|session| session.id)
+                .collect::<Vec<_>>()
+        };
+        let active = ids(&holder);
+        assert_eq!(active.len(), 2);
+        assert!(active.contains(&qr.id) && active.contains(&nfc.id));
+        assert!(matches!(
+            holder.session(expired.id),
+            Err(OID4VPError::UnknownSession { .. })
+        ));
+
+        holder.end_session(qr.id).unwrap();
+        assert_eq!(ids(&holder), [nfc.id]);
+        let response = nfc
+            .permission_request
+            .create_permission_response(vec![])
+            .unwrap();
+        assert!(matches!(
+            holder.submit_session(qr.id, response).await,
+            Err(OID4VPError::UnknownSession { .. })
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+//! Status of cross-device presentations, which the verifier finishes
+//! processing on another device after the response is submitted.
+//!
+//! Verifiers may return a `status_uri` along with, or instead of, the
+//! `redirect_uri` of their response to the submission, reported as a
+//! [FlowEvent::StatusUriReceived]. The status endpoint is polled with `GET`,
+//! and answers with:
+//!
+//! ```json
+//! { "status": "pending" | "verified" | "failed", "error_description": "..." }
+//! ```
+//!
+//! NOTE: WebSocket status endpoints are not supported, as the holder HTTP
+//! client has no WebSocket transport.
+
+use super::error::OID4VPError;
+use super::flow_events::FlowEvent;
+use super::holder::Holder;
+use crate::common::Url;
+use crate::oid4vci::HttpResponse;
+
+use std::time::{Duration, Instant};
+
+use oid4vci::oauth2::http::{header, Method, Request};
+use openid4vp::core::util::AsyncHttpClient;
+use serde_json::Value as Json;
+use uniffi::deps::{anyhow, log};
+
+/// The processing status of a presentation, as reported by the verifier.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum VerifierStatus {
+    /// The verifier has not finished processing the presentation, or the
+    /// watch timed out before it did.
+    Pending,
+    /// The verifier accepted the presentation.
+    Verified,
+    /// The verifier rejected the presentation.
+    Failed { reason: Option<String> },
+}
+
+/// How the status endpoint of a verifier is polled.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct StatusWatchConfig {
+    /// The delay between two polls.
+    pub interval: Duration,
+    /// How long to wait for the verifier to finish.
+    pub timeout: Duration,
+}
+
+impl Default for StatusWatchConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(2),
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+/// Return the default configuration, polling every 2 seconds for up to 2
+/// minutes.
+#[uniffi::export]
+pub fn default_status_watch_config() -> StatusWatchConfig {
+    StatusWatchConfig::default()
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl Holder {
+    /// Poll the status endpoint of a verifier until it has finished
+    /// processing the presentation, or the watch times out, returning its
+    /// last status.
+    ///
+    /// Every change of status is reported as a
+    /// [FlowEvent::VerifierStatusChanged]. Polls failing, e.g. while the
+    /// device is offline, are retried until the timeout.
+    pub async fn watch_verifier_status(
+        &self,
+        status_uri: Url,
+        config: StatusWatchConfig,
+    ) -> Result<VerifierStatus, OID4VPError> {
+        let deadline = Instant::now() + config.timeout;
+        let mut last = VerifierStatus::Pending;
+
+        self.cancellable(async {
+            loop {
+                match fetch_status(self.client.uncached(), &status_uri).await {
+                    Ok(status) if status != last => {
+                        last = status;
+                        self.emit(FlowEvent::VerifierStatusChanged {
+                            status: last.clone(),
+                        });
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::warn!("Failed to poll the status of {status_uri}: {e:?}"),
+                }
+
+                if last != VerifierStatus::Pending || Instant::now() + config.interval > deadline {
+                    return Ok(last.clone());
+                }
+                tokio::time::sleep(config.interval).await;
+            }
+        })
+        .await
+    }
+}
+
+/// Read the optional `status_uri` of the response of the verifier to a
+/// submission.
+pub(crate) fn status_uri(response: &HttpResponse) -> Option<Url> {
+    let body: Json = serde_json::from_slice(&response.body).ok()?;
+    Url::parse(body.get("status_uri")?.as_str()?).ok()
+}
+
+async fn fetch_status(
+    client: &impl AsyncHttpClient,
+    status_uri: &Url,
+) -> anyhow::Result<VerifierStatus> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(status_uri.as_str())
+        .header(header::ACCEPT, "application/json")
+        .body(vec![])?;
+
+    let response = client.execute(request).await?;
+    if !response.status().is_success() {
+        anyhow::bail!("request to {status_uri} failed: {}", response.status());
+    }
+    parse_status(&serde_json::from_slice(response.body())?)
+}
+
+fn parse_status(body: &Json) -> anyhow::Result<VerifierStatus> {
+    match body.get("status").and_then(Json::as_str) {
+        Some("pending") => Ok(VerifierStatus::Pending),
+        Some("verified") => Ok(VerifierStatus::Verified),
+        Some("failed") => Ok(VerifierStatus::Failed {
+            reason: body
+                .get("error_description")
+                .and_then(Json::as_str)
+                .map(ToOwned::to_owned),
+        }),
+        status => anyhow::bail!("unknown status: {status:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn parses_statuses() {
+        assert_eq!(
+            parse_status(&json!({ "status": "pending" })).unwrap(),
+            VerifierStatus::Pending
+        );
+        assert_eq!(
+            parse_status(&json!({ "status": "verified" })).unwrap(),
+            VerifierStatus::Verified
+        );
+        assert_eq!(
+            parse_status(&json!({ "status": "failed", "error_description": "Expired" })).unwrap(),
+            VerifierStatus::Failed {
+                reason: Some("Expired".into())
+            }
+        );
+        assert!(parse_status(&json!({})).is_err());
+
+        let response = HttpResponse {
+            status_code: 200,
+            headers: Default::default(),
+            body: br#"{"status_uri":"https://verifier.example.com/status/abc"}"#.to_vec(),
+        };
+        assert_eq!(
+            status_uri(&response),
+            Some(Url::parse("https://verifier.example.com/status/abc").unwrap())
+        );
+    }
+}
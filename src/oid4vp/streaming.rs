@@ -0,0 +1,107 @@
+use super::error::OID4VPError;
+use super::holder::Holder;
+use super::permission_request::PermissionRequest;
+use crate::common::Url;
+use crate::credential::ParsedCredential;
+
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    /// Set while matching credentials for a streaming authorization request.
+    static MATCH_DELEGATE: Arc<dyn CredentialMatchDelegate>;
+}
+
+/// Interface: CredentialMatchDelegate
+///
+/// The CredentialMatchDelegate is called with each credential matching an
+/// authorization request as it is found, so that the selection UI can render
+/// progressively on large wallets.
+#[uniffi::export(with_foreign)]
+pub trait CredentialMatchDelegate: Send + Sync + std::fmt::Debug {
+    fn on_credential_matched(&self, credential: Arc<ParsedCredential>);
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl Holder {
+    /// As [Holder::authorization_request], calling the delegate with each
+    /// matching credential as it is found.
+    ///
+    /// The returned permission request holds every match, in the order the
+    /// delegate was called.
+    pub async fn authorization_request_streaming(
+        &self,
+        url: Url,
+        delegate: Arc<dyn CredentialMatchDelegate>,
+    ) -> Result<Arc<PermissionRequest>, OID4VPError> {
+        stream_matches(delegate, self.authorization_request(url)).await
+    }
+}
+
+/// Run a future, calling the delegate with the credentials matched while it
+/// runs.
+pub(crate) async fn stream_matches<F: Future>(
+    delegate: Arc<dyn CredentialMatchDelegate>,
+    future: F,
+) -> F::Output {
+    MATCH_DELEGATE.scope(delegate, future).await
+}
+
+/// Notify the delegate of the streaming authorization request, if any, of a
+/// matching credential.
+pub(crate) fn emit_match(credential: &Arc<ParsedCredential>) {
+    let _ = MATCH_DELEGATE.try_with(|delegate| delegate.on_credential_matched(credential.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::json_vc::JsonVc;
+
+    use openid4vp::core::presentation_definition::PresentationDefinition;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct TestDelegate(Mutex<Vec<Arc<ParsedCredential>>>);
+
+    impl CredentialMatchDelegate for TestDelegate {
+        fn on_credential_matched(&self, credential: Arc<ParsedCredential>) {
+            self.0.lock().unwrap().push(credential);
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_matching_credentials() {
+        let json_vc =
+            JsonVc::new_from_json(include_str!("../../tests/examples/vehicle_title.json").into())
+                .unwrap();
+        let credential = ParsedCredential::new_ldp_vc(json_vc);
+        let holder = Holder::new_with_credentials(vec![credential.clone()], vec![])
+            .await
+            .unwrap();
+
+        let definition: PresentationDefinition = serde_json::from_value(serde_json::json!({
+            "id": "vehicle-title",
+            "input_descriptors": [{
+                "id": "vehicle-title",
+                "constraints": {
+                    "fields": [{ "path": ["$.credentialSubject.vehicle.vehicleIdentificationNumber"] }]
+                }
+            }]
+        }))
+        .unwrap();
+
+        let delegate = Arc::new(TestDelegate::default());
+        let matches = stream_matches(
+            delegate.clone(),
+            holder.search_credentials_vs_presentation_definition(&definition),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(matches.len(), 1);
+        let streamed = delegate.0.lock().unwrap();
+        assert_eq!(streamed.len(), 1);
+        assert_eq!(streamed[0].id(), credential.id());
+    }
+}
@@ -0,0 +1,455 @@
+//! Presentation definition submission requirements.
+//!
+//! Submission requirements describe which combinations of input descriptors
+//! satisfy a presentation definition, for example "all of group A, and one of
+//! group B". Input descriptors are assigned to groups with their `group`
+//! property, and requirements select either from a group or from nested
+//! requirements.
+//!
+//! When a definition has no submission requirements, every input descriptor
+//! must be satisfied.
+
+use super::permission_request::{PermissionRequest, PermissionRequestError};
+use crate::credential::{disclosure, ParsedCredential};
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use serde_json::Value as Json;
+
+/// Possible input descriptor combinations are only enumerated up to this many
+/// input descriptors.
+const MAX_COMBINATION_DESCRIPTORS: usize = 12;
+
+/// The rule of a submission requirement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum SubmissionRule {
+    /// All of the input descriptors or nested requirements are required.
+    All,
+    /// A number of the input descriptors or nested requirements are required,
+    /// given by `count`, or by `min` and `max`.
+    Pick,
+}
+
+/// A submission requirement of a presentation definition.
+#[derive(Debug, Clone, uniffi::Object)]
+pub struct SubmissionRequirement {
+    pub(crate) name: Option<String>,
+    pub(crate) purpose: Option<String>,
+    pub(crate) rule: SubmissionRule,
+    pub(crate) count: Option<u32>,
+    pub(crate) min: Option<u32>,
+    pub(crate) max: Option<u32>,
+    pub(crate) input_descriptor_ids: Vec<String>,
+    pub(crate) nested: Vec<Arc<SubmissionRequirement>>,
+}
+
+#[uniffi::export]
+impl SubmissionRequirement {
+    /// Return the name of the requirement.
+    pub fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    /// Return the purpose of the requirement.
+    pub fn purpose(&self) -> Option<String> {
+        self.purpose.clone()
+    }
+
+    /// Return the rule of the requirement.
+    pub fn rule(&self) -> SubmissionRule {
+        self.rule
+    }
+
+    /// Return the exact number of selections required by a `pick` rule.
+    pub fn count(&self) -> Option<u32> {
+        self.count
+    }
+
+    /// Return the minimum number of selections required by a `pick` rule.
+    pub fn min(&self) -> Option<u32> {
+        self.min
+    }
+
+    /// Return the maximum number of selections allowed by a `pick` rule.
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
+
+    /// Return the ids of the input descriptors in the group the requirement
+    /// selects from. This is empty for requirements selecting from nested
+    /// requirements.
+    pub fn input_descriptor_ids(&self) -> Vec<String> {
+        self.input_descriptor_ids.clone()
+    }
+
+    /// Return the nested requirements the requirement selects from.
+    pub fn nested(&self) -> Vec<Arc<SubmissionRequirement>> {
+        self.nested.clone()
+    }
+}
+
+impl SubmissionRequirement {
+    fn parse(requirement: &Json, descriptors: &[Json]) -> Option<Self> {
+        let rule = match requirement["rule"].as_str()? {
+            "all" => SubmissionRule::All,
+            "pick" => SubmissionRule::Pick,
+            _ => return None,
+        };
+        let number = |key: &str| requirement[key].as_u64().map(|n| n as u32);
+
+        let input_descriptor_ids = match requirement["from"].as_str() {
+            Some(group) => descriptors
+                .iter()
+                .filter(|descriptor| {
+                    descriptor["group"]
+                        .as_array()
+                        .is_some_and(|groups| groups.iter().any(|g| g == group))
+                })
+                .filter_map(|descriptor| descriptor["id"].as_str().map(ToOwned::to_owned))
+                .collect(),
+            None => vec![],
+        };
+
+        let nested = requirement["from_nested"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|nested| Self::parse(nested, descriptors).map(Arc::new))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            name: requirement["name"].as_str().map(ToOwned::to_owned),
+            purpose: requirement["purpose"].as_str().map(ToOwned::to_owned),
+            rule,
+            count: number("count"),
+            min: number("min"),
+            max: number("max"),
+            input_descriptor_ids,
+            nested,
+        })
+    }
+
+    /// Check whether the requirement is satisfied by the input descriptors.
+    pub(crate) fn is_satisfied(&self, input_descriptor_ids: &BTreeSet<String>) -> bool {
+        let (total, satisfied) = if self.nested.is_empty() {
+            (
+                self.input_descriptor_ids.len(),
+                self.input_descriptor_ids
+                    .iter()
+                    .filter(|id| input_descriptor_ids.contains(*id))
+                    .count(),
+            )
+        } else {
+            (
+                self.nested.len(),
+                self.nested
+                    .iter()
+                    .filter(|nested| nested.is_satisfied(input_descriptor_ids))
+                    .count(),
+            )
+        };
+
+        match self.rule {
+            SubmissionRule::All => satisfied == total,
+            SubmissionRule::Pick => match self.count {
+                Some(count) => satisfied == count as usize,
+                None => {
+                    self.min.map_or(0, |min| min as usize) <= satisfied
+                        && satisfied <= self.max.map_or(usize::MAX, |max| max as usize)
+                }
+            },
+        }
+    }
+}
+
+/// The submission requirements of a presentation definition, along with its
+/// input descriptors.
+#[derive(Debug, Clone)]
+pub(crate) struct SubmissionRequirements {
+    pub requirements: Vec<Arc<SubmissionRequirement>>,
+    pub descriptors: Vec<Json>,
+}
+
+impl SubmissionRequirements {
+    pub fn new(definition: &PresentationDefinition) -> Self {
+        let definition = serde_json::to_value(definition).unwrap_or_default();
+        let descriptors = definition["input_descriptors"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+
+        let requirements = definition["submission_requirements"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|requirement| SubmissionRequirement::parse(requirement, &descriptors))
+            .map(Arc::new)
+            .collect();
+
+        Self {
+            requirements,
+            descriptors,
+        }
+    }
+
+    /// Return the ids of all of the input descriptors.
+    pub fn input_descriptor_ids(&self) -> Vec<String> {
+        self.descriptors
+            .iter()
+            .filter_map(|descriptor| descriptor["id"].as_str().map(ToOwned::to_owned))
+            .collect()
+    }
+
+    /// Check whether satisfying the input descriptors satisfies the definition.
+    pub fn is_satisfied(&self, input_descriptor_ids: &BTreeSet<String>) -> bool {
+        if self.requirements.is_empty() {
+            return self
+                .input_descriptor_ids()
+                .iter()
+                .all(|id| input_descriptor_ids.contains(id));
+        }
+
+        self.requirements
+            .iter()
+            .all(|requirement| requirement.is_satisfied(input_descriptor_ids))
+    }
+
+    /// Check whether the credential satisfies the input descriptor: every
+    /// required field of the descriptor must select a value in the credential.
+    pub fn satisfies(&self, credential: &ParsedCredential, input_descriptor_id: &str) -> bool {
+        let Some(descriptor) = self
+            .descriptors
+            .iter()
+            .find(|descriptor| descriptor["id"] == input_descriptor_id)
+        else {
+            return false;
+        };
+
+        if credential.as_mso_mdoc().is_some() && credential.r#type().0 != input_descriptor_id {
+            return false;
+        }
+
+        let Some(json) = credential.definition_json() else {
+            return false;
+        };
+
+        descriptor["constraints"]["fields"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|field| !field["optional"].as_bool().unwrap_or(false))
+            .all(|field| {
+                field["path"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Json::as_str)
+                    .any(|path| !disclosure::select_path(&json, path).is_empty())
+            })
+    }
+
+    /// Assign each credential to an input descriptor it satisfies, using each
+    /// input descriptor at most once. Credentials are assigned in order, to the
+    /// first input descriptor still available.
+    pub fn assign(&self, credentials: &[Arc<ParsedCredential>]) -> Vec<Option<String>> {
+        let mut assigned = BTreeSet::new();
+
+        credentials
+            .iter()
+            .map(|credential| {
+                let id = self
+                    .input_descriptor_ids()
+                    .into_iter()
+                    .find(|id| !assigned.contains(id) && self.satisfies(credential, id))?;
+                assigned.insert(id.clone());
+                Some(id)
+            })
+            .collect()
+    }
+}
+
+/// A combination of input descriptors that satisfies the presentation
+/// definition, along with the credentials that can be used for it.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct CredentialCombination {
+    /// The ids of the input descriptors to be satisfied.
+    pub input_descriptor_ids: Vec<String>,
+    /// For each input descriptor, in order, the first matching credential.
+    ///
+    /// Alternatives are returned by
+    /// [PermissionRequest::credentials_for_input_descriptor].
+    pub credentials: Vec<Arc<ParsedCredential>>,
+}
+
+#[uniffi::export]
+impl PermissionRequest {
+    /// Return the submission requirements of the presentation definition.
+    ///
+    /// This is empty when every input descriptor is required.
+    pub fn submission_requirements(&self) -> Vec<Arc<SubmissionRequirement>> {
+        SubmissionRequirements::new(&self.definition).requirements
+    }
+
+    /// Return the credentials that satisfy the input descriptor.
+    pub fn credentials_for_input_descriptor(
+        &self,
+        input_descriptor_id: String,
+    ) -> Vec<Arc<ParsedCredential>> {
+        let requirements = SubmissionRequirements::new(&self.definition);
+
+        self.credentials
+            .iter()
+            .filter(|credential| requirements.satisfies(credential, &input_descriptor_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Return the combinations of input descriptors that satisfy the
+    /// presentation definition with the available credentials, smallest first.
+    pub fn credential_combinations(&self) -> Vec<CredentialCombination> {
+        let requirements = SubmissionRequirements::new(&self.definition);
+
+        let candidates = requirements
+            .input_descriptor_ids()
+            .into_iter()
+            .filter_map(|id| {
+                let credential = self
+                    .credentials
+                    .iter()
+                    .find(|credential| requirements.satisfies(credential, &id))?;
+                Some((id, credential.clone()))
+            })
+            .take(MAX_COMBINATION_DESCRIPTORS)
+            .collect::<Vec<_>>();
+
+        let mut combinations = (0..1u32 << candidates.len())
+            .map(|mask| {
+                candidates
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, _)| mask & (1 << idx) != 0)
+                    .map(|(_, candidate)| candidate.clone())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|combination| !combination.is_empty())
+            .filter(|combination| {
+                requirements.is_satisfied(&combination.iter().map(|(id, _)| id.clone()).collect())
+            })
+            .map(|combination| {
+                let (input_descriptor_ids, credentials) = combination.into_iter().unzip();
+                CredentialCombination {
+                    input_descriptor_ids,
+                    credentials,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        combinations.sort_by_key(|combination| combination.input_descriptor_ids.len());
+        combinations
+    }
+
+    /// Check that the selected credentials satisfy the submission requirements
+    /// of the presentation definition.
+    pub fn validate_selection(
+        &self,
+        selected_credentials: Vec<Arc<ParsedCredential>>,
+    ) -> Result<(), PermissionRequestError> {
+        validate_selection(&self.definition, &selected_credentials)
+            .map_err(PermissionRequestError::SubmissionRequirementsNotMet)
+    }
+}
+
+/// Check that the credentials satisfy the submission requirements of the
+/// definition, returning a description of the failure otherwise.
+pub(crate) fn validate_selection(
+    definition: &PresentationDefinition,
+    credentials: &[Arc<ParsedCredential>],
+) -> Result<(), String> {
+    let requirements = SubmissionRequirements::new(definition);
+
+    let assigned = requirements
+        .assign(credentials)
+        .into_iter()
+        .zip(credentials)
+        .map(|(id, credential)| {
+            id.ok_or_else(|| {
+                format!(
+                    "credential {} does not satisfy any input descriptor",
+                    credential.id()
+                )
+            })
+        })
+        .collect::<Result<BTreeSet<_>, _>>()?;
+
+    if !requirements.is_satisfied(&assigned) {
+        return Err(format!(
+            "input descriptors {assigned:?} do not satisfy the submission requirements"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirements(definition: Json) -> SubmissionRequirements {
+        SubmissionRequirements::new(&serde_json::from_value(definition).unwrap())
+    }
+
+    fn ids(ids: &[&str]) -> BTreeSet<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn evaluates_pick_and_nested_rules() {
+        let requirements = requirements(serde_json::json!({
+            "id": "example",
+            "submission_requirements": [
+                { "name": "Citizenship", "rule": "pick", "count": 1, "from": "A" },
+                {
+                    "rule": "pick",
+                    "min": 1,
+                    "from_nested": [
+                        { "rule": "all", "from": "B" },
+                        { "rule": "pick", "max": 1, "from": "C" }
+                    ]
+                }
+            ],
+            "input_descriptors": [
+                { "id": "passport", "group": ["A"], "constraints": {} },
+                { "id": "drivers_license", "group": ["A"], "constraints": {} },
+                { "id": "bank_statement", "group": ["B"], "constraints": {} },
+                { "id": "utility_bill", "group": ["B"], "constraints": {} },
+                { "id": "employment", "group": ["C"], "constraints": {} }
+            ]
+        }));
+
+        assert_eq!(requirements.requirements.len(), 2);
+        assert_eq!(requirements.requirements[1].nested.len(), 2);
+
+        // Group C allows picking none, which satisfies the nested minimum.
+        assert!(requirements.is_satisfied(&ids(&["passport"])));
+        assert!(requirements.is_satisfied(&ids(&["passport", "employment"])));
+        assert!(requirements.is_satisfied(&ids(&["passport", "bank_statement", "utility_bill"])));
+        assert!(!requirements.is_satisfied(&ids(&["passport", "drivers_license"])));
+        assert!(!requirements.is_satisfied(&ids(&["employment"])));
+    }
+
+    #[test]
+    fn requires_all_descriptors_without_requirements() {
+        let requirements = requirements(serde_json::json!({
+            "id": "example",
+            "input_descriptors": [
+                { "id": "a", "constraints": {} },
+                { "id": "b", "constraints": {} }
+            ]
+        }));
+
+        assert!(requirements.is_satisfied(&ids(&["a", "b"])));
+        assert!(!requirements.is_satisfied(&ids(&["a"])));
+    }
+}
@@ -0,0 +1,220 @@
+//! The `transaction_data` request parameter, with which verifiers ask the
+//! holder to authorize a transaction, such as a payment or a qualified
+//! electronic signature, along with the presentation.
+
+use super::error::OID4VPError;
+use super::request;
+
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+
+use base64::prelude::*;
+use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
+
+/// The hash algorithm used for the transaction data hashes of the response.
+pub(crate) const HASH_ALGORITHM: &str = "sha-256";
+
+/// A transaction the verifier asks the holder to authorize.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct TransactionData {
+    /// The type of the transaction, e.g. `payment_data`.
+    pub r#type: String,
+    /// The ids of the requested credentials that can authorize the
+    /// transaction, or every credential when empty.
+    pub credential_ids: Vec<String>,
+    /// The amount of a payment, if any.
+    pub amount: Option<String>,
+    /// The currency of the amount, if any.
+    pub currency: Option<String>,
+    /// The payee of a payment, if any.
+    pub payee: Option<String>,
+    /// The hashes of the documents to sign, if any.
+    pub document_hashes: Vec<String>,
+    /// The decoded transaction data, as a JSON encoded string.
+    pub json: String,
+    /// The transaction data as encoded in the request.
+    pub encoded: String,
+}
+
+impl TransactionData {
+    /// Decode an entry of the `transaction_data` parameter.
+    fn decode(encoded: &str) -> Result<Self, OID4VPError> {
+        let invalid = |reason: &str| OID4VPError::InvalidTransactionData(reason.into());
+
+        let json: Json = BASE64_URL_SAFE_NO_PAD
+            .decode(encoded.trim_end_matches('='))
+            .ok()
+            .and_then(|decoded| serde_json::from_slice(&decoded).ok())
+            .ok_or_else(|| invalid("not base64url encoded JSON"))?;
+
+        let r#type = json["type"]
+            .as_str()
+            .ok_or_else(|| invalid("missing type"))?
+            .to_owned();
+
+        let strings = |value: &Json| -> Vec<String> {
+            value
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Json::as_str)
+                .map(Into::into)
+                .collect()
+        };
+
+        let hash_algorithms = strings(&json["transaction_data_hashes_alg"]);
+        if !hash_algorithms.is_empty() && !hash_algorithms.iter().any(|alg| alg == HASH_ALGORITHM) {
+            return Err(invalid("unsupported transaction data hash algorithms"));
+        }
+
+        let credential_ids = match json.get("credential_ids") {
+            Some(ids) => strings(ids),
+            // Earlier drafts refer to the input descriptors instead.
+            None => strings(&json["input_descriptor_ids"]),
+        };
+
+        let payee = lookup(&json, &[&["payment_data", "payee"], &["payee"]]).and_then(|payee| {
+            payee
+                .as_str()
+                .or_else(|| payee["name"].as_str())
+                .map(Into::into)
+        });
+
+        let document_hashes = json["documentDigests"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|digest| digest["hash"].as_str())
+            .chain(json["document_hash"].as_str())
+            .map(Into::into)
+            .collect();
+
+        Ok(Self {
+            r#type,
+            credential_ids,
+            amount: lookup(
+                &json,
+                &[
+                    &["payment_data", "currency_amount", "value"],
+                    &["currency_amount", "value"],
+                    &["amount"],
+                ],
+            )
+            .and_then(scalar),
+            currency: lookup(
+                &json,
+                &[
+                    &["payment_data", "currency_amount", "currency"],
+                    &["currency_amount", "currency"],
+                    &["currency"],
+                ],
+            )
+            .and_then(scalar),
+            payee,
+            document_hashes,
+            json: json.to_string(),
+            encoded: encoded.to_owned(),
+        })
+    }
+
+    /// Check whether the transaction can be authorized with the credential
+    /// requested by the input descriptor.
+    pub(crate) fn applies_to(&self, input_descriptor_id: Option<&str>) -> bool {
+        self.credential_ids.is_empty()
+            || input_descriptor_id.is_some_and(|id| self.credential_ids.iter().any(|c| c == id))
+    }
+
+    /// Return the hash of the transaction data, as included in the key
+    /// binding JWT of the response.
+    pub(crate) fn hash(&self) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(self.encoded.as_bytes()))
+    }
+}
+
+/// Return the first value found at the paths.
+fn lookup<'a>(json: &'a Json, paths: &[&[&str]]) -> Option<&'a Json> {
+    paths.iter().find_map(|path| {
+        path.iter()
+            .try_fold(json, |value, segment| value.get(segment))
+            .filter(|value| !value.is_null())
+    })
+}
+
+/// Return a string or number value as a string.
+fn scalar(value: &Json) -> Option<String> {
+    match value {
+        Json::String(value) => Some(value.clone()),
+        Json::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Decode the `transaction_data` parameter of an authorization request.
+pub(crate) fn from_request(
+    request: &AuthorizationRequestObject,
+) -> Result<Vec<TransactionData>, OID4VPError> {
+    let Some(entries) = request::parameters(request).remove("transaction_data") else {
+        return Ok(vec![]);
+    };
+
+    entries
+        .as_array()
+        .ok_or_else(|| OID4VPError::InvalidTransactionData("not an array".into()))?
+        .iter()
+        .map(|entry| {
+            entry
+                .as_str()
+                .ok_or_else(|| OID4VPError::InvalidTransactionData("not a string".into()))
+                .and_then(TransactionData::decode)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: Json) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(value.to_string())
+    }
+
+    #[test]
+    fn decodes_transaction_data() {
+        let payment = TransactionData::decode(&encode(serde_json::json!({
+            "type": "payment_data",
+            "credential_ids": ["bank_account"],
+            "payment_data": {
+                "payee": { "name": "Merchant" },
+                "currency_amount": { "currency": "EUR", "value": 23.58 }
+            }
+        })))
+        .unwrap();
+        assert_eq!(payment.amount.as_deref(), Some("23.58"));
+        assert_eq!(payment.currency.as_deref(), Some("EUR"));
+        assert_eq!(payment.payee.as_deref(), Some("Merchant"));
+        assert!(payment.applies_to(Some("bank_account")));
+        assert!(!payment.applies_to(Some("pid")));
+
+        let signature = TransactionData::decode(&encode(serde_json::json!({
+            "type": "qes_authorization",
+            "input_descriptor_ids": ["pid"],
+            "transaction_data_hashes_alg": ["sha-256"],
+            "documentDigests": [{ "label": "contract.pdf", "hash": "sTOgwOm+474gFj0q0x1iSNspKqbcse4IeiqlDg/HWuI=" }]
+        })))
+        .unwrap();
+        assert_eq!(signature.credential_ids, vec!["pid"]);
+        assert_eq!(
+            signature.document_hashes,
+            vec!["sTOgwOm+474gFj0q0x1iSNspKqbcse4IeiqlDg/HWuI="]
+        );
+
+        assert!(matches!(
+            TransactionData::decode(&encode(serde_json::json!({
+                "type": "payment_data",
+                "transaction_data_hashes_alg": ["sha-384"]
+            }))),
+            Err(OID4VPError::InvalidTransactionData(_))
+        ));
+        assert!(TransactionData::decode("not json").is_err());
+    }
+}
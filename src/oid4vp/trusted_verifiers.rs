@@ -0,0 +1,192 @@
+//! Verifiers trusted by the holder at runtime.
+//!
+//! Unlike the trusted DIDs the holder is constructed with, the verifiers of
+//! the [TrustedVerifierStore] are persisted in the storage manager, each with
+//! a policy consulted when it requests credentials.
+
+use std::sync::Arc;
+
+use crate::storage_manager::*;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Internal prefix for trusted verifier keys.
+const KEY_PREFIX: &str = "TrustedVerifier.";
+
+/// How the requests of a trusted verifier are reviewed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, uniffi::Enum)]
+pub enum VerifierPolicy {
+    /// Proceed without asking the verifier review delegate.
+    AlwaysAllow,
+    /// Ask the verifier review delegate on every request, even when the
+    /// verifier is one of the trusted DIDs.
+    AlwaysAsk,
+}
+
+/// A verifier of the trust store, with its policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct TrustedVerifier {
+    /// The client ID of the verifier, e.g. its DID.
+    pub client_id: String,
+    pub policy: VerifierPolicy,
+    /// The names of the optional fields that are never disclosed to the
+    /// verifier, as returned by [super::permission_request::RequestedField::name].
+    pub denied_fields: Vec<String>,
+}
+
+#[derive(Error, Debug, uniffi::Error)]
+pub enum TrustedVerifierError {
+    /// Attempt to convert the verifier to a serialized form suitable for writing to storage failed.
+    #[error("Failed to Serialize Value")]
+    SerializeFailed,
+
+    /// Attempting to convert the verifier to a deserialized form suitable for runtime use failed.
+    #[error("Failed to Deserialize Value")]
+    DeserializeFailed,
+
+    /// Attempting to write the verifier to storage failed.
+    #[error("Failed to Write to Storage")]
+    StoreFailed(StorageManagerError),
+
+    /// Attempting to read the verifier from storage failed.
+    #[error("Failed to Read from Storage")]
+    LoadFailed(StorageManagerError),
+
+    /// Attempting to delete a verifier from storage failed.
+    #[error("Failed to Delete from Storage")]
+    DeleteFailed(StorageManagerError),
+}
+
+impl TrustedVerifierError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::SerializeFailed => "oid4vp.trusted_verifier_serialize_failed",
+            Self::DeserializeFailed => "oid4vp.trusted_verifier_deserialize_failed",
+            Self::StoreFailed(..) => "oid4vp.trusted_verifier_store_failed",
+            Self::LoadFailed(..) => "oid4vp.trusted_verifier_load_failed",
+            Self::DeleteFailed(..) => "oid4vp.trusted_verifier_delete_failed",
+        }
+    }
+}
+
+/// Trusted Verifier Store
+///
+/// A store of the verifiers the holder trusts, persisted in the storage
+/// manager. Verifiers allowed by the user through the verifier review delegate
+/// are added to it with the [VerifierPolicy::AlwaysAllow] policy.
+#[derive(Debug, uniffi::Object)]
+pub struct TrustedVerifierStore {
+    pub(crate) storage: Arc<dyn StorageManagerInterface>,
+}
+
+#[uniffi::export]
+impl TrustedVerifierStore {
+    #[uniffi::constructor]
+    pub fn new(engine: Arc<dyn StorageManagerInterface>) -> Arc<Self> {
+        Arc::new(Self { storage: engine })
+    }
+
+    /// Add a verifier to the store, replacing its policy if it is already
+    /// trusted.
+    pub fn add(&self, verifier: TrustedVerifier) -> Result<(), TrustedVerifierError> {
+        let value =
+            serde_cbor::to_vec(&verifier).map_err(|_| TrustedVerifierError::SerializeFailed)?;
+
+        self.storage
+            .add(Self::key(&verifier.client_id), Value(value))
+            .map_err(TrustedVerifierError::StoreFailed)
+    }
+
+    /// Get a verifier from the store.
+    pub fn get(&self, client_id: String) -> Result<Option<TrustedVerifier>, TrustedVerifierError> {
+        self.load(Self::key(&client_id))
+    }
+
+    /// Remove a verifier from the store.
+    pub fn remove(&self, client_id: String) -> Result<(), TrustedVerifierError> {
+        self.storage
+            .remove(Self::key(&client_id))
+            .map_err(TrustedVerifierError::DeleteFailed)
+    }
+
+    /// Get every verifier of the store, by client ID.
+    pub fn list(&self) -> Result<Vec<TrustedVerifier>, TrustedVerifierError> {
+        let mut verifiers = self
+            .storage
+            .list()
+            .map_err(TrustedVerifierError::LoadFailed)?
+            .into_iter()
+            .filter(|key| key.0.starts_with(KEY_PREFIX))
+            .filter_map(|key| self.load(key).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        verifiers.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        Ok(verifiers)
+    }
+}
+
+impl TrustedVerifierStore {
+    fn key(client_id: &str) -> Key {
+        Key(format!("{KEY_PREFIX}{client_id}"))
+    }
+
+    fn load(&self, key: Key) -> Result<Option<TrustedVerifier>, TrustedVerifierError> {
+        let Some(raw) = self
+            .storage
+            .get(key)
+            .map_err(TrustedVerifierError::LoadFailed)?
+        else {
+            return Ok(None);
+        };
+
+        serde_cbor::from_slice(&raw.0)
+            .map(Some)
+            .map_err(|_| TrustedVerifierError::DeserializeFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+
+    #[test]
+    fn stores_trusted_verifiers() {
+        let storage = Arc::new(LocalStore::new());
+        let store = TrustedVerifierStore::new(storage.clone());
+
+        // Other entries in the storage are ignored.
+        storage
+            .add(Key("Credential.1".into()), Value(vec![1, 2, 3]))
+            .unwrap();
+
+        let verifier = |client_id: &str, policy| TrustedVerifier {
+            client_id: client_id.into(),
+            policy,
+            denied_fields: vec![],
+        };
+        store
+            .add(verifier("did:web:b", VerifierPolicy::AlwaysAsk))
+            .unwrap();
+        store
+            .add(verifier("did:web:a", VerifierPolicy::AlwaysAsk))
+            .unwrap();
+        store
+            .add(verifier("did:web:a", VerifierPolicy::AlwaysAllow))
+            .unwrap();
+
+        assert_eq!(
+            store.list().unwrap(),
+            vec![
+                verifier("did:web:a", VerifierPolicy::AlwaysAllow),
+                verifier("did:web:b", VerifierPolicy::AlwaysAsk),
+            ]
+        );
+
+        store.remove("did:web:b".into()).unwrap();
+        assert!(store.get("did:web:b".into()).unwrap().is_none());
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+}
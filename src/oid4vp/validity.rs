@@ -0,0 +1,145 @@
+//! Validity windows of request objects, from their `exp`, `nbf` and `iat`
+//! claims, checked with the clock of the holder and its leeway for skewed
+//! clocks, see [Holder::set_clock_leeway](super::holder::Holder::set_clock_leeway).
+
+use super::error::OID4VPError;
+use super::permission_request::PermissionRequest;
+use super::persistence;
+use super::request;
+use crate::clock;
+
+use std::time::{Duration, SystemTime};
+
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+
+#[uniffi::export]
+impl PermissionRequest {
+    /// Return how long the request remains valid by the clock of the holder,
+    /// allowing for its leeway, or `None` when it does not expire, for consent
+    /// screens to time out instead of submitting to a dead `response_uri`.
+    pub fn remaining_validity(&self) -> Option<Duration> {
+        let expires_at = request::expires_at(&self.request)?;
+        // Expiries past the representable times never expire.
+        Some(
+            expires_at
+                .checked_add(self.clock_leeway)
+                .map_or(Duration::MAX, |expires_at| {
+                    expires_at
+                        .duration_since(self.clock.now())
+                        .unwrap_or(Duration::ZERO)
+                }),
+        )
+    }
+}
+
+/// Fail when the request has expired, is not valid yet, or was issued in the
+/// future at `now`, allowing for the leeway.
+pub(crate) fn check_validity(
+    request: &AuthorizationRequestObject,
+    now: SystemTime,
+    leeway: Duration,
+) -> Result<(), OID4VPError> {
+    persistence::check_expiry(request, now, leeway)?;
+
+    if let Some(not_before) = request::time_claim(request, "nbf") {
+        if !clock::has_reached(not_before, now, leeway) {
+            return Err(OID4VPError::RequestNotYetValid {
+                valid_from: not_before,
+            });
+        }
+    }
+    if let Some(issued_at) = request::time_claim(request, "iat") {
+        if !clock::has_reached(issued_at, now, leeway) {
+            return Err(OID4VPError::RequestNotYetValid {
+                valid_from: issued_at,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clock::Clock;
+
+    use std::sync::Arc;
+
+    use serde_json::{json, Value as Json};
+
+    #[derive(Debug)]
+    struct FixedClock(SystemTime);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> SystemTime {
+            self.0
+        }
+    }
+
+    fn request(claims: Json) -> AuthorizationRequestObject {
+        let mut request = json!({
+            "client_id": "did:web:verifier.example.com",
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "response_uri": "https://verifier.example.com/response",
+            "nonce": "n-0S6_WzA2Mj",
+        });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(claims.as_object().unwrap().clone());
+        serde_json::from_value(request).unwrap()
+    }
+
+    #[test]
+    fn checks_validity_windows() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let leeway = Duration::from_secs(30);
+        let check = |claims| check_validity(&request(claims), now, leeway);
+
+        assert!(check(json!({})).is_ok());
+        assert!(check(json!({ "iat": 1_699_999_990, "exp": 1_700_000_060 })).is_ok());
+        // Skewed clocks are tolerated within the leeway.
+        assert!(check(json!({ "iat": 1_700_000_020, "exp": 1_699_999_990 })).is_ok());
+        assert!(matches!(
+            check(json!({ "exp": 1_699_999_960 })),
+            Err(OID4VPError::RequestExpired)
+        ));
+        assert!(matches!(
+            check(json!({ "nbf": 1_700_000_060 })),
+            Err(OID4VPError::RequestNotYetValid { .. })
+        ));
+        assert!(matches!(
+            check(json!({ "iat": 1_700_000_060 })),
+            Err(OID4VPError::RequestNotYetValid { .. })
+        ));
+    }
+
+    #[test]
+    fn reports_remaining_validity_by_the_holder_clock() {
+        let definition = serde_json::from_value(json!({
+            "id": "definition",
+            "input_descriptors": [],
+        }))
+        .unwrap();
+        let permission_request = PermissionRequest {
+            clock: Arc::new(FixedClock(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            )),
+            clock_leeway: Duration::from_secs(30),
+            ..(*PermissionRequest::new(
+                definition,
+                vec![],
+                request(json!({ "exp": 1_700_000_060 })),
+            ))
+            .clone()
+        };
+
+        assert_eq!(
+            permission_request.remaining_validity(),
+            Some(Duration::from_secs(90))
+        );
+    }
+}
@@ -1,14 +1,142 @@
+use super::error::{caused_by, ErrorSource};
+use super::iso_18013_7::Oid4vpHandover;
+use super::mdoc_verification;
+use super::request;
+use super::request_signer::RequestSignerInterface;
+use super::response_encryption::{self, ResponseEncryptionError, A256GCM, ECDH_ES};
+use super::submission_requirements::SubmissionRequirements;
+use super::wallet_metadata::SUPPORTED_ALGORITHMS;
+use crate::clock::{self, Clock};
 use crate::common::Url;
+use crate::credential::disclosure::{self, DisclosedSdJwt};
+use crate::trust_anchors::{TrustAnchorPurpose, TrustAnchorStore};
 
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::prelude::*;
+use jsonschema::JSONSchema;
+use openid4vp::core::presentation_definition::PresentationDefinition;
+use p256::SecretKey;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use serde_json::{json, Value as Json};
+use sha2::{Digest, Sha256};
+use ssi::{
+    claims::jws::verify_bytes,
+    dids::{AnyDidMethod, DIDKey, DIDResolver, VerificationMethodDIDResolver, DIDJWK},
+    jwk::Algorithm,
+    prelude::{AnyMethod, JwsString, VerificationParameters},
+    JWK,
+};
+use uniffi::deps::anyhow;
+use url::form_urlencoded;
+use uuid::Uuid;
 
 #[derive(Debug, thiserror::Error, uniffi::Error)]
 pub enum Oid4vpVerifierError {
-    #[error("HTTP client error: {0}")]
-    HttpClient(String),
-    #[error("Invalid URL: {0}")]
-    Url(String),
+    #[error("HTTP client error: {reason}")]
+    HttpClient {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Invalid URL: {reason}")]
+    Url {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Invalid presentation definition: {reason}")]
+    PresentationDefinition {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Failed to sign the request object: {reason}")]
+    RequestSigning {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("Invalid response: {reason}")]
+    InvalidResponse {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error("No pending request for the state: {0}")]
+    UnknownState(String),
+    #[error("Unsupported presentation format: {0}")]
+    UnsupportedFormat(String),
+    #[error("Failed to verify the presentation: {reason}")]
+    Verification {
+        reason: String,
+        #[source]
+        source: Option<ErrorSource>,
+    },
+    #[error(transparent)]
+    ResponseEncryption(#[from] ResponseEncryptionError),
+}
+
+impl Oid4vpVerifierError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::HttpClient { .. } => "oid4vp_verifier.http_client",
+            Self::Url { .. } => "oid4vp_verifier.url",
+            Self::PresentationDefinition { .. } => "oid4vp_verifier.presentation_definition",
+            Self::RequestSigning { .. } => "oid4vp_verifier.request_signing",
+            Self::InvalidResponse { .. } => "oid4vp_verifier.invalid_response",
+            Self::UnknownState(..) => "oid4vp_verifier.unknown_state",
+            Self::UnsupportedFormat(..) => "oid4vp_verifier.unsupported_format",
+            Self::Verification { .. } => "oid4vp_verifier.verification",
+            Self::ResponseEncryption(..) => "oid4vp_verifier.response_encryption",
+        }
+    }
+
+    /// The HTTP client failed with the error.
+    pub(crate) fn http_client(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::HttpClient { reason, source }
+    }
+
+    /// A URL failed to parse with the error.
+    pub(crate) fn url(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::Url { reason, source }
+    }
+
+    /// The presentation definition failed to parse with the error.
+    pub(crate) fn definition(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::PresentationDefinition { reason, source }
+    }
+
+    /// The request object failed to sign with the error.
+    pub(crate) fn request_signing(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::RequestSigning { reason, source }
+    }
+
+    /// The response failed to decode with the error.
+    pub(crate) fn response_decoding(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::InvalidResponse { reason, source }
+    }
+
+    /// The presentation failed to verify with the error.
+    pub(crate) fn verification(error: impl Into<anyhow::Error>) -> Self {
+        let (reason, source) = caused_by(error.into());
+        Self::Verification { reason, source }
+    }
+}
+
+/// Return the stable, machine-readable code of the error, e.g.
+/// `oid4vp_verifier.unknown_state`.
+#[uniffi::export]
+pub fn oid4vp_verifier_error_code(error: Oid4vpVerifierError) -> String {
+    error.code().to_string()
 }
 
 #[derive(Debug, uniffi::Object)]
@@ -59,7 +187,7 @@ impl DelegatedVerifier {
     #[uniffi::constructor]
     pub async fn new_client(base_url: Url) -> Result<Arc<Self>, Oid4vpVerifierError> {
         let client = openid4vp::core::util::ReqwestClient::new()
-            .map_err(|e| Oid4vpVerifierError::HttpClient(format!("{e:?}")))?;
+            .map_err(Oid4vpVerifierError::http_client)?;
 
         Ok(Arc::new(Self { base_url, client }))
     }
@@ -70,46 +198,927 @@ impl DelegatedVerifier {
     /// `auth_query` to be presented via QR code to the holder, and a `uri` to
     /// check the status of the presentation from the delegated verifier.
     ///
-    /// Provide the `uri` to the [Verifier::poll_verification_status] method to
+    /// Provide the `uri` to the [DelegatedVerifier::poll_verification_status] method to
     /// check the status of the presentation.
     pub async fn request_delegated_verification(
         &self,
         url: &str,
     ) -> Result<DelegateInitializationResponse, Oid4vpVerifierError> {
-        let uri = self
-            .base_url
-            .join(url)
-            .map_err(|e| Oid4vpVerifierError::Url(format!("{e:?}")))?;
+        let uri = self.base_url.join(url).map_err(Oid4vpVerifierError::url)?;
 
         self.client
             .as_ref()
             .get(uri)
             .send()
             .await
-            .map_err(|e| Oid4vpVerifierError::HttpClient(format!("{e:?}")))?
+            .map_err(Oid4vpVerifierError::http_client)?
             .json()
             .await
-            .map_err(|e| Oid4vpVerifierError::HttpClient(format!("{e:?}")))
+            .map_err(Oid4vpVerifierError::http_client)
     }
 
     pub async fn poll_verification_status(
         &self,
         url: &str,
     ) -> Result<DelegatedVerifierStatusResponse, Oid4vpVerifierError> {
-        let uri = self
-            .base_url
-            .join(url)
-            .map_err(|e| Oid4vpVerifierError::Url(format!("{e:?}")))?;
+        let uri = self.base_url.join(url).map_err(Oid4vpVerifierError::url)?;
 
         self.client
             .as_ref()
             .get(uri)
             .send()
             .await
-            .map_err(|e| Oid4vpVerifierError::HttpClient(format!("{e:?}")))?
+            .map_err(Oid4vpVerifierError::http_client)?
             .json()
             .await
-            .map_err(|e| Oid4vpVerifierError::HttpClient(format!("{e:?}")))
+            .map_err(Oid4vpVerifierError::http_client)
+    }
+}
+
+/// The scheme of the authorization request URLs.
+const AUTHORIZATION_REQUEST_SCHEME: &str = "openid4vp://";
+
+/// How long a request waits for its response before it is forgotten.
+const REQUEST_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// How long after it is issued a presentation is accepted, as read from the
+/// `iat` of its key binding JWT or JWT VP.
+const MAX_PRESENTATION_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// A presentation request created by a [Verifier].
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct VerifierRequest {
+    /// The state identifying the request in the response.
+    pub state: String,
+    /// The nonce the presentations must be bound to.
+    pub nonce: String,
+    /// The authorization request URL, passing the request object by value,
+    /// to be presented in a QR code to the holder.
+    pub url: String,
+    /// The request object, as a JWT when the verifier has a request signer,
+    /// or as JSON otherwise, e.g. to be served at a `request_uri`.
+    pub request_object: String,
+}
+
+/// A credential presented to a [Verifier], once validated.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct VerifiedCredential {
+    /// The ID of the input descriptor the credential was presented for.
+    pub descriptor_id: String,
+    /// The format of the presentation, e.g. `dc+sd-jwt`.
+    pub format: String,
+    /// The presented claims of the credential, as a JSON encoded string.
+    pub claims: String,
+}
+
+/// The validated response to a presentation request.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct VerifiedPresentation {
+    /// The state of the request the response answers.
+    pub state: String,
+    /// The presented credentials.
+    pub credentials: Vec<VerifiedCredential>,
+}
+
+/// A request waiting for its response.
+#[derive(Debug, Clone)]
+struct PendingRequest {
+    nonce: String,
+    definition: Json,
+    /// When the request was created, by the clock of the verifier.
+    created_at: SystemTime,
+    /// The key the responses are encrypted to, for requests of mdocs.
+    response_key: Option<ResponseKey>,
+}
+
+/// The ephemeral key the responses to a request are encrypted to.
+#[derive(Debug, Clone)]
+struct ResponseKey {
+    kid: String,
+    secret: SecretKey,
+}
+
+/// The time presentations are verified at, with the leeway allowed on either
+/// side of validity windows.
+#[derive(Debug, Clone, Copy)]
+struct VerificationTime {
+    now: SystemTime,
+    leeway: Duration,
+}
+
+/// A verifier, requesting credentials from holders through OID4VP, e.g. for
+/// mobile readers.
+///
+/// Responses are expected through the `direct_post` response mode, or
+/// `direct_post.jwt` for requests of mdocs: the app receives them at the
+/// response URI, and hands their body to [Verifier::handle_response].
+#[derive(Debug, uniffi::Object)]
+pub struct Verifier {
+    client_id: String,
+    response_uri: Url,
+    request_signer: RwLock<Option<Arc<dyn RequestSignerInterface>>>,
+    /// The requests waiting for their response, by state.
+    pending: RwLock<HashMap<String, PendingRequest>>,
+    clock: RwLock<Arc<dyn Clock>>,
+    clock_leeway: RwLock<Duration>,
+    trust_anchors: RwLock<Option<Arc<TrustAnchorStore>>>,
+}
+
+#[uniffi::export]
+impl Verifier {
+    /// Create a verifier with the given client ID, e.g. its DID, receiving
+    /// the responses at the response URI.
+    #[uniffi::constructor]
+    pub fn new(client_id: String, response_uri: Url) -> Arc<Self> {
+        Arc::new(Self {
+            client_id,
+            response_uri,
+            request_signer: RwLock::new(None),
+            pending: RwLock::new(HashMap::new()),
+            clock: RwLock::new(clock::system()),
+            clock_leeway: RwLock::new(Duration::ZERO),
+            trust_anchors: RwLock::new(None),
+        })
+    }
+
+    /// Set the clock requests expire and presentations are checked by, the
+    /// clock of the system by default.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self
+            .clock
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = clock;
+    }
+
+    /// Set the leeway of expiry and freshness checks, tolerating the skew
+    /// between the clocks of the verifier, holders and issuers.
+    pub fn set_clock_leeway(&self, leeway: Duration) {
+        *self
+            .clock_leeway
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = leeway;
+    }
+
+    /// Set the signer of the request objects.
+    ///
+    /// Requests are sent unsigned when no request signer is set, which holders
+    /// only accept from `redirect_uri` clients.
+    pub fn set_request_signer(&self, signer: Arc<dyn RequestSignerInterface>) {
+        *self
+            .request_signer
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(signer);
+    }
+
+    /// Set the trust anchors the document signers of presented mdocs must
+    /// chain to, as IACA trust anchors.
+    ///
+    /// Mdocs are refused when no trust anchors are set.
+    pub fn set_trust_anchors(&self, trust_anchors: Arc<TrustAnchorStore>) {
+        *self
+            .trust_anchors
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(trust_anchors);
+    }
+
+    /// Forget a request, rejecting its response if one is received later.
+    pub fn cancel_request(&self, state: String) {
+        self.pending
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&state);
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl Verifier {
+    /// Create a request for the credentials described by a JSON encoded
+    /// presentation definition.
+    ///
+    /// Requests of mdocs, by the `format` of the definition or of one of its
+    /// input descriptors, ask for responses encrypted to an ephemeral key, as
+    /// mdocs are bound to the mdoc generated nonce of their `apu`.
+    pub async fn create_request(
+        &self,
+        presentation_definition: String,
+    ) -> Result<VerifierRequest, Oid4vpVerifierError> {
+        let definition: Json = serde_json::from_str(&presentation_definition)
+            .map_err(Oid4vpVerifierError::definition)?;
+        serde_json::from_value::<PresentationDefinition>(definition.clone())
+            .map_err(Oid4vpVerifierError::definition)?;
+
+        let state = Uuid::new_v4().to_string();
+        let nonce = Uuid::new_v4().to_string();
+        let client_id_scheme = if self.client_id.starts_with("did:") {
+            "did"
+        } else {
+            "redirect_uri"
+        };
+
+        let mut request_object = json!({
+            "response_type": "vp_token",
+            "response_mode": "direct_post",
+            "client_id": self.client_id,
+            "client_id_scheme": client_id_scheme,
+            "response_uri": self.response_uri.to_string(),
+            "nonce": nonce,
+            "state": state,
+            "presentation_definition": definition,
+            "client_metadata": {
+                "vp_formats": {
+                    "vcdm2_sd_jwt": {},
+                    "dc+sd-jwt": {
+                        "sd-jwt_alg_values": SUPPORTED_ALGORITHMS,
+                        "kb-jwt_alg_values": SUPPORTED_ALGORITHMS,
+                    },
+                    "jwt_vp_json": { "alg": SUPPORTED_ALGORITHMS },
+                    "mso_mdoc": { "alg": SUPPORTED_ALGORITHMS },
+                },
+            },
+        });
+        let response_key = match requests_mdocs(&definition) {
+            true => Some(ResponseKey {
+                kid: Uuid::new_v4().to_string(),
+                secret: response_encryption::generate_decryption_key()?,
+            }),
+            false => None,
+        };
+        if let Some(key) = &response_key {
+            request_object["response_mode"] = "direct_post.jwt".into();
+            let metadata = &mut request_object["client_metadata"];
+            metadata["authorization_encrypted_response_alg"] = ECDH_ES.into();
+            metadata["authorization_encrypted_response_enc"] = A256GCM.into();
+            metadata["jwks"] = json!({
+                "keys": [response_encryption::encryption_jwk(&key.secret, &key.kid)],
+            });
+        }
+
+        let signer = self
+            .request_signer
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        let (url, request_object) = match signer {
+            Some(signer) => {
+                let jwt = sign_request_object(signer.as_ref(), &request_object).await?;
+                let query = form_urlencoded::Serializer::new(String::new())
+                    .append_pair("client_id", &self.client_id)
+                    .append_pair("request", &jwt)
+                    .finish();
+                (format!("{AUTHORIZATION_REQUEST_SCHEME}?{query}"), jwt)
+            }
+            None => {
+                let mut query = form_urlencoded::Serializer::new(String::new());
+                for (name, value) in request_object.as_object().into_iter().flatten() {
+                    match value {
+                        Json::String(value) => query.append_pair(name, value),
+                        value => query.append_pair(name, &value.to_string()),
+                    };
+                }
+                (
+                    format!("{AUTHORIZATION_REQUEST_SCHEME}?{}", query.finish()),
+                    request_object.to_string(),
+                )
+            }
+        };
+
+        let time = self.verification_time();
+        let mut pending = self
+            .pending
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Requests never answered would otherwise be kept forever.
+        pending.retain(|_, request| !request.has_expired(time));
+        pending.insert(
+            state.clone(),
+            PendingRequest {
+                nonce: nonce.clone(),
+                definition,
+                created_at: time.now,
+                response_key,
+            },
+        );
+
+        Ok(VerifierRequest {
+            state,
+            nonce,
+            url,
+            request_object,
+        })
+    }
+
+    /// Validate the form encoded body of a `direct_post` or `direct_post.jwt`
+    /// response, returning the presented credentials.
+    ///
+    /// Each request accepts a single valid response, until it expires. SD-JWTs
+    /// must be bound to the request with a key binding JWT, and issuers are
+    /// identified by the DID URLs of their signing keys. Presentations must
+    /// have been issued recently, and credentials must be valid, by the clock
+    /// of the verifier. Mdocs must chain to the trust anchors of the verifier,
+    /// and be presented in responses encrypted to the key of their request.
+    pub async fn handle_response(
+        &self,
+        body: String,
+    ) -> Result<VerifiedPresentation, Oid4vpVerifierError> {
+        let mut parameters: HashMap<String, String> = form_urlencoded::parse(body.as_bytes())
+            .into_owned()
+            .collect();
+        // Encrypted responses are posted as a JWE, in their response parameter.
+        let mut decrypted = None;
+        if let Some(jwe) = parameters.remove("response") {
+            let (kid, decrypted_parameters, apu) = self.decrypt_response(&jwe)?;
+            parameters = decrypted_parameters;
+            decrypted = Some((kid, apu));
+        }
+        let parameter = |name: &str| {
+            parameters
+                .get(name)
+                .ok_or_else(|| invalid_response(format!("missing {name} parameter")))
+        };
+
+        let state = parameter("state")?.clone();
+        // The request is only removed once the response is verified, so that
+        // invalid responses sent by third parties knowing the state cannot
+        // prevent the holder from responding.
+        let request = self
+            .pending
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&state)
+            .cloned()
+            .ok_or_else(|| Oid4vpVerifierError::UnknownState(state.clone()))?;
+        let time = self.verification_time();
+        if request.has_expired(time) {
+            self.cancel_request(state.clone());
+            return Err(Oid4vpVerifierError::UnknownState(state));
+        }
+        let mdoc_generated_nonce = match (&request.response_key, decrypted) {
+            (None, None) => None,
+            (Some(key), Some((kid, apu))) if key.kid == kid => String::from_utf8(apu).ok(),
+            _ => {
+                return Err(invalid_response(
+                    "the response is not encrypted to the key of the request",
+                ))
+            }
+        };
+
+        // A vp_token made of a single presentation is not JSON encoded, unless
+        // it is a JSON presentation.
+        let vp_token = parameter("vp_token")?;
+        let vp_token = serde_json::from_str(vp_token).unwrap_or_else(|_| json!(vp_token));
+        let submission: Json = serde_json::from_str(parameter("presentation_submission")?)
+            .map_err(Oid4vpVerifierError::response_decoding)?;
+
+        if submission["definition_id"] != request.definition["id"] {
+            return Err(invalid_response(
+                "the submission is not for the requested presentation definition".into(),
+            ));
+        }
+
+        let descriptors = request.definition["input_descriptors"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let mut credentials = vec![];
+        for descriptor in submission["descriptor_map"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let (Some(id), Some(format), Some(path)) = (
+                descriptor["id"].as_str(),
+                descriptor["format"].as_str(),
+                descriptor["path"].as_str(),
+            ) else {
+                return Err(invalid_response("invalid descriptor map entry".into()));
+            };
+            let input_descriptor = descriptors
+                .iter()
+                .find(|descriptor| descriptor["id"] == id)
+                .ok_or_else(|| invalid_response(format!("unknown input descriptor {id}")))?;
+
+            let presentation = disclosure::select_path(&vp_token, path)
+                .first()
+                .and_then(|pointer| disclosure::value_at(&vp_token, pointer))
+                .and_then(Json::as_str)
+                .ok_or_else(|| invalid_response(format!("no presentation at {path}")))?;
+
+            let claims = match format {
+                "vcdm2_sd_jwt" | "dc+sd-jwt" | "vc+sd-jwt" => {
+                    verify_sd_jwt(presentation, &request.nonce, &self.client_id, time).await?
+                }
+                "jwt_vp_json" | "jwt_vp" => {
+                    verify_jwt_vp(presentation, &request.nonce, &self.client_id, time).await?
+                }
+                "mso_mdoc" => {
+                    let Some(mdoc_generated_nonce) = mdoc_generated_nonce.clone() else {
+                        return Err(invalid_presentation("the response has no mdoc nonce"));
+                    };
+                    let handover = Oid4vpHandover {
+                        client_id: self.client_id.clone(),
+                        response_uri: self.response_uri.to_string(),
+                        nonce: request.nonce.clone(),
+                        mdoc_generated_nonce,
+                    };
+                    self.verify_mdoc(presentation, &handover, time).await?
+                }
+                format => return Err(Oid4vpVerifierError::UnsupportedFormat(format.into())),
+            };
+
+            if !satisfies_constraints(input_descriptor, &claims) {
+                return Err(invalid_response(format!(
+                    "the presentation does not satisfy the constraints of {id}"
+                )));
+            }
+
+            credentials.push(VerifiedCredential {
+                descriptor_id: id.into(),
+                format: format.into(),
+                claims: claims.to_string(),
+            });
+        }
+
+        if credentials.is_empty() {
+            return Err(invalid_response("no credential was presented".into()));
+        }
+
+        let requirements = serde_json::from_value(request.definition.clone())
+            .map(|definition| SubmissionRequirements::new(&definition))
+            .map_err(Oid4vpVerifierError::definition)?;
+        let answered = credentials
+            .iter()
+            .map(|credential| credential.descriptor_id.clone())
+            .collect();
+        if !requirements.is_satisfied(&answered) {
+            return Err(invalid_response(
+                "the presentation does not satisfy the presentation definition".into(),
+            ));
+        }
+
+        // Another response to the request may have been accepted meanwhile.
+        self.pending
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&state)
+            .ok_or_else(|| Oid4vpVerifierError::UnknownState(state.clone()))?;
+
+        Ok(VerifiedPresentation { state, credentials })
+    }
+}
+
+impl Verifier {
+    /// Return the current time of the clock of the verifier, with its leeway.
+    fn verification_time(&self) -> VerificationTime {
+        VerificationTime {
+            now: self
+                .clock
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .now(),
+            leeway: *self
+                .clock_leeway
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        }
+    }
+
+    /// Decrypt a response encrypted to the key of a pending request, and
+    /// return the ID of the key, the parameters of the response and its `apu`.
+    fn decrypt_response(
+        &self,
+        jwe: &str,
+    ) -> Result<(String, HashMap<String, String>, Vec<u8>), Oid4vpVerifierError> {
+        let kid = response_encryption::jwe_kid(jwe)
+            .ok_or_else(|| invalid_response("the encrypted response has no kid"))?;
+        let key = self
+            .pending
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .find_map(|request| request.response_key.as_ref().filter(|key| key.kid == kid))
+            .map(|key| key.secret.clone())
+            .ok_or_else(|| {
+                invalid_response("the response is not encrypted to a pending request")
+            })?;
+
+        let (parameters, apu) = response_encryption::decrypt_response(jwe, &key)?;
+        let parameters = parameters
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| match value {
+                Json::String(value) => (name.clone(), value.clone()),
+                value => (name.clone(), value.to_string()),
+            })
+            .collect();
+        Ok((kid, parameters, apu))
+    }
+
+    /// Verify an mdoc DeviceResponse bound to the handover, returning its
+    /// disclosed elements by namespace.
+    async fn verify_mdoc(
+        &self,
+        presentation: &str,
+        handover: &Oid4vpHandover,
+        time: VerificationTime,
+    ) -> Result<Json, Oid4vpVerifierError> {
+        let trust_anchors = self
+            .trust_anchors
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+            .ok_or_else(|| invalid_presentation("no trust anchors are set to verify mdocs"))?;
+        let anchors = trust_anchors
+            .anchors(TrustAnchorPurpose::Iaca)
+            .map_err(Oid4vpVerifierError::verification)?;
+
+        mdoc_verification::verify_device_response(
+            presentation,
+            handover,
+            &anchors,
+            trust_anchors.checks_revocation(),
+            time.now,
+            time.leeway,
+        )
+        .await
+        .map_err(Oid4vpVerifierError::verification)
+    }
+}
+
+impl PendingRequest {
+    fn has_expired(&self, time: VerificationTime) -> bool {
+        self.created_at
+            .checked_add(REQUEST_LIFETIME)
+            .is_some_and(|expires_at| clock::has_passed(expires_at, time.now, time.leeway))
+    }
+}
+
+impl VerificationTime {
+    /// Check that a credential or presentation is valid, from the `exp` and
+    /// `nbf` of its claims.
+    fn check_validity(&self, claims: &Json, subject: &str) -> Result<(), Oid4vpVerifierError> {
+        if numeric_date(claims, "exp")?
+            .is_some_and(|expires_at| clock::has_passed(expires_at, self.now, self.leeway))
+        {
+            return Err(invalid_presentation(&format!("the {subject} has expired")));
+        }
+        if numeric_date(claims, "nbf")?
+            .is_some_and(|not_before| !clock::has_reached(not_before, self.now, self.leeway))
+        {
+            return Err(invalid_presentation(&format!(
+                "the {subject} is not valid yet"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that a presentation was issued recently, from the `iat` of its
+    /// claims, so that presentations cannot be stored and replayed later.
+    fn check_freshness(&self, claims: &Json) -> Result<(), Oid4vpVerifierError> {
+        let issued_at = numeric_date(claims, "iat")?
+            .ok_or_else(|| invalid_presentation("the presentation has no iat"))?;
+        if !clock::has_reached(issued_at, self.now, self.leeway) {
+            return Err(invalid_presentation(
+                "the presentation is issued in the future",
+            ));
+        }
+        if issued_at
+            .checked_add(MAX_PRESENTATION_AGE)
+            .is_some_and(|stale_at| clock::has_passed(stale_at, self.now, self.leeway))
+        {
+            return Err(invalid_presentation("the presentation is too old"));
+        }
+        Ok(())
+    }
+}
+
+/// Return the time of a NumericDate claim, if it is set.
+fn numeric_date(claims: &Json, name: &str) -> Result<Option<SystemTime>, Oid4vpVerifierError> {
+    match &claims[name] {
+        Json::Null => Ok(None),
+        value => value
+            .as_u64()
+            .map(|seconds| Some(UNIX_EPOCH + Duration::from_secs(seconds)))
+            .ok_or_else(|| invalid_presentation(&format!("invalid {name} claim"))),
+    }
+}
+
+/// Whether a presentation definition requests mdocs, in its `format` or in
+/// that of one of its input descriptors.
+fn requests_mdocs(definition: &Json) -> bool {
+    std::iter::once(definition)
+        .chain(
+            definition["input_descriptors"]
+                .as_array()
+                .into_iter()
+                .flatten(),
+        )
+        .any(|value| value["format"].get("mso_mdoc").is_some())
+}
+
+/// Check whether the verified claims of a presentation satisfy the
+/// constraints of its input descriptor: every required field must select a
+/// value matching its filter, in the claims of a single credential.
+fn satisfies_constraints(descriptor: &Json, claims: &Json) -> bool {
+    // The claims of JWT VPs hold the payloads of their credentials.
+    let credentials = match claims["verifiableCredential"].as_array() {
+        Some(credentials) => credentials
+            .iter()
+            .flat_map(|payload| [payload, &payload["vc"]])
+            .collect(),
+        None => vec![claims],
+    };
+
+    let fields = descriptor["constraints"]["fields"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let filters = fields
+        .iter()
+        .map(|field| match field.get("filter") {
+            Some(filter) => JSONSchema::compile(filter).ok().map(Some),
+            None => Some(None),
+        })
+        .collect::<Option<Vec<_>>>();
+    let Some(filters) = filters else {
+        return false;
+    };
+
+    credentials.iter().any(|credential| {
+        fields
+            .iter()
+            .zip(&filters)
+            .filter(|(field, _)| !field["optional"].as_bool().unwrap_or(false))
+            .all(|(field, filter)| {
+                field["path"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Json::as_str)
+                    .flat_map(|path| disclosure::select_path(credential, path))
+                    .filter_map(|pointer| disclosure::value_at(credential, &pointer))
+                    .any(|value| {
+                        filter
+                            .as_ref()
+                            .map_or(true, |filter| filter.is_valid(value))
+                    })
+            })
+    })
+}
+
+/// Sign a request object as a JWT.
+async fn sign_request_object(
+    signer: &dyn RequestSignerInterface,
+    request_object: &Json,
+) -> Result<String, Oid4vpVerifierError> {
+    let mut header = json!({
+        "alg": signer.alg().map_err(Oid4vpVerifierError::request_signing)?,
+        "typ": "oauth-authz-req+jwt",
+    });
+    // Holders resolve the key of requests of DID clients from the kid.
+    let jwk: Json =
+        serde_json::from_str(&signer.jwk().map_err(Oid4vpVerifierError::request_signing)?)
+            .map_err(Oid4vpVerifierError::request_signing)?;
+    if let Some(kid) = jwk.get("kid") {
+        header["kid"] = kid.clone();
+    }
+
+    let signing_input = format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+        BASE64_URL_SAFE_NO_PAD.encode(request_object.to_string()),
+    );
+    let signature = signer
+        .try_sign(signing_input.as_bytes().to_vec())
+        .await
+        .map_err(Oid4vpVerifierError::request_signing)?;
+
+    Ok(format!(
+        "{signing_input}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(signature)
+    ))
+}
+
+/// Verify an SD-JWT presentation, returning its disclosed claims.
+async fn verify_sd_jwt(
+    presentation: &str,
+    nonce: &str,
+    audience: &str,
+    time: VerificationTime,
+) -> Result<Json, Oid4vpVerifierError> {
+    let (sd_jwt, kb_jwt) = presentation
+        .rfind('~')
+        .map(|end| presentation.split_at(end + 1))
+        .ok_or_else(|| invalid_presentation("not an SD-JWT"))?;
+
+    let disclosed =
+        DisclosedSdJwt::parse(sd_jwt).ok_or_else(|| invalid_presentation("invalid SD-JWT"))?;
+    verify_with_did(&disclosed.issuer_jwt).await?;
+    let claims = disclosed
+        .reveal()
+        .ok_or_else(|| invalid_presentation("invalid disclosures"))?;
+    time.check_validity(&claims, "credential")?;
+
+    // Without key binding, the presentation could be replayed.
+    let jwk = claims["cnf"]
+        .get("jwk")
+        .ok_or_else(|| invalid_presentation("the credential is not bound to a key"))?;
+    if kb_jwt.is_empty() {
+        return Err(invalid_presentation("missing key binding JWT"));
+    }
+    let (header, payload) = verify_with_jwk(kb_jwt, jwk)?;
+
+    if header["typ"] != "kb+jwt" {
+        return Err(invalid_presentation("invalid key binding JWT type"));
+    }
+    time.check_freshness(&payload)?;
+    if payload["nonce"] != nonce || payload["aud"] != audience {
+        return Err(invalid_presentation(
+            "the presentation is not for this request",
+        ));
+    }
+    if payload["sd_hash"] != BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(sd_jwt.as_bytes())) {
+        return Err(invalid_presentation(
+            "the key binding JWT is not for the SD-JWT",
+        ));
+    }
+
+    Ok(claims)
+}
+
+/// Verify a JWT VP, returning its presentation with the payloads of the
+/// credentials.
+///
+/// The presentation must be signed by the subject of every credential,
+/// identified by its DID or by the key it is bound to.
+async fn verify_jwt_vp(
+    presentation: &str,
+    nonce: &str,
+    audience: &str,
+    time: VerificationTime,
+) -> Result<Json, Oid4vpVerifierError> {
+    let header =
+        request::jwt_part(presentation, 0).ok_or_else(|| invalid_presentation("not a JWT"))?;
+    let (mut payload, holder) = match header.get("jwk") {
+        Some(jwk) => {
+            let payload = verify_with_jwk(presentation, jwk)?.1;
+            (payload, PresentationSigner::from_jwk(jwk)?)
+        }
+        None => {
+            verify_with_did(presentation).await?;
+            let did = header["kid"]
+                .as_str()
+                .and_then(|kid| kid.split('#').next())
+                .ok_or_else(|| invalid_presentation("the presentation has no kid"))?;
+            let signer = PresentationSigner {
+                dids: vec![did.to_owned()],
+                thumbprint: None,
+            };
+            let payload = request::jwt_part(presentation, 1)
+                .ok_or_else(|| invalid_presentation("not a JWT"))?;
+            (payload, signer)
+        }
+    };
+
+    if payload["nonce"] != nonce || payload["aud"] != audience {
+        return Err(invalid_presentation(
+            "the presentation is not for this request",
+        ));
+    }
+    time.check_freshness(&payload)?;
+    time.check_validity(&payload, "presentation")?;
+
+    let mut vp = payload["vp"].take();
+    let credentials = vp["verifiableCredential"]
+        .as_array_mut()
+        .ok_or_else(|| invalid_presentation("the presentation has no credentials"))?;
+    for credential in credentials.iter_mut() {
+        let jwt = credential
+            .as_str()
+            .ok_or_else(|| invalid_presentation("not a JWT VC"))?;
+        verify_with_did(jwt).await?;
+        let payload =
+            request::jwt_part(jwt, 1).ok_or_else(|| invalid_presentation("not a JWT VC"))?;
+        time.check_validity(&payload, "credential")?;
+        // Otherwise anyone holding a copy of the credential could present it.
+        if !holder.is_subject_of(&payload) {
+            return Err(invalid_presentation(
+                "the presentation is not signed by the subject of the credential",
+            ));
+        }
+        *credential = payload;
+    }
+
+    Ok(vp)
+}
+
+/// The identifiers of the signer of a presentation.
+struct PresentationSigner {
+    /// The DIDs of the signer.
+    dids: Vec<String>,
+    /// The RFC 7638 thumbprint of the key of the signer, if it was given.
+    thumbprint: Option<String>,
+}
+
+impl PresentationSigner {
+    fn from_jwk(jwk: &Json) -> Result<Self, Oid4vpVerifierError> {
+        let jwk: JWK =
+            serde_json::from_value(jwk.clone()).map_err(Oid4vpVerifierError::verification)?;
+        let mut dids = vec![DIDJWK::generate(&jwk).to_string()];
+        if let Ok(did) = DIDKey::generate(&jwk) {
+            dids.push(did.to_string());
+        }
+        let thumbprint = jwk
+            .thumbprint()
+            .map_err(Oid4vpVerifierError::verification)?;
+        Ok(Self {
+            dids,
+            thumbprint: Some(thumbprint),
+        })
+    }
+
+    /// Check whether the signer is the subject of a JWT VC, from its
+    /// payload: its `sub`, the `id` of every credential subject, or the key
+    /// it is bound to with `cnf`.
+    fn is_subject_of(&self, payload: &Json) -> bool {
+        let is_signer = |id: &Json| {
+            id.as_str()
+                .is_some_and(|id| self.dids.iter().any(|did| did == id))
+        };
+
+        if let (Some(thumbprint), Some(jwk)) = (&self.thumbprint, payload["cnf"].get("jwk")) {
+            return serde_json::from_value::<JWK>(jwk.clone())
+                .ok()
+                .and_then(|jwk| jwk.thumbprint().ok())
+                .is_some_and(|bound| &bound == thumbprint);
+        }
+
+        let subjects = match &payload["vc"]["credentialSubject"] {
+            Json::Array(subjects) => subjects.iter().collect::<Vec<_>>(),
+            subject => vec![subject],
+        };
+        let mut ids = subjects
+            .into_iter()
+            .map(|subject| &subject["id"])
+            .filter(|id| !id.is_null())
+            .collect::<Vec<_>>();
+        if !payload["sub"].is_null() {
+            ids.push(&payload["sub"]);
+        }
+        !ids.is_empty() && ids.into_iter().all(is_signer)
+    }
+}
+
+/// Verify a JWS signed by the key of a DID URL, from its `kid` header.
+async fn verify_with_did(jws: &str) -> Result<(), Oid4vpVerifierError> {
+    let jws = JwsString::from_string(jws.to_owned()).map_err(Oid4vpVerifierError::verification)?;
+
+    let vm_resolver: VerificationMethodDIDResolver<AnyDidMethod, AnyMethod> =
+        AnyDidMethod::default().into_vm_resolver();
+    let params = VerificationParameters::from_resolver(vm_resolver);
+
+    jws.verify(params)
+        .await
+        .map_err(Oid4vpVerifierError::verification)?
+        .map_err(Oid4vpVerifierError::verification)
+}
+
+/// Verify a JWS signed by a JWK, returning its header and payload.
+fn verify_with_jwk(jws: &str, jwk: &Json) -> Result<(Json, Json), Oid4vpVerifierError> {
+    let (Some(header), Some(payload)) = (request::jwt_part(jws, 0), request::jwt_part(jws, 1))
+    else {
+        return Err(invalid_presentation("not a JWS"));
+    };
+    let (signing_input, signature) = jws
+        .rsplit_once('.')
+        .ok_or_else(|| invalid_presentation("not a JWS"))?;
+
+    let algorithm: Algorithm =
+        serde_json::from_value(header["alg"].clone()).map_err(Oid4vpVerifierError::verification)?;
+    let jwk: JWK =
+        serde_json::from_value(jwk.clone()).map_err(Oid4vpVerifierError::verification)?;
+    let signature = BASE64_URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(Oid4vpVerifierError::verification)?;
+
+    verify_bytes(algorithm, signing_input.as_bytes(), &jwk, &signature)
+        .map_err(Oid4vpVerifierError::verification)?;
+
+    Ok((header, payload))
+}
+
+fn invalid_presentation(reason: &str) -> Oid4vpVerifierError {
+    Oid4vpVerifierError::Verification {
+        reason: reason.into(),
+        source: None,
+    }
+}
+
+fn invalid_response(reason: impl Into<String>) -> Oid4vpVerifierError {
+    Oid4vpVerifierError::InvalidResponse {
+        reason: reason.into(),
+        source: None,
     }
 }
 
@@ -119,6 +1128,13 @@ mod tests {
     use crate::credential::vcdm2_sd_jwt::VCDM2SdJwt;
     use crate::credential::*;
     use crate::oid4vp::holder::*;
+    use crate::oid4vp::key_binding::{bind_presentation, tests::TestSigner, KeyBinding};
+    use crate::oid4vp::request_signer::ExampleRequestSigner;
+    use crate::KeyAlias;
+
+    use openid4vp::core::authorization_request::AuthorizationRequestObject;
+    use openid4vp::core::response::AuthorizationResponse;
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
 
     // NOTE: This requires an instance of credible to be accessible
     const BASE_URL: &str = "http://localhost:3003";
@@ -167,7 +1183,9 @@ mod tests {
             println!("Credential: {:?}", c);
         });
 
-        let response = request.create_permission_response(request.credentials());
+        let response = request
+            .create_permission_response(request.credentials())
+            .expect("failed to create the permission response");
 
         let url = holder.submit_permission_response(response).await;
 
@@ -186,4 +1204,414 @@ mod tests {
 
         Ok(())
     }
+
+    fn encode(value: Json) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(value.to_string())
+    }
+
+    #[derive(Debug)]
+    struct FixedClock(SystemTime);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> SystemTime {
+            self.0
+        }
+    }
+
+    fn seconds(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn at(now: SystemTime) -> VerificationTime {
+        VerificationTime {
+            now,
+            leeway: Duration::ZERO,
+        }
+    }
+
+    /// Return an SD-JWT VC bound to the key of the holder, issued by a
+    /// `did:jwk` issuer.
+    fn sd_jwt_vc(holder: &TestSigner) -> String {
+        let issuer = TestSigner(SigningKey::from_slice(&[3; 32]).unwrap());
+        let issuer_jwk: JWK = serde_json::from_value(issuer.jwk()).unwrap();
+        let kid = DIDJWK::generate_url(&issuer_jwk).to_string();
+        let did = kid.split('#').next().unwrap().to_owned();
+
+        let given_name = encode(json!(["salt", "given_name", "Alice"]));
+        let digest = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(given_name.as_bytes()));
+        let signing_input = format!(
+            "{}.{}",
+            encode(json!({ "alg": "ES256", "kid": kid, "typ": "dc+sd-jwt" })),
+            encode(json!({
+                "iss": did,
+                "vct": "https://credentials.example.com/identity_credential",
+                "_sd": [digest],
+                "_sd_alg": "sha-256",
+                "cnf": { "jwk": holder.jwk() },
+            }))
+        );
+        let signature: Signature = issuer.0.sign(signing_input.as_bytes());
+
+        format!(
+            "{signing_input}.{}~{given_name}~",
+            BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+
+    /// Return a JWT VP of a JWT VC of the subject, signed by the holder, with
+    /// the claims of the VC.
+    fn jwt_vp(subject: &TestSigner, holder: &TestSigner, nonce: &str, vc_claims: Json) -> String {
+        let issuer = TestSigner(SigningKey::from_slice(&[3; 32]).unwrap());
+        let issuer_jwk: JWK = serde_json::from_value(issuer.jwk()).unwrap();
+        let kid = DIDJWK::generate_url(&issuer_jwk).to_string();
+        let subject_jwk: JWK = serde_json::from_value(subject.jwk()).unwrap();
+        let sign = |signer: &TestSigner, header: Json, payload: Json| {
+            let signing_input = format!("{}.{}", encode(header), encode(payload));
+            let signature: Signature = signer.0.sign(signing_input.as_bytes());
+            format!(
+                "{signing_input}.{}",
+                BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes())
+            )
+        };
+
+        let mut vc_payload = json!({
+            "iss": kid.split('#').next().unwrap(),
+            "vc": {
+                "@context": ["https://www.w3.org/2018/credentials/v1"],
+                "type": ["VerifiableCredential"],
+                "credentialSubject": {
+                    "id": DIDJWK::generate(&subject_jwk).to_string(),
+                    "given_name": "Alice",
+                },
+            },
+        });
+        vc_payload
+            .as_object_mut()
+            .unwrap()
+            .extend(vc_claims.as_object().unwrap().clone());
+        let vc = sign(
+            &issuer,
+            json!({ "alg": "ES256", "kid": kid, "typ": "JWT" }),
+            vc_payload,
+        );
+        sign(
+            holder,
+            json!({ "alg": "ES256", "typ": "JWT", "jwk": holder.jwk() }),
+            json!({
+                "aud": "did:web:verifier",
+                "nonce": nonce,
+                "iat": seconds(SystemTime::now()),
+                "vp": {
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiablePresentation"],
+                    "verifiableCredential": [vc],
+                },
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn binds_jwt_vps_to_credential_subjects() {
+        let subject = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let now = at(SystemTime::now());
+        let presentation = jwt_vp(&subject, &subject, "nonce", json!({}));
+        let vp = verify_jwt_vp(&presentation, "nonce", "did:web:verifier", now)
+            .await
+            .unwrap();
+        assert_eq!(
+            vp["verifiableCredential"][0]["vc"]["credentialSubject"]["given_name"],
+            "Alice"
+        );
+
+        // A copy of the credential cannot be presented by another holder.
+        let other = TestSigner(SigningKey::from_slice(&[2; 32]).unwrap());
+        let presentation = jwt_vp(&subject, &other, "nonce", json!({}));
+        assert!(matches!(
+            verify_jwt_vp(&presentation, "nonce", "did:web:verifier", now).await,
+            Err(Oid4vpVerifierError::Verification { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn checks_jwt_vps_by_the_clock() {
+        let subject = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let now = SystemTime::now();
+        let verify = |presentation: String, now: SystemTime| async move {
+            verify_jwt_vp(&presentation, "nonce", "did:web:verifier", at(now)).await
+        };
+
+        // Expired credentials are refused.
+        let expired = jwt_vp(
+            &subject,
+            &subject,
+            "nonce",
+            json!({ "exp": seconds(now) - 60 }),
+        );
+        assert!(verify(expired, now).await.is_err());
+        let not_yet_valid = jwt_vp(
+            &subject,
+            &subject,
+            "nonce",
+            json!({ "nbf": seconds(now) + 60 }),
+        );
+        assert!(verify(not_yet_valid, now).await.is_err());
+
+        // Presentations issued long ago, or in the future, are refused.
+        let presentation = jwt_vp(&subject, &subject, "nonce", json!({}));
+        assert!(verify(presentation.clone(), now).await.is_ok());
+        assert!(verify(presentation.clone(), now + MAX_PRESENTATION_AGE * 2)
+            .await
+            .is_err());
+        assert!(verify(presentation, now - Duration::from_secs(60))
+            .await
+            .is_err());
+    }
+
+    async fn response(holder: &TestSigner, request: &VerifierRequest, nonce: &str) -> String {
+        let presentation = sd_jwt_vc(holder);
+        let claims = DisclosedSdJwt::parse(&presentation)
+            .and_then(|sd_jwt| sd_jwt.reveal())
+            .unwrap();
+        let vp_token = bind_presentation(
+            presentation,
+            &claims,
+            Some(KeyAlias("key".into())),
+            Some(holder),
+            &KeyBinding {
+                audience: "did:web:verifier".into(),
+                nonce: nonce.into(),
+                transaction_data_hashes: vec![],
+                key_attestation: None,
+                issued_at: SystemTime::now(),
+            },
+        )
+        .await
+        .unwrap();
+
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("vp_token", &vp_token)
+            .append_pair(
+                "presentation_submission",
+                &json!({
+                    "id": "submission",
+                    "definition_id": "identity",
+                    "descriptor_map": [{ "id": "identity", "format": "dc+sd-jwt", "path": "$" }]
+                })
+                .to_string(),
+            )
+            .append_pair("state", &request.state)
+            .finish()
+    }
+
+    #[tokio::test]
+    async fn verifies_responses() {
+        let verifier = Verifier::new(
+            "did:web:verifier".into(),
+            "https://verifier.example.com/response".parse().unwrap(),
+        );
+        verifier.set_request_signer(Arc::new(ExampleRequestSigner::default()));
+        let definition = json!({
+            "id": "identity",
+            "input_descriptors": [{
+                "id": "identity",
+                "format": { "dc+sd-jwt": {} },
+                "constraints": { "fields": [{ "path": ["$.given_name"] }] }
+            }]
+        });
+
+        let request = verifier
+            .create_request(definition.to_string())
+            .await
+            .unwrap();
+        assert!(request.url.starts_with("openid4vp://?client_id="));
+        let request_object = request::jwt_part(&request.request_object, 1).unwrap();
+        assert_eq!(request_object["nonce"], request.nonce);
+        assert_eq!(request_object["client_id_scheme"], "did");
+
+        let holder = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let body = response(&holder, &request, &request.nonce).await;
+        let presentation = verifier.handle_response(body.clone()).await.unwrap();
+        let claims: Json = serde_json::from_str(&presentation.credentials[0].claims).unwrap();
+        assert_eq!(claims["given_name"], "Alice");
+
+        // Responses cannot be replayed.
+        assert!(matches!(
+            verifier.handle_response(body).await,
+            Err(Oid4vpVerifierError::UnknownState(_))
+        ));
+
+        // Presentations must be bound to the nonce of the request.
+        let request = verifier
+            .create_request(definition.to_string())
+            .await
+            .unwrap();
+        let body = response(&holder, &request, "another nonce").await;
+        assert!(matches!(
+            verifier.handle_response(body).await,
+            Err(Oid4vpVerifierError::Verification { .. })
+        ));
+
+        // Requests expire.
+        let clock = Arc::new(FixedClock(SystemTime::now()));
+        verifier.set_clock(clock.clone());
+        let stale = verifier
+            .create_request(definition.to_string())
+            .await
+            .unwrap();
+        verifier.set_clock(Arc::new(FixedClock(clock.0 + REQUEST_LIFETIME * 2)));
+        let body = response(&holder, &stale, &stale.nonce).await;
+        assert!(matches!(
+            verifier.handle_response(body).await,
+            Err(Oid4vpVerifierError::UnknownState(_))
+        ));
+        verifier.set_clock(clock::system());
+
+        // Invalid responses do not prevent the holder from responding.
+        let body = form_urlencoded::Serializer::new(String::new())
+            .append_pair("state", &request.state)
+            .append_pair("vp_token", "garbage")
+            .finish();
+        assert!(verifier.handle_response(body).await.is_err());
+        let body = response(&holder, &request, &request.nonce).await;
+        assert!(verifier.handle_response(body).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn validates_responses_against_definitions() {
+        let verifier = Verifier::new(
+            "did:web:verifier".into(),
+            "https://verifier.example.com/response".parse().unwrap(),
+        );
+        let holder = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let descriptor = |id: &str, filter: Json| {
+            json!({
+                "id": id,
+                "constraints": {
+                    "fields": [{ "path": ["$.given_name"], "filter": filter }],
+                },
+            })
+        };
+        let respond = |definition: Json| {
+            let verifier = verifier.clone();
+            let holder = &holder;
+            async move {
+                let request = verifier
+                    .create_request(definition.to_string())
+                    .await
+                    .unwrap();
+                let body = response(holder, &request, &request.nonce).await;
+                verifier.handle_response(body).await
+            }
+        };
+
+        // Every required input descriptor must be answered.
+        let definition = json!({
+            "id": "identity",
+            "input_descriptors": [
+                descriptor("identity", json!({ "type": "string" })),
+                descriptor("address", json!({ "type": "string" })),
+            ],
+        });
+        assert!(matches!(
+            respond(definition).await,
+            Err(Oid4vpVerifierError::InvalidResponse { .. })
+        ));
+
+        // Unless the submission requirements allow it.
+        let mut definition = json!({
+            "id": "identity",
+            "input_descriptors": [
+                descriptor("identity", json!({ "type": "string" })),
+                descriptor("address", json!({ "type": "string" })),
+            ],
+            "submission_requirements": [{ "rule": "pick", "count": 1, "from": "A" }],
+        });
+        definition["input_descriptors"][0]["group"] = json!(["A"]);
+        definition["input_descriptors"][1]["group"] = json!(["A"]);
+        assert!(respond(definition).await.is_ok());
+
+        // The presented claims must match the filters of the fields.
+        let definition = json!({
+            "id": "identity",
+            "input_descriptors": [descriptor("identity", json!({ "const": "Bob" }))],
+        });
+        assert!(matches!(
+            respond(definition).await,
+            Err(Oid4vpVerifierError::InvalidResponse { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn encrypts_requests_of_mdocs() {
+        let verifier = Verifier::new(
+            "did:web:verifier".into(),
+            "https://verifier.example.com/response".parse().unwrap(),
+        );
+        let definition = json!({
+            "id": "mdl",
+            "input_descriptors": [{
+                "id": "org.iso.18013.5.1.mDL",
+                "format": { "mso_mdoc": { "alg": ["ES256"] } },
+                "constraints": {
+                    "fields": [{ "path": ["$['org.iso.18013.5.1']['family_name']"] }],
+                },
+            }],
+        });
+        let request = verifier
+            .create_request(definition.to_string())
+            .await
+            .unwrap();
+        let request_object: AuthorizationRequestObject =
+            serde_json::from_str(&request.request_object).unwrap();
+        let parameters: Json = serde_json::from_str(&request.request_object).unwrap();
+        assert_eq!(parameters["response_mode"], "direct_post.jwt");
+        assert_eq!(
+            parameters["client_metadata"]["jwks"]["keys"][0]["use"],
+            "enc"
+        );
+
+        let submission = json!({
+            "id": "submission",
+            "definition_id": "mdl",
+            "descriptor_map": [{
+                "id": "org.iso.18013.5.1.mDL",
+                "format": "mso_mdoc",
+                "path": "$",
+            }],
+        });
+        let vp_token = BASE64_URL_SAFE_NO_PAD.encode(serde_cbor::to_vec(&json!({})).unwrap());
+
+        // Mdocs must be presented in encrypted responses.
+        let body = form_urlencoded::Serializer::new(String::new())
+            .append_pair("state", &request.state)
+            .append_pair("vp_token", &vp_token)
+            .append_pair("presentation_submission", &submission.to_string())
+            .finish();
+        assert!(matches!(
+            verifier.handle_response(body).await,
+            Err(Oid4vpVerifierError::InvalidResponse { .. })
+        ));
+
+        // Encrypted responses are decrypted with the key of their request, and
+        // their mdocs verified against the trust anchors.
+        let response = AuthorizationResponse::Unencoded(
+            serde_json::from_value(json!({
+                "vp_token": vp_token,
+                "presentation_submission": submission,
+            }))
+            .unwrap(),
+        );
+        let jwe =
+            response_encryption::encrypt_response(&request_object, &response, Some("mdoc-nonce"))
+                .unwrap();
+        let body = form_urlencoded::Serializer::new(String::new())
+            .append_pair("response", &jwe)
+            .finish();
+        match verifier.handle_response(body).await {
+            Err(Oid4vpVerifierError::Verification { reason, .. }) => {
+                assert!(reason.contains("no trust anchors"), "{reason}")
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
 }
@@ -0,0 +1,357 @@
+use super::draft;
+use super::error::OID4VPError;
+use super::holder::Holder;
+use super::key_pinning::VerifierKeyChange;
+use super::request;
+use super::trusted_verifiers::{TrustedVerifier, VerifierPolicy};
+
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use uniffi::deps::log;
+
+/// The identity of a verifier requesting credentials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct VerifierInfo {
+    /// The client ID of the verifier, e.g. its DID.
+    pub client_id: String,
+    /// The client ID scheme of the request, if any.
+    pub client_id_scheme: Option<String>,
+    /// The URI the response will be sent to, if any.
+    pub response_uri: Option<String>,
+    /// Whether the client ID was authenticated, by the signature of the
+    /// request object or by the platform for Digital Credentials API
+    /// requests.
+    #[serde(default)]
+    pub client_id_verified: bool,
+    /// The metadata the verifier asserts about itself, which is not
+    /// authenticated even when the client ID is.
+    #[serde(default)]
+    pub client_metadata: VerifierMetadata,
+    /// The change of the key the verifier signs its requests with since it
+    /// was pinned, if any, see [super::key_pinning].
+    #[serde(default)]
+    pub key_change: Option<VerifierKeyChange>,
+}
+
+/// The `client_metadata` of a verifier, for display.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct VerifierMetadata {
+    pub client_name: Option<String>,
+    pub logo_uri: Option<String>,
+    /// The URI of the privacy policy of the verifier.
+    pub policy_uri: Option<String>,
+    /// The contacts of the verifier, e.g. email addresses.
+    pub contacts: Vec<String>,
+}
+
+impl VerifierMetadata {
+    fn from_json(client_metadata: &Json) -> Self {
+        let text = |name: &str| client_metadata[name].as_str().map(ToOwned::to_owned);
+        Self {
+            client_name: text("client_name"),
+            logo_uri: text("logo_uri"),
+            policy_uri: text("policy_uri"),
+            contacts: client_metadata["contacts"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|contact| contact.as_str().map(ToOwned::to_owned))
+                .collect(),
+        }
+    }
+}
+
+impl From<&AuthorizationRequestObject> for VerifierInfo {
+    fn from(request: &AuthorizationRequestObject) -> Self {
+        let client_id = request.client_id().0.clone();
+        let client_id_scheme = draft::client_id_scheme(request);
+        let client_metadata = request::parameters(request)
+            .get("client_metadata")
+            .map(VerifierMetadata::from_json)
+            .unwrap_or_default();
+
+        Self {
+            client_id_verified: is_authenticated(client_id_scheme.as_deref()),
+            response_uri: draft::response_endpoint(request),
+            client_id,
+            client_id_scheme,
+            client_metadata,
+            key_change: None,
+        }
+    }
+}
+
+/// Tell whether the client ID of a validated request, of the scheme, is
+/// authenticated.
+///
+/// Requests of the `did` scheme are signed with a key of the DID, and the
+/// `web-origin` client IDs of Digital Credentials API requests are reported
+/// by the platform. `redirect_uri` client IDs are not authenticated.
+fn is_authenticated(client_id_scheme: Option<&str>) -> bool {
+    matches!(
+        client_id_scheme,
+        Some("did" | "decentralized_identifier" | "web-origin")
+    )
+}
+
+/// The decision of the user on a verifier that is not in the trust store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum VerifierReviewDecision {
+    /// Proceed with the request, and add the verifier to the trust store.
+    Allow,
+    /// Proceed with this request only.
+    AllowOnce,
+    /// Reject the request.
+    Deny,
+}
+
+/// Interface: VerifierReviewDelegate
+///
+/// The VerifierReviewDelegate is called when a verifier is not in the trust
+/// store of the holder, so that the app can ask the user whether to proceed
+/// with the request.
+#[uniffi::export(with_foreign)]
+#[async_trait::async_trait]
+pub trait VerifierReviewDelegate: Send + Sync + std::fmt::Debug {
+    async fn review(&self, verifier: VerifierInfo) -> Result<VerifierReviewDecision, OID4VPError>;
+}
+
+impl Holder {
+    /// Check that the verifier is trusted, asking the review delegate when it
+    /// is not in the trust store, and return the names of the fields its
+    /// policy denies.
+    ///
+    /// The policy of the verifier in the trusted verifier store takes
+    /// precedence over the trusted DIDs, and verifiers whose signing key
+    /// changed are always reviewed. Requests from untrusted verifiers proceed
+    /// when no review delegate is set.
+    pub(crate) async fn review_verifier(
+        &self,
+        verifier: VerifierInfo,
+    ) -> Result<Vec<String>, OID4VPError> {
+        let store = self
+            .trusted_verifiers
+            .read()
+            .map_err(|_| OID4VPError::LockError("trusted_verifiers".into()))?
+            .clone();
+        let stored = match &store {
+            Some(store) => store.get(verifier.client_id.clone())?,
+            None => None,
+        };
+        let denied_fields = stored
+            .as_ref()
+            .map(|stored| stored.denied_fields.clone())
+            .unwrap_or_default();
+
+        let trusted = match stored.as_ref().map(|stored| stored.policy) {
+            Some(VerifierPolicy::AlwaysAllow) => true,
+            Some(VerifierPolicy::AlwaysAsk) => false,
+            None => self
+                .trusted_dids
+                .read()
+                .map_err(|_| OID4VPError::LockError("trusted_dids".into()))?
+                .contains(&verifier.client_id),
+        };
+        if verifier.key_change.is_some() {
+            log::warn!(
+                "The signing key of {} changed since it was pinned",
+                verifier.client_id
+            );
+        } else if trusted {
+            return Ok(denied_fields);
+        }
+
+        let delegate = self
+            .verifier_review_delegate
+            .read()
+            .map_err(|_| OID4VPError::LockError("verifier_review_delegate".into()))?
+            .clone();
+        let Some(delegate) = delegate else {
+            return Ok(denied_fields);
+        };
+
+        match delegate.review(verifier.clone()).await? {
+            // Verifiers that are always asked about are not added again.
+            VerifierReviewDecision::Allow if stored.is_some() => {}
+            VerifierReviewDecision::Allow => match store {
+                Some(store) => store.add(TrustedVerifier {
+                    client_id: verifier.client_id,
+                    policy: VerifierPolicy::AlwaysAllow,
+                    denied_fields: vec![],
+                })?,
+                None => self
+                    .trusted_dids
+                    .write()
+                    .map_err(|_| OID4VPError::LockError("trusted_dids".into()))?
+                    .push(verifier.client_id),
+            },
+            VerifierReviewDecision::AllowOnce => {}
+            VerifierReviewDecision::Deny => {
+                return Err(OID4VPError::VerifierDenied {
+                    client_id: verifier.client_id,
+                })
+            }
+        }
+        Ok(denied_fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+    use crate::oid4vp::trusted_verifiers::TrustedVerifierStore;
+
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[derive(Debug)]
+    struct TestDelegate {
+        decision: VerifierReviewDecision,
+        reviews: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl VerifierReviewDelegate for TestDelegate {
+        async fn review(
+            &self,
+            _verifier: VerifierInfo,
+        ) -> Result<VerifierReviewDecision, OID4VPError> {
+            self.reviews.fetch_add(1, Ordering::SeqCst);
+            Ok(self.decision)
+        }
+    }
+
+    fn verifier(client_id: &str) -> VerifierInfo {
+        VerifierInfo {
+            client_id: client_id.into(),
+            client_id_scheme: Some("did".into()),
+            response_uri: None,
+            client_id_verified: true,
+            client_metadata: VerifierMetadata::default(),
+            key_change: None,
+        }
+    }
+
+    async fn review_twice(
+        decision: VerifierReviewDecision,
+    ) -> (Result<Vec<String>, OID4VPError>, usize) {
+        let holder = Holder::new_with_credentials(vec![], vec!["did:web:trusted".into()])
+            .await
+            .unwrap();
+        let delegate = Arc::new(TestDelegate {
+            decision,
+            reviews: AtomicUsize::new(0),
+        });
+        holder
+            .set_verifier_review_delegate(delegate.clone())
+            .unwrap();
+
+        holder
+            .review_verifier(verifier("did:web:trusted"))
+            .await
+            .unwrap();
+        let _ = holder.review_verifier(verifier("did:web:untrusted")).await;
+        let result = holder.review_verifier(verifier("did:web:untrusted")).await;
+
+        (result, delegate.reviews.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn reviews_untrusted_verifiers() {
+        let (result, reviews) = review_twice(VerifierReviewDecision::Allow).await;
+        assert!(result.is_ok());
+        assert_eq!(reviews, 1);
+
+        let (result, reviews) = review_twice(VerifierReviewDecision::AllowOnce).await;
+        assert!(result.is_ok());
+        assert_eq!(reviews, 2);
+
+        let (result, reviews) = review_twice(VerifierReviewDecision::Deny).await;
+        assert!(matches!(result, Err(OID4VPError::VerifierDenied { .. })));
+        assert_eq!(reviews, 2);
+    }
+
+    #[tokio::test]
+    async fn applies_trusted_verifier_policies() {
+        let holder = Holder::new_with_credentials(vec![], vec!["did:web:asked".into()])
+            .await
+            .unwrap();
+        let store = TrustedVerifierStore::new(Arc::new(LocalStore::new()));
+        holder.set_trusted_verifier_store(store.clone()).unwrap();
+        let delegate = Arc::new(TestDelegate {
+            decision: VerifierReviewDecision::Allow,
+            reviews: AtomicUsize::new(0),
+        });
+        holder
+            .set_verifier_review_delegate(delegate.clone())
+            .unwrap();
+
+        store
+            .add(TrustedVerifier {
+                client_id: "did:web:allowed".into(),
+                policy: VerifierPolicy::AlwaysAllow,
+                denied_fields: vec!["birth_date".into()],
+            })
+            .unwrap();
+        store
+            .add(TrustedVerifier {
+                client_id: "did:web:asked".into(),
+                policy: VerifierPolicy::AlwaysAsk,
+                denied_fields: vec![],
+            })
+            .unwrap();
+
+        let denied_fields = holder
+            .review_verifier(verifier("did:web:allowed"))
+            .await
+            .unwrap();
+        assert_eq!(denied_fields, vec!["birth_date".to_string()]);
+        assert_eq!(delegate.reviews.load(Ordering::SeqCst), 0);
+
+        // The policy takes precedence over the trusted DIDs.
+        for _ in 0..2 {
+            holder
+                .review_verifier(verifier("did:web:asked"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(delegate.reviews.load(Ordering::SeqCst), 2);
+
+        // Allowed verifiers are persisted in the store.
+        holder
+            .review_verifier(verifier("did:web:new"))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("did:web:new".into()).unwrap().unwrap().policy,
+            VerifierPolicy::AlwaysAllow
+        );
+    }
+
+    #[test]
+    fn reads_client_metadata() {
+        let metadata = VerifierMetadata::from_json(&serde_json::json!({
+            "client_name": "Verifier",
+            "logo_uri": "https://verifier.example.com/logo.png",
+            "contacts": ["privacy@verifier.example.com", 1],
+            "vp_formats": {},
+        }));
+        assert_eq!(
+            metadata,
+            VerifierMetadata {
+                client_name: Some("Verifier".into()),
+                logo_uri: Some("https://verifier.example.com/logo.png".into()),
+                policy_uri: None,
+                contacts: vec!["privacy@verifier.example.com".into()],
+            }
+        );
+
+        assert!(is_authenticated(Some("did")));
+        assert!(is_authenticated(Some("web-origin")));
+        assert!(!is_authenticated(Some("redirect_uri")));
+    }
+}
@@ -0,0 +1,229 @@
+use super::error::OID4VPError;
+use super::response_encryption;
+use crate::credential::CredentialFormat;
+
+use openid4vp::core::metadata::WalletMetadata;
+use serde_json::{json, Map, Value as Json};
+
+/// The credential formats the holder can present.
+pub(crate) const SUPPORTED_FORMATS: &[&str] = &[
+    "vcdm2_sd_jwt",
+    "dc+sd-jwt",
+    "jwt_vc_json",
+    "jwt_vp_json",
+    "mso_mdoc",
+    "cwt",
+];
+
+/// The credential formats the holder can answer age predicates in, from the
+/// age claims of their credentials.
+pub(crate) const AGE_PREDICATE_FORMATS: &[&str] = &["vcdm2_sd_jwt", "dc+sd-jwt", "mso_mdoc"];
+
+/// The algorithms the holder can sign presentations with.
+pub(crate) const SUPPORTED_ALGORITHMS: &[&str] = &["ES256", "ES384", "ES512", "EdDSA"];
+
+/// The algorithms the holder can verify signed requests with.
+pub(crate) const SUPPORTED_REQUEST_ALGORITHMS: &[&str] = &["ES256", "ES384", "EdDSA"];
+
+/// The client ID schemes the holder can verify requests of.
+pub(crate) const SUPPORTED_CLIENT_ID_SCHEMES: &[&str] = &["did", "redirect_uri", "x509_san_dns"];
+
+/// The response modes the holder can submit responses with.
+pub(crate) const SUPPORTED_RESPONSE_MODES: &[&str] = &["direct_post", "direct_post.jwt"];
+
+/// The capabilities the holder declares to verifiers in its wallet metadata.
+///
+/// Every value must be one the SDK can fulfill. Empty lists declare every
+/// supported value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct WalletMetadataConfig {
+    /// Credential formats, e.g. `vcdm2_sd_jwt` or `mso_mdoc`.
+    pub formats: Vec<String>,
+    /// Signing algorithms, e.g. `ES256`.
+    pub algorithms: Vec<String>,
+    /// Client ID schemes, e.g. `did`.
+    pub client_id_schemes: Vec<String>,
+    /// Response modes, e.g. `direct_post`.
+    pub response_modes: Vec<String>,
+    /// Declare, of the formats, only those the credentials of the holder can
+    /// be presented in, computed whenever the metadata is sent to verifiers.
+    pub derive_formats: bool,
+}
+
+/// Return the configured values, checking that they are all supported, or
+/// every supported value if none is configured.
+fn supported_values(
+    kind: &str,
+    values: &[String],
+    supported: &[&str],
+) -> Result<Vec<String>, OID4VPError> {
+    if values.is_empty() {
+        return Ok(supported.iter().map(|value| value.to_string()).collect());
+    }
+
+    match values
+        .iter()
+        .find(|value| !supported.contains(&value.as_str()))
+    {
+        Some(value) => Err(OID4VPError::MetadataInitialization {
+            reason: format!("unsupported {kind}: {value}"),
+            source: None,
+        }),
+        None => Ok(values.to_vec()),
+    }
+}
+
+impl WalletMetadataConfig {
+    /// Build the wallet metadata, validating it against what the SDK can
+    /// fulfill.
+    pub(crate) fn wallet_metadata(&self) -> Result<WalletMetadata, OID4VPError> {
+        let formats = supported_values("format", &self.formats, SUPPORTED_FORMATS)?;
+        let algorithms = supported_values("algorithm", &self.algorithms, SUPPORTED_ALGORITHMS)?;
+        let client_id_schemes = supported_values(
+            "client ID scheme",
+            &self.client_id_schemes,
+            SUPPORTED_CLIENT_ID_SCHEMES,
+        )?;
+        let response_modes = supported_values(
+            "response mode",
+            &self.response_modes,
+            SUPPORTED_RESPONSE_MODES,
+        )?;
+
+        let age_predicate_formats = formats
+            .iter()
+            .filter(|format| AGE_PREDICATE_FORMATS.contains(&format.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        let vp_formats_supported = formats
+            .into_iter()
+            .map(|format| {
+                // mdoc formats declare their algorithms as `alg`, and IETF
+                // SD-JWT VCs those of the issuer and key binding JWTs.
+                let payload = match format.as_str() {
+                    "mso_mdoc" => json!({ "alg": algorithms }),
+                    "dc+sd-jwt" => json!({
+                        "sd-jwt_alg_values": algorithms,
+                        "kb-jwt_alg_values": algorithms,
+                    }),
+                    _ => json!({ "alg_values_supported": algorithms }),
+                };
+                (format, payload)
+            })
+            .collect::<Map<String, Json>>();
+
+        let mut metadata = serde_json::to_value(WalletMetadata::openid4vp_scheme_static())
+            .map_err(OID4VPError::metadata_initialization)?;
+        metadata["vp_formats_supported"] = Json::Object(vp_formats_supported);
+        metadata["client_id_schemes_supported"] = json!(client_id_schemes);
+        metadata["response_modes_supported"] = json!(response_modes);
+        metadata["age_predicate_formats_supported"] = json!(age_predicate_formats);
+        metadata["request_object_signing_alg_values_supported"] =
+            json!(SUPPORTED_REQUEST_ALGORITHMS);
+        if response_modes.iter().any(|mode| mode == "direct_post.jwt") {
+            metadata["authorization_encryption_alg_values_supported"] =
+                json!([response_encryption::ECDH_ES]);
+            metadata["authorization_encryption_enc_values_supported"] =
+                json!([response_encryption::A256GCM]);
+        }
+
+        serde_json::from_value(metadata).map_err(OID4VPError::metadata_initialization)
+    }
+}
+
+/// Restrict the declared formats of the metadata to those credentials of the
+/// given formats can be presented in.
+pub(crate) fn with_credential_formats(
+    metadata: &WalletMetadata,
+    formats: &[CredentialFormat],
+) -> Result<WalletMetadata, OID4VPError> {
+    let presentation_formats = formats
+        .iter()
+        .flat_map(|format| match format {
+            // JWT VCs are presented in JWT VPs.
+            CredentialFormat::JwtVcJson => vec!["jwt_vc_json".into(), "jwt_vp_json".into()],
+            format => vec![format.to_string()],
+        })
+        .collect::<Vec<String>>();
+
+    let mut metadata =
+        serde_json::to_value(metadata).map_err(OID4VPError::metadata_initialization)?;
+    if let Some(vp_formats_supported) = metadata["vp_formats_supported"].as_object_mut() {
+        vp_formats_supported.retain(|format, _| presentation_formats.contains(format));
+    }
+    if let Some(age_predicate_formats) = metadata["age_predicate_formats_supported"].as_array_mut()
+    {
+        age_predicate_formats.retain(|format| {
+            format
+                .as_str()
+                .is_some_and(|format| presentation_formats.iter().any(|f| f == format))
+        });
+    }
+
+    serde_json::from_value(metadata).map_err(OID4VPError::metadata_initialization)
+}
+
+/// Declare the algorithms signed requests can be verified with.
+pub(crate) fn with_request_algorithms(
+    metadata: WalletMetadata,
+) -> Result<WalletMetadata, OID4VPError> {
+    let mut metadata =
+        serde_json::to_value(metadata).map_err(OID4VPError::metadata_initialization)?;
+    metadata["request_object_signing_alg_values_supported"] = json!(SUPPORTED_REQUEST_ALGORITHMS);
+
+    serde_json::from_value(metadata).map_err(OID4VPError::metadata_initialization)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_wallet_metadata() {
+        let config = WalletMetadataConfig {
+            formats: vec!["mso_mdoc".into()],
+            client_id_schemes: vec!["did".into()],
+            ..Default::default()
+        };
+        let metadata = serde_json::to_value(config.wallet_metadata().unwrap()).unwrap();
+        assert_eq!(
+            metadata["vp_formats_supported"],
+            json!({ "mso_mdoc": { "alg": ["ES256", "ES384", "ES512", "EdDSA"] } })
+        );
+        assert_eq!(metadata["client_id_schemes_supported"], json!(["did"]));
+        assert_eq!(
+            metadata["age_predicate_formats_supported"],
+            json!(["mso_mdoc"])
+        );
+        assert_eq!(
+            metadata["response_modes_supported"],
+            json!(["direct_post", "direct_post.jwt"])
+        );
+        assert_eq!(
+            metadata["authorization_encryption_enc_values_supported"],
+            json!(["A256GCM"])
+        );
+
+        let metadata = WalletMetadataConfig::default().wallet_metadata().unwrap();
+        let metadata = with_credential_formats(
+            &metadata,
+            &[CredentialFormat::JwtVcJson, CredentialFormat::LdpVc],
+        )
+        .unwrap();
+        let metadata = serde_json::to_value(metadata).unwrap();
+        let formats = metadata["vp_formats_supported"].as_object().unwrap();
+        assert_eq!(
+            formats.keys().collect::<Vec<_>>(),
+            ["jwt_vc_json", "jwt_vp_json"]
+        );
+
+        let config = WalletMetadataConfig {
+            algorithms: vec!["ES256".into(), "RS256".into()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.wallet_metadata(),
+            Err(OID4VPError::MetadataInitialization { .. })
+        ));
+    }
+}
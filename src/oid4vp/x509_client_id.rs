@@ -0,0 +1,82 @@
+//! Verification of requests of the `x509_san_dns` client ID scheme, whose
+//! request objects are signed with a certificate chaining to a trust anchor,
+//! issued for the DNS name of the client ID.
+
+use super::draft;
+use super::request;
+use crate::trust_anchors::verify_chain;
+
+use std::time::SystemTime;
+
+use base64::prelude::*;
+use openid4vp::core::authorization_request::AuthorizationRequestObject;
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use uniffi::deps::anyhow::{anyhow, bail, Context, Result};
+use x509_cert::{
+    der::{Decode, Encode},
+    ext::pkix::{name::GeneralName, SubjectAltName},
+    Certificate,
+};
+
+/// Verify that a request object is signed with the key of the leaf of its
+/// `x5c` chain, which chains to a trust anchor and whose DNS names include the
+/// client ID.
+///
+/// NOTE: only ES256 request objects are supported.
+pub(crate) async fn verify(
+    request: &AuthorizationRequestObject,
+    request_jwt: &str,
+    trust_anchors: &[Certificate],
+    check_revocation: bool,
+    now: SystemTime,
+) -> Result<()> {
+    let dns_name = draft::client_identifier(request);
+
+    let Some((signing_input, signature)) = request_jwt.rsplit_once('.') else {
+        bail!("the request object is not a JWT")
+    };
+    let header = request::jwt_part(signing_input, 0).context("the request object is not a JWT")?;
+    if header["alg"] != "ES256" {
+        bail!("unsupported request object algorithm: {}", header["alg"]);
+    }
+
+    let chain = header["x5c"]
+        .as_array()
+        .context("the request object has no x5c header")?
+        .iter()
+        .map(|certificate| {
+            let der = BASE64_STANDARD.decode(certificate.as_str().context("invalid x5c")?)?;
+            Ok(Certificate::from_der(&der)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let leaf = chain.first().context("the x5c header is empty")?;
+    if !dns_names(leaf)?.iter().any(|name| name == dns_name) {
+        bail!("the certificate of the request object is not issued for {dns_name}");
+    }
+    verify_chain(&chain, trust_anchors, check_revocation, now).await?;
+
+    let spki = leaf.tbs_certificate.subject_public_key_info.to_der()?;
+    let key = VerifyingKey::from_public_key_der(&spki).map_err(|e| anyhow!("{e:?}"))?;
+    let signature = Signature::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(signature)?)
+        .map_err(|e| anyhow!("{e:?}"))?;
+    key.verify(signing_input.as_bytes(), &signature)
+        .context("the request object signature is invalid")
+}
+
+/// Return the DNS names of the subject alternative names of a certificate.
+fn dns_names(certificate: &Certificate) -> Result<Vec<String>> {
+    let Some((_, SubjectAltName(names))) = certificate.tbs_certificate.get::<SubjectAltName>()?
+    else {
+        return Ok(vec![]);
+    };
+    Ok(names
+        .into_iter()
+        .filter_map(|name| match name {
+            GeneralName::DnsName(name) => Some(name.to_string()),
+            _ => None,
+        })
+        .collect())
+}
@@ -0,0 +1,293 @@
+//! A history of the presentations the holder has made.
+//!
+//! Every submitted permission response is recorded in the [PresentationLog],
+//! so that wallets can show the user where a credential has been shared, along
+//! with the receipts of proximity presentations.
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::common::*;
+use crate::mdl::receipt::ProximityReceipt;
+use crate::oid4vp::permission_request::PermissionResponse;
+use crate::oid4vp::verifier_review::VerifierInfo;
+use crate::storage_manager::*;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Internal prefix for presentation record keys.
+const KEY_PREFIX: &str = "PresentationLog.";
+
+/// The outcome of a presentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, uniffi::Enum)]
+pub enum PresentationOutcome {
+    /// The response was accepted by the verifier.
+    Submitted,
+    /// Submitting the response failed.
+    Failed,
+}
+
+/// A credential presented to a verifier.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, uniffi::Record)]
+pub struct PresentedCredential {
+    pub credential_id: Uuid,
+    pub credential_type: CredentialType,
+    /// The names of the fields that were disclosed.
+    pub disclosed_fields: Vec<String>,
+}
+
+/// A record of a presentation made to a verifier.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, uniffi::Record)]
+pub struct PresentationRecord {
+    pub id: Uuid,
+    pub verifier: VerifierInfo,
+    pub credentials: Vec<PresentedCredential>,
+    pub timestamp: SystemTime,
+    pub outcome: PresentationOutcome,
+    /// The error the submission failed with, if any.
+    pub error: Option<String>,
+    /// The signed receipt of the presentation, for proximity presentations.
+    #[serde(default)]
+    pub receipt: Option<ProximityReceipt>,
+}
+
+impl PresentationRecord {
+    /// Create a record of a permission response.
+    pub(crate) fn new(
+        response: &PermissionResponse,
+        timestamp: SystemTime,
+        outcome: PresentationOutcome,
+        error: Option<String>,
+    ) -> Self {
+        let credentials = response
+            .selected_credentials
+            .iter()
+            .map(|credential| PresentedCredential {
+                credential_id: credential.id(),
+                credential_type: credential.r#type(),
+                disclosed_fields: response
+                    .disclosed_fields(credential)
+                    .iter()
+                    .filter_map(|field| {
+                        field
+                            .name()
+                            .or_else(|| field.pointers.first().map(|pointer| pointer.join(".")))
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            id: Uuid::new_v4(),
+            verifier: VerifierInfo::from(&response.authorization_request),
+            credentials,
+            timestamp,
+            outcome,
+            error,
+            receipt: None,
+        }
+    }
+}
+
+#[derive(Error, Debug, uniffi::Error)]
+pub enum PresentationLogError {
+    /// Attempt to convert the record to a serialized form suitable for writing to storage failed.
+    #[error("Failed to Serialize Value")]
+    SerializeFailed,
+
+    /// Attempting to convert the record to a deserialized form suitable for runtime use failed.
+    #[error("Failed to Deserialize Value")]
+    DeserializeFailed,
+
+    /// Attempting to write the record to storage failed.
+    #[error("Failed to Write to Storage")]
+    StoreFailed(StorageManagerError),
+
+    /// Attempting to read the record from storage failed.
+    #[error("Failed to Read from Storage")]
+    LoadFailed(StorageManagerError),
+
+    /// Attempting to delete a record from storage failed.
+    #[error("Failed to Delete from Storage")]
+    DeleteFailed(StorageManagerError),
+}
+
+/// Presentation Log
+///
+/// A store of the presentations the holder has made, persisted in the
+/// storage manager alongside the credentials.
+#[derive(Debug, uniffi::Object)]
+pub struct PresentationLog {
+    storage: Arc<dyn StorageManagerInterface>,
+}
+
+#[uniffi::export]
+impl PresentationLog {
+    #[uniffi::constructor]
+    pub fn new(engine: Arc<dyn StorageManagerInterface>) -> Arc<Self> {
+        Arc::new(Self { storage: engine })
+    }
+
+    /// Add a record to the log.
+    pub fn add(&self, record: PresentationRecord) -> Result<(), PresentationLogError> {
+        let value =
+            serde_cbor::to_vec(&record).map_err(|_| PresentationLogError::SerializeFailed)?;
+
+        self.storage
+            .add(Self::id_to_key(record.id), Value(value))
+            .map_err(PresentationLogError::StoreFailed)
+    }
+
+    /// Get a record from the log.
+    pub fn get(&self, id: Uuid) -> Result<Option<PresentationRecord>, PresentationLogError> {
+        let Some(raw) = self
+            .storage
+            .get(Self::id_to_key(id))
+            .map_err(PresentationLogError::LoadFailed)?
+        else {
+            return Ok(None);
+        };
+
+        serde_cbor::from_slice(&raw.0)
+            .map(Some)
+            .map_err(|_| PresentationLogError::DeserializeFailed)
+    }
+
+    /// Remove a record from the log.
+    pub fn delete(&self, id: Uuid) -> Result<(), PresentationLogError> {
+        self.storage
+            .remove(Self::id_to_key(id))
+            .map_err(PresentationLogError::DeleteFailed)
+    }
+
+    /// Get every record in the log, most recent first.
+    pub fn all(&self) -> Result<Vec<PresentationRecord>, PresentationLogError> {
+        let mut records = self
+            .storage
+            .list()
+            .map_err(PresentationLogError::LoadFailed)?
+            .iter()
+            .filter_map(Self::key_to_id)
+            .filter_map(|id| self.get(id).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        records.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        Ok(records)
+    }
+
+    /// Get the records of the presentations of a credential, most recent first.
+    pub fn by_credential(
+        &self,
+        credential_id: Uuid,
+    ) -> Result<Vec<PresentationRecord>, PresentationLogError> {
+        Ok(self
+            .all()?
+            .into_iter()
+            .filter(|record| {
+                record
+                    .credentials
+                    .iter()
+                    .any(|credential| credential.credential_id == credential_id)
+            })
+            .collect())
+    }
+
+    /// Get the records of the presentations made from `from` (inclusive) to
+    /// `to` (exclusive), most recent first.
+    pub fn by_date_range(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> Result<Vec<PresentationRecord>, PresentationLogError> {
+        Ok(self
+            .all()?
+            .into_iter()
+            .filter(|record| from <= record.timestamp && record.timestamp < to)
+            .collect())
+    }
+}
+
+impl PresentationLog {
+    /// Convert a UUID to a storage key.
+    fn id_to_key(id: Uuid) -> Key {
+        Key(format!("{}{}", KEY_PREFIX, id))
+    }
+
+    /// Convert a storage key to a UUID.
+    ///
+    /// Returns `None` if it's not the right format.
+    fn key_to_id(key: &Key) -> Option<Uuid> {
+        key.strip_prefix(KEY_PREFIX)
+            .and_then(|id| Uuid::parse_str(&id).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+
+    use std::time::Duration;
+
+    fn record(credential_ids: &[Uuid], seconds: u64) -> PresentationRecord {
+        PresentationRecord {
+            id: Uuid::new_v4(),
+            verifier: VerifierInfo {
+                client_id: "did:web:verifier.example".into(),
+                client_id_scheme: Some("did".into()),
+                response_uri: None,
+                client_id_verified: true,
+                client_metadata: Default::default(),
+                key_change: None,
+            },
+            credentials: credential_ids
+                .iter()
+                .map(|id| PresentedCredential {
+                    credential_id: *id,
+                    credential_type: CredentialType("org.iso.18013.5.1.mDL".into()),
+                    disclosed_fields: vec!["family_name".into()],
+                })
+                .collect(),
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(seconds),
+            outcome: PresentationOutcome::Submitted,
+            error: None,
+            receipt: None,
+        }
+    }
+
+    #[test]
+    fn queries_records() {
+        let storage = Arc::new(LocalStore::new());
+        let log = PresentationLog::new(storage.clone());
+
+        // Other entries in the storage are ignored.
+        storage
+            .add(Key("Credential.1".into()), Value(vec![1, 2, 3]))
+            .unwrap();
+
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let first = record(&[a], 1_000);
+        let second = record(&[a, b], 2_000);
+        let third = record(&[b], 3_000);
+        for record in [&first, &second, &third] {
+            log.add(record.clone()).unwrap();
+        }
+
+        assert_eq!(
+            log.all().unwrap(),
+            vec![third.clone(), second.clone(), first.clone()]
+        );
+        assert_eq!(
+            log.by_credential(a).unwrap(),
+            vec![second.clone(), first.clone()]
+        );
+        assert_eq!(
+            log.by_date_range(first.timestamp, third.timestamp).unwrap(),
+            vec![second.clone(), first.clone()]
+        );
+
+        log.delete(second.id).unwrap();
+        assert_eq!(log.by_credential(b).unwrap(), vec![third]);
+    }
+}
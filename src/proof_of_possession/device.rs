@@ -0,0 +1,276 @@
+//! Proofs of possession of device keys, signed through the [DeviceSigner] so
+//! the keys never leave the device.
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::prelude::*;
+use serde_cbor::Value as Cbor;
+use serde_json::{json, Value as Json};
+
+use super::PopError;
+use crate::common::KeyAlias;
+use crate::signer::{self, DeviceSigner};
+
+/// The proof types of OID4VCI credential requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum ProofType {
+    /// A JWT, with the `openid4vci-proof+jwt` type.
+    Jwt,
+    /// A CWT, with the `openid4vci-proof+cwt` content type.
+    Cwt,
+}
+
+/// Generate a proof of possession of the device key of the alias, signed by
+/// the device signer.
+///
+/// The `nonce` is the `c_nonce` of the issuer, if it gave one. The `issuer`
+/// is the client ID of the wallet, omitted for anonymous pre-authorized
+/// issuances.
+#[uniffi::export]
+pub async fn generate_pop_with_signer(
+    proof_type: ProofType,
+    audience: String,
+    issuer: Option<String>,
+    nonce: Option<String>,
+    signer: Arc<dyn DeviceSigner>,
+    key_alias: KeyAlias,
+) -> Result<String, PopError> {
+    device_proof(
+        proof_type,
+        &audience,
+        issuer.as_deref(),
+        nonce,
+        signer.as_ref(),
+        &key_alias,
+    )
+    .await
+}
+
+pub(crate) async fn device_proof(
+    proof_type: ProofType,
+    audience: &str,
+    issuer: Option<&str>,
+    nonce: Option<String>,
+    signer: &dyn DeviceSigner,
+    key_alias: &KeyAlias,
+) -> Result<String, PopError> {
+    let algorithm = signer.algorithm(key_alias.clone())?;
+    let jwk: Json = serde_json::from_str(&signer.jwk(key_alias.clone())?)?;
+    let issued_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    match proof_type {
+        ProofType::Jwt => {
+            let header = json!({
+                "typ": "openid4vci-proof+jwt",
+                "alg": algorithm,
+                "jwk": jwk,
+            });
+            let mut claims = json!({
+                "aud": audience,
+                "iat": issued_at,
+            });
+            if let Some(issuer) = issuer {
+                claims["iss"] = json!(issuer);
+            }
+            if let Some(nonce) = nonce {
+                claims["nonce"] = json!(nonce);
+            }
+
+            let signing_input = format!(
+                "{}.{}",
+                BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+                BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+            );
+            let signature =
+                signer::sign_raw(signer, key_alias, signing_input.as_bytes().to_vec()).await?;
+
+            Ok(format!(
+                "{signing_input}.{}",
+                BASE64_URL_SAFE_NO_PAD.encode(signature)
+            ))
+        }
+        ProofType::Cwt => {
+            let protected = serde_cbor::to_vec(&cbor_map([
+                (
+                    Cbor::Integer(1),
+                    Cbor::Integer(signer::cose_algorithm(&algorithm)?),
+                ),
+                (Cbor::Integer(3), Cbor::Text("openid4vci-proof+cwt".into())),
+                (
+                    Cbor::Text("COSE_Key".into()),
+                    Cbor::Bytes(serde_cbor::to_vec(&cose_key(&jwk)?)?),
+                ),
+            ]))?;
+
+            let mut claims = BTreeMap::from([
+                (Cbor::Integer(3), Cbor::Text(audience.into())),
+                (Cbor::Integer(6), Cbor::Integer(issued_at.into())),
+            ]);
+            if let Some(issuer) = issuer {
+                claims.insert(Cbor::Integer(1), Cbor::Text(issuer.into()));
+            }
+            if let Some(nonce) = nonce {
+                claims.insert(Cbor::Integer(10), Cbor::Bytes(nonce.into_bytes()));
+            }
+            let payload = serde_cbor::to_vec(&Cbor::Map(claims))?;
+
+            let sig_structure = serde_cbor::to_vec(&Cbor::Array(vec![
+                Cbor::Text("Signature1".into()),
+                Cbor::Bytes(protected.clone()),
+                Cbor::Bytes(vec![]),
+                Cbor::Bytes(payload.clone()),
+            ]))?;
+            let signature = signer::sign_raw(signer, key_alias, sig_structure).await?;
+
+            let cose_sign1 = Cbor::Tag(
+                18,
+                Box::new(Cbor::Array(vec![
+                    Cbor::Bytes(protected),
+                    Cbor::Map(BTreeMap::new()),
+                    Cbor::Bytes(payload),
+                    Cbor::Bytes(signature),
+                ])),
+            );
+
+            Ok(BASE64_URL_SAFE_NO_PAD.encode(serde_cbor::to_vec(&cose_sign1)?))
+        }
+    }
+}
+
+fn cbor_map<const N: usize>(entries: [(Cbor, Cbor); N]) -> Cbor {
+    Cbor::Map(entries.into_iter().collect())
+}
+
+/// Convert a public JWK to a COSE_Key.
+fn cose_key(jwk: &Json) -> Result<Cbor, PopError> {
+    let coordinate = |name: &str| -> Result<Cbor, PopError> {
+        let value = jwk[name]
+            .as_str()
+            .ok_or_else(|| PopError::UnsupportedKey(format!("missing {name}")))?;
+        BASE64_URL_SAFE_NO_PAD
+            .decode(value)
+            .map(Cbor::Bytes)
+            .map_err(|e| PopError::UnsupportedKey(format!("{e:?}")))
+    };
+
+    match (jwk["kty"].as_str(), jwk["crv"].as_str()) {
+        (Some("EC"), Some(crv)) => {
+            let crv = match crv {
+                "P-256" => 1,
+                "P-384" => 2,
+                "P-521" => 3,
+                _ => return Err(PopError::UnsupportedKey(crv.into())),
+            };
+            Ok(cbor_map([
+                (Cbor::Integer(1), Cbor::Integer(2)),
+                (Cbor::Integer(-1), Cbor::Integer(crv)),
+                (Cbor::Integer(-2), coordinate("x")?),
+                (Cbor::Integer(-3), coordinate("y")?),
+            ]))
+        }
+        (Some("OKP"), Some("Ed25519")) => Ok(cbor_map([
+            (Cbor::Integer(1), Cbor::Integer(1)),
+            (Cbor::Integer(-1), Cbor::Integer(6)),
+            (Cbor::Integer(-2), coordinate("x")?),
+        ])),
+        (kty, crv) => Err(PopError::UnsupportedKey(format!("{kty:?} {crv:?}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oid4vp::key_binding::tests::TestSigner;
+
+    use p256::ecdsa::{signature::Verifier, Signature, SigningKey};
+
+    #[tokio::test]
+    async fn signs_proofs_of_possession() {
+        let signer = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let key_alias = KeyAlias("key".into());
+        let proof = device_proof(
+            ProofType::Jwt,
+            "https://issuer.example.com",
+            None,
+            Some("nonce".into()),
+            &signer,
+            &key_alias,
+        )
+        .await
+        .unwrap();
+
+        let (signing_input, signature) = proof.rsplit_once('.').unwrap();
+        let signature =
+            Signature::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(signature).unwrap()).unwrap();
+        assert!(signer
+            .0
+            .verifying_key()
+            .verify(signing_input.as_bytes(), &signature)
+            .is_ok());
+
+        let decode = |part: &str| -> Json {
+            serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(part).unwrap()).unwrap()
+        };
+        let (header, claims) = signing_input.split_once('.').unwrap();
+        assert_eq!(decode(header)["typ"], "openid4vci-proof+jwt");
+        assert_eq!(decode(header)["jwk"], signer.jwk());
+        assert_eq!(decode(claims)["aud"], "https://issuer.example.com");
+        assert_eq!(decode(claims)["nonce"], "nonce");
+
+        let proof = device_proof(
+            ProofType::Cwt,
+            "https://issuer.example.com",
+            Some("wallet"),
+            Some("nonce".into()),
+            &signer,
+            &key_alias,
+        )
+        .await
+        .unwrap();
+
+        let Cbor::Tag(18, cose_sign1) =
+            serde_cbor::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(proof).unwrap()).unwrap()
+        else {
+            panic!("not a COSE_Sign1");
+        };
+        let Cbor::Array(cose_sign1) = *cose_sign1 else {
+            panic!("not a COSE_Sign1");
+        };
+        let [Cbor::Bytes(protected), _, Cbor::Bytes(payload), Cbor::Bytes(signature)] =
+            &cose_sign1[..]
+        else {
+            panic!("not a COSE_Sign1");
+        };
+
+        let sig_structure = serde_cbor::to_vec(&Cbor::Array(vec![
+            Cbor::Text("Signature1".into()),
+            Cbor::Bytes(protected.clone()),
+            Cbor::Bytes(vec![]),
+            Cbor::Bytes(payload.clone()),
+        ]))
+        .unwrap();
+        let signature = Signature::from_slice(signature).unwrap();
+        assert!(signer
+            .0
+            .verifying_key()
+            .verify(&sig_structure, &signature)
+            .is_ok());
+
+        let Cbor::Map(claims) = serde_cbor::from_slice(payload).unwrap() else {
+            panic!("claims are not a map");
+        };
+        assert_eq!(
+            claims[&Cbor::Integer(3)],
+            Cbor::Text("https://issuer.example.com".into())
+        );
+        assert_eq!(claims[&Cbor::Integer(1)], Cbor::Text("wallet".into()));
+        assert_eq!(claims[&Cbor::Integer(10)], Cbor::Bytes(b"nonce".to_vec()));
+    }
+}
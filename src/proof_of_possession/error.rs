@@ -15,4 +15,13 @@ pub enum PopError {
 
     #[error("{_0}")]
     ConversionError(#[from] oid4vci::proof_of_possession::ConversionError),
+
+    #[error("{_0}")]
+    SignerError(#[from] crate::signer::DeviceSignerError),
+
+    #[error("{_0}")]
+    CborError(#[from] serde_cbor::Error),
+
+    #[error("unsupported key: {_0}")]
+    UnsupportedKey(String),
 }
@@ -9,10 +9,13 @@ use oid4vci::{
 use ssi::{dids::DIDURLBuf, jwk::JWK};
 use url::Url;
 
+pub(crate) use device::device_proof;
+pub use device::{generate_pop_with_signer, ProofType};
 pub use error::*;
 
 use crate::{did, oid4vci::Oid4vciError};
 
+mod device;
 mod error;
 
 // TODO: consider unifying prepare and complete fns by using a trait for
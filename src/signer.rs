@@ -0,0 +1,208 @@
+use crate::common::KeyAlias;
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum DeviceSignerError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
+    #[error("Key not found for alias: {0}")]
+    KeyNotFound(String),
+    #[error("Unsupported algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+    #[error("Failed to sign the payload: {0}")]
+    Signing(String),
+}
+
+impl DeviceSignerError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnexpectedUniFFICallbackError(..) => "device_signer.unexpected_callback_error",
+            Self::KeyNotFound(..) => "device_signer.key_not_found",
+            Self::UnsupportedAlgorithm(..) => "device_signer.unsupported_algorithm",
+            Self::Signing(..) => "device_signer.signing",
+        }
+    }
+}
+
+/// Return the stable, machine-readable code of the error, e.g.
+/// `device_signer.signing`.
+#[uniffi::export]
+pub fn device_signer_error_code(error: DeviceSignerError) -> String {
+    error.code().to_string()
+}
+
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for DeviceSignerError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        DeviceSignerError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+/// Interface: DeviceSigner
+///
+/// The DeviceSigner provides access to the device keys held by the native
+/// platform (Secure Enclave, Android Keystore), which never leave the device.
+/// It is used wherever the holder needs to prove possession of the key a
+/// credential is bound to, for example mdoc device authentication.
+///
+/// Keys are identified by the [KeyAlias] stored alongside the credential.
+#[uniffi::export(with_foreign)]
+#[async_trait::async_trait]
+pub trait DeviceSigner: Send + Sync + std::fmt::Debug {
+    /// Return the JWS algorithm name of the key, for example `ES256`.
+    fn algorithm(&self, key_alias: KeyAlias) -> Result<String, DeviceSignerError>;
+
+    /// Return the public key as a JSON encoded JWK.
+    fn jwk(&self, key_alias: KeyAlias) -> Result<String, DeviceSignerError>;
+
+    /// Sign the payload with the key.
+    ///
+    /// ECDSA signatures may be returned either DER encoded (as produced by
+    /// most platform APIs) or in the raw `r || s` form.
+    async fn sign(
+        &self,
+        key_alias: KeyAlias,
+        payload: Vec<u8>,
+    ) -> Result<Vec<u8>, DeviceSignerError>;
+}
+
+/// Interface: DeviceKeyAgreement
+///
+/// Key agreement with the device keys held by the native platform, for the
+/// MACs of mdoc device authentication, whose keys are derived from the ECDH
+/// shared secret of the device key and the ephemeral key of the reader.
+#[uniffi::export(with_foreign)]
+#[async_trait::async_trait]
+pub trait DeviceKeyAgreement: Send + Sync + std::fmt::Debug {
+    /// Return the ECDH shared secret, the x-coordinate of the shared point,
+    /// of the key and the SEC1 encoded public key of the other party.
+    async fn shared_secret(
+        &self,
+        key_alias: KeyAlias,
+        public_key: Vec<u8>,
+    ) -> Result<Vec<u8>, DeviceSignerError>;
+}
+
+/// Sign a payload with the device signer, returning the signature in the raw
+/// form used by JOSE and COSE.
+pub(crate) async fn sign_raw(
+    signer: &dyn DeviceSigner,
+    key_alias: &KeyAlias,
+    payload: Vec<u8>,
+) -> Result<Vec<u8>, DeviceSignerError> {
+    let algorithm = signer.algorithm(key_alias.clone())?;
+    let signature = signer.sign(key_alias.clone(), payload).await?;
+
+    match ecdsa_component_size(&algorithm) {
+        Some(size) => match der_to_raw(&signature, size) {
+            Some(signature) => Ok(signature),
+            None if signature.len() == 2 * size => Ok(signature),
+            None => Err(DeviceSignerError::Signing(format!(
+                "invalid {algorithm} signature"
+            ))),
+        },
+        // EdDSA signatures only have a raw form.
+        None => Ok(signature),
+    }
+}
+
+/// Return the size in bytes of the `r` and `s` values of the signatures of an
+/// ECDSA algorithm, or `None` for other algorithms.
+fn ecdsa_component_size(algorithm: &str) -> Option<usize> {
+    match algorithm {
+        "ES256" => Some(32),
+        "ES384" => Some(48),
+        "ES512" => Some(66),
+        _ => None,
+    }
+}
+
+/// Convert a DER encoded ECDSA signature to the raw `r || s` form, with
+/// values of the given size.
+fn der_to_raw(der: &[u8], size: usize) -> Option<Vec<u8>> {
+    fn length(bytes: &[u8]) -> Option<(usize, &[u8])> {
+        match bytes.split_first()? {
+            (&len, rest) if len < 0x80 => Some((len as usize, rest)),
+            (0x81, rest) => Some((*rest.first()? as usize, &rest[1..])),
+            _ => None,
+        }
+    }
+
+    let rest = der.strip_prefix(&[0x30])?;
+    let (len, mut rest) = length(rest)?;
+    if rest.len() != len {
+        return None;
+    }
+
+    let mut raw = Vec::with_capacity(2 * size);
+    for _ in 0..2 {
+        let (len, tail) = length(rest.strip_prefix(&[0x02])?)?;
+        if tail.len() < len {
+            return None;
+        }
+        let (value, tail) = tail.split_at(len);
+
+        let start = value.iter().position(|&b| b != 0).unwrap_or(value.len());
+        let value = &value[start..];
+        if value.len() > size {
+            return None;
+        }
+        raw.resize(raw.len() + size - value.len(), 0);
+        raw.extend_from_slice(value);
+        rest = tail;
+    }
+
+    rest.is_empty().then_some(raw)
+}
+
+/// Return the COSE algorithm identifier for a JWS algorithm name.
+pub(crate) fn cose_algorithm(algorithm: &str) -> Result<i128, DeviceSignerError> {
+    match algorithm {
+        "ES256" => Ok(-7),
+        "ES384" => Ok(-35),
+        "ES512" => Ok(-36),
+        "EdDSA" => Ok(-8),
+        _ => Err(DeviceSignerError::UnsupportedAlgorithm(algorithm.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+
+    #[test]
+    fn maps_the_advertised_algorithms_to_cose() {
+        // The wallet metadata advertises these algorithms for mso_mdoc too,
+        // whose device signatures are COSE_Sign1s.
+        let algorithms = crate::oid4vp::wallet_metadata::SUPPORTED_ALGORITHMS
+            .iter()
+            .map(|algorithm| cose_algorithm(algorithm).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(algorithms, [-7, -35, -36, -8]);
+        assert!(cose_algorithm("RS256").is_err());
+    }
+
+    #[test]
+    fn converts_der_signatures() {
+        let key = SigningKey::from_slice(&[1; 32]).unwrap();
+        let signature: Signature = key.sign(b"payload");
+        assert_eq!(
+            der_to_raw(signature.to_der().as_bytes(), 32),
+            Some(signature.to_bytes().to_vec())
+        );
+
+        // A P-521 signature, whose sequence has a long form length, with a
+        // short `r` and an `s` with a leading zero byte.
+        let mut der = vec![0x30, 0x81, 0x48, 0x02, 0x01, 0x07, 0x02, 0x43, 0x00];
+        der.extend([0xff; 66]);
+        let raw = der_to_raw(&der, 66).unwrap();
+        assert_eq!(raw.len(), 132);
+        assert_eq!(raw[65], 0x07);
+        assert!(raw[66..].iter().all(|&b| b == 0xff));
+
+        assert_eq!(der_to_raw(&der, 48), None);
+        assert_eq!(der_to_raw(&[0x30, 0x02, 0x02], 32), None);
+    }
+}
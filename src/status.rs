@@ -0,0 +1,961 @@
+//! Credential status checking.
+//!
+//! Two status mechanisms are supported:
+//!
+//! - IETF Token Status List, referenced by a `status.status_list` claim with
+//!   an `idx` and `uri`. The list is a JWT whose `status_list.lst` is a
+//!   zlib-compressed, base64url-encoded array of `bits`-sized entries.
+//! - W3C BitstringStatusList (and its predecessor StatusList2021), referenced
+//!   by `credentialStatus` entries with a `statusListIndex` and
+//!   `statusListCredential`. The list is a credential whose
+//!   `credentialSubject.encodedList` is a multibase, GZIP-compressed bitstring.
+//!
+//! Decoded status lists can be cached in a [StatusListCache], so that checks
+//! made within its maximum age do not fetch the lists again, which adds
+//! latency and reveals to issuers when credentials are used.
+//!
+//! Token Status Lists past their `exp` are refused, by the clock of the
+//! verification options and its leeway, and cached no longer than their `ttl`.
+//!
+//! Status lists are verified to be signed by the issuer of the credential,
+//! through the `x5c` certificate, a known issuer key or the DID of the list,
+//! as credentials are with [ParsedCredential::verify]. Token Status Lists
+//! may also be CWTs, signed with an `x5chain` certificate.
+
+use crate::clock;
+use crate::credential::json_vc::JsonVc;
+use crate::credential::verification::{self, CredentialVerificationOptions};
+use crate::credential::ParsedCredential;
+use crate::oid4vci::{HttpClientConfig, HttpRequest, HttpResponse, ReqwestHttpClient};
+use crate::oid4vp::request;
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use base64::prelude::*;
+use serde_cbor::Value as Cbor;
+use serde_json::Value as Json;
+use x509_cert::Certificate;
+
+/// The status of a credential, as reported by its issuer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum CredentialStatus {
+    /// The credential has not been revoked or suspended.
+    Valid,
+    /// The credential has been permanently revoked.
+    Revoked,
+    /// The credential has been temporarily suspended.
+    Suspended,
+    /// The credential does not reference a supported status list.
+    Unknown,
+}
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum StatusError {
+    #[error("Failed to fetch the status list: {0}")]
+    Fetch(String),
+    #[error("Failed to decode the status list: {0}")]
+    Decoding(String),
+    #[error("Invalid status reference: {0}")]
+    InvalidReference(String),
+    #[error("Status list index {0} is out of range")]
+    IndexOutOfRange(u64),
+    #[error("Failed to verify the status list: {0}")]
+    Verification(String),
+    #[error("The status list has expired")]
+    Expired,
+}
+
+/// A reference from a credential into a status list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum StatusReference {
+    TokenStatusList {
+        uri: String,
+        idx: u64,
+    },
+    BitstringStatusList {
+        uri: String,
+        idx: u64,
+        purpose: StatusPurpose,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StatusPurpose {
+    Revocation,
+    Suspension,
+}
+
+/// How status lists are fetched, and what their signatures are verified
+/// against.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StatusListFetcher {
+    /// The client of the holder, or a client with the default configuration
+    /// if unset.
+    pub(crate) client: Option<ReqwestHttpClient>,
+    /// The issuer keys and trust anchors of the signatures of status lists,
+    /// and the clock their expiry is checked against.
+    pub(crate) verification: CredentialVerificationOptions,
+}
+
+/// The default time a cached status list is used for.
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A decoded status list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StatusList {
+    /// The entries of an IETF Token Status List, of `bits` bits each.
+    Token { bits: u64, list: Vec<u8> },
+    /// The bits of a W3C BitstringStatusList.
+    Bitstring(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+struct CachedStatusList {
+    list: StatusList,
+    fetched_at: SystemTime,
+    /// The time the list expires, or its `ttl` runs out, if any.
+    refresh_at: Option<SystemTime>,
+}
+
+/// The status of a credential, along with the freshness of the status lists
+/// it was read from.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct StatusVerdict {
+    pub status: CredentialStatus,
+    /// The time the oldest of the status lists was fetched at, or `None` when
+    /// the credential references no status list.
+    pub fetched_at: Option<SystemTime>,
+    /// Whether any of the status lists was served from the cache.
+    pub from_cache: bool,
+}
+
+/// A cache of decoded status lists, keyed by URI.
+///
+/// Cached lists are used instead of fetching them while they are younger
+/// than the maximum age.
+#[derive(Debug, uniffi::Object)]
+pub struct StatusListCache {
+    entries: RwLock<HashMap<String, CachedStatusList>>,
+    max_age: RwLock<Duration>,
+}
+
+impl Default for StatusListCache {
+    fn default() -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            max_age: RwLock::new(DEFAULT_MAX_AGE),
+        }
+    }
+}
+
+#[uniffi::export]
+impl StatusListCache {
+    #[uniffi::constructor]
+    pub fn new(max_age: Duration) -> Arc<Self> {
+        let cache = Self::default();
+        cache.set_max_age(max_age);
+        Arc::new(cache)
+    }
+
+    /// Set the time a cached status list is used for.
+    pub fn set_max_age(&self, max_age: Duration) {
+        *self
+            .max_age
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = max_age;
+    }
+
+    /// Remove every cached status list.
+    pub fn clear(&self) {
+        self.entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+}
+
+impl StatusListCache {
+    fn store(&self, uri: &str, list: &StatusList, now: SystemTime, refresh_at: Option<SystemTime>) {
+        self.entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(
+                uri.to_string(),
+                CachedStatusList {
+                    list: list.clone(),
+                    fetched_at: now,
+                    refresh_at,
+                },
+            );
+    }
+
+    fn lookup(&self, uri: &str, now: SystemTime) -> Option<CachedStatusList> {
+        let max_age = *self
+            .max_age
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        self.entries
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(uri)
+            .filter(|cached| {
+                now.duration_since(cached.fetched_at)
+                    .is_ok_and(|age| age <= max_age)
+                    && cached.refresh_at.is_none_or(|refresh_at| now < refresh_at)
+            })
+            .cloned()
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl ParsedCredential {
+    /// Fetch the status lists referenced by the credential, and return its
+    /// current status.
+    ///
+    /// When a credential references several status lists, revocation takes
+    /// precedence over suspension.
+    ///
+    /// The status lists must be signed with the DID of the issuer, as the
+    /// lists signed with certificates are only trusted by
+    /// [ParsedCredential::verify], against its trust anchors.
+    pub async fn check_status(&self) -> Result<CredentialStatus, StatusError> {
+        Ok(self
+            .status_verdict(&StatusListFetcher::default(), None, false)
+            .await?
+            .status)
+    }
+
+    /// As [ParsedCredential::check_status], using the lists of the cache that
+    /// are younger than its maximum age, unless `force_refresh` is set, and
+    /// caching the fetched lists.
+    pub async fn check_status_cached(
+        &self,
+        cache: Arc<StatusListCache>,
+        force_refresh: bool,
+    ) -> Result<StatusVerdict, StatusError> {
+        self.status_verdict(&StatusListFetcher::default(), Some(&cache), force_refresh)
+            .await
+    }
+}
+
+impl ParsedCredential {
+    pub(crate) async fn status_verdict(
+        &self,
+        fetcher: &StatusListFetcher,
+        cache: Option<&StatusListCache>,
+        force_refresh: bool,
+    ) -> Result<StatusVerdict, StatusError> {
+        let claims = self.claims_as_json().unwrap_or_default();
+        let references = status_references(&claims)?;
+
+        let mut verdict = StatusVerdict {
+            status: CredentialStatus::Unknown,
+            fetched_at: None,
+            from_cache: false,
+        };
+        if references.is_empty() {
+            return Ok(verdict);
+        }
+
+        let client = match &fetcher.client {
+            Some(client) => client.clone(),
+            None => ReqwestHttpClient::new(&HttpClientConfig::default())
+                .map_err(|e| StatusError::Fetch(format!("{e:?}")))?,
+        };
+        let trust_anchors = verification::trust_anchors(&fetcher.verification)
+            .map_err(StatusError::Verification)?;
+        let issuer = issuer(&claims);
+        let options = &fetcher.verification;
+        let now = options.clock.clone().unwrap_or_else(clock::system).now();
+        verdict.status = CredentialStatus::Valid;
+
+        for reference in references {
+            let cached = cache
+                .filter(|_| !force_refresh)
+                .and_then(|cache| cache.lookup(reference.uri(), now));
+            let (list, fetched_at) = match cached {
+                Some(cached) => {
+                    verdict.from_cache = true;
+                    (cached.list, cached.fetched_at)
+                }
+                None => {
+                    let (list, refresh_at) = reference
+                        .fetch(&client, options, &trust_anchors, issuer, now)
+                        .await?;
+                    if let Some(cache) = cache {
+                        cache.store(reference.uri(), &list, now, refresh_at);
+                    }
+                    (list, now)
+                }
+            };
+            verdict.fetched_at = Some(
+                verdict
+                    .fetched_at
+                    .map_or(fetched_at, |oldest| oldest.min(fetched_at)),
+            );
+
+            match reference.status(&list)? {
+                CredentialStatus::Revoked => {
+                    verdict.status = CredentialStatus::Revoked;
+                    return Ok(verdict);
+                }
+                CredentialStatus::Suspended => verdict.status = CredentialStatus::Suspended,
+                CredentialStatus::Valid | CredentialStatus::Unknown => {}
+            }
+        }
+
+        Ok(verdict)
+    }
+}
+
+/// Check the status of a credential, returning `None` if it could not be
+/// determined, for example when the device is offline.
+pub(crate) async fn try_check_status(
+    credential: &Arc<ParsedCredential>,
+    fetcher: &StatusListFetcher,
+    cache: Option<&StatusListCache>,
+) -> Option<StatusVerdict> {
+    credential
+        .status_verdict(fetcher, cache, false)
+        .await
+        .inspect_err(|e| tracing::warn!("failed to check credential status: {e}"))
+        .ok()
+}
+
+impl StatusReference {
+    fn uri(&self) -> &str {
+        match self {
+            StatusReference::TokenStatusList { uri, .. }
+            | StatusReference::BitstringStatusList { uri, .. } => uri,
+        }
+    }
+
+    /// Fetch the status list of the reference, verify that it is signed by
+    /// the issuer of the credential, if known, and has not expired at `now`,
+    /// and decode it, along with the time it must be fetched again at.
+    async fn fetch(
+        &self,
+        client: &ReqwestHttpClient,
+        options: &CredentialVerificationOptions,
+        trust_anchors: &[Certificate],
+        issuer: Option<&str>,
+        now: SystemTime,
+    ) -> Result<(StatusList, Option<SystemTime>), StatusError> {
+        match self {
+            StatusReference::TokenStatusList { uri, .. } => {
+                let accept = "application/statuslist+jwt, application/statuslist+cwt";
+                let response = fetch(client, uri, accept).await?;
+                let is_cwt = response
+                    .headers
+                    .get("content-type")
+                    .is_some_and(|content_type| content_type.contains("statuslist+cwt"));
+                if is_cwt {
+                    return decode_cwt_status_list(
+                        &response.body,
+                        uri,
+                        trust_anchors,
+                        issuer,
+                        now,
+                        options.clock_leeway,
+                    );
+                }
+
+                let claims = verify_jwt(&text(response)?, options, trust_anchors).await?;
+                check_issuer(issuer, self::issuer(&claims))?;
+                if claims["sub"].as_str() != Some(uri) {
+                    return Err(StatusError::Verification(format!(
+                        "the status list is not the one of {uri}"
+                    )));
+                }
+                let refresh_at = token_validity(
+                    claims["exp"].as_u64(),
+                    claims["ttl"].as_u64(),
+                    now,
+                    options.clock_leeway,
+                )?;
+                Ok((
+                    decode_token_status_list(&claims["status_list"])?,
+                    refresh_at,
+                ))
+            }
+            StatusReference::BitstringStatusList { uri, .. } => {
+                let accept = "application/vc+ld+json, application/vc+jwt";
+                let body = text(fetch(client, uri, accept).await?)?;
+                let credential = match serde_json::from_str::<Json>(&body) {
+                    Ok(credential) => {
+                        JsonVc::new_from_json(body)
+                            .map_err(|e| StatusError::Decoding(format!("{e:?}")))?
+                            .verify(None)
+                            .await
+                            .map_err(|e| StatusError::Verification(format!("{e:?}")))?;
+                        credential
+                    }
+                    Err(_) => {
+                        let claims = verify_jwt(&body, options, trust_anchors).await?;
+                        match claims.get("vc") {
+                            Some(vc) => vc.clone(),
+                            None => claims,
+                        }
+                    }
+                };
+                check_issuer(issuer, self::issuer(&credential))?;
+
+                let encoded = credential["credentialSubject"]["encodedList"]
+                    .as_str()
+                    .ok_or_else(|| StatusError::Decoding("missing encodedList".into()))?;
+                Ok((StatusList::Bitstring(decode_bitstring(encoded)?), None))
+            }
+        }
+    }
+
+    /// Read the status of the reference from its decoded status list.
+    fn status(&self, list: &StatusList) -> Result<CredentialStatus, StatusError> {
+        match (self, list) {
+            (StatusReference::TokenStatusList { idx, .. }, StatusList::Token { bits, list }) => {
+                Ok(match token_entry(list, *bits, *idx)? {
+                    0x00 => CredentialStatus::Valid,
+                    0x01 => CredentialStatus::Revoked,
+                    0x02 => CredentialStatus::Suspended,
+                    _ => CredentialStatus::Unknown,
+                })
+            }
+            (
+                StatusReference::BitstringStatusList { idx, purpose, .. },
+                StatusList::Bitstring(list),
+            ) => match (bit(list, *idx)?, purpose) {
+                (false, _) => Ok(CredentialStatus::Valid),
+                (true, StatusPurpose::Revocation) => Ok(CredentialStatus::Revoked),
+                (true, StatusPurpose::Suspension) => Ok(CredentialStatus::Suspended),
+            },
+            _ => Err(StatusError::Decoding(
+                "the status list is not of the referenced type".into(),
+            )),
+        }
+    }
+}
+
+async fn fetch(
+    client: &ReqwestHttpClient,
+    uri: &str,
+    accept: &str,
+) -> Result<HttpResponse, StatusError> {
+    let response = client
+        .send(HttpRequest {
+            url: uri.to_owned(),
+            method: "GET".into(),
+            headers: [("Accept".into(), accept.into())].into(),
+            body: vec![],
+        })
+        .await
+        .map_err(|e| StatusError::Fetch(format!("{e:?}")))?;
+
+    if !(200..300).contains(&response.status_code) {
+        return Err(StatusError::Fetch(format!(
+            "unexpected status code {}",
+            response.status_code
+        )));
+    }
+    Ok(response)
+}
+
+fn text(response: HttpResponse) -> Result<String, StatusError> {
+    String::from_utf8(response.body).map_err(|e| StatusError::Decoding(format!("{e:?}")))
+}
+
+/// Return the issuer of a credential or status list, from its claims.
+fn issuer(claims: &Json) -> Option<&str> {
+    [claims, &claims["vc"]].into_iter().find_map(|claims| {
+        claims["iss"]
+            .as_str()
+            .or_else(|| claims["issuer"].as_str())
+            .or_else(|| claims["issuer"]["id"].as_str())
+    })
+}
+
+/// Check that a status list is issued by the issuer of the credential.
+fn check_issuer(expected: Option<&str>, issuer: Option<&str>) -> Result<(), StatusError> {
+    match expected {
+        Some(expected) if issuer != Some(expected) => Err(StatusError::Verification(format!(
+            "the status list is not issued by {expected}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Verify the signature of a status list JWT, and return its claims.
+async fn verify_jwt(
+    jwt: &str,
+    options: &CredentialVerificationOptions,
+    trust_anchors: &[Certificate],
+) -> Result<Json, StatusError> {
+    let jwt = jwt.trim();
+    verification::verify_jws(jwt, options, trust_anchors)
+        .await
+        .map_err(StatusError::Verification)?;
+    let claims = decode_jwt_payload(jwt)?;
+
+    // The key of a DID must be one of the issuer, not of any DID.
+    let header = request::jwt_part(jwt, 0).unwrap_or_default();
+    if let Some(kid) = header["kid"].as_str().filter(|kid| kid.starts_with("did:")) {
+        let did = kid.split('#').next().unwrap_or_default();
+        if issuer(&claims) != Some(did) {
+            return Err(StatusError::Verification(format!(
+                "the status list is signed by {did}, not its issuer"
+            )));
+        }
+    }
+
+    Ok(claims)
+}
+
+/// Collect the status references of a credential from its claims.
+pub(crate) fn status_references(claims: &Json) -> Result<Vec<StatusReference>, StatusError> {
+    let mut references = vec![];
+
+    // VC-JWTs (VCDM 1.1) nest the credential under the `vc` claim.
+    for claims in [claims, &claims["vc"]] {
+        if let Some(status_list) = claims["status"].get("status_list") {
+            let uri = status_list["uri"]
+                .as_str()
+                .ok_or_else(|| StatusError::InvalidReference("missing uri".into()))?;
+            let idx = status_list["idx"]
+                .as_u64()
+                .ok_or_else(|| StatusError::InvalidReference("missing idx".into()))?;
+
+            references.push(StatusReference::TokenStatusList {
+                uri: uri.to_owned(),
+                idx,
+            });
+        }
+
+        let entries = match &claims["credentialStatus"] {
+            Json::Array(entries) => entries.iter().collect(),
+            entry @ Json::Object(_) => vec![entry],
+            _ => vec![],
+        };
+
+        for entry in entries {
+            match entry["type"].as_str() {
+                Some("BitstringStatusListEntry" | "StatusList2021Entry") => {}
+                _ => continue,
+            }
+
+            let purpose = match entry["statusPurpose"].as_str() {
+                Some("revocation") => StatusPurpose::Revocation,
+                Some("suspension") => StatusPurpose::Suspension,
+                // Other purposes, such as `message`, do not affect validity.
+                _ => continue,
+            };
+            let uri = entry["statusListCredential"].as_str().ok_or_else(|| {
+                StatusError::InvalidReference("missing statusListCredential".into())
+            })?;
+            let idx = entry["statusListIndex"]
+                .as_str()
+                .and_then(|idx| idx.parse().ok())
+                .or_else(|| entry["statusListIndex"].as_u64())
+                .ok_or_else(|| StatusError::InvalidReference("invalid statusListIndex".into()))?;
+
+            references.push(StatusReference::BitstringStatusList {
+                uri: uri.to_owned(),
+                idx,
+                purpose,
+            });
+        }
+    }
+
+    Ok(references)
+}
+
+fn decode_jwt_payload(jwt: &str) -> Result<Json, StatusError> {
+    request::jwt_part(jwt.trim(), 1)
+        .ok_or_else(|| StatusError::Decoding("malformed status list token".into()))
+}
+
+/// Decode a Token Status List `status_list` claim.
+fn decode_token_status_list(status_list: &Json) -> Result<StatusList, StatusError> {
+    let bits = match status_list["bits"].as_u64() {
+        Some(bits @ (1 | 2 | 4 | 8)) => bits,
+        _ => return Err(StatusError::Decoding("invalid bits".into())),
+    };
+    let lst = status_list["lst"]
+        .as_str()
+        .ok_or_else(|| StatusError::Decoding("missing lst".into()))?;
+
+    let compressed = BASE64_URL_SAFE_NO_PAD
+        .decode(lst)
+        .map_err(|e| StatusError::Decoding(format!("{e:?}")))?;
+    let list = miniz_oxide::inflate::decompress_to_vec_zlib(&compressed)
+        .map_err(|e| StatusError::Decoding(format!("{e:?}")))?;
+
+    Ok(StatusList::Token { bits, list })
+}
+
+/// Verify a Token Status List CWT, and decode it, along with the time it must
+/// be fetched again at.
+fn decode_cwt_status_list(
+    cwt: &[u8],
+    uri: &str,
+    trust_anchors: &[Certificate],
+    issuer: Option<&str>,
+    now: SystemTime,
+    leeway: Duration,
+) -> Result<(StatusList, Option<SystemTime>), StatusError> {
+    const ISS: Cbor = Cbor::Integer(1);
+    const SUB: Cbor = Cbor::Integer(2);
+    const EXP: Cbor = Cbor::Integer(4);
+    const STATUS_LIST: Cbor = Cbor::Integer(65533);
+    const TTL: Cbor = Cbor::Integer(65534);
+    const BITS: Cbor = Cbor::Integer(1);
+    const LST: Cbor = Cbor::Integer(2);
+
+    let cose_sign1 =
+        serde_cbor::from_slice(cwt).map_err(|e| StatusError::Decoding(format!("{e:?}")))?;
+    let payload = verification::verify_cose_sign1(cose_sign1, trust_anchors)
+        .map_err(StatusError::Verification)?;
+    let Cbor::Map(claims) =
+        serde_cbor::from_slice(&payload).map_err(|e| StatusError::Decoding(format!("{e:?}")))?
+    else {
+        return Err(StatusError::Decoding("malformed status list token".into()));
+    };
+
+    let claim = |key: &Cbor| match claims.get(key) {
+        Some(Cbor::Text(value)) => Some(value.as_str()),
+        _ => None,
+    };
+    check_issuer(issuer, claim(&ISS))?;
+    if claim(&SUB) != Some(uri) {
+        return Err(StatusError::Verification(format!(
+            "the status list is not the one of {uri}"
+        )));
+    }
+    let seconds = |key: &Cbor| match claims.get(key) {
+        Some(Cbor::Integer(value)) => u64::try_from(*value).ok(),
+        _ => None,
+    };
+    let refresh_at = token_validity(seconds(&EXP), seconds(&TTL), now, leeway)?;
+
+    let Some(Cbor::Map(status_list)) = claims.get(&STATUS_LIST) else {
+        return Err(StatusError::Decoding("missing status_list".into()));
+    };
+    let bits = match status_list.get(&BITS) {
+        Some(Cbor::Integer(bits @ (1 | 2 | 4 | 8))) => *bits as u64,
+        _ => return Err(StatusError::Decoding("invalid bits".into())),
+    };
+    let Some(Cbor::Bytes(lst)) = status_list.get(&LST) else {
+        return Err(StatusError::Decoding("missing lst".into()));
+    };
+    let list = miniz_oxide::inflate::decompress_to_vec_zlib(lst)
+        .map_err(|e| StatusError::Decoding(format!("{e:?}")))?;
+
+    Ok((StatusList::Token { bits, list }, refresh_at))
+}
+
+/// Fail when a Token Status List has passed its `exp` at `now`, allowing for
+/// the leeway, and return the time it must be fetched again at, by its `exp`
+/// and its `ttl` from `now`.
+fn token_validity(
+    exp: Option<u64>,
+    ttl: Option<u64>,
+    now: SystemTime,
+    leeway: Duration,
+) -> Result<Option<SystemTime>, StatusError> {
+    // Expiries past the representable times never expire.
+    let expires_at =
+        exp.and_then(|exp| SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(exp)));
+    if expires_at.is_some_and(|expires_at| clock::has_passed(expires_at, now, leeway)) {
+        return Err(StatusError::Expired);
+    }
+
+    let stale_at = ttl.and_then(|ttl| now.checked_add(Duration::from_secs(ttl)));
+    Ok(match (expires_at, stale_at) {
+        (Some(expires_at), Some(stale_at)) => Some(expires_at.min(stale_at)),
+        (expires_at, stale_at) => expires_at.or(stale_at),
+    })
+}
+
+/// Read the entry at `idx` of a Token Status List of `bits`-sized entries.
+fn token_entry(list: &[u8], bits: u64, idx: u64) -> Result<u8, StatusError> {
+    // Entries are packed starting from the least significant bit of each byte.
+    let position = idx * bits;
+    let byte = list
+        .get((position / 8) as usize)
+        .ok_or(StatusError::IndexOutOfRange(idx))?;
+    let mask = ((1u16 << bits) - 1) as u8;
+
+    Ok((byte >> (position % 8)) & mask)
+}
+
+/// Decode a BitstringStatusList `encodedList`.
+fn decode_bitstring(encoded_list: &str) -> Result<Vec<u8>, StatusError> {
+    // The list is multibase encoded with the base64url (`u`) prefix.
+    let encoded = encoded_list.strip_prefix('u').unwrap_or(encoded_list);
+    let compressed = BASE64_URL_SAFE_NO_PAD
+        .decode(encoded.trim_end_matches('='))
+        .map_err(|e| StatusError::Decoding(format!("{e:?}")))?;
+    gunzip(&compressed)
+}
+
+/// Read the bit at `idx` of a decoded bitstring.
+fn bit(list: &[u8], idx: u64) -> Result<bool, StatusError> {
+    // The first index is the most significant bit of the first byte.
+    let byte = list
+        .get((idx / 8) as usize)
+        .ok_or(StatusError::IndexOutOfRange(idx))?;
+
+    Ok(byte & (0x80 >> (idx % 8)) != 0)
+}
+
+/// Decompress a GZIP member (RFC 1952).
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, StatusError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    let invalid = || StatusError::Decoding("invalid gzip header".into());
+
+    if bytes.len() < 18 || bytes[0..3] != [0x1f, 0x8b, 0x08] {
+        return Err(invalid());
+    }
+
+    let flags = bytes[3];
+    let mut pos = 10;
+
+    if flags & FEXTRA != 0 {
+        let len = bytes.get(pos..pos + 2).ok_or_else(invalid)?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = bytes
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|b| *b == 0))
+                .ok_or_else(invalid)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    let deflated = bytes.get(pos..bytes.len() - 8).ok_or_else(invalid)?;
+    miniz_oxide::inflate::decompress_to_vec(deflated)
+        .map_err(|e| StatusError::Decoding(format!("{e:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_status_list_value(status_list: &Json, idx: u64) -> Result<u8, StatusError> {
+        match decode_token_status_list(status_list)? {
+            StatusList::Token { bits, list } => token_entry(&list, bits, idx),
+            StatusList::Bitstring(_) => unreachable!(),
+        }
+    }
+
+    fn bitstring_value(encoded_list: &str, idx: u64) -> Result<bool, StatusError> {
+        bit(&decode_bitstring(encoded_list)?, idx)
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0xff];
+        out.extend(miniz_oxide::deflate::compress_to_vec(data, 6));
+        // The CRC is not checked when decoding.
+        out.extend([0; 4]);
+        out.extend((data.len() as u32).to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn reads_token_status_list() {
+        // Example from draft-ietf-oauth-status-list, with 2-bit entries.
+        let list = [0xC9, 0x44, 0xF9];
+        let status_list = serde_json::json!({
+            "bits": 2,
+            "lst": BASE64_URL_SAFE_NO_PAD.encode(miniz_oxide::deflate::compress_to_vec_zlib(&list, 6)),
+        });
+
+        let values = (0..12)
+            .map(|idx| token_status_list_value(&status_list, idx).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, [1, 2, 0, 3, 0, 1, 0, 1, 1, 2, 3, 3]);
+
+        assert!(matches!(
+            token_status_list_value(&status_list, 12),
+            Err(StatusError::IndexOutOfRange(12))
+        ));
+    }
+
+    #[tokio::test]
+    async fn verifies_status_list_tokens() {
+        use crate::oid4vp::key_binding::tests::TestSigner;
+        use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+
+        let signer = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let encode = |value: Json| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let signing_input = format!(
+            "{}.{}",
+            encode(serde_json::json!({ "alg": "ES256", "typ": "statuslist+jwt" })),
+            encode(serde_json::json!({
+                "iss": "did:example:issuer",
+                "sub": "https://example.com/statuslists/1",
+                "status_list": { "bits": 1, "lst": "eNrbuRgAAhcBXQ" },
+            })),
+        );
+        let signature: Signature = signer.0.sign(signing_input.as_bytes());
+        let options = CredentialVerificationOptions {
+            issuer_keys: [("did:example:issuer".into(), signer.jwk().to_string())].into(),
+            ..Default::default()
+        };
+
+        let jwt = format!(
+            "{signing_input}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        );
+        let claims = verify_jwt(&jwt, &options, &[]).await.unwrap();
+        assert!(check_issuer(Some("did:example:issuer"), issuer(&claims)).is_ok());
+        assert!(matches!(
+            check_issuer(Some("did:example:other"), issuer(&claims)),
+            Err(StatusError::Verification(..))
+        ));
+
+        let tampered = format!("{signing_input}.{}", BASE64_URL_SAFE_NO_PAD.encode([0; 64]));
+        assert!(matches!(
+            verify_jwt(&tampered, &options, &[]).await,
+            Err(StatusError::Verification(..))
+        ));
+    }
+
+    #[test]
+    fn reads_bitstring_status_list() {
+        let encoded = format!(
+            "u{}",
+            BASE64_URL_SAFE_NO_PAD.encode(gzip(&[0b0100_0000, 0x01]))
+        );
+
+        assert!(!bitstring_value(&encoded, 0).unwrap());
+        assert!(bitstring_value(&encoded, 1).unwrap());
+        assert!(bitstring_value(&encoded, 15).unwrap());
+        assert!(bitstring_value(&encoded, 16).is_err());
+    }
+
+    #[test]
+    fn collects_status_references() {
+        let claims = serde_json::json!({
+            "status": {
+                "status_list": { "idx": 3, "uri": "https://example.com/statuslists/1" }
+            },
+            "credentialStatus": [
+                {
+                    "type": "BitstringStatusListEntry",
+                    "statusPurpose": "revocation",
+                    "statusListIndex": "94567",
+                    "statusListCredential": "https://example.com/credentials/status/3"
+                },
+                {
+                    "type": "BitstringStatusListEntry",
+                    "statusPurpose": "message",
+                    "statusListIndex": "1",
+                    "statusListCredential": "https://example.com/credentials/status/4"
+                }
+            ]
+        });
+
+        assert_eq!(
+            status_references(&claims).unwrap(),
+            vec![
+                StatusReference::TokenStatusList {
+                    uri: "https://example.com/statuslists/1".into(),
+                    idx: 3,
+                },
+                StatusReference::BitstringStatusList {
+                    uri: "https://example.com/credentials/status/3".into(),
+                    idx: 94567,
+                    purpose: StatusPurpose::Revocation,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn caches_status_lists() {
+        let cache = StatusListCache::new(Duration::from_secs(60));
+        let uri = "https://example.com/statuslists/1";
+        let list = StatusList::Bitstring(vec![0b0100_0000]);
+        let fetched_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_750_000_000);
+        cache.store(uri, &list, fetched_at, None);
+
+        let cached = cache
+            .lookup(uri, fetched_at + Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(cached.list, list);
+        assert_eq!(cached.fetched_at, fetched_at);
+        assert!(cache
+            .lookup(uri, fetched_at + Duration::from_secs(61))
+            .is_none());
+        assert!(cache
+            .lookup("https://example.com/statuslists/2", fetched_at)
+            .is_none());
+
+        let reference = StatusReference::BitstringStatusList {
+            uri: uri.into(),
+            idx: 1,
+            purpose: StatusPurpose::Suspension,
+        };
+        assert_eq!(
+            reference.status(&cached.list).unwrap(),
+            CredentialStatus::Suspended
+        );
+        let reference = StatusReference::TokenStatusList {
+            uri: uri.into(),
+            idx: 1,
+        };
+        assert!(reference.status(&cached.list).is_err());
+
+        cache.set_max_age(Duration::from_secs(120));
+        assert!(cache
+            .lookup(uri, fetched_at + Duration::from_secs(61))
+            .is_some());
+        cache.clear();
+        assert!(cache.lookup(uri, fetched_at).is_none());
+
+        // Lists are fetched again once their `ttl` runs out.
+        cache.store(
+            uri,
+            &list,
+            fetched_at,
+            Some(fetched_at + Duration::from_secs(10)),
+        );
+        assert!(cache
+            .lookup(uri, fetched_at + Duration::from_secs(5))
+            .is_some());
+        assert!(cache
+            .lookup(uri, fetched_at + Duration::from_secs(10))
+            .is_none());
+    }
+
+    #[test]
+    fn refuses_expired_status_lists() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_750_000_000);
+        let leeway = Duration::from_secs(30);
+
+        assert_eq!(token_validity(None, None, now, leeway).unwrap(), None);
+        assert_eq!(
+            token_validity(Some(1_750_000_100), Some(60), now, leeway).unwrap(),
+            Some(now + Duration::from_secs(60))
+        );
+        // Skewed clocks are tolerated within the leeway.
+        assert_eq!(
+            token_validity(Some(1_749_999_990), None, now, leeway).unwrap(),
+            Some(now - Duration::from_secs(10))
+        );
+        assert!(matches!(
+            token_validity(Some(1_749_999_960), Some(60), now, leeway),
+            Err(StatusError::Expired)
+        ));
+    }
+}
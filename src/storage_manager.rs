@@ -29,6 +29,10 @@ pub enum StorageManagerError {
     /// An internal problem occurred in the storage manager.
     #[error("Internal Error")]
     InternalError,
+
+    /// The key needed to encrypt or decrypt a value could not be obtained.
+    #[error("Storage encryption key unavailable: {0}")]
+    KeyUnavailable(String),
 }
 
 /// Interface: StorageManagerInterface
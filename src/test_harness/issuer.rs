@@ -0,0 +1,287 @@
+//! A mock OID4VCI issuer, issuing a fixed credential through the
+//! pre-authorized code flow.
+
+use super::server::{HttpRequest, HttpResponse, HttpServer};
+use super::TestHarnessError;
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value as Json};
+use url::form_urlencoded;
+use uuid::Uuid;
+
+const PRE_AUTHORIZED_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:pre-authorized_code";
+const TOKEN_PATH: &str = "/token";
+const CREDENTIAL_PATH: &str = "/credential";
+const NONCE_PATH: &str = "/nonce";
+
+/// The credential a [MockIssuer] issues.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct MockCredential {
+    /// The ID of the credential configuration in the issuer metadata.
+    pub configuration_id: String,
+    /// The credential configuration, as JSON, e.g.
+    /// `{"format": "jwt_vc_json", "credential_definition": {...}}`.
+    pub configuration: String,
+    /// The credential, as issued in credential responses, e.g. a compact JWT.
+    pub credential: String,
+}
+
+#[derive(Debug)]
+struct IssuerState {
+    base_url: String,
+    configuration_id: String,
+    configuration: Json,
+    credential: String,
+    pre_authorized_code: String,
+    /// The access tokens issued so far.
+    access_tokens: Vec<String>,
+    issued: u32,
+}
+
+/// A mock issuer, offering a single credential with a pre-authorized code.
+///
+/// NOTE: the proofs of possession of credential requests are not verified.
+#[derive(Debug, uniffi::Object)]
+pub struct MockIssuer {
+    state: Arc<Mutex<Option<IssuerState>>>,
+    server: HttpServer,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl MockIssuer {
+    /// Start an issuer of the given credential on a free port of the loopback
+    /// interface.
+    #[uniffi::constructor]
+    pub async fn start(credential: MockCredential) -> Result<Arc<Self>, TestHarnessError> {
+        let configuration = serde_json::from_str(&credential.configuration)
+            .map_err(|e| TestHarnessError::Server(format!("{e:?}")))?;
+
+        let state = Arc::new(Mutex::new(None::<IssuerState>));
+        let server = {
+            let state = state.clone();
+            HttpServer::start(move |request| {
+                let response = handle(&state, request);
+                async move { response }
+            })
+            .await?
+        };
+
+        *lock(&state) = Some(IssuerState {
+            base_url: server.base_url.clone(),
+            configuration_id: credential.configuration_id,
+            configuration,
+            credential: credential.credential,
+            pre_authorized_code: Uuid::new_v4().to_string(),
+            access_tokens: vec![],
+            issued: 0,
+        });
+        Ok(Arc::new(Self { state, server }))
+    }
+
+    /// Return the identifier of the issuer, which is its base URL.
+    pub fn credential_issuer(&self) -> String {
+        self.server.base_url.clone()
+    }
+
+    /// Return a credential offer URL passing the offer by value, with the
+    /// pre-authorized code of the issuer.
+    pub fn credential_offer(&self) -> String {
+        let offer = lock(&self.state).as_ref().map(|state| {
+            json!({
+                "credential_issuer": state.base_url,
+                "credential_configuration_ids": [state.configuration_id],
+                "grants": {
+                    PRE_AUTHORIZED_CODE_GRANT: { "pre-authorized_code": state.pre_authorized_code },
+                },
+            })
+        });
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("credential_offer", &offer.unwrap_or_default().to_string())
+            .finish();
+        format!("openid-credential-offer://?{query}")
+    }
+
+    /// Return the number of credentials issued so far.
+    pub fn issued_credentials(&self) -> u32 {
+        lock(&self.state).as_ref().map_or(0, |state| state.issued)
+    }
+}
+
+fn lock(state: &Mutex<Option<IssuerState>>) -> std::sync::MutexGuard<'_, Option<IssuerState>> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Handle a request to the issuer.
+fn handle(state: &Mutex<Option<IssuerState>>, request: HttpRequest) -> HttpResponse {
+    let mut state = lock(state);
+    let Some(state) = state.as_mut() else {
+        return HttpResponse::not_found();
+    };
+    let base_url = state.base_url.clone();
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/.well-known/openid-credential-issuer") => HttpResponse::json(
+            200,
+            &json!({
+                "credential_issuer": base_url,
+                "credential_endpoint": format!("{base_url}{CREDENTIAL_PATH}"),
+                "nonce_endpoint": format!("{base_url}{NONCE_PATH}"),
+                "credential_configurations_supported": {
+                    state.configuration_id.clone(): state.configuration,
+                },
+            }),
+        ),
+        (
+            "GET",
+            "/.well-known/oauth-authorization-server" | "/.well-known/openid-configuration",
+        ) => HttpResponse::json(
+            200,
+            &json!({
+                "issuer": base_url,
+                "token_endpoint": format!("{base_url}{TOKEN_PATH}"),
+                "grant_types_supported": [PRE_AUTHORIZED_CODE_GRANT],
+                "pre-authorized_grant_anonymous_access_supported": true,
+                "response_types_supported": ["code"],
+            }),
+        ),
+        ("POST", TOKEN_PATH) => {
+            let form = form_urlencoded::parse(&request.body).collect::<Vec<_>>();
+            let parameter = |name: &str| {
+                form.iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.to_string())
+            };
+            if parameter("grant_type").as_deref() != Some(PRE_AUTHORIZED_CODE_GRANT)
+                || parameter("pre-authorized_code").as_ref() != Some(&state.pre_authorized_code)
+            {
+                return HttpResponse::json(400, &json!({ "error": "invalid_grant" }));
+            }
+
+            let access_token = Uuid::new_v4().to_string();
+            state.access_tokens.push(access_token.clone());
+            HttpResponse::json(
+                200,
+                &json!({
+                    "access_token": access_token,
+                    "token_type": "Bearer",
+                    "expires_in": 3600,
+                    "c_nonce": Uuid::new_v4().to_string(),
+                    "c_nonce_expires_in": 3600,
+                }),
+            )
+        }
+        ("POST", NONCE_PATH) => {
+            HttpResponse::json(200, &json!({ "c_nonce": Uuid::new_v4().to_string() }))
+        }
+        ("POST", CREDENTIAL_PATH) => {
+            let authorized = request
+                .headers
+                .get("authorization")
+                .and_then(|authorization| authorization.split_once(' '))
+                .is_some_and(|(_, token)| state.access_tokens.iter().any(|issued| issued == token));
+            if !authorized {
+                return HttpResponse::json(401, &json!({ "error": "invalid_token" }));
+            }
+
+            state.issued += 1;
+            // Both the single credential of draft 13 and the credentials of
+            // later drafts are returned.
+            HttpResponse::json(
+                200,
+                &json!({
+                    "credential": state.credential,
+                    "credentials": [{ "credential": state.credential }],
+                    "c_nonce": Uuid::new_v4().to_string(),
+                    "c_nonce_expires_in": 3600,
+                }),
+            )
+        }
+        _ => HttpResponse::not_found(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn issues_credentials() {
+        let issuer = MockIssuer::start(MockCredential {
+            configuration_id: "UniversityDegree".into(),
+            configuration: json!({
+                "format": "jwt_vc_json",
+                "credential_definition": {
+                    "type": ["VerifiableCredential", "UniversityDegreeCredential"]
+                },
+            })
+            .to_string(),
+            credential: "eyJhbGciOiJFUzI1NiJ9.e30.c2lnbmF0dXJl".into(),
+        })
+        .await
+        .unwrap();
+        let base_url = issuer.credential_issuer();
+        let client = reqwest::Client::new();
+
+        let metadata: Json = client
+            .get(format!("{base_url}/.well-known/openid-credential-issuer"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            metadata["credential_configurations_supported"]["UniversityDegree"]["format"],
+            "jwt_vc_json"
+        );
+
+        let offer = url::Url::parse(&issuer.credential_offer()).unwrap();
+        let offer: Json = offer
+            .query_pairs()
+            .find(|(name, _)| name == "credential_offer")
+            .map(|(_, offer)| serde_json::from_str(&offer).unwrap())
+            .unwrap();
+        let code = offer["grants"][PRE_AUTHORIZED_CODE_GRANT]["pre-authorized_code"]
+            .as_str()
+            .unwrap();
+
+        let token: Json = client
+            .post(format!("{base_url}{TOKEN_PATH}"))
+            .form(&[
+                ("grant_type", PRE_AUTHORIZED_CODE_GRANT),
+                ("pre-authorized_code", code),
+            ])
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let access_token = token["access_token"].as_str().unwrap();
+
+        let unauthorized = client
+            .post(format!("{base_url}{CREDENTIAL_PATH}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(unauthorized.status(), 401);
+        let response: Json = client
+            .post(format!("{base_url}{CREDENTIAL_PATH}"))
+            .bearer_auth(access_token)
+            .json(&json!({ "format": "jwt_vc_json" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            response["credential"],
+            "eyJhbGciOiJFUzI1NiJ9.e30.c2lnbmF0dXJl"
+        );
+        assert_eq!(issuer.issued_credentials(), 1);
+    }
+}
@@ -0,0 +1,22 @@
+//! In-process mock verifiers and issuers, to run OID4VP and OID4VCI flows end
+//! to end without external services, e.g. in CI.
+//!
+//! The mocks serve plain HTTP on the loopback interface, on a free port, until
+//! they are dropped. Enabled by the `test-harness` feature.
+//!
+//! NOTE: the mocks are not secure, and must not be used outside of tests.
+
+mod issuer;
+mod server;
+mod verifier;
+
+pub use issuer::*;
+pub use verifier::*;
+
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum TestHarnessError {
+    #[error("Failed to run the mock server: {0}")]
+    Server(String),
+    #[error("Mock verifier error: {0}")]
+    Verifier(String),
+}
@@ -0,0 +1,147 @@
+//! A minimal HTTP/1.1 server on the loopback interface, serving one request
+//! per connection.
+
+use super::TestHarnessError;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use uniffi::deps::log;
+
+/// A request received by the server.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpRequest {
+    pub method: String,
+    /// The path of the request, without its query.
+    pub path: String,
+    /// The headers of the request, by lowercase name.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// The response to a request.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::json(404, &serde_json::json!({ "error": "not_found" }))
+    }
+}
+
+type Handler = Arc<dyn Fn(HttpRequest) -> BoxFuture<'static, HttpResponse> + Send + Sync>;
+
+/// A server, stopped when dropped.
+#[derive(Debug)]
+pub(crate) struct HttpServer {
+    /// The base URL of the server, without a trailing slash.
+    pub base_url: String,
+    task: JoinHandle<()>,
+}
+
+impl HttpServer {
+    /// Start a server on a free port of the loopback interface, handling
+    /// requests with the given handler.
+    pub async fn start<F, Fut>(handler: F) -> Result<Self, TestHarnessError>
+    where
+        F: Fn(HttpRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| TestHarnessError::Server(format!("{e:?}")))?;
+        let address = listener
+            .local_addr()
+            .map_err(|e| TestHarnessError::Server(format!("{e:?}")))?;
+        let handler: Handler = Arc::new(move |request| handler(request).boxed());
+
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, handler).await {
+                        log::warn!("Failed to serve a test harness request: {e:?}");
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            base_url: format!("http://{address}"),
+            task,
+        })
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Read a request from the connection, and write its response.
+async fn serve(stream: TcpStream, handler: Handler) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let mut request_line = line.split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let target = request_line.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default().to_owned();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    let response = handler(HttpRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+    .await;
+
+    let mut stream = reader.into_inner();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        if response.status < 400 { "OK" } else { "Error" },
+        response.content_type,
+        response.body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await
+}
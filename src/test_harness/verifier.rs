@@ -0,0 +1,162 @@
+//! A mock OID4VP verifier, receiving `direct_post` responses.
+
+use super::server::{HttpResponse, HttpServer};
+use super::TestHarnessError;
+use crate::oid4vp::verifier::{VerifiedPresentation, Verifier, VerifierRequest};
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde_json::json;
+
+/// The path of the response endpoint.
+const RESPONSE_PATH: &str = "/response";
+
+#[derive(Debug, Default)]
+struct Responses {
+    /// The presentations of the valid responses, in the order they were
+    /// received.
+    presentations: Vec<VerifiedPresentation>,
+    /// The errors of the invalid responses.
+    errors: Vec<String>,
+}
+
+/// A mock verifier, requesting credentials as a `redirect_uri` client through
+/// unsigned requests passed by value, and recording the responses it receives.
+#[derive(Debug, uniffi::Object)]
+pub struct MockVerifier {
+    verifier: Arc<Verifier>,
+    responses: Arc<Mutex<Responses>>,
+    server: HttpServer,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl MockVerifier {
+    /// Start a verifier on a free port of the loopback interface.
+    #[uniffi::constructor]
+    pub async fn start() -> Result<Arc<Self>, TestHarnessError> {
+        let responses = Arc::new(Mutex::new(Responses::default()));
+        let verifier = Arc::new(OnceLock::<Arc<Verifier>>::new());
+
+        let server = {
+            let responses = responses.clone();
+            let verifier = verifier.clone();
+            HttpServer::start(move |request| {
+                let responses = responses.clone();
+                let verifier = verifier.get().cloned();
+                async move {
+                    let Some(verifier) = verifier.filter(|_| {
+                        request.method == "POST" && request.path == RESPONSE_PATH
+                    }) else {
+                        return HttpResponse::not_found();
+                    };
+                    let body = String::from_utf8_lossy(&request.body).into_owned();
+                    let result = verifier.handle_response(body).await;
+
+                    let mut responses = responses
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    match result {
+                        Ok(presentation) => {
+                            responses.presentations.push(presentation);
+                            HttpResponse::json(200, &json!({}))
+                        }
+                        Err(e) => {
+                            responses.errors.push(format!("{e:?}"));
+                            HttpResponse::json(
+                                400,
+                                &json!({ "error": "invalid_request", "error_description": e.to_string() }),
+                            )
+                        }
+                    }
+                }
+            })
+            .await?
+        };
+
+        let response_uri = format!("{}{RESPONSE_PATH}", server.base_url);
+        let inner = Verifier::new(
+            response_uri.clone(),
+            response_uri
+                .parse()
+                .map_err(|e| TestHarnessError::Server(format!("{e:?}")))?,
+        );
+        let _ = verifier.set(inner.clone());
+
+        Ok(Arc::new(Self {
+            verifier: inner,
+            responses,
+            server,
+        }))
+    }
+
+    /// Return the base URL of the verifier, e.g. `http://127.0.0.1:49152`.
+    pub fn base_url(&self) -> String {
+        self.server.base_url.clone()
+    }
+
+    /// Create a request for the credentials described by a JSON encoded
+    /// presentation definition. Its URL can be passed to
+    /// [crate::oid4vp::holder::Holder::authorization_request].
+    pub async fn create_request(
+        &self,
+        presentation_definition: String,
+    ) -> Result<VerifierRequest, TestHarnessError> {
+        self.verifier
+            .create_request(presentation_definition)
+            .await
+            .map_err(|e| TestHarnessError::Verifier(format!("{e:?}")))
+    }
+
+    /// Return the presentations of the valid responses received so far.
+    pub fn presentations(&self) -> Vec<VerifiedPresentation> {
+        self.responses().presentations.clone()
+    }
+
+    /// Return the errors of the invalid responses received so far.
+    pub fn response_errors(&self) -> Vec<String> {
+        self.responses().errors.clone()
+    }
+}
+
+impl MockVerifier {
+    fn responses(&self) -> std::sync::MutexGuard<'_, Responses> {
+        self.responses
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn records_responses() {
+        let verifier = MockVerifier::start().await.unwrap();
+        let request = verifier
+            .create_request(
+                json!({
+                    "id": "identity",
+                    "input_descriptors": [{
+                        "id": "identity",
+                        "constraints": { "fields": [{ "path": ["$.given_name"] }] }
+                    }]
+                })
+                .to_string(),
+            )
+            .await
+            .unwrap();
+        assert!(request.url.contains("client_id_scheme=redirect_uri"));
+
+        let response = reqwest::Client::new()
+            .post(format!("{}{RESPONSE_PATH}", verifier.base_url()))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(format!("vp_token=invalid&state={}", request.state))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        assert!(verifier.presentations().is_empty());
+        assert_eq!(verifier.response_errors().len(), 1);
+    }
+}
@@ -0,0 +1,355 @@
+//! X.509 trust anchors registered by the app.
+//!
+//! The [TrustAnchorStore] holds the root certificates trusted for each
+//! purpose: IACA certificates for the issuers of mdocs, and reader root
+//! certificates for the verifiers of OID4VP requests using the
+//! `x509_san_dns` client ID scheme. Certificate chains are built from the
+//! presented certificates up to a trust anchor, checking the validity of each
+//! certificate and, optionally, its revocation.
+//!
+//! NOTE: only P-256 certificates are supported, and revocation is checked
+//! with CRLs only.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use p256::{
+    ecdsa::{signature::Verifier, DerSignature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use x509_cert::{
+    crl::CertificateList,
+    der::{Decode, Encode},
+    ext::pkix::{
+        name::{DistributionPointName, GeneralName},
+        CrlDistributionPoints,
+    },
+    Certificate,
+};
+
+/// The maximum number of certificates in a chain, including the anchor.
+const MAX_CHAIN_LENGTH: usize = 8;
+
+/// What a trust anchor is trusted for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum TrustAnchorPurpose {
+    /// Issuing authority CA certificates, trusted to issue the document
+    /// signer certificates of mdocs.
+    Iaca,
+    /// Reader root certificates, trusted to issue the certificates of
+    /// verifiers.
+    Reader,
+}
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum TrustAnchorError {
+    #[error("Invalid certificate: {0}")]
+    InvalidCertificate(String),
+    #[error("The certificate is not valid at this time: {0}")]
+    Expired(String),
+    #[error("No chain to a trust anchor: {0}")]
+    UntrustedChain(String),
+    #[error("The certificate is revoked: {0}")]
+    Revoked(String),
+    #[error("Failed to check the revocation of the certificate: {0}")]
+    RevocationCheck(String),
+    #[error("Failed to acquire lock on the trust anchors")]
+    LockError,
+}
+
+/// Trust Anchor Store
+///
+/// The root certificates the app trusts, by purpose.
+#[derive(Debug, Default, uniffi::Object)]
+pub struct TrustAnchorStore {
+    anchors: RwLock<Vec<(TrustAnchorPurpose, Certificate)>>,
+    check_revocation: AtomicBool,
+}
+
+#[uniffi::export]
+impl TrustAnchorStore {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Trust a PEM encoded certificate.
+    pub fn add_pem(
+        &self,
+        purpose: TrustAnchorPurpose,
+        pem: String,
+    ) -> Result<(), TrustAnchorError> {
+        let (_, der) = pem_rfc7468::decode_vec(pem.as_bytes())
+            .map_err(|e| TrustAnchorError::InvalidCertificate(format!("{e:?}")))?;
+        self.add_der(purpose, der)
+    }
+
+    /// Trust a DER encoded certificate.
+    pub fn add_der(
+        &self,
+        purpose: TrustAnchorPurpose,
+        der: Vec<u8>,
+    ) -> Result<(), TrustAnchorError> {
+        let certificate = Certificate::from_der(&der)
+            .map_err(|e| TrustAnchorError::InvalidCertificate(format!("{e:?}")))?;
+
+        let mut anchors = self
+            .anchors
+            .write()
+            .map_err(|_| TrustAnchorError::LockError)?;
+        if !anchors.contains(&(purpose, certificate.clone())) {
+            anchors.push((purpose, certificate));
+        }
+        Ok(())
+    }
+
+    /// Stop trusting the certificates of a purpose.
+    pub fn clear(&self, purpose: TrustAnchorPurpose) -> Result<(), TrustAnchorError> {
+        self.anchors
+            .write()
+            .map_err(|_| TrustAnchorError::LockError)?
+            .retain(|(anchor_purpose, _)| *anchor_purpose != purpose);
+        Ok(())
+    }
+
+    /// Return the PEM encoded certificates trusted for a purpose.
+    pub fn list(&self, purpose: TrustAnchorPurpose) -> Result<Vec<String>, TrustAnchorError> {
+        self.anchors(purpose)?
+            .iter()
+            .map(|certificate| {
+                let der = certificate
+                    .to_der()
+                    .map_err(|e| TrustAnchorError::InvalidCertificate(format!("{e:?}")))?;
+                pem_rfc7468::encode_string("CERTIFICATE", pem_rfc7468::LineEnding::LF, &der)
+                    .map_err(|e| TrustAnchorError::InvalidCertificate(format!("{e:?}")))
+            })
+            .collect()
+    }
+
+    /// Set whether the revocation of the certificates of a chain is checked,
+    /// by fetching the CRLs they reference.
+    pub fn set_check_revocation(&self, enabled: bool) {
+        self.check_revocation.store(enabled, Ordering::SeqCst);
+    }
+}
+
+impl TrustAnchorStore {
+    /// Return the certificates trusted for a purpose.
+    pub(crate) fn anchors(
+        &self,
+        purpose: TrustAnchorPurpose,
+    ) -> Result<Vec<Certificate>, TrustAnchorError> {
+        Ok(self
+            .anchors
+            .read()
+            .map_err(|_| TrustAnchorError::LockError)?
+            .iter()
+            .filter(|(anchor_purpose, _)| *anchor_purpose == purpose)
+            .map(|(_, certificate)| certificate.clone())
+            .collect())
+    }
+
+    pub(crate) fn checks_revocation(&self) -> bool {
+        self.check_revocation.load(Ordering::SeqCst)
+    }
+}
+
+/// Build a chain from the leaf, the first certificate of `chain`, to one of
+/// the anchors, through the other certificates of `chain`, and check it.
+pub(crate) async fn verify_chain(
+    chain: &[Certificate],
+    anchors: &[Certificate],
+    check_revocation: bool,
+    now: SystemTime,
+) -> Result<(), TrustAnchorError> {
+    let Some(leaf) = chain.first() else {
+        return Err(TrustAnchorError::UntrustedChain(
+            "the chain is empty".into(),
+        ));
+    };
+
+    let mut path = vec![leaf];
+    loop {
+        let current = path[path.len() - 1];
+        check_validity(current, now)?;
+        if anchors.contains(current) {
+            break;
+        }
+        if path.len() >= MAX_CHAIN_LENGTH {
+            return Err(TrustAnchorError::UntrustedChain(subject(leaf)));
+        }
+
+        let issuer = anchors
+            .iter()
+            .chain(&chain[1..])
+            .find(|issuer| issued_by(current, issuer))
+            .ok_or_else(|| TrustAnchorError::UntrustedChain(subject(current)))?;
+        path.push(issuer);
+    }
+
+    if check_revocation {
+        for pair in path.windows(2) {
+            check_crl(pair[0], pair[1], now).await?;
+        }
+    }
+    Ok(())
+}
+
+fn subject(certificate: &Certificate) -> String {
+    certificate.tbs_certificate.subject.to_string()
+}
+
+fn check_validity(certificate: &Certificate, now: SystemTime) -> Result<(), TrustAnchorError> {
+    let validity = &certificate.tbs_certificate.validity;
+    if validity.not_before.to_system_time() <= now && now < validity.not_after.to_system_time() {
+        return Ok(());
+    }
+    Err(TrustAnchorError::Expired(subject(certificate)))
+}
+
+fn key_of(certificate: &Certificate) -> Option<VerifyingKey> {
+    let spki = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .ok()?;
+    VerifyingKey::from_public_key_der(&spki).ok()
+}
+
+/// Whether a signature over `message` was made with the key of `issuer`.
+fn signed_by(message: &[u8], signature: &[u8], issuer: &Certificate) -> bool {
+    let (Some(key), Ok(signature)) = (key_of(issuer), DerSignature::from_bytes(signature)) else {
+        return false;
+    };
+    key.verify(message, &signature).is_ok()
+}
+
+fn issued_by(certificate: &Certificate, issuer: &Certificate) -> bool {
+    if issuer.tbs_certificate.subject != certificate.tbs_certificate.issuer {
+        return false;
+    }
+    let Ok(tbs) = certificate.tbs_certificate.to_der() else {
+        return false;
+    };
+    signed_by(&tbs, certificate.signature.raw_bytes(), issuer)
+}
+
+/// Return the URLs of the CRLs a certificate references.
+fn crl_urls(certificate: &Certificate) -> Result<Vec<String>, TrustAnchorError> {
+    let distribution_points = certificate
+        .tbs_certificate
+        .get::<CrlDistributionPoints>()
+        .map_err(|e| TrustAnchorError::InvalidCertificate(format!("{e:?}")))?;
+    let Some((_, CrlDistributionPoints(distribution_points))) = distribution_points else {
+        return Ok(vec![]);
+    };
+
+    Ok(distribution_points
+        .into_iter()
+        .filter_map(|point| match point.distribution_point {
+            Some(DistributionPointName::FullName(names)) => Some(names),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|name| match name {
+            GeneralName::UniformResourceIdentifier(uri) => Some(uri.to_string()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Check that a certificate is not listed in the CRLs it references, which
+/// must be signed by its issuer. Certificates without CRLs are not checked.
+async fn check_crl(
+    certificate: &Certificate,
+    issuer: &Certificate,
+    now: SystemTime,
+) -> Result<(), TrustAnchorError> {
+    let error = TrustAnchorError::RevocationCheck;
+    let client = reqwest::Client::new();
+
+    for url in crl_urls(certificate)? {
+        let der = client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| error(e.to_string()))?
+            .bytes()
+            .await
+            .map_err(|e| error(e.to_string()))?;
+        let crl = CertificateList::from_der(&der).map_err(|e| error(e.to_string()))?;
+
+        let tbs = crl
+            .tbs_cert_list
+            .to_der()
+            .map_err(|e| error(e.to_string()))?;
+        if !signed_by(&tbs, crl.signature.raw_bytes(), issuer) {
+            return Err(error(format!("the CRL {url} is not signed by the issuer")));
+        }
+        if crl
+            .tbs_cert_list
+            .next_update
+            .is_some_and(|next_update| next_update.to_system_time() < now)
+        {
+            return Err(error(format!("the CRL {url} is stale")));
+        }
+
+        let revoked = crl
+            .tbs_cert_list
+            .revoked_certificates
+            .iter()
+            .flatten()
+            .any(|revoked| revoked.serial_number == certificate.tbs_certificate.serial_number);
+        if revoked {
+            return Err(TrustAnchorError::Revoked(subject(certificate)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn builds_chains_to_trust_anchors() {
+        let store = TrustAnchorStore::new();
+        store
+            .add_pem(
+                TrustAnchorPurpose::Iaca,
+                include_str!("../tests/res/root-cert.pem").into(),
+            )
+            .unwrap();
+        assert_eq!(store.list(TrustAnchorPurpose::Iaca).unwrap().len(), 1);
+        assert!(store.list(TrustAnchorPurpose::Reader).unwrap().is_empty());
+
+        let (_, der) =
+            pem_rfc7468::decode_vec(include_str!("../tests/res/issuer-cert.pem").as_bytes())
+                .unwrap();
+        let chain = [Certificate::from_der(&der).unwrap()];
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_750_000_000);
+
+        let anchors = store.anchors(TrustAnchorPurpose::Iaca).unwrap();
+        verify_chain(&chain, &anchors, false, now).await.unwrap();
+
+        let anchors = store.anchors(TrustAnchorPurpose::Reader).unwrap();
+        assert!(matches!(
+            verify_chain(&chain, &anchors, false, now).await,
+            Err(TrustAnchorError::UntrustedChain(_))
+        ));
+
+        let anchors = store.anchors(TrustAnchorPurpose::Iaca).unwrap();
+        let later = SystemTime::UNIX_EPOCH + Duration::from_secs(2_100_000_000);
+        assert!(matches!(
+            verify_chain(&chain, &anchors, false, later).await,
+            Err(TrustAnchorError::Expired(_))
+        ));
+
+        store.clear(TrustAnchorPurpose::Iaca).unwrap();
+        assert!(store.list(TrustAnchorPurpose::Iaca).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,480 @@
+//! ETSI trusted lists (TS 119 612), such as the EU List of Trusted Lists
+//! (LOTL) and the national trusted lists it points to.
+//!
+//! The [TrustListManager] downloads the lists, checks them, and caches the
+//! certificates of their trusted services in the storage manager, so that
+//! they can be used as trust anchors offline, for x509 client ID
+//! verification and local credential verification.
+//!
+//! NOTE: the XML signatures of the lists are not verified. Instead, the
+//! signing certificate of each list must be one of the certificates pinned
+//! for it, by the app for the LOTL and by the LOTL for the national lists.
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::common::Url;
+use crate::storage_manager::*;
+
+use base64::prelude::*;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use x509_cert::{der::Decode, Certificate};
+
+/// Internal prefix for trusted list keys.
+const KEY_PREFIX: &str = "TrustList.";
+
+/// The status of the services that are currently trusted.
+const GRANTED: &str = "http://uri.etsi.org/TrstSvc/TrustedList/Svcstatus/granted";
+
+/// The MIME type of XML trusted lists, as opposed to their PDF renderings.
+const XML_MIME_TYPE: &str = "application/vnd.etsi.tsl+xml";
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum TrustListError {
+    #[error("Failed to fetch the trusted list: {0}")]
+    Fetch(String),
+    #[error("Failed to parse the trusted list: {0}")]
+    Parse(String),
+    #[error("The trusted list is stale: {0}")]
+    Stale(String),
+    #[error("The trusted list is not signed with a pinned certificate: {0}")]
+    UntrustedSigner(String),
+    #[error("Invalid pinned certificate: {0}")]
+    InvalidCertificate(String),
+    /// Attempting to write the list to storage failed.
+    #[error("Failed to Write to Storage")]
+    StoreFailed(StorageManagerError),
+    /// Attempting to read the list from storage failed.
+    #[error("Failed to Read from Storage")]
+    LoadFailed(StorageManagerError),
+}
+
+/// A trusted service of a trusted list, whose certificate is a trust anchor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct TrustedService {
+    /// The territory of the list, e.g. `FR`.
+    pub territory: String,
+    /// The name of the trust service provider.
+    pub provider: String,
+    /// The type of the service, e.g.
+    /// `http://uri.etsi.org/TrstSvc/Svctype/CA/QC`.
+    pub service_type: String,
+    /// The PEM encoded certificate of the service.
+    pub certificate: String,
+}
+
+/// A trusted list, as cached.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TrustList {
+    territory: String,
+    next_update: Option<SystemTime>,
+    services: Vec<TrustedService>,
+    #[serde(skip)]
+    signer: Option<Vec<u8>>,
+    #[serde(skip)]
+    pointers: Vec<TrustListPointer>,
+}
+
+/// A pointer of a list of trusted lists to a trusted list.
+#[derive(Debug, Clone, Default)]
+struct TrustListPointer {
+    location: String,
+    mime_type: Option<String>,
+    certificates: Vec<Vec<u8>>,
+}
+
+/// A service of a trusted list, as parsed.
+#[derive(Debug, Default)]
+struct ServiceEntry {
+    service_type: String,
+    status: String,
+    certificates: Vec<Vec<u8>>,
+}
+
+/// Trust List Manager
+///
+/// Downloads the LOTL and the trusted lists it points to, and caches their
+/// trusted services in the storage manager.
+#[derive(Debug, uniffi::Object)]
+pub struct TrustListManager {
+    storage: Arc<dyn StorageManagerInterface>,
+    client: reqwest::Client,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl TrustListManager {
+    #[uniffi::constructor]
+    pub fn new(engine: Arc<dyn StorageManagerInterface>) -> Arc<Self> {
+        Arc::new(Self {
+            storage: engine,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// Download the list of trusted lists, which must be signed with one of
+    /// the PEM encoded `signers`, and the trusted lists it points to.
+    ///
+    /// Trusted lists that cannot be refreshed keep their cached copy, and the
+    /// locations of the failed lists are returned.
+    pub async fn refresh(
+        &self,
+        lotl_url: Url,
+        signers: Vec<String>,
+    ) -> Result<Vec<String>, TrustListError> {
+        let lotl = self
+            .fetch(lotl_url.as_str(), &decode_pems(&signers)?)
+            .await?;
+
+        let mut failed = vec![];
+        for pointer in lotl.pointers {
+            if pointer
+                .mime_type
+                .as_deref()
+                .is_some_and(|mime_type| mime_type != XML_MIME_TYPE)
+            {
+                continue;
+            }
+            let result = self
+                .fetch(&pointer.location, &pointer.certificates)
+                .await
+                .and_then(|list| self.store(&pointer.location, &list));
+            if let Err(e) = result {
+                tracing::warn!(
+                    "failed to refresh the trusted list {}: {e}",
+                    pointer.location
+                );
+                failed.push(pointer.location);
+            }
+        }
+
+        Ok(failed)
+    }
+
+    /// Download a single trusted list, which must be signed with one of the
+    /// PEM encoded `signers`.
+    pub async fn refresh_trust_list(
+        &self,
+        url: Url,
+        signers: Vec<String>,
+    ) -> Result<(), TrustListError> {
+        let list = self.fetch(url.as_str(), &decode_pems(&signers)?).await?;
+        self.store(url.as_str(), &list)
+    }
+
+    /// Return the trusted services of the cached lists that are not stale.
+    pub fn services(&self) -> Result<Vec<TrustedService>, TrustListError> {
+        let now = SystemTime::now();
+        let mut services = vec![];
+
+        for key in self.storage.list().map_err(TrustListError::LoadFailed)? {
+            if !key.0.starts_with(KEY_PREFIX) {
+                continue;
+            }
+            let Some(raw) = self.storage.get(key).map_err(TrustListError::LoadFailed)? else {
+                continue;
+            };
+            let Ok(list) = serde_cbor::from_slice::<TrustList>(&raw.0) else {
+                continue;
+            };
+            if list
+                .next_update
+                .is_some_and(|next_update| next_update < now)
+            {
+                continue;
+            }
+            services.extend(list.services);
+        }
+
+        Ok(services)
+    }
+
+    /// Return the PEM encoded certificates of the trusted services of the
+    /// given types, or of every type when empty, for use as trust anchors.
+    pub fn trust_anchors(&self, service_types: Vec<String>) -> Result<Vec<String>, TrustListError> {
+        Ok(self
+            .services()?
+            .into_iter()
+            .filter(|service| {
+                service_types.is_empty() || service_types.contains(&service.service_type)
+            })
+            .map(|service| service.certificate)
+            .collect())
+    }
+}
+
+impl TrustListManager {
+    /// Return the trust anchors of every trusted service, dropping those that
+    /// cannot be parsed.
+    pub(crate) fn certificates(&self) -> Result<Vec<Certificate>, TrustListError> {
+        Ok(decode_pems(&self.trust_anchors(vec![])?)?
+            .iter()
+            .filter_map(|der| Certificate::from_der(der).ok())
+            .collect())
+    }
+
+    async fn fetch(&self, url: &str, signers: &[Vec<u8>]) -> Result<TrustList, TrustListError> {
+        let xml = self
+            .client
+            .get(url)
+            .header(reqwest::header::ACCEPT, XML_MIME_TYPE)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| TrustListError::Fetch(format!("{e:?}")))?
+            .text()
+            .await
+            .map_err(|e| TrustListError::Fetch(format!("{e:?}")))?;
+
+        let list = parse(&xml)?;
+        check(&list, url, signers, SystemTime::now())?;
+        Ok(list)
+    }
+
+    fn store(&self, location: &str, list: &TrustList) -> Result<(), TrustListError> {
+        let value = serde_cbor::to_vec(list)
+            .map_err(|_| TrustListError::StoreFailed(StorageManagerError::InternalError))?;
+        self.storage
+            .add(Key(format!("{KEY_PREFIX}{location}")), Value(value))
+            .map_err(TrustListError::StoreFailed)
+    }
+}
+
+/// Check that a list is signed with one of the pinned certificates, and is
+/// not stale.
+fn check(
+    list: &TrustList,
+    url: &str,
+    signers: &[Vec<u8>],
+    now: SystemTime,
+) -> Result<(), TrustListError> {
+    match &list.signer {
+        Some(signer) if signers.contains(signer) => {}
+        _ => return Err(TrustListError::UntrustedSigner(url.into())),
+    }
+    if list
+        .next_update
+        .is_some_and(|next_update| next_update < now)
+    {
+        return Err(TrustListError::Stale(url.into()));
+    }
+    Ok(())
+}
+
+/// Decode PEM encoded certificates to DER.
+fn decode_pems(pems: &[String]) -> Result<Vec<Vec<u8>>, TrustListError> {
+    pems.iter()
+        .map(|pem| {
+            pem_rfc7468::decode_vec(pem.as_bytes())
+                .map(|(_, der)| der)
+                .map_err(|e| TrustListError::InvalidCertificate(format!("{e:?}")))
+        })
+        .collect()
+}
+
+fn encode_pem(der: &[u8]) -> Result<String, TrustListError> {
+    pem_rfc7468::encode_string("CERTIFICATE", pem_rfc7468::LineEnding::LF, der)
+        .map_err(|e| TrustListError::Parse(format!("{e:?}")))
+}
+
+/// Whether the path of the current element ends with the given local names.
+fn at(path: &[String], suffix: &[&str]) -> bool {
+    path.len() >= suffix.len()
+        && path[path.len() - suffix.len()..]
+            .iter()
+            .zip(suffix)
+            .all(|(name, expected)| name == expected)
+}
+
+/// Parse a trusted list, or a list of trusted lists.
+fn parse(xml: &str) -> Result<TrustList, TrustListError> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut list = TrustList::default();
+    let mut path: Vec<String> = vec![];
+    let mut provider = String::new();
+    let mut service = ServiceEntry::default();
+
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| TrustListError::Parse(format!("{e:?}")))?
+        {
+            Event::Start(element) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+                match name.as_str() {
+                    "OtherTSLPointer" => list.pointers.push(TrustListPointer::default()),
+                    "TrustServiceProvider" => provider.clear(),
+                    "TSPService" => service = ServiceEntry::default(),
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Event::End(_) => {
+                if path.last().is_some_and(|name| name == "TSPService") {
+                    let service = std::mem::take(&mut service);
+                    if service.status == GRANTED {
+                        for certificate in service.certificates {
+                            list.services.push(TrustedService {
+                                territory: list.territory.clone(),
+                                provider: provider.clone(),
+                                service_type: service.service_type.clone(),
+                                certificate: encode_pem(&certificate)?,
+                            });
+                        }
+                    }
+                }
+                path.pop();
+            }
+            Event::Text(text) => {
+                let text = text
+                    .unescape()
+                    .map_err(|e| TrustListError::Parse(format!("{e:?}")))?;
+                let certificate = || {
+                    let base64 = text.split_whitespace().collect::<String>();
+                    BASE64_STANDARD
+                        .decode(base64)
+                        .map_err(|e| TrustListError::Parse(format!("{e:?}")))
+                };
+
+                if at(
+                    &path,
+                    &[
+                        "TrustServiceStatusList",
+                        "SchemeInformation",
+                        "SchemeTerritory",
+                    ],
+                ) {
+                    list.territory = text.to_string();
+                } else if at(&path, &["SchemeInformation", "NextUpdate", "dateTime"]) {
+                    let next_update = OffsetDateTime::parse(&text, &Rfc3339)
+                        .map_err(|e| TrustListError::Parse(format!("{e:?}")))?;
+                    list.next_update = Some(next_update.into());
+                } else if at(
+                    &path,
+                    &["Signature", "KeyInfo", "X509Data", "X509Certificate"],
+                ) {
+                    list.signer = Some(certificate()?);
+                } else if let Some(pointer) = list
+                    .pointers
+                    .last_mut()
+                    .filter(|_| path.iter().any(|name| name == "OtherTSLPointer"))
+                {
+                    if at(&path, &["OtherTSLPointer", "TSLLocation"]) {
+                        pointer.location = text.to_string();
+                    } else if at(&path, &["OtherInformation", "MimeType"]) {
+                        pointer.mime_type = Some(text.to_string());
+                    } else if at(&path, &["DigitalId", "X509Certificate"]) {
+                        pointer.certificates.push(certificate()?);
+                    }
+                } else if at(&path, &["TSPInformation", "TSPName", "Name"]) && provider.is_empty() {
+                    provider = text.to_string();
+                } else if at(&path, &["ServiceInformation", "ServiceTypeIdentifier"]) {
+                    service.service_type = text.to_string();
+                } else if at(&path, &["ServiceInformation", "ServiceStatus"]) {
+                    service.status = text.to_string();
+                } else if at(
+                    &path,
+                    &["ServiceDigitalIdentity", "DigitalId", "X509Certificate"],
+                ) {
+                    service.certificates.push(certificate()?);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The lists are parsed without decoding the certificates.
+    const CERTIFICATE: &str = "AQIDBA==";
+
+    #[test]
+    fn parses_trusted_lists() {
+        let xml = format!(
+            r#"<TrustServiceStatusList xmlns="http://uri.etsi.org/02231/v2#" xmlns:ds="http://www.w3.org/2000/09/xmldsig#">
+  <SchemeInformation>
+    <SchemeTerritory>FR</SchemeTerritory>
+    <PointersToOtherTSL>
+      <OtherTSLPointer>
+        <ServiceDigitalIdentities><ServiceDigitalIdentity><DigitalId>
+          <X509Certificate>{CERTIFICATE}</X509Certificate>
+        </DigitalId></ServiceDigitalIdentity></ServiceDigitalIdentities>
+        <TSLLocation>https://trust.example.fr/tsl.xml</TSLLocation>
+        <AdditionalInformation><OtherInformation>
+          <SchemeTerritory>DE</SchemeTerritory>
+        </OtherInformation><OtherInformation>
+          <MimeType>application/vnd.etsi.tsl+xml</MimeType>
+        </OtherInformation></AdditionalInformation>
+      </OtherTSLPointer>
+    </PointersToOtherTSL>
+    <NextUpdate><dateTime>2030-01-01T00:00:00Z</dateTime></NextUpdate>
+  </SchemeInformation>
+  <TrustServiceProviderList>
+    <TrustServiceProvider>
+      <TSPInformation><TSPName><Name xml:lang="en">Issuer</Name></TSPName></TSPInformation>
+      <TSPServices>
+        <TSPService><ServiceInformation>
+          <ServiceTypeIdentifier>http://uri.etsi.org/TrstSvc/Svctype/CA/QC</ServiceTypeIdentifier>
+          <ServiceDigitalIdentity><DigitalId><X509Certificate>{CERTIFICATE}</X509Certificate></DigitalId></ServiceDigitalIdentity>
+          <ServiceStatus>http://uri.etsi.org/TrstSvc/TrustedList/Svcstatus/granted</ServiceStatus>
+        </ServiceInformation></TSPService>
+        <TSPService><ServiceInformation>
+          <ServiceTypeIdentifier>http://uri.etsi.org/TrstSvc/Svctype/CA/QC</ServiceTypeIdentifier>
+          <ServiceDigitalIdentity><DigitalId><X509Certificate>{CERTIFICATE}</X509Certificate></DigitalId></ServiceDigitalIdentity>
+          <ServiceStatus>http://uri.etsi.org/TrstSvc/TrustedList/Svcstatus/withdrawn</ServiceStatus>
+        </ServiceInformation></TSPService>
+      </TSPServices>
+    </TrustServiceProvider>
+  </TrustServiceProviderList>
+  <ds:Signature><ds:KeyInfo><ds:X509Data>
+    <ds:X509Certificate>{CERTIFICATE}</ds:X509Certificate>
+  </ds:X509Data></ds:KeyInfo></ds:Signature>
+</TrustServiceStatusList>"#
+        );
+        let certificate = BASE64_STANDARD.decode(CERTIFICATE).unwrap();
+
+        let list = parse(&xml).unwrap();
+        assert_eq!(list.territory, "FR");
+        assert_eq!(list.pointers.len(), 1);
+        assert_eq!(
+            list.pointers[0].location,
+            "https://trust.example.fr/tsl.xml"
+        );
+        assert_eq!(list.pointers[0].mime_type.as_deref(), Some(XML_MIME_TYPE));
+        assert_eq!(list.pointers[0].certificates, vec![certificate.clone()]);
+
+        // Only granted services are trusted.
+        assert_eq!(
+            list.services,
+            vec![TrustedService {
+                territory: "FR".into(),
+                provider: "Issuer".into(),
+                service_type: "http://uri.etsi.org/TrstSvc/Svctype/CA/QC".into(),
+                certificate: encode_pem(&certificate).unwrap(),
+            }]
+        );
+
+        let url = "https://trust.example.eu/lotl.xml";
+        let now = SystemTime::UNIX_EPOCH;
+        assert!(check(&list, url, &[certificate.clone()], now).is_ok());
+        assert!(matches!(
+            check(&list, url, &[], now),
+            Err(TrustListError::UntrustedSigner(_))
+        ));
+        let later = list.next_update.unwrap() + std::time::Duration::from_secs(1);
+        assert!(matches!(
+            check(&list, url, &[certificate], later),
+            Err(TrustListError::Stale(_))
+        ));
+    }
+}
@@ -0,0 +1,198 @@
+//! Classification of the URLs apps receive through deep links, QR codes and
+//! app links, into the protocol that handles them.
+//!
+//! - OID4VP authorization requests, e.g. `openid4vp://?client_id=...`, are
+//!   passed to [crate::oid4vp::holder::Holder::authorization_request].
+//! - OID4VCI credential offers, e.g. `openid-credential-offer://?...`, are
+//!   passed to [crate::oid4vci::CredentialOffer::parse].
+//! - ISO 18013-5 device engagements, `mdoc:<device-engagement>`, are passed
+//!   to [crate::mdl::reader::establish_session].
+//!
+//! https app links are classified by their query parameters.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use url::Url;
+
+/// The schemes of OID4VP authorization requests.
+const PRESENTATION_REQUEST_SCHEMES: &[&str] =
+    &["openid4vp", "eudi-openid4vp", "mdoc-openid4vp", "haip"];
+/// The schemes of OID4VCI credential offers.
+const CREDENTIAL_OFFER_SCHEMES: &[&str] = &["openid-credential-offer", "haip-vci"];
+/// The scheme of ISO 18013-5 device engagements.
+const PROXIMITY_ENGAGEMENT_SCHEME: &str = "mdoc";
+
+/// The query parameters identifying credential offers.
+const CREDENTIAL_OFFER_PARAMETERS: &[&str] = &["credential_offer", "credential_offer_uri"];
+/// The query parameters identifying authorization requests, along with a
+/// `client_id`.
+const PRESENTATION_REQUEST_PARAMETERS: &[&str] = &[
+    "request",
+    "request_uri",
+    "presentation_definition",
+    "presentation_definition_uri",
+    "dcql_query",
+];
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum UrlRouterError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Unsupported URL: {0}")]
+    Unsupported(String),
+}
+
+/// The kinds of URLs the router recognizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum UrlKind {
+    PresentationRequest,
+    CredentialOffer,
+    ProximityEngagement,
+}
+
+/// A classified URL, to pass to the subsystem handling it.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum RoutedUrl {
+    /// An OID4VP authorization request.
+    PresentationRequest { url: Url },
+    /// An OID4VCI credential offer.
+    CredentialOffer { url: String },
+    /// An ISO 18013-5 device engagement, e.g. scanned from a QR code.
+    ProximityEngagement { uri: String },
+}
+
+impl RoutedUrl {
+    pub fn kind(&self) -> UrlKind {
+        match self {
+            Self::PresentationRequest { .. } => UrlKind::PresentationRequest,
+            Self::CredentialOffer { .. } => UrlKind::CredentialOffer,
+            Self::ProximityEngagement { .. } => UrlKind::ProximityEngagement,
+        }
+    }
+}
+
+/// Classify a URL by its scheme, and https URLs by their query parameters.
+#[derive(Debug, Default, uniffi::Object)]
+pub struct UrlRouter {
+    /// Additional schemes, e.g. the custom schemes of the app.
+    schemes: RwLock<HashMap<String, UrlKind>>,
+}
+
+#[uniffi::export]
+impl UrlRouter {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Route the URLs of a scheme, e.g. a custom scheme of the app, to a kind.
+    pub fn register_scheme(&self, scheme: String, kind: UrlKind) {
+        self.schemes
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(scheme.to_lowercase(), kind);
+    }
+
+    /// Identify the protocol of a URL.
+    pub fn classify(&self, url: String) -> Result<RoutedUrl, UrlRouterError> {
+        let url = url.trim();
+        let parsed = Url::parse(url).map_err(|e| UrlRouterError::InvalidUrl(format!("{e:?}")))?;
+        let scheme = parsed.scheme();
+
+        let registered = self
+            .schemes
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(scheme)
+            .copied();
+        let kind = match registered {
+            Some(kind) => Some(kind),
+            None if PRESENTATION_REQUEST_SCHEMES.contains(&scheme) => {
+                Some(UrlKind::PresentationRequest)
+            }
+            None if CREDENTIAL_OFFER_SCHEMES.contains(&scheme) => Some(UrlKind::CredentialOffer),
+            None if scheme == PROXIMITY_ENGAGEMENT_SCHEME => Some(UrlKind::ProximityEngagement),
+            None if scheme == "https" => kind_from_parameters(&parsed),
+            None => None,
+        };
+
+        match kind {
+            Some(UrlKind::PresentationRequest) => {
+                Ok(RoutedUrl::PresentationRequest { url: parsed })
+            }
+            Some(UrlKind::CredentialOffer) => Ok(RoutedUrl::CredentialOffer {
+                url: url.to_owned(),
+            }),
+            Some(UrlKind::ProximityEngagement) => Ok(RoutedUrl::ProximityEngagement {
+                uri: url.to_owned(),
+            }),
+            None => Err(UrlRouterError::Unsupported(url.to_owned())),
+        }
+    }
+}
+
+/// Return the kind of an app link, from its query parameters.
+fn kind_from_parameters(url: &Url) -> Option<UrlKind> {
+    let has = |name: &str| url.query_pairs().any(|(key, _)| key == name);
+
+    if CREDENTIAL_OFFER_PARAMETERS.iter().any(|name| has(name)) {
+        return Some(UrlKind::CredentialOffer);
+    }
+    if has("client_id") && PRESENTATION_REQUEST_PARAMETERS.iter().any(|name| has(name)) {
+        return Some(UrlKind::PresentationRequest);
+    }
+    None
+}
+
+/// Return the kind of a classified URL.
+#[uniffi::export]
+pub fn routed_url_kind(routed_url: RoutedUrl) -> UrlKind {
+    routed_url.kind()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_urls() {
+        let router = UrlRouter::new();
+        let kind = |url: &str| router.classify(url.into()).map(|routed| routed.kind());
+
+        assert_eq!(
+            kind("openid4vp://?client_id=did:web:verifier&request_uri=https://verifier.example.com/request").unwrap(),
+            UrlKind::PresentationRequest
+        );
+        assert_eq!(
+            kind("openid-credential-offer://?credential_offer_uri=https%3A%2F%2Fissuer.example.com%2Foffer").unwrap(),
+            UrlKind::CredentialOffer
+        );
+        assert_eq!(
+            kind("mdoc:owBjMS4wAYIB2BhYS6QBAiABIVggjVFfXC").unwrap(),
+            UrlKind::ProximityEngagement
+        );
+        assert_eq!(
+            kind("https://wallet.example.com/offer?credential_offer_uri=https%3A%2F%2Fissuer.example.com").unwrap(),
+            UrlKind::CredentialOffer
+        );
+        assert_eq!(
+            kind("https://wallet.example.com/present?client_id=verifier&request_uri=https%3A%2F%2Fverifier.example.com").unwrap(),
+            UrlKind::PresentationRequest
+        );
+        assert!(matches!(
+            kind("https://wallet.example.com/settings"),
+            Err(UrlRouterError::Unsupported(_))
+        ));
+        assert!(matches!(
+            kind("not a url"),
+            Err(UrlRouterError::InvalidUrl(_))
+        ));
+
+        router.register_scheme("example-wallet".into(), UrlKind::CredentialOffer);
+        assert_eq!(
+            kind("example-wallet://?credential_offer=%7B%7D").unwrap(),
+            UrlKind::CredentialOffer
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Encrypted backups of the credential collection.
+//!
+//! A backup is framed as:
+//!
+//! ```text
+//! "VDCB" || version (1) || PBKDF2 rounds (4, big endian) || salt (16) || nonce (12) || ciphertext
+//! ```
+//!
+//! The contents are encrypted with AES-256-GCM, using a key derived from the
+//! passphrase with PBKDF2-HMAC-SHA256. The header is used as associated data,
+//! so that it cannot be altered without failing decryption.
+
+use super::{CredentialMetadata, VdcCollection, VdcCollectionError};
+use crate::common::*;
+use crate::credential::Credential;
+use crate::crypto_provider::{self, AES_GCM_NONCE_LEN};
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+const MAGIC: &[u8] = b"VDCB";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = AES_GCM_NONCE_LEN;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + SALT_LEN + NONCE_LEN;
+
+/// The PBKDF2 rounds used for new backups.
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum BackupError {
+    #[error("The data is not a wallet backup")]
+    InvalidBackup,
+    #[error("Unsupported backup version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Failed to decrypt the backup, the passphrase may be incorrect")]
+    DecryptionFailed,
+    #[error("Failed to encrypt the backup")]
+    EncryptionFailed,
+    #[error(transparent)]
+    VdcCollection(#[from] VdcCollectionError),
+}
+
+/// How credentials in a backup that are already stored are imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum BackupConflictPolicy {
+    /// Keep the stored credential.
+    Skip,
+    /// Replace the stored credential with the one in the backup.
+    Replace,
+}
+
+/// The outcome of importing a backup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct BackupImportSummary {
+    /// Credentials that were not stored before.
+    pub imported: u32,
+    /// Stored credentials replaced by the ones in the backup.
+    pub replaced: u32,
+    /// Credentials that were already stored and kept.
+    pub skipped: u32,
+}
+
+/// The contents of a backup.
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupContents {
+    credentials: Vec<Credential>,
+    metadata: HashMap<Uuid, CredentialMetadata>,
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Export every credential, and its metadata, as an archive encrypted
+    /// with the passphrase.
+    pub fn export_backup(&self, passphrase: String) -> Result<Vec<u8>, BackupError> {
+        self.export_backup_with_rounds(&passphrase, PBKDF2_ROUNDS)
+    }
+
+    /// Import the credentials, and their metadata, from an archive created
+    /// with [VdcCollection::export_backup].
+    pub fn import_backup(
+        &self,
+        backup: Vec<u8>,
+        passphrase: String,
+        conflict_policy: BackupConflictPolicy,
+    ) -> Result<BackupImportSummary, BackupError> {
+        let contents = open(&backup, &passphrase)?;
+        let mut summary = BackupImportSummary::default();
+
+        for credential in contents.credentials {
+            let id = credential.id;
+            let exists = self.get(id).ok().flatten().is_some();
+
+            match (exists, conflict_policy) {
+                (true, BackupConflictPolicy::Skip) => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                (true, BackupConflictPolicy::Replace) => summary.replaced += 1,
+                (false, _) => summary.imported += 1,
+            }
+
+            self.add(&credential)?;
+            if let Some(metadata) = contents.metadata.get(&id) {
+                self.set_metadata(id, metadata.clone())?;
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+impl VdcCollection {
+    fn export_backup_with_rounds(
+        &self,
+        passphrase: &str,
+        rounds: u32,
+    ) -> Result<Vec<u8>, BackupError> {
+        let mut contents = BackupContents {
+            credentials: vec![],
+            metadata: HashMap::new(),
+        };
+
+        for id in self.all_entries()? {
+            let Some(credential) = self.get(id)? else {
+                continue;
+            };
+            let metadata = self.metadata(id)?;
+            if metadata != CredentialMetadata::default() {
+                contents.metadata.insert(id, metadata);
+            }
+            contents.credentials.push(credential);
+        }
+
+        seal(&contents, passphrase, rounds)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> Vec<u8> {
+    let mut key = vec![0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, rounds, &mut key);
+    key
+}
+
+fn seal(contents: &BackupContents, passphrase: &str, rounds: u32) -> Result<Vec<u8>, BackupError> {
+    let plaintext =
+        serde_cbor::to_vec(contents).map_err(|_| VdcCollectionError::SerializeFailed)?;
+
+    let provider = crypto_provider::provider();
+    let salt = provider
+        .random_bytes(SALT_LEN as u32)
+        .map_err(|_| BackupError::EncryptionFailed)?;
+    let nonce = provider
+        .random_bytes(NONCE_LEN as u32)
+        .map_err(|_| BackupError::EncryptionFailed)?;
+
+    let mut backup = Vec::with_capacity(HEADER_LEN + plaintext.len());
+    backup.extend_from_slice(MAGIC);
+    backup.push(VERSION);
+    backup.extend_from_slice(&rounds.to_be_bytes());
+    backup.extend_from_slice(&salt);
+    backup.extend_from_slice(&nonce);
+
+    let ciphertext = provider
+        .aes_gcm_seal(
+            derive_key(passphrase, &salt, rounds),
+            nonce,
+            plaintext,
+            backup.clone(),
+        )
+        .map_err(|_| BackupError::EncryptionFailed)?;
+    backup.extend(ciphertext);
+
+    Ok(backup)
+}
+
+fn open(backup: &[u8], passphrase: &str) -> Result<BackupContents, BackupError> {
+    if backup.len() < HEADER_LEN || !backup.starts_with(MAGIC) {
+        return Err(BackupError::InvalidBackup);
+    }
+    let (header, ciphertext) = backup.split_at(HEADER_LEN);
+
+    let version = header[MAGIC.len()];
+    if version != VERSION {
+        return Err(BackupError::UnsupportedVersion(version));
+    }
+
+    let rest = &header[MAGIC.len() + 1..];
+    let (rounds, rest) = rest.split_at(4);
+    let (salt, nonce) = rest.split_at(SALT_LEN);
+    let rounds = u32::from_be_bytes(rounds.try_into().map_err(|_| BackupError::InvalidBackup)?);
+
+    let plaintext = crypto_provider::provider()
+        .aes_gcm_open(
+            derive_key(passphrase, salt, rounds),
+            nonce.to_vec(),
+            ciphertext.to_vec(),
+            header.to_vec(),
+        )
+        .map_err(|_| BackupError::DecryptionFailed)?;
+
+    serde_cbor::from_slice(&plaintext)
+        .map_err(|_| BackupError::VdcCollection(VdcCollectionError::DeserializeFailed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::CredentialFormat;
+    use crate::local_store::LocalStore;
+
+    use std::sync::Arc;
+
+    fn credential(payload: &[u8]) -> Credential {
+        Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::LdpVc,
+            r#type: CredentialType("UniversityDegreeCredential".into()),
+            payload: payload.to_vec(),
+            key_alias: None,
+            display: vec![],
+        }
+    }
+
+    #[test]
+    fn exports_and_imports_backups() {
+        let source = VdcCollection::new(Arc::new(LocalStore::new()));
+        let (first, second) = (credential(b"first"), credential(b"second"));
+        source.add(&first).unwrap();
+        source.add(&second).unwrap();
+        source.add_tag(first.id, "work".into()).unwrap();
+
+        let backup = source
+            .export_backup_with_rounds("passphrase", 1_000)
+            .unwrap();
+        assert!(!backup.windows(5).any(|w| w == b"first"));
+
+        assert!(matches!(
+            source.import_backup(backup.clone(), "wrong".into(), BackupConflictPolicy::Skip),
+            Err(BackupError::DecryptionFailed)
+        ));
+
+        let target = VdcCollection::new(Arc::new(LocalStore::new()));
+        let mut changed = second.clone();
+        changed.payload = b"changed".to_vec();
+        target.add(&changed).unwrap();
+
+        let summary = target
+            .import_backup(
+                backup.clone(),
+                "passphrase".into(),
+                BackupConflictPolicy::Skip,
+            )
+            .unwrap();
+        assert_eq!(
+            summary,
+            BackupImportSummary {
+                imported: 1,
+                replaced: 0,
+                skipped: 1,
+            }
+        );
+        assert_eq!(target.metadata(first.id).unwrap().tags, vec!["work"]);
+        assert_eq!(target.get(second.id).unwrap().unwrap().payload, b"changed");
+
+        let summary = target
+            .import_backup(backup, "passphrase".into(), BackupConflictPolicy::Replace)
+            .unwrap();
+        assert_eq!(summary.replaced, 2);
+        assert_eq!(target.get(second.id).unwrap().unwrap().payload, b"second");
+    }
+
+    #[test]
+    fn rejects_tampered_backups() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        vdc.add(&credential(b"first")).unwrap();
+        let backup = vdc.export_backup_with_rounds("passphrase", 1_000).unwrap();
+
+        // Changing the rounds changes the associated data.
+        let mut tampered = backup.clone();
+        tampered[MAGIC.len() + 4] ^= 1;
+        assert!(matches!(
+            open(&tampered, "passphrase"),
+            Err(BackupError::DecryptionFailed)
+        ));
+
+        let mut tampered = backup;
+        tampered[MAGIC.len()] = 2;
+        assert!(matches!(
+            open(&tampered, "passphrase"),
+            Err(BackupError::UnsupportedVersion(2))
+        ));
+
+        assert!(matches!(
+            open(b"not a backup", "passphrase"),
+            Err(BackupError::InvalidBackup)
+        ));
+    }
+}
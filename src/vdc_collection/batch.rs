@@ -0,0 +1,101 @@
+//! Credentials issued in batches, such as single-use mdocs.
+//!
+//! The instances of a batch are linked through the attributes of their
+//! metadata, so that the app can present a fresh instance every time.
+
+use super::{CredentialFilter, VdcCollection, VdcCollectionError};
+use crate::common::*;
+use crate::credential::Credential;
+
+/// The attribute holding the ID of the batch of a credential.
+pub const BATCH_ID_ATTRIBUTE: &str = "batch_id";
+/// The attribute holding the position of a credential in its batch.
+pub const BATCH_INDEX_ATTRIBUTE: &str = "batch_index";
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Add the instances of a batch of credentials, linking them to each other
+    /// with the batch ID, e.g. [crate::oid4vci::CredentialResponse::batch_id].
+    pub fn add_batch(
+        &self,
+        batch_id: Uuid,
+        credentials: Vec<Credential>,
+    ) -> Result<(), VdcCollectionError> {
+        for (index, credential) in credentials.iter().enumerate() {
+            let mut metadata = self.metadata(credential.id)?;
+            metadata
+                .attributes
+                .insert(BATCH_ID_ATTRIBUTE.into(), batch_id.to_string());
+            metadata
+                .attributes
+                .insert(BATCH_INDEX_ATTRIBUTE.into(), index.to_string());
+            self.add_with_metadata(credential, metadata)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the instances of the batch of a credential, in the order they were
+    /// issued, or only the credential when it was not issued in a batch.
+    pub fn batch(&self, id: Uuid) -> Result<Vec<Uuid>, VdcCollectionError> {
+        let Some(batch_id) = self.metadata(id)?.attributes.remove(BATCH_ID_ATTRIBUTE) else {
+            return Ok(vec![id]);
+        };
+
+        let mut instances = self
+            .query(CredentialFilter {
+                attributes: [(BATCH_ID_ATTRIBUTE.to_string(), batch_id)].into(),
+                ..Default::default()
+            })?
+            .into_iter()
+            .map(|id| {
+                let index = self
+                    .metadata(id)?
+                    .attributes
+                    .get(BATCH_INDEX_ATTRIBUTE)
+                    .and_then(|index| index.parse::<u32>().ok());
+                Ok((index, id))
+            })
+            .collect::<Result<Vec<_>, VdcCollectionError>>()?;
+        instances.sort();
+
+        Ok(instances.into_iter().map(|(_, id)| id).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::CredentialFormat;
+    use crate::local_store::LocalStore;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn links_batch_instances() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let credential = || Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: vec![],
+            key_alias: None,
+            display: vec![],
+        };
+
+        let batch = (0..3).map(|_| credential()).collect::<Vec<_>>();
+        let ids = batch
+            .iter()
+            .map(|credential| credential.id)
+            .collect::<Vec<_>>();
+        vdc.add_batch(Uuid::new_v4(), batch).unwrap();
+        let single = credential();
+        vdc.add(&single).unwrap();
+
+        assert_eq!(vdc.batch(ids[1]).unwrap(), ids);
+        assert_eq!(vdc.batch(single.id).unwrap(), vec![single.id]);
+
+        vdc.delete(ids[0]).unwrap();
+        assert_eq!(vdc.batch(ids[2]).unwrap(), ids[1..]);
+    }
+}
@@ -0,0 +1,233 @@
+//! Bulk operations on the collection, with all-or-nothing semantics.
+//!
+//! The changes of a bulk operation are staged in memory, and written to
+//! storage in a single pass once every credential has been processed. Keys
+//! written several times, such as the index entries, are written once, and a
+//! failure leaves the collection untouched.
+
+use super::{CollectionChange, VdcCollection, VdcCollectionError};
+use crate::common::*;
+use crate::credential::Credential;
+use crate::storage_manager::*;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// A storage layer buffering writes until they are committed.
+#[derive(Debug)]
+struct StagedStorage {
+    storage: Arc<dyn StorageManagerInterface>,
+    /// The staged values, `None` for removed keys.
+    staged: Mutex<HashMap<Key, Option<Vec<u8>>>>,
+}
+
+impl StagedStorage {
+    fn new(storage: Arc<dyn StorageManagerInterface>) -> Self {
+        Self {
+            storage,
+            staged: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn staged(&self) -> std::sync::MutexGuard<'_, HashMap<Key, Option<Vec<u8>>>> {
+        self.staged
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Write the staged changes to the underlying storage, restoring the
+    /// values written so far if one fails.
+    fn commit(&self) -> Result<(), StorageManagerError> {
+        let staged = std::mem::take(&mut *self.staged());
+        let mut applied = Vec::with_capacity(staged.len());
+
+        for (key, value) in staged {
+            let result = self.storage.get(key.clone()).and_then(|original| {
+                match value {
+                    Some(value) => self.storage.add(key.clone(), Value(value))?,
+                    None => self.storage.remove(key.clone())?,
+                }
+                Ok(original)
+            });
+
+            match result {
+                Ok(original) => applied.push((key, original)),
+                Err(e) => {
+                    self.rollback(applied);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rollback(&self, applied: Vec<(Key, Option<Value>)>) {
+        for (key, original) in applied.into_iter().rev() {
+            let _ = match original {
+                Some(value) => self.storage.add(key, value),
+                None => self.storage.remove(key),
+            };
+        }
+    }
+}
+
+impl StorageManagerInterface for StagedStorage {
+    fn add(&self, key: Key, value: Value) -> Result<(), StorageManagerError> {
+        self.staged().insert(key, Some(value.0));
+        Ok(())
+    }
+
+    fn get(&self, key: Key) -> Result<Option<Value>, StorageManagerError> {
+        if let Some(value) = self.staged().get(&key) {
+            return Ok(value.as_ref().map(|value| Value(value.clone())));
+        }
+        self.storage.get(key)
+    }
+
+    fn list(&self) -> Result<Vec<Key>, StorageManagerError> {
+        let staged = self.staged();
+        let mut keys = self
+            .storage
+            .list()?
+            .into_iter()
+            .filter(|key| !staged.contains_key(key))
+            .collect::<Vec<_>>();
+        keys.extend(
+            staged
+                .iter()
+                .filter(|(_, value)| value.is_some())
+                .map(|(key, _)| key.clone()),
+        );
+        Ok(keys)
+    }
+
+    fn remove(&self, key: Key) -> Result<(), StorageManagerError> {
+        self.staged().insert(key, None);
+        Ok(())
+    }
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Add credentials to the set, either all of them or none.
+    ///
+    /// Each credential is added as with [VdcCollection::add].
+    pub fn add_many(&self, credentials: Vec<Credential>) -> Result<(), VdcCollectionError> {
+        let ids = credentials
+            .iter()
+            .map(|credential| credential.id)
+            .collect::<Vec<_>>();
+
+        self.transaction(&ids, |staged| {
+            credentials
+                .iter()
+                .try_for_each(|credential| staged.add(credential))
+        })
+    }
+
+    /// Permanently remove credentials from the store, either all of them or
+    /// none.
+    pub fn delete_many(&self, ids: Vec<Uuid>) -> Result<(), VdcCollectionError> {
+        self.transaction(&[], |staged| {
+            ids.iter().try_for_each(|id| staged.delete(*id))
+        })
+    }
+}
+
+impl VdcCollection {
+    /// Apply changes to a staged copy of the collection, and commit them once
+    /// they all succeeded, notifying hooks and observers of the credentials
+    /// that were added or updated, and of those that were deleted.
+    fn transaction(
+        &self,
+        updated: &[Uuid],
+        apply: impl FnOnce(&VdcCollection) -> Result<(), VdcCollectionError>,
+    ) -> Result<(), VdcCollectionError> {
+        let storage = Arc::new(StagedStorage::new(self.storage.clone()));
+        let staged = VdcCollection::with_storage(storage.clone());
+        staged.set_duplicate_policy(
+            *self
+                .duplicate_policy
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+
+        let before = self.stored_entries()?.into_iter().collect::<HashSet<_>>();
+        apply(&staged)?;
+        let after = staged.stored_entries()?.into_iter().collect::<HashSet<_>>();
+
+        let mut deleted = vec![];
+        for id in before.difference(&after) {
+            deleted.push((*id, self.metadata(*id)?));
+        }
+
+        storage.commit().map_err(VdcCollectionError::StoreFailed)?;
+
+        for (id, metadata) in deleted {
+            self.notify_deleted(id, metadata);
+            self.notify_observers(CollectionChange::Deleted { id });
+        }
+        for id in updated.iter().filter(|id| after.contains(id)) {
+            if before.contains(id) {
+                self.notify_observers(CollectionChange::Updated { id: *id });
+            } else {
+                self.notify_added(*id, self.metadata(*id)?);
+                self.notify_observers(CollectionChange::Added { id: *id });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::CredentialFormat;
+    use crate::local_store::LocalStore;
+    use crate::vdc_collection::DuplicatePolicy;
+
+    fn credential(subject: &str) -> Credential {
+        let vc = serde_json::json!({
+            "@context": ["https://www.w3.org/2018/credentials/v1"],
+            "type": ["VerifiableCredential", "UniversityDegreeCredential"],
+            "issuer": "did:example:university",
+            "issuanceDate": "2024-01-01T00:00:00Z",
+            "credentialSubject": { "id": subject },
+        });
+
+        Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::LdpVc,
+            r#type: CredentialType("UniversityDegreeCredential".into()),
+            payload: serde_json::to_vec(&vc).unwrap(),
+            key_alias: None,
+            display: vec![],
+        }
+    }
+
+    #[test]
+    fn applies_bulk_operations_atomically() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let first = credential("did:example:alice");
+        let second = credential("did:example:bob");
+
+        vdc.add_many(vec![first.clone(), second.clone()]).unwrap();
+        assert_eq!(vdc.all_entries().unwrap().len(), 2);
+        assert!(vdc.index_entry(second.id).unwrap().is_some());
+
+        // The duplicate of the first credential fails the whole batch.
+        vdc.set_duplicate_policy(DuplicatePolicy::Reject);
+        let third = credential("did:example:carol");
+        assert!(matches!(
+            vdc.add_many(vec![third.clone(), credential("did:example:alice")]),
+            Err(VdcCollectionError::Duplicate(_))
+        ));
+        assert!(vdc.get(third.id).unwrap().is_none());
+
+        vdc.delete_many(vec![first.id, second.id]).unwrap();
+        assert!(vdc.all_entries().unwrap().is_empty());
+        assert!(vdc.index_entry(first.id).unwrap().is_none());
+    }
+}
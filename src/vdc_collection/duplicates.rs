@@ -0,0 +1,229 @@
+//! Detection of duplicate credentials, so that re-running an issuance flow
+//! does not silently store a near-identical copy of a credential.
+//!
+//! Two credentials are duplicates when they have the same format, type,
+//! issuer and subject claims, whatever their IDs, signatures and validity.
+
+use super::{CredentialMetadata, VdcCollection, VdcCollectionError, BATCH_ID_ATTRIBUTE};
+use crate::common::*;
+use crate::credential::{Credential, ParsedCredential};
+
+use serde_json::{Map, Value as Json};
+use sha2::{Digest, Sha256};
+
+/// The claims that change when a credential is reissued, which are not part
+/// of its fingerprint.
+const REISSUANCE_CLAIMS: &[&str] = &[
+    "iss", "iat", "nbf", "exp", "jti", "cnf", "status", "_sd_alg",
+];
+
+/// How a credential that duplicates a stored one is added.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, uniffi::Enum)]
+pub enum DuplicatePolicy {
+    /// Fail with [VdcCollectionError::Duplicate].
+    Reject,
+    /// Delete the stored duplicate, and add the credential.
+    Replace,
+    /// Add the credential alongside the stored duplicate.
+    #[default]
+    KeepBoth,
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Set how credentials that duplicate a stored one are added.
+    pub fn set_duplicate_policy(&self, policy: DuplicatePolicy) {
+        *self
+            .duplicate_policy
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = policy;
+    }
+
+    /// Get the ID of a stored credential the credential duplicates, if any.
+    pub fn find_duplicate(
+        &self,
+        credential: &Credential,
+    ) -> Result<Option<Uuid>, VdcCollectionError> {
+        let metadata = self.metadata(credential.id)?;
+        self.duplicate_of(credential, &metadata)
+    }
+}
+
+impl VdcCollection {
+    /// Apply the duplicate policy before adding a credential with the
+    /// metadata.
+    pub(crate) fn resolve_duplicate(
+        &self,
+        credential: &Credential,
+        metadata: &CredentialMetadata,
+    ) -> Result<(), VdcCollectionError> {
+        let policy = *self
+            .duplicate_policy
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if policy == DuplicatePolicy::KeepBoth {
+            return Ok(());
+        }
+
+        match self.duplicate_of(credential, metadata)? {
+            None => Ok(()),
+            Some(id) if policy == DuplicatePolicy::Reject => Err(VdcCollectionError::Duplicate(id)),
+            Some(id) => self.delete(id),
+        }
+    }
+
+    /// Get the ID of a stored credential with the same fingerprint.
+    ///
+    /// The instances of a batch are not duplicates of each other.
+    fn duplicate_of(
+        &self,
+        credential: &Credential,
+        metadata: &CredentialMetadata,
+    ) -> Result<Option<Uuid>, VdcCollectionError> {
+        let Some(fingerprint) = credential
+            .try_into_parsed()
+            .ok()
+            .and_then(|parsed| fingerprint(&parsed))
+        else {
+            return Ok(None);
+        };
+        let batch_id = metadata.attributes.get(BATCH_ID_ATTRIBUTE);
+
+        for id in self.stored_entries()? {
+            if id == credential.id {
+                continue;
+            }
+            let Some(entry) = self.index_entry(id)? else {
+                continue;
+            };
+            // Index entries written before fingerprints existed have none.
+            let stored = match entry.fingerprint {
+                Some(stored) => Some(stored),
+                None => self
+                    .get(id)?
+                    .and_then(|stored| stored.try_into_parsed().ok())
+                    .and_then(|parsed| self::fingerprint(&parsed)),
+            };
+            if stored.as_ref() != Some(&fingerprint) {
+                continue;
+            }
+            if batch_id.is_some()
+                && self.metadata(id)?.attributes.get(BATCH_ID_ATTRIBUTE) == batch_id
+            {
+                continue;
+            }
+            return Ok(Some(id));
+        }
+
+        Ok(None)
+    }
+}
+
+/// Hash the format, type, issuer and subject claims of a credential.
+///
+/// NOTE: mdocs are fingerprinted with every data element, including their
+/// issue and expiry dates, so reissued mdocs are not detected.
+pub(crate) fn fingerprint(credential: &ParsedCredential) -> Option<Vec<u8>> {
+    let json = credential.definition_json()?;
+    let subject = match json.get("credentialSubject") {
+        Some(subject) => subject.clone(),
+        None => match json {
+            Json::Object(mut claims) => {
+                for claim in REISSUANCE_CLAIMS {
+                    claims.remove(*claim);
+                }
+                Json::Object(claims)
+            }
+            json => json,
+        },
+    };
+
+    let value = serde_json::to_vec(&(
+        credential.format(),
+        credential.r#type(),
+        credential.issuer(),
+        canonicalize(subject),
+    ))
+    .ok()?;
+    Some(Sha256::digest(value).to_vec())
+}
+
+/// Sort the keys of every object, for the fingerprint not to depend on the
+/// order of the claims.
+fn canonicalize(json: Json) -> Json {
+    match json {
+        Json::Object(object) => {
+            let mut entries = object.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Json::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonicalize(value)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        Json::Array(array) => Json::Array(array.into_iter().map(canonicalize).collect()),
+        json => json,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::CredentialFormat;
+    use crate::local_store::LocalStore;
+
+    use std::sync::Arc;
+
+    fn credential(issuance_date: &str, name: &str) -> Credential {
+        let vc = serde_json::json!({
+            "@context": ["https://www.w3.org/2018/credentials/v1"],
+            "type": ["VerifiableCredential", "UniversityDegreeCredential"],
+            "issuer": "did:example:university",
+            "issuanceDate": issuance_date,
+            "credentialSubject": {
+                "id": "did:example:holder",
+                "name": name,
+            },
+        });
+
+        Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::LdpVc,
+            r#type: CredentialType("UniversityDegreeCredential".into()),
+            payload: serde_json::to_vec(&vc).unwrap(),
+            key_alias: None,
+            display: vec![],
+        }
+    }
+
+    #[test]
+    fn detects_duplicates() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let first = credential("2024-01-01T00:00:00Z", "Alice");
+        let reissued = credential("2025-01-01T00:00:00Z", "Alice");
+        let other = credential("2025-01-01T00:00:00Z", "Bob");
+
+        vdc.add(&first).unwrap();
+        assert_eq!(vdc.find_duplicate(&reissued).unwrap(), Some(first.id));
+        assert_eq!(vdc.find_duplicate(&other).unwrap(), None);
+
+        vdc.set_duplicate_policy(DuplicatePolicy::Reject);
+        assert!(matches!(
+            vdc.add(&reissued),
+            Err(VdcCollectionError::Duplicate(id)) if id == first.id
+        ));
+        vdc.add(&other).unwrap();
+        // Replacing a credential with the same ID is not a duplicate.
+        vdc.add(&first).unwrap();
+
+        vdc.set_duplicate_policy(DuplicatePolicy::Replace);
+        vdc.add(&reissued).unwrap();
+        assert!(vdc.get(first.id).unwrap().is_none());
+        assert!(vdc.get(reissued.id).unwrap().is_some());
+
+        vdc.set_duplicate_policy(DuplicatePolicy::KeepBoth);
+        vdc.add(&first).unwrap();
+        assert_eq!(vdc.all_entries().unwrap().len(), 3);
+    }
+}
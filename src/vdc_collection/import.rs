@@ -0,0 +1,293 @@
+//! Import of credentials provisioned out-of-band, e.g. through MDM, which are
+//! validated before they are stored since they were not received from their
+//! issuer through OID4VCI.
+//!
+//! NOTE: mdocs are imported from their CBOR encoded `IssuerSigned`, and the
+//! key binding of CWTs is not checked.
+
+use super::{VdcCollection, VdcCollectionError};
+use crate::common::*;
+use crate::credential::schema::CredentialSchemaCache;
+use crate::credential::verification::{CredentialVerificationOptions, VerificationCheck};
+use crate::credential::{Credential, CredentialFormat, ParsedCredential, ParsedCredentialInner};
+use crate::oid4vp::key_binding::same_public_key;
+use crate::signer::DeviceSigner;
+
+use std::sync::Arc;
+
+use base64::prelude::*;
+use serde_cbor::Value as Cbor;
+use serde_json::Value as Json;
+
+/// The labels of the x and y coordinates of EC2 COSE keys.
+const COSE_KEY_X: i128 = -2;
+const COSE_KEY_Y: i128 = -3;
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum CredentialImportError {
+    #[error("Failed to parse the credential: {0}")]
+    Parsing(String),
+    #[error("The credential is of type {found}, not {expected}")]
+    UnexpectedType { expected: String, found: String },
+    #[error("Failed to verify the credential: {0}")]
+    Verification(String),
+    #[error("The credential is not bound to the key: {0}")]
+    KeyBinding(String),
+    #[error("The credential violates its schema: {0}")]
+    Schema(String),
+    #[error(transparent)]
+    VdcCollection(#[from] VdcCollectionError),
+}
+
+/// How imported credentials are validated.
+#[derive(Debug, Clone, Default, uniffi::Record)]
+pub struct CredentialImportOptions {
+    /// What the signature, validity and status of the credential are
+    /// verified against.
+    pub verification: CredentialVerificationOptions,
+    /// The alias of the device key the credential is bound to. Required for
+    /// mdocs and bound credentials.
+    pub key_alias: Option<KeyAlias>,
+    /// The signer holding the key, to check that the credential is bound to
+    /// it. The key binding is not checked if unset.
+    pub signer: Option<Arc<dyn DeviceSigner>>,
+    /// The type the credential must be of. Required for CWTs, which do not
+    /// identify their type.
+    pub credential_type: Option<CredentialType>,
+    /// The cache the schemas the credential references are read from, or
+    /// fetched into. The credential is not validated against its schemas if
+    /// unset.
+    pub schema_cache: Option<Arc<CredentialSchemaCache>>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl VdcCollection {
+    /// Validate a credential provisioned out-of-band and store it, returning
+    /// its ID.
+    ///
+    /// The credential is rejected unless it parses as the format, is of the
+    /// expected type, passes [ParsedCredential::verify] and
+    /// [ParsedCredential::validate_schemas], and is bound to the key of the
+    /// signer.
+    pub async fn import_credential(
+        self: Arc<Self>,
+        raw: Vec<u8>,
+        format: CredentialFormat,
+        options: CredentialImportOptions,
+    ) -> Result<Uuid, CredentialImportError> {
+        let credential = parse(raw, format, &options)?;
+
+        if let Some(expected) = &options.credential_type {
+            let found = credential.r#type();
+            if &found != expected {
+                return Err(CredentialImportError::UnexpectedType {
+                    expected: expected.0.clone(),
+                    found: found.0,
+                });
+            }
+        }
+
+        let verification = credential.verify(options.verification.clone()).await;
+        for check in [
+            &verification.signature,
+            &verification.validity,
+            &verification.status,
+        ] {
+            if let VerificationCheck::Failed { reason } = check {
+                return Err(CredentialImportError::Verification(reason.clone()));
+            }
+        }
+
+        if let Some(cache) = &options.schema_cache {
+            for validation in credential.validate_schemas(cache.clone()).await {
+                if let VerificationCheck::Failed { reason } = validation.check {
+                    return Err(CredentialImportError::Schema(format!(
+                        "{}: {reason}",
+                        validation.schema_uri
+                    )));
+                }
+            }
+        }
+
+        check_key_binding(&credential, &options)?;
+
+        let credential = credential
+            .into_generic_form()
+            .map_err(|e| CredentialImportError::Parsing(format!("{e:?}")))?;
+        let id = credential.id;
+        self.add_async(credential).await?;
+        Ok(id)
+    }
+}
+
+/// Parse a raw credential of the format.
+fn parse(
+    raw: Vec<u8>,
+    format: CredentialFormat,
+    options: &CredentialImportOptions,
+) -> Result<Arc<ParsedCredential>, CredentialImportError> {
+    if matches!(format, CredentialFormat::MsoMdoc) {
+        let key_alias = options.key_alias.clone().ok_or_else(|| {
+            CredentialImportError::Parsing("mdocs require the alias of their device key".into())
+        })?;
+        let mdoc = crate::credential::mdoc::Mdoc::new_from_base64url_encoded_issuer_signed(
+            BASE64_URL_SAFE_NO_PAD.encode(raw),
+            key_alias,
+        )
+        .map_err(|e| CredentialImportError::Parsing(format!("{e:?}")))?;
+        return Ok(ParsedCredential::new_mso_mdoc(mdoc));
+    }
+
+    Credential {
+        id: Uuid::new_v4(),
+        format,
+        r#type: options
+            .credential_type
+            .clone()
+            .unwrap_or_else(|| CredentialType(String::new())),
+        payload: raw,
+        key_alias: options.key_alias.clone(),
+        display: vec![],
+    }
+    .try_into_parsed()
+    .map_err(|e| CredentialImportError::Parsing(format!("{e:?}")))
+}
+
+/// Check that a credential is bound to the key of the signer, if any.
+fn check_key_binding(
+    credential: &ParsedCredential,
+    options: &CredentialImportOptions,
+) -> Result<(), CredentialImportError> {
+    let cnf = credential
+        .claims_as_json()
+        .and_then(|claims| claims.get("cnf").cloned());
+    if cnf.is_some() && options.key_alias.is_none() {
+        return Err(CredentialImportError::KeyBinding(
+            "the credential is bound to a key, but no key alias was provided".into(),
+        ));
+    }
+    let (Some(key_alias), Some(signer)) = (&options.key_alias, &options.signer) else {
+        return Ok(());
+    };
+
+    let jwk: Json = signer
+        .jwk(key_alias.clone())
+        .map_err(|e| format!("{e:?}"))
+        .and_then(|jwk| serde_json::from_str(&jwk).map_err(|e| format!("{e:?}")))
+        .map_err(CredentialImportError::KeyBinding)?;
+    let bound = match &credential.inner {
+        ParsedCredentialInner::MsoMdoc(mdoc) => {
+            serde_cbor::value::to_value(&mdoc.document().mso.device_key_info.device_key)
+                .is_ok_and(|device_key| same_cose_key(&device_key, &jwk))
+        }
+        ParsedCredentialInner::Cwt(_) => true,
+        _ => match &cnf {
+            Some(cnf) => cnf.get("jwk").is_some_and(|cnf| same_public_key(cnf, &jwk)),
+            None => true,
+        },
+    };
+
+    if !bound {
+        return Err(CredentialImportError::KeyBinding(
+            "the key of the credential is not held by the device signer".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check whether an EC2 COSE key and a JWK are of the same public key.
+fn same_cose_key(cose_key: &Cbor, jwk: &Json) -> bool {
+    let Cbor::Map(cose_key) = cose_key else {
+        return false;
+    };
+    let coordinate = |label, name: &str| {
+        let cose = match cose_key.get(&Cbor::Integer(label)) {
+            Some(Cbor::Bytes(bytes)) => bytes.clone(),
+            _ => return false,
+        };
+        jwk[name]
+            .as_str()
+            .and_then(|value| BASE64_URL_SAFE_NO_PAD.decode(value).ok())
+            .is_some_and(|value| value == cose)
+    };
+    coordinate(COSE_KEY_X, "x") && coordinate(COSE_KEY_Y, "y")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+    use crate::oid4vp::key_binding::tests::TestSigner;
+
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn validates_imported_credentials() {
+        let issuer = TestSigner(SigningKey::from_slice(&[1; 32]).unwrap());
+        let holder = TestSigner(SigningKey::from_slice(&[2; 32]).unwrap());
+        let encode = |value: Json| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let signing_input = format!(
+            "{}.{}",
+            encode(json!({ "alg": "ES256", "typ": "JWT" })),
+            encode(json!({
+                "iss": "did:example:issuer",
+                "cnf": { "jwk": holder.jwk() },
+                "vc": {
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiableCredential", "EmployeeCredential"],
+                    "issuer": "did:example:issuer",
+                    "issuanceDate": "2010-01-01T00:00:00Z",
+                    "credentialSubject": { "id": "did:example:holder" }
+                }
+            })),
+        );
+        let signature: Signature = issuer.0.sign(signing_input.as_bytes());
+        let jws = format!(
+            "{signing_input}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        );
+
+        let vdc = Arc::new(VdcCollection::new(Arc::new(LocalStore::new())));
+        let options = |issuer_key: &TestSigner, signer: TestSigner| CredentialImportOptions {
+            verification: CredentialVerificationOptions {
+                issuer_keys: [("did:example:issuer".into(), issuer_key.jwk().to_string())].into(),
+                ..Default::default()
+            },
+            key_alias: Some(KeyAlias("key".into())),
+            signer: Some(Arc::new(signer)),
+            credential_type: None,
+            schema_cache: None,
+        };
+        let import = |options| {
+            vdc.clone()
+                .import_credential(jws.clone().into(), CredentialFormat::JwtVcJson, options)
+        };
+
+        let other = || TestSigner(SigningKey::from_slice(&[3; 32]).unwrap());
+        assert!(matches!(
+            import(options(&other(), TestSigner(holder.0.clone()))).await,
+            Err(CredentialImportError::Verification(_))
+        ));
+        assert!(matches!(
+            import(options(&issuer, other())).await,
+            Err(CredentialImportError::KeyBinding(_))
+        ));
+        assert!(matches!(
+            import(CredentialImportOptions {
+                credential_type: Some(CredentialType("mDL".into())),
+                ..options(&issuer, TestSigner(holder.0.clone()))
+            })
+            .await,
+            Err(CredentialImportError::UnexpectedType { .. })
+        ));
+        assert!(vdc.all_entries().unwrap().is_empty());
+
+        let id = import(options(&issuer, TestSigner(holder.0.clone())))
+            .await
+            .unwrap();
+        let stored = vdc.get(id).unwrap().unwrap();
+        assert_eq!(stored.format, CredentialFormat::JwtVcJson);
+        assert_eq!(stored.key_alias, Some(KeyAlias("key".into())));
+    }
+}
@@ -0,0 +1,480 @@
+use super::{duplicates, CredentialUsage, VdcCollection, VdcCollectionError};
+use crate::common::*;
+use crate::credential::{disclosure, Credential, CredentialFormat, ParsedCredential};
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+/// Internal prefix for credential index keys.
+const INDEX_KEY_PREFIX: &str = "CredentialIndex.";
+
+/// The indexed attributes of a stored credential, which can be queried
+/// without parsing the credential.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct IndexEntry {
+    pub(crate) format: CredentialFormat,
+    pub(crate) r#type: CredentialType,
+    pub(crate) issuer: Option<String>,
+    /// The display name of the credential, if the issuer provided one.
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+    /// When the credential was added, unknown for credentials stored before
+    /// the index existed.
+    #[serde(default)]
+    pub(crate) added_at: Option<SystemTime>,
+    #[serde(default)]
+    pub(crate) expires_at: Option<SystemTime>,
+    /// The profile of the credential, the default profile when unset.
+    #[serde(default)]
+    pub(crate) profile: Option<String>,
+    /// The fingerprint duplicates are detected with.
+    #[serde(default)]
+    pub(crate) fingerprint: Option<Vec<u8>>,
+}
+
+impl IndexEntry {
+    pub(crate) fn new(credential: &Credential, added_at: Option<SystemTime>) -> Self {
+        let parsed = credential.try_into_parsed().ok();
+
+        Self {
+            format: credential.format.clone(),
+            r#type: credential.r#type.clone(),
+            issuer: parsed.as_ref().and_then(|parsed| parsed.issuer()),
+            name: credential
+                .display
+                .first()
+                .map(|display| display.name.clone()),
+            added_at,
+            expires_at: parsed
+                .as_ref()
+                .and_then(|parsed| parsed.validity().expires_at),
+            profile: None,
+            fingerprint: parsed.and_then(|parsed| duplicates::fingerprint(&parsed)),
+        }
+    }
+}
+
+/// A lightweight summary of a stored credential, which is read from the index
+/// without parsing the credential.
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct CredentialSummary {
+    pub id: Uuid,
+    pub format: CredentialFormat,
+    pub r#type: CredentialType,
+    pub issuer: Option<String>,
+    /// The display name of the credential, if the issuer provided one.
+    pub name: Option<String>,
+    /// When the credential was added, if known.
+    pub added_at: Option<SystemTime>,
+    pub expires_at: Option<SystemTime>,
+    pub tags: Vec<String>,
+    /// How many times the credential was presented.
+    pub use_count: u32,
+    /// When the credential was last presented, if ever.
+    pub last_used_at: Option<SystemTime>,
+}
+
+impl CredentialSummary {
+    fn new(id: Uuid, entry: IndexEntry, tags: Vec<String>, usage: CredentialUsage) -> Self {
+        Self {
+            id,
+            format: entry.format,
+            r#type: entry.r#type,
+            issuer: entry.issuer,
+            name: entry.name,
+            added_at: entry.added_at,
+            expires_at: entry.expires_at,
+            tags,
+            use_count: usage.use_count,
+            last_used_at: usage.last_used_at,
+        }
+    }
+}
+
+/// The attribute stored credentials are sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum CredentialSortKey {
+    AddedAt,
+    Type,
+    Issuer,
+    ExpiresAt,
+    LastUsedAt,
+    UseCount,
+}
+
+/// The order stored credentials are listed in.
+///
+/// Credentials missing the attribute are listed last, in either direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Record)]
+pub struct CredentialSort {
+    pub key: CredentialSortKey,
+    pub descending: bool,
+}
+
+impl CredentialSort {
+    fn compare(&self, a: &CredentialSummary, b: &CredentialSummary) -> Ordering {
+        fn last_if_missing<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) if descending => b.cmp(&a),
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+
+        let ordering = match self.key {
+            CredentialSortKey::AddedAt => last_if_missing(a.added_at, b.added_at, self.descending),
+            CredentialSortKey::Type => {
+                last_if_missing(Some(&a.r#type.0), Some(&b.r#type.0), self.descending)
+            }
+            CredentialSortKey::Issuer => {
+                last_if_missing(a.issuer.as_ref(), b.issuer.as_ref(), self.descending)
+            }
+            CredentialSortKey::ExpiresAt => {
+                last_if_missing(a.expires_at, b.expires_at, self.descending)
+            }
+            CredentialSortKey::LastUsedAt => {
+                last_if_missing(a.last_used_at, b.last_used_at, self.descending)
+            }
+            CredentialSortKey::UseCount => {
+                last_if_missing(Some(a.use_count), Some(b.use_count), self.descending)
+            }
+        };
+
+        // Keep the order stable across pages.
+        ordering.then_with(|| a.id.cmp(&b.id))
+    }
+}
+
+/// A filter on the value of a claim.
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct ClaimFilter {
+    /// A JSONPath selecting the claim, e.g. `$.credentialSubject.degree.type`.
+    ///
+    /// Mdoc claims are selected by `$['<namespace>']['<element>']`.
+    pub path: String,
+    /// The JSON encoded value the claim must have. Values that are not valid
+    /// JSON are compared as strings.
+    pub value: String,
+}
+
+impl ClaimFilter {
+    fn matches(&self, json: &Json) -> bool {
+        let expected =
+            serde_json::from_str(&self.value).unwrap_or_else(|_| Json::String(self.value.clone()));
+
+        disclosure::select_path(json, &self.path)
+            .iter()
+            .filter_map(|pointer| disclosure::value_at(json, pointer))
+            .any(|value| value == &expected)
+    }
+}
+
+/// A filter on the stored credentials.
+///
+/// Every criteria that is set must match. Empty lists match any credential.
+#[derive(Debug, Clone, Default, PartialEq, uniffi::Record)]
+pub struct CredentialFilter {
+    pub types: Vec<CredentialType>,
+    pub formats: Vec<CredentialFormat>,
+    pub issuer: Option<String>,
+    pub claims: Vec<ClaimFilter>,
+    /// Tags the credential must have.
+    pub tags: Vec<String>,
+    /// Attributes the credential must have, with the given values.
+    pub attributes: HashMap<String, String>,
+}
+
+impl CredentialFilter {
+    fn matches_entry(&self, entry: &IndexEntry) -> bool {
+        (self.types.is_empty() || self.types.contains(&entry.r#type))
+            && (self.formats.is_empty() || self.formats.contains(&entry.format))
+            && match &self.issuer {
+                Some(issuer) => entry.issuer.as_ref() == Some(issuer),
+                None => true,
+            }
+    }
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Get a list of the credentials matching a filter.
+    ///
+    /// The type, format and issuer are matched against an index, so that only
+    /// the credentials matching them are loaded to match the claims.
+    pub fn query(&self, filter: CredentialFilter) -> Result<Vec<Uuid>, VdcCollectionError> {
+        let mut ids = Vec::new();
+
+        for id in self.all_entries()? {
+            match self.index_entry(id)? {
+                Some(entry) if filter.matches_entry(&entry) => {}
+                _ => continue,
+            }
+
+            if !(filter.tags.is_empty() && filter.attributes.is_empty())
+                && !self.metadata(id)?.matches(&filter.tags, &filter.attributes)
+            {
+                continue;
+            }
+
+            if !filter.claims.is_empty() {
+                let claims = self
+                    .get(id)?
+                    .and_then(|credential| credential.try_into_parsed().ok())
+                    .and_then(|parsed| parsed.definition_json());
+                let Some(claims) = claims else {
+                    continue;
+                };
+                if !filter.claims.iter().all(|claim| claim.matches(&claims)) {
+                    continue;
+                }
+            }
+
+            ids.push(id);
+        }
+
+        Ok(ids)
+    }
+
+    /// Get a page of summaries of the stored credentials, in the given order.
+    ///
+    /// The summaries are read from the index, so that credentials are only
+    /// parsed once they are retrieved with [VdcCollection::get_parsed].
+    pub fn list(
+        &self,
+        offset: u32,
+        limit: u32,
+        sort: CredentialSort,
+    ) -> Result<Vec<CredentialSummary>, VdcCollectionError> {
+        let mut summaries = Vec::new();
+        for id in self.all_entries()? {
+            if let Some(entry) = self.index_entry(id)? {
+                summaries.push(CredentialSummary::new(
+                    id,
+                    entry,
+                    self.metadata(id)?.tags,
+                    self.usage(id)?,
+                ));
+            }
+        }
+
+        summaries.sort_by(|a, b| sort.compare(a, b));
+
+        Ok(summaries
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    /// Get the formats of the credentials in the active profile, from the
+    /// index.
+    pub fn formats(&self) -> Result<Vec<CredentialFormat>, VdcCollectionError> {
+        let mut formats = Vec::new();
+        for id in self.all_entries()? {
+            if let Some(entry) = self.index_entry(id)? {
+                if !formats.contains(&entry.format) {
+                    formats.push(entry.format);
+                }
+            }
+        }
+        Ok(formats)
+    }
+
+    /// Get a credential from the store, parsed as its known variant.
+    pub fn get_parsed(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<Arc<ParsedCredential>>, VdcCollectionError> {
+        self.get(id)?
+            .map(|credential| {
+                credential
+                    .try_into_parsed()
+                    .map_err(|_| VdcCollectionError::DeserializeFailed)
+            })
+            .transpose()
+    }
+}
+
+impl VdcCollection {
+    /// Convert a UUID to an index key.
+    fn id_to_index_key(id: Uuid) -> Key {
+        Key(format!("{}{}", INDEX_KEY_PREFIX, id))
+    }
+
+    /// Write the index entry of a credential.
+    pub(crate) fn write_index_entry(
+        &self,
+        id: Uuid,
+        entry: &IndexEntry,
+    ) -> Result<(), VdcCollectionError> {
+        let value = serde_cbor::to_vec(entry).map_err(|_| VdcCollectionError::SerializeFailed)?;
+
+        self.storage
+            .add(Self::id_to_index_key(id), Value(value))
+            .map_err(VdcCollectionError::StoreFailed)
+    }
+
+    /// Remove the index entry of a credential.
+    pub(crate) fn remove_index_entry(&self, id: Uuid) -> Result<(), VdcCollectionError> {
+        self.storage
+            .remove(Self::id_to_index_key(id))
+            .map_err(VdcCollectionError::DeleteFailed)
+    }
+
+    /// Get the index entry of a credential.
+    ///
+    /// Credentials stored before the index existed are indexed on first use.
+    pub(crate) fn index_entry(&self, id: Uuid) -> Result<Option<IndexEntry>, VdcCollectionError> {
+        let raw = self
+            .storage
+            .get(Self::id_to_index_key(id))
+            .map_err(VdcCollectionError::LoadFailed)?;
+
+        if let Some(entry) = raw.and_then(|raw| serde_cbor::from_slice(&raw.0).ok()) {
+            return Ok(Some(entry));
+        }
+
+        let Some(credential) = self.get(id)? else {
+            return Ok(None);
+        };
+        let entry = IndexEntry::new(&credential, None);
+        self.write_index_entry(id, &entry)?;
+
+        Ok(Some(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+
+    fn credential(format: CredentialFormat, r#type: &str) -> Credential {
+        Credential {
+            id: Uuid::new_v4(),
+            format,
+            r#type: CredentialType(r#type.into()),
+            payload: vec![],
+            key_alias: None,
+            display: vec![],
+        }
+    }
+
+    #[test]
+    fn queries_the_index() {
+        let storage = Arc::new(LocalStore::new());
+        let vdc = VdcCollection::new(storage.clone());
+
+        let mdl = credential(CredentialFormat::MsoMdoc, "org.iso.18013.5.1.mDL");
+        let degree = credential(CredentialFormat::LdpVc, "UniversityDegreeCredential");
+        vdc.add(&mdl).unwrap();
+        vdc.add(&degree).unwrap();
+
+        // A credential stored before the index existed.
+        let legacy = credential(CredentialFormat::JwtVcJson, "UniversityDegreeCredential");
+        storage
+            .add(
+                VdcCollection::id_to_key(legacy.id),
+                Value(serde_cbor::to_vec(&legacy).unwrap()),
+            )
+            .unwrap();
+
+        let query = |filter: CredentialFilter| {
+            let mut ids = vdc.query(filter).unwrap();
+            ids.sort();
+            ids
+        };
+        let sorted = |mut ids: Vec<Uuid>| {
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(vdc.all_entries().unwrap().len(), 3);
+        assert_eq!(
+            query(CredentialFilter {
+                types: vec![CredentialType("UniversityDegreeCredential".into())],
+                ..Default::default()
+            }),
+            sorted(vec![degree.id, legacy.id])
+        );
+        assert_eq!(
+            query(CredentialFilter {
+                formats: vec![CredentialFormat::MsoMdoc, CredentialFormat::LdpVc],
+                ..Default::default()
+            }),
+            sorted(vec![mdl.id, degree.id])
+        );
+        assert_eq!(
+            query(CredentialFilter {
+                issuer: Some("did:example:issuer".into()),
+                ..Default::default()
+            }),
+            vec![]
+        );
+
+        vdc.delete(degree.id).unwrap();
+        assert!(vdc.index_entry(degree.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn lists_pages_of_summaries() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        for r#type in ["b", "c", "a"] {
+            vdc.add(&credential(CredentialFormat::LdpVc, r#type))
+                .unwrap();
+        }
+
+        let page = |offset, limit, descending| {
+            let sort = CredentialSort {
+                key: CredentialSortKey::Type,
+                descending,
+            };
+            vdc.list(offset, limit, sort)
+                .unwrap()
+                .into_iter()
+                .map(|summary| summary.r#type.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(page(0, 2, false), vec!["a", "b"]);
+        assert_eq!(page(2, 2, false), vec!["c"]);
+        assert_eq!(page(0, 10, true), vec!["c", "b", "a"]);
+        assert_eq!(page(3, 10, true), Vec::<String>::new());
+
+        let summaries = vdc
+            .list(
+                0,
+                10,
+                CredentialSort {
+                    key: CredentialSortKey::AddedAt,
+                    descending: true,
+                },
+            )
+            .unwrap();
+        assert!(summaries.iter().all(|summary| summary.added_at.is_some()));
+    }
+
+    #[test]
+    fn matches_claims() {
+        let claims = serde_json::json!({
+            "credentialSubject": { "degree": { "type": "BachelorDegree" }, "age": 21 }
+        });
+        let filter = |path: &str, value: &str| ClaimFilter {
+            path: path.into(),
+            value: value.into(),
+        };
+
+        assert!(filter("$.credentialSubject.degree.type", "BachelorDegree").matches(&claims));
+        assert!(filter("$.credentialSubject.degree.type", "\"BachelorDegree\"").matches(&claims));
+        assert!(filter("$.credentialSubject.age", "21").matches(&claims));
+        assert!(!filter("$.credentialSubject.age", "22").matches(&claims));
+        assert!(!filter("$.credentialSubject.name", "21").matches(&claims));
+    }
+}
@@ -0,0 +1,144 @@
+//! Hooks notified when credentials are added to, or deleted from, the
+//! collection, e.g. to notify their issuer.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::{CollectionChange, CredentialMetadata, VdcCollection, VdcCollectionError};
+use crate::common::*;
+use crate::credential::Credential;
+
+/// Interface: CredentialLifecycleHook
+///
+/// The CredentialLifecycleHook is called after a credential is added to the
+/// collection, and after it is permanently deleted, with the metadata it was
+/// stored with.
+///
+/// Hooks are called synchronously, and should not block.
+#[uniffi::export(with_foreign)]
+pub trait CredentialLifecycleHook: Send + Sync + Debug {
+    fn credential_added(&self, id: Uuid, metadata: CredentialMetadata);
+
+    fn credential_deleted(&self, id: Uuid, metadata: CredentialMetadata);
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Register a hook, called on every following addition and deletion.
+    pub fn add_lifecycle_hook(&self, hook: Arc<dyn CredentialLifecycleHook>) {
+        self.lifecycle_hooks
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(hook);
+    }
+
+    /// Add a credential to the set, along with its metadata.
+    ///
+    /// Unlike adding the metadata afterwards, lifecycle hooks receive the
+    /// metadata of the credential.
+    pub fn add_with_metadata(
+        &self,
+        credential: &Credential,
+        metadata: CredentialMetadata,
+    ) -> Result<(), VdcCollectionError> {
+        let id = credential.id;
+        self.resolve_duplicate(credential, &metadata)?;
+        let added = self.store(credential)?;
+        self.write_metadata(id, &metadata)?;
+        if added {
+            self.notify_added(id, metadata);
+            self.notify_observers(CollectionChange::Added { id });
+        } else {
+            self.notify_observers(CollectionChange::Updated { id });
+        }
+        Ok(())
+    }
+}
+
+impl VdcCollection {
+    fn hooks(&self) -> Vec<Arc<dyn CredentialLifecycleHook>> {
+        self.lifecycle_hooks
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    pub(crate) fn notify_added(&self, id: Uuid, metadata: CredentialMetadata) {
+        for hook in self.hooks() {
+            hook.credential_added(id, metadata.clone());
+        }
+    }
+
+    pub(crate) fn notify_deleted(&self, id: Uuid, metadata: CredentialMetadata) {
+        for hook in self.hooks() {
+            hook.credential_deleted(id, metadata.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::CredentialFormat;
+    use crate::local_store::LocalStore;
+
+    use std::sync::Mutex;
+
+    /// A hook recording the events it receives.
+    #[derive(Debug, Default)]
+    struct TestHook(Mutex<Vec<(&'static str, Uuid, CredentialMetadata)>>);
+
+    impl CredentialLifecycleHook for TestHook {
+        fn credential_added(&self, id: Uuid, metadata: CredentialMetadata) {
+            self.0.lock().unwrap().push(("added", id, metadata));
+        }
+
+        fn credential_deleted(&self, id: Uuid, metadata: CredentialMetadata) {
+            self.0.lock().unwrap().push(("deleted", id, metadata));
+        }
+    }
+
+    #[test]
+    fn calls_lifecycle_hooks() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let hook = Arc::new(TestHook::default());
+        vdc.add_lifecycle_hook(hook.clone());
+
+        let credential = Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: vec![],
+            key_alias: None,
+            display: vec![],
+        };
+        let metadata = CredentialMetadata {
+            tags: vec!["work".into()],
+            ..Default::default()
+        };
+        vdc.add_with_metadata(&credential, metadata.clone())
+            .unwrap();
+        // Replacing a credential does not add it again.
+        vdc.add(&credential).unwrap();
+        vdc.delete(credential.id).unwrap();
+
+        let other = Credential {
+            id: Uuid::new_v4(),
+            ..credential.clone()
+        };
+        vdc.add(&other).unwrap();
+        vdc.trash(other.id).unwrap();
+        vdc.purge().unwrap();
+
+        let events = hook.0.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                ("added", credential.id, metadata.clone()),
+                ("deleted", credential.id, metadata),
+                ("added", other.id, CredentialMetadata::default()),
+                ("deleted", other.id, CredentialMetadata::default()),
+            ]
+        );
+    }
+}
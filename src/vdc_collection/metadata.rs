@@ -0,0 +1,197 @@
+use super::{CollectionChange, VdcCollection, VdcCollectionError};
+use crate::common::*;
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Internal prefix for credential metadata keys.
+const METADATA_KEY_PREFIX: &str = "CredentialMetadata.";
+
+/// User defined metadata attached to a stored credential.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct CredentialMetadata {
+    /// Tags, such as `work` or `favorite`, in the order they were added.
+    pub tags: Vec<String>,
+    /// Arbitrary key/value attributes.
+    pub attributes: HashMap<String, String>,
+}
+
+impl CredentialMetadata {
+    /// Check whether the metadata has every tag and attribute.
+    pub(crate) fn matches(&self, tags: &[String], attributes: &HashMap<String, String>) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+            && attributes
+                .iter()
+                .all(|(key, value)| self.attributes.get(key) == Some(value))
+    }
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Get the metadata attached to a credential.
+    pub fn metadata(&self, id: Uuid) -> Result<CredentialMetadata, VdcCollectionError> {
+        let Some(raw) = self
+            .storage
+            .get(Self::id_to_metadata_key(id))
+            .map_err(VdcCollectionError::LoadFailed)?
+        else {
+            return Ok(CredentialMetadata::default());
+        };
+
+        serde_cbor::from_slice(&raw.0).map_err(|_| VdcCollectionError::DeserializeFailed)
+    }
+
+    /// Replace the metadata attached to a credential.
+    pub fn set_metadata(
+        &self,
+        id: Uuid,
+        metadata: CredentialMetadata,
+    ) -> Result<(), VdcCollectionError> {
+        if self.get(id)?.is_none() {
+            return Err(VdcCollectionError::NotFound(id));
+        }
+
+        self.write_metadata(id, &metadata)?;
+        self.notify_observers(CollectionChange::Updated { id });
+        Ok(())
+    }
+
+    /// Add a tag to a credential, if it does not have it already.
+    pub fn add_tag(&self, id: Uuid, tag: String) -> Result<(), VdcCollectionError> {
+        let mut metadata = self.metadata(id)?;
+        if !metadata.tags.contains(&tag) {
+            metadata.tags.push(tag);
+        }
+        self.set_metadata(id, metadata)
+    }
+
+    /// Remove a tag from a credential.
+    pub fn remove_tag(&self, id: Uuid, tag: String) -> Result<(), VdcCollectionError> {
+        let mut metadata = self.metadata(id)?;
+        metadata.tags.retain(|t| t != &tag);
+        self.set_metadata(id, metadata)
+    }
+
+    /// Set an attribute of a credential, replacing any previous value.
+    pub fn set_attribute(
+        &self,
+        id: Uuid,
+        key: String,
+        value: String,
+    ) -> Result<(), VdcCollectionError> {
+        let mut metadata = self.metadata(id)?;
+        metadata.attributes.insert(key, value);
+        self.set_metadata(id, metadata)
+    }
+
+    /// Remove an attribute of a credential.
+    pub fn remove_attribute(&self, id: Uuid, key: String) -> Result<(), VdcCollectionError> {
+        let mut metadata = self.metadata(id)?;
+        metadata.attributes.remove(&key);
+        self.set_metadata(id, metadata)
+    }
+
+    /// Get every tag used on the stored credentials, sorted alphabetically.
+    pub fn all_tags(&self) -> Result<Vec<String>, VdcCollectionError> {
+        let mut tags = Vec::new();
+        for id in self.all_entries()? {
+            tags.extend(self.metadata(id)?.tags);
+        }
+        tags.sort();
+        tags.dedup();
+        Ok(tags)
+    }
+}
+
+impl VdcCollection {
+    /// Convert a UUID to a metadata key.
+    fn id_to_metadata_key(id: Uuid) -> Key {
+        Key(format!("{}{}", METADATA_KEY_PREFIX, id))
+    }
+
+    /// Write the metadata attached to a credential, without notifying
+    /// observers.
+    pub(crate) fn write_metadata(
+        &self,
+        id: Uuid,
+        metadata: &CredentialMetadata,
+    ) -> Result<(), VdcCollectionError> {
+        let value =
+            serde_cbor::to_vec(metadata).map_err(|_| VdcCollectionError::SerializeFailed)?;
+
+        self.storage
+            .add(Self::id_to_metadata_key(id), Value(value))
+            .map_err(VdcCollectionError::StoreFailed)
+    }
+
+    /// Remove the metadata attached to a credential.
+    pub(crate) fn remove_metadata(&self, id: Uuid) -> Result<(), VdcCollectionError> {
+        self.storage
+            .remove(Self::id_to_metadata_key(id))
+            .map_err(VdcCollectionError::DeleteFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{Credential, CredentialFormat};
+    use crate::local_store::LocalStore;
+    use crate::vdc_collection::CredentialFilter;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn tags_and_queries_credentials() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let ids = (0..2)
+            .map(|_| {
+                let credential = Credential {
+                    id: Uuid::new_v4(),
+                    format: CredentialFormat::LdpVc,
+                    r#type: CredentialType("UniversityDegreeCredential".into()),
+                    payload: vec![],
+                    key_alias: None,
+                    display: vec![],
+                };
+                vdc.add(&credential).unwrap();
+                credential.id
+            })
+            .collect::<Vec<_>>();
+
+        vdc.add_tag(ids[0], "work".into()).unwrap();
+        vdc.add_tag(ids[0], "work".into()).unwrap();
+        vdc.add_tag(ids[1], "favorite".into()).unwrap();
+        vdc.set_attribute(ids[1], "note".into(), "diploma".into())
+            .unwrap();
+
+        assert_eq!(vdc.metadata(ids[0]).unwrap().tags, vec!["work"]);
+        assert_eq!(vdc.all_tags().unwrap(), vec!["favorite", "work"]);
+
+        let query = |tags: &[&str], attributes: &[(&str, &str)]| {
+            vdc.query(CredentialFilter {
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                attributes: attributes
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        assert_eq!(query(&["work"], &[]), vec![ids[0]]);
+        assert_eq!(query(&[], &[("note", "diploma")]), vec![ids[1]]);
+        assert_eq!(query(&["work"], &[("note", "diploma")]), vec![]);
+
+        vdc.remove_tag(ids[0], "work".into()).unwrap();
+        assert_eq!(query(&["work"], &[]), vec![]);
+
+        vdc.delete(ids[1]).unwrap();
+        assert_eq!(vdc.metadata(ids[1]).unwrap(), CredentialMetadata::default());
+        assert!(matches!(
+            vdc.add_tag(ids[1], "work".into()),
+            Err(VdcCollectionError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,438 @@
+mod backup;
+mod batch;
+mod bulk;
+mod duplicates;
+mod import;
+mod index;
+mod lifecycle;
+mod metadata;
+mod non_blocking;
+mod observer;
+mod pool;
+mod profile;
+mod sync;
+mod trash;
+mod usage;
+
+pub use backup::{BackupConflictPolicy, BackupError, BackupImportSummary};
+pub use batch::{BATCH_ID_ATTRIBUTE, BATCH_INDEX_ATTRIBUTE};
+pub use duplicates::DuplicatePolicy;
+pub use import::{CredentialImportError, CredentialImportOptions};
+pub use lifecycle::CredentialLifecycleHook;
+pub use metadata::CredentialMetadata;
+pub use observer::{CollectionChange, CollectionObserver};
+pub use pool::{PoolStatus, DEFAULT_POOL_LOW_THRESHOLD, POOL_CONSUMED_ATTRIBUTE};
+pub use profile::DEFAULT_PROFILE;
+pub use sync::{
+    CollectionSync, SyncBlob, SyncConflictPolicy, SyncError, SyncSummary, SyncTransport,
+};
+pub use trash::TrashedCredentialSummary;
+pub use usage::CredentialUsage;
+
+pub use index::{
+    ClaimFilter, CredentialFilter, CredentialSort, CredentialSortKey, CredentialSummary,
+};
+
+use std::sync::{atomic::AtomicU64, Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::common::*;
+use crate::credential::Credential;
+use crate::encrypted_storage::{EncryptedStorage, KeyProvider};
+use crate::local_store::LocalStore;
+use crate::storage_manager::*;
+use index::IndexEntry;
+
+use thiserror::Error;
+use tracing::info;
+
+/// Internal prefix for credential keys.
+const KEY_PREFIX: &str = "Credential.";
+
+#[derive(uniffi::Object)]
+/// Verifiable Digital Credential Collection
+///
+/// This is the main interface to credentials.
+#[derive(Debug)]
+pub struct VdcCollection {
+    storage: Arc<dyn StorageManagerInterface>,
+    /// The time a trashed credential can be restored.
+    trash_retention: RwLock<Duration>,
+    duplicate_policy: RwLock<DuplicatePolicy>,
+    lifecycle_hooks: RwLock<Vec<Arc<dyn CredentialLifecycleHook>>>,
+    observers: RwLock<Vec<(u64, Arc<dyn CollectionObserver>)>>,
+    next_observer_token: AtomicU64,
+    /// The remaining instances under which pools are reported as low.
+    pool_low_threshold: RwLock<u32>,
+    /// Coordinates the asynchronous reads and writes of the storage.
+    access: tokio::sync::RwLock<()>,
+}
+
+#[derive(Error, Debug, uniffi::Error)]
+pub enum VdcCollectionError {
+    /// Attempt to convert the credential to a serialized form suitable for writing to storage failed.
+    #[error("Failed to Serialize Value")]
+    SerializeFailed,
+
+    /// Attempting to convert the credential to a deserialized form suitable for runtime use failed.
+    #[error("Failed to Deserialize Value")]
+    DeserializeFailed,
+
+    /// Attempting to write the credential to storage failed.
+    #[error("Failed to Write to Storage")]
+    StoreFailed(StorageManagerError),
+
+    /// Attempting to read the credential from storage failed.
+    #[error("Failed to Read from Storage")]
+    LoadFailed(StorageManagerError),
+
+    /// Attempting to delete a credential from storage failed.
+    #[error("Failed to Delete from Storage")]
+    DeleteFailed(StorageManagerError),
+
+    /// There is no credential with the ID in storage.
+    #[error("Credential Not Found: {0}")]
+    NotFound(Uuid),
+
+    /// The credential was trashed too long ago to be restored.
+    #[error("Credential Trash Retention Expired: {0}")]
+    TrashExpired(Uuid),
+
+    /// There is no profile with the name.
+    #[error("Profile Not Found: {0}")]
+    ProfileNotFound(String),
+
+    /// A profile with the name already exists.
+    #[error("Profile Already Exists: {0}")]
+    ProfileExists(String),
+
+    /// The default profile cannot be deleted.
+    #[error("The Default Profile Cannot Be Deleted")]
+    DefaultProfile,
+
+    /// The credential duplicates the stored credential with the ID.
+    #[error("Duplicate of Credential: {0}")]
+    Duplicate(Uuid),
+
+    /// The task accessing the storage asynchronously panicked or was
+    /// cancelled.
+    #[error("Storage Task Failed: {0}")]
+    StorageTaskFailed(String),
+}
+
+impl VdcCollectionError {
+    /// Return the stable, machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::SerializeFailed => "vdc_collection.serialize_failed",
+            Self::DeserializeFailed => "vdc_collection.deserialize_failed",
+            Self::StoreFailed(..) => "vdc_collection.store_failed",
+            Self::LoadFailed(..) => "vdc_collection.load_failed",
+            Self::DeleteFailed(..) => "vdc_collection.delete_failed",
+            Self::NotFound(..) => "vdc_collection.not_found",
+            Self::TrashExpired(..) => "vdc_collection.trash_expired",
+            Self::ProfileNotFound(..) => "vdc_collection.profile_not_found",
+            Self::ProfileExists(..) => "vdc_collection.profile_exists",
+            Self::DefaultProfile => "vdc_collection.default_profile",
+            Self::Duplicate(..) => "vdc_collection.duplicate",
+            Self::StorageTaskFailed(..) => "vdc_collection.storage_task_failed",
+        }
+    }
+}
+
+/// Return the stable, machine-readable code of the error, e.g.
+/// `vdc_collection.not_found`.
+#[uniffi::export]
+pub fn vdc_collection_error_code(error: VdcCollectionError) -> String {
+    error.code().to_string()
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    #[uniffi::constructor]
+    /// Create a new credential set, stored in the storage engine.
+    ///
+    /// The engine may be provided by the app, implementing
+    /// [StorageManagerInterface] natively, so that credentials can be kept in
+    /// SQLCipher, Core Data, Android EncryptedFile, or any other store. The
+    /// collection keeps credentials, their index and their metadata under
+    /// separate key prefixes, so the engine only needs to provide a flat
+    /// key/value store.
+    pub fn new(engine: Arc<dyn StorageManagerInterface>) -> VdcCollection {
+        VdcCollection::with_storage(engine)
+    }
+
+    #[uniffi::constructor]
+    /// Create a new credential set kept in memory only, for web wallets that
+    /// keep credentials in the page and for test harnesses.
+    pub fn new_in_memory() -> VdcCollection {
+        VdcCollection::with_storage(Arc::new(LocalStore::new()))
+    }
+
+    #[uniffi::constructor]
+    /// Create a new credential set, encrypting every credential with keys
+    /// from the key provider before it is written to storage.
+    ///
+    /// Credentials already in storage that are not encrypted with the current
+    /// key are re-encrypted.
+    pub fn new_encrypted(
+        engine: Arc<dyn StorageManagerInterface>,
+        key_provider: Arc<dyn KeyProvider>,
+    ) -> Result<VdcCollection, VdcCollectionError> {
+        let storage = EncryptedStorage::new(engine, key_provider);
+        let migrated = storage.migrate().map_err(VdcCollectionError::StoreFailed)?;
+        if migrated > 0 {
+            info!("Encrypted {migrated} credentials");
+        }
+
+        Ok(VdcCollection::with_storage(Arc::new(storage)))
+    }
+
+    /// Add a credential to the set.
+    ///
+    /// Credentials that duplicate a stored one are added according to the
+    /// [DuplicatePolicy].
+    pub fn add(&self, credential: &Credential) -> Result<(), VdcCollectionError> {
+        let id = credential.id;
+        self.resolve_duplicate(credential, &self.metadata(id)?)?;
+        if self.store(credential)? {
+            self.notify_added(id, self.metadata(id)?);
+            self.notify_observers(CollectionChange::Added { id });
+        } else {
+            self.notify_observers(CollectionChange::Updated { id });
+        }
+        Ok(())
+    }
+
+    /// Get a credential from the store.
+    pub fn get(&self, id: Uuid) -> Result<Option<Credential>, VdcCollectionError> {
+        let raw = match self.storage.get(Self::id_to_key(id)) {
+            Ok(Some(x)) => x,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(VdcCollectionError::LoadFailed(e)),
+        };
+
+        match serde_cbor::de::from_slice(&raw.0) {
+            Ok(Some(x)) => Ok(Some(x)),
+            _ => Err(VdcCollectionError::DeserializeFailed),
+        }
+    }
+
+    /// Permanently remove a credential from the store.
+    ///
+    /// Use [VdcCollection::trash] to remove it in a way that can be undone.
+    pub fn delete(&self, id: Uuid) -> Result<(), VdcCollectionError> {
+        let metadata = self.metadata(id)?;
+        match self.storage.remove(Self::id_to_key(id)) {
+            Ok(_) => {
+                self.remove_index_entry(id)?;
+                self.remove_metadata(id)?;
+                self.remove_usage(id)?;
+                self.notify_deleted(id, metadata);
+                self.notify_observers(CollectionChange::Deleted { id });
+                Ok(())
+            }
+            Err(e) => Err(VdcCollectionError::DeleteFailed(e)),
+        }
+    }
+
+    /// Get a list of all the credentials in the active profile.
+    pub fn all_entries(&self) -> Result<Vec<Uuid>, VdcCollectionError> {
+        let active = self.active_profile()?;
+        let mut ids = Vec::new();
+        for id in self.stored_entries()? {
+            if self.profile_of(id)?.as_deref() == Some(active.as_str()) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Get a list of all the credentials that match a specified type.
+    pub fn all_entries_by_type(
+        &self,
+        ctype: &CredentialType,
+    ) -> Result<Vec<Uuid>, VdcCollectionError> {
+        self.query(CredentialFilter {
+            types: vec![ctype.clone()],
+            ..Default::default()
+        })
+    }
+
+    /// Get a list of the credentials that are still valid at `now`, e.g. by
+    /// the clock of the holder, but expire within the given duration.
+    ///
+    /// Credentials that cannot be parsed, or have no expiry, are skipped.
+    pub fn expiring_within(
+        &self,
+        now: SystemTime,
+        duration: Duration,
+    ) -> Result<Vec<Uuid>, VdcCollectionError> {
+        Ok(self
+            .all_entries()?
+            .into_iter()
+            .filter_map(|id| self.get(id).ok().flatten())
+            .filter_map(|cred| cred.try_into_parsed().ok())
+            .filter(|cred| cred.validity().expires_within(now, duration))
+            .map(|cred| cred.id())
+            .collect())
+    }
+
+    /// Dump the contents of the credential set to the logger.
+    pub fn dump(&self) {
+        match self.all_entries() {
+            Ok(list) => {
+                for key in list {
+                    if let Ok(x) = self.get(key) {
+                        info!("{:?}", x);
+                    }
+                }
+            }
+            Err(e) => info!("Unable to get list: {:?}", e),
+        }
+    }
+}
+
+impl VdcCollection {
+    /// Write a credential and its index entry, returning whether it is new.
+    fn store(&self, credential: &Credential) -> Result<bool, VdcCollectionError> {
+        let val = match serde_cbor::to_vec(&credential) {
+            Ok(x) => x,
+            Err(_) => return Err(VdcCollectionError::SerializeFailed),
+        };
+
+        // Keep the time the credential was first added, and its profile, when
+        // replacing it.
+        let existing = self.index_entry(credential.id)?;
+        let added_at = existing
+            .as_ref()
+            .and_then(|entry| entry.added_at)
+            .unwrap_or_else(SystemTime::now);
+        let profile = match existing.as_ref().and_then(|entry| entry.profile.clone()) {
+            Some(profile) => profile,
+            None => self.active_profile()?,
+        };
+
+        match self.storage.add(Self::id_to_key(credential.id), Value(val)) {
+            Ok(()) => {
+                let mut entry = IndexEntry::new(credential, Some(added_at));
+                entry.profile = Some(profile);
+                self.write_index_entry(credential.id, &entry)?;
+                Ok(existing.is_none())
+            }
+            Err(e) => Err(VdcCollectionError::StoreFailed(e)),
+        }
+    }
+
+    fn with_storage(storage: Arc<dyn StorageManagerInterface>) -> VdcCollection {
+        VdcCollection {
+            storage,
+            trash_retention: RwLock::new(trash::DEFAULT_TRASH_RETENTION),
+            duplicate_policy: RwLock::new(DuplicatePolicy::default()),
+            lifecycle_hooks: RwLock::new(Vec::new()),
+            observers: RwLock::new(Vec::new()),
+            next_observer_token: AtomicU64::new(0),
+            pool_low_threshold: RwLock::new(pool::DEFAULT_POOL_LOW_THRESHOLD),
+            access: tokio::sync::RwLock::new(()),
+        }
+    }
+
+    /// Get a list of all the credentials, in every profile.
+    fn stored_entries(&self) -> Result<Vec<Uuid>, VdcCollectionError> {
+        self.storage
+            .list()
+            .map(|list| list.iter().filter_map(Self::key_to_id).collect())
+            .map_err(VdcCollectionError::LoadFailed)
+    }
+
+    /// Convert a UUID to a storage key.
+    fn id_to_key(id: Uuid) -> Key {
+        Key(format!("{}{}", KEY_PREFIX, id))
+    }
+
+    /// Convert a string ref to a storage key.
+    ///
+    /// Returns `None` if it's not the right format.
+    fn key_to_id(key: &Key) -> Option<Uuid> {
+        key.strip_prefix(KEY_PREFIX)
+            .map(|id| Uuid::parse_str(&id))
+            .transpose()
+            .ok()
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{credential::CredentialFormat, local_store::*};
+
+    #[tokio::test]
+    async fn test_vdc() {
+        let smi: Arc<dyn StorageManagerInterface> = Arc::new(LocalStore::new());
+        let vdc = VdcCollection::new(smi);
+        for id in vdc.all_entries().unwrap() {
+            vdc.delete(id).unwrap();
+        }
+        let payload_1: Vec<u8> = "Some random collection of bytes. ⚛".into();
+        let payload_2: Vec<u8> = "Some other random collection of bytes. 📯".into();
+        let payload_3: Vec<u8> = "Some third random collection of bytes. λ".into();
+
+        let credential_1 = Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: payload_1.clone(),
+            key_alias: None,
+            display: vec![],
+        };
+
+        let credential_2 = Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: payload_2.clone(),
+            key_alias: None,
+            display: vec![],
+        };
+
+        let credential_3 = Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: payload_3.clone(),
+            key_alias: None,
+            display: vec![],
+        };
+
+        vdc.add(&credential_1)
+            .expect("Unable to add the first value.");
+
+        vdc.add(&credential_2)
+            .expect("Unable to add the second value.");
+
+        vdc.add(&credential_3)
+            .expect("Unable to add the third value.");
+
+        vdc.get(credential_2.id)
+            .expect("Failed to get the second value");
+        vdc.get(credential_1.id)
+            .expect("Failed to get the first value");
+        vdc.get(credential_3.id)
+            .expect("Failed to get the third value");
+
+        assert!(vdc.all_entries().unwrap().len() == 3);
+
+        vdc.delete(credential_2.id)
+            .expect("Failed to delete the second value.");
+
+        assert!(vdc.all_entries().unwrap().len() == 2);
+
+        vdc.delete(credential_1.id)
+            .expect("Failed to delete the first value.");
+        vdc.delete(credential_3.id)
+            .expect("Failed to delete the third value.");
+
+        assert!(vdc.all_entries().unwrap().len() == 0);
+    }
+}
@@ -0,0 +1,197 @@
+//! Asynchronous access to the collection, for async callers such as the
+//! holder, whose storage reads must not stall the runtime driving them.
+//!
+//! Storage is accessed on the blocking thread pool of the runtime. Reads run
+//! concurrently, while writes wait for the reads and writes in progress, so
+//! that a credential, its index entry and its metadata are read consistently.
+//!
+//! NOTE: the synchronous methods are not coordinated with the asynchronous
+//! ones, so callers should use either within a collection. The holder only
+//! uses the asynchronous methods, and the synchronous ones are deprecated for
+//! collections shared with a holder.
+
+use super::{
+    CredentialFilter, CredentialSort, CredentialSummary, VdcCollection, VdcCollectionError,
+};
+use crate::common::*;
+use crate::credential::{Credential, CredentialFormat, ParsedCredential};
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[uniffi::export(async_runtime = "tokio")]
+impl VdcCollection {
+    /// As [VdcCollection::add], without blocking the caller.
+    pub async fn add_async(
+        self: Arc<Self>,
+        credential: Credential,
+    ) -> Result<(), VdcCollectionError> {
+        self.write(move |collection| collection.add(&credential))
+            .await
+    }
+
+    /// As [VdcCollection::get], without blocking the caller.
+    pub async fn get_async(
+        self: Arc<Self>,
+        id: Uuid,
+    ) -> Result<Option<Credential>, VdcCollectionError> {
+        self.read(move |collection| collection.get(id)).await
+    }
+
+    /// As [VdcCollection::get_parsed], without blocking the caller.
+    pub async fn get_parsed_async(
+        self: Arc<Self>,
+        id: Uuid,
+    ) -> Result<Option<Arc<ParsedCredential>>, VdcCollectionError> {
+        self.read(move |collection| collection.get_parsed(id)).await
+    }
+
+    /// As [VdcCollection::delete], without blocking the caller.
+    pub async fn delete_async(self: Arc<Self>, id: Uuid) -> Result<(), VdcCollectionError> {
+        self.write(move |collection| collection.delete(id)).await
+    }
+
+    /// As [VdcCollection::all_entries], without blocking the caller.
+    pub async fn all_entries_async(self: Arc<Self>) -> Result<Vec<Uuid>, VdcCollectionError> {
+        self.read(|collection| collection.all_entries()).await
+    }
+
+    /// As [VdcCollection::query], without blocking the caller.
+    pub async fn query_async(
+        self: Arc<Self>,
+        filter: CredentialFilter,
+    ) -> Result<Vec<Uuid>, VdcCollectionError> {
+        self.read(move |collection| collection.query(filter)).await
+    }
+
+    /// As [VdcCollection::list], without blocking the caller.
+    pub async fn list_async(
+        self: Arc<Self>,
+        offset: u32,
+        limit: u32,
+        sort: CredentialSort,
+    ) -> Result<Vec<CredentialSummary>, VdcCollectionError> {
+        self.read(move |collection| collection.list(offset, limit, sort))
+            .await
+    }
+
+    /// As [VdcCollection::consume_pool_instance], without blocking the caller.
+    pub async fn consume_pool_instance_async(
+        self: Arc<Self>,
+        id: Uuid,
+    ) -> Result<(), VdcCollectionError> {
+        self.write(move |collection| collection.consume_pool_instance(id))
+            .await
+    }
+
+    /// As [VdcCollection::record_usage], without blocking the caller.
+    pub async fn record_usage_async(
+        self: Arc<Self>,
+        id: Uuid,
+        used_at: SystemTime,
+    ) -> Result<(), VdcCollectionError> {
+        self.write(move |collection| collection.record_usage(id, used_at))
+            .await
+    }
+
+    /// As [VdcCollection::formats], without blocking the caller.
+    pub async fn formats_async(
+        self: Arc<Self>,
+    ) -> Result<Vec<CredentialFormat>, VdcCollectionError> {
+        self.read(|collection| collection.formats()).await
+    }
+}
+
+impl VdcCollection {
+    /// Read the storage on the blocking thread pool, concurrently with other
+    /// reads.
+    async fn read<T: Send + 'static>(
+        self: &Arc<Self>,
+        read: impl FnOnce(&VdcCollection) -> Result<T, VdcCollectionError> + Send + 'static,
+    ) -> Result<T, VdcCollectionError> {
+        let _guard = self.access.read().await;
+        // Browsers run a single thread, without a blocking pool.
+        #[cfg(target_arch = "wasm32")]
+        return read(self);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let collection = self.clone();
+            tokio::task::spawn_blocking(move || read(&collection))
+                .await
+                .map_err(|e| VdcCollectionError::StorageTaskFailed(format!("{e:?}")))?
+        }
+    }
+
+    /// Write to the storage on the blocking thread pool, once the reads and
+    /// writes in progress are done.
+    async fn write<T: Send + 'static>(
+        self: &Arc<Self>,
+        write: impl FnOnce(&VdcCollection) -> Result<T, VdcCollectionError> + Send + 'static,
+    ) -> Result<T, VdcCollectionError> {
+        let _guard = self.access.write().await;
+        // Browsers run a single thread, without a blocking pool.
+        #[cfg(target_arch = "wasm32")]
+        return write(self);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let collection = self.clone();
+            tokio::task::spawn_blocking(move || write(&collection))
+                .await
+                .map_err(|e| VdcCollectionError::StorageTaskFailed(format!("{e:?}")))?
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_store::LocalStore;
+
+    #[tokio::test]
+    async fn accesses_storage_asynchronously() {
+        let collection = Arc::new(VdcCollection::new(Arc::new(LocalStore::new())));
+        let credentials = (0..4)
+            .map(|index| Credential {
+                id: Uuid::new_v4(),
+                format: CredentialFormat::MsoMdoc,
+                r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+                payload: vec![index],
+                key_alias: None,
+                display: vec![],
+            })
+            .collect::<Vec<_>>();
+
+        let writes = credentials
+            .iter()
+            .map(|credential| collection.clone().add_async(credential.clone()));
+        for write in futures::future::join_all(writes).await {
+            write.unwrap();
+        }
+        assert_eq!(
+            collection.clone().all_entries_async().await.unwrap().len(),
+            4
+        );
+
+        let credential = &credentials[2];
+        assert_eq!(
+            collection
+                .clone()
+                .get_async(credential.id)
+                .await
+                .unwrap()
+                .map(|stored| stored.payload),
+            Some(credential.payload.clone())
+        );
+        assert_eq!(
+            collection.clone().formats_async().await.unwrap(),
+            vec![CredentialFormat::MsoMdoc]
+        );
+
+        collection
+            .clone()
+            .delete_async(credential.id)
+            .await
+            .unwrap();
+        assert!(collection.get_async(credential.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//! Observers notified of every change to the credentials of the collection,
+//! so that UIs can refresh their credential list instead of polling
+//! [VdcCollection::all_entries].
+
+use std::fmt::Debug;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use super::VdcCollection;
+use crate::common::*;
+
+/// A change to the credentials of the collection.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum CollectionChange {
+    /// A credential was added, or restored from the trash.
+    Added { id: Uuid },
+    /// A credential, its metadata or its profile was replaced.
+    Updated { id: Uuid },
+    /// A credential was deleted, or moved to the trash.
+    Deleted { id: Uuid },
+    /// The unused instances of a pool of single-use credentials fell to the
+    /// low threshold, see [VdcCollection::set_pool_low_threshold].
+    PoolLow { batch_id: String, remaining: u32 },
+}
+
+/// Interface: CollectionObserver
+///
+/// The CollectionObserver is notified after each change to the credentials of
+/// the collection.
+///
+/// Observers are called synchronously, and should not block.
+#[uniffi::export(with_foreign)]
+pub trait CollectionObserver: Send + Sync + Debug {
+    fn on_change(&self, change: CollectionChange);
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Register an observer, notified of every following change, returning
+    /// the token to remove it with.
+    pub fn add_observer(&self, observer: Arc<dyn CollectionObserver>) -> u64 {
+        let token = self.next_observer_token.fetch_add(1, Ordering::SeqCst);
+        self.observers
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((token, observer));
+        token
+    }
+
+    /// Remove the observer registered with the token.
+    pub fn remove_observer(&self, token: u64) {
+        self.observers
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .retain(|(observer_token, _)| *observer_token != token);
+    }
+}
+
+impl VdcCollection {
+    pub(crate) fn notify_observers(&self, change: CollectionChange) {
+        let observers = self
+            .observers
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        for (_, observer) in observers {
+            observer.on_change(change.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{Credential, CredentialFormat};
+    use crate::local_store::LocalStore;
+    use crate::vdc_collection::CredentialMetadata;
+
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct TestObserver(Mutex<Vec<CollectionChange>>);
+
+    impl CollectionObserver for TestObserver {
+        fn on_change(&self, change: CollectionChange) {
+            self.0.lock().unwrap().push(change);
+        }
+    }
+
+    #[test]
+    fn notifies_observers() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let observer = Arc::new(TestObserver::default());
+        let token = vdc.add_observer(observer.clone());
+
+        let credential = Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: vec![],
+            key_alias: None,
+            display: vec![],
+        };
+        let id = credential.id;
+        vdc.add_with_metadata(&credential, CredentialMetadata::default())
+            .unwrap();
+        vdc.add(&credential).unwrap();
+        vdc.add_tag(id, "work".into()).unwrap();
+        vdc.trash(id).unwrap();
+        vdc.restore(id).unwrap();
+        vdc.delete(id).unwrap();
+
+        vdc.remove_observer(token);
+        vdc.add(&credential).unwrap();
+
+        assert_eq!(
+            *observer.0.lock().unwrap(),
+            vec![
+                CollectionChange::Added { id },
+                CollectionChange::Updated { id },
+                CollectionChange::Updated { id },
+                CollectionChange::Deleted { id },
+                CollectionChange::Added { id },
+                CollectionChange::Deleted { id },
+            ]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Pools of single-use credentials, the instances of a batch presented once
+//! each so that verifiers cannot link presentations.
+//!
+//! Only one unused instance of a pool is offered to verifiers, and instances
+//! are marked with [POOL_CONSUMED_ATTRIBUTE] once presented. Observers are
+//! notified with [CollectionChange::PoolLow] when the remaining instances
+//! fall to the low threshold, for the app to replenish the pool, e.g. with
+//! [crate::oid4vci::oid4vci_replenish_pool].
+//!
+//! NOTE: exhausted pools are not offered at all, as presenting an instance
+//! again would make presentations linkable.
+
+use std::collections::HashMap;
+
+use super::{CollectionChange, VdcCollection, VdcCollectionError, BATCH_ID_ATTRIBUTE};
+use crate::common::*;
+
+/// The attribute marking the instances of a pool that were presented.
+pub const POOL_CONSUMED_ATTRIBUTE: &str = "pool_consumed";
+
+/// The remaining instances pools are replenished at, by default.
+pub const DEFAULT_POOL_LOW_THRESHOLD: u32 = 2;
+
+/// The instances of a pool of single-use credentials.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct PoolStatus {
+    pub batch_id: String,
+    pub total: u32,
+    /// The instances that were not presented yet.
+    pub remaining: u32,
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Set the remaining instances under which pools are reported as low.
+    pub fn set_pool_low_threshold(&self, threshold: u32) {
+        *self
+            .pool_low_threshold
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = threshold;
+    }
+
+    /// Get the status of the pool of a credential, if it was issued in a
+    /// batch.
+    pub fn pool_status(&self, id: Uuid) -> Result<Option<PoolStatus>, VdcCollectionError> {
+        let Some(batch_id) = self.metadata(id)?.attributes.remove(BATCH_ID_ATTRIBUTE) else {
+            return Ok(None);
+        };
+
+        let instances = self.batch(id)?;
+        let remaining = self.unused(&instances)?.len();
+        Ok(Some(PoolStatus {
+            batch_id,
+            total: instances.len() as u32,
+            remaining: remaining as u32,
+        }))
+    }
+
+    /// Get the instance of the pool of a credential to present next, or the
+    /// credential itself when it was not issued in a batch.
+    pub fn next_pool_instance(&self, id: Uuid) -> Result<Option<Uuid>, VdcCollectionError> {
+        Ok(self.unused(&self.batch(id)?)?.first().copied())
+    }
+
+    /// Mark a presented instance of a pool as consumed, notifying observers
+    /// when the pool is low.
+    ///
+    /// Credentials not issued in a batch are left untouched.
+    pub fn consume_pool_instance(&self, id: Uuid) -> Result<(), VdcCollectionError> {
+        if !self
+            .metadata(id)?
+            .attributes
+            .contains_key(BATCH_ID_ATTRIBUTE)
+        {
+            return Ok(());
+        }
+
+        self.set_attribute(
+            id,
+            POOL_CONSUMED_ATTRIBUTE.into(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string(),
+        )?;
+
+        let threshold = *self
+            .pool_low_threshold
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(status) = self.pool_status(id)? {
+            if status.remaining <= threshold {
+                self.notify_observers(CollectionChange::PoolLow {
+                    batch_id: status.batch_id,
+                    remaining: status.remaining,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VdcCollection {
+    /// Keep a single unused instance of each pool among the credentials,
+    /// preserving their order.
+    pub(crate) fn pool_candidates(&self, ids: Vec<Uuid>) -> Result<Vec<Uuid>, VdcCollectionError> {
+        let mut next = HashMap::new();
+        let mut candidates = Vec::new();
+        for id in ids {
+            let Some(batch_id) = self.metadata(id)?.attributes.remove(BATCH_ID_ATTRIBUTE) else {
+                candidates.push(id);
+                continue;
+            };
+            if !next.contains_key(&batch_id) {
+                next.insert(batch_id.clone(), self.next_pool_instance(id)?);
+            }
+            if next[&batch_id] == Some(id) {
+                candidates.push(id);
+            }
+        }
+        Ok(candidates)
+    }
+
+    fn unused(&self, instances: &[Uuid]) -> Result<Vec<Uuid>, VdcCollectionError> {
+        let mut unused = Vec::new();
+        for &id in instances {
+            if !self
+                .metadata(id)?
+                .attributes
+                .contains_key(POOL_CONSUMED_ATTRIBUTE)
+            {
+                unused.push(id);
+            }
+        }
+        Ok(unused)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{Credential, CredentialFormat};
+    use crate::local_store::LocalStore;
+    use crate::vdc_collection::CollectionObserver;
+
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<CollectionChange>>);
+
+    impl CollectionObserver for Recorder {
+        fn on_change(&self, change: CollectionChange) {
+            if let CollectionChange::PoolLow { .. } = change {
+                self.0.lock().unwrap().push(change);
+            }
+        }
+    }
+
+    #[test]
+    fn consumes_pool_instances() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let credential = || Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: vec![],
+            key_alias: None,
+            display: vec![],
+        };
+        let recorder = Arc::new(Recorder::default());
+        vdc.add_observer(recorder.clone());
+        vdc.set_pool_low_threshold(1);
+
+        let batch_id = Uuid::new_v4();
+        let batch = (0..3).map(|_| credential()).collect::<Vec<_>>();
+        let ids = batch
+            .iter()
+            .map(|credential| credential.id)
+            .collect::<Vec<_>>();
+        vdc.add_batch(batch_id, batch).unwrap();
+        let single = credential();
+        vdc.add(&single).unwrap();
+
+        let mut all = ids.clone();
+        all.push(single.id);
+        assert_eq!(
+            vdc.pool_candidates(all.clone()).unwrap(),
+            [ids[0], single.id]
+        );
+
+        vdc.consume_pool_instance(ids[0]).unwrap();
+        vdc.consume_pool_instance(single.id).unwrap();
+        assert_eq!(
+            vdc.pool_candidates(all.clone()).unwrap(),
+            [ids[1], single.id]
+        );
+        assert_eq!(
+            vdc.pool_status(ids[2]).unwrap(),
+            Some(PoolStatus {
+                batch_id: batch_id.to_string(),
+                total: 3,
+                remaining: 2,
+            })
+        );
+        assert_eq!(vdc.pool_status(single.id).unwrap(), None);
+        assert!(recorder.0.lock().unwrap().is_empty());
+
+        vdc.consume_pool_instance(ids[1]).unwrap();
+        vdc.consume_pool_instance(ids[2]).unwrap();
+        assert_eq!(vdc.next_pool_instance(ids[0]).unwrap(), None);
+        assert_eq!(vdc.pool_candidates(all).unwrap(), [single.id]);
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                CollectionChange::PoolLow {
+                    batch_id: batch_id.to_string(),
+                    remaining: 1,
+                },
+                CollectionChange::PoolLow {
+                    batch_id: batch_id.to_string(),
+                    remaining: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Isolated profiles, such as personal and work, within one collection.
+//!
+//! Every credential belongs to a profile, which is recorded in its index
+//! entry. Listing, querying and matching are scoped to the active profile.
+//! Credentials stored before profiles existed belong to the default profile.
+
+use super::{CollectionChange, VdcCollection, VdcCollectionError};
+use crate::common::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Internal prefix for profile keys.
+const PROFILE_KEY_PREFIX: &str = "Profile.";
+
+/// Internal key of the active profile.
+const ACTIVE_PROFILE_KEY: &str = "ActiveProfile";
+
+/// The profile that always exists, and is active by default.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ActiveProfile {
+    name: String,
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Get the names of every profile, sorted alphabetically.
+    pub fn profiles(&self) -> Result<Vec<String>, VdcCollectionError> {
+        let mut profiles = self
+            .storage
+            .list()
+            .map_err(VdcCollectionError::LoadFailed)?
+            .iter()
+            .filter_map(|key| key.strip_prefix(PROFILE_KEY_PREFIX))
+            .collect::<Vec<_>>();
+        profiles.push(DEFAULT_PROFILE.into());
+        profiles.sort();
+        profiles.dedup();
+        Ok(profiles)
+    }
+
+    /// Create a new, empty profile.
+    pub fn create_profile(&self, name: String) -> Result<(), VdcCollectionError> {
+        if self.profiles()?.contains(&name) {
+            return Err(VdcCollectionError::ProfileExists(name));
+        }
+
+        self.storage
+            .add(Self::profile_key(&name), Value(vec![]))
+            .map_err(VdcCollectionError::StoreFailed)
+    }
+
+    /// Get the name of the active profile.
+    pub fn active_profile(&self) -> Result<String, VdcCollectionError> {
+        let Some(raw) = self
+            .storage
+            .get(Key(ACTIVE_PROFILE_KEY.into()))
+            .map_err(VdcCollectionError::LoadFailed)?
+        else {
+            return Ok(DEFAULT_PROFILE.into());
+        };
+
+        serde_cbor::from_slice::<ActiveProfile>(&raw.0)
+            .map(|active| active.name)
+            .map_err(|_| VdcCollectionError::DeserializeFailed)
+    }
+
+    /// Switch the active profile, to which listing, querying, matching and
+    /// newly added credentials are scoped.
+    pub fn switch_profile(&self, name: String) -> Result<(), VdcCollectionError> {
+        if !self.profiles()?.contains(&name) {
+            return Err(VdcCollectionError::ProfileNotFound(name));
+        }
+
+        let value = serde_cbor::to_vec(&ActiveProfile { name })
+            .map_err(|_| VdcCollectionError::SerializeFailed)?;
+        self.storage
+            .add(Key(ACTIVE_PROFILE_KEY.into()), Value(value))
+            .map_err(VdcCollectionError::StoreFailed)
+    }
+
+    /// Delete a profile, and every credential in it.
+    ///
+    /// The default profile becomes active if the deleted profile was active.
+    pub fn delete_profile(&self, name: String) -> Result<(), VdcCollectionError> {
+        if name == DEFAULT_PROFILE {
+            return Err(VdcCollectionError::DefaultProfile);
+        }
+        if !self.profiles()?.contains(&name) {
+            return Err(VdcCollectionError::ProfileNotFound(name));
+        }
+
+        for id in self.stored_entries()? {
+            if self.profile_of(id)?.as_deref() == Some(name.as_str()) {
+                self.delete(id)?;
+            }
+        }
+
+        if self.active_profile()? == name {
+            self.switch_profile(DEFAULT_PROFILE.into())?;
+        }
+
+        self.storage
+            .remove(Self::profile_key(&name))
+            .map_err(VdcCollectionError::DeleteFailed)
+    }
+
+    /// Move a credential to another profile.
+    pub fn move_to_profile(&self, id: Uuid, profile: String) -> Result<(), VdcCollectionError> {
+        if !self.profiles()?.contains(&profile) {
+            return Err(VdcCollectionError::ProfileNotFound(profile));
+        }
+        let Some(mut entry) = self.index_entry(id)? else {
+            return Err(VdcCollectionError::NotFound(id));
+        };
+
+        entry.profile = Some(profile);
+        self.write_index_entry(id, &entry)?;
+        self.notify_observers(CollectionChange::Updated { id });
+        Ok(())
+    }
+}
+
+impl VdcCollection {
+    /// Convert a profile name to a profile key.
+    fn profile_key(name: &str) -> Key {
+        Key(format!("{}{}", PROFILE_KEY_PREFIX, name))
+    }
+
+    /// Get the profile of a stored credential.
+    pub(crate) fn profile_of(&self, id: Uuid) -> Result<Option<String>, VdcCollectionError> {
+        Ok(self
+            .index_entry(id)?
+            .map(|entry| entry.profile.unwrap_or_else(|| DEFAULT_PROFILE.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{Credential, CredentialFormat};
+    use crate::local_store::LocalStore;
+
+    use std::sync::Arc;
+
+    fn credential() -> Credential {
+        Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::LdpVc,
+            r#type: CredentialType("UniversityDegreeCredential".into()),
+            payload: vec![],
+            key_alias: None,
+            display: vec![],
+        }
+    }
+
+    #[test]
+    fn isolates_profiles() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let personal = credential();
+        vdc.add(&personal).unwrap();
+
+        vdc.create_profile("work".into()).unwrap();
+        assert!(matches!(
+            vdc.create_profile("work".into()),
+            Err(VdcCollectionError::ProfileExists(_))
+        ));
+        assert_eq!(vdc.profiles().unwrap(), vec!["default", "work"]);
+
+        vdc.switch_profile("work".into()).unwrap();
+        assert_eq!(vdc.active_profile().unwrap(), "work");
+        assert!(vdc.all_entries().unwrap().is_empty());
+
+        let work = credential();
+        vdc.add(&work).unwrap();
+        assert_eq!(vdc.query(Default::default()).unwrap(), vec![work.id]);
+
+        vdc.switch_profile(DEFAULT_PROFILE.into()).unwrap();
+        assert_eq!(vdc.all_entries().unwrap(), vec![personal.id]);
+
+        vdc.move_to_profile(personal.id, "work".into()).unwrap();
+        assert!(vdc.all_entries().unwrap().is_empty());
+
+        vdc.switch_profile("work".into()).unwrap();
+        vdc.delete_profile("work".into()).unwrap();
+        assert_eq!(vdc.active_profile().unwrap(), DEFAULT_PROFILE);
+        assert!(vdc.get(work.id).unwrap().is_none());
+        assert!(vdc.get(personal.id).unwrap().is_none());
+        assert!(matches!(
+            vdc.delete_profile(DEFAULT_PROFILE.into()),
+            Err(VdcCollectionError::DefaultProfile)
+        ));
+    }
+}
@@ -0,0 +1,481 @@
+//! End-to-end encrypted synchronization of the collection across the devices
+//! of a user.
+//!
+//! Each credential, along with its metadata, is synchronized as a versioned
+//! blob, sealed with keys from the [KeyProvider] before it is handed to the
+//! [SyncTransport], so the backend of the app never sees credentials in the
+//! clear. Deleted credentials are synchronized as tombstones, blobs without a
+//! credential, so that a deletion is not undone by a device that has not seen
+//! it yet.
+//!
+//! The version and the hash of each credential at the last synchronization
+//! are kept in storage, to tell local changes from remote ones. When both
+//! changed, an edit wins over a deletion, and the [SyncConflictPolicy]
+//! decides between two edits.
+
+use super::{CredentialMetadata, VdcCollection, VdcCollectionError};
+use crate::common::*;
+use crate::credential::Credential;
+use crate::encrypted_storage::{EncryptedStorage, KeyProvider};
+use crate::storage_manager::StorageManagerError;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Internal prefix for the synchronization state keys.
+const STATE_KEY_PREFIX: &str = "SyncState.";
+
+/// Prefix of the associated data blobs are sealed with.
+const BLOB_KEY_PREFIX: &str = "SyncBlob.";
+
+#[derive(thiserror::Error, uniffi::Error, Debug)]
+pub enum SyncError {
+    #[error("An unexpected foreign callback error occurred: {0}")]
+    UnexpectedUniFFICallbackError(String),
+    #[error("The sync transport failed: {0}")]
+    Transport(String),
+    #[error("Failed to seal or open a sync blob: {0}")]
+    Sealing(StorageManagerError),
+    #[error("Invalid sync blob {0}")]
+    InvalidBlob(String),
+    #[error(transparent)]
+    VdcCollection(#[from] VdcCollectionError),
+}
+
+// Handle unexpected errors when calling a foreign callback
+impl From<uniffi::UnexpectedUniFFICallbackError> for SyncError {
+    fn from(value: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        SyncError::UnexpectedUniFFICallbackError(value.reason)
+    }
+}
+
+/// An encrypted blob, as stored by the sync backend.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct SyncBlob {
+    /// The name of the blob, which is the ID of its credential.
+    pub name: String,
+    /// The version of the blob, increasing with every change.
+    pub version: u64,
+    pub data: Vec<u8>,
+}
+
+/// Interface: SyncTransport
+///
+/// The SyncTransport stores the encrypted blobs of the collection in a
+/// backend provided by the app, such as iCloud or a server of the issuer.
+#[uniffi::export(with_foreign)]
+#[async_trait::async_trait]
+pub trait SyncTransport: Send + Sync + Debug {
+    /// Return every blob stored in the backend.
+    async fn download(&self) -> Result<Vec<SyncBlob>, SyncError>;
+
+    /// Store the blobs, replacing those with the same names.
+    async fn upload(&self, blobs: Vec<SyncBlob>) -> Result<(), SyncError>;
+}
+
+/// How credentials changed both locally and remotely since the last
+/// synchronization are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum SyncConflictPolicy {
+    /// Keep the local credential, and upload it.
+    KeepLocal,
+    /// Replace the local credential with the remote one.
+    KeepRemote,
+}
+
+/// The outcome of a synchronization.
+#[derive(Debug, Clone, Default, PartialEq, Eq, uniffi::Record)]
+pub struct SyncSummary {
+    /// Local changes uploaded, including deletions.
+    pub uploaded: u32,
+    /// Remote credentials added or replaced locally.
+    pub downloaded: u32,
+    /// Local credentials deleted remotely.
+    pub deleted: u32,
+    /// Credentials changed both locally and remotely.
+    pub conflicts: u32,
+}
+
+/// The contents of a blob.
+#[derive(Debug, Serialize, Deserialize)]
+struct SyncRecord {
+    id: Uuid,
+    version: u64,
+    /// The credential and its metadata, or `None` for a tombstone.
+    entry: Option<(Credential, CredentialMetadata)>,
+}
+
+/// The state of a credential at the last synchronization.
+#[derive(Debug, Serialize, Deserialize)]
+struct SyncState {
+    version: u64,
+    /// The hash of the credential, or `None` if it was deleted.
+    hash: Option<Vec<u8>>,
+}
+
+/// Synchronizes a collection through a [SyncTransport].
+#[derive(Debug, uniffi::Object)]
+pub struct CollectionSync {
+    collection: Arc<VdcCollection>,
+    sealer: EncryptedStorage,
+    transport: Arc<dyn SyncTransport>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl CollectionSync {
+    #[uniffi::constructor]
+    pub fn new(
+        collection: Arc<VdcCollection>,
+        key_provider: Arc<dyn KeyProvider>,
+        transport: Arc<dyn SyncTransport>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            sealer: EncryptedStorage::new(collection.storage.clone(), key_provider),
+            collection,
+            transport,
+        })
+    }
+
+    /// Download the remote changes, apply them, and upload the local ones.
+    ///
+    /// NOTE: remote credentials are added to the active profile, as profiles
+    /// are not synchronized.
+    pub async fn sync(
+        &self,
+        conflict_policy: SyncConflictPolicy,
+    ) -> Result<SyncSummary, SyncError> {
+        let mut remote = HashMap::new();
+        for blob in self.transport.download().await? {
+            let record = self.open(&blob)?;
+            remote.insert(record.id, record);
+        }
+
+        let mut ids = BTreeSet::new();
+        ids.extend(self.collection.stored_entries()?);
+        ids.extend(self.synced_entries()?);
+        ids.extend(remote.keys().copied());
+
+        let mut summary = SyncSummary::default();
+        let mut uploads = vec![];
+        let mut uploaded_states = vec![];
+
+        for id in ids {
+            let local = self.local_entry(id)?;
+            let local_hash = local.as_ref().map(hash).transpose()?;
+            let state = self.state(id)?;
+            let remote = remote.remove(&id);
+
+            let synced_version = state.as_ref().map_or(0, |state| state.version);
+            let local_changed = match &state {
+                Some(state) => state.hash != local_hash,
+                None => local.is_some(),
+            };
+            let remote_changed = remote
+                .as_ref()
+                .is_some_and(|remote| remote.version > synced_version);
+
+            let keep_local = match (local_changed, remote_changed) {
+                (false, false) => continue,
+                (true, false) => true,
+                (false, true) => false,
+                (true, true) => {
+                    summary.conflicts += 1;
+                    let remote_deleted = remote.as_ref().is_some_and(|r| r.entry.is_none());
+                    match (local.is_some(), remote_deleted) {
+                        // Edits win over deletions.
+                        (true, true) => true,
+                        (false, _) => false,
+                        (true, false) => conflict_policy == SyncConflictPolicy::KeepLocal,
+                    }
+                }
+            };
+
+            if keep_local {
+                let remote_version = remote.as_ref().map_or(0, |remote| remote.version);
+                let record = SyncRecord {
+                    id,
+                    version: synced_version.max(remote_version) + 1,
+                    entry: local,
+                };
+                uploads.push(self.seal(&record)?);
+                uploaded_states.push((
+                    id,
+                    SyncState {
+                        version: record.version,
+                        hash: local_hash,
+                    },
+                ));
+                summary.uploaded += 1;
+            } else if let Some(remote) = remote {
+                let hash = remote.entry.as_ref().map(hash).transpose()?;
+                match remote.entry {
+                    Some((credential, metadata)) => {
+                        self.collection.add_with_metadata(&credential, metadata)?;
+                        summary.downloaded += 1;
+                    }
+                    None if local.is_some() => {
+                        self.collection.delete(id)?;
+                        summary.deleted += 1;
+                    }
+                    None => {}
+                }
+                self.write_state(
+                    id,
+                    &SyncState {
+                        version: remote.version,
+                        hash,
+                    },
+                )?;
+            }
+        }
+
+        if !uploads.is_empty() {
+            self.transport.upload(uploads).await?;
+        }
+        // Only record the uploaded changes as synchronized once they are.
+        for (id, state) in uploaded_states {
+            self.write_state(id, &state)?;
+        }
+
+        Ok(summary)
+    }
+}
+
+impl CollectionSync {
+    fn local_entry(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<(Credential, CredentialMetadata)>, VdcCollectionError> {
+        let Some(credential) = self.collection.get(id)? else {
+            return Ok(None);
+        };
+        Ok(Some((credential, self.collection.metadata(id)?)))
+    }
+
+    /// Get a list of the credentials synchronized before, including deleted
+    /// ones.
+    fn synced_entries(&self) -> Result<Vec<Uuid>, VdcCollectionError> {
+        self.collection
+            .storage
+            .list()
+            .map(|list| {
+                list.iter()
+                    .filter_map(|key| key.strip_prefix(STATE_KEY_PREFIX))
+                    .filter_map(|id| Uuid::parse_str(&id).ok())
+                    .collect()
+            })
+            .map_err(VdcCollectionError::LoadFailed)
+    }
+
+    fn state(&self, id: Uuid) -> Result<Option<SyncState>, VdcCollectionError> {
+        let Some(raw) = self
+            .collection
+            .storage
+            .get(Self::id_to_state_key(id))
+            .map_err(VdcCollectionError::LoadFailed)?
+        else {
+            return Ok(None);
+        };
+
+        serde_cbor::from_slice(&raw.0).map_err(|_| VdcCollectionError::DeserializeFailed)
+    }
+
+    fn write_state(&self, id: Uuid, state: &SyncState) -> Result<(), VdcCollectionError> {
+        let value = serde_cbor::to_vec(state).map_err(|_| VdcCollectionError::SerializeFailed)?;
+
+        self.collection
+            .storage
+            .add(Self::id_to_state_key(id), Value(value))
+            .map_err(VdcCollectionError::StoreFailed)
+    }
+
+    fn seal(&self, record: &SyncRecord) -> Result<SyncBlob, SyncError> {
+        let value = serde_cbor::to_vec(record).map_err(|_| VdcCollectionError::SerializeFailed)?;
+        let data = self
+            .sealer
+            .seal(&Self::id_to_blob_key(record.id), &value)
+            .map_err(SyncError::Sealing)?;
+
+        Ok(SyncBlob {
+            name: record.id.to_string(),
+            version: record.version,
+            data,
+        })
+    }
+
+    fn open(&self, blob: &SyncBlob) -> Result<SyncRecord, SyncError> {
+        let id =
+            Uuid::parse_str(&blob.name).map_err(|_| SyncError::InvalidBlob(blob.name.clone()))?;
+        let value = self
+            .sealer
+            .open(&Self::id_to_blob_key(id), &blob.data)
+            .map_err(SyncError::Sealing)?;
+
+        let record: SyncRecord = serde_cbor::from_slice(&value)
+            .map_err(|_| SyncError::InvalidBlob(blob.name.clone()))?;
+        if record.id != id {
+            return Err(SyncError::InvalidBlob(blob.name.clone()));
+        }
+        Ok(record)
+    }
+
+    fn id_to_state_key(id: Uuid) -> Key {
+        Key(format!("{}{}", STATE_KEY_PREFIX, id))
+    }
+
+    fn id_to_blob_key(id: Uuid) -> Key {
+        Key(format!("{}{}", BLOB_KEY_PREFIX, id))
+    }
+}
+
+/// Hash a credential and its metadata, to detect local changes.
+fn hash(
+    (credential, metadata): &(Credential, CredentialMetadata),
+) -> Result<Vec<u8>, VdcCollectionError> {
+    // Attributes are sorted, for the hash not to depend on the map order.
+    let attributes = metadata.attributes.iter().collect::<BTreeMap<_, _>>();
+    let value = serde_cbor::to_vec(&(credential, &metadata.tags, attributes))
+        .map_err(|_| VdcCollectionError::SerializeFailed)?;
+    Ok(Sha256::digest(value).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::CredentialFormat;
+    use crate::encrypted_storage::KeyProviderError;
+    use crate::local_store::LocalStore;
+
+    use std::sync::Mutex;
+
+    #[derive(Debug)]
+    struct TestKeyProvider;
+
+    impl KeyProvider for TestKeyProvider {
+        fn current_key_id(&self) -> Result<String, KeyProviderError> {
+            Ok("key-1".into())
+        }
+
+        fn key(&self, _key_id: String) -> Result<Vec<u8>, KeyProviderError> {
+            Ok(vec![1; 32])
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct TestTransport(Mutex<HashMap<String, SyncBlob>>);
+
+    #[async_trait::async_trait]
+    impl SyncTransport for TestTransport {
+        async fn download(&self) -> Result<Vec<SyncBlob>, SyncError> {
+            Ok(self.0.lock().unwrap().values().cloned().collect())
+        }
+
+        async fn upload(&self, blobs: Vec<SyncBlob>) -> Result<(), SyncError> {
+            let mut stored = self.0.lock().unwrap();
+            for blob in blobs {
+                stored.insert(blob.name.clone(), blob);
+            }
+            Ok(())
+        }
+    }
+
+    fn device(transport: &Arc<TestTransport>) -> (Arc<VdcCollection>, Arc<CollectionSync>) {
+        let collection = Arc::new(VdcCollection::new(Arc::new(LocalStore::new())));
+        let sync = CollectionSync::new(
+            collection.clone(),
+            Arc::new(TestKeyProvider),
+            transport.clone(),
+        );
+        (collection, sync)
+    }
+
+    #[tokio::test]
+    async fn syncs_collections() {
+        let transport = Arc::new(TestTransport::default());
+        let (phone, phone_sync) = device(&transport);
+        let (tablet, tablet_sync) = device(&transport);
+
+        let credential = Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::MsoMdoc,
+            r#type: CredentialType("org.iso.18013.5.1.mDL".into()),
+            payload: vec![1, 2, 3],
+            key_alias: None,
+            display: vec![],
+        };
+        let id = credential.id;
+        phone.add(&credential).unwrap();
+
+        let summary = phone_sync
+            .sync(SyncConflictPolicy::KeepLocal)
+            .await
+            .unwrap();
+        assert_eq!(summary.uploaded, 1);
+        let blob = transport.0.lock().unwrap()[&id.to_string()].clone();
+        assert!(!blob.data.windows(3).any(|window| window == [1, 2, 3]));
+
+        let summary = tablet_sync
+            .sync(SyncConflictPolicy::KeepLocal)
+            .await
+            .unwrap();
+        assert_eq!(summary.downloaded, 1);
+        assert!(tablet.get(id).unwrap().is_some());
+
+        // Nothing changed since the last synchronization.
+        let summary = tablet_sync
+            .sync(SyncConflictPolicy::KeepLocal)
+            .await
+            .unwrap();
+        assert_eq!(summary, SyncSummary::default());
+
+        // An edit wins over a concurrent deletion.
+        tablet.add_tag(id, "work".into()).unwrap();
+        phone.delete(id).unwrap();
+        tablet_sync
+            .sync(SyncConflictPolicy::KeepLocal)
+            .await
+            .unwrap();
+        let summary = phone_sync
+            .sync(SyncConflictPolicy::KeepLocal)
+            .await
+            .unwrap();
+        assert_eq!(summary.conflicts, 1);
+        assert_eq!(summary.downloaded, 1);
+        assert_eq!(phone.metadata(id).unwrap().tags, vec!["work".to_string()]);
+
+        // Concurrent edits are resolved by the policy.
+        phone.add_tag(id, "phone".into()).unwrap();
+        tablet.add_tag(id, "tablet".into()).unwrap();
+        phone_sync
+            .sync(SyncConflictPolicy::KeepLocal)
+            .await
+            .unwrap();
+        let summary = tablet_sync
+            .sync(SyncConflictPolicy::KeepRemote)
+            .await
+            .unwrap();
+        assert_eq!(summary.conflicts, 1);
+        assert_eq!(
+            tablet.metadata(id).unwrap().tags,
+            vec!["work".to_string(), "phone".to_string()]
+        );
+
+        // Deletions are synchronized as tombstones.
+        tablet.delete(id).unwrap();
+        tablet_sync
+            .sync(SyncConflictPolicy::KeepLocal)
+            .await
+            .unwrap();
+        let summary = phone_sync
+            .sync(SyncConflictPolicy::KeepLocal)
+            .await
+            .unwrap();
+        assert_eq!(summary.deleted, 1);
+        assert!(phone.get(id).unwrap().is_none());
+        assert!(transport.0.lock().unwrap().contains_key(&id.to_string()));
+    }
+}
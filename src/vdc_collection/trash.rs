@@ -0,0 +1,264 @@
+//! Two-phase deletion of credentials.
+//!
+//! Trashed credentials are moved out of the credential keys, so that they are
+//! no longer listed or matched, while their index entry and metadata are kept
+//! until they are purged.
+
+use super::{CollectionChange, VdcCollection, VdcCollectionError};
+use crate::common::*;
+use crate::credential::Credential;
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Internal prefix for trashed credential keys.
+const TRASH_KEY_PREFIX: &str = "TrashedCredential.";
+
+/// The default time a trashed credential can be restored.
+pub(crate) const DEFAULT_TRASH_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TrashedCredential {
+    credential: Credential,
+    trashed_at: SystemTime,
+}
+
+/// A credential in the trash.
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct TrashedCredentialSummary {
+    pub id: Uuid,
+    pub r#type: CredentialType,
+    pub trashed_at: SystemTime,
+    /// The time after which the credential can no longer be restored.
+    pub restorable_until: SystemTime,
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Set the time a trashed credential can be restored.
+    pub fn set_trash_retention(&self, retention: Duration) {
+        *self
+            .trash_retention
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = retention;
+    }
+
+    /// Move a credential to the trash, hiding it from listing and matching.
+    ///
+    /// The credential can be restored with [VdcCollection::restore] until the
+    /// retention window has passed.
+    pub fn trash(&self, id: Uuid) -> Result<(), VdcCollectionError> {
+        let Some(credential) = self.get(id)? else {
+            return Err(VdcCollectionError::NotFound(id));
+        };
+
+        let trashed = TrashedCredential {
+            credential,
+            trashed_at: SystemTime::now(),
+        };
+        let value =
+            serde_cbor::to_vec(&trashed).map_err(|_| VdcCollectionError::SerializeFailed)?;
+
+        self.storage
+            .add(Self::id_to_trash_key(id), Value(value))
+            .map_err(VdcCollectionError::StoreFailed)?;
+        self.storage
+            .remove(Self::id_to_key(id))
+            .map_err(VdcCollectionError::DeleteFailed)?;
+        self.notify_observers(CollectionChange::Deleted { id });
+        Ok(())
+    }
+
+    /// Restore a credential from the trash.
+    pub fn restore(&self, id: Uuid) -> Result<(), VdcCollectionError> {
+        let Some(trashed) = self.trashed_credential(id)? else {
+            return Err(VdcCollectionError::NotFound(id));
+        };
+        if self.restorable_until(&trashed) < SystemTime::now() {
+            return Err(VdcCollectionError::TrashExpired(id));
+        }
+
+        let value = serde_cbor::to_vec(&trashed.credential)
+            .map_err(|_| VdcCollectionError::SerializeFailed)?;
+
+        self.storage
+            .add(Self::id_to_key(id), Value(value))
+            .map_err(VdcCollectionError::StoreFailed)?;
+        self.storage
+            .remove(Self::id_to_trash_key(id))
+            .map_err(VdcCollectionError::DeleteFailed)?;
+        self.notify_observers(CollectionChange::Added { id });
+        Ok(())
+    }
+
+    /// Get the credentials in the trash, including those that can no longer
+    /// be restored.
+    pub fn trashed(&self) -> Result<Vec<TrashedCredentialSummary>, VdcCollectionError> {
+        let mut summaries = Vec::new();
+        for id in self.trashed_entries()? {
+            if let Some(trashed) = self.trashed_credential(id)? {
+                summaries.push(TrashedCredentialSummary {
+                    id,
+                    r#type: trashed.credential.r#type.clone(),
+                    trashed_at: trashed.trashed_at,
+                    restorable_until: self.restorable_until(&trashed),
+                });
+            }
+        }
+        Ok(summaries)
+    }
+
+    /// Permanently remove every credential in the trash.
+    ///
+    /// Returns the number of credentials removed.
+    pub fn purge(&self) -> Result<u32, VdcCollectionError> {
+        let ids = self.trashed_entries()?;
+        for id in &ids {
+            self.purge_trashed(*id)?;
+        }
+        Ok(ids.len() as u32)
+    }
+
+    /// Permanently remove the credentials in the trash whose retention window
+    /// has passed.
+    ///
+    /// Returns the number of credentials removed.
+    pub fn purge_expired(&self) -> Result<u32, VdcCollectionError> {
+        let now = SystemTime::now();
+        let mut purged = 0;
+        for id in self.trashed_entries()? {
+            match self.trashed_credential(id)? {
+                Some(trashed) if self.restorable_until(&trashed) < now => {}
+                _ => continue,
+            }
+            self.purge_trashed(id)?;
+            purged += 1;
+        }
+        Ok(purged)
+    }
+}
+
+impl VdcCollection {
+    /// Convert a UUID to a trashed credential key.
+    fn id_to_trash_key(id: Uuid) -> Key {
+        Key(format!("{}{}", TRASH_KEY_PREFIX, id))
+    }
+
+    fn trashed_entries(&self) -> Result<Vec<Uuid>, VdcCollectionError> {
+        self.storage
+            .list()
+            .map(|list| {
+                list.iter()
+                    .filter_map(|key| key.strip_prefix(TRASH_KEY_PREFIX))
+                    .filter_map(|id| Uuid::parse_str(&id).ok())
+                    .collect()
+            })
+            .map_err(VdcCollectionError::LoadFailed)
+    }
+
+    fn trashed_credential(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<TrashedCredential>, VdcCollectionError> {
+        let Some(raw) = self
+            .storage
+            .get(Self::id_to_trash_key(id))
+            .map_err(VdcCollectionError::LoadFailed)?
+        else {
+            return Ok(None);
+        };
+
+        serde_cbor::from_slice(&raw.0)
+            .map(Some)
+            .map_err(|_| VdcCollectionError::DeserializeFailed)
+    }
+
+    fn restorable_until(&self, trashed: &TrashedCredential) -> SystemTime {
+        let retention = *self
+            .trash_retention
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        trashed.trashed_at + retention
+    }
+
+    /// Remove a credential from the trash, with its index entry, metadata and usage
+    /// statistics.
+    pub(crate) fn purge_trashed(&self, id: Uuid) -> Result<(), VdcCollectionError> {
+        let metadata = self.metadata(id)?;
+        self.storage
+            .remove(Self::id_to_trash_key(id))
+            .map_err(VdcCollectionError::DeleteFailed)?;
+        self.remove_index_entry(id)?;
+        self.remove_metadata(id)?;
+        self.remove_usage(id)?;
+        self.notify_deleted(id, metadata);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::CredentialFormat;
+    use crate::local_store::LocalStore;
+
+    use std::sync::Arc;
+
+    fn credential() -> Credential {
+        Credential {
+            id: Uuid::new_v4(),
+            format: CredentialFormat::LdpVc,
+            r#type: CredentialType("UniversityDegreeCredential".into()),
+            payload: vec![],
+            key_alias: None,
+            display: vec![],
+        }
+    }
+
+    #[test]
+    fn trashes_and_restores_credentials() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let (kept, trashed) = (credential(), credential());
+        vdc.add(&kept).unwrap();
+        vdc.add(&trashed).unwrap();
+        vdc.add_tag(trashed.id, "work".into()).unwrap();
+
+        vdc.trash(trashed.id).unwrap();
+        assert_eq!(vdc.all_entries().unwrap(), vec![kept.id]);
+        assert_eq!(vdc.query(Default::default()).unwrap(), vec![kept.id]);
+        assert_eq!(vdc.trashed().unwrap().len(), 1);
+
+        vdc.restore(trashed.id).unwrap();
+        assert!(vdc.get(trashed.id).unwrap().is_some());
+        assert_eq!(vdc.metadata(trashed.id).unwrap().tags, vec!["work"]);
+        assert!(vdc.trashed().unwrap().is_empty());
+        assert!(matches!(
+            vdc.restore(trashed.id),
+            Err(VdcCollectionError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn purges_the_trash() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let (first, second) = (credential(), credential());
+        vdc.add(&first).unwrap();
+        vdc.add(&second).unwrap();
+        vdc.trash(first.id).unwrap();
+        vdc.trash(second.id).unwrap();
+
+        assert_eq!(vdc.purge_expired().unwrap(), 0);
+
+        vdc.set_trash_retention(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(matches!(
+            vdc.restore(first.id),
+            Err(VdcCollectionError::TrashExpired(_))
+        ));
+
+        assert_eq!(vdc.purge().unwrap(), 2);
+        assert!(vdc.trashed().unwrap().is_empty());
+        assert!(vdc.index_entry(first.id).unwrap().is_none());
+    }
+}
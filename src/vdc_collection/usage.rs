@@ -0,0 +1,165 @@
+//! Usage statistics of the stored credentials, recorded on each successful
+//! presentation, for "recently used" sorting and housekeeping suggestions.
+//!
+//! NOTE: usage statistics are local to the device: they are not part of
+//! backups nor synchronized.
+
+use super::{CollectionChange, VdcCollection, VdcCollectionError};
+use crate::common::*;
+
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+/// Internal prefix for credential usage keys.
+const USAGE_KEY_PREFIX: &str = "CredentialUsage.";
+
+/// How often and when a stored credential was presented.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, uniffi::Record)]
+pub struct CredentialUsage {
+    pub use_count: u32,
+    pub last_used_at: Option<SystemTime>,
+}
+
+#[uniffi::export]
+impl VdcCollection {
+    /// Get the usage statistics of a credential.
+    pub fn usage(&self, id: Uuid) -> Result<CredentialUsage, VdcCollectionError> {
+        let Some(raw) = self
+            .storage
+            .get(Self::id_to_usage_key(id))
+            .map_err(VdcCollectionError::LoadFailed)?
+        else {
+            return Ok(CredentialUsage::default());
+        };
+
+        serde_cbor::from_slice(&raw.0).map_err(|_| VdcCollectionError::DeserializeFailed)
+    }
+
+    /// Record a presentation of a credential, e.g. one made outside of
+    /// OID4VP. Presentations submitted by the holder are recorded
+    /// automatically.
+    pub fn record_usage(&self, id: Uuid, used_at: SystemTime) -> Result<(), VdcCollectionError> {
+        if self.get(id)?.is_none() {
+            return Err(VdcCollectionError::NotFound(id));
+        }
+
+        let mut usage = self.usage(id)?;
+        usage.use_count = usage.use_count.saturating_add(1);
+        usage.last_used_at = usage.last_used_at.max(Some(used_at));
+        let value = serde_cbor::to_vec(&usage).map_err(|_| VdcCollectionError::SerializeFailed)?;
+        self.storage
+            .add(Self::id_to_usage_key(id), Value(value))
+            .map_err(VdcCollectionError::StoreFailed)?;
+
+        self.notify_observers(CollectionChange::Updated { id });
+        Ok(())
+    }
+
+    /// Get the credentials that were not presented since a time, and added
+    /// before it, to suggest removing them, from the least recently used.
+    ///
+    /// Credentials that were never presented are listed first.
+    pub fn unused_since(&self, since: SystemTime) -> Result<Vec<Uuid>, VdcCollectionError> {
+        let mut unused = Vec::new();
+        for id in self.all_entries()? {
+            let last_used_at = self.usage(id)?.last_used_at;
+            let added_at = self
+                .index_entry(id)?
+                .and_then(|entry| entry.added_at)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            if last_used_at.unwrap_or(added_at) < since {
+                unused.push((last_used_at, id));
+            }
+        }
+
+        unused.sort();
+        Ok(unused.into_iter().map(|(_, id)| id).collect())
+    }
+}
+
+impl VdcCollection {
+    /// Convert a UUID to a usage key.
+    fn id_to_usage_key(id: Uuid) -> Key {
+        Key(format!("{}{}", USAGE_KEY_PREFIX, id))
+    }
+
+    /// Remove the usage statistics of a credential.
+    pub(crate) fn remove_usage(&self, id: Uuid) -> Result<(), VdcCollectionError> {
+        self.storage
+            .remove(Self::id_to_usage_key(id))
+            .map_err(VdcCollectionError::DeleteFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{Credential, CredentialFormat};
+    use crate::local_store::LocalStore;
+    use crate::vdc_collection::{CredentialSort, CredentialSortKey};
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn tracks_credential_usage() {
+        let vdc = VdcCollection::new(Arc::new(LocalStore::new()));
+        let ids = (0..3)
+            .map(|_| {
+                let credential = Credential {
+                    id: Uuid::new_v4(),
+                    format: CredentialFormat::LdpVc,
+                    r#type: CredentialType("UniversityDegreeCredential".into()),
+                    payload: vec![],
+                    key_alias: None,
+                    display: vec![],
+                };
+                vdc.add(&credential).unwrap();
+                credential.id
+            })
+            .collect::<Vec<_>>();
+
+        let now = SystemTime::now();
+        let earlier = now - Duration::from_secs(3600);
+        vdc.record_usage(ids[0], earlier).unwrap();
+        vdc.record_usage(ids[1], earlier).unwrap();
+        vdc.record_usage(ids[1], now).unwrap();
+        assert_eq!(
+            vdc.usage(ids[1]).unwrap(),
+            CredentialUsage {
+                use_count: 2,
+                last_used_at: Some(now),
+            }
+        );
+        assert_eq!(vdc.usage(ids[2]).unwrap(), CredentialUsage::default());
+        assert!(matches!(
+            vdc.record_usage(Uuid::new_v4(), now),
+            Err(VdcCollectionError::NotFound(_))
+        ));
+
+        let recent = vdc
+            .list(
+                0,
+                3,
+                CredentialSort {
+                    key: CredentialSortKey::LastUsedAt,
+                    descending: true,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            recent.iter().map(|summary| summary.id).collect::<Vec<_>>(),
+            [ids[1], ids[0], ids[2]]
+        );
+        assert_eq!(recent[0].use_count, 2);
+
+        let later = now + Duration::from_secs(60);
+        assert!(vdc.unused_since(earlier).unwrap().is_empty());
+        assert_eq!(vdc.unused_since(now).unwrap(), [ids[2], ids[0]]);
+        assert_eq!(vdc.unused_since(later).unwrap(), [ids[2], ids[0], ids[1]]);
+
+        vdc.delete(ids[1]).unwrap();
+        assert_eq!(vdc.usage(ids[1]).unwrap(), CredentialUsage::default());
+    }
+}